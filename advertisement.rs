@@ -0,0 +1,273 @@
+//! Payloads of the BLE advertisements a device sends for its broadcasts. An ElID does not fit
+//! into the 31 bytes of an advertisement, so the payload carries its [CompactElId], see
+//! [spirit_broadcast_compact](crate::spirit::spirit_broadcast_compact), and receivers record the
+//! compact IDs they hear in a [ReceivedTable], which is traced against the compacted confirmed
+//! ElIDs with [trace_received].
+
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use std::collections::{HashMap, HashSet, hash_map::Entry};
+
+#[cfg(not(feature = "std"))]
+use hashbrown::{HashMap, HashSet, hash_map::Entry};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+use crate::{
+    constants::BROADCAST_PAYLOAD_DST,
+    spirit::{CompactElId, Epoch},
+};
+
+/// Version of the payload format. Payloads of other versions are rejected.
+pub const VERSION: u8 = 1;
+
+/// Payload of an advertisement: the [VERSION], the compact ElID, a 2-byte metadata field of the
+/// radio layer, e.g. the transmit power, and a check byte, which is the first byte of a hash of
+/// the other fields. The check byte catches corrupted payloads, but not forged ones, as anyone
+/// can recompute it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BroadcastPayload {
+    pub el_id: CompactElId,
+    pub metadata: u16,
+}
+
+#[derive(Error, Debug, PartialEq, Eq, Clone)]
+pub enum PayloadError {
+    #[error("Payload has {actual} bytes, expected {}.", BroadcastPayload::SIZE)]
+    InvalidLength { actual: usize },
+    #[error("Unsupported payload version {0}, expected {VERSION}.")]
+    UnsupportedVersion(u8),
+    #[error("Payload check byte does not match.")]
+    InvalidCheck,
+}
+
+impl BroadcastPayload {
+    /// Size of the encoding `VERSION || el_id || metadata || check`, with the metadata as
+    /// little-endian `u16`.
+    pub const SIZE: usize = 1 + CompactElId::SIZE + 2 + 1;
+
+    pub fn new(el_id: CompactElId, metadata: u16) -> Self {
+        Self { el_id, metadata }
+    }
+
+    pub fn to_bytes(&self) -> [u8; Self::SIZE] {
+        let mut bytes = [0; Self::SIZE];
+        bytes[0] = VERSION;
+        bytes[1..=CompactElId::SIZE].copy_from_slice(&self.el_id.to_bytes());
+        bytes[CompactElId::SIZE + 1..Self::SIZE - 1].copy_from_slice(&self.metadata.to_le_bytes());
+        bytes[Self::SIZE - 1] = check_byte(&bytes[..Self::SIZE - 1]);
+        bytes
+    }
+
+    /// Parses the payload of a received advertisement, which has to be exactly [Self::SIZE]
+    /// bytes of the current [VERSION] with a matching check byte.
+    pub fn parse(bytes: &[u8]) -> Result<Self, PayloadError> {
+        let bytes: &[u8; Self::SIZE] =
+            bytes.try_into().map_err(|_| PayloadError::InvalidLength {
+                actual: bytes.len(),
+            })?;
+        if bytes[0] != VERSION {
+            return Err(PayloadError::UnsupportedVersion(bytes[0]));
+        }
+        if bytes[Self::SIZE - 1] != check_byte(&bytes[..Self::SIZE - 1]) {
+            return Err(PayloadError::InvalidCheck);
+        }
+        let (el_id, metadata) = bytes[1..Self::SIZE - 1].split_at(CompactElId::SIZE);
+        Ok(Self {
+            el_id: CompactElId::from_bytes(el_id.try_into().unwrap()),
+            metadata: u16::from_le_bytes(metadata.try_into().unwrap()),
+        })
+    }
+
+    /// Records the payload, heard in `epoch` for `duration_secs` at a signal attenuation of
+    /// `attenuation_db`, in `received`, for
+    /// [spirit_trace_risk](crate::spirit::spirit_trace_risk). Returns `false` if its compact ElID
+    /// was heard before, in which case the earlier encounter is kept with the durations added up,
+    /// saturating at `u16::MAX`, and the lower attenuation.
+    pub fn receive(
+        &self,
+        epoch: Epoch,
+        duration_secs: u16,
+        attenuation_db: u8,
+        received: &mut ReceivedTable,
+    ) -> bool {
+        match received.entry(self.el_id) {
+            Entry::Occupied(mut entry) => {
+                let encounter = entry.get_mut();
+                encounter.duration_secs = encounter.duration_secs.saturating_add(duration_secs);
+                encounter.attenuation_db = encounter.attenuation_db.min(attenuation_db);
+                false
+            }
+            Entry::Vacant(entry) => {
+                entry.insert(ReceivedEncounter {
+                    epoch,
+                    metadata: self.metadata,
+                    duration_secs,
+                    attenuation_db,
+                });
+                true
+            }
+        }
+    }
+}
+
+fn check_byte(fields: &[u8]) -> u8 {
+    Sha256::new()
+        .chain_update(BROADCAST_PAYLOAD_DST)
+        .chain_update(fields)
+        .finalize()[0]
+}
+
+/// An advertisement first heard in `epoch`, with the metadata of its payload and the duration
+/// and attenuation of all scans it was heard in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReceivedEncounter {
+    pub epoch: Epoch,
+    pub metadata: u16,
+    pub duration_secs: u16,
+    pub attenuation_db: u8,
+}
+
+/// Compact ElIDs a device heard, see [BroadcastPayload::receive].
+pub type ReceivedTable = HashMap<CompactElId, ReceivedEncounter>;
+
+/// Outcome of [trace_received].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReceivedTrace {
+    /// The heard compact ElIDs that were confirmed, in the order of their first occurrence in
+    /// `cf`.
+    pub matched: Vec<CompactElId>,
+    /// Number of distinct matched ElIDs, i.e. `matched.len()`.
+    pub count: usize,
+    pub alarm: bool,
+}
+
+/// Like [spirit_trace_compact](crate::spirit::spirit_trace_compact), for the compact ElIDs in
+/// `received`. The alarm is raised if at least `exposure_limit` distinct ElIDs match, and at
+/// least one.
+pub fn trace_received<'a>(
+    cf: impl IntoIterator<Item = &'a CompactElId>,
+    received: &ReceivedTable,
+    exposure_limit: usize,
+) -> ReceivedTrace {
+    let mut seen = HashSet::new();
+    let matched: Vec<_> = cf
+        .into_iter()
+        .filter(|el_id| received.contains_key(*el_id) && seen.insert(**el_id))
+        .copied()
+        .collect();
+    ReceivedTrace {
+        count: matched.len(),
+        alarm: matched.len() >= exposure_limit.max(1),
+        matched,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rand::rngs::OsRng;
+
+    use super::*;
+    use crate::{
+        G1G2,
+        elgamal::DecryptionKey,
+        spirit::{DomainConfig, EncounterTable, Interval, UserKeys, spirit_broadcast_compact},
+    };
+
+    fn payload() -> BroadcastPayload {
+        BroadcastPayload::new(CompactElId::new(&G1G2::random(&mut OsRng)), 0xfe05)
+    }
+
+    #[test]
+    fn payloads_round_trip() {
+        let payload = payload();
+        let bytes = payload.to_bytes();
+        assert!(bytes.len() <= 31);
+        assert_eq!(bytes[0], VERSION);
+        assert_eq!(BroadcastPayload::parse(&bytes), Ok(payload));
+    }
+
+    #[test]
+    fn malformed_payloads_are_rejected() {
+        // no bit flip of this payload keeps its check byte, which holds for most but not all
+        // payloads
+        let bytes = BroadcastPayload::new(CompactElId::from_bytes([0; 16]), 0xfe05).to_bytes();
+        for length in [
+            0,
+            BroadcastPayload::SIZE - 1,
+            BroadcastPayload::SIZE + 1,
+            31,
+        ] {
+            let mut resized = bytes.to_vec();
+            resized.resize(length, 0);
+            assert_eq!(
+                BroadcastPayload::parse(&resized),
+                Err(PayloadError::InvalidLength { actual: length })
+            );
+        }
+
+        let mut other_version = bytes;
+        other_version[0] = VERSION + 1;
+        assert_eq!(
+            BroadcastPayload::parse(&other_version),
+            Err(PayloadError::UnsupportedVersion(VERSION + 1))
+        );
+
+        // a flipped bit of the ElID, the metadata or the check byte
+        for bit in 8..8 * BroadcastPayload::SIZE {
+            let mut corrupted = bytes;
+            corrupted[bit / 8] ^= 1 << (bit % 8);
+            assert_eq!(
+                BroadcastPayload::parse(&corrupted),
+                Err(PayloadError::InvalidCheck)
+            );
+        }
+    }
+
+    #[test]
+    fn received_payloads_are_traced() {
+        let domain = DomainConfig::default();
+        let pk_ha = DecryptionKey::random(&mut OsRng).encryption_key();
+        let keys = UserKeys::random(OsRng);
+        let mut t_el = EncounterTable::new();
+        let heard: Vec<_> = (0..3)
+            .map(|i| {
+                spirit_broadcast_compact(
+                    &domain,
+                    0,
+                    Interval(i),
+                    &keys,
+                    &pk_ha,
+                    &mut t_el,
+                    &mut OsRng,
+                )
+                .0
+            })
+            .collect();
+
+        let mut received = ReceivedTable::new();
+        for (epoch, el_id) in heard.iter().enumerate() {
+            let bytes = BroadcastPayload::new(*el_id, 7).to_bytes();
+            let payload = BroadcastPayload::parse(&bytes).unwrap();
+            assert!(payload.receive(epoch as Epoch, 60, 70, &mut received));
+        }
+        assert!(!BroadcastPayload::new(heard[0], 9).receive(5, 30, 50, &mut received));
+        assert_eq!(
+            received[&heard[0]],
+            ReceivedEncounter {
+                epoch: 0,
+                metadata: 7,
+                duration_secs: 90,
+                attenuation_db: 50
+            }
+        );
+
+        let other = CompactElId::new(&G1G2::random(&mut OsRng));
+        let cf = [other, heard[2], heard[0], heard[2]];
+        let trace = trace_received(&cf, &received, 2);
+        assert_eq!(trace.matched, [heard[2], heard[0]]);
+        assert_eq!(trace.count, 2);
+        assert!(trace.alarm);
+        assert!(!trace_received(&[other], &received, 1).alarm);
+    }
+}
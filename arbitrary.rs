@@ -0,0 +1,184 @@
+use std::{collections::HashSet, fmt};
+
+use ark_ec::AffineRepr;
+use ark_ff::{PrimeField, Zero};
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize, SerializationError};
+use proptest::{collection::btree_set, prelude::*};
+use rand::SeedableRng;
+use rand_chacha::ChaCha20Rng;
+
+use crate::{
+    G1G2, Scalar,
+    atact::StRG,
+    bls381_helpers::{G1Affine, G2Affine},
+    pedersen::Commitment,
+    spirit::{
+        ChallengeSet, DomainConfig, ElID, Epoch, Interval, PrfProof, Token, TraceReport, UserKeys,
+        prf_prove, spirit_diagnosis,
+    },
+    tsw::{PublicKey, Signature},
+};
+
+/// Encodes `value` compressed and decodes it again, with all validity checks.
+pub(crate) fn round_trip<T: CanonicalSerialize + CanonicalDeserialize>(
+    value: &T,
+) -> Result<T, SerializationError> {
+    let mut bytes = Vec::new();
+    value.serialize_compressed(&mut bytes)?;
+    T::deserialize_compressed(&bytes[..])
+}
+
+/// Scalars reduced from 32 random bytes, which shrink towards small values.
+pub(crate) fn scalar() -> impl Strategy<Value = Scalar> {
+    any::<[u8; 32]>().prop_map(|bytes| Scalar::from_le_bytes_mod_order(&bytes))
+}
+
+pub(crate) fn nonzero_scalar() -> impl Strategy<Value = Scalar> {
+    scalar().prop_filter("zero scalar", |k| !k.is_zero())
+}
+
+/// Points `(g1^a, g2^b)` for nonzero `a` and `b`, which shrink towards small multiples of the
+/// generators. The identity never occurs in protocol messages and is not generated.
+pub(crate) fn g1g2() -> impl Strategy<Value = G1G2> {
+    (nonzero_scalar(), nonzero_scalar())
+        .prop_map(|(a, b)| G1G2(G1Affine::generator() * a, G2Affine::generator() * b))
+}
+
+pub(crate) fn commitment() -> impl Strategy<Value = Commitment> {
+    g1g2().prop_map(Commitment)
+}
+
+pub(crate) fn signature() -> impl Strategy<Value = Signature> {
+    g1g2().prop_map(Signature)
+}
+
+pub(crate) fn public_key() -> impl Strategy<Value = PublicKey> {
+    g1g2().prop_map(PublicKey)
+}
+
+pub(crate) fn token() -> impl Strategy<Value = Token> {
+    (commitment(), signature(), any::<Epoch>()).prop_map(|(cm, s, expiry)| Token(cm, s, expiry))
+}
+
+pub(crate) fn domain() -> impl Strategy<Value = DomainConfig> {
+    prop::collection::vec(any::<u8>(), 0..16).prop_map(DomainConfig::new)
+}
+
+/// A PRF evaluation with its proof, as produced by [prf_prove].
+#[derive(Debug, Clone)]
+pub(crate) struct PrfEvaluation {
+    pub(crate) domain: DomainConfig,
+    pub(crate) k: Scalar,
+    pub(crate) epoch: Epoch,
+    pub(crate) index: Interval,
+    pub(crate) el_id: ElID,
+    pub(crate) proof: PrfProof,
+}
+
+pub(crate) fn prf_evaluation() -> impl Strategy<Value = PrfEvaluation> {
+    (
+        domain(),
+        nonzero_scalar(),
+        any::<Epoch>(),
+        (0..10_000u64).prop_map(Interval),
+        any::<u64>(),
+    )
+        .prop_map(|(domain, k, epoch, index, seed)| {
+            let mut rng = ChaCha20Rng::seed_from_u64(seed);
+            let (el_id, proof) = prf_prove(&domain, &k, epoch, index, &mut rng);
+            PrfEvaluation {
+                domain,
+                k,
+                epoch,
+                index,
+                el_id,
+                proof,
+            }
+        })
+}
+
+/// Registration `(Token, StRG)` of a user with identity `a` and commitment randomness `r`. The
+/// token's signature is a random point, so it is only valid against a registry that contains it.
+fn registration(
+    domain: &DomainConfig,
+    a: Scalar,
+    r: Scalar,
+    s: Signature,
+    expiry: Epoch,
+) -> (Token, StRG) {
+    let (cm, opening) = Commitment::commit_with_randomness(&a, &r);
+    (
+        Token::new(domain, &cm, s, expiry),
+        StRG {
+            a,
+            r: opening.r,
+            attributes: Vec::new(),
+        },
+    )
+}
+
+/// An honestly generated trace report disclosing between one and three ElIDs of epochs before
+/// the token's expiry, submitted in epoch 0 for a challenge of `challenges`.
+pub(crate) struct Diagnosis {
+    pub(crate) domain: DomainConfig,
+    pub(crate) ppu: (Token, StRG),
+    pub(crate) report: TraceReport,
+    pub(crate) challenges: ChallengeSet,
+}
+
+// the user's secrets are not printed
+impl fmt::Debug for Diagnosis {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Diagnosis")
+            .field("domain", &self.domain)
+            .field("token", &self.ppu.0)
+            .field(
+                "disclosures",
+                &self
+                    .report
+                    .disclosures
+                    .iter()
+                    .map(|disclosure| (disclosure.epoch, disclosure.index))
+                    .collect::<Vec<_>>(),
+            )
+            .finish_non_exhaustive()
+    }
+}
+
+pub(crate) fn diagnosis() -> impl Strategy<Value = Diagnosis> {
+    (
+        domain(),
+        (scalar(), scalar(), nonzero_scalar()),
+        (signature(), 1..Epoch::MAX),
+        btree_set((0..4u64, (0..96u64).prop_map(Interval)), 1..4),
+        any::<u64>(),
+    )
+        .prop_map(|(domain, (a, r, prv), (s, expiry), cp, seed)| {
+            let mut rng = ChaCha20Rng::seed_from_u64(seed);
+            let ppu = registration(&domain, a, r, s, expiry);
+            let cp: HashSet<_> = cp
+                .into_iter()
+                .map(|(epoch, index)| (epoch % expiry, index))
+                .collect();
+            let mut challenges = ChallengeSet::new(0);
+            let challenge = challenges.issue(0, &mut rng);
+            let report = spirit_diagnosis(
+                &domain,
+                &ppu,
+                &UserKeys::new(prv),
+                None,
+                &cp,
+                0,
+                &challenge,
+                &[],
+                &mut rng,
+            )
+            .expect("diagnosis failed");
+            Diagnosis {
+                domain,
+                ppu,
+                report,
+                challenges,
+            }
+        })
+}
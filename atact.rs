@@ -1,22 +1,147 @@
-use std::cmp::max;
+use alloc::{vec, vec::Vec};
+use core::{cmp::max, iter};
 
-use ark_ff::{Field, UniformRand, Zero};
-use ark_serialize::CanonicalSerialize;
+#[cfg(feature = "std")]
+use std::sync::Arc;
+
+#[cfg(feature = "std")]
+use ark_ff::Zero;
+use ark_ff::{Field, UniformRand};
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
+use rand::{CryptoRng, Rng, RngCore, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+#[cfg(feature = "rayon")]
 use rayon::iter::{IntoParallelIterator, ParallelIterator};
-use sha3::digest::{ExtendableOutput, Update, XofReader};
+use sha2::{Digest, Sha256};
 use thiserror::Error;
+#[cfg(feature = "zeroize")]
+use zeroize::{Zeroize, ZeroizeOnDrop};
 
+#[cfg(feature = "std")]
+use crate::{
+    audit::{IssuanceLog, IssuanceRecorder},
+    bls381_helpers::multi_pairing,
+    lagrange::LagrangeCache,
+    pedersen::get_parameters,
+};
 use crate::{
-    bls381_helpers::{Scalar, multi_pairing},
+    bls381_helpers::{
+        G1G2, HashBackendId, HashToCurveSuite, OnceGlobal, Precomputed, Scalar,
+        hash_with_domain_separation, msm,
+    },
+    constants::{COMMITTEE_DST, PARAMETERS_FINGERPRINT_DST, TOKEN_PROOF_PROTOCOL},
+    elgamal::{DecryptionKey, EncryptionKey},
+    fiat_shamir::ProofTranscript,
     lagrange::Lagrange,
-    pedersen::{Commitment, Proof2PK},
-    tsw::{self, PublicKey, SecretKey, Signature},
+    pedersen::{Commitment, MultiBasePublicParameters, Proof2PK},
+    tsw::{self, PreparedPublicKey, ProofOfPossession, PublicKey, SecretKey, Signature},
 };
 
-const UNIQUE_ATTRIBUTE_INDEX: usize = 0;
+pub(crate) const UNIQUE_ATTRIBUTE_INDEX: usize = 0;
 
 pub struct Issuer {
     sk: SecretKey,
+    /// Share of the committee's [decryption key](PublicParameters::decryption_key), which only
+    /// issuers set up by a dealer hold.
+    dk: Option<DecryptionKey>,
+    /// Log of the requests signed in [tissue], see [Self::with_issuance_log].
+    #[cfg(feature = "std")]
+    log: Option<IssuanceRecorder>,
+    /// Seat in the committees of [select_committee], see [Self::with_committee].
+    committee: Option<CommitteeSeat>,
+}
+
+/// Index of an issuer among the `num_issuers` of the deployment, and the beacon and current
+/// epoch of the committees it checks its membership in.
+struct CommitteeSeat {
+    beacon: Vec<u8>,
+    index: usize,
+    num_issuers: usize,
+    epoch: u64,
+}
+
+impl CommitteeSeat {
+    /// Fails unless the seat is in the committee of `t` issuers of its epoch.
+    fn check(&self, t: usize) -> Result<(), AtACTError> {
+        if select_committee(&self.beacon, self.epoch, self.num_issuers, t).contains(&self.index) {
+            Ok(())
+        } else {
+            Err(AtACTError::NotInCommittee { epoch: self.epoch })
+        }
+    }
+}
+
+impl Issuer {
+    pub(crate) fn new(sk: SecretKey) -> Self {
+        Self {
+            sk,
+            dk: None,
+            #[cfg(feature = "std")]
+            log: None,
+            committee: None,
+        }
+    }
+
+    /// Makes the issuer sign in [tissue] only in the epochs in which it is in the committee that
+    /// [select_committee] assigns from `beacon`, being the issuer of `index` among
+    /// `num_issuers`. The issuer starts in epoch 0, see [Self::advance_epoch].
+    pub fn with_committee(mut self, beacon: Vec<u8>, index: usize, num_issuers: usize) -> Self {
+        self.committee = Some(CommitteeSeat {
+            beacon,
+            index,
+            num_issuers,
+            epoch: 0,
+        });
+        self
+    }
+
+    /// Moves the issuer's committee seat to `epoch`. Issuers without one sign in every epoch.
+    pub fn advance_epoch(&mut self, epoch: u64) {
+        if let Some(seat) = &mut self.committee {
+            seat.epoch = epoch;
+        }
+    }
+
+    /// Whether the issuer signs requests of `pp` in its current epoch.
+    pub fn in_committee(&self, pp: &PublicParameters) -> bool {
+        self.committee
+            .as_ref()
+            .is_none_or(|seat| seat.check(pp.t).is_ok())
+    }
+
+    /// Records every blind request signed in [tissue] in an [IssuanceLog], under the hash of the
+    /// request salted with `salt`, see [request_hash](crate::audit::request_hash). The issuers
+    /// of a deployment share the salt with the authority that audits their logs.
+    ///
+    /// The entries are signed with a log key derived from the issuer's key share, so they are
+    /// no signatures under the share. The key stays the same when the share is refreshed.
+    #[cfg(feature = "std")]
+    pub fn with_issuance_log(mut self, salt: [u8; 32]) -> Self {
+        self.log = Some(IssuanceRecorder::new(salt, &self.sk));
+        self
+    }
+
+    /// The public key the entries of the issuer's log verify under, if it keeps one.
+    #[cfg(feature = "std")]
+    pub fn issuance_log_key(&self) -> Option<PublicKey> {
+        self.log.as_ref().map(IssuanceRecorder::public_key)
+    }
+
+    /// A copy of the issuer's log, if it keeps one.
+    #[cfg(feature = "std")]
+    pub fn issuance_log(&self) -> Option<IssuanceLog> {
+        self.log.as_ref().map(IssuanceRecorder::log)
+    }
+
+    /// The issuer's share of the committee's decryption key, if it holds one.
+    pub(crate) fn decryption_share(&self) -> Option<&DecryptionKey> {
+        self.dk.as_ref()
+    }
+
+    #[cfg(feature = "std")]
+    pub(crate) fn refresh(&mut self, delta: &Scalar) {
+        self.sk.sk += delta;
+    }
 }
 
 impl AsRef<SecretKey> for Issuer {
@@ -34,94 +159,577 @@ pub struct PublicParameters {
     pub lagrange_t: Lagrange,
     lagrange_tprime: Lagrange,
     pub tsw_pp: tsw::PublicParameters,
+    /// Generators of the attributes after the first in [register_attributes].
+    pub attribute_bases: MultiBasePublicParameters,
+    /// Domain-separation tag appended to the transcript of the issuance proof.
+    pub(crate) dst: Vec<u8>,
+    /// Hash-to-curve suite of the deployment, see [Self::hash_to_curve].
+    pub(crate) hash_to_curve: HashToCurveSuite,
+    /// Hash function of the deployment, see [Self::hash_backend].
+    pub(crate) hash_backend: HashBackendId,
+    /// Public keys `g^sk_j` of the issuers' key shares in committee order, against which their
+    /// blind tokens are checked with [verify_blind_token]. Empty if they are not known, e.g. in
+    /// parameters rebuilt on a user's device.
+    pub issuer_keys: Vec<PublicKey>,
+    /// Proofs of possession of the [issuer_keys](Self::issuer_keys) by the issuers of a dealt
+    /// committee, checked by [Self::validate]. Empty for committees of the [dkg](crate::dkg),
+    /// whose keys follow from the dealers' commitments, and after a share refresh.
+    pub issuer_pops: Vec<ProofOfPossession>,
+    /// The committee's key for the threshold decryption of encounter secrets, dealt
+    /// independently of `pk`, so that a share of a decryption is no share of a signature. None
+    /// for committees without a dealer.
+    pub decryption_key: Option<EncryptionKey>,
+    /// Keys `g^x_j` of the issuers' shares of the decryption key in committee order, against
+    /// which their [partial decryptions](crate::elgamal::PartialDecryption) are checked.
+    pub decryption_keys: Vec<EncryptionKey>,
+    /// Result of [Self::validate], computed on the first [Self::validate_cached].
+    pub(crate) validated: OnceGlobal<Result<(), ParamError>>,
+    /// Table of `pk` for blinding, built by [Self::precompute] or on first use.
+    pk_table: OnceGlobal<Precomputed<G1G2>>,
+    prepared_pk: OnceGlobal<PreparedPublicKey>,
+    /// Interpolations of the quorums of [aggregate_unblind_from], see [Self::issuer_lagrange].
+    #[cfg(feature = "std")]
+    lagrange_cache: LagrangeCache,
+}
+
+impl PublicParameters {
+    pub(crate) fn new(pk: PublicKey, n: usize, t: usize, tprime: usize, l: usize) -> Self {
+        let scalars: Vec<_> = (1..=max(n, t) as u64).map(Scalar::from).collect();
+
+        Self {
+            pk,
+            n,
+            t,
+            tprime,
+            lagrange_n: Lagrange::new(&scalars[..n]),
+            lagrange_t: Lagrange::new(&scalars[..t]),
+            lagrange_tprime: Lagrange::new(&scalars[..tprime]),
+            tsw_pp: tsw::PublicParameters::new(l + 1),
+            attribute_bases: MultiBasePublicParameters::new(l.saturating_sub(1)),
+            dst: Vec::new(),
+            hash_to_curve: HashToCurveSuite::default(),
+            hash_backend: HashBackendId::default(),
+            issuer_keys: Vec::new(),
+            issuer_pops: Vec::new(),
+            decryption_key: None,
+            decryption_keys: Vec::new(),
+            validated: OnceGlobal::new(),
+            pk_table: OnceGlobal::new(),
+            prepared_pk: OnceGlobal::new(),
+            #[cfg(feature = "std")]
+            lagrange_cache: LagrangeCache::default(),
+        }
+    }
+
+    /// Builds the [Precomputed] table of `pk` used to blind token requests and proofs, e.g. to
+    /// store it with [CanonicalSerialize] and install it with [Self::with_precomputed].
+    pub fn precompute(&self) -> &Precomputed<G1G2> {
+        self.pk_table.get_or_init(|| Precomputed::new(&self.pk.0))
+    }
+
+    /// Installs a stored table of `pk`. The table is compared with the one built from `pk`, so
+    /// tables of another key, or with any multiple that is not the one of `pk`, are rejected.
+    pub fn with_precomputed(mut self, table: Precomputed<G1G2>) -> Result<Self, ParamError> {
+        if table != Precomputed::new(&self.pk.0) {
+            return Err(ParamError::InvalidPrecomputation);
+        }
+        self.pk_table = OnceGlobal::with_value(table);
+        Ok(self)
+    }
+
+    /// Interpolation of the key shares of the issuers at the sorted, distinct `indices` in the
+    /// committee, starting at 0. The parameters keep the interpolations of the last quorums, so
+    /// that registrations with the same issuers compute the coefficients once.
+    #[cfg(feature = "std")]
+    pub fn issuer_lagrange(&self, indices: &[usize]) -> Arc<Lagrange> {
+        self.lagrange_cache.get(indices)
+    }
+
+    /// `pk * scalar`, from the table of `pk` unless `pk` was replaced after it was built.
+    pub(crate) fn pk_mul(&self, scalar: Scalar) -> PublicKey {
+        let table = self.precompute();
+        if table.base() == &self.pk.0 {
+            PublicKey(table.mul(&scalar))
+        } else {
+            &self.pk * scalar
+        }
+    }
+
+    /// The [PreparedPublicKey] of `pk`, prepared on first use and kept with the parameters, so
+    /// that a verifier holding them, e.g. a [SpiritSystem](crate::spirit::SpiritSystem),
+    /// prepares the key only once.
+    pub fn prepared_key(&self) -> &PreparedPublicKey {
+        self.prepared_pk
+            .get_or_init(|| PreparedPublicKey::new(&self.pk))
+    }
+
+    /// Verifies `signature` on `(commitment, index)` under `pk`, with the [Self::prepared_key]
+    /// unless `pk` was replaced after it was prepared.
+    pub(crate) fn verify_under_pk(
+        &self,
+        commitment: &Commitment,
+        index: usize,
+        signature: &Signature,
+    ) -> Result<(), tsw::Error> {
+        let prepared = self.prepared_key();
+        if prepared.public_key() == &self.pk {
+            signature.verify_prepared(prepared, commitment, index, &self.tsw_pp)
+        } else {
+            self.pk
+                .verify_pedersen_commitment(commitment, index, signature, &self.tsw_pp)
+        }
+    }
+
+    /// Hash-to-curve suite of the [DomainConfig](crate::spirit::DomainConfig) of the deployment
+    /// the parameters are set up for. Registration fails for a domain of another suite.
+    pub fn hash_to_curve(&self) -> HashToCurveSuite {
+        self.hash_to_curve
+    }
+
+    /// Hash function of the [DomainConfig](crate::spirit::DomainConfig) of the deployment, of
+    /// which registration fails for a domain of another backend as well.
+    pub fn hash_backend(&self) -> HashBackendId {
+        self.hash_backend
+    }
+
+    /// Number of attributes a token carries, the `l` of [setup].
+    pub fn num_attributes(&self) -> usize {
+        self.attribute_bases.len() + 1
+    }
+
+    /// SHA-256 of the parameters users register with: the public key, the committee sizes,
+    /// the number of attributes, the domain-separation tag and the hash suites. The
+    /// [issuer_keys](Self::issuer_keys) are left out, so that parameters rebuilt on a user's
+    /// device have the fingerprint of the issuers' parameters.
+    pub fn fingerprint(&self) -> [u8; 32] {
+        let mut hasher = Sha256::new();
+        hasher.update(PARAMETERS_FINGERPRINT_DST);
+        hasher.update(self.pk.to_bytes());
+        for size in [self.n, self.t, self.tprime, self.num_attributes()] {
+            hasher.update((size as u64).to_le_bytes());
+        }
+        hasher.update((self.dst.len() as u64).to_le_bytes());
+        hasher.update(&self.dst);
+        hasher.update([self.hash_to_curve as u8, self.hash_backend as u8]);
+
+        let mut fingerprint = [0u8; 32];
+        fingerprint.copy_from_slice(&hasher.finalize());
+        fingerprint
+    }
+
+    /// Checks parameters received from an untrusted source: the committee sizes, the
+    /// interpolation points, that the public key and the issuers' keys are valid points other
+    /// than the identity, that the generators are the ones derived for [Self::num_attributes],
+    /// and, if the issuers' keys are included, that the public key is their aggregate and that
+    /// every key has a valid [proof of possession](Self::issuer_pops) if any are included. A
+    /// dishonest issuer could otherwise announce a key that cancels the keys of the others.
+    pub fn validate(&self) -> Result<(), ParamError> {
+        let (n, t, tprime) = (self.n, self.t, self.tprime);
+        let num_issuers = if self.issuer_keys.is_empty() {
+            t + 1
+        } else {
+            self.issuer_keys.len()
+        };
+        check_parameters(num_issuers, n, t, tprime).map_err(|_| ParamError::InvalidSizes {
+            n,
+            t,
+            tprime,
+        })?;
+
+        // the points of a committee of size k are 1, ..., k
+        let is_committee = |lagrange: &Lagrange, k: usize| {
+            lagrange
+                .points()
+                .iter()
+                .copied()
+                .eq((1..=k as u64).map(Scalar::from))
+        };
+        if !is_committee(&self.lagrange_n, n)
+            || !is_committee(&self.lagrange_t, t)
+            || !is_committee(&self.lagrange_tprime, tprime)
+        {
+            return Err(ParamError::InvalidLagrange);
+        }
+
+        if !self.pk.0.is_valid_element() || !self.pk.is_valid() {
+            return Err(ParamError::InvalidPublicKey);
+        }
+        if let Some(index) = self
+            .issuer_keys
+            .iter()
+            .position(|pk| !pk.0.is_valid_element() || !pk.is_valid())
+        {
+            return Err(ParamError::InvalidIssuerKey(index));
+        }
+
+        let l = self.num_attributes();
+        if self.tsw_pp != tsw::PublicParameters::new(l + 1)
+            || self.attribute_bases != MultiBasePublicParameters::new(l - 1)
+        {
+            return Err(ParamError::InvalidGenerators { num_attributes: l });
+        }
+
+        if !self.issuer_keys.is_empty() {
+            let keys: Vec<_> = self.issuer_keys[..t]
+                .iter()
+                .map(|pk| pk.0.clone())
+                .collect();
+            let consistent = self.lagrange_t.eval_0(&keys) == self.pk.0
+                && self.issuer_keys[t..]
+                    .iter()
+                    .zip(t + 1..)
+                    .all(|(pk, x)| self.lagrange_t.eval(Scalar::from(x as u64), &keys) == pk.0);
+            if !consistent {
+                return Err(ParamError::InconsistentIssuerKeys);
+            }
+
+            let pops = &self.issuer_pops;
+            let invalid_pop = (0..self.issuer_keys.len().max(pops.len())).find(|&j| {
+                match (self.issuer_keys.get(j), pops.get(j)) {
+                    (Some(pk), Some(pop)) => !pk.verify_pop(pop),
+                    _ => true,
+                }
+            });
+            if let (false, Some(index)) = (pops.is_empty(), invalid_pop) {
+                return Err(ParamError::InvalidProofOfPossession(index));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// [Self::validate], but only checked once. Later changes to the parameters are not
+    /// checked again.
+    pub fn validate_cached(&self) -> Result<(), ParamError> {
+        self.validated.get_or_init(|| self.validate()).clone()
+    }
+}
+
+pub(crate) fn check_parameters(
+    num_issuers: usize,
+    n: usize,
+    t: usize,
+    tprime: usize,
+) -> Result<(), AtACTError> {
+    if tprime < 2 || tprime >= n || t < 2 || t >= num_issuers {
+        Err(AtACTError::InvalidParameters)
+    } else {
+        Ok(())
+    }
 }
 
+/// Sets up a committee of `num_issuers` issuers with a trusted dealer, which derives all issuer
+/// keys from one secret key, so no issuer can choose its key. Committees without a dealer run
+/// the [dkg](crate::dkg), in which the dealers prove possession of their contributions. The
+/// dealer also shares a second, independent key among the issuers, the
+/// [decryption key](PublicParameters::decryption_key).
 pub fn setup(
     num_issuers: usize,
     n: usize,
     t: usize,
     tprime: usize,
     l: usize,
+    rng: &mut (impl RngCore + CryptoRng),
 ) -> Result<(PublicParameters, Vec<Issuer>), AtACTError> {
-    if tprime < 2 || tprime >= n || t < 2 || t >= num_issuers {
+    check_parameters(num_issuers, n, t, tprime)?;
+
+    let sk = SecretKey::random(rng);
+    let mut pp = PublicParameters::new(sk.to_public_key(), n, t, tprime, l);
+    let sks = sk.into_shares(num_issuers, t, rng);
+    let dk = DecryptionKey::random(rng);
+    let dks = SecretKey { sk: dk.0 }.into_shares(num_issuers, t, rng);
+    let issuers: Vec<_> = sks
+        .into_iter()
+        .zip(dks)
+        .map(|(sk, dk)| Issuer {
+            dk: Some(DecryptionKey(dk.sk)),
+            ..Issuer::new(sk)
+        })
+        .collect();
+    pp.issuer_keys = issuer_keys(&issuers);
+    pp.issuer_pops = issuers.iter().map(|issuer| issuer.sk.pop()).collect();
+    pp.decryption_key = Some(dk.encryption_key());
+    pp.decryption_keys = decryption_keys(&issuers);
+    Ok((pp, issuers))
+}
+
+/// Reshares the issuer key held by `old_issuers` to a new committee of `new_num_issuers`
+/// issuers with threshold `new_t`. `old_issuers` are in the order returned by [setup], of which
+/// the first `pp.t` deal a sharing of their share. The public key in `pp` is preserved, so tokens
+/// issued by the old committee keep verifying, and `pp` is updated to the new threshold. The old
+/// shares have to be discarded afterwards.
+#[cfg(feature = "std")]
+pub fn reshare(
+    pp: &mut PublicParameters,
+    old_issuers: &[Issuer],
+    new_num_issuers: usize,
+    new_t: usize,
+) -> Result<Vec<Issuer>, AtACTError> {
+    check_parameters(new_num_issuers, pp.n, new_t, pp.tprime)?;
+    if old_issuers.len() < pp.t {
         return Err(AtACTError::InvalidParameters);
     }
 
-    let sk = SecretKey::new();
-    let pk = sk.to_public_key();
-    let scalars: Vec<_> = (1..=max(n, t) as u64).map(Scalar::from).collect();
+    let quorum = &old_issuers[..pp.t];
+    let sks = reshare_shares(
+        pp,
+        quorum.iter().map(|issuer| issuer.sk.sk),
+        new_num_issuers,
+        new_t,
+    );
+    // the decryption key is reshared the same way if the quorum holds shares of it
+    let dks = quorum
+        .iter()
+        .map(|issuer| issuer.dk.as_ref().map(|dk| dk.0))
+        .collect::<Option<Vec<_>>>()
+        .map(|dks| reshare_shares(pp, dks.into_iter(), new_num_issuers, new_t));
 
-    let pp = PublicParameters {
-        pk,
-        n,
-        t,
-        tprime,
-        lagrange_n: Lagrange::new(&scalars[..n]),
-        lagrange_t: Lagrange::new(&scalars[..t]),
-        lagrange_tprime: Lagrange::new(&scalars[..tprime]),
-        tsw_pp: tsw::PublicParameters::new(l + 1),
-    };
+    let scalars: Vec<_> = (1..=new_t as u64).map(Scalar::from).collect();
+    pp.t = new_t;
+    pp.lagrange_t = Lagrange::new(&scalars);
 
-    Ok((
-        pp,
-        sk.into_shares(num_issuers, t)
+    let issuers: Vec<_> = match dks {
+        Some(dks) => sks
             .into_iter()
-            .map(|sk| Issuer { sk })
+            .zip(dks)
+            .map(|(sk, dk)| Issuer {
+                dk: Some(DecryptionKey(dk)),
+                ..Issuer::new(SecretKey { sk })
+            })
             .collect(),
-    ))
+        None => {
+            pp.decryption_key = None;
+            sks.into_iter()
+                .map(|sk| Issuer::new(SecretKey { sk }))
+                .collect()
+        }
+    };
+    pp.issuer_keys = issuer_keys(&issuers);
+    pp.issuer_pops = issuers.iter().map(|issuer| issuer.sk.pop()).collect();
+    pp.decryption_keys = decryption_keys(&issuers);
+    pp.validated = OnceGlobal::new();
+    Ok(issuers)
+}
+
+// shares for `new_num_issuers` with threshold `new_t` of the secret of which `shares` are the
+// shares of the first `pp.t` issuers
+#[cfg(feature = "std")]
+fn reshare_shares(
+    pp: &PublicParameters,
+    shares: impl Iterator<Item = Scalar>,
+    new_num_issuers: usize,
+    new_t: usize,
+) -> Vec<Scalar> {
+    // sk = Σ_j λ_j sk_j, so the sum of sharings of the λ_j sk_j is a sharing of sk
+    let mut sks = vec![Scalar::zero(); new_num_issuers];
+    for (j, share) in shares.enumerate() {
+        let weighted = SecretKey {
+            sk: share * pp.lagrange_t.eval_j_0(j),
+        };
+        for (sk, share) in sks.iter_mut().zip(weighted.into_shares(
+            new_num_issuers,
+            new_t,
+            &mut rand::thread_rng(),
+        )) {
+            *sk += share.sk;
+        }
+    }
+    sks
+}
+
+/// The [PublicParameters::issuer_keys] of `issuers`.
+pub(crate) fn issuer_keys(issuers: &[Issuer]) -> Vec<PublicKey> {
+    issuers
+        .iter()
+        .map(|issuer| issuer.sk.to_public_key())
+        .collect()
 }
 
-#[derive(Clone)]
+/// The [PublicParameters::decryption_keys] of `issuers`, empty unless all of them hold a share.
+fn decryption_keys(issuers: &[Issuer]) -> Vec<EncryptionKey> {
+    issuers
+        .iter()
+        .map(|issuer| issuer.dk.as_ref().map(DecryptionKey::encryption_key))
+        .collect::<Option<_>>()
+        .unwrap_or_default()
+}
+
+#[derive(Clone, CanonicalSerialize, CanonicalDeserialize)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct StRG {
-    a: Scalar,
-    r: Scalar,
+    #[cfg_attr(
+        feature = "serde",
+        serde(with = "crate::bls381_helpers::serde_compressed")
+    )]
+    pub(crate) a: Scalar,
+    #[cfg_attr(
+        feature = "serde",
+        serde(with = "crate::bls381_helpers::serde_compressed")
+    )]
+    pub(crate) r: Scalar,
+    /// The attributes of [register_attributes] after the identity.
+    #[cfg_attr(
+        feature = "serde",
+        serde(with = "crate::bls381_helpers::serde_compressed")
+    )]
+    pub(crate) attributes: Vec<Scalar>,
+}
+
+impl StRG {
+    /// The identity followed by the other attributes.
+    pub(crate) fn messages(&self) -> Vec<Scalar> {
+        iter::once(self.a)
+            .chain(self.attributes.iter().copied())
+            .collect()
+    }
+
+    /// The commitment of [register_attributes] opened by `self`.
+    pub fn commitment(&self, pp: &PublicParameters) -> Commitment {
+        Commitment::commit_vec(&self.messages(), &self.r, &pp.attribute_bases).0
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl Zeroize for StRG {
+    fn zeroize(&mut self) {
+        self.a.zeroize();
+        self.r.zeroize();
+        self.attributes.zeroize();
+    }
+}
+
+pub fn register(
+    a: &Scalar,
+    pp: &PublicParameters,
+    rng: &mut (impl RngCore + CryptoRng),
+) -> Result<(StRG, Commitment), AtACTError> {
+    register_attributes(&[*a], pp, rng)
 }
 
-pub fn register(a: &Scalar, _pp: &PublicParameters) -> Result<(StRG, Commitment), AtACTError> {
+/// Like [register], but commits to the vector of `attributes` with [Commitment::commit_vec].
+/// The first attribute is the user's identity, which stays hidden; the others are disclosed to
+/// the registrar, see [verify_with_attributes]. Attributes missing at the end are zero.
+pub fn register_attributes(
+    attributes: &[Scalar],
+    pp: &PublicParameters,
+    rng: &mut (impl RngCore + CryptoRng),
+) -> Result<(StRG, Commitment), AtACTError> {
+    if attributes.is_empty() || attributes.len() > pp.num_attributes() {
+        return Err(AtACTError::InvalidAttribute);
+    }
+
     // Step 7
-    let (cm, opening) = Commitment::commit(a);
+    let (cm, opening) = Commitment::commit_vec(attributes, &Scalar::rand(rng), &pp.attribute_bases);
 
     Ok((
         StRG {
-            a: *a,
+            a: attributes[0],
             r: opening.r,
+            attributes: attributes[1..].to_vec(),
         },
         cm,
     ))
 }
 
+#[derive(CanonicalSerialize, CanonicalDeserialize)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct BlindRequest {
-    cm: Commitment,
-    cm_ks: Vec<Commitment>,
-    bold_cm_k: Commitment,
+    pub(crate) cm: Commitment,
+    pub(crate) cm_ks: Vec<Commitment>,
+    pub(crate) bold_cm_k: Commitment,
+    /// The [fingerprint](PublicParameters::fingerprint) of the parameters the request is built
+    /// for, which [tissue] checks against the issuer's.
+    pub(crate) fingerprint: [u8; 32],
 }
 
+impl BlindRequest {
+    /// The [fingerprint](PublicParameters::fingerprint) of the parameters the request is for.
+    pub fn fingerprint(&self) -> [u8; 32] {
+        self.fingerprint
+    }
+
+    /// The commitment `g^r u^a` to the identity, for the `disclosed` attributes after the first
+    /// of [register_attributes].
+    pub fn identity_commitment(
+        &self,
+        disclosed: &[Scalar],
+        pp: &PublicParameters,
+    ) -> Result<Commitment, AtACTError> {
+        if disclosed.len() >= pp.num_attributes() {
+            return Err(AtACTError::InvalidAttribute);
+        }
+        Ok(disclosed
+            .iter()
+            .enumerate()
+            .fold(self.cm.clone(), |cm, (i, attribute)| {
+                Commitment(cm.0 - &pp.attribute_bases[i] * *attribute)
+            }))
+    }
+}
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Rand {
     strg: StRG,
     r_ks: Vec<PublicKey>,
+    #[cfg_attr(
+        feature = "serde",
+        serde(with = "crate::bls381_helpers::serde_compressed")
+    )]
     bold_k: Scalar,
+    #[cfg_attr(
+        feature = "serde",
+        serde(with = "crate::bls381_helpers::serde_compressed")
+    )]
     bold_rk: Scalar,
 }
 
+impl Rand {
+    /// Number of tokens of the request the randomness blinds.
+    pub(crate) fn request_size(&self) -> usize {
+        self.r_ks.len()
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl Zeroize for Rand {
+    fn zeroize(&mut self) {
+        self.strg.zeroize();
+        for r_k in &mut self.r_ks {
+            r_k.0.zeroize();
+        }
+        self.bold_k.zeroize();
+        self.bold_rk.zeroize();
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl Drop for Rand {
+    fn drop(&mut self) {
+        self.zeroize();
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl ZeroizeOnDrop for Rand {}
+
 pub fn token_request(
     strg: &StRG,
     commitment: &Commitment,
     pp: &PublicParameters,
+    rng: &mut (impl RngCore + CryptoRng),
 ) -> Result<(BlindRequest, Rand), AtACTError> {
-    let mut rng = rand::thread_rng();
-
     // Step 8
     let mut rks = vec![];
     let mut coms = vec![];
     for _ in 0..(pp.tprime - 1) {
-        let ak = Scalar::rand(&mut rng);
-        let (cm_k, o_k) = Commitment::commit(&ak);
+        let ak = Scalar::rand(rng);
+        let (cm_k, o_k) = Commitment::commit_with_randomness(&ak, &Scalar::rand(rng));
         coms.push(cm_k);
-        rks.push(&pp.pk * o_k.r);
+        rks.push(pp.pk_mul(o_k.r));
     }
 
     // Step 9
-    let pk_r = &pp.pk * strg.r;
+    let pk_r = pp.pk_mul(strg.r);
 
     let base_points: Vec<_> = (1..=pp.tprime as u64).map(Scalar::from).collect();
     let mut lagrange = Lagrange::new(&base_points);
@@ -155,14 +763,16 @@ pub fn token_request(
     }
 
     // Step 10
-    let bold_k = Scalar::rand(&mut rng);
-    let (bold_cm_k, bold_cm_opening) = Commitment::commit(&bold_k);
+    let bold_k = Scalar::rand(rng);
+    let (bold_cm_k, bold_cm_opening) =
+        Commitment::commit_with_randomness(&bold_k, &Scalar::rand(rng));
 
     Ok((
         BlindRequest {
             cm: commitment.clone(),
             cm_ks: coms,
             bold_cm_k,
+            fingerprint: pp.fingerprint(),
         },
         Rand {
             strg: strg.clone(),
@@ -173,22 +783,66 @@ pub fn token_request(
     ))
 }
 
+#[derive(Debug, Clone, PartialEq, Eq, CanonicalSerialize, CanonicalDeserialize)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct BlindToken {
-    sigma: Signature,
+    pub(crate) sigma: Signature,
+}
+
+/// Indices of the `t` of `n` issuers that issue tokens in `epoch`, in ascending order, drawn from
+/// the public `beacon`, e.g. the output of a randomness beacon published before the epoch. As
+/// the choice is a function of public values, anyone can audit that a token was issued by the
+/// epoch's committee rather than by a subset that the user picked.
+pub fn select_committee(beacon: &[u8], epoch: u64, n: usize, t: usize) -> Vec<usize> {
+    let mut msg = Vec::with_capacity(beacon.len() + 16);
+    msg.extend_from_slice(&(beacon.len() as u64).to_le_bytes());
+    msg.extend_from_slice(beacon);
+    msg.extend_from_slice(&epoch.to_le_bytes());
+    let digest = Sha256::digest(hash_with_domain_separation(&msg, COMMITTEE_DST).to_bytes());
+    let mut seed = [0u8; 32];
+    seed.copy_from_slice(&digest);
+    let mut rng = ChaCha20Rng::from_seed(seed);
+
+    // Fisher-Yates, of which the last `t` positions are drawn
+    let mut indices: Vec<usize> = (0..n).collect();
+    for i in (n.saturating_sub(t).max(1)..n).rev() {
+        indices.swap(i, rng.gen_range(0..=i));
+    }
+    let mut committee = indices.split_off(n.saturating_sub(t));
+    committee.sort_unstable();
+    committee
 }
 
+/// Signs the commitments of `blind_request` with the key share of `prv_j`. Issuers with a
+/// [committee seat](Issuer::with_committee) reject requests in the epochs in which they are not
+/// in the committee. Requests for parameters of another
+/// [fingerprint](PublicParameters::fingerprint) than `pp`, e.g. from before a [reshare], fail
+/// with [AtACTError::StaleParameters]. The fingerprint is part of the request an
+/// [issuance log](Issuer::with_issuance_log) hashes.
 pub fn tissue(
     blind_request: &BlindRequest,
     prv_j: &Issuer,
     pp: &PublicParameters,
 ) -> Result<Vec<BlindToken>, AtACTError> {
+    if let Some(seat) = &prv_j.committee {
+        seat.check(pp.t)?;
+    }
+    if blind_request.fingerprint != pp.fingerprint() {
+        return Err(AtACTError::StaleParameters);
+    }
+    if blind_request.cm_ks.len() != pp.n {
+        return Err(AtACTError::InvalidRequestLength {
+            expected: pp.n,
+            actual: blind_request.cm_ks.len(),
+        });
+    }
     let check_cm = pp.lagrange_n.eval_0(blind_request.cm_ks.as_ref());
 
     if check_cm != blind_request.cm {
         return Err(AtACTError::InvalidCommitment);
     }
 
-    Ok(blind_request
+    let blind_tokens = blind_request
         .cm_ks
         .iter()
         .map(|commitment| BlindToken {
@@ -198,69 +852,233 @@ pub fn tissue(
                 &pp.tsw_pp,
             ),
         })
-        .collect())
+        .collect();
+    #[cfg(feature = "std")]
+    if let Some(log) = &prv_j.log {
+        log.record(blind_request);
+    }
+    Ok(blind_tokens)
 }
 
+/// [tissue] for every blind request of a batch, e.g. of all users onboarded together, which is
+/// sent to the issuer in a single message. The result of each request is independent of the
+/// others, so an invalid request fails alone.
+pub fn tissue_batch(
+    blind_requests: &[BlindRequest],
+    prv_j: &Issuer,
+    pp: &PublicParameters,
+) -> Vec<Result<Vec<BlindToken>, AtACTError>> {
+    blind_requests
+        .iter()
+        .map(|blind_request| tissue(blind_request, prv_j, pp))
+        .collect()
+}
+
+#[derive(CanonicalSerialize, CanonicalDeserialize)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Token {
-    s: Signature,
+    pub(crate) s: Signature,
     sks: Vec<Signature>,
 }
 
 impl Token {
+    /// The aggregated signature on the user's commitment.
+    pub fn signature(&self) -> &Signature {
+        &self.s
+    }
+
+    /// The `pp.tprime - 1` indices of the token's shares that a [TokenProof] opens.
     pub fn hash_prime(&self, pp: &PublicParameters) -> Vec<usize> {
-        let mut buffer = Vec::new();
-        self.s.0.serialize_uncompressed(&mut buffer).unwrap();
-        let mut hasher = sha3::Shake256::default();
-        hasher.update(&buffer);
-        let mut reader = hasher.finalize_xof();
-        debug_assert!(pp.n < 256);
-
-        let mask = pp.n.next_power_of_two() - 1;
-        let mut ret = vec![];
-        while ret.len() < pp.tprime - 1 {
-            let mut buffer = [0u8; 1];
-            reader.read(&mut buffer);
-            let value = (u8::from_le_bytes(buffer) as usize) & mask;
-            if value < pp.n && !ret.contains(&value) {
-                ret.push(value);
-            }
+        challenge_indices(&mut token_proof_transcript(self, pp), pp)
+    }
+}
+
+/// Transcript of a [TokenProof] of `token`, starting with the public parameters it depends on.
+fn token_proof_transcript(token: &Token, pp: &PublicParameters) -> ProofTranscript {
+    let mut transcript = ProofTranscript::new(TOKEN_PROOF_PROTOCOL);
+    transcript.append_message(b"dst", &pp.dst);
+    transcript.append_u64(b"n", pp.n as u64);
+    transcript.append_u64(b"tprime", pp.tprime as u64);
+    transcript.append_point(b"pk", &pp.pk.0);
+    transcript.append_point(b"s", &token.s.0);
+    transcript
+}
+
+/// Draws `pp.tprime - 1` distinct indices below `pp.n` as the challenge of `transcript`.
+fn challenge_indices(transcript: &mut ProofTranscript, pp: &PublicParameters) -> Vec<usize> {
+    let mut rng = transcript.challenge_rng(b"indices");
+    debug_assert!(pp.n < 256);
+
+    let mask = pp.n.next_power_of_two() - 1;
+    let mut ret = vec![];
+    while ret.len() < pp.tprime - 1 {
+        let mut buffer = [0u8; 1];
+        rng.fill_bytes(&mut buffer);
+        let value = (u8::from_le_bytes(buffer) as usize) & mask;
+        if value < pp.n && !ret.contains(&value) {
+            ret.push(value);
         }
-        ret
     }
+    ret
 }
 
+/// Appends the revealed parts of a [TokenProof], which its ZK proof is bound to.
+fn append_token_proof(
+    transcript: &mut ProofTranscript,
+    ss: &[Signature],
+    pk_prime: &PublicKey,
+    rs: &[PublicKey],
+) {
+    transcript.append_points(b"ss", ss.iter().map(|s| &s.0));
+    transcript.append_point(b"pk_prime", &pk_prime.0);
+    transcript.append_points(b"rs", rs.iter().map(|r| &r.0));
+}
+
+/// Unblinds the blind tokens of the first `pp.t` issuers, in issuer order. Fails if there are
+/// fewer, or if `rand` or the blind tokens of an issuer are not for `pp.n` commitments.
 pub fn aggregate_unblind(
     blind_tokens: &Vec<Vec<BlindToken>>,
     rand: &Rand,
     pp: &PublicParameters,
-) -> Token {
-    #[cfg(debug_assertions)]
+) -> Result<Token, AtACTError> {
+    let blind_tokens: Vec<_> = blind_tokens
+        .iter()
+        .take(pp.t)
+        .map(Vec::as_slice)
+        .enumerate()
+        .collect();
+    aggregate_unblind_with(&blind_tokens, &pp.lagrange_t, rand, pp)
+}
+
+/// Like [aggregate_unblind], but for the blind tokens of any `pp.t` distinct issuers, given with
+/// the index of the issuer in the committee, starting at 0. Fails like [aggregate_unblind], and
+/// if an issuer is given more than once among the first `pp.t`.
+pub fn aggregate_unblind_from(
+    blind_tokens: &[(usize, Vec<BlindToken>)],
+    rand: &Rand,
+    pp: &PublicParameters,
+) -> Result<Token, AtACTError> {
+    // the interpolation is of the sorted indices, the sum of the shares in any order
+    let mut blind_tokens: Vec<_> = blind_tokens.iter().take(pp.t).collect();
+    blind_tokens.sort_unstable_by_key(|(index, _)| *index);
+    let indices: Vec<_> = blind_tokens.iter().map(|(index, _)| *index).collect();
+    if let Some(pair) = indices.windows(2).find(|pair| pair[0] == pair[1]) {
+        return Err(AtACTError::DuplicateIssuer {
+            issuer_index: pair[0],
+        });
+    }
+    if indices.len() < pp.t {
+        return Err(AtACTError::NotEnoughIssuers {
+            required: pp.t,
+            available: indices.len(),
+        });
+    }
+    #[cfg(feature = "std")]
+    let lagrange = pp.issuer_lagrange(&indices);
+    #[cfg(not(feature = "std"))]
+    let lagrange = {
+        let points: Vec<_> = indices
+            .iter()
+            .map(|index| Scalar::from(*index as u64 + 1))
+            .collect();
+        Lagrange::new(&points)
+    };
+
+    let blind_tokens: Vec<_> = blind_tokens
+        .iter()
+        .map(|(index, tokens)| (*index, tokens.as_slice()))
+        .collect();
+    aggregate_unblind_with(&blind_tokens, &lagrange, rand, pp)
+}
+
+/// Unblinds the blind tokens of the issuers, given with their indices, whose shares are
+/// interpolated with `lagrange`.
+fn aggregate_unblind_with(
+    blind_tokens: &[(usize, &[BlindToken])],
+    lagrange: &Lagrange,
+    rand: &Rand,
+    pp: &PublicParameters,
+) -> Result<Token, AtACTError> {
+    let required = lagrange.points().len();
+    if blind_tokens.len() != required {
+        return Err(AtACTError::NotEnoughIssuers {
+            required,
+            available: blind_tokens.len(),
+        });
+    }
+    if rand.request_size() != pp.n {
+        return Err(AtACTError::InvalidRequestLength {
+            expected: pp.n,
+            actual: rand.request_size(),
+        });
+    }
+    if let Some((issuer_index, tokens)) =
+        blind_tokens.iter().find(|(_, tokens)| tokens.len() != pp.n)
     {
-        for blind_token in blind_tokens {
-            assert_eq!(blind_token.len(), pp.n);
-        }
+        return Err(AtACTError::InvalidBlindTokens {
+            issuer_index: *issuer_index,
+            expected: pp.n,
+            actual: tokens.len(),
+        });
     }
-    debug_assert_eq!(rand.r_ks.len(), pp.n);
+    let blind_tokens: Vec<_> = blind_tokens.iter().map(|(_, tokens)| *tokens).collect();
 
-    let sks: Vec<_> = (0..pp.n)
-        .into_par_iter()
+    #[cfg(feature = "rayon")]
+    let indices = (0..pp.n).into_par_iter();
+    #[cfg(not(feature = "rayon"))]
+    let indices = 0..pp.n;
+    let sks: Vec<_> = indices
         .map(|k| {
             let sigs: Vec<_> = blind_tokens
                 .iter()
-                .map(|token| &token[k].sigma)
-                .take(pp.t)
-                .cloned()
+                .map(|token| token[k].sigma.clone())
                 .collect();
-            Signature::from_shares(&sigs, &pp.lagrange_t) - &rand.r_ks[k]
+            Signature::from_shares(&sigs, lagrange) - &rand.r_ks[k]
         })
         .collect();
 
-    Token {
-        s: pp.lagrange_n.eval_0(&sks),
+    let shares: Vec<_> = sks.iter().map(|sk| sk.0.clone()).collect();
+    Ok(Token {
+        s: Signature(msm(&shares, pp.lagrange_n.coefficients_0())),
         sks,
+    })
+}
+
+/// Checks the blind tokens one issuer returned by [tissue] for `blind_request` against the
+/// issuer's public key `issuer_pk`, e.g. from [PublicParameters::issuer_keys], so that a
+/// misbehaving issuer is identified before its tokens spoil the aggregate.
+pub fn verify_blind_token(
+    blind_token: &[BlindToken],
+    issuer_pk: &PublicKey,
+    blind_request: &BlindRequest,
+    pp: &PublicParameters,
+) -> bool {
+    if blind_token.len() != pp.n || blind_request.cm_ks.len() != pp.n {
+        return false;
+    }
+    let messages: Vec<_> = blind_request
+        .cm_ks
+        .iter()
+        .zip(blind_token)
+        .map(|(commitment, token)| (commitment, UNIQUE_ATTRIBUTE_INDEX, &token.sigma))
+        .collect();
+
+    #[cfg(feature = "std")]
+    {
+        issuer_pk.verify_batch(&messages, &pp.tsw_pp).is_ok()
+    }
+    #[cfg(not(feature = "std"))]
+    {
+        messages.iter().all(|(commitment, index, sigma)| {
+            issuer_pk
+                .verify_pedersen_commitment(commitment, *index, sigma, &pp.tsw_pp)
+                .is_ok()
+        })
     }
 }
 
+#[derive(CanonicalSerialize, CanonicalDeserialize)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct TokenProof {
     ss: Vec<Signature>,
     pk_prime: PublicKey,
@@ -268,24 +1086,39 @@ pub struct TokenProof {
     pi_zk: Proof2PK,
 }
 
-pub fn prove(token: &Token, rand: &Rand, pp: &PublicParameters) -> TokenProof {
-    let c = token.hash_prime(pp);
+impl TokenProof {
+    /// Size in bytes of the compressed encoding of the proof: `n + tprime` points, besides those
+    /// of the [Proof2PK].
+    pub fn encoded_size(&self) -> usize {
+        self.compressed_size()
+    }
+}
+
+pub fn prove(
+    token: &Token,
+    rand: &Rand,
+    pp: &PublicParameters,
+    rng: &mut (impl RngCore + CryptoRng),
+) -> TokenProof {
+    let mut transcript = token_proof_transcript(token, pp);
+    let c = challenge_indices(&mut transcript, pp);
     debug_assert_eq!(c.len(), pp.tprime - 1);
 
-    let ss = token
+    let ss: Vec<_> = token
         .sks
         .iter()
         .map(|signature| signature * rand.bold_k)
         .collect();
-    let pk_prime = &pp.pk * rand.bold_k;
+    let pk_prime = pp.pk_mul(rand.bold_k);
     debug_assert!(pk_prime.is_valid());
 
-    let rs = c.iter().map(|k| &rand.r_ks[*k] * rand.bold_k).collect();
+    let rs: Vec<_> = c.iter().map(|k| &rand.r_ks[*k] * rand.bold_k).collect();
+    append_token_proof(&mut transcript, &ss, &pk_prime, &rs);
 
     let (cm, o) = Commitment::commit_with_randomness(&rand.strg.a, &rand.strg.r);
     let (bold_cm, bold_o) = Commitment::commit_with_randomness(&rand.bold_k, &rand.bold_rk);
 
-    let pi_zk = cm.proof_2_pk(
+    let pi_zk = cm.proof_2_pk_with_transcript(
         &rand.strg.a,
         &o,
         &bold_cm,
@@ -293,6 +1126,8 @@ pub fn prove(token: &Token, rand: &Rand, pp: &PublicParameters) -> TokenProof {
         &bold_o,
         &pp.pk.0,
         &pk_prime.0,
+        &mut transcript,
+        rng,
     );
 
     TokenProof {
@@ -303,24 +1138,82 @@ pub fn prove(token: &Token, rand: &Rand, pp: &PublicParameters) -> TokenProof {
     }
 }
 
+/// Verifies a token and its proof against the blind request it was issued for. The pairing
+/// equations of the token and of the opened signatures, and the equations of the proof of
+/// knowledge, are combined with random scalars from `rng`, which has to be unpredictable to the
+/// prover.
+#[cfg(feature = "std")]
 pub fn verify(
     token: &Token,
     token_proof: &TokenProof,
     blind_request: &BlindRequest,
     pp: &PublicParameters,
+    rng: &mut (impl RngCore + CryptoRng),
+) -> Result<(), AtACTError> {
+    verify_with_attributes(token, token_proof, blind_request, &[], pp, rng)
+}
+
+/// Like [verify], for a token of [register_attributes] whose attributes after the first are
+/// `disclosed`. The proof of knowledge covers only the identity, so it verifies only if the
+/// disclosed attributes are the committed ones.
+#[cfg(feature = "std")]
+pub fn verify_with_attributes(
+    token: &Token,
+    token_proof: &TokenProof,
+    blind_request: &BlindRequest,
+    disclosed: &[Scalar],
+    pp: &PublicParameters,
+    rng: &mut (impl RngCore + CryptoRng),
 ) -> Result<(), AtACTError> {
-    let c = token.hash_prime(pp);
+    let identity_commitment = blind_request.identity_commitment(disclosed, pp)?;
+    verify_with_identity(
+        token,
+        token_proof,
+        blind_request,
+        &identity_commitment,
+        pp,
+        rng,
+    )
+}
+
+/// Like [verify_with_attributes], with the commitment `g^r u^a` to the identity given instead
+/// of the disclosed attributes, for a request whose other attributes the verifier only knows
+/// committed, e.g. from a proof of knowledge of their part of `blind_request.cm`.
+#[cfg(feature = "std")]
+pub fn verify_with_identity(
+    token: &Token,
+    token_proof: &TokenProof,
+    blind_request: &BlindRequest,
+    identity_commitment: &Commitment,
+    pp: &PublicParameters,
+    rng: &mut (impl RngCore + CryptoRng),
+) -> Result<(), AtACTError> {
+    let mut transcript = token_proof_transcript(token, pp);
+    let c = challenge_indices(&mut transcript, pp);
     debug_assert_eq!(c.len(), pp.tprime - 1);
 
+    // the lengths come from the user and are checked before indexing
+    if blind_request.cm_ks.len() != pp.n {
+        return Err(AtACTError::InvalidRequestLength {
+            expected: pp.n,
+            actual: blind_request.cm_ks.len(),
+        });
+    }
+    if token_proof.ss.len() != pp.n || token_proof.rs.len() != c.len() {
+        return Err(AtACTError::InvalidProofLength);
+    }
+
     let pk_prime = &token_proof.pk_prime;
 
-    if blind_request
-        .cm
-        .verify_proof_2_pk(
+    append_token_proof(&mut transcript, &token_proof.ss, pk_prime, &token_proof.rs);
+    if identity_commitment
+        .verify_proof_2_pk_with_transcript(
             &blind_request.bold_cm_k,
             &pp.pk.0,
-            &token_proof.pk_prime.0,
+            &pk_prime.0,
+            &mut transcript,
             &token_proof.pi_zk,
+            rng,
         )
         .is_err()
     {
@@ -331,34 +1224,18 @@ pub fn verify(
         .map(|k| &token_proof.ss[k] * pp.lagrange_tprime.eval_j_0(k))
         .sum();
     let sk_prod = -sk_prod.0;
-    if !multi_pairing(&[(&token.s.0, &token_proof.pk_prime.0), (&sk_prod, &pp.pk.0)]).is_zero()
-        || !multi_pairing(&[(&token_proof.pk_prime.0, &token.s.0), (&pp.pk.0, &sk_prod)]).is_zero()
-    {
-        return Err(AtACTError::InvalidToken);
-    }
 
     let mut base_points: Vec<_> = c.iter().map(|k| Scalar::from(*k as u64 + 1)).collect();
     base_points.push(Scalar::from(u64::MAX));
     let mut lagrange = Lagrange::new(&base_points);
 
+    let mut sigmas = Vec::with_capacity(c.len());
     for k in 0..pp.n {
         if let Some(k_index) = c.iter().position(|v| k == *v) {
-            // 29.e
+            // 29.e, checked in one batch below
             let rk_prime = &token_proof.rs[k_index];
             let sk_prime = &token_proof.ss[k];
-
-            let sigma = sk_prime + rk_prime;
-            if pk_prime
-                .verify_pedersen_commitment(
-                    &blind_request.cm_ks[k],
-                    UNIQUE_ATTRIBUTE_INDEX,
-                    &sigma,
-                    &pp.tsw_pp,
-                )
-                .is_err()
-            {
-                return Err(AtACTError::InvalidSignature(k));
-            }
+            sigmas.push((k, sk_prime + rk_prime));
         } else {
             lagrange.update_point(pp.tprime - 1, Scalar::from(k as u64 + 1));
 
@@ -377,9 +1254,114 @@ pub fn verify(
         }
     }
 
+    let messages: Vec<_> = sigmas
+        .iter()
+        .map(|(k, sigma)| (&blind_request.cm_ks[*k], UNIQUE_ATTRIBUTE_INDEX, sigma))
+        .collect();
+    if !pairing_equations_hold(&token.s.0, &sk_prod, pk_prime, &messages, pp, rng) {
+        // a valid token with valid signatures always passes, so one of the equations fails
+        if !multi_pairing(&[(&token.s.0, &pk_prime.0), (&sk_prod, &pp.pk.0)]).is_zero()
+            || !multi_pairing(&[(&pk_prime.0, &token.s.0), (&pp.pk.0, &sk_prod)]).is_zero()
+        {
+            return Err(AtACTError::InvalidToken);
+        }
+        let is_invalid =
+            |(message, (k, _)): (&(&Commitment, usize, &Signature), &(usize, Signature))| {
+                let (commitment, index, sigma) = message;
+                pk_prime
+                    .verify_pedersen_commitment(commitment, *index, sigma, &pp.tsw_pp)
+                    .is_err()
+                    .then_some(*k)
+            };
+        #[cfg(feature = "rayon")]
+        let invalid = {
+            use rayon::iter::{IndexedParallelIterator, IntoParallelRefIterator};
+
+            messages.par_iter().zip(&sigmas).find_map_first(is_invalid)
+        };
+        #[cfg(not(feature = "rayon"))]
+        let invalid = messages.iter().zip(&sigmas).find_map(is_invalid);
+        return Err(invalid.map_or(AtACTError::InvalidToken, AtACTError::InvalidSignature));
+    }
+
     Ok(())
 }
 
+/// Whether the token's `s` satisfies `e(s, pk') = e(sk, pk)` for the interpolated signatures
+/// `sk = -neg_sk`, and the `messages` are signed under `pk'`. Like [PublicKey::verify_batch],
+/// both sides of every equation are paired in both groups, and all equations are combined with
+/// random 128-bit scalars from `rng` into a single multi-pairing of six pairs. If one of them
+/// does not hold, the check passes with probability at most 2^-127. With the `rayon` feature,
+/// the multi-scalar multiplications of the terms run in parallel.
+#[cfg(feature = "std")]
+fn pairing_equations_hold(
+    s: &G1G2,
+    neg_sk: &G1G2,
+    pk_prime: &PublicKey,
+    messages: &[(&Commitment, usize, &Signature)],
+    pp: &PublicParameters,
+    rng: &mut (impl RngCore + CryptoRng),
+) -> bool {
+    let g = &get_parameters().g;
+    let rhos: Vec<_> = iter::repeat_with(|| Scalar::from(rng.r#gen::<u128>()))
+        .take(messages.len() + 1)
+        .collect();
+    let rho_dual = Scalar::from(rng.r#gen::<u128>());
+
+    // the terms paired with pk', g and pk, first with their G1 and then with their G2 parts
+    let mut points: Vec<_> = messages
+        .iter()
+        .map(|(commitment, index, _)| -(&pp.tsw_pp[*index] + &commitment.0))
+        .collect();
+    points.push(s.clone());
+    let signatures: Vec<_> = messages
+        .iter()
+        .map(|(_, _, signature)| signature.0.clone())
+        .collect();
+    #[cfg(feature = "rayon")]
+    let (on_pk_prime, on_g) = rayon::join(
+        || msm(&points, &rhos),
+        || msm(&signatures, &rhos[..messages.len()]),
+    );
+    #[cfg(not(feature = "rayon"))]
+    let (on_pk_prime, on_g) = (
+        msm(&points, &rhos),
+        msm(&signatures, &rhos[..messages.len()]),
+    );
+    let on_pk = neg_sk * rhos[messages.len()];
+    let dual = [&on_pk_prime * rho_dual, &on_g * rho_dual, &on_pk * rho_dual];
+
+    multi_pairing(&[
+        (&on_pk_prime, &pk_prime.0),
+        (&on_g, g),
+        (&on_pk, &pp.pk.0),
+        (&pk_prime.0, &dual[0]),
+        (g, &dual[1]),
+        (&pp.pk.0, &dual[2]),
+    ])
+    .is_zero()
+}
+
+#[derive(Error, Debug, PartialEq, Eq, Clone)]
+pub enum ParamError {
+    #[error("Invalid committee sizes: n = {n}, t = {t}, t' = {tprime}.")]
+    InvalidSizes { n: usize, t: usize, tprime: usize },
+    #[error("Invalid interpolation points.")]
+    InvalidLagrange,
+    #[error("Invalid public key.")]
+    InvalidPublicKey,
+    #[error("Invalid public key of issuer {0}.")]
+    InvalidIssuerKey(usize),
+    #[error("Invalid generators for {num_attributes} attributes.")]
+    InvalidGenerators { num_attributes: usize },
+    #[error("The public key is not the aggregate of the issuers' keys.")]
+    InconsistentIssuerKeys,
+    #[error("The precomputed table is not of the public key.")]
+    InvalidPrecomputation,
+    #[error("Invalid or missing proof of possession of the key of issuer {0}.")]
+    InvalidProofOfPossession(usize),
+}
+
 #[derive(Error, Debug, PartialEq, Clone)]
 pub enum AtACTError {
     #[error("Invalid parameters.")]
@@ -398,16 +1380,139 @@ pub enum AtACTError {
     InvalidToken,
     #[error("Invalid ZK proof.")]
     InvalidZKProof,
+    #[error("Invalid blind request: expected {expected} commitments, got {actual}.")]
+    InvalidRequestLength { expected: usize, actual: usize },
+    #[error("Invalid number of signatures or keys in the token proof.")]
+    InvalidProofLength,
     #[error("Invalid token proof: {0:?}")]
     InvalidProof(Vec<AtACTError>),
     #[error("Unknown error")]
     UnknownError,
+    #[error("The issuer is not in the committee of epoch {epoch}.")]
+    NotInCommittee { epoch: u64 },
+    #[error("Not enough issuers: {available} available, {required} required.")]
+    NotEnoughIssuers { required: usize, available: usize },
+    #[error("Issuer {issuer_index} returned {actual} blind tokens, {expected} expected.")]
+    InvalidBlindTokens {
+        issuer_index: usize,
+        expected: usize,
+        actual: usize,
+    },
+    #[error("Issuer {issuer_index} is chosen more than once.")]
+    DuplicateIssuer { issuer_index: usize },
+    #[error("The blind request is for other parameters.")]
+    StaleParameters,
 }
 
 #[cfg(test)]
 mod test {
+    use alloc::collections::BTreeSet;
+
     use super::*;
 
+    #[cfg(feature = "zeroize")]
+    #[test]
+    fn rand_zeroized_on_drop() {
+        use crate::bls381_helpers::test::assert_zeroized_on_drop;
+
+        let mut rng = rand::thread_rng();
+        let pp = PublicParameters::new(SecretKey::new().to_public_key(), 8, 4, 4, 1);
+        let (strg, cm) = register(&Scalar::from(1u64), &pp, &mut rng).unwrap();
+        let (_, rand) = token_request(&strg, &cm, &pp, &mut rng).unwrap();
+        assert_zeroized_on_drop(rand, |rand| {
+            vec![rand.strg.a, rand.strg.r, rand.bold_k, rand.bold_rk]
+        });
+    }
+
+    #[test]
+    fn batched_pairings_reject_any_failing_equation() {
+        let mut rng = rand::thread_rng();
+        let g = &get_parameters().g;
+        let (x, y, z) = (
+            Scalar::rand(&mut rng),
+            Scalar::rand(&mut rng),
+            Scalar::rand(&mut rng),
+        );
+        // e(g^xz, g^y) = e(g^yz, g^x)
+        let pp = PublicParameters::new(PublicKey(g * x), 4, 2, 2, 1);
+        let sk_prime = SecretKey { sk: y };
+        let pk_prime = sk_prime.to_public_key();
+        let s = g * (x * z);
+        let neg_sk = -(g * (y * z));
+        let commitments: Vec<_> = (0..3)
+            .map(|m| Commitment::commit(&Scalar::from(m as u64)).0)
+            .collect();
+        let signatures: Vec<_> = commitments
+            .iter()
+            .map(|cm| sk_prime.sign_pedersen_commitment(cm, UNIQUE_ATTRIBUTE_INDEX, &pp.tsw_pp))
+            .collect();
+        let mut holds = |s: &G1G2, neg_sk: &G1G2, signatures: &[Signature]| {
+            let messages: Vec<_> = commitments
+                .iter()
+                .zip(signatures)
+                .map(|(cm, signature)| (cm, UNIQUE_ATTRIBUTE_INDEX, signature))
+                .collect();
+            pairing_equations_hold(s, neg_sk, &pk_prime, &messages, &pp, &mut rng)
+        };
+        assert!(holds(&s, &neg_sk, &signatures));
+
+        // a changed G1 or G2 part fails only one of the two pairings of an equation
+        let shifts: [fn(&G1G2) -> G1G2; 2] = [
+            |point: &G1G2| G1G2(point.0 + get_parameters().g.0, point.1),
+            |point: &G1G2| G1G2(point.0, point.1 + get_parameters().g.1),
+        ];
+        for shift in shifts {
+            let mut forged = signatures.clone();
+            forged[1] = Signature(shift(&forged[1].0));
+            for _ in 0..16 {
+                assert!(!holds(&shift(&s), &neg_sk, &signatures));
+                assert!(!holds(&s, &shift(&neg_sk), &signatures));
+                assert!(!holds(&s, &neg_sk, &forged));
+            }
+        }
+    }
+
+    #[test]
+    fn precomputed_tables_are_installed() {
+        let mut rng = rand::thread_rng();
+        let (pp, _) = setup(4, 4, 2, 2, 1, &mut rng).expect("setup failed");
+        let mut bytes = Vec::new();
+        pp.precompute().serialize_compressed(&mut bytes).unwrap();
+        let table = Precomputed::deserialize_compressed(&bytes[..]).unwrap();
+
+        let scalar = Scalar::rand(&mut rng);
+        let blinded = pp.pk_mul(scalar);
+        assert_eq!(blinded, &pp.pk * scalar);
+
+        let (other, _) = setup(4, 4, 2, 2, 1, &mut rng).expect("setup failed");
+        assert!(matches!(
+            other.with_precomputed(table.clone()),
+            Err(ParamError::InvalidPrecomputation)
+        ));
+        // a table of the key whose second and third multiples are swapped
+        let mut swapped = bytes.clone();
+        let entry = |index: usize| {
+            let start = G1G2::ENCODED_SIZE + 8 + index * G1G2::ENCODED_SIZE;
+            start..start + G1G2::ENCODED_SIZE
+        };
+        swapped[entry(2)].copy_from_slice(&bytes[entry(3)]);
+        swapped[entry(3)].copy_from_slice(&bytes[entry(2)]);
+        let swapped = Precomputed::deserialize_compressed(&swapped[..]).unwrap();
+        assert_eq!(swapped.base(), table.base());
+        assert!(matches!(
+            PublicParameters::new(pp.pk.clone(), 4, 2, 2, 1).with_precomputed(swapped),
+            Err(ParamError::InvalidPrecomputation)
+        ));
+        let mut pp = PublicParameters::new(pp.pk.clone(), 4, 2, 2, 1)
+            .with_precomputed(table)
+            .expect("table rejected");
+        assert_eq!(pp.pk_mul(scalar), blinded);
+
+        // a replaced key is not multiplied with the stale table
+        pp.pk = SecretKey::new().to_public_key();
+        assert_eq!(pp.pk_mul(scalar), &pp.pk * scalar);
+    }
+
     #[test]
     fn basic() {
         const NUM_ISSUERS: usize = 8;
@@ -421,12 +1526,12 @@ mod test {
             .map(|_| Scalar::rand(&mut rng))
             .collect();
 
-        let (pp, issuers) = setup(NUM_ISSUERS, N, T, TPRIME, 1).expect("setup failed");
+        let (pp, issuers) = setup(NUM_ISSUERS, N, T, TPRIME, 1, &mut rng).expect("setup failed");
 
         for a in attributes {
-            let (strg, cm) = register(&a, &pp).expect("register failed");
+            let (strg, cm) = register(&a, &pp, &mut rng).expect("register failed");
             let (blind_request, rand) =
-                token_request(&strg, &cm, &pp).expect("token request failed");
+                token_request(&strg, &cm, &pp, &mut rng).expect("token request failed");
             let mut blind_tokens = vec![];
             for issuer in &issuers {
                 let blind_token = tissue(&blind_request, issuer, &pp).expect("tissue failed");
@@ -434,24 +1539,629 @@ mod test {
                 blind_tokens.push(blind_token);
             }
 
-            let token = aggregate_unblind(&blind_tokens, &rand, &pp);
-            let token_proof = prove(&token, &rand, &pp);
-            assert_eq!(verify(&token, &token_proof, &blind_request, &pp), Ok(()));
+            let token = aggregate_unblind(&blind_tokens, &rand, &pp).expect("unblinding failed");
+            let token_proof = prove(&token, &rand, &pp, &mut rng);
+            assert_eq!(
+                verify(&token, &token_proof, &blind_request, &pp, &mut rng),
+                Ok(())
+            );
         }
     }
 
+    #[test]
+    fn aggregate_from_any_issuers() {
+        let mut rng = rand::thread_rng();
+        let (pp, issuers) = setup(6, 4, 3, 2, 1, &mut rng).expect("setup failed");
+
+        let (strg, cm) = register(&Scalar::rand(&mut rng), &pp, &mut rng).expect("register failed");
+        let (blind_request, rand) =
+            token_request(&strg, &cm, &pp, &mut rng).expect("token request failed");
+        let blind_tokens: Vec<_> = [5, 1, 3]
+            .into_iter()
+            .map(|index| {
+                let blind_token =
+                    tissue(&blind_request, &issuers[index], &pp).expect("tissue failed");
+                (index, blind_token)
+            })
+            .collect();
+
+        let token = aggregate_unblind_from(&blind_tokens, &rand, &pp).expect("unblinding failed");
+        let token_proof = prove(&token, &rand, &pp, &mut rng);
+        assert_eq!(
+            verify(&token, &token_proof, &blind_request, &pp, &mut rng),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn unblinding_checks_lengths() {
+        let mut rng = rand::thread_rng();
+        let (pp, issuers) = setup(6, 4, 3, 2, 1, &mut rng).expect("setup failed");
+
+        let (strg, cm) = register(&Scalar::rand(&mut rng), &pp, &mut rng).expect("register failed");
+        let (blind_request, mut rand) =
+            token_request(&strg, &cm, &pp, &mut rng).expect("token request failed");
+        let blind_tokens: Vec<_> = issuers
+            .iter()
+            .map(|issuer| tissue(&blind_request, issuer, &pp).expect("tissue failed"))
+            .collect();
+        let from: Vec<_> = blind_tokens.iter().cloned().enumerate().collect();
+        let unblind = |blind_tokens: &Vec<Vec<BlindToken>>, rand: &Rand| {
+            aggregate_unblind(blind_tokens, rand, &pp).map(|_| ())
+        };
+        let unblind_from = |blind_tokens: &[(usize, Vec<BlindToken>)], rand: &Rand| {
+            aggregate_unblind_from(blind_tokens, rand, &pp).map(|_| ())
+        };
+
+        // the blind tokens of too few issuers
+        let too_few = Err(AtACTError::NotEnoughIssuers {
+            required: 3,
+            available: 2,
+        });
+        assert_eq!(unblind(&blind_tokens[..2].to_vec(), &rand), too_few);
+        assert_eq!(unblind_from(&from[..2], &rand), too_few);
+
+        // an issuer given twice
+        let twice = [from[1].clone(), from[0].clone(), from[1].clone()];
+        assert_eq!(
+            unblind_from(&twice, &rand),
+            Err(AtACTError::DuplicateIssuer { issuer_index: 1 })
+        );
+
+        // the blind tokens of an issuer for fewer commitments
+        let mut truncated = blind_tokens.clone();
+        truncated[1].pop();
+        let truncated_from: Vec<_> = truncated.iter().cloned().enumerate().skip(1).collect();
+        let invalid = Err(AtACTError::InvalidBlindTokens {
+            issuer_index: 1,
+            expected: 4,
+            actual: 3,
+        });
+        assert_eq!(unblind(&truncated, &rand), invalid);
+        assert_eq!(unblind_from(&truncated_from, &rand), invalid);
+
+        // the randomness of a request for fewer commitments
+        assert_eq!(unblind(&blind_tokens, &rand), Ok(()));
+        rand.r_ks.pop();
+        let short_request = Err(AtACTError::InvalidRequestLength {
+            expected: 4,
+            actual: 3,
+        });
+        assert_eq!(unblind(&blind_tokens, &rand), short_request);
+        assert_eq!(unblind_from(&from, &rand), short_request);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn cached_interpolations_aggregate_like_fresh_ones() {
+        let mut rng = rand::thread_rng();
+        let (pp, issuers) = setup(6, 4, 3, 2, 1, &mut rng).expect("setup failed");
+
+        let (strg, cm) = register(&Scalar::rand(&mut rng), &pp, &mut rng).expect("register failed");
+        let (blind_request, rand) =
+            token_request(&strg, &cm, &pp, &mut rng).expect("token request failed");
+        let blind_tokens: Vec<_> = issuers
+            .iter()
+            .map(|issuer| tissue(&blind_request, issuer, &pp).expect("tissue failed"))
+            .collect();
+
+        // the repeated quorums, in any order, hit the cache
+        for quorum in [[5, 1, 3], [1, 3, 5], [0, 2, 4], [3, 5, 1]] {
+            let points: Vec<_> = quorum
+                .iter()
+                .map(|index| Scalar::from(*index as u64 + 1))
+                .collect();
+            let fresh = Lagrange::new(&points);
+            for j in 0..points.len() {
+                assert_eq!(fresh.eval_j_0(j), fresh.eval_j(Scalar::zero(), j));
+            }
+            let shares: Vec<_> = quorum
+                .iter()
+                .map(|index| (*index, blind_tokens[*index].as_slice()))
+                .collect();
+            let expected =
+                aggregate_unblind_with(&shares, &fresh, &rand, &pp).expect("unblinding failed");
+
+            let quorum: Vec<_> = quorum
+                .iter()
+                .map(|index| (*index, blind_tokens[*index].clone()))
+                .collect();
+            let token = aggregate_unblind_from(&quorum, &rand, &pp).expect("unblinding failed");
+            assert_eq!((&token.s, &token.sks), (&expected.s, &expected.sks));
+        }
+        assert_eq!(pp.lagrange_cache.len(), 2);
+        assert_eq!(
+            pp.issuer_lagrange(&[1, 3, 5]).points(),
+            [2u64, 4, 6].map(Scalar::from)
+        );
+    }
+
+    #[test]
+    fn blind_tokens_verify_against_issuer_keys() {
+        let mut rng = rand::thread_rng();
+        let (pp, issuers) = setup(4, 4, 3, 2, 1, &mut rng).expect("setup failed");
+        assert_eq!(pp.issuer_keys.len(), 4);
+
+        let (strg, cm) = register(&Scalar::rand(&mut rng), &pp, &mut rng).expect("register failed");
+        let (blind_request, _) =
+            token_request(&strg, &cm, &pp, &mut rng).expect("token request failed");
+        let blind_token = tissue(&blind_request, &issuers[1], &pp).expect("tissue failed");
+        assert!(verify_blind_token(
+            &blind_token,
+            &pp.issuer_keys[1],
+            &blind_request,
+            &pp
+        ));
+        assert!(!verify_blind_token(
+            &blind_token,
+            &pp.issuer_keys[2],
+            &blind_request,
+            &pp
+        ));
+        assert!(!verify_blind_token(
+            &blind_token[1..],
+            &pp.issuer_keys[1],
+            &blind_request,
+            &pp
+        ));
+
+        let rogue = Issuer::new(SecretKey::new());
+        let blind_token = tissue(&blind_request, &rogue, &pp).expect("tissue failed");
+        assert!(!verify_blind_token(
+            &blind_token,
+            &pp.issuer_keys[1],
+            &blind_request,
+            &pp
+        ));
+    }
+
+    #[test]
+    fn malformed_parameters_are_rejected() {
+        let valid = || {
+            setup(5, 4, 3, 2, 2, &mut rand::thread_rng())
+                .expect("setup failed")
+                .0
+        };
+        let pp = valid();
+        assert_eq!(pp.validate(), Ok(()));
+        let invalid_sizes = Err(ParamError::InvalidSizes {
+            n: 4,
+            t: 3,
+            tprime: 2,
+        });
+
+        let mut pp = valid();
+        pp.t = 5;
+        assert_eq!(
+            pp.validate(),
+            Err(ParamError::InvalidSizes {
+                n: 4,
+                t: 5,
+                tprime: 2
+            })
+        );
+        let mut pp = valid();
+        pp.issuer_keys.truncate(3);
+        assert_eq!(pp.validate(), invalid_sizes);
+
+        let mut pp = valid();
+        pp.lagrange_t.update_point(1, Scalar::from(7u64));
+        assert_eq!(pp.validate(), Err(ParamError::InvalidLagrange));
+
+        let mut pp = valid();
+        pp.pk = PublicKey(G1G2::default());
+        assert_eq!(pp.validate(), Err(ParamError::InvalidPublicKey));
+        // the G1 and G2 parts of the key have different discrete logarithms
+        let mut pp = valid();
+        pp.pk.0.1 *= Scalar::from(2u64);
+        assert_eq!(pp.validate(), Err(ParamError::InvalidPublicKey));
+        let mut pp = valid();
+        pp.issuer_keys[2] = PublicKey(G1G2(pp.pk.0.0, Default::default()));
+        assert_eq!(pp.validate(), Err(ParamError::InvalidIssuerKey(2)));
+
+        let mut pp = valid();
+        pp.tsw_pp = tsw::PublicParameters::new(2);
+        assert_eq!(
+            pp.validate(),
+            Err(ParamError::InvalidGenerators { num_attributes: 2 })
+        );
+        let mut pp = valid();
+        pp.attribute_bases = MultiBasePublicParameters::new(2);
+        assert_eq!(
+            pp.validate(),
+            Err(ParamError::InvalidGenerators { num_attributes: 3 })
+        );
+
+        let mut pp = valid();
+        pp.pk = SecretKey::new().to_public_key();
+        assert_eq!(pp.validate(), Err(ParamError::InconsistentIssuerKeys));
+        let mut pp = valid();
+        pp.issuer_keys.swap(3, 4);
+        assert_eq!(pp.validate(), Err(ParamError::InconsistentIssuerKeys));
+        // parameters without the issuers' keys, e.g. of a user
+        let mut pp = valid();
+        pp.issuer_keys.clear();
+        assert_eq!(pp.validate(), Ok(()));
+
+        // the result of the first check is kept
+        let mut pp = valid();
+        assert_eq!(pp.validate_cached(), Ok(()));
+        pp.t = 5;
+        assert_eq!(pp.validate_cached(), Ok(()));
+        let mut pp = valid();
+        pp.t = 5;
+        assert!(pp.validate_cached().is_err());
+    }
+
+    #[test]
+    fn rogue_issuer_key() {
+        let (mut pp, issuers) =
+            setup(5, 4, 3, 2, 1, &mut rand::thread_rng()).expect("setup failed");
+        for issuer in &issuers {
+            assert!(issuer.sk.to_public_key().verify_pop(&issuer.sk.pop()));
+        }
+
+        assert_eq!(pp.validate(), Ok(()));
+
+        // the third issuer announces a key that cancels the other two of the first quorum, so
+        // that the quorum's aggregate is a key `g^x` it knows the secret of, and the keys of
+        // the other issuers that follow from the quorum's
+        let x = SecretKey::new();
+        let others: G1G2 = (0..2)
+            .map(|j| &pp.issuer_keys[j].0 * pp.lagrange_t.eval_j_0(j))
+            .sum();
+        let lambda = pp
+            .lagrange_t
+            .eval_j_0(2)
+            .inverse()
+            .expect("Lagrange coefficients are nonzero");
+        let rogue = PublicKey(&(&x.to_public_key().0 - &others) * lambda);
+        pp.issuer_keys[2] = rogue;
+        pp.pk = x.to_public_key();
+        let keys: Vec<_> = pp.issuer_keys[..3].iter().map(|pk| pk.0.clone()).collect();
+        assert_eq!(pp.lagrange_t.eval_0(&keys), x.to_public_key().0);
+        for (j, key) in pp.issuer_keys.iter_mut().enumerate().skip(3) {
+            *key = PublicKey(pp.lagrange_t.eval(Scalar::from(j as u64 + 1), &keys));
+        }
+
+        // the keys are consistent, but the rogue key has no proof of possession
+        assert_eq!(pp.validate(), Err(ParamError::InvalidProofOfPossession(2)));
+        pp.issuer_pops[2] = x.pop();
+        assert_eq!(pp.validate(), Err(ParamError::InvalidProofOfPossession(2)));
+        pp.issuer_pops.truncate(2);
+        assert_eq!(pp.validate(), Err(ParamError::InvalidProofOfPossession(2)));
+    }
+
+    #[test]
+    fn batch_requests_fail_alone() {
+        let mut rng = rand::thread_rng();
+        let (pp, issuers) = setup(4, 4, 2, 2, 1, &mut rng).expect("setup failed");
+        let mut blind_requests = Vec::new();
+        for id in 1..=3u64 {
+            let (strg, cm) = register(&Scalar::from(id), &pp, &mut rng).unwrap();
+            blind_requests.push(token_request(&strg, &cm, &pp, &mut rng).unwrap().0);
+        }
+        blind_requests[1].cm_ks.pop();
+
+        let responses = tissue_batch(&blind_requests, &issuers[0], &pp);
+        assert_eq!(responses.len(), 3);
+        for (blind_request, response) in blind_requests.iter().zip(responses) {
+            assert_eq!(response, tissue(blind_request, &issuers[0], &pp));
+        }
+        assert!(tissue(&blind_requests[0], &issuers[0], &pp).is_ok());
+        assert_eq!(
+            tissue(&blind_requests[1], &issuers[0], &pp),
+            Err(AtACTError::InvalidRequestLength {
+                expected: pp.n,
+                actual: pp.n - 1
+            })
+        );
+    }
+
+    #[test]
+    fn committees_are_deterministic_and_uniform() {
+        const EPOCHS: u64 = 300;
+        const N: usize = 6;
+        const T: usize = 3;
+
+        let committee = select_committee(b"beacon", 7, N, T);
+        assert_eq!(committee, select_committee(b"beacon", 7, N, T));
+        assert_eq!(committee.len(), T);
+        assert!(committee.windows(2).all(|pair| pair[0] < pair[1]));
+        assert!(committee.iter().all(|index| *index < N));
+        assert_eq!(select_committee(b"beacon", 7, N, N), [0, 1, 2, 3, 4, 5]);
+        assert!(select_committee(b"beacon", 7, N, 0).is_empty());
+
+        // every issuer is in about half of the committees, 4 standard deviations of 8.7 apart
+        let mut seats = [0u64; N];
+        let mut distinct = BTreeSet::new();
+        for epoch in 0..EPOCHS {
+            let committee = select_committee(b"beacon", epoch, N, T);
+            for index in &committee {
+                seats[*index] += 1;
+            }
+            distinct.insert(committee);
+        }
+        let expected = EPOCHS * T as u64 / N as u64;
+        for count in seats {
+            assert!(count.abs_diff(expected) < 35, "{seats:?}");
+        }
+        // all 20 committees of 3 of 6 issuers occur
+        assert_eq!(distinct.len(), 20);
+        assert_ne!(
+            (0..10)
+                .map(|epoch| select_committee(b"beacon", epoch, N, T))
+                .collect::<Vec<_>>(),
+            (0..10)
+                .map(|epoch| select_committee(b"other", epoch, N, T))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn issuers_outside_the_committee_reject_requests() {
+        const NUM_ISSUERS: usize = 4;
+
+        let mut rng = rand::thread_rng();
+        let (pp, issuers) = setup(NUM_ISSUERS, 4, 2, 2, 1, &mut rng).expect("setup failed");
+        let mut issuers: Vec<_> = issuers
+            .into_iter()
+            .enumerate()
+            .map(|(index, issuer)| issuer.with_committee(b"beacon".to_vec(), index, NUM_ISSUERS))
+            .collect();
+        let (strg, cm) = register(&Scalar::rand(&mut rng), &pp, &mut rng).unwrap();
+        let (blind_request, _) = token_request(&strg, &cm, &pp, &mut rng).unwrap();
+
+        for epoch in [0, 1, 2] {
+            let committee = select_committee(b"beacon", epoch, NUM_ISSUERS, pp.t);
+            for (index, issuer) in issuers.iter_mut().enumerate() {
+                issuer.advance_epoch(epoch);
+                let issued = tissue(&blind_request, issuer, &pp);
+                assert_eq!(issuer.in_committee(&pp), committee.contains(&index));
+                if committee.contains(&index) {
+                    assert!(issued.is_ok());
+                } else {
+                    assert_eq!(issued, Err(AtACTError::NotInCommittee { epoch }));
+                }
+            }
+        }
+
+        // issuers without a seat sign in every epoch
+        let (_, unseated) = setup(NUM_ISSUERS, 4, 2, 2, 1, &mut rng).expect("setup failed");
+        assert!(unseated.iter().all(|issuer| issuer.in_committee(&pp)));
+    }
+
+    #[test]
+    fn three_attributes() {
+        let mut rng = rand::thread_rng();
+        let (pp, issuers) = setup(4, 4, 2, 2, 3, &mut rng).expect("setup failed");
+        assert_eq!(pp.num_attributes(), 3);
+
+        let attributes: Vec<_> = (0..3).map(|_| Scalar::rand(&mut rng)).collect();
+        let (strg, cm) = register_attributes(&attributes, &pp, &mut rng).expect("register failed");
+        assert_eq!(
+            cm,
+            Commitment::commit_vec(&attributes, &strg.r, &pp.attribute_bases).0
+        );
+        let (blind_request, rand) =
+            token_request(&strg, &cm, &pp, &mut rng).expect("token request failed");
+        let blind_tokens: Vec<_> = issuers
+            .iter()
+            .map(|issuer| tissue(&blind_request, issuer, &pp).expect("tissue failed"))
+            .collect();
+        let token = aggregate_unblind(&blind_tokens, &rand, &pp).expect("unblinding failed");
+        let token_proof = prove(&token, &rand, &pp, &mut rng);
+
+        assert_eq!(
+            verify_with_attributes(
+                &token,
+                &token_proof,
+                &blind_request,
+                &attributes[1..],
+                &pp,
+                &mut rng
+            ),
+            Ok(())
+        );
+        // the disclosed attributes have to be the committed ones
+        let mut other = attributes[1..].to_vec();
+        other[1] += Scalar::from(1u64);
+        for disclosed in [&other[..], &attributes[1..2], &[]] {
+            assert_eq!(
+                verify_with_attributes(
+                    &token,
+                    &token_proof,
+                    &blind_request,
+                    disclosed,
+                    &pp,
+                    &mut rng
+                ),
+                Err(AtACTError::InvalidZKProof)
+            );
+        }
+        assert_eq!(
+            verify_with_attributes(
+                &token,
+                &token_proof,
+                &blind_request,
+                &attributes,
+                &pp,
+                &mut rng
+            ),
+            Err(AtACTError::InvalidAttribute)
+        );
+        assert_eq!(
+            register_attributes(&[attributes.clone(), attributes].concat(), &pp, &mut rng).err(),
+            Some(AtACTError::InvalidAttribute)
+        );
+    }
+
+    #[test]
+    fn token_proof_binds_its_transcript() {
+        let mut rng = rand::thread_rng();
+        let (pp, issuers) = setup(4, 8, 4, 4, 1, &mut rng).expect("setup failed");
+
+        let (strg, cm) = register(&Scalar::rand(&mut rng), &pp, &mut rng).expect("register failed");
+        let (blind_request, rand) =
+            token_request(&strg, &cm, &pp, &mut rng).expect("token request failed");
+        let blind_tokens: Vec<_> = issuers
+            .iter()
+            .map(|issuer| tissue(&blind_request, issuer, &pp).expect("tissue failed"))
+            .collect();
+        let token = aggregate_unblind(&blind_tokens, &rand, &pp).expect("unblinding failed");
+
+        // a ZK proof with a standalone transcript omits the token and the revealed parts
+        let mut token_proof = prove(&token, &rand, &pp, &mut rng);
+        let (cm, o) = Commitment::commit_with_randomness(&rand.strg.a, &rand.strg.r);
+        let (bold_cm, bold_o) = Commitment::commit_with_randomness(&rand.bold_k, &rand.bold_rk);
+        token_proof.pi_zk = cm.proof_2_pk(
+            &rand.strg.a,
+            &o,
+            &bold_cm,
+            &rand.bold_k,
+            &bold_o,
+            &pp.pk.0,
+            &token_proof.pk_prime.0,
+            &mut rng,
+        );
+        assert_eq!(
+            verify(&token, &token_proof, &blind_request, &pp, &mut rng),
+            Err(AtACTError::InvalidZKProof)
+        );
+
+        // reordered signatures or keys change the transcript
+        let mut token_proof = prove(&token, &rand, &pp, &mut rng);
+        token_proof.ss.swap(0, 1);
+        assert_eq!(
+            verify(&token, &token_proof, &blind_request, &pp, &mut rng),
+            Err(AtACTError::InvalidZKProof)
+        );
+        let mut token_proof = prove(&token, &rand, &pp, &mut rng);
+        token_proof.rs.swap(0, 2);
+        assert_eq!(
+            verify(&token, &token_proof, &blind_request, &pp, &mut rng),
+            Err(AtACTError::InvalidZKProof)
+        );
+    }
+
+    #[test]
+    fn malformed_lengths_rejected() {
+        let mut rng = rand::thread_rng();
+        let (pp, issuers) = setup(4, 4, 2, 2, 1, &mut rng).expect("setup failed");
+
+        let (strg, cm) = register(&Scalar::rand(&mut rng), &pp, &mut rng).expect("register failed");
+        let (mut blind_request, rand) =
+            token_request(&strg, &cm, &pp, &mut rng).expect("token request failed");
+        let blind_tokens: Vec<_> = issuers
+            .iter()
+            .map(|issuer| tissue(&blind_request, issuer, &pp).expect("tissue failed"))
+            .collect();
+        let token = aggregate_unblind(&blind_tokens, &rand, &pp).expect("unblinding failed");
+
+        let mut token_proof = prove(&token, &rand, &pp, &mut rng);
+        token_proof.rs.pop();
+        assert_eq!(
+            verify(&token, &token_proof, &blind_request, &pp, &mut rng),
+            Err(AtACTError::InvalidProofLength)
+        );
+        let mut token_proof = prove(&token, &rand, &pp, &mut rng);
+        token_proof.ss.truncate(1);
+        assert_eq!(
+            verify(&token, &token_proof, &blind_request, &pp, &mut rng),
+            Err(AtACTError::InvalidProofLength)
+        );
+
+        let cm_k = blind_request.cm_ks.pop().unwrap();
+        let expected = Err(AtACTError::InvalidRequestLength {
+            expected: pp.n,
+            actual: pp.n - 1,
+        });
+        assert_eq!(tissue(&blind_request, &issuers[0], &pp), expected);
+        let token_proof = prove(&token, &rand, &pp, &mut rng);
+        assert_eq!(
+            verify(&token, &token_proof, &blind_request, &pp, &mut rng),
+            expected.map(|_| ())
+        );
+
+        blind_request.cm_ks.extend([cm_k.clone(), cm_k]);
+        assert_eq!(
+            tissue(&blind_request, &issuers[0], &pp),
+            Err(AtACTError::InvalidRequestLength {
+                expected: pp.n,
+                actual: pp.n + 1
+            })
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_round_trips() {
+        use crate::bls381_helpers::serde_round_trip;
+
+        let mut rng = rand::thread_rng();
+        let (pp, issuers) = setup(4, 4, 2, 2, 1, &mut rng).expect("setup failed");
+        let a = Scalar::rand(&mut rng);
+
+        let (strg, cm) = register(&a, &pp, &mut rng).expect("register failed");
+        let (blind_request, rand) =
+            token_request(&strg, &cm, &pp, &mut rng).expect("token request failed");
+        let blind_request = serde_round_trip(&blind_request);
+        let rand = serde_round_trip(&rand);
+
+        let blind_tokens: Vec<_> = issuers
+            .iter()
+            .map(|issuer| tissue(&blind_request, issuer, &pp).expect("tissue failed"))
+            .collect();
+        let blind_tokens = serde_round_trip(&blind_tokens);
+
+        let token = serde_round_trip(
+            &aggregate_unblind(&blind_tokens, &rand, &pp).expect("unblinding failed"),
+        );
+        let token_proof = serde_round_trip(&prove(&token, &rand, &pp, &mut rng));
+        assert_eq!(
+            verify(&token, &token_proof, &blind_request, &pp, &mut rng),
+            Ok(())
+        );
+
+        let final_token = serde_round_trip(&(cm, token.s));
+        assert_eq!(final_token.0, blind_request.cm);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_tampered_token() {
+        let mut rng = rand::thread_rng();
+        let (pp, issuers) = setup(4, 4, 2, 2, 1, &mut rng).expect("setup failed");
+        let a = Scalar::rand(&mut rng);
+
+        let (strg, cm) = register(&a, &pp, &mut rng).expect("register failed");
+        let (blind_request, rand) =
+            token_request(&strg, &cm, &pp, &mut rng).expect("token request failed");
+        let blind_tokens: Vec<_> = issuers
+            .iter()
+            .map(|issuer| tissue(&blind_request, issuer, &pp).expect("tissue failed"))
+            .collect();
+        let token = aggregate_unblind(&blind_tokens, &rand, &pp).expect("unblinding failed");
+
+        let mut bytes = bincode::serialize(&token).unwrap();
+        // skip the length prefix and modify the x coordinate of the G1 element of s
+        bytes[8 + 20] ^= 0x01;
+        assert!(bincode::deserialize::<Token>(&bytes).is_err());
+    }
+
     #[test]
     fn parameters_for_benches() {
         const NUM_ISSUERS: [usize; 3] = [4, 16, 64];
         const N: [usize; 3] = [30, 40, 128];
 
+        let mut rng = rand::thread_rng();
         for num_issuers in NUM_ISSUERS {
             for n in N {
                 let t = num_issuers / 2 + 1;
                 let tprime = n / 2 + 1;
 
                 assert!(
-                    setup(num_issuers, n, t, tprime, 1).is_ok(),
+                    setup(num_issuers, n, t, tprime, 1, &mut rng).is_ok(),
                     "issuers {num_issuers}, t {t}, n {n}, t' {tprime}"
                 );
             }
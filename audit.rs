@@ -0,0 +1,364 @@
+//! Issuer-side audit logs of the blind issuance, with the `std` feature. An [Issuer] set up with
+//! [Issuer::with_issuance_log] appends an [IssuanceEntry] to its [IssuanceLog] for every blind
+//! request it signs in [tissue](crate::atact::tissue), so a health authority can count the
+//! tokens each issuer signed, and check with [IssuanceLog::verify_chain] that no entry was
+//! dropped or altered since. Entries are signed with the issuer's
+//! [log key](crate::atact::Issuer::issuance_log_key), which is derived from its key share under
+//! [ISSUANCE_LOG_KEY_DST], so no entry is a signature under the share itself.
+//!
+//! An entry holds the [request_hash] of the request under a salt the issuers of a deployment
+//! share with the authority. The honest logs of all issuers of one registration thus hold the
+//! same hash, and the authority can find the entries of a request in an abuse report, while
+//! whoever reads an exported log without the salt can neither link its entries to requests nor
+//! to the entries of other issuers. No entry reveals the user's identity, which the request
+//! only commits to.
+
+use std::{
+    sync::{Mutex, PoisonError},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use ark_ff::PrimeField;
+use ark_serialize::CanonicalSerialize;
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+use crate::{
+    atact::BlindRequest,
+    bls381_helpers::DecodeError,
+    bls381_helpers::Scalar,
+    constants::{ISSUANCE_LOG_DST, ISSUANCE_LOG_KEY_DST, ISSUANCE_REQUEST_DST},
+    envelope::{self, ArtifactType},
+    registry::{registrar_message, verify_registrar_signature},
+    tsw::{PublicKey, SecretKey, Signature},
+};
+
+/// Size of an entry in [IssuanceLog::to_bytes].
+const ENTRY_SIZE: usize = 8 + 32 + 8 + Signature::ENCODED_SIZE;
+
+/// Issuance of the blind tokens of one request, the `counter`-th of the issuer's log.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IssuanceEntry {
+    pub counter: u64,
+    /// The [request_hash] of the request.
+    pub request_hash: [u8; 32],
+    /// Seconds since the Unix epoch.
+    pub timestamp: u64,
+    /// Signature of the issuer's log key on the entry and the [digest](Self::digest) of the
+    /// previous one, so entries are not moved between positions or logs.
+    pub signature: Signature,
+}
+
+/// Entries of the blind requests an issuer signed, in the order of issuance.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IssuanceLog {
+    entries: Vec<IssuanceEntry>,
+}
+
+#[derive(Error, Debug, PartialEq, Eq, Clone)]
+pub enum LogError {
+    #[error("Entry {actual} is in the place of entry {expected}.")]
+    NotContiguous { expected: u64, actual: u64 },
+    #[error("Entry {counter} has an invalid signature.")]
+    InvalidSignature { counter: u64 },
+    #[error("Invalid encoding: {0}")]
+    InvalidEncoding(#[source] DecodeError),
+    #[error("Invalid length {0} of the entries.")]
+    InvalidLength(usize),
+}
+
+/// Hash of the encoding of `blind_request` under `salt`, by which the entries of the request
+/// in the logs of all issuers of a deployment are found.
+pub fn request_hash(salt: &[u8; 32], blind_request: &BlindRequest) -> [u8; 32] {
+    let mut bytes = Vec::new();
+    blind_request.serialize_compressed(&mut bytes).unwrap();
+    Sha256::new()
+        .chain_update(ISSUANCE_REQUEST_DST)
+        .chain_update(salt)
+        .chain_update(bytes)
+        .finalize()
+        .into()
+}
+
+impl IssuanceEntry {
+    /// Hash of the entry, which the signature of the next entry covers.
+    pub fn digest(&self) -> [u8; 32] {
+        Sha256::new()
+            .chain_update(self.counter.to_le_bytes())
+            .chain_update(self.request_hash)
+            .chain_update(self.timestamp.to_le_bytes())
+            .chain_update(self.signature.to_bytes())
+            .finalize()
+            .into()
+    }
+}
+
+// the signed message of an entry behind the entry with digest `previous`
+fn entry_message(previous: &[u8; 32], request_hash: &[u8; 32], timestamp: u64) -> Vec<u8> {
+    let mut msg = previous.to_vec();
+    msg.extend_from_slice(request_hash);
+    msg.extend_from_slice(&timestamp.to_le_bytes());
+    msg
+}
+
+impl IssuanceLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn entries(&self) -> &[IssuanceEntry] {
+        &self.entries
+    }
+
+    /// Number of signed requests, each of which yields the `n` blind tokens of a registration.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    fn previous_digest(&self) -> [u8; 32] {
+        self.entries.last().map_or([0; 32], IssuanceEntry::digest)
+    }
+
+    /// Appends and signs the entry of a request with the hash `request_hash` at `timestamp`.
+    pub(crate) fn append(&mut self, sk: &SecretKey, request_hash: [u8; 32], timestamp: u64) {
+        let counter = self.entries.len() as u64;
+        let msg = entry_message(&self.previous_digest(), &request_hash, timestamp);
+        self.entries.push(IssuanceEntry {
+            counter,
+            request_hash,
+            timestamp,
+            signature: Signature(registrar_message(ISSUANCE_LOG_DST, counter, &msg) * sk.sk),
+        });
+    }
+
+    /// Checks that the counters of the entries are `0, 1, 2, ...` and that every entry is
+    /// signed under the [log key](crate::atact::Issuer::issuance_log_key) `pk` of the issuer
+    /// behind the entry before it. Entries dropped
+    /// from the end of a log are only noticed against a count the authority kept.
+    pub fn verify_chain(&self, pk: &PublicKey) -> Result<(), LogError> {
+        let mut previous = [0; 32];
+        for (expected, entry) in (0..).zip(&self.entries) {
+            if entry.counter != expected {
+                return Err(LogError::NotContiguous {
+                    expected,
+                    actual: entry.counter,
+                });
+            }
+            let msg = entry_message(&previous, &entry.request_hash, entry.timestamp);
+            if !verify_registrar_signature(
+                pk,
+                ISSUANCE_LOG_DST,
+                entry.counter,
+                &msg,
+                &entry.signature,
+            ) {
+                return Err(LogError::InvalidSignature {
+                    counter: entry.counter,
+                });
+            }
+            previous = entry.digest();
+        }
+        Ok(())
+    }
+
+    /// Encodes the log for export as the concatenation of its entries behind an
+    /// [envelope](crate::envelope) header. An entry is its counter, the request hash, the
+    /// timestamp, all little-endian, and the signature.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = envelope::header(ArtifactType::IssuanceLog).to_vec();
+        for entry in &self.entries {
+            bytes.extend_from_slice(&entry.counter.to_le_bytes());
+            bytes.extend_from_slice(&entry.request_hash);
+            bytes.extend_from_slice(&entry.timestamp.to_le_bytes());
+            bytes.extend_from_slice(&entry.signature.to_bytes());
+        }
+        bytes
+    }
+
+    /// Decodes an exported log, whose chain is not checked.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, LogError> {
+        match envelope::open(bytes, ArtifactType::IssuanceLog).map_err(LogError::InvalidEncoding)? {
+            (1, payload) => Self::from_payload_v1(payload),
+            (version, _) => Err(LogError::InvalidEncoding(DecodeError::UnsupportedVersion(
+                version,
+            ))),
+        }
+    }
+
+    fn from_payload_v1(bytes: &[u8]) -> Result<Self, LogError> {
+        if bytes.len() % ENTRY_SIZE != 0 {
+            return Err(LogError::InvalidLength(bytes.len()));
+        }
+        let entries = bytes
+            .chunks_exact(ENTRY_SIZE)
+            .map(|entry| {
+                let (counter, rest) = entry.split_at(8);
+                let (request_hash, rest) = rest.split_at(32);
+                let (timestamp, signature) = rest.split_at(8);
+                Ok(IssuanceEntry {
+                    counter: u64::from_le_bytes(counter.try_into().unwrap()),
+                    request_hash: request_hash.try_into().unwrap(),
+                    timestamp: u64::from_le_bytes(timestamp.try_into().unwrap()),
+                    signature: Signature::from_bytes(signature)
+                        .map_err(LogError::InvalidEncoding)?,
+                })
+            })
+            .collect::<Result<_, LogError>>()?;
+        Ok(Self { entries })
+    }
+}
+
+/// Log of an [Issuer](crate::atact::Issuer) with the salt of its request hashes, see
+/// [Issuer::with_issuance_log](crate::atact::Issuer::with_issuance_log).
+pub(crate) struct IssuanceRecorder {
+    salt: [u8; 32],
+    sk: SecretKey,
+    log: Mutex<IssuanceLog>,
+}
+
+impl IssuanceRecorder {
+    /// The recorder of the issuer with the key share `share`, whose log key is derived from it.
+    pub(crate) fn new(salt: [u8; 32], share: &SecretKey) -> Self {
+        let mut bytes = Vec::new();
+        share.sk.serialize_compressed(&mut bytes).unwrap();
+        let digest = Sha256::new()
+            .chain_update(ISSUANCE_LOG_KEY_DST)
+            .chain_update(bytes)
+            .finalize();
+        Self {
+            salt,
+            sk: SecretKey {
+                sk: Scalar::from_le_bytes_mod_order(&digest),
+            },
+            log: Mutex::new(IssuanceLog::new()),
+        }
+    }
+
+    pub(crate) fn public_key(&self) -> PublicKey {
+        self.sk.to_public_key()
+    }
+
+    /// Appends the entry of `blind_request` signed with the log key at the current time.
+    pub(crate) fn record(&self, blind_request: &BlindRequest) {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs());
+        let request_hash = request_hash(&self.salt, blind_request);
+        self.log
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .append(&self.sk, request_hash, timestamp);
+    }
+
+    pub(crate) fn log(&self) -> IssuanceLog {
+        self.log
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rand::rngs::OsRng;
+
+    use super::*;
+    use crate::{
+        atact::{Issuer, tissue},
+        spirit::{Epoch, SetupOutput, UserSecret, spirit_register_begin, spirit_setup_osrng},
+    };
+
+    const SALT: [u8; 32] = [7; 32];
+    const EXPIRY: Epoch = 100;
+
+    fn setup() -> (SetupOutput, Vec<Issuer>) {
+        let mut setup = spirit_setup_osrng(3, 4, 4, b"test").expect("setup failed");
+        let issuers = std::mem::take(&mut setup.issuers)
+            .into_iter()
+            .map(|issuer| issuer.with_issuance_log(SALT))
+            .collect();
+        (setup, issuers)
+    }
+
+    #[test]
+    fn deleted_entries_break_the_chain() {
+        let (SetupOutput { pp, domain, .. }, issuers) = setup();
+        for _ in 0..3 {
+            let user = UserSecret::random(OsRng);
+            let (blind_request, _) =
+                spirit_register_begin(&domain, &user.id_u, &pp, EXPIRY, &mut OsRng)
+                    .expect("token request failed");
+            tissue(&blind_request, &issuers[0], &pp).expect("issuance failed");
+        }
+        let log = issuers[0].issuance_log().unwrap();
+        let pk = issuers[0].issuance_log_key().unwrap();
+        assert_eq!(log.len(), 3);
+        assert_eq!(log.verify_chain(&pk), Ok(()));
+        assert_eq!(IssuanceLog::from_bytes(&log.to_bytes()), Ok(log.clone()));
+        assert_eq!(
+            log.verify_chain(&issuers[1].issuance_log_key().unwrap()),
+            Err(LogError::InvalidSignature { counter: 0 })
+        );
+        // the entries are no signatures under the issuer's key share
+        assert_ne!(pk, issuers[0].as_ref().to_public_key());
+        assert_eq!(
+            log.verify_chain(&issuers[0].as_ref().to_public_key()),
+            Err(LogError::InvalidSignature { counter: 0 })
+        );
+
+        let mut deleted = log.clone();
+        deleted.entries.remove(1);
+        assert_eq!(
+            deleted.verify_chain(&pk),
+            Err(LogError::NotContiguous {
+                expected: 1,
+                actual: 2
+            })
+        );
+
+        // renumbering the rest does not restore the chain
+        deleted.entries[1].counter = 1;
+        assert_eq!(
+            deleted.verify_chain(&pk),
+            Err(LogError::InvalidSignature { counter: 1 })
+        );
+        let mut altered = log;
+        altered.entries[2].timestamp += 1;
+        assert_eq!(
+            altered.verify_chain(&pk),
+            Err(LogError::InvalidSignature { counter: 2 })
+        );
+    }
+
+    #[test]
+    fn issuers_log_the_same_request_hash() {
+        let (SetupOutput { pp, domain, .. }, issuers) = setup();
+        let user = UserSecret::random(OsRng);
+        let (blind_request, _) =
+            spirit_register_begin(&domain, &user.id_u, &pp, EXPIRY, &mut OsRng)
+                .expect("token request failed");
+        for issuer in &issuers[..2] {
+            tissue(&blind_request, issuer, &pp).expect("issuance failed");
+        }
+
+        let logs: Vec<_> = issuers
+            .iter()
+            .map(|issuer| issuer.issuance_log().unwrap())
+            .collect();
+        let hash = request_hash(&SALT, &blind_request);
+        assert_eq!(logs[0].entries()[0].request_hash, hash);
+        assert_eq!(logs[1].entries()[0].request_hash, hash);
+        assert!(logs[2].is_empty());
+        assert_ne!(request_hash(&[0; 32], &blind_request), hash);
+        for (issuer, log) in issuers.iter().zip(&logs) {
+            assert_eq!(
+                log.verify_chain(&issuer.issuance_log_key().unwrap()),
+                Ok(())
+            );
+        }
+    }
+}
@@ -0,0 +1,498 @@
+//! Passphrase-encrypted backups of a user's secrets, so a user who loses their device can restore
+//! their registration on a new one instead of registering again, and of their [EncounterTable].
+//!
+//! A backup is an [envelope] artifact of type [ArtifactType::UserBackup], or
+//! [ArtifactType::EncounterBackup] for an encounter table, whose payload is
+//!
+//! ```text
+//! m_cost || t_cost || p_cost || salt || nonce || ciphertext
+//! ```
+//!
+//! with the Argon2id costs as little-endian `u32`s. The ciphertext is the XChaCha20-Poly1305
+//! encryption of the secrets, or of the table as an [ArtifactType::EncounterTable] artifact,
+//! under the key Argon2id derives from the passphrase and the salt, with everything in front of
+//! it as associated data. The costs are part of the payload, so they can be raised without a new
+//! version.
+
+use alloc::vec::Vec;
+
+use argon2::{Algorithm, Argon2, Params, Version};
+use ark_serialize::CanonicalDeserialize;
+use chacha20poly1305::{
+    Key, KeyInit, XChaCha20Poly1305, XNonce,
+    aead::{Aead, Payload},
+};
+use rand::{CryptoRng, RngCore};
+use thiserror::Error;
+#[cfg(feature = "zeroize")]
+use zeroize::Zeroize;
+
+use crate::{
+    DecodeError, Scalar,
+    atact::StRG,
+    bls381_helpers::{append_compressed, split_array},
+    envelope::{self, ArtifactType, HEADER_SIZE, LoadMode, LoadWarning},
+    spirit::{EncounterTable, Epoch, Token, UserSecret},
+};
+
+const SALT_SIZE: usize = 16;
+const NONCE_SIZE: usize = 24;
+const TAG_SIZE: usize = 16;
+/// Size of the costs, the salt and the nonce in front of the ciphertext.
+const PREFIX_SIZE: usize = 3 * size_of::<u32>() + SALT_SIZE + NONCE_SIZE;
+/// Largest memory cost in KiB accepted when importing, so a crafted backup cannot make the
+/// import allocate more than 1 GiB.
+const MAX_M_COST: u32 = 1 << 20;
+/// Largest number of passes accepted when importing, far above any recommended one.
+const MAX_T_COST: u32 = 16;
+/// Largest degree of parallelism accepted when importing.
+const MAX_P_COST: u32 = 16;
+
+#[derive(Error, Debug, PartialEq, Eq, Clone)]
+pub enum BackupError {
+    #[error("Invalid backup encoding: {0}")]
+    Decode(#[from] DecodeError),
+    #[error("Backup is truncated.")]
+    Truncated,
+    #[error("Unsupported key derivation costs.")]
+    InvalidCosts,
+    #[error("Wrong passphrase or corrupted backup.")]
+    Authentication,
+    #[error("Invalid backup contents.")]
+    InvalidContents,
+    #[error("Backup encryption failed.")]
+    Encryption,
+}
+
+/// Costs of the Argon2id key derivation, see [UserSecret::export_encrypted_with_costs].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BackupCosts {
+    /// Memory in KiB.
+    pub m_cost: u32,
+    /// Number of passes over the memory.
+    pub t_cost: u32,
+    /// Degree of parallelism.
+    pub p_cost: u32,
+}
+
+/// The minimal Argon2id configuration recommended by OWASP: 19 MiB of memory, two passes and no
+/// parallelism.
+impl Default for BackupCosts {
+    fn default() -> Self {
+        Self {
+            m_cost: 19 * 1024,
+            t_cost: 2,
+            p_cost: 1,
+        }
+    }
+}
+
+impl BackupCosts {
+    // the costs come from the backup, so they are capped before anything is derived
+    fn derive_key(&self, passphrase: &[u8], salt: &[u8]) -> Result<[u8; 32], BackupError> {
+        if self.m_cost > MAX_M_COST || self.t_cost > MAX_T_COST || self.p_cost > MAX_P_COST {
+            return Err(BackupError::InvalidCosts);
+        }
+        let params = Params::new(self.m_cost, self.t_cost, self.p_cost, Some(32))
+            .map_err(|_| BackupError::InvalidCosts)?;
+        let mut key = [0u8; 32];
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+            .hash_password_into(passphrase, salt, &mut key)
+            .map_err(|_| BackupError::InvalidCosts)?;
+        Ok(key)
+    }
+}
+
+/// Contents of a backup restored by [UserSecret::import_encrypted].
+pub struct RestoredUser {
+    pub secret: UserSecret,
+    /// The registered token and its opening, as returned by the registration.
+    pub ppu: (Token, StRG),
+    /// The epoch the user was in when the backup was taken.
+    pub epoch: Epoch,
+}
+
+impl UserSecret {
+    /// Backup of the user's identity and PRF key, their registration `ppu` and the
+    /// `current_epoch`, encrypted under `passphrase` with the [default](BackupCosts::default)
+    /// costs.
+    pub fn export_encrypted(
+        &self,
+        ppu: &(Token, StRG),
+        current_epoch: Epoch,
+        passphrase: &[u8],
+        rng: &mut (impl RngCore + CryptoRng),
+    ) -> Result<Vec<u8>, BackupError> {
+        self.export_encrypted_with_costs(
+            ppu,
+            current_epoch,
+            passphrase,
+            BackupCosts::default(),
+            rng,
+        )
+    }
+
+    /// Like [Self::export_encrypted], but with the given key derivation `costs`, e.g. higher
+    /// ones for backups kept off the device.
+    pub fn export_encrypted_with_costs(
+        &self,
+        ppu: &(Token, StRG),
+        current_epoch: Epoch,
+        passphrase: &[u8],
+        costs: BackupCosts,
+        rng: &mut (impl RngCore + CryptoRng),
+    ) -> Result<Vec<u8>, BackupError> {
+        #[cfg_attr(not(feature = "zeroize"), allow(unused_mut))]
+        let mut plaintext = self.backup_plaintext(ppu, current_epoch);
+        let bytes = seal(ArtifactType::UserBackup, &plaintext, passphrase, costs, rng);
+        #[cfg(feature = "zeroize")]
+        plaintext.zeroize();
+        bytes
+    }
+
+    /// Restores a backup of [Self::export_encrypted]. A wrong `passphrase` fails the
+    /// authentication of the ciphertext, so it never yields other keys than the exported ones.
+    pub fn import_encrypted(bytes: &[u8], passphrase: &[u8]) -> Result<RestoredUser, BackupError> {
+        #[cfg_attr(not(feature = "zeroize"), allow(unused_mut))]
+        let mut plaintext = open(bytes, ArtifactType::UserBackup, passphrase)?;
+        let restored = Self::from_backup_plaintext(&plaintext);
+        #[cfg(feature = "zeroize")]
+        plaintext.zeroize();
+        restored
+    }
+
+    /// `id_u || prv || epoch || token || st_rg`, with the scalars and the opening compressed.
+    fn backup_plaintext(&self, (token, st_rg): &(Token, StRG), epoch: Epoch) -> Vec<u8> {
+        let mut plaintext = Vec::new();
+        append_compressed(&mut plaintext, &self.id_u);
+        append_compressed(&mut plaintext, &self.keys.prv);
+        plaintext.extend_from_slice(&epoch.to_le_bytes());
+        plaintext.extend_from_slice(&token.payload());
+        append_compressed(&mut plaintext, st_rg);
+        plaintext
+    }
+
+    fn from_backup_plaintext(mut plaintext: &[u8]) -> Result<RestoredUser, BackupError> {
+        let id_u = Scalar::deserialize_compressed(&mut plaintext)
+            .map_err(|_| BackupError::InvalidContents)?;
+        let prv = Scalar::deserialize_compressed(&mut plaintext)
+            .map_err(|_| BackupError::InvalidContents)?;
+        let Some((epoch, rest)) = plaintext.split_first_chunk() else {
+            return Err(BackupError::InvalidContents);
+        };
+        let Some((token, mut rest)) = rest.split_at_checked(Token::PAYLOAD_SIZE) else {
+            return Err(BackupError::InvalidContents);
+        };
+        let token = Token::from_payload(token).map_err(|_| BackupError::InvalidContents)?;
+        let st_rg =
+            StRG::deserialize_compressed(&mut rest).map_err(|_| BackupError::InvalidContents)?;
+        if !rest.is_empty() {
+            return Err(BackupError::InvalidContents);
+        }
+        Ok(RestoredUser {
+            secret: Self::new(id_u, prv),
+            ppu: (token, st_rg),
+            epoch: Epoch::from_le_bytes(*epoch),
+        })
+    }
+}
+
+impl EncounterTable {
+    /// Backup of the table, encrypted under `passphrase` with the [default](BackupCosts::default)
+    /// costs. It is the only way to take the encounters off the device.
+    pub fn export_for_backup(
+        &self,
+        passphrase: &[u8],
+        rng: &mut (impl RngCore + CryptoRng),
+    ) -> Result<Vec<u8>, BackupError> {
+        self.export_for_backup_with_costs(passphrase, BackupCosts::default(), rng)
+    }
+
+    /// Like [Self::export_for_backup], but with the given key derivation `costs`.
+    pub fn export_for_backup_with_costs(
+        &self,
+        passphrase: &[u8],
+        costs: BackupCosts,
+        rng: &mut (impl RngCore + CryptoRng),
+    ) -> Result<Vec<u8>, BackupError> {
+        #[cfg_attr(not(feature = "zeroize"), allow(unused_mut))]
+        let mut plaintext = self.to_bytes();
+        let bytes = seal(
+            ArtifactType::EncounterBackup,
+            &plaintext,
+            passphrase,
+            costs,
+            rng,
+        );
+        #[cfg(feature = "zeroize")]
+        plaintext.zeroize();
+        bytes
+    }
+
+    /// Restores a backup of [Self::export_for_backup]. A table that does not decode fails
+    /// with [BackupError::InvalidContents], see [Self::import_backup_with].
+    pub fn import_backup(bytes: &[u8], passphrase: &[u8]) -> Result<Self, BackupError> {
+        Self::import_backup_with(bytes, passphrase, LoadMode::Strict).map(|(table, _)| table)
+    }
+
+    /// Like [Self::import_backup], but with [LoadMode::Lenient] the entries that do not decode
+    /// are skipped and returned as warnings with their offsets in the decrypted table.
+    pub fn import_backup_with(
+        bytes: &[u8],
+        passphrase: &[u8],
+        mode: LoadMode,
+    ) -> Result<(Self, Vec<LoadWarning>), BackupError> {
+        #[cfg_attr(not(feature = "zeroize"), allow(unused_mut))]
+        let mut plaintext = open(bytes, ArtifactType::EncounterBackup, passphrase)?;
+        let restored =
+            Self::from_bytes_with(&plaintext, mode).map_err(|_| BackupError::InvalidContents);
+        #[cfg(feature = "zeroize")]
+        plaintext.zeroize();
+        restored
+    }
+}
+
+/// Encrypts `plaintext` under `passphrase` into a backup artifact of type `artifact`.
+fn seal(
+    artifact: ArtifactType,
+    plaintext: &[u8],
+    passphrase: &[u8],
+    costs: BackupCosts,
+    rng: &mut (impl RngCore + CryptoRng),
+) -> Result<Vec<u8>, BackupError> {
+    let mut salt = [0u8; SALT_SIZE];
+    rng.fill_bytes(&mut salt);
+    let mut nonce = [0u8; NONCE_SIZE];
+    rng.fill_bytes(&mut nonce);
+    #[cfg_attr(not(feature = "zeroize"), allow(unused_mut))]
+    let mut key = costs.derive_key(passphrase, &salt)?;
+
+    let mut payload = Vec::with_capacity(PREFIX_SIZE);
+    payload.extend_from_slice(&costs.m_cost.to_le_bytes());
+    payload.extend_from_slice(&costs.t_cost.to_le_bytes());
+    payload.extend_from_slice(&costs.p_cost.to_le_bytes());
+    payload.extend_from_slice(&salt);
+    payload.extend_from_slice(&nonce);
+    let mut bytes = envelope::seal(artifact, &payload);
+
+    let ciphertext = XChaCha20Poly1305::new(Key::from_slice(&key))
+        .encrypt(
+            XNonce::from_slice(&nonce),
+            Payload {
+                msg: plaintext,
+                aad: &bytes,
+            },
+        )
+        .map_err(|_| BackupError::Encryption);
+    #[cfg(feature = "zeroize")]
+    key.zeroize();
+    bytes.extend_from_slice(&ciphertext?);
+    Ok(bytes)
+}
+
+/// Decrypts the plaintext of a backup artifact of type `artifact`.
+fn open(bytes: &[u8], artifact: ArtifactType, passphrase: &[u8]) -> Result<Vec<u8>, BackupError> {
+    match envelope::open(bytes, artifact)? {
+        (1, payload) => open_payload_v1(bytes, payload, passphrase),
+        (version, _) => Err(DecodeError::UnsupportedVersion(version).into()),
+    }
+}
+
+fn open_payload_v1(
+    bytes: &[u8],
+    payload: &[u8],
+    passphrase: &[u8],
+) -> Result<Vec<u8>, BackupError> {
+    if payload.len() < PREFIX_SIZE + TAG_SIZE {
+        return Err(BackupError::Truncated);
+    }
+    let (prefix, ciphertext) = payload.split_at(PREFIX_SIZE);
+    let (m_cost, rest) = split_array(prefix)?;
+    let (t_cost, rest) = split_array(rest)?;
+    let (p_cost, rest) = split_array(rest)?;
+    let costs = BackupCosts {
+        m_cost: u32::from_le_bytes(*m_cost),
+        t_cost: u32::from_le_bytes(*t_cost),
+        p_cost: u32::from_le_bytes(*p_cost),
+    };
+    let (salt, nonce) = rest.split_at(SALT_SIZE);
+    #[cfg_attr(not(feature = "zeroize"), allow(unused_mut))]
+    let mut key = costs.derive_key(passphrase, salt)?;
+
+    let plaintext = XChaCha20Poly1305::new(Key::from_slice(&key)).decrypt(
+        XNonce::from_slice(nonce),
+        Payload {
+            msg: ciphertext,
+            aad: &bytes[..HEADER_SIZE + PREFIX_SIZE],
+        },
+    );
+    #[cfg(feature = "zeroize")]
+    key.zeroize();
+    plaintext.map_err(|_| BackupError::Authentication)
+}
+
+#[cfg(test)]
+mod test {
+    use ark_ff::UniformRand;
+    use rand::rngs::OsRng;
+
+    use super::*;
+    use crate::{
+        G1G2,
+        elgamal::DecryptionKey,
+        pedersen::Commitment,
+        spirit::{DomainConfig, Interval, UserKeys, spirit_broadcast_osrng},
+        tsw::Signature,
+    };
+
+    /// Cheap costs, so the tests do not spend most of their time in Argon2id.
+    const COSTS: BackupCosts = BackupCosts {
+        m_cost: 64,
+        t_cost: 1,
+        p_cost: 1,
+    };
+    const EPOCH: Epoch = 10;
+
+    fn backup() -> (UserSecret, (Token, StRG), Vec<u8>) {
+        let user = UserSecret::random(OsRng);
+        let ppu = (
+            Token(
+                Commitment(G1G2::random(OsRng)),
+                Signature(G1G2::random(OsRng)),
+                100,
+            ),
+            StRG {
+                a: user.id_u,
+                r: Scalar::rand(&mut OsRng),
+                attributes: vec![Scalar::from(7u64)],
+            },
+        );
+        let bytes = user
+            .export_encrypted_with_costs(&ppu, EPOCH, b"correct horse", COSTS, &mut OsRng)
+            .unwrap();
+        (user, ppu, bytes)
+    }
+
+    #[test]
+    fn backups_restore_the_exported_secrets() {
+        let (user, (token, st_rg), bytes) = backup();
+        let restored = UserSecret::import_encrypted(&bytes, b"correct horse").unwrap();
+        assert_eq!(restored.secret.id_u, user.id_u);
+        assert_eq!(restored.secret.keys.prv, user.keys.prv);
+        assert_eq!(restored.ppu.0, token);
+        assert_eq!(restored.ppu.1.a, st_rg.a);
+        assert_eq!(restored.ppu.1.r, st_rg.r);
+        assert_eq!(restored.ppu.1.attributes, st_rg.attributes);
+        assert_eq!(restored.epoch, EPOCH);
+    }
+
+    #[test]
+    fn wrong_passphrases_and_modified_backups_fail_authentication() {
+        let (_, _, bytes) = backup();
+        assert_eq!(
+            UserSecret::import_encrypted(&bytes, b"wrong horse").err(),
+            Some(BackupError::Authentication)
+        );
+
+        // the costs and the salt are authenticated, too
+        for i in [HEADER_SIZE, HEADER_SIZE + 12, bytes.len() - 1] {
+            let mut modified = bytes.clone();
+            modified[i] ^= 1;
+            assert_eq!(
+                UserSecret::import_encrypted(&modified, b"correct horse").err(),
+                Some(BackupError::Authentication)
+            );
+        }
+    }
+
+    #[test]
+    fn excessive_costs_are_rejected() {
+        let (user, ppu, bytes) = backup();
+        for (offset, max) in [(0, MAX_M_COST), (4, MAX_T_COST), (8, MAX_P_COST)] {
+            let mut expensive = bytes.clone();
+            let start = HEADER_SIZE + offset;
+            expensive[start..start + 4].copy_from_slice(&(max + 1).to_le_bytes());
+            assert_eq!(
+                UserSecret::import_encrypted(&expensive, b"correct horse").err(),
+                Some(BackupError::InvalidCosts)
+            );
+        }
+        let costs = BackupCosts {
+            t_cost: MAX_T_COST + 1,
+            ..COSTS
+        };
+        assert_eq!(
+            user.export_encrypted_with_costs(&ppu, EPOCH, b"correct horse", costs, &mut OsRng)
+                .err(),
+            Some(BackupError::InvalidCosts)
+        );
+    }
+
+    #[test]
+    fn truncated_backups_are_rejected() {
+        let (_, _, bytes) = backup();
+        assert_eq!(
+            UserSecret::import_encrypted(&bytes[..HEADER_SIZE - 1], b"correct horse").err(),
+            Some(BackupError::Decode(DecodeError::InvalidHeader))
+        );
+        assert_eq!(
+            UserSecret::import_encrypted(&bytes[..HEADER_SIZE + PREFIX_SIZE], b"correct horse")
+                .err(),
+            Some(BackupError::Truncated)
+        );
+        assert_eq!(
+            UserSecret::import_encrypted(&bytes[..bytes.len() - 1], b"correct horse").err(),
+            Some(BackupError::Authentication)
+        );
+
+        let mut later_version = bytes;
+        later_version[4] = 2;
+        assert_eq!(
+            UserSecret::import_encrypted(&later_version, b"correct horse").err(),
+            Some(BackupError::Decode(DecodeError::UnsupportedVersion(2)))
+        );
+    }
+
+    #[test]
+    fn encounter_backups_restore_the_table() {
+        let domain = DomainConfig::default();
+        let keys = UserKeys::random(OsRng);
+        let pk_ha = DecryptionKey::random(&mut OsRng).encryption_key();
+        let mut t_el = EncounterTable::new();
+        for i in 0..3 {
+            spirit_broadcast_osrng(&domain, i, Interval(i), &keys, &pk_ha, &mut t_el);
+        }
+        let bytes = t_el
+            .export_for_backup_with_costs(b"correct horse", COSTS, &mut OsRng)
+            .unwrap();
+        assert_eq!(
+            EncounterTable::import_backup(&bytes, b"correct horse").as_ref(),
+            Ok(&t_el)
+        );
+        // the ElIDs are encrypted along with their secrets
+        for el_id in t_el.el_ids() {
+            let el_id = el_id.to_bytes();
+            assert!(!bytes.windows(el_id.len()).any(|window| window == el_id));
+        }
+
+        assert_eq!(
+            EncounterTable::import_backup(&bytes, b"wrong horse").err(),
+            Some(BackupError::Authentication)
+        );
+        let (_, _, user_backup) = backup();
+        assert_eq!(
+            EncounterTable::import_backup(&user_backup, b"correct horse").err(),
+            Some(BackupError::Decode(DecodeError::UnexpectedArtifactType {
+                expected: ArtifactType::EncounterBackup,
+                actual: ArtifactType::UserBackup as u8
+            }))
+        );
+        let empty = EncounterTable::new()
+            .export_for_backup_with_costs(b"", COSTS, &mut OsRng)
+            .unwrap();
+        assert_eq!(
+            EncounterTable::import_backup_with(&empty, b"", LoadMode::Lenient),
+            Ok((EncounterTable::new(), Vec::new()))
+        );
+    }
+}
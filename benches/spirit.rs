@@ -0,0 +1,551 @@
+use std::collections::HashSet;
+
+use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main};
+use rand::rngs::OsRng;
+#[cfg(feature = "rayon")]
+use spirit::spirit::{spirit_verify_batch_parallel, spirit_verify_parallel};
+use spirit::{
+    G1G2, Precomputed, Scalar, atact,
+    elgamal::DecryptionKey,
+    msm,
+    pedersen::{Commitment, Proof2PK},
+    registry::RevocationList,
+    spirit::{
+        DomainConfig, EncounterTable, Interval, UserKeys, UserSecret, prf_broadcast,
+        spirit_broadcast_osrng, spirit_broadcast_range_osrng, spirit_register_batch,
+        spirit_register_osrng, spirit_trace, spirit_verify, spirit_verify_batch,
+    },
+    testkit::{Deployment, confirmed_contacts, encounter_table},
+    tsw::{self, SecretKey, Signature, verify_aggregate},
+};
+
+const EPOCH: u64 = 1;
+const EXPIRY: u64 = 100;
+
+fn bench_prf(c: &mut Criterion) {
+    let domain = DomainConfig::default();
+    let k = Scalar::from(2u64);
+
+    c.bench_function("prf", |b| {
+        b.iter(|| prf_broadcast(&domain, &k, EPOCH, Interval(7)))
+    });
+}
+
+fn bench_verify(c: &mut Criterion) {
+    let mut deployment = Deployment::new(4, 8, 8, 1).unwrap();
+    // every user submits one report per epoch
+    let users = deployment.unsigned_users(1000, EXPIRY, 2);
+    deployment.populate_registry(10_000, EXPIRY, 3);
+    let revoked = RevocationList::new();
+
+    let mut group = c.benchmark_group("spirit_verify");
+    group.sample_size(10);
+    for num_reports in [1, 100, 1000] {
+        let reports = deployment
+            .trace_reports(&users[..num_reports], EPOCH, 4)
+            .unwrap();
+
+        group.bench_with_input(
+            BenchmarkId::new("sequential", num_reports),
+            &reports,
+            |b, reports| {
+                b.iter(|| {
+                    let mut nullifiers = HashSet::new();
+                    let mut cp = HashSet::new();
+                    for report in reports {
+                        spirit_verify(
+                            &deployment.domain,
+                            report,
+                            &deployment.t_rgstr,
+                            &revoked,
+                            EPOCH,
+                            &mut nullifiers,
+                            &deployment.challenges,
+                            &mut cp,
+                        );
+                    }
+                    cp
+                })
+            },
+        );
+        group.bench_with_input(
+            BenchmarkId::new("batch", num_reports),
+            &reports,
+            |b, reports| {
+                b.iter(|| {
+                    let mut cp = HashSet::new();
+                    spirit_verify_batch(
+                        &deployment.domain,
+                        reports,
+                        &deployment.t_rgstr,
+                        &revoked,
+                        EPOCH,
+                        &mut HashSet::new(),
+                        &deployment.challenges,
+                        &mut cp,
+                    );
+                    cp
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+// the pools of more threads than cores do not speed up verification any further
+#[cfg(feature = "rayon")]
+fn bench_verify_parallel(c: &mut Criterion) {
+    let mut deployment = Deployment::new(4, 8, 8, 1).unwrap();
+    let users = deployment.unsigned_users(1000, EXPIRY, 2);
+    let reports = deployment.trace_reports(&users, EPOCH, 4).unwrap();
+    let revoked = RevocationList::new();
+
+    let mut group = c.benchmark_group("spirit_verify_parallel");
+    group.sample_size(10);
+    for num_threads in [1, 2, 4, 8] {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(num_threads)
+            .build()
+            .unwrap();
+
+        group.bench_with_input(
+            BenchmarkId::new("per_report", num_threads),
+            &reports,
+            |b, reports| {
+                b.iter(|| {
+                    let mut cp = HashSet::new();
+                    pool.install(|| {
+                        spirit_verify_parallel(
+                            &deployment.domain,
+                            reports,
+                            &deployment.t_rgstr,
+                            &revoked,
+                            EPOCH,
+                            &mut HashSet::new(),
+                            &deployment.challenges,
+                            &mut cp,
+                        )
+                    });
+                    cp
+                })
+            },
+        );
+        group.bench_with_input(
+            BenchmarkId::new("batch", num_threads),
+            &reports,
+            |b, reports| {
+                b.iter(|| {
+                    let mut cp = HashSet::new();
+                    pool.install(|| {
+                        spirit_verify_batch_parallel(
+                            &deployment.domain,
+                            reports,
+                            &deployment.t_rgstr,
+                            &revoked,
+                            EPOCH,
+                            &mut HashSet::new(),
+                            &deployment.challenges,
+                            &mut cp,
+                            reports.len().div_ceil(num_threads),
+                        )
+                    });
+                    cp
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+// run with and without the `rayon` feature to compare parallel and sequential issuance
+fn bench_register(c: &mut Criterion) {
+    let mut group = c.benchmark_group("spirit_register");
+    group.sample_size(10);
+    for (n, t) in [(16, 11), (64, 43)] {
+        let mut deployment = Deployment::new(t, n, n, 1).unwrap();
+        let user = UserSecret::new(Scalar::from(1u64), Scalar::from(2u64));
+
+        group.bench_function(BenchmarkId::from_parameter(format!("n={n},t={t}")), |b| {
+            b.iter(|| {
+                spirit_register_osrng(
+                    &deployment.domain,
+                    &user,
+                    &deployment.issuers,
+                    &deployment.pp,
+                    EXPIRY,
+                    &mut deployment.t_rgstr,
+                )
+                .unwrap()
+            })
+        });
+    }
+    group.finish();
+}
+
+// registering must not depend on the size of the registry, unlike the clone of it that
+// spirit_register used to return
+fn bench_register_large_registry(c: &mut Criterion) {
+    let mut deployment = Deployment::new(4, 8, 8, 1).unwrap();
+    deployment.populate_registry(1_000_000, EXPIRY, 3);
+    let user = UserSecret::new(Scalar::from(1u64), Scalar::from(2u64));
+
+    let mut group = c.benchmark_group("spirit_register_1m");
+    group.sample_size(10);
+    group.bench_function("single", |b| {
+        b.iter(|| {
+            spirit_register_osrng(
+                &deployment.domain,
+                &user,
+                &deployment.issuers,
+                &deployment.pp,
+                EXPIRY,
+                &mut deployment.t_rgstr,
+            )
+            .unwrap()
+        })
+    });
+    group.bench_function("clone_registry", |b| b.iter(|| deployment.t_rgstr.clone()));
+    group.finish();
+}
+
+// the interpolation of a quorum is cached after its first registration, so the same quorum
+// and quorums rotating among a few sets are fast, while more quorums than the cache holds are not
+fn bench_aggregate_unblind(c: &mut Criterion) {
+    let (t, num_issuers) = (43, 64);
+    let deployment = Deployment::new(t, num_issuers, num_issuers, 1).unwrap();
+    let pp = &deployment.pp;
+    let (strg, cm) = atact::register(&Scalar::from(1u64), pp, &mut OsRng).unwrap();
+    let (blind_request, rand) = atact::token_request(&strg, &cm, pp, &mut OsRng).unwrap();
+    let blind_tokens: Vec<_> = deployment
+        .issuers
+        .iter()
+        .map(|issuer| atact::tissue(&blind_request, issuer, pp).unwrap())
+        .collect();
+    // the t issuers from the q-th on
+    let quorums: Vec<Vec<_>> = (0..num_issuers)
+        .map(|q| {
+            (q..q + t)
+                .map(|index| {
+                    let index = index % num_issuers;
+                    (index, blind_tokens[index].clone())
+                })
+                .collect()
+        })
+        .collect();
+
+    let mut group = c.benchmark_group("aggregate_unblind");
+    group.sample_size(10);
+    group.bench_function("same_quorum", |b| {
+        b.iter(|| atact::aggregate_unblind_from(&quorums[0], &rand, pp).unwrap())
+    });
+    for num_quorums in [8, num_issuers] {
+        group.bench_function(BenchmarkId::new("rotating_quorums", num_quorums), |b| {
+            let mut rotation = quorums[..num_quorums].iter().cycle();
+            b.iter(|| atact::aggregate_unblind_from(rotation.next().unwrap(), &rand, pp).unwrap())
+        });
+    }
+    group.finish();
+}
+
+// fifty users onboarded together, one round of issuance against fifty
+fn bench_register_batch(c: &mut Criterion) {
+    let mut group = c.benchmark_group("spirit_register_batch");
+    group.sample_size(10);
+    let mut deployment = Deployment::new(4, 8, 8, 1).unwrap();
+    let users: Vec<_> = (0..50u64)
+        .map(|id| UserSecret::new(Scalar::from(id), Scalar::from(2u64)))
+        .collect();
+    let ids: Vec<_> = (0..50u64).map(Scalar::from).collect();
+
+    group.bench_function("sequential", |b| {
+        b.iter(|| {
+            for user in &users {
+                spirit_register_osrng(
+                    &deployment.domain,
+                    user,
+                    &deployment.issuers,
+                    &deployment.pp,
+                    EXPIRY,
+                    &mut deployment.t_rgstr,
+                )
+                .unwrap();
+            }
+        })
+    });
+    group.bench_function("batch", |b| {
+        b.iter(|| {
+            spirit_register_batch(
+                &deployment.domain,
+                &ids,
+                &deployment.issuers,
+                &deployment.pp,
+                EXPIRY,
+                &mut deployment.t_rgstr,
+                &mut OsRng,
+            )
+        })
+    });
+    group.finish();
+}
+
+// ten of a thousand confirmed ElIDs are the user's own
+fn bench_trace(c: &mut Criterion) {
+    let mut group = c.benchmark_group("spirit_trace");
+    for num_entries in [10_000, 100_000] {
+        let t_el = encounter_table(num_entries, EPOCH, 1);
+        let cf = confirmed_contacts(&t_el, 10, 990, 2);
+
+        group.bench_with_input(BenchmarkId::from_parameter(num_entries), &cf, |b, cf| {
+            b.iter(|| spirit_trace(cf, &t_el, 10))
+        });
+    }
+    group.finish();
+}
+
+// 100k lookups in the packed table, and in a set of the expanded ElIDs
+fn bench_encounter_lookup(c: &mut Criterion) {
+    let t_el = encounter_table(100_000, EPOCH, 1);
+    let own: Vec<_> = t_el.el_ids().collect();
+    let expanded: HashSet<_> = own.iter().cloned().collect();
+
+    let mut group = c.benchmark_group("encounter_lookup_100k");
+    group.sample_size(10);
+    group.bench_function("packed", |b| {
+        b.iter(|| own.iter().filter(|el_id| t_el.contains(el_id)).count())
+    });
+    group.bench_function("hash_set", |b| {
+        b.iter(|| own.iter().filter(|el_id| expanded.contains(*el_id)).count())
+    });
+    group.finish();
+}
+
+// one ElID per 15-minute interval of a day
+fn bench_broadcast(c: &mut Criterion) {
+    let domain = DomainConfig::default();
+    let keys = UserKeys::new(Scalar::from(2u64));
+    let pk_ha = DecryptionKey::random(&mut OsRng).encryption_key();
+
+    let mut group = c.benchmark_group("spirit_broadcast");
+    group.sample_size(10);
+    group.bench_function("loop", |b| {
+        b.iter(|| {
+            let mut t_el = EncounterTable::new();
+            for i in 0..96 {
+                spirit_broadcast_osrng(&domain, 0, Interval(i), &keys, &pk_ha, &mut t_el);
+            }
+            t_el
+        })
+    });
+    group.bench_function("range", |b| {
+        b.iter(|| {
+            let mut t_el = EncounterTable::new();
+            let (start, end) = (Interval(0), Interval(96));
+            spirit_broadcast_range_osrng(&domain, 0, start, end, &keys, &pk_ha, &mut t_el);
+            t_el
+        })
+    });
+    group.finish();
+}
+
+// broadcasting must not depend on the size of the encounter table
+fn bench_broadcast_large_table(c: &mut Criterion) {
+    let domain = DomainConfig::default();
+    let keys = UserKeys::new(Scalar::from(2u64));
+    let pk_ha = DecryptionKey::random(&mut OsRng).encryption_key();
+
+    let mut t_el = encounter_table(1_000_000, 0, 1);
+
+    let mut group = c.benchmark_group("spirit_broadcast_1m");
+    group.sample_size(10);
+    group.bench_function("single", |b| {
+        b.iter(|| spirit_broadcast_osrng(&domain, 1, Interval(0), &keys, &pk_ha, &mut t_el))
+    });
+    group.finish();
+}
+
+fn bench_tsw_verify(c: &mut Criterion) {
+    let pp = tsw::PublicParameters::new(1);
+    let sks: Vec<_> = (0..100).map(|_| SecretKey::new()).collect();
+    let pks: Vec<_> = sks.iter().map(SecretKey::to_public_key).collect();
+
+    let mut group = c.benchmark_group("tsw_verify");
+    group.sample_size(10);
+    for num_signatures in [10, 100] {
+        let signed: Vec<_> = (0..num_signatures)
+            .map(|i| {
+                let (cm, _) = Commitment::commit(&Scalar::from(i as u64));
+                let sig = sks[i].sign_pedersen_commitment(&cm, 0, &pp);
+                (cm, sig)
+            })
+            .collect();
+        let single_key_sigs: Vec<_> = signed
+            .iter()
+            .map(|(cm, _)| sks[0].sign_pedersen_commitment(cm, 0, &pp))
+            .collect();
+        let single_key: Vec<_> = signed
+            .iter()
+            .zip(&single_key_sigs)
+            .map(|((cm, _), sig)| (cm, 0, sig))
+            .collect();
+        let signers: Vec<_> = signed
+            .iter()
+            .zip(&pks)
+            .map(|((cm, _), pk)| (cm, 0, pk))
+            .collect();
+        let sigs: Vec<_> = signed.iter().map(|(_, sig)| sig.clone()).collect();
+        let aggregate = Signature::aggregate(&sigs);
+        let prepared = tsw::PreparedPublicKey::new(&pks[0]);
+
+        group.bench_with_input(
+            BenchmarkId::new("sequential", num_signatures),
+            &single_key,
+            |b, messages| {
+                b.iter(|| {
+                    messages.iter().all(|(cm, index, sig)| {
+                        pks[0]
+                            .verify_pedersen_commitment(cm, *index, sig, &pp)
+                            .is_ok()
+                    })
+                })
+            },
+        );
+        group.bench_with_input(
+            BenchmarkId::new("prepared", num_signatures),
+            &single_key,
+            |b, messages| {
+                b.iter(|| {
+                    messages.iter().all(|(cm, index, sig)| {
+                        sig.verify_prepared(&prepared, cm, *index, &pp).is_ok()
+                    })
+                })
+            },
+        );
+        group.bench_with_input(
+            BenchmarkId::new("batch", num_signatures),
+            &single_key,
+            |b, messages| b.iter(|| pks[0].verify_batch(messages, &pp).is_ok()),
+        );
+        group.bench_with_input(
+            BenchmarkId::new("aggregate", num_signatures),
+            &signers,
+            |b, messages| b.iter(|| verify_aggregate(messages, &aggregate, &pp).is_ok()),
+        );
+    }
+    group.finish();
+}
+
+// the unbatched verification is measured with `--save-baseline` on an earlier revision
+fn bench_tact_verify(c: &mut Criterion) {
+    let mut group = c.benchmark_group("tact_verify");
+    group.sample_size(10);
+    for (n, t) in [(16, 11), (64, 43)] {
+        let (pp, issuers) = atact::setup(n, n, t, t, 1, &mut OsRng).unwrap();
+        let (strg, cm) = atact::register(&Scalar::from(1u64), &pp, &mut OsRng).unwrap();
+        let (blind_request, rand) = atact::token_request(&strg, &cm, &pp, &mut OsRng).unwrap();
+        let blind_tokens: Vec<_> = issuers
+            .iter()
+            .map(|issuer| atact::tissue(&blind_request, issuer, &pp).unwrap())
+            .collect();
+        let token = atact::aggregate_unblind(&blind_tokens, &rand, &pp).unwrap();
+        let token_proof = atact::prove(&token, &rand, &pp, &mut OsRng);
+
+        group.bench_function(BenchmarkId::from_parameter(format!("n={n},t={t}")), |b| {
+            b.iter(|| atact::verify(&token, &token_proof, &blind_request, &pp, &mut OsRng).unwrap())
+        });
+    }
+    group.finish();
+
+    let pedersen_pp = spirit::pedersen::get_parameters();
+    let (x, y) = (Scalar::from(3u64), Scalar::from(5u64));
+    let (cm_1, o_1) = Commitment::commit(&x);
+    let (cm_2, o_2) = Commitment::commit(&y);
+    let pk = &pedersen_pp.g * y;
+    let proof = cm_1.proof_2_pk(&x, &o_1, &cm_2, &y, &o_2, &pedersen_pp.g, &pk, &mut OsRng);
+    let statement = [(&cm_1, &cm_2, &pedersen_pp.g, &pk, &[][..])];
+
+    let mut group = c.benchmark_group("proof_2_pk_verify");
+    group.bench_function("sequential", |b| {
+        b.iter(|| {
+            cm_1.verify_proof_2_pk(&cm_2, &pedersen_pp.g, &pk, &proof)
+                .unwrap()
+        })
+    });
+    group.bench_function("batched", |b| {
+        b.iter(|| Commitment::verify_proof_2_pk_batch(&statement, &[&proof]).unwrap())
+    });
+
+    // a batch of 64 proofs, and one whose last proof is for another context
+    let mut statements = [statement[0]; 64];
+    let proofs = [&proof; 64];
+    group.bench_function("batch_of_64", |b| {
+        b.iter(|| Proof2PK::verify_batch(&statements, &proofs, &mut OsRng).unwrap())
+    });
+    statements[63].4 = b"other";
+    group.bench_function("bisect_64", |b| {
+        b.iter(|| Proof2PK::verify_batch(&statements, &proofs, &mut OsRng).unwrap_err())
+    });
+    group.finish();
+}
+
+// the precomputed table has to beat the generic multiplication by the same base
+fn bench_fixed_base(c: &mut Criterion) {
+    let base = G1G2::random(OsRng);
+    let table = Precomputed::new(&base);
+    // a full-width scalar, as small ones shorten the generic double-and-add
+    let scalar = -Scalar::from(0x5eed_u64);
+
+    let mut group = c.benchmark_group("fixed_base_mul");
+    group.bench_function("generic", |b| b.iter(|| &base * scalar));
+    group.bench_function("precomputed", |b| b.iter(|| table.mul(&scalar)));
+    group.bench_function("build_table", |b| b.iter(|| Precomputed::new(&base)));
+    group.finish();
+}
+
+// the crossover of the naive sum and Pippenger's buckets, and the speedup for many terms
+fn bench_msm(c: &mut Criterion) {
+    let points: Vec<_> = (0..512).map(|_| G1G2::random(OsRng)).collect();
+    let scalars: Vec<_> = (0..512).map(|i| -Scalar::from(i as u64 + 1)).collect();
+
+    let mut group = c.benchmark_group("msm");
+    for size in [2, 4, 8, 16, 64, 512] {
+        let (points, scalars) = (&points[..size], &scalars[..size]);
+        group.bench_function(BenchmarkId::new("naive", size), |b| {
+            b.iter(|| {
+                points
+                    .iter()
+                    .zip(scalars)
+                    .map(|(point, scalar)| point * *scalar)
+                    .sum::<G1G2>()
+            })
+        });
+        group.bench_function(BenchmarkId::new("msm", size), |b| {
+            b.iter(|| msm(points, scalars))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_prf,
+    bench_fixed_base,
+    bench_msm,
+    bench_tsw_verify,
+    bench_tact_verify,
+    bench_verify,
+    bench_register,
+    bench_register_large_registry,
+    bench_aggregate_unblind,
+    bench_register_batch,
+    bench_broadcast,
+    bench_broadcast_large_table,
+    bench_encounter_lookup,
+    bench_trace
+);
+#[cfg(feature = "rayon")]
+criterion_group!(parallel_benches, bench_verify_parallel);
+
+#[cfg(not(feature = "rayon"))]
+criterion_main!(benches);
+#[cfg(feature = "rayon")]
+criterion_main!(benches, parallel_benches);
@@ -0,0 +1,38 @@
+//! Writes the known-answer vectors of the [testvectors](spirit::testvectors) module for a seed
+//! to stdout, or checks a vectors file with `--check`.
+//!
+//! ```text
+//! cargo run --bin gen-vectors --features testvectors -- [seed] > vectors.json
+//! cargo run --bin gen-vectors --features testvectors -- --check vectors.json
+//! ```
+
+use std::{env, fs, process};
+
+use spirit::testvectors::{DEFAULT_SEED, TestVectors, generate, verify};
+
+fn main() {
+    let args: Vec<_> = env::args().skip(1).collect();
+    match args.as_slice() {
+        [] => print!("{}", generate(DEFAULT_SEED).to_json()),
+        [flag, path] if flag == "--check" => {
+            let json = fs::read_to_string(path).expect("reading the vectors failed");
+            let vectors = TestVectors::from_json(&json).expect("parsing the vectors failed");
+            if let Err(e) = verify(&vectors) {
+                eprintln!("{e}");
+                process::exit(1);
+            }
+            if generate(vectors.seed).to_json() != json {
+                eprintln!("The vectors are not the ones of seed {}.", vectors.seed);
+                process::exit(1);
+            }
+        }
+        [seed] => {
+            let seed = seed.parse().expect("the seed has to be a u64");
+            print!("{}", generate(seed).to_json());
+        }
+        _ => {
+            eprintln!("usage: gen-vectors [seed] | gen-vectors --check <file>");
+            process::exit(2);
+        }
+    }
+}
@@ -1,79 +1,681 @@
-use std::{
+use alloc::vec::Vec;
+use core::{
     borrow::Borrow,
+    cmp::Ordering,
+    hash::{Hash, Hasher},
     iter::Sum,
     ops::{Add, Mul, Neg, Sub},
 };
 
-use ark_bls12_381::Bls12_381;
-use ark_ec::pairing::{Pairing, PairingOutput};
-use ark_ff::{UniformRand, Zero};
-use ark_serialize::CanonicalSerialize;
+use ark_bls12_381::{Bls12_381, Fq, Fq2, g1, g2};
+use ark_ec::{
+    AffineRepr, CurveGroup, VariableBaseMSM,
+    hashing::{curve_maps::wb::WBMap, map_to_curve_hasher::MapToCurve},
+    pairing::{Pairing, PairingOutput},
+};
+use ark_ff::{BigInteger, PrimeField, UniformRand, Zero};
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
 use rand::{RngCore, SeedableRng};
 use rand_chacha::ChaCha20Rng;
-use sha2::Digest;
+use sha2::{Digest, Sha256, digest::core_api::BlockSizeUser};
+use sha3::Sha3_256;
+use subtle::{Choice, ConstantTimeEq};
+use thiserror::Error;
+#[cfg(feature = "zeroize")]
+use zeroize::Zeroize;
+
+use crate::{
+    constants::{HASHED_INDEX_DST, OVERSIZE_DST_PREFIX},
+    envelope::ArtifactType,
+};
 
 pub type G1Affine = <Bls12_381 as Pairing>::G1Affine;
 pub type G1Projective = <Bls12_381 as Pairing>::G1;
 pub type G2Affine = <Bls12_381 as Pairing>::G2Affine;
 pub type G2Projective = <Bls12_381 as Pairing>::G2;
+pub type G2Prepared = <Bls12_381 as Pairing>::G2Prepared;
 pub type Gt = PairingOutput<Bls12_381>;
 pub type Scalar = <Bls12_381 as Pairing>::ScalarField;
 
-#[inline]
-fn hash_with_domain_separation_1(msg: &[u8], domain_separator: &[u8]) -> G1Projective {
-    let mut digest = sha2::Sha256::new();
-    digest.update(domain_separator);
-    digest.update(msg);
+/// Hash function of the domain-separated hashes of a deployment, chosen by its
+/// [HashBackendId] in the [DomainConfig](crate::spirit::DomainConfig). Points hashed with one
+/// backend differ from the points of another in both [HashToCurveSuite]s.
+pub trait HashBackend: Digest + BlockSizeUser + Sized {
+    const ID: HashBackendId;
 
-    let mut rng = ChaCha20Rng::from_seed(digest.finalize().into());
-    G1Projective::rand(&mut rng)
+    /// `expand_message_xmd` of RFC 9380 with the backend: `len` uniform bytes from `msg` and
+    /// `dst`.
+    fn expand(msg: &[u8], dst: &[u8], len: usize) -> Vec<u8> {
+        expand_message_xmd::<Self>(msg, dst, len)
+    }
 }
 
-#[inline]
-fn hash_with_domain_separation_2(msg: &[u8], domain_separator: &[u8]) -> G2Projective {
-    let mut digest = sha2::Sha256::new();
-    digest.update(domain_separator);
-    digest.update(msg);
+impl HashBackend for Sha256 {
+    const ID: HashBackendId = HashBackendId::Sha256;
+}
 
-    let mut rng = ChaCha20Rng::from_seed(digest.finalize().into());
-    G2Projective::rand(&mut rng)
+impl HashBackend for Sha3_256 {
+    const ID: HashBackendId = HashBackendId::Sha3_256;
 }
 
+/// Identifier of a [HashBackend], recorded in the tACT public parameters of a deployment so
+/// that parameters and a [DomainConfig](crate::spirit::DomainConfig) of different backends are
+/// not used together.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[repr(u8)]
+pub enum HashBackendId {
+    /// SHA-256, the hash of the releases before the backend was configurable.
+    #[default]
+    Sha256 = 0,
+    Sha3_256 = 1,
+}
+
+impl HashBackendId {
+    /// Name of the hash function in the suite IDs of RFC 9380, e.g. `SHA-256`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Sha256 => "SHA-256",
+            Self::Sha3_256 => "SHA3-256",
+        }
+    }
+}
+
+/// The [HashToCurveSuite::Legacy] hash with SHA-256, which the fixed generators of the Pedersen
+/// commitments and signatures keep using in every suite and with every backend.
 #[inline]
 pub fn hash_with_domain_separation(msg: &[u8], domain_separator: &[u8]) -> G1G2 {
+    hash_with_domain_separation_with::<Sha256>(msg, domain_separator)
+}
+
+/// The [HashToCurveSuite::Legacy] hash with the backend `B`: points drawn from a ChaCha20 RNG
+/// seeded with the first 32 bytes of `B(dst || msg)`.
+pub fn hash_with_domain_separation_with<B: HashBackend>(
+    msg: &[u8],
+    domain_separator: &[u8],
+) -> G1G2 {
+    let digest = B::new()
+        .chain_update(domain_separator)
+        .chain_update(msg)
+        .finalize();
+    let mut seed = [0u8; 32];
+    seed.copy_from_slice(&digest[..32]);
+
+    let rng = || ChaCha20Rng::from_seed(seed);
     G1G2(
-        hash_with_domain_separation_1(msg, domain_separator),
-        hash_with_domain_separation_2(msg, domain_separator),
+        G1Projective::rand(&mut rng()),
+        G2Projective::rand(&mut rng()),
     )
 }
 
+/// Hash-to-curve of the domain-separated hashes of a deployment, e.g. of the PRF, chosen in its
+/// [DomainConfig](crate::spirit::DomainConfig). ElIDs, tokens and proofs of one suite do not
+/// verify under the other.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[repr(u8)]
+pub enum HashToCurveSuite {
+    /// Points drawn from a ChaCha20 RNG seeded with `SHA-256(dst || msg)`, as in the releases
+    /// before the RFC 9380 suites, which remains the default so that existing deployments keep
+    /// their points. Only this crate implements it.
+    #[default]
+    Legacy = 0,
+    /// The `BLS12381G1_XMD:SHA-256_SSWU_RO_` and `BLS12381G2_XMD:SHA-256_SSWU_RO_` suites of
+    /// RFC 9380, with the suite ID appended to the tag as the DST, so that other BLS12-381
+    /// implementations derive the same points. RFC 9380 defines these suites only with
+    /// [HashBackendId::Sha256].
+    Rfc9380 = 1,
+}
+
+/// Bytes per base field element in hash_to_field, `ceil((381 + 128) / 8)`.
+const FIELD_ELEMENT_SIZE: usize = 64;
+
+/// Hashes `msg` under the tag `domain_separator` to both groups with `suite` and SHA-256.
+pub fn hash_to_curve(suite: HashToCurveSuite, msg: &[u8], domain_separator: &[u8]) -> G1G2 {
+    hash_to_curve_with(suite, HashBackendId::Sha256, msg, domain_separator)
+}
+
+/// Like [hash_to_curve], but with the hash function `backend`.
+pub fn hash_to_curve_with(
+    suite: HashToCurveSuite,
+    backend: HashBackendId,
+    msg: &[u8],
+    domain_separator: &[u8],
+) -> G1G2 {
+    match backend {
+        HashBackendId::Sha256 => hash_to_curve_backend::<Sha256>(suite, msg, domain_separator),
+        HashBackendId::Sha3_256 => hash_to_curve_backend::<Sha3_256>(suite, msg, domain_separator),
+    }
+}
+
+fn hash_to_curve_backend<B: HashBackend>(
+    suite: HashToCurveSuite,
+    msg: &[u8],
+    domain_separator: &[u8],
+) -> G1G2 {
+    match suite {
+        HashToCurveSuite::Legacy => hash_with_domain_separation_with::<B>(msg, domain_separator),
+        HashToCurveSuite::Rfc9380 => {
+            let dst = |group: &[u8]| suite_dst::<B>(domain_separator, group);
+            G1G2(
+                hash_to_g1::<B>(msg, &dst(b"G1")),
+                hash_to_g2::<B>(msg, &dst(b"G2")),
+            )
+        }
+    }
+}
+
+/// `domain_separator` followed by the suite ID `BLS12381<group>_XMD:<hash>_SSWU_RO_`.
+fn suite_dst<B: HashBackend>(domain_separator: &[u8], group: &[u8]) -> Vec<u8> {
+    [
+        domain_separator,
+        b"BLS12381",
+        group,
+        b"_XMD:",
+        B::ID.name().as_bytes(),
+        b"_SSWU_RO_",
+    ]
+    .concat()
+}
+
+/// `expand_message_xmd` of RFC 9380 with the hash `D`: `len` uniform bytes from `msg` and
+/// `dst`. Tags longer than 255 bytes, e.g. of a long `app_id`, are hashed first as the RFC
+/// specifies.
+pub(crate) fn expand_message_xmd<D: Digest + BlockSizeUser>(
+    msg: &[u8],
+    dst: &[u8],
+    len: usize,
+) -> Vec<u8> {
+    let output_size = <D as Digest>::output_size();
+    let ell = len.div_ceil(output_size);
+    debug_assert!(ell <= 255 && len <= usize::from(u16::MAX));
+    let oversize;
+    let dst = if dst.len() > 255 {
+        oversize = D::new()
+            .chain_update(OVERSIZE_DST_PREFIX)
+            .chain_update(dst)
+            .finalize();
+        &oversize[..]
+    } else {
+        dst
+    };
+    let with_dst = |digest: D| {
+        digest
+            .chain_update(dst)
+            .chain_update([dst.len() as u8])
+            .finalize()
+    };
+
+    let b_0 = with_dst(
+        D::new()
+            .chain_update(alloc::vec![0u8; D::block_size()])
+            .chain_update(msg)
+            .chain_update((len as u16).to_be_bytes())
+            .chain_update([0]),
+    );
+    let mut b_i = with_dst(D::new().chain_update(&b_0).chain_update([1]));
+    let mut bytes = Vec::with_capacity(output_size * ell);
+    bytes.extend_from_slice(&b_i);
+    for i in 2..=ell {
+        let xored: Vec<u8> = b_0.iter().zip(&b_i).map(|(a, b)| a ^ b).collect();
+        b_i = with_dst(D::new().chain_update(xored).chain_update([i as u8]));
+        bytes.extend_from_slice(&b_i);
+    }
+    bytes.truncate(len);
+    bytes
+}
+
+/// `hash_to_curve` of `BLS12381G1_XMD:<hash>_SSWU_RO_` with the full DST `dst`.
+pub(crate) fn hash_to_g1<B: HashBackend>(msg: &[u8], dst: &[u8]) -> G1Projective {
+    let bytes = B::expand(msg, dst, 2 * FIELD_ELEMENT_SIZE);
+    let map = WBMap::<g1::Config>::new().expect("valid SSWU parameters");
+    let point: G1Projective = bytes
+        .chunks_exact(FIELD_ELEMENT_SIZE)
+        .map(|u| map.map_to_curve(Fq::from_be_bytes_mod_order(u)).unwrap())
+        .sum();
+    point.into_affine().clear_cofactor().into_group()
+}
+
+/// `hash_to_curve` of `BLS12381G2_XMD:<hash>_SSWU_RO_` with the full DST `dst`.
+pub(crate) fn hash_to_g2<B: HashBackend>(msg: &[u8], dst: &[u8]) -> G2Projective {
+    let bytes = B::expand(msg, dst, 4 * FIELD_ELEMENT_SIZE);
+    let map = WBMap::<g2::Config>::new().expect("valid SSWU parameters");
+    let point: G2Projective = bytes
+        .chunks_exact(2 * FIELD_ELEMENT_SIZE)
+        .map(|u| {
+            let (c0, c1) = u.split_at(FIELD_ELEMENT_SIZE);
+            let u = Fq2::new(
+                Fq::from_be_bytes_mod_order(c0),
+                Fq::from_be_bytes_mod_order(c1),
+            );
+            map.map_to_curve(u).unwrap()
+        })
+        .sum();
+    point.into_affine().clear_cofactor().into_group()
+}
+
 #[inline]
 pub fn hash_usize(size: usize) -> G1G2 {
     let bytes = (size as u64).to_le_bytes();
-    hash_with_domain_separation(&bytes, b"hash-usize")
+    hash_with_domain_separation(&bytes, HASHED_INDEX_DST)
+}
+
+#[cfg(feature = "std")]
+std::thread_local! {
+    /// Pairings and terms of multi-scalar multiplications computed on the thread, see [metered].
+    static COSTS: core::cell::Cell<(usize, usize)> = const { core::cell::Cell::new((0, 0)) };
+}
+
+#[inline]
+fn count(pairings: usize, msm_terms: usize) {
+    #[cfg(feature = "std")]
+    COSTS.with(|costs| {
+        let (counted_pairings, counted_terms) = costs.get();
+        costs.set((counted_pairings + pairings, counted_terms + msm_terms));
+    });
+    #[cfg(not(feature = "std"))]
+    let _ = (pairings, msm_terms);
+}
+
+/// Runs `f` and returns its result with the number of pairings and of terms of multi-scalar
+/// multiplications it computed on this thread with the pairings of this module and [msm].
+#[cfg(feature = "std")]
+pub(crate) fn metered<R>(f: impl FnOnce() -> R) -> (R, usize, usize) {
+    let (pairings, msm_terms) = COSTS.with(core::cell::Cell::get);
+    let result = f();
+    let (counted_pairings, counted_terms) = COSTS.with(core::cell::Cell::get);
+    (
+        result,
+        counted_pairings - pairings,
+        counted_terms - msm_terms,
+    )
 }
 
 #[allow(dead_code)]
 #[inline]
 pub fn pairing(lhs: &G1G2, rhs: &G1G2) -> Gt {
+    count(1, 0);
     Bls12_381::pairing(lhs.0, rhs.1)
 }
 
 #[inline]
 pub fn multi_pairing(elements: &[(&G1G2, &G1G2)]) -> Gt {
+    count(elements.len(), 0);
     Bls12_381::multi_pairing(
         elements.iter().map(|(lhs, _rhs)| G1Affine::from(lhs.0)),
         elements.iter().map(|(_lhs, rhs)| G2Affine::from(rhs.1)),
     )
 }
 
-#[derive(Debug, Clone, Default, PartialEq, Eq, CanonicalSerialize)]
+/// Like [multi_pairing], with the G2 parts of the right-hand sides [prepared](prepare_g2).
+#[inline]
+pub fn multi_pairing_prepared(elements: &[(&G1G2, &G2Prepared)]) -> Gt {
+    count(elements.len(), 0);
+    Bls12_381::multi_pairing(
+        elements.iter().map(|(lhs, _rhs)| G1Affine::from(lhs.0)),
+        elements.iter().map(|(_lhs, rhs)| (*rhs).clone()),
+    )
+}
+
+/// Precomputes the lines of the Miller loop of the G2 part of `point`, for many pairings with
+/// the same right-hand side.
+pub fn prepare_g2(point: &G1G2) -> G2Prepared {
+    G2Affine::from(point.1).into()
+}
+
+/// Computes `Σ_i points_i * scalars_i` with one multi-scalar multiplication per group.
+pub fn multi_scalar_mul(points: &[G1G2], scalars: &[Scalar]) -> G1G2 {
+    debug_assert_eq!(points.len(), scalars.len());
+
+    let points_1: Vec<_> = points.iter().map(|point| point.0).collect();
+    let points_2: Vec<_> = points.iter().map(|point| point.1).collect();
+    G1G2(
+        G1Projective::msm_unchecked(&G1Projective::normalize_batch(&points_1), scalars),
+        G2Projective::msm_unchecked(&G2Projective::normalize_batch(&points_2), scalars),
+    )
+}
+
+/// Below this many terms, [msm] adds up the products, as the normalization and the buckets of
+/// [multi_scalar_mul] cost more than they save.
+const MSM_NAIVE_TERMS: usize = 8;
+
+/// Computes `Σ_i points_i * scalars_i`, one product at a time for few terms and with the
+/// bucketed Pippenger multiplication of [multi_scalar_mul] otherwise.
+pub fn msm(points: &[G1G2], scalars: &[Scalar]) -> G1G2 {
+    debug_assert_eq!(points.len(), scalars.len());
+    count(0, points.len());
+
+    if points.len() < MSM_NAIVE_TERMS {
+        points
+            .iter()
+            .zip(scalars)
+            .map(|(point, scalar)| point * *scalar)
+            .sum()
+    } else {
+        multi_scalar_mul(points, scalars)
+    }
+}
+
+#[derive(Debug, Clone, Default, CanonicalSerialize, CanonicalDeserialize)]
 pub struct G1G2(pub G1Projective, pub G2Projective);
 
+/// Global that is initialized on first use, backed by `OnceLock` with `std` and by
+/// `once_cell::race::OnceBox` without.
+pub(crate) struct OnceGlobal<T> {
+    #[cfg(feature = "std")]
+    inner: std::sync::OnceLock<T>,
+    #[cfg(not(feature = "std"))]
+    inner: once_cell::race::OnceBox<T>,
+}
+
+impl<T> OnceGlobal<T> {
+    pub(crate) const fn new() -> Self {
+        Self {
+            #[cfg(feature = "std")]
+            inner: std::sync::OnceLock::new(),
+            #[cfg(not(feature = "std"))]
+            inner: once_cell::race::OnceBox::new(),
+        }
+    }
+
+    /// Global that is already initialized to `value`.
+    pub(crate) fn with_value(value: T) -> Self {
+        Self {
+            #[cfg(feature = "std")]
+            inner: std::sync::OnceLock::from(value),
+            #[cfg(not(feature = "std"))]
+            inner: once_cell::race::OnceBox::with_value(alloc::boxed::Box::new(value)),
+        }
+    }
+
+    pub(crate) fn get_or_init(&self, f: impl FnOnce() -> T) -> &T {
+        #[cfg(feature = "std")]
+        return self.inner.get_or_init(f);
+        #[cfg(not(feature = "std"))]
+        return self.inner.get_or_init(|| alloc::boxed::Box::new(f()));
+    }
+}
+
+/// Bits of a scalar per window of a [Precomputed] table.
+const WINDOW_BITS: usize = 4;
+const WINDOW_SIZE: usize = 1 << WINDOW_BITS;
+const NUM_WINDOWS: usize = (Scalar::MODULUS_BIT_SIZE as usize).div_ceil(WINDOW_BITS);
+
+/// Fixed-window table of the multiples `j 2^(4i) base` of a fixed base for all digits `j < 16`
+/// and windows `i`, so a multiplication by a scalar takes one addition per 4-bit window and no
+/// doublings. Tables are built once, e.g. in [spirit_setup](crate::spirit::spirit_setup), and
+/// can be stored in their canonical serialization to skip rebuilding them. Like the generic
+/// multiplication, the lookups depend on the scalar.
+#[derive(Debug, Clone, PartialEq, Eq, CanonicalSerialize, CanonicalDeserialize)]
+pub struct Precomputed<T: CanonicalSerialize + CanonicalDeserialize> {
+    base: T,
+    /// `windows[WINDOW_SIZE * i + j]` holds `j 2^(WINDOW_BITS i) base`.
+    windows: Vec<T>,
+}
+
+impl Precomputed<G1G2> {
+    pub fn new(base: &G1G2) -> Self {
+        let mut windows = Vec::with_capacity(NUM_WINDOWS * WINDOW_SIZE);
+        let mut window_base = base.clone();
+        for _ in 0..NUM_WINDOWS {
+            let mut multiple = G1G2::default();
+            for _ in 0..WINDOW_SIZE {
+                windows.push(multiple.clone());
+                multiple = &multiple + &window_base;
+            }
+            window_base = multiple;
+        }
+        Self {
+            base: base.clone(),
+            windows,
+        }
+    }
+
+    pub fn base(&self) -> &G1G2 {
+        &self.base
+    }
+
+    /// `base * scalar`, equal to the generic multiplication.
+    pub fn mul(&self, scalar: &Scalar) -> G1G2 {
+        let digits = scalar
+            .into_bigint()
+            .to_bytes_le()
+            .into_iter()
+            .flat_map(|byte| [byte & 0x0f, byte >> 4]);
+        let (mut g_1, mut g_2) = (G1Projective::zero(), G2Projective::zero());
+        for (window, digit) in self.windows.chunks_exact(WINDOW_SIZE).zip(digits) {
+            if digit != 0 {
+                let multiple = &window[usize::from(digit)];
+                g_1 += multiple.0;
+                g_2 += multiple.1;
+            }
+        }
+        G1G2(g_1, g_2)
+    }
+}
+
+#[derive(Error, Debug, PartialEq, Eq, Clone)]
+pub enum DecodeError {
+    #[error("Invalid length: expected {expected} bytes, got {actual}.")]
+    InvalidLength { expected: usize, actual: usize },
+    #[error("Invalid point encoding.")]
+    InvalidPoint,
+    #[error("Unexpected identity element.")]
+    Identity,
+    #[error("Missing or invalid artifact header.")]
+    InvalidHeader,
+    #[error("Unexpected artifact type {actual}, expected {expected:?}.")]
+    UnexpectedArtifactType { expected: ArtifactType, actual: u8 },
+    #[error("Unsupported artifact version {0}.")]
+    UnsupportedVersion(u16),
+    #[error("Invalid scalar encoding.")]
+    InvalidScalar,
+    #[error("Checksum does not match the contents.")]
+    ChecksumMismatch,
+}
+
+/// Splits the first `N` bytes off `bytes`, for decoders that read fields of a fixed size
+/// without indexing past the end of a short input.
+pub(crate) fn split_array<const N: usize>(bytes: &[u8]) -> Result<(&[u8; N], &[u8]), DecodeError> {
+    bytes.split_first_chunk().ok_or(DecodeError::InvalidLength {
+        expected: N,
+        actual: bytes.len(),
+    })
+}
+
+/// Appends the compressed encoding of `value` to `bytes`, e.g. to hash it.
+pub(crate) fn append_compressed(bytes: &mut Vec<u8>, value: &impl CanonicalSerialize) {
+    // infallible for a Vec
+    let _ = value.serialize_compressed(bytes);
+}
+
 impl G1G2 {
+    /// Size of the compressed G1 element followed by the compressed G2 element.
+    pub const ENCODED_SIZE: usize = 48 + 96;
+
     pub fn random(mut rng: impl RngCore) -> Self {
         Self(G1Projective::rand(&mut rng), G2Projective::rand(&mut rng))
     }
+
+    pub fn to_bytes(&self) -> [u8; Self::ENCODED_SIZE] {
+        let mut bytes = [0u8; Self::ENCODED_SIZE];
+        self.serialize_compressed(&mut bytes[..])
+            .expect("the buffer has the size of the compressed encoding");
+        bytes
+    }
+
+    /// Whether both points are the identity, e.g. the output of a multiplication by 0.
+    pub fn is_identity(&self) -> bool {
+        self.0.is_zero() && self.1.is_zero()
+    }
+
+    /// Whether both points are on their curve, in the prime-order subgroup and not the
+    /// identity, as checked by [Self::from_bytes], e.g. for points that were not decoded.
+    pub(crate) fn is_valid_element(&self) -> bool {
+        if self.0.is_zero() || self.1.is_zero() {
+            return false;
+        }
+        let (p1, p2) = (self.0.into_affine(), self.1.into_affine());
+        p1.is_on_curve()
+            && p1.is_in_correct_subgroup_assuming_on_curve()
+            && p2.is_on_curve()
+            && p2.is_in_correct_subgroup_assuming_on_curve()
+    }
+
+    /// Decodes a compressed G1G2 element. Both points are checked to be in the prime-order
+    /// subgroup and to not be the identity, which never occurs for protocol messages.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, DecodeError> {
+        if bytes.len() != Self::ENCODED_SIZE {
+            return Err(DecodeError::InvalidLength {
+                expected: Self::ENCODED_SIZE,
+                actual: bytes.len(),
+            });
+        }
+
+        let g1g2 = Self::deserialize_compressed(bytes).map_err(|_| DecodeError::InvalidPoint)?;
+        if g1g2.0.is_zero() || g1g2.1.is_zero() {
+            return Err(DecodeError::Identity);
+        }
+        Ok(g1g2)
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl Zeroize for G1G2 {
+    fn zeroize(&mut self) {
+        self.0.zeroize();
+        self.1.zeroize();
+    }
+}
+
+/// Compares the canonical encodings in constant time. The conversion to affine coordinates
+/// before encoding is not constant-time, but only depends on the points themselves and not on
+/// the value they are compared against.
+impl ConstantTimeEq for G1G2 {
+    fn ct_eq(&self, other: &Self) -> Choice {
+        self.to_bytes()[..].ct_eq(&other.to_bytes()[..])
+    }
+}
+
+/// Equality of the points, which agrees with the equality of their [encodings](G1G2::to_bytes)
+/// whatever their projective coordinates.
+impl PartialEq for G1G2 {
+    fn eq(&self, other: &Self) -> bool {
+        self.0 == other.0 && self.1 == other.1
+    }
+}
+
+impl Eq for G1G2 {}
+
+/// Hashes the [encoding](G1G2::to_bytes), so that equal points hash the same whatever their
+/// projective coordinates, and in every process.
+impl Hash for G1G2 {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        state.write(&self.to_bytes());
+    }
+}
+
+/// Orders points by their [encoding](G1G2::to_bytes), so that sorted collections of points
+/// iterate and serialize in the same order everywhere.
+impl Ord for G1G2 {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.to_bytes().cmp(&other.to_bytes())
+    }
+}
+
+impl PartialOrd for G1G2 {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Constant-time equality of scalars. [Scalar] is a foreign type, so [ConstantTimeEq] cannot
+/// be implemented for it directly; this compares the canonical encodings instead.
+pub fn scalar_ct_eq(lhs: &Scalar, rhs: &Scalar) -> Choice {
+    let mut lhs_bytes = [0u8; 32];
+    let mut rhs_bytes = [0u8; 32];
+    lhs.serialize_compressed(&mut lhs_bytes[..])
+        .expect("the buffer has the size of the scalar encoding");
+    rhs.serialize_compressed(&mut rhs_bytes[..])
+        .expect("the buffer has the size of the scalar encoding");
+    lhs_bytes.ct_eq(&rhs_bytes)
+}
+
+/// serde adapter for arkworks types using their compressed encoding. Points are checked to be
+/// on the curve and in the prime-order subgroup when deserializing.
+///
+/// Use as `#[serde(with = "crate::bls381_helpers::serde_compressed")]` for fields of foreign
+/// types such as [Scalar].
+#[cfg(feature = "serde")]
+pub(crate) mod serde_compressed {
+    use alloc::vec::Vec;
+
+    use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
+    use serde::{Deserialize, Deserializer, Serializer, de::Error as _, ser::Error as _};
+
+    pub fn serialize<T, S>(value: &T, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        T: CanonicalSerialize,
+        S: Serializer,
+    {
+        let mut bytes = Vec::with_capacity(value.compressed_size());
+        value
+            .serialize_compressed(&mut bytes)
+            .map_err(S::Error::custom)?;
+        serializer.serialize_bytes(&bytes)
+    }
+
+    pub fn deserialize<'de, T, D>(deserializer: D) -> Result<T, D::Error>
+    where
+        T: CanonicalDeserialize,
+        D: Deserializer<'de>,
+    {
+        let bytes = Vec::<u8>::deserialize(deserializer)?;
+        let mut reader = bytes.as_slice();
+        let value = T::deserialize_compressed(&mut reader).map_err(D::Error::custom)?;
+        if !reader.is_empty() {
+            return Err(D::Error::custom("trailing bytes"));
+        }
+        Ok(value)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for G1G2 {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serde_compressed::serialize(self, serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for G1G2 {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        serde_compressed::deserialize(deserializer)
+    }
+}
+
+/// Serializes `value` with bincode and JSON, deserializes it again and checks that the encodings
+/// are stable.
+#[cfg(all(test, feature = "serde"))]
+pub(crate) fn serde_round_trip<T>(value: &T) -> T
+where
+    T: serde::Serialize + serde::de::DeserializeOwned,
+{
+    let bytes = bincode::serialize(value).unwrap();
+    let from_bytes: T = bincode::deserialize(&bytes).unwrap();
+    assert_eq!(bincode::serialize(&from_bytes).unwrap(), bytes);
+
+    let json = serde_json::to_string(value).unwrap();
+    let from_json: T = serde_json::from_str(&json).unwrap();
+    assert_eq!(serde_json::to_string(&from_json).unwrap(), json);
+
+    from_bytes
 }
 
 impl<G> Add<G> for G1G2
@@ -229,8 +831,31 @@ impl From<&G1G2> for G2Affine {
  */
 
 #[cfg(test)]
-mod test {
+pub(crate) mod test {
+    use ark_ec::AffineRepr;
+    use proptest::{prop_assert_eq, proptest};
+
     use super::*;
+    use crate::arbitrary;
+
+    /// Zeroizes `value` like its `Drop` does and checks that the scalars selected by `secrets`,
+    /// which must be non-zero before, are cleared. They are inspected before the value is
+    /// dropped, as the memory of a dropped value must not be read.
+    #[cfg(feature = "zeroize")]
+    pub(crate) fn assert_zeroized_on_drop<T: zeroize::Zeroize + zeroize::ZeroizeOnDrop>(
+        mut value: T,
+        secrets: impl Fn(&T) -> Vec<Scalar>,
+    ) {
+        assert!(secrets(&value).iter().all(|secret| !secret.is_zero()));
+
+        value.zeroize();
+        for secret in secrets(&value) {
+            let mut bytes = [0u8; 32];
+            secret.serialize_compressed(&mut bytes[..]).unwrap();
+            assert_eq!(bytes, [0u8; 32]);
+        }
+        drop(value);
+    }
 
     #[test]
     fn pp() {
@@ -248,6 +873,408 @@ mod test {
         let pp = multi_pairing(&[(&lhs1, &rhs1), (&-lhs2, &rhs2)]);
         assert_eq!(check, pp);
     }
+
+    #[test]
+    fn msm() {
+        let mut rng = rand::thread_rng();
+        let points: Vec<_> = (0..10).map(|_| G1G2::random(&mut rng)).collect();
+        let scalars: Vec<_> = (0..10).map(|_| Scalar::rand(&mut rng)).collect();
+
+        let check: G1G2 = points
+            .iter()
+            .zip(&scalars)
+            .map(|(point, scalar)| point * *scalar)
+            .sum();
+        assert_eq!(multi_scalar_mul(&points, &scalars), check);
+    }
+
+    #[test]
+    fn msm_matches_the_naive_sum() {
+        let mut rng = rand::thread_rng();
+        let points: Vec<_> = (0..512).map(|_| G1G2::random(&mut rng)).collect();
+        let scalars: Vec<_> = (0..512).map(|_| Scalar::rand(&mut rng)).collect();
+
+        // both sides of the crossover, and sizes with partial windows of buckets
+        for size in [
+            1,
+            2,
+            MSM_NAIVE_TERMS - 1,
+            MSM_NAIVE_TERMS,
+            9,
+            33,
+            100,
+            257,
+            512,
+        ] {
+            let (points, scalars) = (&points[..size], &scalars[..size]);
+            let check: G1G2 = points
+                .iter()
+                .zip(scalars)
+                .map(|(point, scalar)| point * *scalar)
+                .sum();
+            assert_eq!(msm(points, scalars), check, "size {size}");
+        }
+        assert_eq!(msm(&[], &[]), G1G2::default());
+
+        // scalars of zero, one and minus one do not fill every bucket
+        let edge = [Scalar::zero(), Scalar::from(1u64), -Scalar::from(1u64)];
+        let scalars: Vec<_> = (0..64).map(|i| edge[i % 3]).collect();
+        let check: G1G2 = points[..64]
+            .iter()
+            .zip(&scalars)
+            .map(|(point, scalar)| point * *scalar)
+            .sum();
+        assert_eq!(msm(&points[..64], &scalars), check);
+    }
+
+    const KAT_G1G2_1: &str = "97f1d3a73197d7942695638c4fa9ac0fc3688c4f9774b905a14e3a3f171bac586c55e83ff97a1aeffb3af00adb22c6bb93e02b6052719f607dacd3a088274f65596bd0d09920b61ab5da61bbdc7f5049334cf11213945d57e5ac7d055d042b7e024aa2b2f08f0a91260805272dc51051c6e47ad4fa403b02b4510b647ae3d1770bac0326a805bbefd48056c8c121bdb8";
+    const KAT_G1G2_2: &str = "a572cbea904d67468808c8eb50a9450c9721db309128012543902d0ac358a62ae28f75bb8f1c7c42c39a8c5529bf0f4eaa4edef9c1ed7f729f520e47730a124fd70662a904ba1074728114d1031e1572c6c886f6b57ec72a6178288c47c335771638533957d540a9d2370f17cc7ed5863bc0b995b8825e0ee1ea1e1e4d00dbae81f14b0bf3611b78c952aacab827a053";
+
+    pub(crate) fn generator_multiple(k: u64) -> G1G2 {
+        G1G2(G1Affine::generator().into(), G2Affine::generator().into()) * Scalar::from(k)
+    }
+
+    #[test]
+    fn ct_eq_agrees_with_eq() {
+        let mut rng = rand::thread_rng();
+        for _ in 0..10 {
+            let lhs = G1G2::random(&mut rng);
+            let rhs = G1G2::random(&mut rng);
+            // same point in a different projective representation
+            let same = &(&lhs + &lhs) - &lhs;
+            assert_eq!(bool::from(lhs.ct_eq(&rhs)), lhs == rhs);
+            assert_eq!(bool::from(lhs.ct_eq(&same)), lhs == same);
+            assert!(bool::from(lhs.ct_eq(&same)));
+
+            let a = Scalar::rand(&mut rng);
+            let b = Scalar::rand(&mut rng);
+            assert_eq!(bool::from(scalar_ct_eq(&a, &b)), a == b);
+            assert!(bool::from(scalar_ct_eq(&a, &(a + b - b))));
+        }
+    }
+
+    /// `expand_message_xmd` vectors of RFC 9380, appendix K.1.
+    #[test]
+    fn expand_message_xmd_vectors() {
+        const DST: &[u8] = b"QUUX-V01-CS02-with-expander-SHA256-128";
+        let q128 = [b"q128_".as_slice(), &[b'q'; 128]].concat();
+        let a512 = [b"a512_".as_slice(), &[b'a'; 512]].concat();
+        for (msg, uniform_bytes) in [
+            (
+                &b""[..],
+                "68a985b87eb6b46952128911f2a4412bbc302a9d759667f87f7a21d803f07235",
+            ),
+            (
+                b"abc",
+                "d8ccab23b5985ccea865c6c97b6e5b8350e794e603b4b97902f53a8a0d605615",
+            ),
+            (
+                b"abcdef0123456789",
+                "eff31487c770a893cfb36f912fbfcbff40d5661771ca4b2cb4eafe524333f5c1",
+            ),
+            (
+                &q128,
+                "b23a1d2b4d97b2ef7785562a7e8bac7eed54ed6e97e29aa51bfe3f12ddad1ff9",
+            ),
+            (
+                &a512,
+                "4623227bcc01293b8c130bf771da8c298dede7383243dc0993d2d94823958c4c",
+            ),
+        ] {
+            assert_eq!(
+                hex::encode(expand_message_xmd::<Sha256>(msg, DST, 0x20)),
+                uniform_bytes
+            );
+        }
+        assert_eq!(
+            hex::encode(expand_message_xmd::<Sha256>(b"", DST, 0x80)),
+            "af84c27ccfd45d41914fdff5df25293e221afc53d8ad2ac06d5e3e29485dadbee0d121587713a3e0dd4d5e69e93eb7cd4f5df4cd103e188cf60cb02edc3edf18eda8576c412b18ffb658e3dd6ec849469b979d444cf7b26911a08e63cf31f9dcc541708d3491184472c2c29bb749d4286b004ceb5ee6b9a7fa5b646c993f0ced"
+        );
+    }
+
+    /// `expand_message_xmd` with SHA3-256, for the tag of the SHA-256 vectors with the hash
+    /// replaced. Computed independently of this crate, RFC 9380 lists no vectors for SHA3-256.
+    #[test]
+    fn expand_message_xmd_sha3_vectors() {
+        const DST: &[u8] = b"QUUX-V01-CS02-with-expander-SHA3-256-128";
+        for (msg, uniform_bytes) in [
+            (
+                &b""[..],
+                "f9b3bacae4f0463c178de7e151a911aacf42c897abb9b3e60c335d5dcda9b637",
+            ),
+            (
+                b"abc",
+                "1029292ebbccaf22590ef8596b6e189b7801fbc4ce0a2f6156bbca7c1b05aade",
+            ),
+            (
+                b"abcdef0123456789",
+                "4b38e3a5b6c31087a1da4291bdc32f1d7d6b56d0c633f6d727e3712fba8d6a02",
+            ),
+        ] {
+            assert_eq!(hex::encode(Sha3_256::expand(msg, DST, 0x20)), uniform_bytes);
+        }
+        assert_eq!(
+            hex::encode(Sha3_256::expand(b"", DST, 0x80)),
+            "5029e7e0cb1d8c7076278a67c21f59c00caa3afe0e4d0ebee56259bd6d13714f5aaf296bd241c1554a8be6ecacc6ba152b7f0d09ea8f33e50e3574ba6fd81761471ed9f915aa5901da49c1023be3c0a19bd6c4502cc434317e157e9ebcb5bc41f2961cc534595537264e0966b1b172bc0ba464745354814aa1e53dfee3ca394e"
+        );
+    }
+
+    fn fq(hex: &str) -> Fq {
+        Fq::from_be_bytes_mod_order(&hex::decode(hex).unwrap())
+    }
+
+    /// `hash_to_curve` vectors of RFC 9380, appendices J.9.1 and J.10.1.
+    #[test]
+    fn hash_to_curve_vectors() {
+        const DST_1: &[u8] = b"QUUX-V01-CS02-with-BLS12381G1_XMD:SHA-256_SSWU_RO_";
+        const DST_2: &[u8] = b"QUUX-V01-CS02-with-BLS12381G2_XMD:SHA-256_SSWU_RO_";
+        for (msg, x, y) in [
+            (
+                &b""[..],
+                "052926add2207b76ca4fa57a8734416c8dc95e24501772c814278700eed6d1e4e8cf62d9c09db0fac349612b759e79a1",
+                "08ba738453bfed09cb546dbb0783dbb3a5f1f566ed67bb6be0e8c67e2e81a4cc68ee29813bb7994998f3eae0c9c6a265",
+            ),
+            (
+                b"abc",
+                "03567bc5ef9c690c2ab2ecdf6a96ef1c139cc0b2f284dca0a9a7943388a49a3aee664ba5379a7655d3c68900be2f6903",
+                "0b9c15f3fe6e5cf4211f346271d7b01c8f3b28be689c8429c85b67af215533311f0b8dfaaa154fa6b88176c229f2885d",
+            ),
+        ] {
+            assert_eq!(
+                hash_to_g1::<Sha256>(msg, DST_1).into_affine(),
+                G1Affine::new_unchecked(fq(x), fq(y))
+            );
+        }
+        for (msg, [x_0, x_1], [y_0, y_1]) in [
+            (
+                &b""[..],
+                [
+                    "0141ebfbdca40eb85b87142e130ab689c673cf60f1a3e98d69335266f30d9b8d4ac44c1038e9dcdd5393faf5c41fb78a",
+                    "05cb8437535e20ecffaef7752baddf98034139c38452458baeefab379ba13dff5bf5dd71b72418717047f5b0f37da03d",
+                ],
+                [
+                    "0503921d7f6a12805e72940b963c0cf3471c7b2a524950ca195d11062ee75ec076daf2d4bc358c4b190c0c98064fdd92",
+                    "12424ac32561493f3fe3c260708a12b7c620e7be00099a974e259ddc7d1f6395c3c811cdd19f1e8dbf3e9ecfdcbab8d6",
+                ],
+            ),
+            (
+                b"abc",
+                [
+                    "02c2d18e033b960562aae3cab37a27ce00d80ccd5ba4b7fe0e7a210245129dbec7780ccc7954725f4168aff2787776e6",
+                    "139cddbccdc5e91b9623efd38c49f81a6f83f175e80b06fc374de9eb4b41dfe4ca3a230ed250fbe3a2acf73a41177fd8",
+                ],
+                [
+                    "1787327b68159716a37440985269cf584bcb1e621d3a7202be6ea05c4cfe244aeb197642555a0645fb87bf7466b2ba48",
+                    "00aa65dae3c8d732d10ecd2c50f8a1baf3001578f71c694e03866e9f3d49ac1e1ce70dd94a733534f106d4cec0eddd16",
+                ],
+            ),
+        ] {
+            assert_eq!(
+                hash_to_g2::<Sha256>(msg, DST_2).into_affine(),
+                G2Affine::new_unchecked(Fq2::new(fq(x_0), fq(x_1)), Fq2::new(fq(y_0), fq(y_1)))
+            );
+        }
+    }
+
+    #[test]
+    fn suites_derive_different_points() {
+        let legacy = hash_to_curve(HashToCurveSuite::Legacy, b"msg", b"dst");
+        let rfc_9380 = hash_to_curve(HashToCurveSuite::Rfc9380, b"msg", b"dst");
+        assert_eq!(legacy, hash_with_domain_separation(b"msg", b"dst"));
+        assert_ne!(legacy, rfc_9380);
+        assert_eq!(
+            rfc_9380.0,
+            hash_to_g1::<Sha256>(b"msg", b"dstBLS12381G1_XMD:SHA-256_SSWU_RO_")
+        );
+        assert!(rfc_9380.is_valid_element());
+
+        // tags longer than 255 bytes are hashed first
+        let long_tag = [7u8; 300];
+        let hashed_tag = Sha256::new()
+            .chain_update(b"H2C-OVERSIZE-DST-")
+            .chain_update(long_tag)
+            .finalize();
+        assert_eq!(
+            expand_message_xmd::<Sha256>(b"msg", &long_tag, 64),
+            expand_message_xmd::<Sha256>(b"msg", &hashed_tag, 64)
+        );
+    }
+
+    #[test]
+    fn backends_derive_different_points() {
+        for suite in [HashToCurveSuite::Legacy, HashToCurveSuite::Rfc9380] {
+            let sha_256 = hash_to_curve_with(suite, HashBackendId::Sha256, b"msg", b"dst");
+            let sha3_256 = hash_to_curve_with(suite, HashBackendId::Sha3_256, b"msg", b"dst");
+            assert_eq!(sha_256, hash_to_curve(suite, b"msg", b"dst"));
+            assert_ne!(sha_256, sha3_256);
+            assert!(sha3_256.is_valid_element());
+        }
+        assert_eq!(
+            hash_to_curve_with(
+                HashToCurveSuite::Rfc9380,
+                HashBackendId::Sha3_256,
+                b"msg",
+                b"dst"
+            )
+            .0,
+            hash_to_g1::<Sha3_256>(b"msg", b"dstBLS12381G1_XMD:SHA3-256_SSWU_RO_")
+        );
+    }
+
+    #[test]
+    fn g1g2_bytes_kat() {
+        for (k, kat) in [(1, KAT_G1G2_1), (2, KAT_G1G2_2)] {
+            let g1g2 = generator_multiple(k);
+            let bytes = g1g2.to_bytes();
+            assert_eq!(hex::encode(bytes), kat);
+            assert_eq!(G1G2::from_bytes(&bytes), Ok(g1g2));
+        }
+    }
+
+    #[test]
+    fn g1g2_from_bytes_invalid() {
+        let bytes = hex::decode(KAT_G1G2_1).unwrap();
+
+        assert_eq!(
+            G1G2::from_bytes(&bytes[1..]),
+            Err(DecodeError::InvalidLength {
+                expected: G1G2::ENCODED_SIZE,
+                actual: G1G2::ENCODED_SIZE - 1
+            })
+        );
+
+        // uncompressed flag
+        let mut invalid = bytes.clone();
+        invalid[0] &= 0x7f;
+        assert_eq!(G1G2::from_bytes(&invalid), Err(DecodeError::InvalidPoint));
+
+        // (0, 2) is on the curve but not in the prime-order subgroup
+        let mut invalid = bytes.clone();
+        invalid[..48].copy_from_slice(&[0u8; 48]);
+        invalid[0] = 0x80;
+        assert_eq!(G1G2::from_bytes(&invalid), Err(DecodeError::InvalidPoint));
+
+        let mut identity = bytes.clone();
+        identity[..48].copy_from_slice(&[0u8; 48]);
+        identity[0] = 0xc0;
+        assert_eq!(G1G2::from_bytes(&identity), Err(DecodeError::Identity));
+        assert_eq!(
+            G1G2::from_bytes(&G1G2::default().to_bytes()),
+            Err(DecodeError::Identity)
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_g1g2() {
+        let g1g2 = G1G2::random(rand::thread_rng());
+        assert_eq!(serde_round_trip(&g1g2), g1g2);
+        assert_eq!(serde_round_trip(&G1G2::default()), G1G2::default());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_scalar() {
+        #[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+        struct Wrapper(#[serde(with = "serde_compressed")] Scalar);
+
+        let scalar = Wrapper(Scalar::rand(&mut rand::thread_rng()));
+        assert_eq!(serde_round_trip(&scalar), scalar);
+
+        // not a canonical field element
+        let json = serde_json::to_string(&[0xffu8; 32]).unwrap();
+        assert!(serde_json::from_str::<Wrapper>(&json).is_err());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_g1g2_tampered() {
+        let g1g2 = G1G2::random(rand::thread_rng());
+        let bytes = bincode::serialize(&g1g2).unwrap();
+        // skip the length prefix and modify the x coordinates of the G1 and the G2 element
+        for offset in [8 + 20, 8 + 48 + 20] {
+            let mut tampered = bytes.clone();
+            tampered[offset] ^= 0x01;
+            assert!(bincode::deserialize::<G1G2>(&tampered).is_err());
+        }
+
+        let mut truncated = bytes.clone();
+        truncated.truncate(bytes.len() - 1);
+        assert!(bincode::deserialize::<G1G2>(&truncated).is_err());
+    }
+
+    #[test]
+    fn precomputed_mul_is_bit_exact() {
+        let mut rng = rand::thread_rng();
+        let base = G1G2::random(&mut rng);
+        let table = Precomputed::new(&base);
+        assert_eq!(table.base(), &base);
+
+        let edge_cases = [0u64, 1, 15, 16, 17].map(Scalar::from).into_iter();
+        let scalars = edge_cases
+            .chain([-Scalar::from(1u64)])
+            .chain((0..32).map(|_| Scalar::rand(&mut rng)));
+        for scalar in scalars {
+            assert_eq!(table.mul(&scalar).to_bytes(), (&base * scalar).to_bytes());
+        }
+
+        let restored = arbitrary::round_trip(&table).unwrap();
+        assert_eq!(restored, table);
+        let scalar = Scalar::rand(&mut rng);
+        assert_eq!(restored.mul(&scalar), &base * scalar);
+    }
+
+    #[test]
+    fn equal_points_hash_and_compare_equal() {
+        use std::{
+            collections::{BTreeSet, HashSet, hash_map::DefaultHasher},
+            hash::Hasher,
+        };
+
+        let mut rng = rand::thread_rng();
+        let (p, q) = (G1G2::random(&mut rng), G1G2::random(&mut rng));
+        // the same point with other projective coordinates
+        let other = &(&p + &q) - &q;
+        assert_ne!((other.0.z, other.1.z), (p.0.z, p.1.z));
+        assert_eq!(other, p);
+        assert_eq!(other.cmp(&p), Ordering::Equal);
+
+        let hash = |point: &G1G2| {
+            let mut hasher = DefaultHasher::new();
+            point.hash(&mut hasher);
+            hasher.finish()
+        };
+        assert_eq!(hash(&other), hash(&p));
+        assert_eq!(HashSet::from([p.clone(), other.clone()]).len(), 1);
+        assert_eq!(p.cmp(&q), p.to_bytes().cmp(&q.to_bytes()));
+
+        // sets of points serialize in the order of their encodings, however they were built
+        let points: Vec<_> = (0..8).map(|_| G1G2::random(&mut rng)).collect();
+        let forward: BTreeSet<_> = points.iter().cloned().collect();
+        let backward: BTreeSet<_> = points.iter().rev().map(|point| point + &q - &q).collect();
+        let mut sorted: Vec<_> = points.iter().map(G1G2::to_bytes).collect();
+        sorted.sort();
+        let mut bytes = (points.len() as u64).to_le_bytes().to_vec();
+        bytes.extend(sorted.concat());
+        for set in [forward, backward] {
+            let mut encoded = Vec::new();
+            set.serialize_compressed(&mut encoded).unwrap();
+            assert_eq!(encoded, bytes);
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn g1g2_bytes_round_trip(point in arbitrary::g1g2()) {
+            prop_assert_eq!(G1G2::from_bytes(&point.to_bytes()), Ok(point.clone()));
+            prop_assert_eq!(arbitrary::round_trip(&point).unwrap(), point);
+        }
+
+        #[test]
+        fn scalar_encoding_round_trip(k in arbitrary::scalar()) {
+            prop_assert_eq!(arbitrary::round_trip(&k).unwrap(), k);
+        }
+    }
 }
 
 pub mod gs {
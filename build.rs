@@ -0,0 +1,32 @@
+use std::{env, path::PathBuf};
+
+/// With the `ffi` feature, generates the C header `spirit.h` of the ffi module in `OUT_DIR` and
+/// compiles the C round-trip test against it.
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed=ffi.rs");
+    println!("cargo:rerun-if-changed=cbindgen.toml");
+    println!("cargo:rerun-if-changed=tests/ffi/round_trip.c");
+
+    if env::var_os("CARGO_FEATURE_FFI").is_none() {
+        return;
+    }
+
+    let crate_dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap());
+    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+
+    let config = cbindgen::Config::from_file(crate_dir.join("cbindgen.toml"))
+        .expect("reading cbindgen.toml failed");
+    cbindgen::Builder::new()
+        .with_crate(&crate_dir)
+        .with_config(config)
+        .generate()
+        .expect("generating the C header failed")
+        .write_to_file(out_dir.join("spirit.h"));
+
+    cc::Build::new()
+        .file(crate_dir.join("tests/ffi/round_trip.c"))
+        .include(&out_dir)
+        .warnings_into_errors(true)
+        .compile("spirit_ffi_test");
+}
@@ -0,0 +1,129 @@
+//! Encodings of version 1 of the [envelope](crate::envelope) format. Every release has to decode
+//! them, so the blobs are never changed, only blobs of later versions are added.
+
+use std::{env, fs};
+
+use rand::{Rng, rngs::OsRng};
+
+use crate::{
+    DecodeError,
+    bls381_helpers::test::generator_multiple,
+    envelope::{ArtifactType, HEADER_SIZE},
+    exposure::{ExposurePolicy, RiskLevel},
+    pedersen::Commitment,
+    registry::{FileRegistry, RegistryFilter, RevocationList, TokenRegistry},
+    spirit::Token,
+    transcript::{Draw, Transcript},
+    tsw::{PublicKey, Signature},
+};
+
+const PUBLIC_KEY_V1: &str = "5350525301000197f1d3a73197d7942695638c4fa9ac0fc3688c4f9774b905a14e3a3f171bac586c55e83ff97a1aeffb3af00adb22c6bb93e02b6052719f607dacd3a088274f65596bd0d09920b61ab5da61bbdc7f5049334cf11213945d57e5ac7d055d042b7e024aa2b2f08f0a91260805272dc51051c6e47ad4fa403b02b4510b647ae3d1770bac0326a805bbefd48056c8c121bdb8";
+const SIGNATURE_V1: &str = "5350525301000289ece308f9d1f0131765212deca99697b112d61f9be9a5f1f3780a51335b3ff981747a0b2ca2179b96d2c0c9024e522489380275bbc8e5dcea7dc4dd7e0550ff2ac480905396eda55062650f8d251c96eb480673937cc6d9d6a44aaa56ca66dc122915c824a0857e2ee414a3dccb23ae691ae54329781315a0c75df1c04d6d7a50a030fc866f09d516020ef82324afae";
+const TOKEN_V1: &str = "53505253010003b0e7791fb972fe014159aa33a98622da3cdc98ff707965e536d8636b5fcc5ac7a91a8c46e59a00dca575af0f18fb13dc80fb837804dba8213329db46608b6c121d973363c1234a86dd183baff112709cf97096c5e9a1a770ee9d7dc641a894d60411a5de6730ffece671a9f21d65028cc0f1102378de124562cb1ff49db6f004fcd14d683024b0548eff3d1468df2688b928f3beb93519eecf0145da903b40a4c97dca00b21f12ac0df3be9116ef2ef27b2ae6bcd4c5bc2d54ef5a70627efcb78d0273f6bf31ed37c3b8d68083ec3d8e20b5f2cc170fa24b9b5be35b34ed013f9a921f1cad1644d4bdb14674247234c8049cd1dbb2d2c3581e54c088135fef36505a6823d61b859437bfc79b617030dc8b40e32bad1fa85b9c0f368af6d38d3c5a00000000000000";
+/// Log with the token of [TOKEN_V1] as its only entry.
+const REGISTRY_LOG_V1: &str = "53505253010004b0e7791fb972fe014159aa33a98622da3cdc98ff707965e536d8636b5fcc5ac7a91a8c46e59a00dca575af0f18fb13dc80fb837804dba8213329db46608b6c121d973363c1234a86dd183baff112709cf97096c5e9a1a770ee9d7dc641a894d60411a5de6730ffece671a9f21d65028cc0f1102378de124562cb1ff49db6f004fcd14d683024b0548eff3d1468df2688b928f3beb93519eecf0145da903b40a4c97dca00b21f12ac0df3be9116ef2ef27b2ae6bcd4c5bc2d54ef5a70627efcb78d0273f6bf31ed37c3b8d68083ec3d8e20b5f2cc170fa24b9b5be35b34ed013f9a921f1cad1644d4bdb14674247234c8049cd1dbb2d2c3581e54c088135fef36505a6823d61b859437bfc79b617030dc8b40e32bad1fa85b9c0f368af6d38d3c5a00000000000000";
+/// Filter of 128 bits and three hash functions with the token of [TOKEN_V1] inserted.
+const REGISTRY_FILTER_V1: &str = "535052530100050300000000000000400000000080000000000001";
+/// List revoking the token of [TOKEN_V1].
+const REVOCATION_LIST_V1: &str =
+    "53505253010006f2edec4dff6825e8378bfc75a62617226ef33b2062f434b18d98c24cba99d4b3";
+/// Transcript of the draws `U32(1)`, `U64(2)` and `Bytes([3; 5])`.
+const TRANSCRIPT_V1: &str = "53505253010007010100000002020000000000000003050000000303030303";
+/// Policy of the tiers `(1, Low)`, `(3, Medium)` and `(10, High)`.
+const EXPOSURE_POLICY_V1: &str =
+    "535052530100090100000000000000010300000000000000020a0000000000000003";
+
+fn blob(hex: &str) -> Vec<u8> {
+    hex::decode(hex).unwrap()
+}
+
+fn token() -> Token {
+    Token(
+        Commitment(generator_multiple(5)),
+        Signature(generator_multiple(7)),
+        90,
+    )
+}
+
+#[test]
+fn points_v1() {
+    assert_eq!(
+        PublicKey::from_bytes(&blob(PUBLIC_KEY_V1)),
+        Ok(PublicKey(generator_multiple(1)))
+    );
+    assert_eq!(
+        Signature::from_bytes(&blob(SIGNATURE_V1)),
+        Ok(Signature(generator_multiple(3)))
+    );
+}
+
+#[test]
+fn token_v1() {
+    assert_eq!(Token::from_bytes(&blob(TOKEN_V1)), Ok(token()));
+}
+
+#[test]
+fn registry_log_v1() {
+    let path = env::temp_dir().join(format!("spirit-compat-{:016x}.log", OsRng.r#gen::<u64>()));
+    fs::write(&path, blob(REGISTRY_LOG_V1)).unwrap();
+
+    let registry = FileRegistry::open(&path).expect("open failed");
+    assert_eq!(registry.len(), 1);
+    assert!(registry.contains(&token()));
+
+    drop(registry);
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn registry_filter_v1() {
+    let filter = RegistryFilter::from_bytes(&blob(REGISTRY_FILTER_V1)).expect("decoding failed");
+    assert!(filter.maybe_contains(&token()));
+}
+
+#[test]
+fn revocation_list_v1() {
+    let revoked = RevocationList::from_bytes(&blob(REVOCATION_LIST_V1)).expect("decoding failed");
+    assert_eq!(revoked.len(), 1);
+    assert!(revoked.is_revoked(&token()));
+}
+
+#[test]
+fn transcript_v1() {
+    let transcript = Transcript::from_bytes(&blob(TRANSCRIPT_V1)).expect("decoding failed");
+    assert_eq!(
+        transcript.draws(),
+        [Draw::U32(1), Draw::U64(2), Draw::Bytes(vec![3; 5])]
+    );
+}
+
+#[test]
+fn exposure_policy_v1() {
+    let policy = ExposurePolicy::builder()
+        .tier(1, RiskLevel::Low)
+        .tier(3, RiskLevel::Medium)
+        .tier(10, RiskLevel::High)
+        .build()
+        .unwrap();
+    assert_eq!(
+        ExposurePolicy::from_bytes(&blob(EXPOSURE_POLICY_V1)),
+        Ok(policy)
+    );
+}
+
+#[test]
+fn later_versions_and_other_types_are_rejected() {
+    let mut bytes = blob(TOKEN_V1);
+    bytes[4..HEADER_SIZE - 1].copy_from_slice(&2u16.to_le_bytes());
+    assert_eq!(
+        Token::from_bytes(&bytes),
+        Err(DecodeError::UnsupportedVersion(2))
+    );
+    assert_eq!(
+        Signature::from_bytes(&blob(TOKEN_V1)),
+        Err(DecodeError::UnexpectedArtifactType {
+            expected: ArtifactType::Signature,
+            actual: ArtifactType::Token as u8
+        })
+    );
+}
@@ -0,0 +1,431 @@
+//! Drift between the replicas of a registrar, e.g. behind a load balancer, where a replica that
+//! misses a registration or runs other parameters silently rejects some valid reports.
+//! [diff_params] compares the parameters of two replicas field by field and [diff_registries]
+//! their registries, and [reconcile] computes the registrations that make two registries
+//! converge. The diffs are serializable with the `serde` feature, for operator tooling.
+
+use thiserror::Error;
+
+use crate::{
+    atact::PublicParameters,
+    bls381_helpers::{HashBackendId, HashToCurveSuite},
+    registry::{RegistryError, TokenRegistry},
+    spirit::{Token, TokenId},
+    tsw::PublicKey,
+};
+
+/// Field in which two [PublicParameters] differ, with the values of both, see [diff_params].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ParamDiff {
+    /// The aggregate public keys of the issuers.
+    PublicKey {
+        a: PublicKey,
+        b: PublicKey,
+    },
+    /// The committee sizes `(n, t, tprime)`.
+    Sizes {
+        a: (usize, usize, usize),
+        b: (usize, usize, usize),
+    },
+    NumAttributes {
+        a: usize,
+        b: usize,
+    },
+    /// The domain-separation tags of the issuance proofs.
+    Dst {
+        a: Vec<u8>,
+        b: Vec<u8>,
+    },
+    HashToCurve {
+        a: HashToCurveSuite,
+        b: HashToCurveSuite,
+    },
+    HashBackend {
+        a: HashBackendId,
+        b: HashBackendId,
+    },
+    /// The committee positions at which the issuers' keys differ, including the positions
+    /// beyond the shorter list.
+    IssuerKeys {
+        positions: Vec<usize>,
+    },
+}
+
+/// The fields in which `a` and `b` differ, in the order of [ParamDiff], or none if users
+/// register with both alike. Like their [fingerprints](PublicParameters::fingerprint), the
+/// issuers' keys are only compared if both parameters include them.
+pub fn diff_params(a: &PublicParameters, b: &PublicParameters) -> Vec<ParamDiff> {
+    let mut diffs = Vec::new();
+    if a.pk != b.pk {
+        diffs.push(ParamDiff::PublicKey {
+            a: a.pk.clone(),
+            b: b.pk.clone(),
+        });
+    }
+    let (sizes_a, sizes_b) = ((a.n, a.t, a.tprime), (b.n, b.t, b.tprime));
+    if sizes_a != sizes_b {
+        diffs.push(ParamDiff::Sizes {
+            a: sizes_a,
+            b: sizes_b,
+        });
+    }
+    if a.num_attributes() != b.num_attributes() {
+        diffs.push(ParamDiff::NumAttributes {
+            a: a.num_attributes(),
+            b: b.num_attributes(),
+        });
+    }
+    if a.dst != b.dst {
+        diffs.push(ParamDiff::Dst {
+            a: a.dst.clone(),
+            b: b.dst.clone(),
+        });
+    }
+    if a.hash_to_curve != b.hash_to_curve {
+        diffs.push(ParamDiff::HashToCurve {
+            a: a.hash_to_curve,
+            b: b.hash_to_curve,
+        });
+    }
+    if a.hash_backend != b.hash_backend {
+        diffs.push(ParamDiff::HashBackend {
+            a: a.hash_backend,
+            b: b.hash_backend,
+        });
+    }
+    if !a.issuer_keys.is_empty() && !b.issuer_keys.is_empty() {
+        let len = a.issuer_keys.len().max(b.issuer_keys.len());
+        let positions: Vec<_> = (0..len)
+            .filter(|&j| a.issuer_keys.get(j) != b.issuer_keys.get(j))
+            .collect();
+        if !positions.is_empty() {
+            diffs.push(ParamDiff::IssuerKeys { positions });
+        }
+    }
+    diffs
+}
+
+/// Tokens registered in one of two registries but not in the other, by their ids in the order of
+/// the tokens' canonical encodings, see [diff_registries].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RegistryDiff {
+    pub only_in_a: Vec<TokenId>,
+    pub only_in_b: Vec<TokenId>,
+}
+
+impl RegistryDiff {
+    /// Whether both registries hold the same tokens.
+    pub fn is_empty(&self) -> bool {
+        self.only_in_a.is_empty() && self.only_in_b.is_empty()
+    }
+}
+
+/// The tokens registered in only one of `a` and `b`. The tokens of both are sorted by their
+/// canonical encodings and compared in a single pass, instead of a lookup of every token in the
+/// other registry.
+pub fn diff_registries(a: &impl TokenRegistry, b: &impl TokenRegistry) -> RegistryDiff {
+    let (only_in_a, only_in_b) = difference(a, b);
+    RegistryDiff {
+        only_in_a: only_in_a.iter().map(Token::id).collect(),
+        only_in_b: only_in_b.iter().map(Token::id).collect(),
+    }
+}
+
+/// Registrations that make a `primary` registry and its `replica` converge, see [reconcile].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Reconciliation {
+    /// Tokens of the primary that the replica misses.
+    pub to_replica: Vec<Token>,
+    /// Tokens of the replica that the primary misses, e.g. registrations that reached only the
+    /// replica.
+    pub to_primary: Vec<Token>,
+}
+
+#[derive(Error, Debug)]
+pub enum ReconcileError {
+    #[error("Registering a token of the replica at the primary failed: {0}")]
+    Primary(#[source] RegistryError),
+    #[error("Registering a token of the primary at the replica failed: {0}")]
+    Replica(#[source] RegistryError),
+}
+
+impl Reconciliation {
+    pub fn is_empty(&self) -> bool {
+        self.to_replica.is_empty() && self.to_primary.is_empty()
+    }
+
+    /// Registers the tokens of the primary that the replica misses at the replica. The tokens
+    /// only the replica holds are not registered at the primary, see [Self::apply_with].
+    pub fn apply(&self, replica: &mut impl TokenRegistry) -> Result<(), ReconcileError> {
+        for token in &self.to_replica {
+            replica
+                .insert(token.clone())
+                .map_err(ReconcileError::Replica)?;
+        }
+        Ok(())
+    }
+
+    /// [Self::apply], and registers at the primary the tokens only the replica holds for which
+    /// `verify` holds, e.g. that the registrar recorded when their registrations finished.
+    /// Returns the tokens of the replica that `verify` rejected. If it accepts all of them, both
+    /// registries hold the union of their tokens and verify the same reports alike.
+    pub fn apply_with(
+        &self,
+        primary: &mut impl TokenRegistry,
+        replica: &mut impl TokenRegistry,
+        mut verify: impl FnMut(&Token) -> bool,
+    ) -> Result<Vec<TokenId>, ReconcileError> {
+        let mut rejected = Vec::new();
+        for token in &self.to_primary {
+            if !verify(token) {
+                rejected.push(token.id());
+                continue;
+            }
+            primary
+                .insert(token.clone())
+                .map_err(ReconcileError::Primary)?;
+        }
+        self.apply(replica)?;
+        Ok(rejected)
+    }
+}
+
+/// The registrations each of `primary` and `replica` misses. Registries only grow, so they
+/// converge to the union of their tokens. The tokens only the replica holds are registrations
+/// that did not reach the primary, or were forged at the replica, so they are only registered
+/// at the primary by [Reconciliation::apply_with] once they are checked.
+pub fn reconcile(primary: &impl TokenRegistry, replica: &impl TokenRegistry) -> Reconciliation {
+    let (to_replica, to_primary) = difference(primary, replica);
+    Reconciliation {
+        to_replica,
+        to_primary,
+    }
+}
+
+/// The tokens only in `a` and only in `b`, sorted.
+fn difference(a: &impl TokenRegistry, b: &impl TokenRegistry) -> (Vec<Token>, Vec<Token>) {
+    let [mut a, mut b] = [a.tokens().collect::<Vec<_>>(), b.tokens().collect()];
+    a.sort_unstable();
+    b.sort_unstable();
+
+    let (mut only_in_a, mut only_in_b) = (Vec::new(), Vec::new());
+    let (mut a, mut b) = (a.into_iter().peekable(), b.into_iter().peekable());
+    loop {
+        match (a.peek(), b.peek()) {
+            (Some(x), Some(y)) if x < y => only_in_a.extend(a.next()),
+            (Some(x), Some(y)) if x > y => only_in_b.extend(b.next()),
+            (Some(_), Some(_)) => {
+                a.next();
+                b.next();
+            }
+            (Some(_), None) => only_in_a.extend(a.next()),
+            (None, Some(_)) => only_in_b.extend(b.next()),
+            (None, None) => break,
+        }
+    }
+    (only_in_a, only_in_b)
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashSet;
+
+    use rand::rngs::OsRng;
+
+    use super::*;
+    use crate::{
+        bls381_helpers::G1G2,
+        pedersen::Commitment,
+        registry::RevocationList,
+        spirit::{
+            ChallengeSet, Interval, SetupOutput, UserSecret, VerifyRejection,
+            spirit_diagnosis_osrng, spirit_register_osrng, spirit_setup_osrng, spirit_verify,
+        },
+        tsw::Signature,
+    };
+
+    const NOW: u64 = 10;
+    const EXPIRY: u64 = 100;
+
+    #[test]
+    fn parameters_of_replicas_are_compared_field_by_field() {
+        let setup = || {
+            spirit_setup_osrng(3, 4, 4, b"test")
+                .expect("setup failed")
+                .pp
+        };
+        let (pp, mut other) = (setup(), setup());
+        assert!(diff_params(&pp, &pp).is_empty());
+
+        other.dst = b"other".to_vec();
+        other.hash_backend = HashBackendId::Sha3_256;
+        let diffs = diff_params(&pp, &other);
+        assert_eq!(
+            diffs,
+            [
+                ParamDiff::PublicKey {
+                    a: pp.pk.clone(),
+                    b: other.pk.clone()
+                },
+                ParamDiff::Dst {
+                    a: pp.dst.clone(),
+                    b: b"other".to_vec()
+                },
+                ParamDiff::HashBackend {
+                    a: pp.hash_backend,
+                    b: HashBackendId::Sha3_256
+                },
+                ParamDiff::IssuerKeys {
+                    positions: vec![0, 1, 2, 3]
+                },
+            ]
+        );
+
+        // parameters rebuilt without the issuers' keys, and of another committee
+        let mut rebuilt = setup();
+        rebuilt.issuer_keys.clear();
+        assert!(
+            !diff_params(&pp, &rebuilt)
+                .iter()
+                .any(|diff| matches!(diff, ParamDiff::IssuerKeys { .. }))
+        );
+        let larger = spirit_setup_osrng(3, 5, 5, b"test")
+            .expect("setup failed")
+            .pp;
+        assert!(diff_params(&pp, &larger).contains(&ParamDiff::IssuerKeys {
+            positions: vec![0, 1, 2, 3, 4]
+        }));
+
+        #[cfg(feature = "serde")]
+        {
+            use crate::bls381_helpers::serde_round_trip;
+
+            assert_eq!(serde_round_trip(&diffs), diffs);
+        }
+    }
+
+    #[test]
+    fn reconciled_replicas_verify_alike() {
+        let SetupOutput {
+            pp,
+            issuers,
+            domain,
+            ..
+        } = spirit_setup_osrng(3, 4, 4, b"test").expect("setup failed");
+        let (mut primary, mut replica) = (HashSet::new(), HashSet::new());
+        // two users registered at both, one only at the primary and one only at the replica
+        let users: Vec<_> = (0..4)
+            .map(|i| {
+                let user = UserSecret::random(OsRng);
+                let registry = if i == 3 { &mut replica } else { &mut primary };
+                let ppu = spirit_register_osrng(&domain, &user, &issuers, &pp, EXPIRY, registry)
+                    .expect("registration failed");
+                if i < 2 {
+                    replica.insert(ppu.0.clone());
+                }
+                (user, ppu)
+            })
+            .collect();
+        let id = |i: usize| users[i].1.0.id();
+
+        let diff = diff_registries(&primary, &replica);
+        assert_eq!(
+            diff,
+            RegistryDiff {
+                only_in_a: vec![id(2)],
+                only_in_b: vec![id(3)],
+            }
+        );
+        assert!(diff_registries(&primary, &primary).is_empty());
+
+        let mut challenges = ChallengeSet::new(0);
+        let cp = HashSet::from([(0, Interval(0))]);
+        let reports: Vec<_> = users
+            .iter()
+            .map(|(user, ppu)| {
+                let challenge = challenges.issue(NOW, &mut OsRng);
+                spirit_diagnosis_osrng(&domain, ppu, user.keys(), &cp, NOW, &challenge, &[])
+                    .expect("diagnosis failed")
+            })
+            .collect();
+        let verify = |registry: &HashSet<Token>| -> Vec<_> {
+            reports
+                .iter()
+                .map(|report| {
+                    spirit_verify(
+                        &domain,
+                        report,
+                        registry,
+                        &RevocationList::new(),
+                        NOW,
+                        &mut HashSet::new(),
+                        &challenges,
+                        &mut HashSet::new(),
+                    )
+                    .map(|_| ())
+                })
+                .collect()
+        };
+        let unregistered = |i: usize| Err(VerifyRejection::Unregistered { token: id(i) });
+        assert_eq!(verify(&primary), [Ok(()), Ok(()), Ok(()), unregistered(3)]);
+        assert_eq!(verify(&replica), [Ok(()), Ok(()), unregistered(2), Ok(())]);
+
+        let reconciliation = reconcile(&primary, &replica);
+        assert_eq!(reconciliation.to_replica, [users[2].1.0.clone()]);
+        assert_eq!(reconciliation.to_primary, [users[3].1.0.clone()]);
+
+        // by default only the replica catches up
+        let (mut primary_copy, mut replica_copy) = (primary.clone(), replica.clone());
+        reconciliation
+            .apply(&mut replica_copy)
+            .expect("reconciliation failed");
+        assert_eq!(primary_copy, primary);
+        assert_eq!(
+            diff_registries(&primary_copy, &replica_copy),
+            RegistryDiff {
+                only_in_a: vec![],
+                only_in_b: vec![id(3)],
+            }
+        );
+
+        // a token forged at the replica does not reach the primary, which only accepts the
+        // registrations it has a record of
+        let issued: HashSet<_> = users.iter().map(|(_, ppu)| ppu.0.id()).collect();
+        let forged = Token(
+            Commitment(G1G2::random(&mut OsRng)),
+            Signature(G1G2::random(&mut OsRng)),
+            EXPIRY,
+        );
+        replica_copy.insert(forged.clone());
+        let forging = reconcile(&primary_copy, &replica_copy);
+        let rejected = forging
+            .apply_with(&mut primary_copy, &mut replica_copy, |token| {
+                issued.contains(&token.id())
+            })
+            .expect("reconciliation failed");
+        assert_eq!(rejected, [forged.id()]);
+        assert!(primary_copy.contains(&users[3].1.0) && !primary_copy.contains(&forged));
+
+        let rejected = reconciliation
+            .apply_with(&mut primary, &mut replica, |token| {
+                issued.contains(&token.id())
+            })
+            .expect("reconciliation failed");
+        assert!(rejected.is_empty());
+        assert!(diff_registries(&primary, &replica).is_empty());
+        assert!(reconcile(&primary, &replica).is_empty());
+        assert_eq!(verify(&primary), verify(&replica));
+        assert_eq!(verify(&primary), vec![Ok(()); 4]);
+
+        #[cfg(feature = "serde")]
+        {
+            use crate::bls381_helpers::serde_round_trip;
+
+            assert_eq!(serde_round_trip(&diff), diff);
+            assert_eq!(serde_round_trip(&reconciliation), reconciliation);
+        }
+    }
+}
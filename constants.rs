@@ -0,0 +1,187 @@
+//! Domain-separation tags, generator seeds and protocol labels of the crate, collected so that a
+//! review or another implementation finds every one of them in one place. All of them are ASCII.
+//!
+//! The fixed generators are hashed to the curve from a seed under a tag, with SHA-256 and the
+//! [Legacy](crate::HashToCurveSuite::Legacy) suite of every deployment, so anyone can recompute
+//! them and no one knows their discrete logarithms. The generators of a deployment are hashed
+//! from a seed under one of the tags of its [DomainConfig](crate::spirit::DomainConfig) with its
+//! hash-to-curve suite. Digests and signed messages are prefixed with their tag, and the
+//! transcripts of the Fiat-Shamir proofs start with their protocol label.
+//!
+//! Changing any of the values changes the generators, digests or challenges derived from it, so
+//! registrations, proofs and persisted artifacts of one value do not verify under another. Such
+//! a change raises the [wire version](crate::wire::VERSION). The suite IDs of RFC 9380, e.g.
+//! `BLS12381G1_XMD:SHA-256_SSWU_RO_`, are fixed by the RFC and built where they are used.
+
+// -- fixed generators --
+
+/// Tag of the Pedersen generators `g` and `u` of [pedersen](crate::pedersen).
+pub const PEDERSEN_DST: &[u8] = b"Pedersen-PP";
+/// Seed of the Pedersen generator `g`, hashed under [PEDERSEN_DST].
+pub const PEDERSEN_G_SEED: &[u8] = b"g";
+/// Seed of the Pedersen generator `u`, hashed under [PEDERSEN_DST].
+pub const PEDERSEN_U_SEED: &[u8] = b"u";
+/// Tag of the generators `h_i` of multi-message commitments, each hashed from its index as
+/// little-endian `u64`. The first is also the base of the users' public keys.
+pub const MULTI_PEDERSEN_DST: &[u8] = b"Multi-Pedersen-PP";
+/// Tag of the hashed indices of the threshold structure-preserving signatures of
+/// [tsw](crate::tsw), each hashed from its index as little-endian `u64`.
+pub const HASHED_INDEX_DST: &[u8] = b"hash-usize";
+/// Tag of the base of the tags of [registration receipts](crate::receipt).
+pub const RECEIPT_DST: &[u8] = b"Registration-receipt";
+/// Seed of the base of the receipt tags, hashed under [RECEIPT_DST].
+pub const RECEIPT_BASE_SEED: &[u8] = b"tag";
+/// Seed of the bases of the expiry epochs in the receipt tags, hashed under [RECEIPT_DST]
+/// followed by the epoch as little-endian `u64`.
+pub const RECEIPT_EXPIRY_SEED: &[u8] = b"expiry";
+/// Seed of the bases of the revocation handles of receipt presentations, hashed under
+/// [RECEIPT_DST] followed by the presentation's nonce.
+pub const RECEIPT_HANDLE_SEED: &[u8] = b"handle";
+
+// -- generators of a deployment --
+
+/// Default `app_id` of a [DomainConfig](crate::spirit::DomainConfig).
+pub const DEFAULT_APP_ID: &[u8] = b"SPiRiT";
+/// Default tag of the PRF that derives ElIDs and nullifiers.
+pub const DEFAULT_PRF_DST: &[u8] = b"PRF-domain";
+/// Default tag of the generators of the PRF proofs and of the show tags.
+pub const DEFAULT_PROOF_DST: &[u8] = b"PRF-proof";
+/// Default tag of the generators of registration: the expiry and identity bases and the
+/// diagnosis authorizations.
+pub const DEFAULT_REGISTRATION_DST: &[u8] = b"Registration";
+/// Seed of the bases of token expiries, hashed with the expiry epoch under the registration
+/// tag.
+pub const EXPIRY_BASE_SEED: &[u8] = b"v";
+/// Seed of the base `H_id` of identity tags, hashed under the registration tag.
+pub const IDENTITY_BASE_SEED: &[u8] = b"identity";
+/// Seed of the generator of the PRF's public keys, hashed under the proof tag.
+pub const PRF_GENERATOR_SEED: &[u8] = b"g";
+/// Prefix of the seed of a show base and of the label of its proof, followed by the index of
+/// the show.
+pub const SHOW_SEED: &[u8] = b"show";
+/// Prefix of the seed of the message of a diagnosis authorization, hashed under the
+/// registration tag.
+pub const AUTHORIZATION_SEED: &[u8] = b"authorization";
+
+// -- PRF purposes and tags --
+
+/// Tag of the PRF inputs of broadcast ElIDs.
+pub const PRF_BROADCAST_TAG: &[u8] = b"broadcast";
+/// Tag of the PRF inputs of nullifiers, and label of their proofs.
+pub const PRF_NULLIFIER_TAG: &[u8] = b"nullifier";
+/// Tag of the PRF inputs of venue check-ins.
+pub const PRF_CHECKIN_TAG: &[u8] = b"checkin";
+/// Tag of the PRF inputs of device keys.
+pub const PRF_DEVICE_TAG: &[u8] = b"device";
+
+// -- digests --
+
+pub const TOKEN_ID_DST: &[u8] = b"Token-id";
+pub const EPOCH_KEY_DST: &[u8] = b"UserKeys-epoch";
+pub const ENCOUNTER_SECRET_DST: &[u8] = b"encounter-secret";
+pub const COMPACT_EL_ID_DST: &[u8] = b"compact-el-id";
+/// Tag of the leaves of broadcast batches, after the leaf prefix of the Merkle trees.
+pub const BROADCAST_LEAF_DST: &[u8] = b"broadcast";
+pub const BROADCAST_PAYLOAD_DST: &[u8] = b"broadcast-payload";
+pub const CONFIRMED_SET_DST: &[u8] = b"Confirmed-set";
+pub const ENCOUNTER_TABLE_DST: &[u8] = b"Encounter-table";
+pub const REGISTRY_FILTER_DST: &[u8] = b"Registry-filter";
+pub const ISSUANCE_REQUEST_DST: &[u8] = b"Issuer-issuance-request";
+pub const PARAMETERS_FINGERPRINT_DST: &[u8] = b"tACT-parameters";
+/// Tag of the hash of the receipt key and a token's id into the exponent of its receipt tag.
+pub const RECEIPT_EXPONENT_DST: &[u8] = b"Registration-receipt-exponent-v2";
+/// Tag of the hash of the registrar's signing key into its receipt key.
+pub const RECEIPT_KEY_DST: &[u8] = b"Registration-receipt-key";
+/// Tag of the point hashed from a beacon and an epoch whose digest seeds the committee
+/// selection.
+pub const COMMITTEE_DST: &[u8] = b"tACT-committee";
+/// Tag of the hash of the Pedersen generators that the challenges of the Pedersen proofs start
+/// from.
+pub const PEDERSEN_PROOF_HASH_DST: &[u8] = b"hash-pedersen-proof";
+/// Prefix of tags longer than 255 bytes before they are hashed, fixed by RFC 9380.
+pub const OVERSIZE_DST_PREFIX: &[u8] = b"H2C-OVERSIZE-DST-";
+
+// -- signed messages --
+
+pub const REGISTRY_ROOT_DST: &[u8] = b"Registrar-Merkle-root";
+pub const REGISTRY_SNAPSHOT_DST: &[u8] = b"Registrar-snapshot";
+/// Version 2 signs the [KeyId](crate::registry::KeyId) of the anchor, so anchors of version 1
+/// do not verify.
+pub const BROADCAST_ANCHOR_DST: &[u8] = b"Registrar-broadcast-anchor-v2";
+pub const REGISTRY_DELTA_DST: &[u8] = b"Registrar-delta";
+pub const CONFIRMED_DELTA_DST: &[u8] = b"Confirmed-ElID-delta";
+pub const ISSUANCE_LOG_DST: &[u8] = b"Issuer-issuance-log";
+/// Tag of the derivation of an issuer's log key from its key share.
+pub const ISSUANCE_LOG_KEY_DST: &[u8] = b"Issuer-issuance-log-key";
+/// Tag of the pings the issuers answer, signed followed by its length. Version 2 signs the
+/// challenge nonce of the ping.
+pub const ISSUER_PING_DST: &[u8] = b"tACT-issuer-ping-v2";
+/// Version 2 signs the [KeyId](crate::registry::KeyId) of the record, like
+/// [BROADCAST_ANCHOR_DST].
+pub const KEY_ROTATION_DST: &[u8] = b"Registrar-key-rotation-v2";
+pub const REPORT_RETENTION_DST: &[u8] = b"Registrar-retained-report";
+/// Tag of the point that a proof of possession of a signing key share signs.
+pub const PROOF_OF_POSSESSION_DST: &[u8] = b"tsw-PoP";
+
+// -- transcript protocols --
+
+pub const TOKEN_PROOF_PROTOCOL: &[u8] = b"tACT-TokenProof";
+pub const PARTIAL_DECRYPTION_PROTOCOL: &[u8] = b"ElGamal-Partial-Decryption";
+/// Version 2 proves that the ElIDs of the confirmed set are in the committed table, instead of
+/// that the entries of the table are in the confirmed set.
+pub const EXPOSURE_PROTOCOL: &[u8] = b"SPiRiT-Exposure-v2";
+pub const KEY_CLAIM_PROTOCOL: &[u8] = b"SPiRiT-KeyClaim";
+pub const TOKEN_CLAIM_PROTOCOL: &[u8] = b"SPiRiT-TokenClaim";
+pub const KEY_BINDING_PROTOCOL: &[u8] = b"SPiRiT-KeyBinding";
+pub const DIAGNOSIS_BUNDLE_PROTOCOL: &[u8] = b"SPiRiT-DiagnosisBundle";
+pub const PROOF_2PK_PROTOCOL: &[u8] = b"Pedersen-Proof2PK";
+pub const OPENING_PROTOCOL: &[u8] = b"Pedersen-Opening";
+pub const MESSAGE_OPENING_PROTOCOL: &[u8] = b"Pedersen-Message-Opening";
+pub const DISCLOSURE_PROTOCOL: &[u8] = b"Pedersen-Disclosure";
+pub const RECEIPT_PRESENTATION_PROTOCOL: &[u8] = b"SPiRiT-ReceiptPresentation-v2";
+
+#[cfg(test)]
+mod test {
+    use rand::rngs::OsRng;
+
+    use super::*;
+    use crate::{
+        G1G2,
+        bls381_helpers::hash_with_domain_separation,
+        pedersen::get_parameters,
+        spirit::{SetupOutput, spirit_setup_with_attributes},
+    };
+
+    fn indexed(index: usize, dst: &[u8]) -> G1G2 {
+        hash_with_domain_separation(&(index as u64).to_le_bytes(), dst)
+    }
+
+    #[test]
+    fn generators_are_hashed_from_their_seeds() {
+        let pedersen = get_parameters();
+        assert_eq!(
+            pedersen.g,
+            hash_with_domain_separation(PEDERSEN_G_SEED, PEDERSEN_DST)
+        );
+        assert_eq!(
+            pedersen.u,
+            hash_with_domain_separation(PEDERSEN_U_SEED, PEDERSEN_DST)
+        );
+
+        // the generators in the parameters of a deployment, compared by their encodings
+        let SetupOutput { pp, .. } =
+            spirit_setup_with_attributes(3, 4, 4, 3, b"test", &mut OsRng).expect("setup failed");
+        for index in 0..2 {
+            assert_eq!(
+                pp.attribute_bases[index].to_bytes(),
+                indexed(index, MULTI_PEDERSEN_DST).to_bytes()
+            );
+        }
+        for index in 0..4 {
+            assert_eq!(
+                pp.tsw_pp[index].to_bytes(),
+                indexed(index, HASHED_INDEX_DST).to_bytes()
+            );
+        }
+    }
+}
@@ -0,0 +1,753 @@
+//! Distributed key generation for the tACT issuers.
+//!
+//! Instead of a trusted dealer as in [atact::setup](crate::atact::setup), every issuer deals a
+//! Feldman-verifiable sharing of a random secret and the issuer key is the sum of the secrets of
+//! all qualified dealers. The protocol runs in two rounds:
+//!
+//! 1. [DkgRound1::new] returns the dealer's [DkgCommitment], to be broadcast, and one
+//!    [DkgShare] per participant, to be sent over a private channel.
+//! 2. [DkgRound1::receive] checks the shares addressed to the participant against the
+//!    commitments of their dealers and returns a [DkgComplaint] for every dealer whose share or
+//!    commitment is missing or inconsistent, to be broadcast.
+//!
+//! [DkgRound2::finalize] then excludes every dealer with a complaint against it and derives the
+//! same [PublicParameters] as all other participants and the participant's [Issuer] state.
+//!
+//! [RefreshRound1] and [RefreshRound2] run the same protocol with sharings of zero to refresh
+//! the shares of an existing committee, and [refresh_shares] runs it in-process.
+//!
+//! The channels are assumed to be authenticated, i.e. the `sender` of a message cannot be
+//! forged. A complaint is not answered by the accused dealer, so a dishonest participant can
+//! exclude honest dealers and, as with every Feldman-based DKG, bias the issuer key by doing so.
+//! As long as one qualified dealer is honest, the key stays unknown to any coalition of fewer
+//! than `t` participants.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use ark_ff::{UniformRand, Zero};
+use rand::thread_rng;
+use thiserror::Error;
+
+use crate::{
+    atact::{AtACTError, Issuer, PublicParameters, check_parameters},
+    bls381_helpers::{G1G2, OnceGlobal, Scalar},
+    pedersen::get_parameters,
+    tsw::{ProofOfPossession, PublicKey, SecretKey},
+};
+
+/// Parameters all participants agree on before running the DKG. They have the same meaning as
+/// the arguments of [atact::setup](crate::atact::setup).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DkgParameters {
+    pub num_issuers: usize,
+    pub n: usize,
+    pub t: usize,
+    pub tprime: usize,
+    pub l: usize,
+}
+
+/// Feldman commitments `g^{a_j}` to the coefficients of a dealer's polynomial and a proof of
+/// possession of `a_0`, broadcast in the first round.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DkgCommitment {
+    pub sender: usize,
+    pub(crate) coefficients: Vec<G1G2>,
+    pub(crate) pop: ProofOfPossession,
+}
+
+/// Evaluation of the dealer's polynomial at the recipient's index, sent privately in the first
+/// round.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DkgShare {
+    pub sender: usize,
+    pub recipient: usize,
+    #[cfg_attr(
+        feature = "serde",
+        serde(with = "crate::bls381_helpers::serde_compressed")
+    )]
+    pub(crate) share: Scalar,
+}
+
+/// Accusation of a dealer by a participant that did not receive a consistent share from it,
+/// broadcast in the second round.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DkgComplaint {
+    pub accuser: usize,
+    pub accused: usize,
+}
+
+/// State of a participant after dealing its sharing.
+pub struct DkgRound1 {
+    params: DkgParameters,
+    dealer: Dealer,
+}
+
+/// State of a participant after checking its shares.
+pub struct DkgRound2 {
+    params: DkgParameters,
+    received: Received,
+}
+
+/// Result of a successful DKG for one participant.
+pub struct DkgOutput {
+    pub pp: PublicParameters,
+    pub issuer: Issuer,
+    /// Indices of the disqualified dealers, in ascending order.
+    pub excluded: Vec<usize>,
+}
+
+/// State of an issuer after dealing its sharing of zero in a share refresh.
+pub struct RefreshRound1 {
+    dealer: Dealer,
+}
+
+/// State of an issuer after checking its refresh shares.
+pub struct RefreshRound2 {
+    received: Received,
+}
+
+// Σ_j a_j x^j, evaluated with Horner's rule
+fn evaluate(coefficients: &[Scalar], x: Scalar) -> Scalar {
+    coefficients
+        .iter()
+        .rev()
+        .fold(Scalar::zero(), |acc, a| acc * x + a)
+}
+
+// Π_j (g^{a_j})^{x^j} = g^{f(x)}
+fn feldman_evaluate(coefficients: &[G1G2], x: Scalar) -> G1G2 {
+    coefficients
+        .iter()
+        .rev()
+        .fold(G1G2::default(), |acc, c| acc * x + c)
+}
+
+// g^{f(i)} for the sum f of the dealers' polynomials and the participants i = 1..=num_issuers
+fn public_shares<'a>(
+    commitments: impl Iterator<Item = &'a DkgCommitment> + Clone,
+    num_issuers: usize,
+) -> Vec<PublicKey> {
+    (1..=num_issuers)
+        .map(|index| {
+            let x = Scalar::from(index as u64);
+            PublicKey(
+                commitments
+                    .clone()
+                    .map(|commitment| feldman_evaluate(&commitment.coefficients, x))
+                    .sum(),
+            )
+        })
+        .collect()
+}
+
+// a participant that dealt a sharing of `secret` with a polynomial of degree t - 1
+struct Dealer {
+    num_issuers: usize,
+    t: usize,
+    index: usize,
+    own_share: Scalar,
+    own_commitment: DkgCommitment,
+}
+
+// the consistent sharings received by a participant
+struct Received {
+    num_issuers: usize,
+    commitments: BTreeMap<usize, DkgCommitment>,
+    shares: BTreeMap<usize, Scalar>,
+    accused: BTreeSet<usize>,
+}
+
+impl Dealer {
+    fn new(
+        num_issuers: usize,
+        t: usize,
+        index: usize,
+        secret: Scalar,
+    ) -> Result<(Self, DkgCommitment, Vec<DkgShare>), DkgError> {
+        if index == 0 || index > num_issuers {
+            return Err(DkgError::InvalidIndex(index));
+        }
+
+        let mut rng = thread_rng();
+        let g = &get_parameters().g;
+        let polynomial: Vec<_> = std::iter::once(secret)
+            .chain((1..t).map(|_| Scalar::rand(&mut rng)))
+            .collect();
+        let commitment = DkgCommitment {
+            sender: index,
+            coefficients: polynomial.iter().map(|a| g * *a).collect(),
+            pop: SecretKey { sk: secret }.pop(),
+        };
+
+        let mut own_share = Scalar::zero();
+        let mut shares = Vec::with_capacity(num_issuers - 1);
+        for recipient in 1..=num_issuers {
+            let share = evaluate(&polynomial, Scalar::from(recipient as u64));
+            if recipient == index {
+                own_share = share;
+            } else {
+                shares.push(DkgShare {
+                    sender: index,
+                    recipient,
+                    share,
+                });
+            }
+        }
+
+        Ok((
+            Self {
+                num_issuers,
+                t,
+                index,
+                own_share,
+                own_commitment: commitment.clone(),
+            },
+            commitment,
+            shares,
+        ))
+    }
+
+    // complains about every dealer whose commitment is missing, malformed or rejected by
+    // `is_valid`, or whose share is missing or does not match the commitment
+    fn receive(
+        self,
+        commitments: &[DkgCommitment],
+        shares: &[DkgShare],
+        is_valid: impl Fn(&DkgCommitment) -> bool,
+    ) -> (Received, Vec<DkgComplaint>) {
+        let Self {
+            num_issuers,
+            t,
+            index,
+            own_share,
+            own_commitment,
+        } = self;
+        let in_range = |sender: usize| sender != 0 && sender <= num_issuers && sender != index;
+
+        let mut received_commitments: BTreeMap<_, _> = commitments
+            .iter()
+            .filter(|commitment| in_range(commitment.sender))
+            .map(|commitment| (commitment.sender, commitment.clone()))
+            .collect();
+        received_commitments.insert(index, own_commitment);
+        let mut received_shares: BTreeMap<_, _> = shares
+            .iter()
+            .filter(|share| in_range(share.sender) && share.recipient == index)
+            .map(|share| (share.sender, share.share))
+            .collect();
+        received_shares.insert(index, own_share);
+
+        let g = &get_parameters().g;
+        let x = Scalar::from(index as u64);
+        let complaints: Vec<_> = (1..=num_issuers)
+            .filter(|dealer| {
+                match (
+                    received_commitments.get(dealer),
+                    received_shares.get(dealer),
+                ) {
+                    (Some(commitment), Some(share)) => {
+                        commitment.coefficients.len() != t
+                            || !is_valid(commitment)
+                            || feldman_evaluate(&commitment.coefficients, x) != g * *share
+                    }
+                    _ => true,
+                }
+            })
+            .map(|accused| DkgComplaint {
+                accuser: index,
+                accused,
+            })
+            .collect();
+
+        (
+            Received {
+                num_issuers,
+                commitments: received_commitments,
+                shares: received_shares,
+                accused: complaints
+                    .iter()
+                    .map(|complaint| complaint.accused)
+                    .collect(),
+            },
+            complaints,
+        )
+    }
+}
+
+impl Received {
+    // the dealers accused neither in `complaints` nor by this participant, and the excluded ones
+    fn qualified(&self, complaints: &[DkgComplaint]) -> Result<(Vec<usize>, Vec<usize>), DkgError> {
+        let num_issuers = self.num_issuers;
+        let mut excluded: BTreeSet<_> = complaints
+            .iter()
+            .filter(|complaint| complaint.accuser != 0 && complaint.accuser <= num_issuers)
+            .map(|complaint| complaint.accused)
+            .filter(|accused| *accused != 0 && *accused <= num_issuers)
+            .collect();
+        excluded.extend(&self.accused);
+        let qualified: Vec<_> = (1..=num_issuers)
+            .filter(|dealer| !excluded.contains(dealer))
+            .collect();
+        if qualified.is_empty() {
+            return Err(DkgError::NoQualifiedDealers);
+        }
+        Ok((qualified, excluded.into_iter().collect()))
+    }
+
+    fn issuer_keys(&self, qualified: &[usize]) -> Vec<PublicKey> {
+        public_shares(
+            qualified.iter().map(|dealer| &self.commitments[dealer]),
+            self.num_issuers,
+        )
+    }
+
+    // this participant did not accuse a qualified dealer, so its commitment and share are
+    // present and consistent
+    fn combine(&self, qualified: &[usize]) -> (G1G2, Scalar) {
+        (
+            qualified
+                .iter()
+                .map(|dealer| &self.commitments[dealer].coefficients[0])
+                .sum(),
+            qualified.iter().map(|dealer| self.shares[dealer]).sum(),
+        )
+    }
+}
+
+impl DkgRound1 {
+    /// Starts the DKG for the participant with the 1-based `index`, which is also the index of
+    /// its share of the issuer key.
+    pub fn new(
+        params: DkgParameters,
+        index: usize,
+    ) -> Result<(Self, DkgCommitment, Vec<DkgShare>), DkgError> {
+        check_parameters(params.num_issuers, params.n, params.t, params.tprime)?;
+        let secret = Scalar::rand(&mut thread_rng());
+        let (dealer, commitment, shares) =
+            Dealer::new(params.num_issuers, params.t, index, secret)?;
+        Ok((Self { params, dealer }, commitment, shares))
+    }
+
+    /// Checks the shares addressed to this participant against the dealers' commitments.
+    /// Messages from unknown senders and shares for other recipients are ignored.
+    pub fn receive(
+        self,
+        commitments: &[DkgCommitment],
+        shares: &[DkgShare],
+    ) -> (DkgRound2, Vec<DkgComplaint>) {
+        // the proof of possession keeps a dealer from choosing its contribution to the key
+        // from the contributions of the others
+        let (received, complaints) = self.dealer.receive(commitments, shares, |commitment| {
+            PublicKey(commitment.coefficients[0].clone()).verify_pop(&commitment.pop)
+        });
+        (
+            DkgRound2 {
+                params: self.params,
+                received,
+            },
+            complaints,
+        )
+    }
+}
+
+impl DkgRound2 {
+    /// Disqualifies every dealer accused in `complaints` or by the participant itself, and
+    /// combines the sharings of the remaining dealers. All participants that receive the same
+    /// complaints derive the same public parameters.
+    pub fn finalize(self, complaints: &[DkgComplaint]) -> Result<DkgOutput, DkgError> {
+        let (qualified, excluded) = self.received.qualified(complaints)?;
+        let (pk, sk) = self.received.combine(&qualified);
+
+        let DkgParameters {
+            n, t, tprime, l, ..
+        } = self.params;
+        let mut pp = PublicParameters::new(PublicKey(pk), n, t, tprime, l);
+        pp.issuer_keys = self.received.issuer_keys(&qualified);
+        Ok(DkgOutput {
+            pp,
+            issuer: Issuer::new(SecretKey { sk }),
+            excluded,
+        })
+    }
+}
+
+impl RefreshRound1 {
+    /// Starts a share refresh for the issuer with the 1-based `index` in a committee of
+    /// `num_issuers` issuers under `pp`. The issuer deals a sharing of zero, so the issuer key
+    /// and `pp` are unchanged.
+    pub fn new(
+        pp: &PublicParameters,
+        num_issuers: usize,
+        index: usize,
+    ) -> Result<(Self, DkgCommitment, Vec<DkgShare>), DkgError> {
+        check_parameters(num_issuers, pp.n, pp.t, pp.tprime)?;
+        let (dealer, commitment, shares) = Dealer::new(num_issuers, pp.t, index, Scalar::zero())?;
+        Ok((Self { dealer }, commitment, shares))
+    }
+
+    /// Like [DkgRound1::receive], but additionally complains about dealers whose commitment
+    /// does not open to zero.
+    pub fn receive(
+        self,
+        commitments: &[DkgCommitment],
+        shares: &[DkgShare],
+    ) -> (RefreshRound2, Vec<DkgComplaint>) {
+        let (received, complaints) = self.dealer.receive(commitments, shares, |commitment| {
+            commitment.coefficients[0] == G1G2::default()
+        });
+        (RefreshRound2 { received }, complaints)
+    }
+}
+
+impl RefreshRound2 {
+    /// Adds the sharings of zero of all qualified dealers to the share of `issuer` and returns
+    /// the excluded dealers. The refreshed shares only combine with shares refreshed with the
+    /// same complaints; the old shares have to be discarded.
+    pub fn finalize(
+        self,
+        issuer: &mut Issuer,
+        complaints: &[DkgComplaint],
+    ) -> Result<Vec<usize>, DkgError> {
+        let (qualified, excluded) = self.received.qualified(complaints)?;
+        let (_, delta) = self.received.combine(&qualified);
+        issuer.refresh(&delta);
+        Ok(excluded)
+    }
+}
+
+/// Updates the [PublicParameters::issuer_keys] of `pp` after a share refresh with the broadcast
+/// `commitments` of the refresh, of which the dealers in `excluded` were disqualified. Anyone who
+/// saw the broadcast, e.g. a registrar, can update its parameters, so that it keeps checking the
+/// refreshed shares' blind tokens.
+pub fn refresh_issuer_keys(
+    pp: &mut PublicParameters,
+    commitments: &[DkgCommitment],
+    excluded: &[usize],
+) {
+    let num_issuers = pp.issuer_keys.len();
+    let qualified: BTreeMap<_, _> = commitments
+        .iter()
+        .rev()
+        .filter(|commitment| commitment.sender != 0 && commitment.sender <= num_issuers)
+        .filter(|commitment| !excluded.contains(&commitment.sender))
+        .map(|commitment| (commitment.sender, commitment))
+        .collect();
+    let deltas = public_shares(qualified.into_values(), num_issuers);
+    for (key, delta) in pp.issuer_keys.iter_mut().zip(deltas) {
+        key.0 = &key.0 + delta.0;
+    }
+    // the proofs are of the old keys
+    pp.issuer_pops.clear();
+    pp.validated = OnceGlobal::new();
+}
+
+/// Runs a share refresh of all `issuers`, in the order returned by
+/// [atact::setup](crate::atact::setup), in-process, and updates the issuer keys of `pp`. Returns
+/// the excluded dealers, which can only be non-empty if the refresh messages were tampered with.
+pub fn refresh_shares(
+    issuers: &mut [Issuer],
+    pp: &mut PublicParameters,
+) -> Result<Vec<usize>, DkgError> {
+    let mut states = Vec::with_capacity(issuers.len());
+    let mut commitments = Vec::with_capacity(issuers.len());
+    let mut shares = Vec::new();
+    for index in 1..=issuers.len() {
+        let (state, commitment, dealt) = RefreshRound1::new(pp, issuers.len(), index)?;
+        states.push(state);
+        commitments.push(commitment);
+        shares.extend(dealt);
+    }
+
+    let (states, complaints): (Vec<_>, Vec<_>) = states
+        .into_iter()
+        .map(|state| state.receive(&commitments, &shares))
+        .unzip();
+    let complaints: Vec<_> = complaints.into_iter().flatten().collect();
+
+    let mut excluded = Vec::new();
+    for (state, issuer) in states.into_iter().zip(issuers) {
+        excluded = state.finalize(issuer, &complaints)?;
+    }
+    refresh_issuer_keys(pp, &commitments, &excluded);
+    Ok(excluded)
+}
+
+#[derive(Error, Debug, PartialEq, Clone)]
+pub enum DkgError {
+    #[error("Invalid parameters: {0}")]
+    InvalidParameters(#[from] AtACTError),
+    #[error("Invalid participant index {0}.")]
+    InvalidIndex(usize),
+    #[error("All dealers were disqualified.")]
+    NoQualifiedDealers,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::atact::{
+        self, BlindRequest, BlindToken, Rand, aggregate_unblind, prove, register, setup, tissue,
+        token_request, verify,
+    };
+
+    const PARAMS: DkgParameters = DkgParameters {
+        num_issuers: 5,
+        n: 8,
+        t: 3,
+        tprime: 2,
+        l: 1,
+    };
+
+    fn run_dkg(tamper: impl Fn(&mut DkgShare)) -> Vec<DkgOutput> {
+        let mut states = Vec::new();
+        let mut commitments = Vec::new();
+        let mut shares = Vec::new();
+        for index in 1..=PARAMS.num_issuers {
+            let (state, commitment, dealt) = DkgRound1::new(PARAMS, index).unwrap();
+            states.push(state);
+            commitments.push(commitment);
+            shares.extend(dealt.into_iter().map(|mut share| {
+                tamper(&mut share);
+                share
+            }));
+        }
+
+        let (states, complaints): (Vec<_>, Vec<_>) = states
+            .into_iter()
+            .map(|state| state.receive(&commitments, &shares))
+            .unzip();
+        let complaints: Vec<_> = complaints.into_iter().flatten().collect();
+        states
+            .into_iter()
+            .map(|state| state.finalize(&complaints).expect("finalize failed"))
+            .collect()
+    }
+
+    fn assert_issuance(outputs: &[DkgOutput]) {
+        assert_eq!(outputs[0].pp.issuer_keys.len(), outputs.len());
+        for (output, issuer_key) in outputs.iter().zip(&outputs[0].pp.issuer_keys) {
+            assert_eq!(output.pp.pk, outputs[0].pp.pk);
+            assert_eq!(output.pp.issuer_keys, outputs[0].pp.issuer_keys);
+            assert_eq!(&output.issuer.as_ref().to_public_key(), issuer_key);
+        }
+        let pp = &outputs[0].pp;
+
+        let a = Scalar::rand(&mut thread_rng());
+        let (strg, cm) = register(&a, pp, &mut thread_rng()).expect("register failed");
+        let (blind_request, rand) =
+            token_request(&strg, &cm, pp, &mut thread_rng()).expect("token request failed");
+        // the aggregation interpolates at the indices 1..=t of the first t issuers
+        let blind_tokens: Vec<_> = outputs[..pp.t]
+            .iter()
+            .map(|output| tissue(&blind_request, &output.issuer, pp).expect("tissue failed"))
+            .collect();
+        let token = aggregate_unblind(&blind_tokens, &rand, pp).expect("unblinding failed");
+        let token_proof = prove(&token, &rand, pp, &mut thread_rng());
+        assert_eq!(
+            verify(&token, &token_proof, &blind_request, pp, &mut thread_rng()),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn issuance() {
+        let outputs = run_dkg(|_| {});
+        assert!(outputs.iter().all(|output| output.excluded.is_empty()));
+        assert_issuance(&outputs);
+
+        // the shares interpolate to the secret key of the public key
+        let shares = outputs[..PARAMS.t]
+            .iter()
+            .map(|output| output.issuer.as_ref());
+        assert_eq!(
+            PublicKey::from_secret_key_shares(shares, &outputs[0].pp.lagrange_t),
+            outputs[0].pp.pk
+        );
+    }
+
+    #[test]
+    fn inconsistent_share() {
+        let outputs = run_dkg(|share| {
+            if share.sender == 3 && share.recipient == 1 {
+                share.share += Scalar::from(1u64);
+            }
+        });
+        assert!(outputs.iter().all(|output| output.excluded == vec![3]));
+        assert_issuance(&outputs);
+    }
+
+    // aggregates `blind_tokens` into a token and checks it against `pp`
+    fn issue_with(
+        pp: &PublicParameters,
+        blind_request: &BlindRequest,
+        rand: &Rand,
+        blind_tokens: Vec<Vec<BlindToken>>,
+    ) -> Result<(), AtACTError> {
+        let token = aggregate_unblind(&blind_tokens, rand, pp)?;
+        let token_proof = prove(&token, rand, pp, &mut thread_rng());
+        verify(&token, &token_proof, blind_request, pp, &mut thread_rng())
+    }
+
+    fn partial_tokens(
+        pp: &PublicParameters,
+        blind_request: &BlindRequest,
+        issuers: &[Issuer],
+    ) -> Vec<Vec<BlindToken>> {
+        issuers
+            .iter()
+            .map(|issuer| tissue(blind_request, issuer, pp).expect("tissue failed"))
+            .collect()
+    }
+
+    #[test]
+    fn refresh() {
+        let (mut pp, mut issuers) = setup(
+            PARAMS.num_issuers,
+            PARAMS.n,
+            PARAMS.t,
+            PARAMS.tprime,
+            PARAMS.l,
+            &mut thread_rng(),
+        )
+        .unwrap();
+        let (strg, cm) =
+            register(&Scalar::rand(&mut thread_rng()), &pp, &mut thread_rng()).unwrap();
+        let (before, before_rand) = token_request(&strg, &cm, &pp, &mut thread_rng()).unwrap();
+        let (mixed, mixed_rand) = token_request(&strg, &cm, &pp, &mut thread_rng()).unwrap();
+        let before_tokens = partial_tokens(&pp, &before, &issuers[..pp.t]);
+        let stale = partial_tokens(&pp, &mixed, &issuers[..1]);
+
+        let old_share = issuers[0].as_ref().sk;
+        assert_eq!(refresh_shares(&mut issuers, &mut pp), Ok(Vec::new()));
+        assert_ne!(issuers[0].as_ref().sk, old_share);
+        assert_eq!(pp.issuer_keys, atact::issuer_keys(&issuers));
+
+        let (after, after_rand) = token_request(&strg, &cm, &pp, &mut thread_rng()).unwrap();
+        let after_tokens = partial_tokens(&pp, &after, &issuers[..pp.t]);
+        assert_eq!(
+            issue_with(&pp, &before, &before_rand, before_tokens),
+            Ok(())
+        );
+        assert_eq!(issue_with(&pp, &after, &after_rand, after_tokens), Ok(()));
+
+        // a partial token from before the refresh does not combine with refreshed ones
+        let mut mixed_tokens = stale;
+        mixed_tokens.extend(partial_tokens(&pp, &mixed, &issuers[1..pp.t]));
+        assert!(issue_with(&pp, &mixed, &mixed_rand, mixed_tokens).is_err());
+    }
+
+    #[test]
+    fn refresh_non_zero_dealer() {
+        let (pp, mut issuers) = setup(
+            PARAMS.num_issuers,
+            PARAMS.n,
+            PARAMS.t,
+            PARAMS.tprime,
+            PARAMS.l,
+            &mut thread_rng(),
+        )
+        .unwrap();
+
+        // the second issuer deals a sharing of one, which would change the issuer key
+        let mut states = Vec::new();
+        let mut commitments = Vec::new();
+        let mut shares = Vec::new();
+        for index in 1..=issuers.len() {
+            let (state, commitment, dealt) = RefreshRound1::new(&pp, issuers.len(), index).unwrap();
+            let (commitment, dealt) = if index == 2 {
+                let (_, commitment, dealt) =
+                    Dealer::new(issuers.len(), pp.t, index, Scalar::from(1u64)).unwrap();
+                (commitment, dealt)
+            } else {
+                (commitment, dealt)
+            };
+            states.push(state);
+            commitments.push(commitment);
+            shares.extend(dealt);
+        }
+        let (states, complaints): (Vec<_>, Vec<_>) = states
+            .into_iter()
+            .map(|state| state.receive(&commitments, &shares))
+            .unzip();
+        let complaints: Vec<_> = complaints.into_iter().flatten().collect();
+        assert_eq!(complaints.len(), issuers.len() - 1);
+        for (state, issuer) in states.into_iter().zip(&mut issuers) {
+            assert_eq!(state.finalize(issuer, &complaints), Ok(vec![2]));
+        }
+
+        let (strg, cm) =
+            register(&Scalar::rand(&mut thread_rng()), &pp, &mut thread_rng()).unwrap();
+        let (blind_request, rand) = token_request(&strg, &cm, &pp, &mut thread_rng()).unwrap();
+        let blind_tokens = partial_tokens(&pp, &blind_request, &issuers[..pp.t]);
+        assert_eq!(issue_with(&pp, &blind_request, &rand, blind_tokens), Ok(()));
+    }
+
+    #[test]
+    fn rogue_key() {
+        let mut states = Vec::new();
+        let mut commitments = Vec::new();
+        let mut shares = Vec::new();
+        for index in 1..=PARAMS.num_issuers {
+            let (state, commitment, dealt) = DkgRound1::new(PARAMS, index).unwrap();
+            states.push(state);
+            commitments.push(commitment);
+            shares.extend(dealt);
+        }
+
+        // the last dealer cancels the contributions of the others to obtain the key `g^x`
+        let x = SecretKey::new();
+        let others: G1G2 = commitments[..PARAMS.num_issuers - 1]
+            .iter()
+            .map(|commitment| &commitment.coefficients[0])
+            .sum();
+        let rogue = commitments.last_mut().unwrap();
+        rogue.coefficients[0] = &x.to_public_key().0 - &others;
+        rogue.pop = x.pop();
+
+        let (states, complaints): (Vec<_>, Vec<_>) = states
+            .into_iter()
+            .map(|state| state.receive(&commitments, &shares))
+            .unzip();
+        let complaints: Vec<_> = complaints.into_iter().flatten().collect();
+        let outputs: Vec<_> = states
+            .into_iter()
+            .map(|state| state.finalize(&complaints).expect("finalize failed"))
+            .collect();
+        assert!(
+            outputs
+                .iter()
+                .all(|output| output.excluded == vec![PARAMS.num_issuers])
+        );
+        assert_ne!(outputs[0].pp.pk, x.to_public_key());
+        assert_issuance(&outputs);
+    }
+
+    #[test]
+    fn missing_share() {
+        let (first, _, _) = DkgRound1::new(PARAMS, 1).unwrap();
+        let (_, complaints) = first.receive(&[], &[]);
+        assert_eq!(
+            complaints.iter().map(|c| c.accused).collect::<Vec<_>>(),
+            (2..=PARAMS.num_issuers).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn invalid_index() {
+        assert!(matches!(
+            DkgRound1::new(PARAMS, 0),
+            Err(DkgError::InvalidIndex(0))
+        ));
+        assert!(matches!(
+            DkgRound1::new(PARAMS, PARAMS.num_issuers + 1),
+            Err(DkgError::InvalidIndex(6))
+        ));
+        assert!(matches!(
+            DkgRound1::new(DkgParameters { t: 5, ..PARAMS }, 1),
+            Err(DkgError::InvalidParameters(AtACTError::InvalidParameters))
+        ));
+    }
+}
@@ -0,0 +1,200 @@
+use alloc::{collections::BTreeMap, vec::Vec};
+
+use ark_ff::UniformRand;
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
+use rand::{CryptoRng, RngCore};
+#[cfg(feature = "zeroize")]
+use zeroize::Zeroize;
+
+use crate::{
+    bls381_helpers::{G1G2, Scalar},
+    constants::PARTIAL_DECRYPTION_PROTOCOL,
+    fiat_shamir::ProofTranscript,
+    lagrange::Lagrange,
+    pedersen::get_parameters,
+};
+
+/// ElGamal decryption key `x`, e.g. of the health authority.
+#[derive(Clone, CanonicalSerialize, CanonicalDeserialize)]
+pub struct DecryptionKey(pub(crate) Scalar);
+
+/// ElGamal encryption key `g^x`, over the generator `g` of the Pedersen commitments.
+#[derive(Debug, Clone, PartialEq, Eq, CanonicalSerialize, CanonicalDeserialize)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EncryptionKey(pub(crate) G1G2);
+
+/// ElGamal ciphertext `(g^k, m pk^k)` of the group element `m`.
+#[derive(Debug, Clone, PartialEq, Eq, CanonicalSerialize, CanonicalDeserialize)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Ciphertext {
+    pub(crate) c1: G1G2,
+    pub(crate) c2: G1G2,
+}
+
+impl DecryptionKey {
+    pub fn random(rng: &mut (impl RngCore + CryptoRng)) -> Self {
+        Self(Scalar::rand(rng))
+    }
+
+    pub fn encryption_key(&self) -> EncryptionKey {
+        EncryptionKey(&get_parameters().g * self.0)
+    }
+
+    pub fn decrypt(&self, ciphertext: &Ciphertext) -> G1G2 {
+        &ciphertext.c2 - &ciphertext.c1 * self.0
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl Zeroize for DecryptionKey {
+    fn zeroize(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+impl EncryptionKey {
+    pub fn encrypt(&self, message: &G1G2, rng: &mut (impl RngCore + CryptoRng)) -> Ciphertext {
+        let k = Scalar::rand(rng);
+        Ciphertext {
+            c1: &get_parameters().g * k,
+            c2: message + &self.0 * k,
+        }
+    }
+}
+
+impl Ciphertext {
+    /// Ciphertext of the same message under fresh randomness. Without the decryption key, it
+    /// cannot be linked to `self` under the decisional Diffie-Hellman assumption.
+    pub fn rerandomize(&self, key: &EncryptionKey, rng: &mut (impl RngCore + CryptoRng)) -> Self {
+        let k = Scalar::rand(rng);
+        Self {
+            c1: &self.c1 + &get_parameters().g * k,
+            c2: &self.c2 + &key.0 * k,
+        }
+    }
+}
+
+/// Share `c1^x_j` of the decryption of a ciphertext `(c1, c2)` under the share `x_j` of the
+/// decryption key held by the issuer of index `index` in the committee, starting at 0. It carries
+/// a proof that `log_g pk_j = log_c1 c1^x_j` for the issuer's encryption key `pk_j = g^x_j`, so
+/// corrupted shares are detected before they are combined. The shares `x_j` are of the
+/// committee's [decryption key](crate::atact::PublicParameters::decryption_key), not of its
+/// signing key, so a partial decryption of any `c1` is no signature share.
+#[derive(Debug, Clone, PartialEq, Eq, CanonicalSerialize, CanonicalDeserialize)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PartialDecryption {
+    pub(crate) index: usize,
+    pub(crate) ciphertext: Ciphertext,
+    pub(crate) share: G1G2,
+    pub(crate) c: Scalar,
+    pub(crate) s: Scalar,
+}
+
+fn partial_decryption_challenge(
+    index: usize,
+    pk: &G1G2,
+    ciphertext: &Ciphertext,
+    share: &G1G2,
+    t1: &G1G2,
+    t2: &G1G2,
+) -> Scalar {
+    let mut transcript = ProofTranscript::new(PARTIAL_DECRYPTION_PROTOCOL);
+    transcript.append_u64(b"index", index as u64);
+    transcript.append_point(b"pk", pk);
+    transcript.append_point(b"c1", &ciphertext.c1);
+    transcript.append_point(b"c2", &ciphertext.c2);
+    transcript.append_point(b"share", share);
+    transcript.append_point(b"t1", t1);
+    transcript.append_point(b"t2", t2);
+    transcript.challenge_scalar(b"c")
+}
+
+impl PartialDecryption {
+    pub(crate) fn new(
+        x_j: &Scalar,
+        index: usize,
+        ciphertext: &Ciphertext,
+        rng: &mut (impl RngCore + CryptoRng),
+    ) -> Self {
+        let g = &get_parameters().g;
+        let share = &ciphertext.c1 * x_j;
+
+        let r = Scalar::rand(rng);
+        let t1 = g * r;
+        let t2 = &ciphertext.c1 * r;
+        let c = partial_decryption_challenge(index, &(g * x_j), ciphertext, &share, &t1, &t2);
+
+        Self {
+            index,
+            ciphertext: ciphertext.clone(),
+            share,
+            c,
+            s: r + c * x_j,
+        }
+    }
+
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    pub fn ciphertext(&self) -> &Ciphertext {
+        &self.ciphertext
+    }
+
+    /// Verifies the share against the encryption key `pk` of the share of the issuer of index
+    /// [Self::index], one of the
+    /// [decryption_keys](crate::atact::PublicParameters::decryption_keys) of the parameters.
+    pub fn verify(&self, pk: &EncryptionKey) -> bool {
+        let t1 = &get_parameters().g * self.s - &pk.0 * self.c;
+        let t2 = &self.ciphertext.c1 * self.s - &self.share * self.c;
+        partial_decryption_challenge(self.index, &pk.0, &self.ciphertext, &self.share, &t1, &t2)
+            == self.c
+    }
+}
+
+/// Plaintext `c2 / Π_j (c1^x_j)^λ_j` of the ciphertext of the first of `partials`, with the
+/// shares interpolated at the issuers' indices. Repeated indices are only counted once. The
+/// plaintext is only correct for verified shares of at least the threshold of distinct issuers,
+/// all of the same ciphertext.
+pub(crate) fn combine(partials: &[PartialDecryption]) -> G1G2 {
+    let Some(first) = partials.first() else {
+        return G1G2::default();
+    };
+    let shares: BTreeMap<_, _> = partials
+        .iter()
+        .rev()
+        .map(|partial| (partial.index, &partial.share))
+        .collect();
+    let points: Vec<_> = shares
+        .keys()
+        .map(|index| Scalar::from(*index as u64 + 1))
+        .collect();
+    let lagrange = Lagrange::new(&points);
+    let shares: Vec<_> = shares.into_values().cloned().collect();
+    &first.ciphertext.c2 - lagrange.eval_0(&shares)
+}
+
+#[cfg(test)]
+mod test {
+    use rand::rngs::OsRng;
+
+    use super::*;
+
+    #[test]
+    fn rerandomized_ciphertexts_decrypt_to_the_message() {
+        let sk = DecryptionKey::random(&mut OsRng);
+        let pk = sk.encryption_key();
+        let message = G1G2::random(OsRng);
+
+        let ciphertext = pk.encrypt(&message, &mut OsRng);
+        assert_eq!(sk.decrypt(&ciphertext), message);
+
+        let rerandomized = ciphertext.rerandomize(&pk, &mut OsRng);
+        assert_ne!(rerandomized.c1, ciphertext.c1);
+        assert_ne!(rerandomized.c2, ciphertext.c2);
+        assert_eq!(sk.decrypt(&rerandomized), message);
+
+        let other = DecryptionKey::random(&mut OsRng);
+        assert_ne!(other.decrypt(&ciphertext), message);
+    }
+}
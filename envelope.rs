@@ -0,0 +1,141 @@
+use alloc::vec::Vec;
+
+use crate::bls381_helpers::DecodeError;
+
+/// First bytes of every persisted artifact.
+pub const MAGIC: [u8; 4] = *b"SPRS";
+/// Version of the payloads written by this release, unless the payload of an artifact changed
+/// on its own, e.g. of [registry snapshots](crate::registry::SNAPSHOT_VERSION). Decoders keep
+/// reading the payloads of all earlier versions and reject later ones with
+/// [DecodeError::UnsupportedVersion].
+pub const VERSION: u16 = 1;
+/// Size of the header `MAGIC || version || type` in front of every payload, with the version as
+/// little-endian `u16` and the type as its [ArtifactType] byte.
+pub const HEADER_SIZE: usize = MAGIC.len() + size_of::<u16>() + 1;
+
+/// Type of a persisted artifact. Group elements are fields of the payloads, not artifacts of
+/// their own, so [G1G2::to_bytes](crate::G1G2::to_bytes) has no header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum ArtifactType {
+    PublicKey = 1,
+    Signature = 2,
+    Token = 3,
+    RegistryLog = 4,
+    RegistryFilter = 5,
+    RevocationList = 6,
+    Transcript = 7,
+    RegistrySnapshot = 8,
+    ExposurePolicy = 9,
+    UserBackup = 10,
+    IssuanceLog = 11,
+    RegistryIndex = 12,
+    EncounterTable = 13,
+    EncounterBackup = 14,
+}
+
+/// How the loader of an artifact of many entries, e.g.
+/// [import_registry_with](crate::registry::import_registry_with) or
+/// [EncounterTable::import_backup_with](crate::spirit::EncounterTable::import_backup_with), treats
+/// entries that do not decode.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LoadMode {
+    /// The first entry that does not decode fails the load.
+    #[default]
+    Strict,
+    /// Entries that do not decode are skipped and reported as [LoadWarning]s, so a corrupted
+    /// entry does not lose the others. An invalid header or checksum still fails the load.
+    Lenient,
+}
+
+/// Entry of an artifact that a [LoadMode::Lenient] load skipped.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LoadWarning {
+    /// Offset of the entry from the start of the artifact, i.e. of its header.
+    pub offset: u64,
+    pub reason: DecodeError,
+}
+
+/// Header of an `artifact` of the current [VERSION].
+pub(crate) const fn header(artifact: ArtifactType) -> [u8; HEADER_SIZE] {
+    versioned_header(artifact, VERSION)
+}
+
+/// Header of an `artifact` whose payload has the `version` of its own.
+pub(crate) const fn versioned_header(artifact: ArtifactType, version: u16) -> [u8; HEADER_SIZE] {
+    let version = version.to_le_bytes();
+    [
+        MAGIC[0],
+        MAGIC[1],
+        MAGIC[2],
+        MAGIC[3],
+        version[0],
+        version[1],
+        artifact as u8,
+    ]
+}
+
+/// `payload` of an `artifact` of the current [VERSION] behind its header.
+pub(crate) fn seal(artifact: ArtifactType, payload: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(HEADER_SIZE + payload.len());
+    bytes.extend_from_slice(&header(artifact));
+    bytes.extend_from_slice(payload);
+    bytes
+}
+
+/// Splits the encoding of an `artifact` into its version and its payload. The decoders of the
+/// artifacts dispatch on the version, which is not checked here.
+pub(crate) fn open(bytes: &[u8], artifact: ArtifactType) -> Result<(u16, &[u8]), DecodeError> {
+    let Some((header, payload)) = bytes.split_at_checked(HEADER_SIZE) else {
+        return Err(DecodeError::InvalidHeader);
+    };
+    if header[..MAGIC.len()] != MAGIC {
+        return Err(DecodeError::InvalidHeader);
+    }
+    if header[HEADER_SIZE - 1] != artifact as u8 {
+        return Err(DecodeError::UnexpectedArtifactType {
+            expected: artifact,
+            actual: header[HEADER_SIZE - 1],
+        });
+    }
+    Ok((u16::from_le_bytes([header[4], header[5]]), payload))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn headers_are_checked() {
+        let bytes = seal(ArtifactType::Token, b"payload");
+        assert_eq!(bytes[..HEADER_SIZE], header(ArtifactType::Token));
+        assert_eq!(
+            open(&bytes, ArtifactType::Token),
+            Ok((VERSION, &b"payload"[..]))
+        );
+        assert_eq!(
+            open(&bytes, ArtifactType::Signature),
+            Err(DecodeError::UnexpectedArtifactType {
+                expected: ArtifactType::Signature,
+                actual: ArtifactType::Token as u8
+            })
+        );
+        assert_eq!(
+            open(&bytes[..HEADER_SIZE - 1], ArtifactType::Token),
+            Err(DecodeError::InvalidHeader)
+        );
+
+        let mut other_magic = bytes.clone();
+        other_magic[0] ^= 1;
+        assert_eq!(
+            open(&other_magic, ArtifactType::Token),
+            Err(DecodeError::InvalidHeader)
+        );
+        let mut later_version = bytes;
+        later_version[4] = 2;
+        assert_eq!(
+            open(&later_version, ArtifactType::Token),
+            Ok((2, &b"payload"[..]))
+        );
+    }
+}
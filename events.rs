@@ -0,0 +1,206 @@
+//! Events of a [SpiritSystem], for applications that react to alarms and rejected reports
+//! without inspecting every return value. A system hands its events to the [EventSink] set with
+//! [SpiritSystem::set_event_sink], in the order of the calls they result from. With the `async`
+//! feature, a [ChannelSink] forwards them to an async consumer.
+//!
+//! Like the events of the [telemetry](crate::telemetry) feature, they carry counts, risk levels
+//! and the [reasons](VerifyRejection::reason) of rejections, but never ElIDs, keys or tokens.
+
+use std::sync::Arc;
+
+#[cfg(feature = "async")]
+use futures::channel::mpsc::{self, UnboundedReceiver, UnboundedSender};
+
+use crate::{
+    exposure::RiskLevel,
+    spirit::{Accepted, SpiritSystem, VerifyRejection},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    /// A trace raised an alarm for `count` matched ElIDs, or for the score `count` of a
+    /// weighted trace. Traces against an exposure limit raise [RiskLevel::High].
+    ExposureAlarm { count: u64, risk: RiskLevel },
+    /// A trace report or bundle was rejected for the [reason](VerifyRejection::reason), e.g.
+    /// `"revoked"` for a revoked token or `"replayed_nullifier"` for a reused nullifier.
+    ReportRejected { reason: &'static str },
+    /// A trace report or bundle was accepted with `elid_count` confirmed ElIDs.
+    ReportAccepted { elid_count: usize },
+}
+
+impl Event {
+    /// The event of the outcome of verifying one report.
+    pub fn verified(verified: &Result<Accepted, VerifyRejection>) -> Self {
+        match verified {
+            Ok(accepted) => Self::ReportAccepted {
+                elid_count: accepted.verdicts.iter().filter(|verdict| **verdict).count(),
+            },
+            Err(rejection) => Self::ReportRejected {
+                reason: rejection.reason(),
+            },
+        }
+    }
+}
+
+/// Receiver of the [Event]s of a [SpiritSystem]. A sink is called while the system's method
+/// runs, so it should hand the event off rather than block.
+pub trait EventSink: Send + Sync {
+    fn emit(&self, event: Event);
+}
+
+impl<S: EventSink + ?Sized> EventSink for Arc<S> {
+    fn emit(&self, event: Event) {
+        (**self).emit(event);
+    }
+}
+
+/// [EventSink] that sends the events on an unbounded channel, with the `async` feature.
+#[cfg(feature = "async")]
+#[derive(Debug, Clone)]
+pub struct ChannelSink {
+    sender: UnboundedSender<Event>,
+}
+
+#[cfg(feature = "async")]
+impl ChannelSink {
+    /// A sink and the receiving end of its channel. Events emitted after the receiver is
+    /// dropped are discarded.
+    pub fn new() -> (Self, UnboundedReceiver<Event>) {
+        let (sender, receiver) = mpsc::unbounded();
+        (Self { sender }, receiver)
+    }
+}
+
+#[cfg(feature = "async")]
+impl EventSink for ChannelSink {
+    fn emit(&self, event: Event) {
+        // a dropped receiver means nobody listens anymore
+        let _ = self.sender.unbounded_send(event);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{collections::HashSet, sync::Mutex};
+
+    use rand::rngs::OsRng;
+
+    use super::*;
+    use crate::{
+        elgamal::DecryptionKey,
+        exposure::ExposurePolicy,
+        spirit::{
+            DomainConfig, EncounterTable, Interval, UserSecret, spirit_broadcast_osrng,
+            spirit_diagnosis_osrng,
+        },
+    };
+
+    const NOW: u64 = 10;
+    const EXPIRY: u64 = 100;
+
+    #[derive(Default)]
+    struct RecordingSink {
+        events: Mutex<Vec<Event>>,
+    }
+
+    impl EventSink for RecordingSink {
+        fn emit(&self, event: Event) {
+            self.events.lock().unwrap().push(event);
+        }
+    }
+
+    #[test]
+    fn systems_emit_the_events_of_their_calls() {
+        let domain = DomainConfig::default();
+        let (mut system, issuers) = SpiritSystem::setup(3, 4, 4).expect("setup failed");
+        let sink = Arc::new(RecordingSink::default());
+        system.set_event_sink(sink.clone());
+
+        let pk_ha = DecryptionKey::random(&mut OsRng).encryption_key();
+        let mut t_el = EncounterTable::new();
+        let mut reports = Vec::new();
+        for _ in 0..2 {
+            let user = UserSecret::random(OsRng);
+            let ppu = system
+                .register(&user, &issuers, EXPIRY)
+                .expect("registration failed");
+            for index in 0..2 {
+                let i = Interval(index);
+                spirit_broadcast_osrng(&domain, 0, i, user.keys(), &pk_ha, &mut t_el);
+            }
+            let challenge = system.issue_challenge(NOW);
+            let cp = HashSet::from([(0, Interval(0)), (0, Interval(1))]);
+            let report =
+                spirit_diagnosis_osrng(&domain, &ppu, user.keys(), &cp, NOW, &challenge, &[])
+                    .expect("diagnosis failed");
+            reports.push((ppu.0, report));
+        }
+        let [(_, first), (revoked, second)] = &reports[..] else {
+            unreachable!()
+        };
+
+        // accepted, replayed, and with a revoked token
+        let mut cf = HashSet::new();
+        assert!(system.verify_report(first, NOW, &mut cf).is_ok());
+        assert!(system.verify_report(first, NOW, &mut cf).is_err());
+        system.revoke(revoked);
+        assert!(system.verify_report(second, NOW, &mut cf).is_err());
+
+        // an alarm of both kinds of traces, and none without a match
+        assert!(system.trace(&cf, &t_el, 2).alarm);
+        let policy = ExposurePolicy::builder()
+            .tier(1, RiskLevel::Low)
+            .tier(2, RiskLevel::Medium)
+            .build()
+            .unwrap();
+        assert_eq!(
+            system.trace_with_policy(&cf, &t_el, &policy).risk,
+            RiskLevel::Medium
+        );
+        assert!(!system.trace(&cf, &EncounterTable::new(), 1).alarm);
+
+        assert_eq!(
+            *sink.events.lock().unwrap(),
+            [
+                Event::ReportAccepted { elid_count: 2 },
+                Event::ReportRejected {
+                    reason: "replayed_nullifier"
+                },
+                Event::ReportRejected { reason: "revoked" },
+                Event::ExposureAlarm {
+                    count: 2,
+                    risk: RiskLevel::High
+                },
+                Event::ExposureAlarm {
+                    count: 2,
+                    risk: RiskLevel::Medium
+                },
+            ]
+        );
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn channel_sinks_forward_the_events() {
+        use futures::{StreamExt, executor::block_on};
+
+        let (sink, mut receiver) = ChannelSink::new();
+        sink.emit(Event::ReportAccepted { elid_count: 1 });
+        sink.emit(Event::ReportRejected { reason: "revoked" });
+        drop(sink);
+        let events: Vec<_> = block_on(async {
+            let mut events = Vec::new();
+            while let Some(event) = receiver.next().await {
+                events.push(event);
+            }
+            events
+        });
+        assert_eq!(
+            events,
+            [
+                Event::ReportAccepted { elid_count: 1 },
+                Event::ReportRejected { reason: "revoked" }
+            ]
+        );
+    }
+}
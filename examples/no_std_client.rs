@@ -0,0 +1,91 @@
+//! The client side of SPiRiT built without `std`: a user broadcasts the ElIDs of one day and,
+//! after a positive diagnosis, discloses a few of them in a trace report.
+//!
+//! ```text
+//! cargo run --example no_std_client --no-default-features --features alloc
+//! ```
+//!
+//! Only the entry point links `std`, for its runtime and panic handler; the example and the
+//! library use nothing but `core` and `alloc`, and all randomness comes from a seeded
+//! [ChaCha20Rng] instead of `OsRng`. The issuers are simulated locally with the tACT functions,
+//! as a registrar would run them.
+#![no_std]
+
+extern crate alloc;
+extern crate std;
+
+use alloc::vec::Vec;
+
+use ark_ff::UniformRand;
+use rand::SeedableRng;
+use rand_chacha::ChaCha20Rng;
+use spirit::{
+    Scalar,
+    atact::{self, tissue},
+    elgamal::DecryptionKey,
+    spirit::{
+        DomainConfig, EncounterTable, Interval, ReportChallenge, UserKeys, spirit_broadcast_range,
+        spirit_diagnosis, spirit_register_begin, spirit_register_finish,
+    },
+};
+
+const EPOCH: u64 = 1;
+const EXPIRY: u64 = 100;
+const INTERVALS_PER_DAY: u64 = 96;
+const DISCLOSED: usize = 4;
+
+fn main() {
+    let mut rng = ChaCha20Rng::seed_from_u64(30);
+    let domain = DomainConfig::default();
+
+    // Registration, with the issuers run in-process.
+    let (pp, issuers) = atact::setup(4, 8, 3, 2, 1, &mut rng).expect("tACT setup failed");
+    let id_u = Scalar::rand(&mut rng);
+    let (blind_request, pending) =
+        spirit_register_begin(&domain, &id_u, &pp, EXPIRY, &mut rng).expect("token request failed");
+    let blind_tokens: Vec<_> = issuers
+        .iter()
+        .map(|issuer| tissue(&blind_request, issuer, &pp).expect("issuance failed"))
+        .collect();
+    let (ppu, _, _) = spirit_register_finish(&domain, pending, &blind_tokens, &pp, &mut rng)
+        .expect("unblinding failed");
+
+    // Broadcast all intervals of the day, with the encounter secrets encrypted to the health
+    // authority.
+    let pk_ha = DecryptionKey::random(&mut rng).encryption_key();
+    let keys = UserKeys::random(&mut rng);
+    let mut t_el = EncounterTable::default();
+    let broadcast = spirit_broadcast_range(
+        &domain,
+        EPOCH,
+        Interval(0),
+        Interval(INTERVALS_PER_DAY),
+        &keys,
+        &pk_ha,
+        &mut t_el,
+        &mut rng,
+    );
+    assert_eq!(broadcast.len() as u64, INTERVALS_PER_DAY);
+
+    // Disclose the first few intervals.
+    let cp = broadcast
+        .iter()
+        .take(DISCLOSED)
+        .map(|(index, _)| (EPOCH, *index))
+        .collect();
+    // The challenge is normally received from the verifier.
+    let challenge = ReportChallenge::new(EPOCH, &mut rng);
+    let report = spirit_diagnosis(
+        &domain,
+        &ppu,
+        &keys,
+        None,
+        &cp,
+        EPOCH,
+        &challenge,
+        &[],
+        &mut rng,
+    )
+    .expect("diagnosis failed");
+    assert_eq!(report.disclosures.len(), DISCLOSED);
+}
@@ -0,0 +1,763 @@
+use std::collections::{HashMap, HashSet};
+
+use ark_ff::{UniformRand, Zero};
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
+use rand::{CryptoRng, RngCore, seq::SliceRandom};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+use crate::{
+    DecodeError, G1G2, Scalar,
+    constants::{CONFIRMED_SET_DST, ENCOUNTER_TABLE_DST, EXPOSURE_PROTOCOL},
+    envelope::{self, ArtifactType, HEADER_SIZE},
+    fiat_shamir::ProofTranscript,
+    lagrange::Lagrange,
+    pedersen::get_parameters,
+    spirit::{ElID, EncounterTable},
+};
+
+/// Digest of a confirmed set, see [confirmed_digest].
+pub type ConfirmedDigest = [u8; 32];
+
+/// Digest of the entries of a [TableCommitment], see [TableCommitment::root].
+pub type TableRoot = [u8; 32];
+
+/// The distinct ElIDs of `cf`, ordered by their encoding.
+fn confirmed_set<'a>(cf: impl IntoIterator<Item = &'a ElID>) -> Vec<ElID> {
+    let mut cf: Vec<_> = cf
+        .into_iter()
+        .collect::<HashSet<_>>()
+        .into_iter()
+        .cloned()
+        .collect();
+    cf.sort_by_cached_key(G1G2::to_bytes);
+    cf
+}
+
+fn digest(cf: &[ElID]) -> ConfirmedDigest {
+    let mut hasher = Sha256::new()
+        .chain_update(CONFIRMED_SET_DST)
+        .chain_update((cf.len() as u64).to_le_bytes());
+    for el_id in cf {
+        hasher.update(el_id.to_bytes());
+    }
+    hasher.finalize().into()
+}
+
+/// Digest of the distinct ElIDs of the confirmed set `cf`, independent of their order and
+/// multiplicity. The server verifies [ExposureProof]s against the digest of the set it
+/// published.
+pub fn confirmed_digest<'a>(cf: impl IntoIterator<Item = &'a ElID>) -> ConfirmedDigest {
+    digest(&confirmed_set(cf))
+}
+
+fn table_root(entries: &[G1G2]) -> TableRoot {
+    let mut hasher = Sha256::new()
+        .chain_update(ENCOUNTER_TABLE_DST)
+        .chain_update((entries.len() as u64).to_le_bytes());
+    for entry in entries {
+        hasher.update(entry.to_bytes());
+    }
+    hasher.finalize().into()
+}
+
+/// Commitment of a client to its encounter table: a hiding commitment `el_id g^rho` to every
+/// ElID of the table, in random order, see [commit_table]. The client hands the
+/// [root](Self::root) to the server before the confirmed set is published, so that its
+/// [ExposureProof]s cannot be made for a table assembled from the confirmed set.
+pub struct TableCommitment {
+    entries: Vec<G1G2>,
+    /// The ElID and the randomness of every entry.
+    openings: Vec<(ElID, Scalar)>,
+}
+
+impl TableCommitment {
+    /// Digest of the entries, from which the server learns only the size of the table.
+    pub fn root(&self) -> TableRoot {
+        table_root(&self.entries)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+/// Commits to the ElIDs of `t_el`, see [TableCommitment].
+pub fn commit_table(
+    t_el: &EncounterTable,
+    rng: &mut (impl RngCore + CryptoRng),
+) -> TableCommitment {
+    let g = &get_parameters().g;
+    let mut openings: Vec<_> = t_el
+        .el_ids()
+        .map(|el_id| (el_id, Scalar::rand(rng)))
+        .collect();
+    openings.shuffle(rng);
+    let entries = openings
+        .iter()
+        .map(|(el_id, rho)| el_id + g * *rho)
+        .collect();
+    TableCommitment { entries, openings }
+}
+
+/// Proof that at least `exposure_limit` ElIDs of a confirmed set `cf` are in a client's
+/// encounter table, see [prove_exposure].
+///
+/// The proof consists of the entries of the client's [TableCommitment] and for every ElID of
+/// `cf` a proof that one of the entries opens to it. These are combined into a threshold proof
+/// in the style of Cramer, Damgård and Schoenmakers: the challenges of the ElIDs lie on a
+/// polynomial of degree `|cf| - exposure_limit` through the Fiat-Shamir challenge, so all but
+/// `exposure_limit` of the proofs can be simulated but the others have to be real. As the
+/// ElIDs of `cf` are distinct, so are the encounters they count, however often the table holds
+/// them.
+///
+/// The server learns the size of the encounter table, but neither which entries are confirmed
+/// nor how many beyond the limit. Like the count of [spirit_trace](crate::spirit::spirit_trace),
+/// the proof speaks for the table the client committed to, which [verify_exposure] checks
+/// against its root. Its size is the number of entries times `|cf|`.
+#[derive(CanonicalSerialize, CanonicalDeserialize)]
+pub struct ExposureProof {
+    pub(crate) cf: Vec<ElID>,
+    pub(crate) entries: Vec<G1G2>,
+    /// For every ElID of `cf`, the challenges and responses of its proofs for the entries.
+    pub(crate) challenges: Vec<Vec<Scalar>>,
+    pub(crate) responses: Vec<Vec<Scalar>>,
+}
+
+/// Position of the ElID `i` of `cf` on the challenge polynomial, whose value at 0 is the
+/// Fiat-Shamir challenge.
+fn position(i: usize) -> Scalar {
+    Scalar::from(i as u64 + 1)
+}
+
+fn challenge(
+    cf_digest: &ConfirmedDigest,
+    root: &TableRoot,
+    exposure_limit: usize,
+    entries: &[G1G2],
+    commitments: &[Vec<G1G2>],
+) -> Scalar {
+    let mut transcript = ProofTranscript::new(EXPOSURE_PROTOCOL);
+    transcript.append_message(b"cf", cf_digest);
+    transcript.append_message(b"table", root);
+    transcript.append_u64(b"limit", exposure_limit as u64);
+    transcript.append_points(b"entries", entries.iter());
+    for commitments in commitments {
+        transcript.append_points(b"t", commitments.iter());
+    }
+    transcript.challenge_scalar(b"c")
+}
+
+/// Value at `x` of the polynomial through the points `(xs[l], ys[l])`.
+fn interpolate(lagrange: &Lagrange, ys: &[Scalar], x: Scalar) -> Scalar {
+    ys.iter()
+        .enumerate()
+        .map(|(l, y)| *y * lagrange.eval_j(x, l))
+        .sum()
+}
+
+/// Proves that at least `exposure_limit` ElIDs of the confirmed set `cf` are in the table
+/// committed to by `table`, or returns `None` if fewer are. A limit of 0 is treated like 1, as
+/// in [spirit_trace](crate::spirit::spirit_trace).
+pub fn prove_exposure<'a>(
+    table: &TableCommitment,
+    cf: impl IntoIterator<Item = &'a ElID>,
+    exposure_limit: usize,
+    rng: &mut (impl RngCore + CryptoRng),
+) -> Option<ExposureProof> {
+    let g = &get_parameters().g;
+    let cf = confirmed_set(cf);
+    let cf_digest = digest(&cf);
+    let root = table.root();
+    let limit = exposure_limit.max(1);
+    let positions: HashMap<_, _> = table
+        .openings
+        .iter()
+        .enumerate()
+        .map(|(j, (el_id, _))| (el_id, j))
+        .collect();
+    // the entry that every ElID of cf is opened by, if it is in the table
+    let matches: Vec<_> = cf
+        .iter()
+        .map(|el_id| positions.get(el_id).copied())
+        .collect();
+
+    // the proofs of the first `limit` ElIDs in the table are real, all others are simulated
+    let real: HashSet<_> = matches
+        .iter()
+        .enumerate()
+        .filter(|(_, j)| j.is_some())
+        .map(|(i, _)| i)
+        .take(limit)
+        .collect();
+    if real.len() < limit {
+        return None;
+    }
+
+    let entries = &table.entries;
+    let mut challenges: Vec<Vec<_>> = cf
+        .iter()
+        .map(|_| entries.iter().map(|_| Scalar::rand(rng)).collect())
+        .collect();
+    let mut responses: Vec<Vec<_>> = cf
+        .iter()
+        .map(|_| entries.iter().map(|_| Scalar::rand(rng)).collect())
+        .collect();
+    let nonces: Vec<_> = cf.iter().map(|_| Scalar::rand(rng)).collect();
+    let commitments: Vec<Vec<_>> = cf
+        .iter()
+        .enumerate()
+        .map(|(i, el_id)| {
+            entries
+                .iter()
+                .enumerate()
+                .map(|(j, entry)| {
+                    if real.contains(&i) && matches[i] == Some(j) {
+                        g * nonces[i]
+                    } else {
+                        g * responses[i][j] - (entry - el_id) * challenges[i][j]
+                    }
+                })
+                .collect()
+        })
+        .collect();
+
+    let c = challenge(&cf_digest, &root, limit, entries, &commitments);
+
+    // the challenges of the real proofs are fixed by c and those of the simulated ones
+    let simulated: Vec<_> = (0..cf.len()).filter(|i| !real.contains(i)).collect();
+    let xs: Vec<_> = core::iter::once(Scalar::zero())
+        .chain(simulated.iter().map(|i| position(*i)))
+        .collect();
+    let ys: Vec<_> = core::iter::once(c)
+        .chain(simulated.iter().map(|i| challenges[*i].iter().sum()))
+        .collect();
+    let lagrange = Lagrange::new(&xs);
+    for i in real {
+        // real ElIDs are in the table
+        let j = matches[i].unwrap_or_default();
+        let (_, rho) = &table.openings[j];
+        let others: Scalar = challenges[i]
+            .iter()
+            .enumerate()
+            .filter(|(other, _)| *other != j)
+            .map(|(_, c)| *c)
+            .sum();
+        challenges[i][j] = interpolate(&lagrange, &ys, position(i)) - others;
+        responses[i][j] = nonces[i] + *rho * challenges[i][j];
+    }
+
+    Some(ExposureProof {
+        cf,
+        entries: entries.clone(),
+        challenges,
+        responses,
+    })
+}
+
+/// Verifies that `proof` shows at least `exposure_limit` distinct ElIDs of the confirmed set
+/// with digest `cf_digest` in the table with root `root`. The proof has to be made for
+/// `exposure_limit`; a limit of 0 is treated like 1.
+pub fn verify_exposure(
+    proof: &ExposureProof,
+    cf_digest: &ConfirmedDigest,
+    root: &TableRoot,
+    exposure_limit: usize,
+) -> bool {
+    let g = &get_parameters().g;
+    let limit = exposure_limit.max(1);
+    let num_el_ids = proof.cf.len();
+    // digest does not deduplicate, so a cf of repeated or unordered ElIDs does not match
+    if digest(&proof.cf) != *cf_digest
+        || table_root(&proof.entries) != *root
+        || proof.entries.is_empty()
+        || num_el_ids < limit
+        || proof.challenges.len() != num_el_ids
+        || proof.responses.len() != num_el_ids
+        || proof
+            .challenges
+            .iter()
+            .chain(&proof.responses)
+            .any(|row| row.len() != proof.entries.len())
+    {
+        return false;
+    }
+
+    let commitments: Vec<Vec<_>> = proof
+        .cf
+        .iter()
+        .zip(proof.challenges.iter().zip(&proof.responses))
+        .map(|(el_id, (challenges, responses))| {
+            proof
+                .entries
+                .iter()
+                .zip(challenges.iter().zip(responses))
+                .map(|(entry, (c, s))| g * *s - (entry - el_id) * *c)
+                .collect()
+        })
+        .collect();
+    let c = challenge(cf_digest, root, limit, &proof.entries, &commitments);
+
+    // c and the challenges of the ElIDs lie on a polynomial of degree `num_el_ids - limit`,
+    // which is determined by c and the first `num_el_ids - limit` ElIDs
+    let el_id_challenges: Vec<Scalar> = proof
+        .challenges
+        .iter()
+        .map(|challenges| challenges.iter().sum())
+        .collect();
+    let degree = num_el_ids - limit;
+    let xs: Vec<_> = (0..=degree as u64).map(Scalar::from).collect();
+    let ys: Vec<_> = core::iter::once(c)
+        .chain(el_id_challenges[..degree].iter().copied())
+        .collect();
+    let lagrange = Lagrange::new(&xs);
+    (degree..num_el_ids).all(|i| interpolate(&lagrange, &ys, position(i)) == el_id_challenges[i])
+}
+
+/// Risk a user is at by their confirmed encounters, as graded by an [ExposurePolicy].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[repr(u8)]
+pub enum RiskLevel {
+    None = 0,
+    Low = 1,
+    Medium = 2,
+    High = 3,
+}
+
+impl TryFrom<u8> for RiskLevel {
+    type Error = PolicyError;
+
+    fn try_from(level: u8) -> Result<Self, Self::Error> {
+        match level {
+            0 => Ok(Self::None),
+            1 => Ok(Self::Low),
+            2 => Ok(Self::Medium),
+            3 => Ok(Self::High),
+            _ => Err(PolicyError::InvalidLevel(level)),
+        }
+    }
+}
+
+/// Tiers of a score, i.e. of the number of distinct matches or of the sum of their weights.
+/// Every tier assigns its [RiskLevel] from its threshold up to the threshold of the next tier,
+/// and scores below the first threshold are of [RiskLevel::None]. For example, the tiers
+/// `(1, Low)`, `(3, Medium)` and `(10, High)` grade 1-2 matches as low and 3-9 as medium risk.
+///
+/// Policies are built with [ExposurePolicy::builder] and decoded with
+/// [ExposurePolicy::from_bytes], which both check that the thresholds are strictly increasing,
+/// so that servers can push policy updates to their clients.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(try_from = "Vec<(u64, RiskLevel)>", into = "Vec<(u64, RiskLevel)>")
+)]
+pub struct ExposurePolicy {
+    tiers: Vec<(u64, RiskLevel)>,
+}
+
+/// Size of the encoding of a tier, its threshold as little-endian `u64` and its level.
+const TIER_SIZE: usize = size_of::<u64>() + 1;
+
+impl ExposurePolicy {
+    pub fn builder() -> ExposurePolicyBuilder {
+        ExposurePolicyBuilder::default()
+    }
+
+    /// The tiers as pairs of threshold and level, by increasing threshold.
+    pub fn tiers(&self) -> &[(u64, RiskLevel)] {
+        &self.tiers
+    }
+
+    /// Level of the highest tier whose threshold `score` reaches.
+    pub fn risk(&self, score: u64) -> RiskLevel {
+        self.tiers
+            .iter()
+            .rev()
+            .find(|(threshold, _)| score >= *threshold)
+            .map_or(RiskLevel::None, |(_, level)| *level)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(HEADER_SIZE + TIER_SIZE * self.tiers.len());
+        bytes.extend_from_slice(&envelope::header(ArtifactType::ExposurePolicy));
+        for (threshold, level) in &self.tiers {
+            bytes.extend_from_slice(&threshold.to_le_bytes());
+            bytes.push(*level as u8);
+        }
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, PolicyError> {
+        match envelope::open(bytes, ArtifactType::ExposurePolicy)? {
+            (1, payload) => Self::from_payload_v1(payload),
+            (version, _) => Err(DecodeError::UnsupportedVersion(version).into()),
+        }
+    }
+
+    fn from_payload_v1(bytes: &[u8]) -> Result<Self, PolicyError> {
+        if bytes.len() % TIER_SIZE != 0 {
+            return Err(PolicyError::InvalidLength {
+                length: bytes.len(),
+            });
+        }
+
+        let tiers = bytes
+            .chunks_exact(TIER_SIZE)
+            .map(|tier| {
+                let threshold = u64::from_le_bytes(tier[..TIER_SIZE - 1].try_into().unwrap());
+                Ok((threshold, RiskLevel::try_from(tier[TIER_SIZE - 1])?))
+            })
+            .collect::<Result<Vec<_>, PolicyError>>()?;
+        Self::try_from(tiers)
+    }
+}
+
+impl TryFrom<Vec<(u64, RiskLevel)>> for ExposurePolicy {
+    type Error = PolicyError;
+
+    fn try_from(tiers: Vec<(u64, RiskLevel)>) -> Result<Self, Self::Error> {
+        if tiers.is_empty() {
+            return Err(PolicyError::Empty);
+        }
+        // a threshold of 0 would grade users at risk without any match
+        let mut previous = 0;
+        for (index, (threshold, level)) in tiers.iter().enumerate() {
+            if *threshold <= previous {
+                return Err(PolicyError::NotIncreasing(index));
+            }
+            if *level == RiskLevel::None {
+                return Err(PolicyError::NoRisk(index));
+            }
+            previous = *threshold;
+        }
+        Ok(Self { tiers })
+    }
+}
+
+impl From<ExposurePolicy> for Vec<(u64, RiskLevel)> {
+    fn from(policy: ExposurePolicy) -> Self {
+        policy.tiers
+    }
+}
+
+/// Builder of an [ExposurePolicy], see [ExposurePolicy::builder].
+#[derive(Debug, Clone, Default)]
+pub struct ExposurePolicyBuilder {
+    tiers: Vec<(u64, RiskLevel)>,
+}
+
+impl ExposurePolicyBuilder {
+    /// Adds the tier of `level` from the score `threshold` on. Tiers are added by increasing
+    /// threshold.
+    pub fn tier(mut self, threshold: u64, level: RiskLevel) -> Self {
+        self.tiers.push((threshold, level));
+        self
+    }
+
+    pub fn build(self) -> Result<ExposurePolicy, PolicyError> {
+        ExposurePolicy::try_from(self.tiers)
+    }
+}
+
+/// Weight of a confirmed encounter by its duration and the attenuation of its signal, as the
+/// receiver recorded them with
+/// [BroadcastPayload::receive](crate::advertisement::BroadcastPayload::receive) and summed up
+/// by [spirit_trace_risk](crate::spirit::spirit_trace_risk).
+pub trait RiskModel {
+    fn weight(&self, duration_secs: u16, attenuation_db: u8) -> u64;
+
+    /// Score from which a user is at risk.
+    fn threshold(&self) -> u64;
+}
+
+/// [RiskModel] of attenuation buckets, each with a weight per full minute of an encounter.
+/// Encounters shorter than `min_duration_secs` or attenuated beyond the last bucket weigh 0.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BucketRiskModel {
+    /// Pairs of the highest attenuation of a bucket and its weight, by increasing attenuation.
+    buckets: Vec<(u8, u64)>,
+    min_duration_secs: u16,
+    threshold: u64,
+}
+
+impl BucketRiskModel {
+    /// Model of the attenuation `buckets` as pairs of their highest attenuation and their weight
+    /// per minute, in any order.
+    pub fn new(mut buckets: Vec<(u8, u64)>, min_duration_secs: u16, threshold: u64) -> Self {
+        buckets.sort_unstable();
+        Self {
+            buckets,
+            min_duration_secs,
+            threshold,
+        }
+    }
+
+    pub fn buckets(&self) -> &[(u8, u64)] {
+        &self.buckets
+    }
+}
+
+/// Immediate (up to 55 dB), near (63 dB) and medium (73 dB) proximity weigh 4, 2 and 1 per
+/// minute of encounters of at least 5 minutes, and 60, e.g. 15 minutes in immediate proximity,
+/// put a user at risk.
+impl Default for BucketRiskModel {
+    fn default() -> Self {
+        Self::new(vec![(55, 4), (63, 2), (73, 1)], 5 * 60, 60)
+    }
+}
+
+impl RiskModel for BucketRiskModel {
+    fn weight(&self, duration_secs: u16, attenuation_db: u8) -> u64 {
+        if duration_secs < self.min_duration_secs {
+            return 0;
+        }
+        self.buckets
+            .iter()
+            .find(|(max_attenuation, _)| attenuation_db <= *max_attenuation)
+            .map_or(0, |(_, weight)| weight * u64::from(duration_secs / 60))
+    }
+
+    fn threshold(&self) -> u64 {
+        self.threshold
+    }
+}
+
+#[derive(Error, Debug, PartialEq, Eq, Clone)]
+pub enum PolicyError {
+    #[error("The exposure policy has no tiers.")]
+    Empty,
+    #[error("The threshold of tier {0} is not above the previous one, or 0.")]
+    NotIncreasing(usize),
+    #[error("Tier {0} is of risk level none.")]
+    NoRisk(usize),
+    #[error("Invalid risk level {0}.")]
+    InvalidLevel(u8),
+    #[error("Exposure policy encoding of {length} bytes is not a sequence of tiers.")]
+    InvalidLength { length: usize },
+    #[error("Invalid exposure policy encoding: {0}")]
+    InvalidEncoding(#[from] DecodeError),
+}
+
+#[cfg(test)]
+mod test {
+    use rand::rngs::OsRng;
+
+    use super::*;
+    use crate::{
+        elgamal::DecryptionKey,
+        spirit::{DomainConfig, Interval, UserKeys, spirit_broadcast_osrng, spirit_trace},
+    };
+
+    /// Encounter table of a user's broadcasts `0..num_entries` of epoch 0, and its ElIDs in
+    /// index order.
+    fn encounter_table(keys: &UserKeys, num_entries: usize) -> (EncounterTable, Vec<ElID>) {
+        let domain = DomainConfig::default();
+        let pk_ha = DecryptionKey::random(&mut OsRng).encryption_key();
+        let mut t_el = EncounterTable::new();
+        let el_ids = (0..num_entries)
+            .map(|i| {
+                spirit_broadcast_osrng(&domain, 0, Interval::from(i), keys, &pk_ha, &mut t_el).0
+            })
+            .collect();
+        (t_el, el_ids)
+    }
+
+    #[test]
+    fn counts_around_the_limit() {
+        const LIMIT: usize = 3;
+        let (t_el, own) = encounter_table(&UserKeys::random(OsRng), 6);
+        let (_, others) = encounter_table(&UserKeys::random(OsRng), 4);
+
+        for count in LIMIT - 1..=LIMIT + 1 {
+            // own ElIDs occur twice, which counts once
+            let cf: Vec<_> = own[..count]
+                .iter()
+                .chain(&own[..1])
+                .chain(&others)
+                .cloned()
+                .collect();
+            assert_eq!(spirit_trace(&cf, &t_el, LIMIT).count, count);
+            let cf_digest = confirmed_digest(&cf);
+            let table = commit_table(&t_el, &mut OsRng);
+            let root = table.root();
+
+            let proof = prove_exposure(&table, &cf, LIMIT, &mut OsRng);
+            assert_eq!(proof.is_some(), count >= LIMIT);
+            if let Some(proof) = proof {
+                assert!(verify_exposure(&proof, &cf_digest, &root, LIMIT));
+                assert!(!verify_exposure(&proof, &cf_digest, &root, LIMIT + 1));
+            }
+
+            // a proof of `count` entries is no proof of more
+            let proof = prove_exposure(&table, &cf, count, &mut OsRng).expect("proof failed");
+            assert!(verify_exposure(&proof, &cf_digest, &root, count));
+            assert!(!verify_exposure(&proof, &cf_digest, &root, count + 1));
+        }
+    }
+
+    #[test]
+    fn proofs_are_bound_to_the_confirmed_set() {
+        let (t_el, own) = encounter_table(&UserKeys::random(OsRng), 5);
+        let (_, others) = encounter_table(&UserKeys::random(OsRng), 3);
+        let cf: Vec<_> = own[..2].iter().chain(&others).cloned().collect();
+        let cf_digest = confirmed_digest(&cf);
+        assert_eq!(confirmed_digest(cf.iter().rev()), cf_digest);
+
+        let table = commit_table(&t_el, &mut OsRng);
+        let root = table.root();
+
+        let mut proof = prove_exposure(&table, &cf, 2, &mut OsRng).expect("proof failed");
+        assert!(verify_exposure(&proof, &cf_digest, &root, 2));
+        assert!(!verify_exposure(
+            &proof,
+            &confirmed_digest(&others),
+            &root,
+            2
+        ));
+        assert!(
+            proof
+                .entries
+                .iter()
+                .all(|entry| !own.contains(entry) && !others.contains(entry))
+        );
+
+        proof.entries.swap(0, 1);
+        assert!(!verify_exposure(&proof, &cf_digest, &root, 2));
+        proof.entries.swap(0, 1);
+        proof.responses[0][0] += Scalar::from(1u64);
+        assert!(!verify_exposure(&proof, &cf_digest, &root, 2));
+
+        assert!(prove_exposure(&table, &others, 1, &mut OsRng).is_none());
+        assert!(prove_exposure(&table, &[], 1, &mut OsRng).is_none());
+    }
+
+    #[test]
+    fn proofs_are_bound_to_the_committed_table() {
+        let (t_el, own) = encounter_table(&UserKeys::random(OsRng), 4);
+        let cf_digest = confirmed_digest(&own);
+        let table = commit_table(&t_el, &mut OsRng);
+        let proof = prove_exposure(&table, &own, 4, &mut OsRng).expect("proof failed");
+        assert!(verify_exposure(&proof, &cf_digest, &table.root(), 4));
+
+        // a commitment to the same table made after the confirmed set is published differs
+        let recommitted = commit_table(&t_el, &mut OsRng);
+        assert_eq!(recommitted.len(), table.len());
+        assert!(!verify_exposure(&proof, &cf_digest, &recommitted.root(), 4));
+    }
+
+    #[test]
+    fn repeated_encounters_count_once() {
+        let (t_el, own) = encounter_table(&UserKeys::random(OsRng), 1);
+        let (_, others) = encounter_table(&UserKeys::random(OsRng), 3);
+        let cf: Vec<_> = own.iter().chain(&others).cloned().collect();
+        let cf_digest = confirmed_digest(&cf);
+
+        // a table that commits to the one confirmed ElID three times
+        let committed = commit_table(&t_el, &mut OsRng);
+        let table = TableCommitment {
+            entries: committed.entries.repeat(3),
+            openings: committed.openings.repeat(3),
+        };
+        assert!(prove_exposure(&table, &cf, 2, &mut OsRng).is_none());
+        let proof = prove_exposure(&table, &cf, 1, &mut OsRng).expect("proof failed");
+        assert!(verify_exposure(&proof, &cf_digest, &table.root(), 1));
+        assert!(!verify_exposure(&proof, &cf_digest, &table.root(), 2));
+
+        // the confirmed set of the proof cannot repeat the ElID either
+        let mut repeated = proof;
+        repeated.cf.push(own[0].clone());
+        repeated.challenges.push(repeated.challenges[0].clone());
+        repeated.responses.push(repeated.responses[0].clone());
+        assert!(!verify_exposure(&repeated, &cf_digest, &table.root(), 2));
+    }
+
+    #[test]
+    fn policies_grade_scores_at_the_tier_boundaries() {
+        let policy = ExposurePolicy::builder()
+            .tier(1, RiskLevel::Low)
+            .tier(3, RiskLevel::Medium)
+            .tier(10, RiskLevel::High)
+            .build()
+            .expect("invalid policy");
+        let risks: Vec<_> = [0, 1, 2, 3, 9, 10, u64::MAX]
+            .into_iter()
+            .map(|score| policy.risk(score))
+            .collect();
+        assert_eq!(
+            risks,
+            [
+                RiskLevel::None,
+                RiskLevel::Low,
+                RiskLevel::Low,
+                RiskLevel::Medium,
+                RiskLevel::Medium,
+                RiskLevel::High,
+                RiskLevel::High
+            ]
+        );
+
+        let bytes = policy.to_bytes();
+        assert_eq!(ExposurePolicy::from_bytes(&bytes), Ok(policy));
+        assert_eq!(
+            ExposurePolicy::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(PolicyError::InvalidLength {
+                length: 3 * TIER_SIZE - 1
+            })
+        );
+        let mut invalid_level = bytes.clone();
+        *invalid_level.last_mut().unwrap() = 4;
+        assert_eq!(
+            ExposurePolicy::from_bytes(&invalid_level),
+            Err(PolicyError::InvalidLevel(4))
+        );
+    }
+
+    #[test]
+    fn policies_with_unordered_tiers_are_rejected() {
+        let build = |tiers: &[(u64, RiskLevel)]| {
+            tiers
+                .iter()
+                .fold(ExposurePolicy::builder(), |builder, (threshold, level)| {
+                    builder.tier(*threshold, *level)
+                })
+                .build()
+        };
+        assert_eq!(build(&[]), Err(PolicyError::Empty));
+        assert_eq!(
+            build(&[(0, RiskLevel::High)]),
+            Err(PolicyError::NotIncreasing(0))
+        );
+        assert_eq!(
+            build(&[(3, RiskLevel::Low), (3, RiskLevel::High)]),
+            Err(PolicyError::NotIncreasing(1))
+        );
+        assert_eq!(
+            build(&[
+                (3, RiskLevel::Low),
+                (5, RiskLevel::Medium),
+                (4, RiskLevel::High)
+            ]),
+            Err(PolicyError::NotIncreasing(2))
+        );
+        assert_eq!(
+            build(&[(1, RiskLevel::Low), (2, RiskLevel::None)]),
+            Err(PolicyError::NoRisk(1))
+        );
+
+        // decoding validates the tiers like the builder
+        let unordered = [(5u64, 2u8), (4, 3)]
+            .into_iter()
+            .flat_map(|(threshold, level)| threshold.to_le_bytes().into_iter().chain([level]))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            ExposurePolicy::from_bytes(&envelope::seal(ArtifactType::ExposurePolicy, &unordered)),
+            Err(PolicyError::NotIncreasing(1))
+        );
+    }
+}
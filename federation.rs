@@ -0,0 +1,306 @@
+//! Federation of deployments, so that the trace reports of travellers are verified in regions
+//! other than the one they registered in. A verifier keeps the public parameters and the
+//! registrar's key of every deployment it trusts in a [FederationRegistry], and checks the token
+//! of a report with a [MembershipProof] against a root signed by the home deployment's
+//! registrar.
+//!
+//! A deployment is identified by the `app_id` of its [DomainConfig], which every report carries
+//! as its [TraceReport::deployment]. All domain-separation tags are prefixed with the `app_id`,
+//! so the deployments of a federation never derive colliding ElIDs, nullifiers or proofs.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    atact::PublicParameters,
+    registry::{MembershipProof, RevocationList, SignedRoot},
+    spirit::{
+        Accepted, ChallengeSet, DomainConfig, ElID, Epoch, Nullifier, SpiritError, TraceReport,
+        VerifyRejection, check_hash_to_curve, check_membership, check_points, check_signature,
+        check_valid_at, verify_disclosures,
+    },
+    tsw::PublicKey,
+};
+
+/// Identifier of a deployment: the `app_id` of its [DomainConfig].
+pub type DeploymentId = Vec<u8>;
+
+/// A deployment as known to the verifiers of a federation.
+pub struct Deployment {
+    pub(crate) domain: DomainConfig,
+    pub(crate) pp: PublicParameters,
+    pub(crate) registrar: PublicKey,
+    /// Sequence number of the latest root of the registrar seen, see
+    /// [SignedRoot::verify_fresh].
+    pub(crate) latest_root_seq: u64,
+    pub(crate) revoked: RevocationList,
+}
+
+impl Deployment {
+    pub fn domain(&self) -> &DomainConfig {
+        &self.domain
+    }
+
+    pub fn public_parameters(&self) -> &PublicParameters {
+        &self.pp
+    }
+
+    pub fn registrar(&self) -> &PublicKey {
+        &self.registrar
+    }
+
+    pub fn revocations(&self) -> &RevocationList {
+        &self.revoked
+    }
+}
+
+/// The deployments whose reports a verifier accepts, by their [DeploymentId].
+#[derive(Default)]
+pub struct FederationRegistry {
+    deployments: HashMap<DeploymentId, Deployment>,
+}
+
+impl FederationRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the deployment of `domain` with the public parameters `pp` and the key of its
+    /// `registrar`, replacing an earlier deployment with the same `app_id`. Fails if the
+    /// parameters are invalid or do not match the domain's hash-to-curve suite.
+    pub fn insert(
+        &mut self,
+        domain: DomainConfig,
+        pp: PublicParameters,
+        registrar: PublicKey,
+    ) -> Result<(), SpiritError> {
+        pp.validate_cached()
+            .map_err(SpiritError::InvalidParameters)?;
+        check_hash_to_curve(&domain, &pp)?;
+        self.deployments.insert(
+            domain.app_id.clone(),
+            Deployment {
+                domain,
+                pp,
+                registrar,
+                latest_root_seq: 0,
+                revoked: RevocationList::new(),
+            },
+        );
+        Ok(())
+    }
+
+    pub fn get(&self, deployment: &[u8]) -> Option<&Deployment> {
+        self.deployments.get(deployment)
+    }
+
+    pub fn len(&self) -> usize {
+        self.deployments.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.deployments.is_empty()
+    }
+
+    /// Replaces the revocation list of `deployment`, e.g. after syncing it from the
+    /// deployment's registrar.
+    pub fn set_revocations(
+        &mut self,
+        deployment: &[u8],
+        revoked: RevocationList,
+    ) -> Result<(), SpiritError> {
+        self.get_mut(deployment)?.revoked = revoked;
+        Ok(())
+    }
+
+    fn get_mut(&mut self, deployment: &[u8]) -> Result<&mut Deployment, SpiritError> {
+        self.deployments
+            .get_mut(deployment)
+            .ok_or_else(|| SpiritError::UnknownDeployment(deployment.to_vec()))
+    }
+}
+
+/// Like [spirit_verify_with_proof](crate::spirit::spirit_verify_with_proof), but with the
+/// domain, the public parameters, the registrar's key and the revocation list of the report's
+/// [deployment](TraceReport::deployment) in `federation`. Besides its membership in the
+/// registrar's root, the token's signature is checked against the deployment's parameters.
+/// `root` and `membership` come from the home deployment's registrar, while the report's
+/// challenge is one of the verifier's `challenges`.
+///
+/// Rejects the report with [VerifyRejection::UnknownDeployment] if its deployment is not in
+/// `federation`.
+#[allow(clippy::too_many_arguments)]
+pub fn spirit_verify_federated(
+    federation: &mut FederationRegistry,
+    tr: &TraceReport,
+    root: &SignedRoot,
+    membership: &MembershipProof,
+    current_epoch: Epoch,
+    nullifiers: &mut HashSet<Nullifier>,
+    challenges: &ChallengeSet,
+    cp: &mut HashSet<ElID>,
+) -> Result<Accepted, VerifyRejection> {
+    let Some(deployment) = federation.deployments.get_mut(&tr.deployment) else {
+        return Err(VerifyRejection::UnknownDeployment {
+            token: tr.token.id(),
+            deployment: tr.deployment.clone(),
+        });
+    };
+    let registered = check_points(tr)
+        .and_then(|()| check_valid_at(&tr.token, &deployment.revoked, current_epoch))
+        .and_then(|()| {
+            check_membership(
+                tr,
+                root,
+                &deployment.registrar,
+                &mut deployment.latest_root_seq,
+                membership,
+            )
+        })
+        .and_then(|()| check_signature(tr, &deployment.pp));
+    verify_disclosures(
+        &deployment.domain,
+        tr,
+        registered,
+        current_epoch,
+        nullifiers,
+        challenges,
+        cp,
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use rand::rngs::OsRng;
+
+    use super::*;
+    use crate::{
+        registry::{MerkleRegistry, RegistrarKeypair},
+        spirit::{
+            Interval, IssuerHandle, SpiritSystem, UserSecret, prf_broadcast,
+            spirit_client_parameters, spirit_diagnosis_osrng,
+        },
+    };
+
+    const T: usize = 4;
+    const N: usize = 8;
+    const NUM_ISSUERS: usize = 8;
+    const NOW: Epoch = 10;
+    const EXPIRY: Epoch = 100;
+
+    fn deployment(app_id: &[u8]) -> (SpiritSystem<MerkleRegistry>, IssuerHandle) {
+        SpiritSystem::with_registry(
+            T,
+            N,
+            NUM_ISSUERS,
+            DomainConfig::new(app_id.to_vec()),
+            MerkleRegistry::new(RegistrarKeypair::random(&mut OsRng)),
+        )
+        .expect("setup failed")
+    }
+
+    #[test]
+    fn reports_are_verified_under_their_home_deployment() {
+        let (mut region_a, issuers) = deployment(b"region-a");
+        let (mut region_b, _) = deployment(b"region-b");
+        let mut federation = FederationRegistry::new();
+        for region in [&region_a, &region_b] {
+            federation
+                .insert(
+                    region.domain().clone(),
+                    spirit_client_parameters(
+                        region.domain(),
+                        region.public_parameters().pk.clone(),
+                        N,
+                        T,
+                    )
+                    .unwrap(),
+                    region.registry().verifying_key(),
+                )
+                .expect("invalid deployment");
+        }
+        assert_eq!(federation.len(), 2);
+
+        // a traveller registered in region A, whose ElIDs differ from the ones of region B
+        let user = UserSecret::random(OsRng);
+        let ppu = region_a
+            .register(&user, &issuers, EXPIRY)
+            .expect("registration failed");
+        let (domain_a, domain_b) = (region_a.domain(), region_b.domain());
+        let k = user.keys().epoch_key(0);
+        assert_ne!(
+            prf_broadcast(domain_a, &k, 0, Interval(2)),
+            prf_broadcast(domain_b, &k, 0, Interval(2))
+        );
+
+        // reports to a verifier in region B
+        let cp = HashSet::from([(0, Interval(2))]);
+        let report = |region_b: &mut SpiritSystem<MerkleRegistry>| {
+            let challenge = region_b.issue_challenge(NOW);
+            spirit_diagnosis_osrng(domain_a, &ppu, user.keys(), &cp, NOW, &challenge, &[])
+                .expect("diagnosis failed")
+        };
+        let honest = report(&mut region_b);
+        assert_eq!(honest.deployment, b"region-a");
+        let mut unknown = report(&mut region_b);
+        unknown.deployment = b"region-c".to_vec();
+        let mut relabelled = report(&mut region_b);
+        relabelled.deployment = b"region-b".to_vec();
+
+        let root = region_a.registry().signed_root(NOW);
+        let membership = region_a.registry().prove(&ppu.0).unwrap();
+        let verify = |federation: &mut FederationRegistry, tr: &TraceReport| {
+            spirit_verify_federated(
+                federation,
+                tr,
+                &root,
+                &membership,
+                NOW,
+                &mut HashSet::new(),
+                region_b.challenges(),
+                &mut HashSet::new(),
+            )
+        };
+        let token = ppu.0.id();
+        assert_eq!(
+            verify(&mut federation, &unknown),
+            Err(VerifyRejection::UnknownDeployment {
+                token,
+                deployment: b"region-c".to_vec()
+            })
+        );
+        // region A's root is not signed by the registrar of region B
+        assert_eq!(
+            verify(&mut federation, &relabelled),
+            Err(VerifyRejection::InvalidRoot { token })
+        );
+        assert_eq!(
+            verify(&mut federation, &honest),
+            Ok(Accepted {
+                verdicts: vec![true]
+            })
+        );
+
+        let mut revoked = RevocationList::new();
+        revoked.revoke(&ppu.0);
+        federation.set_revocations(b"region-a", revoked).unwrap();
+        assert_eq!(
+            verify(&mut federation, &honest),
+            Err(VerifyRejection::Revoked { token })
+        );
+
+        // region A with the issuers' key of region B does not accept the signatures of A
+        let mut misconfigured = FederationRegistry::new();
+        misconfigured
+            .insert(
+                domain_a.clone(),
+                spirit_client_parameters(domain_a, region_b.public_parameters().pk.clone(), N, T)
+                    .unwrap(),
+                region_a.registry().verifying_key(),
+            )
+            .expect("invalid deployment");
+        assert_eq!(
+            verify(&mut misconfigured, &honest),
+            Err(VerifyRejection::InvalidSignature { token })
+        );
+    }
+}
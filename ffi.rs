@@ -0,0 +1,689 @@
+use std::{
+    cell::RefCell,
+    collections::HashSet,
+    ffi::{CString, c_char},
+    panic::{self, AssertUnwindSafe},
+    ptr, slice,
+};
+
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize, SerializationError};
+use rand::rngs::OsRng;
+use thiserror::Error;
+
+use crate::{
+    DecodeError, Scalar,
+    atact::{self, BlindRequest, BlindToken, PublicParameters, StRG, TokenProof},
+    spirit::{
+        DomainConfig, Epoch, IdentityClaim, Interval, IssuerHandle, PendingRegistration,
+        ReportChallenge, SpiritError, SpiritSystem, Token, TraceReport, UserKeys, prf_broadcast,
+        spirit_client_parameters, spirit_diagnosis, spirit_register_begin, spirit_register_finish,
+    },
+    tsw::PublicKey,
+};
+
+/// Status code returned by every `spirit_ffi_*` function. On failure,
+/// [spirit_ffi_last_error_message] describes the error.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpiritFfiStatus {
+    Ok = 0,
+    NullPointer = 1,
+    InvalidEncoding = 2,
+    InvalidState = 3,
+    ProtocolError = 4,
+    Panic = 5,
+}
+
+#[derive(Error, Debug)]
+enum FfiError {
+    #[error("Null pointer argument.")]
+    NullPointer,
+    #[error("Invalid encoding: {0}")]
+    InvalidEncoding(#[from] SerializationError),
+    #[error("Invalid encoding: {0}")]
+    InvalidPoint(#[from] DecodeError),
+    #[error("The registration has already been finished.")]
+    RegistrationFinished,
+    #[error(transparent)]
+    Protocol(#[from] SpiritError),
+}
+
+impl FfiError {
+    fn status(&self) -> SpiritFfiStatus {
+        match self {
+            Self::NullPointer => SpiritFfiStatus::NullPointer,
+            Self::InvalidEncoding(_) | Self::InvalidPoint(_) => SpiritFfiStatus::InvalidEncoding,
+            Self::RegistrationFinished => SpiritFfiStatus::InvalidState,
+            Self::Protocol(_) => SpiritFfiStatus::ProtocolError,
+        }
+    }
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// Byte buffer allocated by the library. Every buffer written to an output argument has to be
+/// released with [spirit_ffi_buffer_free].
+#[repr(C)]
+pub struct SpiritBuffer {
+    pub data: *mut u8,
+    pub len: usize,
+}
+
+impl SpiritBuffer {
+    fn new(bytes: Vec<u8>) -> Self {
+        let len = bytes.len();
+        let data = Box::into_raw(bytes.into_boxed_slice()).cast();
+        Self { data, len }
+    }
+}
+
+/// User side of a deployment: its [DomainConfig] and tACT public parameters.
+pub struct SpiritFfiClient {
+    domain: DomainConfig,
+    pp: PublicParameters,
+}
+
+/// Registration started by [spirit_ffi_register_begin] and consumed by
+/// [spirit_ffi_register_finish].
+pub struct SpiritFfiRegistration {
+    pending: Option<PendingRegistration>,
+}
+
+/// Registrar side of a deployment together with the shares of its issuers, for registrars and
+/// tests embedded in the same process.
+pub struct SpiritFfiRegistrar {
+    system: SpiritSystem,
+    issuers: IssuerHandle,
+}
+
+fn encode(value: &impl CanonicalSerialize) -> SpiritBuffer {
+    let mut bytes = Vec::with_capacity(value.compressed_size());
+    value
+        .serialize_compressed(&mut bytes)
+        .expect("writing to a Vec does not fail");
+    SpiritBuffer::new(bytes)
+}
+
+fn decode<T: CanonicalDeserialize>(bytes: &[u8]) -> Result<T, FfiError> {
+    Ok(T::deserialize_compressed(bytes)?)
+}
+
+/// Reads the input buffer `data[..len]`. A null `data` is only accepted for `len == 0`.
+///
+/// # Safety
+///
+/// `data` has to be valid for reads of `len` bytes for the lifetime `'a`.
+unsafe fn input<'a>(data: *const u8, len: usize) -> Result<&'a [u8], FfiError> {
+    if len == 0 {
+        Ok(&[])
+    } else if data.is_null() {
+        Err(FfiError::NullPointer)
+    } else {
+        // SAFETY: guaranteed by the caller
+        Ok(unsafe { slice::from_raw_parts(data, len) })
+    }
+}
+
+/// # Safety
+///
+/// `handle` has to be null or point to a live `T` that is not mutated for the lifetime `'a`.
+unsafe fn handle<'a, T>(handle: *const T) -> Result<&'a T, FfiError> {
+    // SAFETY: guaranteed by the caller
+    unsafe { handle.as_ref() }.ok_or(FfiError::NullPointer)
+}
+
+/// # Safety
+///
+/// `handle` has to be null or point to a live `T` that is not aliased for the lifetime `'a`.
+unsafe fn handle_mut<'a, T>(handle: *mut T) -> Result<&'a mut T, FfiError> {
+    // SAFETY: guaranteed by the caller
+    unsafe { handle.as_mut() }.ok_or(FfiError::NullPointer)
+}
+
+/// Writes `value` to the output argument `out`.
+///
+/// # Safety
+///
+/// `out` has to be null or valid for writes.
+unsafe fn output<T>(out: *mut T, value: T) -> Result<(), FfiError> {
+    if out.is_null() {
+        return Err(FfiError::NullPointer);
+    }
+    // SAFETY: guaranteed by the caller
+    unsafe { out.write(value) };
+    Ok(())
+}
+
+/// Runs `f`, records its error or panic for [spirit_ffi_last_error_message] and converts the
+/// outcome to a status code. Panics do not unwind into the caller.
+fn ffi_call(f: impl FnOnce() -> Result<(), FfiError>) -> SpiritFfiStatus {
+    let (status, message) = match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(())) => (SpiritFfiStatus::Ok, None),
+        Ok(Err(err)) => (err.status(), Some(err.to_string())),
+        Err(_) => (SpiritFfiStatus::Panic, Some("Internal error.".to_owned())),
+    };
+    LAST_ERROR.set(message.map(|message| CString::new(message).unwrap_or_default()));
+    status
+}
+
+/// Message of the error of the last failed `spirit_ffi_*` call on this thread, or null if the
+/// last call succeeded. The string is owned by the library and valid until the next
+/// `spirit_ffi_*` call on this thread.
+#[unsafe(no_mangle)]
+pub extern "C" fn spirit_ffi_last_error_message() -> *const c_char {
+    LAST_ERROR.with_borrow(|message| message.as_ref().map_or(ptr::null(), |m| m.as_ptr()))
+}
+
+/// Releases a buffer returned by the library. Releasing a buffer with null `data` is a no-op.
+///
+/// # Safety
+///
+/// `buffer` has to be returned by the library and not released before.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn spirit_ffi_buffer_free(buffer: SpiritBuffer) {
+    if !buffer.data.is_null() {
+        // SAFETY: the buffer was created from a boxed slice of length `len`
+        drop(unsafe { Box::from_raw(ptr::slice_from_raw_parts_mut(buffer.data, buffer.len)) });
+    }
+}
+
+/// Creates the user side of the deployment `app_id` whose issuers share the tACT public key
+/// `pk`, as given by [PublicKey::to_bytes], with the parameters `n` and `t` of
+/// [spirit_setup](crate::spirit::spirit_setup).
+///
+/// # Safety
+///
+/// The input buffers have to be valid for reads of their lengths and `out_client` valid for
+/// writes. The client has to be released with [spirit_ffi_client_free].
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn spirit_ffi_client_new(
+    app_id: *const u8,
+    app_id_len: usize,
+    pk: *const u8,
+    pk_len: usize,
+    n: usize,
+    t: usize,
+    out_client: *mut *mut SpiritFfiClient,
+) -> SpiritFfiStatus {
+    ffi_call(|| {
+        if out_client.is_null() {
+            return Err(FfiError::NullPointer);
+        }
+        let domain = DomainConfig::new(unsafe { input(app_id, app_id_len) }?.to_vec());
+        let pk = PublicKey::from_bytes(unsafe { input(pk, pk_len) }?)?;
+        let pp = spirit_client_parameters(&domain, pk, n, t)?;
+
+        let client = Box::into_raw(Box::new(SpiritFfiClient { domain, pp }));
+        unsafe { output(out_client, client) }
+    })
+}
+
+/// # Safety
+///
+/// `client` has to be null or returned by [spirit_ffi_client_new] and not released before.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn spirit_ffi_client_free(client: *mut SpiritFfiClient) {
+    if !client.is_null() {
+        // SAFETY: the client was created by Box::into_raw
+        drop(unsafe { Box::from_raw(client) });
+    }
+}
+
+/// Starts the registration of the user with the encoded identity scalar `id` for a token that
+/// expires at the start of epoch `expiry`, see [spirit_register_begin]. Writes the encoded blind
+/// request for the issuers, together with its [IdentityClaim], to `out_blind_request`.
+///
+/// # Safety
+///
+/// `client` has to be a live client, the input buffers valid for reads of their lengths and the
+/// output arguments valid for writes. The registration has to be released with
+/// [spirit_ffi_registration_free].
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn spirit_ffi_register_begin(
+    client: *const SpiritFfiClient,
+    id: *const u8,
+    id_len: usize,
+    expiry: Epoch,
+    out_registration: *mut *mut SpiritFfiRegistration,
+    out_blind_request: *mut SpiritBuffer,
+) -> SpiritFfiStatus {
+    ffi_call(|| {
+        let client = unsafe { handle(client) }?;
+        let id_u: Scalar = decode(unsafe { input(id, id_len) }?)?;
+        if out_registration.is_null() || out_blind_request.is_null() {
+            return Err(FfiError::NullPointer);
+        }
+
+        let (blind_request, pending) =
+            spirit_register_begin(&client.domain, &id_u, &client.pp, expiry, &mut OsRng)?;
+        let claim = pending.identity_claim(&client.domain, &mut OsRng);
+        let registration = SpiritFfiRegistration {
+            pending: Some(pending),
+        };
+        unsafe { output(out_registration, Box::into_raw(Box::new(registration))) }?;
+        unsafe { output(out_blind_request, encode(&(blind_request, claim))) }
+    })
+}
+
+/// # Safety
+///
+/// `registration` has to be null or returned by [spirit_ffi_register_begin] and not released
+/// before.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn spirit_ffi_registration_free(registration: *mut SpiritFfiRegistration) {
+    if !registration.is_null() {
+        // SAFETY: the registration was created by Box::into_raw
+        drop(unsafe { Box::from_raw(registration) });
+    }
+}
+
+/// Unblinds the encoded blind tokens of the first `t` issuers, ordered by issuer, into the token
+/// requested with [spirit_ffi_register_begin], see [spirit_register_finish]. Writes the encoded
+/// `(Token, StRG)` pair the user keeps to `out_ppu`, and the encoded unblinded tACT token and
+/// its proof for the registrar to `out_registration`. A registration can only be finished once.
+///
+/// # Safety
+///
+/// `client` and `registration` have to be live handles, the input buffer valid for reads of its
+/// length and the output arguments valid for writes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn spirit_ffi_register_finish(
+    client: *const SpiritFfiClient,
+    registration: *mut SpiritFfiRegistration,
+    blind_tokens: *const u8,
+    blind_tokens_len: usize,
+    out_ppu: *mut SpiritBuffer,
+    out_registration: *mut SpiritBuffer,
+) -> SpiritFfiStatus {
+    ffi_call(|| {
+        let client = unsafe { handle(client) }?;
+        let registration = unsafe { handle_mut(registration) }?;
+        let blind_tokens: Vec<Vec<BlindToken>> =
+            decode(unsafe { input(blind_tokens, blind_tokens_len) }?)?;
+        if out_ppu.is_null() || out_registration.is_null() {
+            return Err(FfiError::NullPointer);
+        }
+        let pending = registration
+            .pending
+            .take()
+            .ok_or(FfiError::RegistrationFinished)?;
+
+        let (ppu, token, token_proof) = spirit_register_finish(
+            &client.domain,
+            pending,
+            &blind_tokens,
+            &client.pp,
+            &mut OsRng,
+        )?;
+        unsafe { output(out_ppu, encode(&ppu)) }?;
+        unsafe { output(out_registration, encode(&(token, token_proof))) }
+    })
+}
+
+/// Writes the encoded ElID of interval `i` of `epoch` for the encoded key `prv` to `out_el_id`.
+///
+/// # Safety
+///
+/// `client` has to be a live client, the input buffer valid for reads of its length and
+/// `out_el_id` valid for writes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn spirit_ffi_broadcast(
+    client: *const SpiritFfiClient,
+    prv: *const u8,
+    prv_len: usize,
+    epoch: Epoch,
+    i: u64,
+    out_el_id: *mut SpiritBuffer,
+) -> SpiritFfiStatus {
+    ffi_call(|| {
+        let client = unsafe { handle(client) }?;
+        let keys = UserKeys::new(decode(unsafe { input(prv, prv_len) }?)?);
+
+        let el_id = prf_broadcast(&client.domain, &keys.epoch_key(epoch), epoch, Interval(i));
+        unsafe { output(out_el_id, SpiritBuffer::new(el_id.to_bytes().to_vec())) }
+    })
+}
+
+/// Discloses the ElIDs of the `indices` of `epoch` for the encoded key `prv` and the encoded
+/// `ppu` of [spirit_ffi_register_finish] in a report submitted in `report_epoch` for the
+/// encoded `challenge` of [spirit_ffi_registrar_challenge], and writes the encoded trace report
+/// to `out_report`.
+///
+/// # Safety
+///
+/// `client` has to be a live client, the input buffers valid for reads of their lengths,
+/// `indices` valid for reads of `indices_len` elements and `out_report` valid for writes.
+#[unsafe(no_mangle)]
+#[allow(clippy::too_many_arguments)]
+pub unsafe extern "C" fn spirit_ffi_diagnose(
+    client: *const SpiritFfiClient,
+    ppu: *const u8,
+    ppu_len: usize,
+    prv: *const u8,
+    prv_len: usize,
+    epoch: Epoch,
+    indices: *const u64,
+    indices_len: usize,
+    report_epoch: Epoch,
+    challenge: *const u8,
+    challenge_len: usize,
+    out_report: *mut SpiritBuffer,
+) -> SpiritFfiStatus {
+    ffi_call(|| {
+        let client = unsafe { handle(client) }?;
+        let ppu: (Token, StRG) = decode(unsafe { input(ppu, ppu_len) }?)?;
+        let keys = UserKeys::new(decode(unsafe { input(prv, prv_len) }?)?);
+        let challenge: ReportChallenge = decode(unsafe { input(challenge, challenge_len) }?)?;
+        let indices: &[u64] = if indices_len == 0 {
+            &[]
+        } else if indices.is_null() {
+            return Err(FfiError::NullPointer);
+        } else {
+            unsafe { slice::from_raw_parts(indices, indices_len) }
+        };
+        let cp = indices
+            .iter()
+            .map(|index| (epoch, Interval(*index)))
+            .collect();
+
+        let report = spirit_diagnosis(
+            &client.domain,
+            &ppu,
+            &keys,
+            None,
+            &cp,
+            report_epoch,
+            &challenge,
+            &[],
+            &mut OsRng,
+        )?;
+        unsafe { output(out_report, encode(&report)) }
+    })
+}
+
+/// Sets up a registrar for the deployment `app_id` with in-process issuers, see
+/// [SpiritSystem::setup_with_domain].
+///
+/// # Safety
+///
+/// `app_id` has to be valid for reads of `app_id_len` bytes and `out_registrar` valid for
+/// writes. The registrar has to be released with [spirit_ffi_registrar_free].
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn spirit_ffi_registrar_new(
+    app_id: *const u8,
+    app_id_len: usize,
+    t: usize,
+    n: usize,
+    num_issuers: usize,
+    out_registrar: *mut *mut SpiritFfiRegistrar,
+) -> SpiritFfiStatus {
+    ffi_call(|| {
+        if out_registrar.is_null() {
+            return Err(FfiError::NullPointer);
+        }
+        let domain = DomainConfig::new(unsafe { input(app_id, app_id_len) }?.to_vec());
+        let (system, issuers) = SpiritSystem::setup_with_domain(t, n, num_issuers, domain)?;
+
+        let registrar = Box::into_raw(Box::new(SpiritFfiRegistrar { system, issuers }));
+        unsafe { output(out_registrar, registrar) }
+    })
+}
+
+/// # Safety
+///
+/// `registrar` has to be null or returned by [spirit_ffi_registrar_new] and not released before.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn spirit_ffi_registrar_free(registrar: *mut SpiritFfiRegistrar) {
+    if !registrar.is_null() {
+        // SAFETY: the registrar was created by Box::into_raw
+        drop(unsafe { Box::from_raw(registrar) });
+    }
+}
+
+/// Writes the issuers' public key, the `pk` of [spirit_ffi_client_new], to `out_pk`.
+///
+/// # Safety
+///
+/// `registrar` has to be a live registrar and `out_pk` valid for writes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn spirit_ffi_registrar_public_key(
+    registrar: *const SpiritFfiRegistrar,
+    out_pk: *mut SpiritBuffer,
+) -> SpiritFfiStatus {
+    ffi_call(|| {
+        let registrar = unsafe { handle(registrar) }?;
+        let pk = registrar.system.public_parameters().pk.to_bytes();
+        unsafe { output(out_pk, SpiritBuffer::new(pk.to_vec())) }
+    })
+}
+
+/// Issues blind tokens for the encoded blind request and identity claim of
+/// [spirit_ffi_register_begin] and writes them to `out_blind_tokens`, see [SpiritSystem::issue].
+///
+/// # Safety
+///
+/// `registrar` has to be a live registrar, the input buffer valid for reads of its length and
+/// `out_blind_tokens` valid for writes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn spirit_ffi_registrar_issue(
+    registrar: *const SpiritFfiRegistrar,
+    blind_request: *const u8,
+    blind_request_len: usize,
+    out_blind_tokens: *mut SpiritBuffer,
+) -> SpiritFfiStatus {
+    ffi_call(|| {
+        let registrar = unsafe { handle(registrar) }?;
+        let (blind_request, claim): (BlindRequest, IdentityClaim) =
+            decode(unsafe { input(blind_request, blind_request_len) }?)?;
+
+        let blind_tokens = registrar
+            .system
+            .issue(&registrar.issuers, &blind_request, &claim)?;
+        unsafe { output(out_blind_tokens, encode(&blind_tokens)) }
+    })
+}
+
+/// Checks the encoded `registration` of [spirit_ffi_register_finish] against the user's blind
+/// request and identity claim, and registers the token that expires at the start of epoch
+/// `expiry`, see [SpiritSystem::complete_registration]. Writes the encoded token to `out_token`.
+///
+/// # Safety
+///
+/// `registrar` has to be a live registrar, the input buffers valid for reads of their lengths
+/// and `out_token` valid for writes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn spirit_ffi_registrar_complete(
+    registrar: *mut SpiritFfiRegistrar,
+    blind_request: *const u8,
+    blind_request_len: usize,
+    registration: *const u8,
+    registration_len: usize,
+    expiry: Epoch,
+    out_token: *mut SpiritBuffer,
+) -> SpiritFfiStatus {
+    ffi_call(|| {
+        let registrar = unsafe { handle_mut(registrar) }?;
+        let (blind_request, claim): (BlindRequest, IdentityClaim) =
+            decode(unsafe { input(blind_request, blind_request_len) }?)?;
+        let (token, token_proof): (atact::Token, TokenProof) =
+            decode(unsafe { input(registration, registration_len) }?)?;
+        if out_token.is_null() {
+            return Err(FfiError::NullPointer);
+        }
+
+        let token = registrar.system.complete_registration(
+            &blind_request,
+            &claim,
+            &token,
+            &token_proof,
+            expiry,
+        )?;
+        unsafe { output(out_token, SpiritBuffer::new(token.to_bytes().to_vec())) }
+    })
+}
+
+/// Issues a challenge for a user's trace report in `current_epoch` and writes its encoding to
+/// `out_challenge`, see [SpiritSystem::issue_challenge].
+///
+/// # Safety
+///
+/// `registrar` has to be a live registrar and `out_challenge` valid for writes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn spirit_ffi_registrar_challenge(
+    registrar: *mut SpiritFfiRegistrar,
+    current_epoch: Epoch,
+    out_challenge: *mut SpiritBuffer,
+) -> SpiritFfiStatus {
+    ffi_call(|| {
+        let registrar = unsafe { handle_mut(registrar) }?;
+        if out_challenge.is_null() {
+            return Err(FfiError::NullPointer);
+        }
+
+        let challenge = registrar.system.issue_challenge(current_epoch);
+        unsafe { output(out_challenge, encode(&challenge)) }
+    })
+}
+
+/// Verifies the encoded trace report of [spirit_ffi_diagnose] at `current_epoch`, see
+/// [SpiritSystem::verify_report]. Writes one byte per disclosure to `out_verdicts`, 1 if it is
+/// accepted and 0 otherwise, so all bytes are 0 for a rejected report. The registrar accepts a
+/// report only once.
+///
+/// # Safety
+///
+/// `registrar` has to be a live registrar, the input buffer valid for reads of its length and
+/// `out_verdicts` valid for writes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn spirit_ffi_verify(
+    registrar: *mut SpiritFfiRegistrar,
+    report: *const u8,
+    report_len: usize,
+    current_epoch: Epoch,
+    out_verdicts: *mut SpiritBuffer,
+) -> SpiritFfiStatus {
+    ffi_call(|| {
+        let registrar = unsafe { handle_mut(registrar) }?;
+        let report: TraceReport = decode(unsafe { input(report, report_len) }?)?;
+
+        let verdicts =
+            match registrar
+                .system
+                .verify_report(&report, current_epoch, &mut HashSet::new())
+            {
+                Ok(accepted) => accepted.verdicts,
+                Err(_) => vec![false; report.disclosures.len()],
+            };
+        let verdicts = verdicts.into_iter().map(u8::from).collect();
+        unsafe { output(out_verdicts, SpiritBuffer::new(verdicts)) }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::ffi::{CStr, c_int};
+
+    use super::*;
+
+    unsafe extern "C" {
+        /// Registration round trip of `tests/ffi/round_trip.c`, compiled by the build script.
+        /// Returns 0 on success and the failing line otherwise.
+        fn spirit_ffi_test_round_trip() -> c_int;
+    }
+
+    #[test]
+    fn c_round_trip() {
+        assert_eq!(unsafe { spirit_ffi_test_round_trip() }, 0);
+    }
+
+    #[test]
+    fn errors_are_reported() {
+        let mut client = ptr::null_mut();
+        let status = unsafe {
+            spirit_ffi_client_new(ptr::null(), 0, [0u8; 3].as_ptr(), 3, 4, 3, &mut client)
+        };
+        assert_eq!(status, SpiritFfiStatus::InvalidEncoding);
+        assert!(client.is_null());
+        assert!(!spirit_ffi_last_error_message().is_null());
+
+        let mut pk = SpiritBuffer {
+            data: ptr::null_mut(),
+            len: 0,
+        };
+        let status = unsafe { spirit_ffi_registrar_public_key(ptr::null(), &mut pk) };
+        assert_eq!(status, SpiritFfiStatus::NullPointer);
+        let message = unsafe { CStr::from_ptr(spirit_ffi_last_error_message()) };
+        assert_eq!(message.to_str(), Ok("Null pointer argument."));
+    }
+
+    #[test]
+    fn registration_finishes_once() {
+        let mut registrar = ptr::null_mut();
+        let mut client = ptr::null_mut();
+        let mut registration = ptr::null_mut();
+        let empty = || SpiritBuffer {
+            data: ptr::null_mut(),
+            len: 0,
+        };
+        let (mut pk, mut blind_request, mut blind_tokens) = (empty(), empty(), empty());
+        let (mut ppu, mut registration_bytes) = (empty(), empty());
+        let id = encode(&Scalar::from(1u64));
+
+        unsafe {
+            assert_eq!(
+                spirit_ffi_registrar_new(ptr::null(), 0, 3, 4, 4, &mut registrar),
+                SpiritFfiStatus::Ok
+            );
+            assert_eq!(
+                spirit_ffi_registrar_public_key(registrar, &mut pk),
+                SpiritFfiStatus::Ok
+            );
+            assert_eq!(
+                spirit_ffi_client_new(ptr::null(), 0, pk.data, pk.len, 4, 3, &mut client),
+                SpiritFfiStatus::Ok
+            );
+            assert_eq!(
+                spirit_ffi_register_begin(
+                    client,
+                    id.data,
+                    id.len,
+                    100,
+                    &mut registration,
+                    &mut blind_request
+                ),
+                SpiritFfiStatus::Ok
+            );
+            assert_eq!(
+                spirit_ffi_registrar_issue(
+                    registrar,
+                    blind_request.data,
+                    blind_request.len,
+                    &mut blind_tokens
+                ),
+                SpiritFfiStatus::Ok
+            );
+            for expected in [SpiritFfiStatus::Ok, SpiritFfiStatus::InvalidState] {
+                assert_eq!(
+                    spirit_ffi_register_finish(
+                        client,
+                        registration,
+                        blind_tokens.data,
+                        blind_tokens.len,
+                        &mut ppu,
+                        &mut registration_bytes
+                    ),
+                    expected
+                );
+            }
+
+            for buffer in [id, pk, blind_request, blind_tokens, ppu, registration_bytes] {
+                spirit_ffi_buffer_free(buffer);
+            }
+            spirit_ffi_registration_free(registration);
+            spirit_ffi_client_free(client);
+            spirit_ffi_registrar_free(registrar);
+        }
+    }
+}
@@ -0,0 +1,177 @@
+use alloc::vec::Vec;
+
+use ark_ff::Field;
+use ark_serialize::CanonicalSerialize;
+use rand::{RngCore, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+use sha2::{Digest, Sha256};
+
+use crate::bls381_helpers::{G1G2, Scalar};
+
+const OP_APPEND: u8 = 0;
+const OP_CHALLENGE: u8 = 1;
+
+/// Fiat-Shamir transcript in the style of merlin: every public input of a proof is appended
+/// under a label, and challenges are derived from everything appended before them.
+///
+/// Every operation is framed with its type, its label and the length of its message, so
+/// transcripts that differ in any appended element, its label or the order of the appends
+/// yield unrelated challenges. Each challenge is appended to the transcript as well, so later
+/// challenges depend on the earlier ones.
+#[derive(Clone)]
+pub(crate) struct ProofTranscript {
+    hasher: Sha256,
+}
+
+impl ProofTranscript {
+    /// Starts the transcript of the protocol `protocol`.
+    pub(crate) fn new(protocol: &'static [u8]) -> Self {
+        let mut transcript = Self {
+            hasher: Sha256::new(),
+        };
+        transcript.append_message(b"protocol", protocol);
+        transcript
+    }
+
+    fn frame(&mut self, op: u8, label: &'static [u8], message: &[u8]) {
+        self.hasher.update([op]);
+        // labels are short constants
+        self.hasher.update((label.len() as u32).to_le_bytes());
+        self.hasher.update(label);
+        self.hasher.update((message.len() as u64).to_le_bytes());
+        self.hasher.update(message);
+    }
+
+    pub(crate) fn append_message(&mut self, label: &'static [u8], message: &[u8]) {
+        self.frame(OP_APPEND, label, message);
+    }
+
+    pub(crate) fn append_u64(&mut self, label: &'static [u8], value: u64) {
+        self.append_message(label, &value.to_le_bytes());
+    }
+
+    /// Appends the uncompressed encoding of `point`.
+    pub(crate) fn append_point(&mut self, label: &'static [u8], point: &G1G2) {
+        let mut bytes = Vec::with_capacity(point.uncompressed_size());
+        point.serialize_uncompressed(&mut bytes).unwrap();
+        self.append_message(label, &bytes);
+    }
+
+    pub(crate) fn append_scalar(&mut self, label: &'static [u8], scalar: &Scalar) {
+        let mut bytes = Vec::with_capacity(scalar.compressed_size());
+        scalar.serialize_compressed(&mut bytes).unwrap();
+        self.append_message(label, &bytes);
+    }
+
+    /// Appends the points of `points` under `label`, preceded by their number.
+    pub(crate) fn append_points<'a>(
+        &mut self,
+        label: &'static [u8],
+        points: impl ExactSizeIterator<Item = &'a G1G2>,
+    ) {
+        self.append_u64(label, points.len() as u64);
+        for point in points {
+            self.append_point(label, point);
+        }
+    }
+
+    /// RNG seeded with the challenge `label` of the transcript, for challenges that are not a
+    /// single scalar.
+    pub(crate) fn challenge_rng(&mut self, label: &'static [u8]) -> ChaCha20Rng {
+        self.frame(OP_CHALLENGE, label, &[]);
+        let seed: [u8; 32] = self.hasher.clone().finalize().into();
+        self.append_message(b"challenge", &seed);
+        ChaCha20Rng::from_seed(seed)
+    }
+
+    /// Uniform scalar challenge `label`, sampled by rejection from the [challenge
+    /// RNG](Self::challenge_rng).
+    pub(crate) fn challenge_scalar(&mut self, label: &'static [u8]) -> Scalar {
+        let mut rng = self.challenge_rng(label);
+        loop {
+            let mut bytes = [0u8; 32];
+            rng.fill_bytes(&mut bytes);
+            if let Some(scalar) = Scalar::from_random_bytes(&bytes) {
+                return scalar;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashSet;
+
+    use super::*;
+    use crate::bls381_helpers::test::generator_multiple;
+
+    const LABELS: [&[u8]; 4] = [b"a", b"b", b"c", b"d"];
+
+    /// Challenge of a transcript with the points `k * (g1, g2)` for the `(label, k)` of
+    /// `appends`, in order.
+    fn challenge(appends: &[(&'static [u8], u64)]) -> Scalar {
+        let mut transcript = ProofTranscript::new(b"test");
+        for (label, k) in appends {
+            transcript.append_point(*label, &generator_multiple(*k));
+        }
+        transcript.challenge_scalar(b"c")
+    }
+
+    #[test]
+    fn omitted_or_reordered_elements_change_the_challenge() {
+        let appends: Vec<_> = LABELS.into_iter().zip(1..).collect();
+        let mut challenges = HashSet::from([challenge(&appends)]);
+
+        for omitted in 0..appends.len() {
+            let mut omitting = appends.clone();
+            omitting.remove(omitted);
+            assert!(challenges.insert(challenge(&omitting)));
+        }
+        for i in 0..appends.len() {
+            for j in i + 1..appends.len() {
+                let mut reordered = appends.clone();
+                reordered.swap(i, j);
+                assert!(challenges.insert(challenge(&reordered)));
+
+                // the points alone in another order
+                let mut relabelled = appends.clone();
+                relabelled[i].1 = appends[j].1;
+                relabelled[j].1 = appends[i].1;
+                assert!(challenges.insert(challenge(&relabelled)));
+            }
+        }
+        assert_eq!(challenge(&appends), challenge(&appends));
+    }
+
+    #[test]
+    fn framing_separates_messages() {
+        let challenge = |protocol: &'static str, messages: &[(&'static str, &str)]| {
+            let mut transcript = ProofTranscript::new(protocol.as_bytes());
+            for (label, message) in messages {
+                transcript.append_message(label.as_bytes(), message.as_bytes());
+            }
+            transcript.challenge_scalar(b"c")
+        };
+
+        let challenges = [
+            challenge("test", &[("a", "xy")]),
+            challenge("test", &[("a", "x"), ("a", "y")]),
+            challenge("test", &[("a", "xy"), ("a", "")]),
+            challenge("test", &[("ax", "y")]),
+            challenge("other", &[("a", "xy")]),
+        ];
+        assert_eq!(
+            challenges.iter().collect::<HashSet<_>>().len(),
+            challenges.len()
+        );
+    }
+
+    #[test]
+    fn challenges_are_chained() {
+        let mut transcript = ProofTranscript::new(b"test");
+        let first = transcript.challenge_scalar(b"c");
+        let second = transcript.challenge_scalar(b"c");
+        assert_ne!(first, second);
+        assert_eq!(ProofTranscript::new(b"test").challenge_scalar(b"c"), first);
+    }
+}
@@ -0,0 +1,437 @@
+use std::{collections::HashSet, sync::OnceLock};
+
+use ark_ec::AffineRepr;
+use ark_ff::PrimeField;
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
+use rand::{SeedableRng, rngs::OsRng};
+use rand_chacha::ChaCha20Rng;
+
+use crate::{
+    G1G2, Scalar,
+    atact::{BlindRequest, PublicParameters, Token as tACTToken, TokenProof, tissue, verify},
+    bls381_helpers::{G1Affine, G2Affine},
+    pedersen::{Commitment, Proof, Proof2PK},
+    registry::RevocationList,
+    spirit::{
+        ChallengeSet, DeviceId, DiagnosisAuthorization, Disclosure, DomainConfig, Epoch, Interval,
+        NullifierProof, PrfProof, RegistrarId, ReportChallenge, SetupOutput, ShowTag, Token,
+        TraceReport, UserKeys, spirit_complete_registration, spirit_diagnosis,
+        spirit_register_begin, spirit_register_finish, spirit_setup, spirit_verify,
+        spirit_verify_batch,
+    },
+    tsw::Signature,
+};
+
+const EXPIRY: Epoch = 100;
+
+/// A registered user's tACT token with its blind request, and an honest trace report of the
+/// user for a challenge of `challenges`, which the fuzzed inputs are mixed into.
+struct Fixture {
+    domain: DomainConfig,
+    pp: PublicParameters,
+    blind_request: BlindRequest,
+    token: tACTToken,
+    token_proof: TokenProof,
+    t_rgstr: HashSet<Token>,
+    challenges: ChallengeSet,
+    report: TraceReport,
+}
+
+fn fixture() -> &'static Fixture {
+    static FIXTURE: OnceLock<Fixture> = OnceLock::new();
+    FIXTURE.get_or_init(|| {
+        let mut rng = ChaCha20Rng::seed_from_u64(37);
+        let SetupOutput {
+            pp,
+            issuers,
+            mut t_rgstr,
+            domain,
+            ..
+        } = spirit_setup(3, 4, 4, b"fuzz", &mut rng).expect("setup failed");
+
+        let (blind_request, pending) =
+            spirit_register_begin(&domain, &Scalar::from(1u64), &pp, EXPIRY, &mut rng)
+                .expect("request failed");
+        let blind_tokens: Vec<_> = issuers[..pp.t]
+            .iter()
+            .map(|issuer| tissue(&blind_request, issuer, &pp))
+            .collect::<Result<_, _>>()
+            .expect("issuance failed");
+        let (ppu, token, token_proof) =
+            spirit_register_finish(&domain, pending, &blind_tokens, &pp, &mut rng)
+                .expect("unblinding failed");
+        spirit_complete_registration(
+            &domain,
+            &blind_request,
+            &token,
+            &token_proof,
+            &pp,
+            EXPIRY,
+            &mut t_rgstr,
+        )
+        .expect("registration failed");
+
+        let cp = HashSet::from([(0, Interval(0)), (0, Interval(1)), (1, Interval(5))]);
+        let keys = UserKeys::new(Scalar::from(2u64));
+        let mut challenges = ChallengeSet::new(1);
+        let challenge = challenges.issue(1, &mut rng);
+        let report = spirit_diagnosis(
+            &domain,
+            &ppu,
+            &keys,
+            None,
+            &cp,
+            1,
+            &challenge,
+            &[],
+            &mut rng,
+        )
+        .expect("diagnosis failed");
+
+        Fixture {
+            domain,
+            pp,
+            blind_request,
+            token,
+            token_proof,
+            t_rgstr,
+            challenges,
+            report,
+        }
+    })
+}
+
+/// Reads structured values from fuzzer input. Exhausted input reads as zeros, so every input
+/// describes a complete value.
+struct Input<'a>(&'a [u8]);
+
+impl Input<'_> {
+    fn bytes<const N: usize>(&mut self) -> [u8; N] {
+        let mut bytes = [0u8; N];
+        let len = N.min(self.0.len());
+        bytes[..len].copy_from_slice(&self.0[..len]);
+        self.0 = &self.0[len..];
+        bytes
+    }
+
+    fn byte(&mut self) -> u8 {
+        self.bytes::<1>()[0]
+    }
+
+    fn scalar(&mut self) -> Scalar {
+        Scalar::from_le_bytes_mod_order(&self.bytes::<32>())
+    }
+
+    /// A valid point, possibly the identity.
+    fn point(&mut self) -> G1G2 {
+        G1G2(
+            G1Affine::generator() * self.scalar(),
+            G2Affine::generator() * self.scalar(),
+        )
+    }
+
+    fn proof(&mut self) -> Proof {
+        Proof {
+            t: self.point(),
+            s_1: self.scalar(),
+            s_2: self.scalar(),
+        }
+    }
+
+    /// `honest` or, if the next byte is odd, a value from `random`.
+    fn keep_or<T: Clone>(&mut self, honest: &T, random: impl FnOnce(&mut Self) -> T) -> T {
+        if self.byte() & 1 == 0 {
+            honest.clone()
+        } else {
+            random(self)
+        }
+    }
+
+    fn disclosure(&mut self, honest: &Disclosure) -> Disclosure {
+        Disclosure {
+            epoch: self.keep_or(&honest.epoch, |input| Epoch::from(input.byte() % 4)),
+            index: self.keep_or(&honest.index, |input| Interval(u64::from(input.byte()))),
+            device: self.keep_or(&honest.device, |input| {
+                Some(DeviceId::from(input.byte() % 2))
+            }),
+            venue: self.keep_or(&honest.venue, |input| Some(vec![input.byte() % 2])),
+            el_id: self.keep_or(&honest.el_id, Self::point),
+            proof: if self.byte() & 1 == 0 {
+                copy(&honest.proof)
+            } else {
+                Proof2PK {
+                    pi_1: self.proof(),
+                    pi_2: self.proof(),
+                    t3: self.point(),
+                }
+            },
+            prf_proof: self.keep_or(&honest.prf_proof, |input| PrfProof {
+                c: input.scalar(),
+                s: input.scalar(),
+            }),
+        }
+    }
+
+    /// The honest report of the fixture with any of its parts replaced by values from the input.
+    fn report(&mut self, honest: &TraceReport) -> TraceReport {
+        let deployment = self.keep_or(&honest.deployment, |input| vec![input.byte() % 2]);
+        let registrar = self.keep_or(&honest.registrar, |input| {
+            RegistrarId::from(input.byte() % 2)
+        });
+        let token = self.keep_or(&honest.token, |input| {
+            Token(
+                Commitment(input.point()),
+                Signature(input.point()),
+                Epoch::from(input.byte()),
+            )
+        });
+        let cm_prv = self.keep_or(&honest.cm_prv, |input| {
+            (0..input.byte() % 4)
+                .map(|_| (Epoch::from(input.byte() % 4), Commitment(input.point())))
+                .collect()
+        });
+        let prf_pk = self.keep_or(&honest.prf_pk, |input| {
+            (0..input.byte() % 4)
+                .map(|_| (Epoch::from(input.byte() % 4), input.point()))
+                .collect()
+        });
+        let device_keys = self.keep_or(&honest.device_keys, |input| {
+            (0..input.byte() % 4)
+                .map(|_| {
+                    let key = (
+                        DeviceId::from(input.byte() % 2),
+                        Epoch::from(input.byte() % 4),
+                    );
+                    (key, (Commitment(input.point()), input.point()))
+                })
+                .collect()
+        });
+        let report_epoch =
+            self.keep_or(&honest.report_epoch, |input| Epoch::from(input.byte() % 4));
+        let challenge = self.keep_or(&honest.challenge, |input| ReportChallenge {
+            nonce: input.bytes(),
+            issued_at: Epoch::from(input.byte() % 4),
+        });
+        let nullifier = self.keep_or(&honest.nullifier, Self::point);
+        let nullifier_proof = self.keep_or(&honest.nullifier_proof, |input| NullifierProof {
+            c: input.scalar(),
+            s_1: input.scalar(),
+            s_2: input.scalar(),
+        });
+        let show = self.keep_or(&honest.show, |input| {
+            Some(ShowTag {
+                index: usize::from(input.byte() % 4),
+                tag: input.point(),
+                proof: NullifierProof {
+                    c: input.scalar(),
+                    s_1: input.scalar(),
+                    s_2: input.scalar(),
+                },
+            })
+        });
+        let authorization = self.keep_or(&honest.authorization, |input| {
+            Some(DiagnosisAuthorization {
+                diagnosis_epoch: Epoch::from(input.byte() % 4),
+                signature: Signature(input.point()),
+            })
+        });
+        let num_disclosures = usize::from(self.byte() % 8);
+        let disclosures = honest
+            .disclosures
+            .iter()
+            .cycle()
+            .take(num_disclosures)
+            .map(|disclosure| self.disclosure(disclosure))
+            .collect();
+
+        TraceReport {
+            deployment,
+            registrar,
+            token,
+            cm_prv,
+            prf_pk,
+            device_keys,
+            disclosures,
+            report_epoch,
+            challenge,
+            nullifier,
+            nullifier_proof,
+            show,
+            attributes: copy(&honest.attributes),
+            key_binding: copy(&honest.key_binding),
+            authorization,
+            attestation: honest.attestation.clone(),
+            rotation: honest.rotation.clone(),
+        }
+    }
+}
+
+// proofs are not Clone on purpose, so they are only copied here
+fn copy<T: CanonicalSerialize + CanonicalDeserialize>(value: &T) -> T {
+    let mut bytes = Vec::new();
+    value.serialize_uncompressed(&mut bytes).unwrap();
+    T::deserialize_uncompressed_unchecked(&bytes[..]).unwrap()
+}
+
+/// Decodes `data` as a [G1G2] element. Accepted encodings are canonical.
+pub fn g1g2_from_bytes(data: &[u8]) {
+    if let Ok(point) = G1G2::from_bytes(data) {
+        assert_eq!(point.to_bytes()[..], data[..]);
+    }
+}
+
+/// Decodes `data` as a [Token]. Accepted encodings are canonical.
+pub fn token_from_bytes(data: &[u8]) {
+    if let Ok(token) = Token::from_bytes(data) {
+        assert_eq!(token.to_bytes()[..], data[..]);
+    }
+}
+
+/// Decodes `data` as a [TokenProof] and, if it decodes, verifies it for the fixture's token.
+pub fn token_proof_from_bytes(data: &[u8]) {
+    if let Ok(token_proof) = TokenProof::deserialize_compressed(data) {
+        let fixture = fixture();
+        let _ = verify(
+            &fixture.token,
+            &token_proof,
+            &fixture.blind_request,
+            &fixture.pp,
+            &mut OsRng,
+        );
+    }
+}
+
+/// Verifies a report derived from `data` with [spirit_verify] and [spirit_verify_batch], which
+/// have to agree on whether all disclosures are accepted and on the confirmed ElIDs.
+pub fn verify_report(data: &[u8]) {
+    let fixture = fixture();
+    let report = Input(data).report(&fixture.report);
+    let revoked = RevocationList::new();
+    let current_epoch = 1;
+
+    let mut cp = HashSet::new();
+    let result = spirit_verify(
+        &fixture.domain,
+        &report,
+        &fixture.t_rgstr,
+        &revoked,
+        current_epoch,
+        &mut HashSet::new(),
+        &fixture.challenges,
+        &mut cp,
+    );
+    if let Ok(accepted) = &result {
+        assert_eq!(accepted.verdicts.len(), report.disclosures.len());
+    }
+
+    let mut batch_cp = HashSet::new();
+    let batch = spirit_verify_batch(
+        &fixture.domain,
+        core::slice::from_ref(&report),
+        &fixture.t_rgstr,
+        &revoked,
+        current_epoch,
+        &mut HashSet::new(),
+        &fixture.challenges,
+        &mut batch_cp,
+    );
+    let all_accepted = result.is_ok_and(|accepted| !accepted.verdicts.contains(&false));
+    assert_eq!(batch[0].is_ok(), all_accepted);
+    if all_accepted {
+        assert_eq!(batch_cp, cp);
+    } else {
+        assert!(batch_cp.is_empty());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rand::{Rng, RngCore};
+
+    use super::*;
+    use crate::spirit::{Accepted, VerifyRejection};
+
+    const ITERATIONS: usize = 200;
+
+    /// Runs `target` on random inputs of up to `max_len` bytes and on `seeds` with random bytes
+    /// replaced.
+    fn smoke(target: fn(&[u8]), seeds: &[Vec<u8>], max_len: usize) {
+        let mut rng = ChaCha20Rng::seed_from_u64(37);
+        for _ in 0..ITERATIONS {
+            let mut data = vec![0u8; rng.gen_range(0..=max_len)];
+            rng.fill_bytes(&mut data);
+            target(&data);
+
+            for seed in seeds {
+                let mut data = seed.clone();
+                let index = rng.gen_range(0..data.len());
+                data[index] = rng.r#gen();
+                target(&data);
+            }
+        }
+    }
+
+    fn encoding(value: &impl CanonicalSerialize) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        value.serialize_compressed(&mut bytes).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn g1g2_from_bytes_smoke() {
+        let seed = fixture().report.token.0.0.to_bytes().to_vec();
+        smoke(g1g2_from_bytes, &[seed], 2 * G1G2::ENCODED_SIZE);
+    }
+
+    #[test]
+    fn token_from_bytes_smoke() {
+        let seed = fixture().report.token.to_bytes().to_vec();
+        smoke(token_from_bytes, &[seed], 2 * Token::ENCODED_SIZE);
+    }
+
+    #[test]
+    fn token_proof_from_bytes_smoke() {
+        let seed = encoding(&fixture().token_proof);
+        smoke(token_proof_from_bytes, &[seed], 4096);
+    }
+
+    #[test]
+    fn verify_report_smoke() {
+        // zero bytes keep the honest parts of the report
+        let fixture = fixture();
+        let mut cp = HashSet::new();
+        let report = Input(&[0; 1]).report(&fixture.report);
+        assert_eq!(
+            spirit_verify(
+                &fixture.domain,
+                &report,
+                &fixture.t_rgstr,
+                &RevocationList::new(),
+                1,
+                &mut HashSet::new(),
+                &fixture.challenges,
+                &mut cp,
+            ),
+            Err(VerifyRejection::NoDisclosures {
+                token: report.token.id()
+            })
+        );
+        assert_eq!(
+            spirit_verify(
+                &fixture.domain,
+                &Input(&[0, 0, 0, 0, 0, 0, 0, 3]).report(&fixture.report),
+                &fixture.t_rgstr,
+                &RevocationList::new(),
+                1,
+                &mut HashSet::new(),
+                &fixture.challenges,
+                &mut cp,
+            ),
+            Ok(Accepted {
+                verdicts: vec![true; 3]
+            })
+        );
+
+        smoke(verify_report, &[], 512);
+    }
+}
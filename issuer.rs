@@ -0,0 +1,862 @@
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError};
+
+use futures::future::join_all;
+use rand::{CryptoRng, RngCore};
+use thiserror::Error;
+
+use crate::{
+    atact::{AtACTError, BlindRequest, BlindToken, Issuer, PublicParameters, tissue},
+    constants::ISSUER_PING_DST,
+    spirit::Epoch,
+    tsw::{PublicKey, Signature},
+};
+
+/// Connection to one issuer of the committee, e.g. over the network, through which
+/// [spirit_register_async](crate::spirit::spirit_register_async) requests blind tokens.
+pub trait IssuerClient {
+    /// Requests the issuer's blind tokens for `blind_request`, one per commitment of the request.
+    fn issue(
+        &self,
+        blind_request: &BlindRequest,
+    ) -> impl Future<Output = Result<Vec<BlindToken>, IssueError>>;
+
+    /// Asks the issuer for its [PingResponse] to the challenge `nonce`, see [probe_quorum].
+    /// Clients of issuers that do not answer pings keep the default, which reports the issuer
+    /// as unavailable.
+    fn ping(&self, _nonce: &[u8; 32]) -> impl Future<Output = Result<PingResponse, IssueError>> {
+        async {
+            Err(IssueError::Unavailable(
+                "pings are not supported".to_owned(),
+            ))
+        }
+    }
+}
+
+#[derive(Error, Debug, PartialEq, Clone)]
+pub enum IssueError {
+    #[error("The issuer rejected the blind request: {0}")]
+    Rejected(#[source] AtACTError),
+    #[error("The issuer is unavailable: {0}")]
+    Unavailable(String),
+    #[error("The blind request is for other parameters than the issuer serves.")]
+    StaleParameters,
+}
+
+/// An issuer's answer to a ping: its index in the committee, the
+/// [fingerprint](PublicParameters::fingerprint) of the parameters it serves, its current epoch
+/// and the challenge nonce of the ping, signed with its key share. The nonce keeps an old
+/// response from being replayed for a later ping. The response travels as a
+/// [WireMessage](crate::wire::WireMessage).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PingResponse {
+    pub issuer_index: usize,
+    pub fingerprint: [u8; 32],
+    pub epoch: Epoch,
+    pub nonce: [u8; 32],
+    pub(crate) signature: Signature,
+}
+
+impl PingResponse {
+    /// The response of `issuer`, the `issuer_index`-th of the committee serving `pp`, in `epoch`
+    /// to the ping with challenge `nonce`.
+    pub fn new(
+        issuer: &Issuer,
+        issuer_index: usize,
+        pp: &PublicParameters,
+        epoch: Epoch,
+        nonce: &[u8; 32],
+    ) -> Self {
+        let fingerprint = pp.fingerprint();
+        let message = ping_message(issuer_index, &fingerprint, epoch, nonce);
+        Self {
+            issuer_index,
+            fingerprint,
+            epoch,
+            nonce: *nonce,
+            signature: issuer.as_ref().sign_message(&message, &ping_dst()),
+        }
+    }
+
+    /// Verifies the signature under the issuer's key `issuer_pk`, e.g. from
+    /// [PublicParameters::issuer_keys].
+    pub fn verify(&self, issuer_pk: &PublicKey) -> bool {
+        let message = ping_message(
+            self.issuer_index,
+            &self.fingerprint,
+            self.epoch,
+            &self.nonce,
+        );
+        issuer_pk.verify_message(&message, &ping_dst(), &self.signature)
+    }
+}
+
+/// [ISSUER_PING_DST] followed by its length, as the DST_prime of RFC 9380, so that the bytes a
+/// ping signs cannot be those of a message under a tag that extends [ISSUER_PING_DST].
+fn ping_dst() -> Vec<u8> {
+    [ISSUER_PING_DST, &[ISSUER_PING_DST.len() as u8]].concat()
+}
+
+fn ping_message(
+    issuer_index: usize,
+    fingerprint: &[u8; 32],
+    epoch: Epoch,
+    nonce: &[u8; 32],
+) -> Vec<u8> {
+    let mut message = Vec::with_capacity(80);
+    message.extend_from_slice(&(issuer_index as u64).to_le_bytes());
+    message.extend_from_slice(fingerprint);
+    message.extend_from_slice(&epoch.to_le_bytes());
+    message.extend_from_slice(nonce);
+    message
+}
+
+/// Issuers of a committee by their answers to [probe_quorum], each list ordered by issuer index.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct QuorumStatus {
+    /// Number of live issuers a registration needs.
+    pub required: usize,
+    /// Issuers that answered with a valid response for the expected parameters.
+    pub live: Vec<usize>,
+    /// Issuers that answered for other parameters or another epoch, under another index, to
+    /// another nonce or with an invalid signature.
+    pub inconsistent: Vec<usize>,
+    /// Issuers that did not answer.
+    pub unreachable: Vec<usize>,
+}
+
+impl QuorumStatus {
+    /// Whether at least [Self::required] issuers are live.
+    pub fn is_available(&self) -> bool {
+        self.live.len() >= self.required
+    }
+}
+
+/// Pings all `clients`, one per issuer and ordered by issuer index, concurrently with a fresh
+/// nonce from `rng`, and sorts the issuers by their answers, of which `t` have to be live for a
+/// registration. A response is live if it is of the client's index, for the fingerprint of
+/// `pp`, in `current_epoch` and to the nonce, and, if `pp` includes the
+/// [issuer_keys](PublicParameters::issuer_keys), signed with the issuer's key.
+pub async fn probe_quorum(
+    clients: &[impl IssuerClient],
+    pp: &PublicParameters,
+    t: usize,
+    current_epoch: Epoch,
+    rng: &mut (impl RngCore + CryptoRng),
+) -> QuorumStatus {
+    let fingerprint = pp.fingerprint();
+    let mut nonce = [0u8; 32];
+    rng.fill_bytes(&mut nonce);
+    let responses = join_all(clients.iter().map(|client| client.ping(&nonce))).await;
+
+    let mut status = QuorumStatus {
+        required: t,
+        ..QuorumStatus::default()
+    };
+    for (index, response) in responses.into_iter().enumerate() {
+        let signed = |response: &PingResponse| {
+            pp.issuer_keys.is_empty()
+                || pp
+                    .issuer_keys
+                    .get(index)
+                    .is_some_and(|issuer_pk| response.verify(issuer_pk))
+        };
+        match response {
+            Ok(response)
+                if response.issuer_index == index
+                    && response.fingerprint == fingerprint
+                    && response.epoch == current_epoch
+                    && response.nonce == nonce
+                    && signed(&response) =>
+            {
+                status.live.push(index)
+            }
+            Ok(_) => status.inconsistent.push(index),
+            Err(_) => status.unreachable.push(index),
+        }
+    }
+    status
+}
+
+/// [IssuerClient] for an issuer whose key share lives in this process.
+pub struct LocalIssuerClient<'a> {
+    issuer: &'a Issuer,
+    pp: &'a PublicParameters,
+    index: usize,
+    epoch: Epoch,
+}
+
+impl<'a> LocalIssuerClient<'a> {
+    /// Client of `issuer`, which answers pings as the first issuer of the committee in epoch 0
+    /// unless set with [Self::with_index] and [Self::with_epoch].
+    pub fn new(issuer: &'a Issuer, pp: &'a PublicParameters) -> Self {
+        Self {
+            issuer,
+            pp,
+            index: 0,
+            epoch: 0,
+        }
+    }
+
+    /// Sets the index of the issuer in the committee, starting at 0.
+    pub fn with_index(mut self, index: usize) -> Self {
+        self.index = index;
+        self
+    }
+
+    /// Sets the current epoch of the issuer.
+    pub fn with_epoch(mut self, epoch: Epoch) -> Self {
+        self.epoch = epoch;
+        self
+    }
+}
+
+impl IssuerClient for LocalIssuerClient<'_> {
+    async fn issue(&self, blind_request: &BlindRequest) -> Result<Vec<BlindToken>, IssueError> {
+        tissue(blind_request, self.issuer, self.pp).map_err(IssueError::Rejected)
+    }
+
+    async fn ping(&self, nonce: &[u8; 32]) -> Result<PingResponse, IssueError> {
+        Ok(PingResponse::new(
+            self.issuer,
+            self.index,
+            self.pp,
+            self.epoch,
+            nonce,
+        ))
+    }
+}
+
+/// An [Issuer] serving the blind requests of many concurrent registrations, e.g. behind a
+/// network endpoint that calls [Self::handle] from a thread pool. The signing work runs with at
+/// most `permits` requests at once, and further requests wait for a permit, so a burst of
+/// registrations queues up instead of overloading the issuer's machine.
+pub struct IssuerService {
+    issuer: Issuer,
+    pp: Arc<PublicParameters>,
+    fingerprint: [u8; 32],
+    permits: Permits,
+}
+
+impl IssuerService {
+    /// Service of `issuer` for the deployment of `pp`, which signs at most `permits` requests
+    /// at once, and at least one.
+    pub fn new(issuer: Issuer, pp: Arc<PublicParameters>, permits: usize) -> Self {
+        let fingerprint = pp.fingerprint();
+        Self {
+            issuer,
+            pp,
+            fingerprint,
+            permits: Permits::new(permits.max(1)),
+        }
+    }
+
+    /// The [fingerprint](PublicParameters::fingerprint) of the parameters the service serves.
+    pub fn fingerprint(&self) -> [u8; 32] {
+        self.fingerprint
+    }
+
+    /// Issues the blind tokens for `blind_request` as [tissue], once a permit is free. Fails
+    /// with [IssueError::StaleParameters] without waiting if the user built the request for
+    /// parameters of another [fingerprint](BlindRequest::fingerprint) than the service's, e.g.
+    /// from before a [reshare](crate::atact::reshare).
+    pub fn handle(&self, blind_request: &BlindRequest) -> Result<Vec<BlindToken>, IssueError> {
+        if blind_request.fingerprint() != self.fingerprint {
+            return Err(IssueError::StaleParameters);
+        }
+        let _permit = self.permits.acquire();
+        tissue(blind_request, &self.issuer, &self.pp).map_err(IssueError::Rejected)
+    }
+
+    /// The most requests that were signed at once so far, which is at most the number of
+    /// permits.
+    pub fn peak_concurrency(&self) -> usize {
+        self.permits.lock().peak
+    }
+}
+
+/// Counting semaphore of the requests an [IssuerService] signs at once.
+struct Permits {
+    state: Mutex<PermitState>,
+    released: Condvar,
+}
+
+struct PermitState {
+    available: usize,
+    in_use: usize,
+    peak: usize,
+}
+
+/// A permit of [Permits], which is returned when it is dropped.
+struct Permit<'a>(&'a Permits);
+
+impl Permits {
+    fn new(permits: usize) -> Self {
+        Self {
+            state: Mutex::new(PermitState {
+                available: permits,
+                in_use: 0,
+                peak: 0,
+            }),
+            released: Condvar::new(),
+        }
+    }
+
+    // the state is updated in single steps, so it is consistent even if a holder panicked
+    fn lock(&self) -> MutexGuard<'_, PermitState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn acquire(&self) -> Permit<'_> {
+        let mut state = self.lock();
+        while state.available == 0 {
+            state = self
+                .released
+                .wait(state)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+        state.available -= 1;
+        state.in_use += 1;
+        state.peak = state.peak.max(state.in_use);
+        Permit(self)
+    }
+}
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        let mut state = self.0.lock();
+        state.available += 1;
+        state.in_use -= 1;
+        self.0.released.notify_one();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        cell::Cell,
+        collections::{BTreeMap, HashSet},
+        pin::Pin,
+        task::{Context, Poll},
+        thread,
+    };
+
+    use futures::executor::block_on;
+    use rand::rngs::OsRng;
+
+    use super::*;
+    use crate::{
+        atact::verify,
+        spirit::{
+            RegistrationSession, SetupOutput, SpiritError, Token, UserSecret,
+            spirit_register_async, spirit_register_async_probed, spirit_register_begin,
+            spirit_register_finish, spirit_setup_osrng,
+        },
+        wire::WireMessage,
+    };
+
+    const T: usize = 4;
+    const N: usize = 8;
+    const NUM_ISSUERS: usize = 8;
+    const EXPIRY: u64 = 100;
+    const NOW: u64 = 10;
+
+    /// Future that is pending for its first `self.0` polls. It wakes itself in between, so the
+    /// other requests make progress.
+    struct Latency(usize);
+
+    impl Future for Latency {
+        type Output = ();
+
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+            if self.0 == 0 {
+                Poll::Ready(())
+            } else {
+                self.0 -= 1;
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            }
+        }
+    }
+
+    struct MockIssuerClient<'a> {
+        inner: LocalIssuerClient<'a>,
+        latency: usize,
+        fails: bool,
+        requests: &'a Cell<usize>,
+    }
+
+    impl IssuerClient for MockIssuerClient<'_> {
+        async fn issue(&self, blind_request: &BlindRequest) -> Result<Vec<BlindToken>, IssueError> {
+            self.requests.set(self.requests.get() + 1);
+            Latency(self.latency).await;
+            if self.fails {
+                Err(IssueError::Unavailable("connection reset".to_owned()))
+            } else {
+                self.inner.issue(blind_request).await
+            }
+        }
+
+        async fn ping(&self, nonce: &[u8; 32]) -> Result<PingResponse, IssueError> {
+            Latency(self.latency).await;
+            if self.fails {
+                Err(IssueError::Unavailable("connection reset".to_owned()))
+            } else {
+                self.inner.ping(nonce).await
+            }
+        }
+    }
+
+    /// Mock clients of `issuers` with varying latencies, of which those in `failing` fail.
+    fn mock_clients<'a>(
+        issuers: &'a [Issuer],
+        pp: &'a PublicParameters,
+        failing: &[usize],
+        requests: &'a Cell<usize>,
+    ) -> Vec<MockIssuerClient<'a>> {
+        issuers
+            .iter()
+            .enumerate()
+            .map(|(index, issuer)| MockIssuerClient {
+                inner: LocalIssuerClient::new(issuer, pp).with_index(index),
+                latency: (index * 5) % 7,
+                fails: failing.contains(&index),
+                requests,
+            })
+            .collect()
+    }
+
+    /// Registers a user through mock clients with varying latencies, of which those in `failing`
+    /// fail. Returns whether the token was registered and the number of requests sent.
+    fn register_with_failures(failing: &[usize]) -> (Result<bool, SpiritError>, usize) {
+        let SetupOutput {
+            pp,
+            issuers,
+            mut t_rgstr,
+            domain,
+            ..
+        } = spirit_setup_osrng(T, N, NUM_ISSUERS, b"test").expect("setup failed");
+        let requests = Cell::new(0);
+        let clients = mock_clients(&issuers, &pp, failing, &requests);
+
+        let result = block_on(spirit_register_async(
+            &domain,
+            &UserSecret::random(OsRng),
+            &clients,
+            &pp,
+            EXPIRY,
+            &mut t_rgstr,
+            &mut OsRng,
+        ));
+        let registered = result.map(|(token, _)| t_rgstr.contains(&token));
+        (registered, requests.get())
+    }
+
+    #[test]
+    fn register_without_failures() {
+        assert_eq!(register_with_failures(&[]), (Ok(true), T));
+    }
+
+    #[test]
+    fn register_with_exactly_t_responses() {
+        assert_eq!(
+            register_with_failures(&[0, 2, 5, 6]),
+            (Ok(true), NUM_ISSUERS)
+        );
+    }
+
+    #[test]
+    fn register_with_too_many_failures() {
+        assert_eq!(
+            register_with_failures(&[0, 1, 2, 3, 4]),
+            (
+                Err(SpiritError::NotEnoughIssuers {
+                    required: T,
+                    available: T - 1
+                }),
+                NUM_ISSUERS
+            )
+        );
+    }
+
+    #[test]
+    fn probes_sort_issuers_by_their_answers() {
+        let SetupOutput { pp, issuers, .. } =
+            spirit_setup_osrng(T, N, NUM_ISSUERS, b"test").expect("setup failed");
+        let SetupOutput {
+            pp: stale,
+            issuers: others,
+            ..
+        } = spirit_setup_osrng(T, N, NUM_ISSUERS, b"stale").expect("setup failed");
+        assert_ne!(stale.fingerprint(), pp.fingerprint());
+
+        // down, on old parameters, with another key, answering as another issuer and in
+        // another epoch
+        let requests = Cell::new(0);
+        let mut clients = mock_clients(&issuers, &pp, &[1, 6], &requests);
+        clients[2].inner = LocalIssuerClient::new(&issuers[2], &stale).with_index(2);
+        clients[4].inner = LocalIssuerClient::new(&others[4], &pp).with_index(4);
+        clients[5].inner = LocalIssuerClient::new(&issuers[5], &pp).with_index(3);
+        clients[7].inner = LocalIssuerClient::new(&issuers[7], &pp)
+            .with_index(7)
+            .with_epoch(NOW);
+        let status = block_on(probe_quorum(&clients, &pp, T, 0, &mut OsRng));
+        assert_eq!(
+            status,
+            QuorumStatus {
+                required: T,
+                live: vec![0, 3],
+                inconsistent: vec![2, 4, 5, 7],
+                unreachable: vec![1, 6],
+            }
+        );
+        assert!(!status.is_available());
+        assert_eq!(requests.get(), 0);
+
+        let client = LocalIssuerClient::new(&issuers[3], &pp)
+            .with_index(3)
+            .with_epoch(NOW);
+        let response = block_on(client.ping(&[0x11; 32])).expect("ping failed");
+        assert_eq!(
+            (
+                response.issuer_index,
+                response.fingerprint,
+                response.epoch,
+                response.nonce
+            ),
+            (3, pp.fingerprint(), NOW, [0x11; 32])
+        );
+        assert!(response.verify(&pp.issuer_keys[3]));
+        assert!(!response.verify(&pp.issuer_keys[2]));
+        let mut forged = response.clone();
+        forged.epoch += 1;
+        assert!(!forged.verify(&pp.issuer_keys[3]));
+        let decoded = PingResponse::decode(&response.encode()).expect("decoding failed");
+        assert!(decoded.verify(&pp.issuer_keys[3]));
+        let mut forged = response;
+        forged.nonce = [0x22; 32];
+        assert!(!forged.verify(&pp.issuer_keys[3]));
+    }
+
+    /// Client that answers every ping with the same recorded response.
+    struct ReplayingIssuerClient(PingResponse);
+
+    impl IssuerClient for ReplayingIssuerClient {
+        async fn issue(&self, _: &BlindRequest) -> Result<Vec<BlindToken>, IssueError> {
+            Err(IssueError::Unavailable("connection reset".to_owned()))
+        }
+
+        async fn ping(&self, _: &[u8; 32]) -> Result<PingResponse, IssueError> {
+            Ok(self.0.clone())
+        }
+    }
+
+    /// Client of an issuer that does not answer pings.
+    struct UnprobedIssuerClient;
+
+    impl IssuerClient for UnprobedIssuerClient {
+        async fn issue(&self, _: &BlindRequest) -> Result<Vec<BlindToken>, IssueError> {
+            Err(IssueError::Unavailable("connection reset".to_owned()))
+        }
+    }
+
+    #[test]
+    fn probes_reject_replayed_responses() {
+        let SetupOutput { pp, issuers, .. } =
+            spirit_setup_osrng(T, N, NUM_ISSUERS, b"test").expect("setup failed");
+        let all: Vec<_> = (0..NUM_ISSUERS).collect();
+
+        // the signed responses to an earlier ping do not count for the next one
+        let clients: Vec<_> = issuers
+            .iter()
+            .enumerate()
+            .map(|(index, issuer)| {
+                let client = LocalIssuerClient::new(issuer, &pp).with_index(index);
+                ReplayingIssuerClient(block_on(client.ping(&[0x11; 32])).expect("ping failed"))
+            })
+            .collect();
+        assert!(
+            clients
+                .iter()
+                .all(|client| client.0.verify(&pp.issuer_keys[client.0.issuer_index]))
+        );
+        let status = block_on(probe_quorum(&clients, &pp, T, 0, &mut OsRng));
+        assert_eq!((status.live, status.inconsistent), (vec![], all.clone()));
+
+        let clients: Vec<_> = (0..NUM_ISSUERS).map(|_| UnprobedIssuerClient).collect();
+        let status = block_on(probe_quorum(&clients, &pp, T, 0, &mut OsRng));
+        assert_eq!((status.live, status.unreachable), (vec![], all));
+    }
+
+    #[test]
+    fn probed_registrations_fail_fast() {
+        let SetupOutput {
+            pp,
+            issuers,
+            mut t_rgstr,
+            domain,
+            ..
+        } = spirit_setup_osrng(T, N, NUM_ISSUERS, b"test").expect("setup failed");
+        let SetupOutput { pp: stale, .. } =
+            spirit_setup_osrng(T, N, NUM_ISSUERS, b"stale").expect("setup failed");
+        let register = |clients: &[MockIssuerClient<'_>], t_rgstr: &mut HashSet<Token>| {
+            block_on(spirit_register_async_probed(
+                &domain,
+                &UserSecret::random(OsRng),
+                clients,
+                &pp,
+                EXPIRY,
+                0,
+                t_rgstr,
+                &mut OsRng,
+            ))
+        };
+
+        // no blind request is sent without a live quorum
+        let requests = Cell::new(0);
+        let mut clients = mock_clients(&issuers, &pp, &[0, 1, 2], &requests);
+        for index in [3, 4] {
+            clients[index].inner =
+                LocalIssuerClient::new(&issuers[index], &stale).with_index(index);
+        }
+        assert_eq!(
+            register(&clients, &mut t_rgstr),
+            Err(SpiritError::QuorumUnavailable {
+                required: T,
+                live: T - 1,
+                inconsistent: vec![3, 4],
+            })
+        );
+        assert_eq!(requests.get(), 0);
+
+        // with one issuer back on the parameters, only the live issuers are asked
+        clients[4].inner = LocalIssuerClient::new(&issuers[4], &pp).with_index(4);
+        let (token, _) = register(&clients, &mut t_rgstr).expect("registration failed");
+        assert_eq!(requests.get(), T);
+        assert!(t_rgstr.contains(&token));
+    }
+
+    #[test]
+    fn registration_sessions_resume_after_a_timeout() {
+        let SetupOutput {
+            pp,
+            issuers,
+            mut t_rgstr,
+            domain,
+            ..
+        } = spirit_setup_osrng(T, N, NUM_ISSUERS, b"test").expect("setup failed");
+        let user = UserSecret::random(OsRng);
+        let mut session =
+            RegistrationSession::begin(&domain, &user.id_u, &pp, NOW, 2, EXPIRY, &mut OsRng)
+                .expect("registration failed");
+
+        // all issuers but the first t - 1 time out
+        let requests = Cell::new(0);
+        let timed_out: Vec<_> = (T - 1..NUM_ISSUERS).collect();
+        let clients = mock_clients(&issuers, &pp, &timed_out, &requests);
+        assert_eq!(
+            block_on(session.collect(&clients, &pp)),
+            Err(SpiritError::NotEnoughIssuers {
+                required: T,
+                available: T - 1
+            })
+        );
+        let mut collected: Vec<_> = session.issuers().collect();
+        collected.sort();
+        assert_eq!(collected, (0..T - 1).collect::<Vec<_>>());
+
+        // blind tokens for another blind request, and of a collected issuer, are refused
+        let (other_request, _) = spirit_register_begin(
+            &domain,
+            &UserSecret::random(OsRng).id_u,
+            &pp,
+            EXPIRY,
+            &mut OsRng,
+        )
+        .unwrap();
+        let foreign = tissue(&other_request, &issuers[T - 1], &pp).unwrap();
+        assert_eq!(
+            session.add_blind_tokens(T - 1, foreign, &pp),
+            Err(SpiritError::ForeignBlindTokens {
+                issuer_index: T - 1
+            })
+        );
+        let repeated = tissue(session.blind_request(), &issuers[0], &pp).unwrap();
+        assert_eq!(
+            session.add_blind_tokens(0, repeated, &pp),
+            Err(SpiritError::DuplicateIssuer { issuer_index: 0 })
+        );
+
+        #[cfg(feature = "serde")]
+        let mut session = crate::bls381_helpers::serde_round_trip(&session);
+
+        // the resumed session asks one more issuer, in the last epoch it can be resumed in
+        let requests = Cell::new(0);
+        let clients = mock_clients(&issuers, &pp, &[], &requests);
+        let last_epoch = session.last_epoch();
+        assert_eq!(last_epoch, NOW + 2);
+        let (token, _) =
+            block_on(session.resume(&domain, &clients, &pp, last_epoch, &mut t_rgstr, &mut OsRng))
+                .expect("resumed registration failed");
+        assert_eq!(requests.get(), 1);
+        assert!(t_rgstr.contains(&token));
+        assert_eq!(t_rgstr.len(), 1);
+
+        // sessions expire
+        let mut stale =
+            RegistrationSession::begin(&domain, &user.id_u, &pp, NOW, 2, EXPIRY, &mut OsRng)
+                .expect("registration failed");
+        assert_eq!(
+            block_on(stale.resume(&domain, &clients, &pp, NOW + 3, &mut t_rgstr, &mut OsRng)),
+            Err(SpiritError::SessionExpired { started: NOW })
+        );
+        assert_eq!(requests.get(), 1);
+    }
+
+    fn assert_send_sync<S: Send + Sync>() {}
+
+    #[test]
+    fn services_issue_concurrent_requests() {
+        const USERS: usize = 8;
+        const REQUESTS: usize = 1000;
+        const THREADS: usize = 8;
+        assert_send_sync::<IssuerService>();
+
+        let SetupOutput {
+            pp,
+            issuers,
+            domain,
+            ..
+        } = spirit_setup_osrng(T, N, NUM_ISSUERS, b"test").expect("setup failed");
+        let pp = Arc::new(pp);
+        let registrations: Vec<_> = (0..USERS)
+            .map(|_| {
+                spirit_register_begin(
+                    &domain,
+                    &UserSecret::random(OsRng).id_u,
+                    &pp,
+                    EXPIRY,
+                    &mut OsRng,
+                )
+                .expect("registration failed")
+            })
+            .collect();
+        // the blind tokens of the first T issuers, issued one request at a time
+        let expected: Vec<Vec<_>> = registrations
+            .iter()
+            .map(|(blind_request, _)| {
+                issuers[..T]
+                    .iter()
+                    .map(|issuer| tissue(blind_request, issuer, &pp).unwrap())
+                    .collect()
+            })
+            .collect();
+        let services: Vec<_> = issuers
+            .into_iter()
+            .take(T)
+            .map(|issuer| IssuerService::new(issuer, pp.clone(), 3))
+            .collect();
+
+        // request i is of user i % USERS to issuer (i / USERS) % T
+        let issued: BTreeMap<_, _> = thread::scope(|scope| {
+            let workers: Vec<_> = (0..THREADS)
+                .map(|worker| {
+                    let (services, registrations) = (&services, &registrations);
+                    scope.spawn(move || {
+                        (worker..REQUESTS)
+                            .step_by(THREADS)
+                            .map(|i| {
+                                let (user, issuer) = (i % USERS, (i / USERS) % T);
+                                let blind_tokens = services[issuer]
+                                    .handle(&registrations[user].0)
+                                    .expect("issuance failed");
+                                ((user, issuer), blind_tokens)
+                            })
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect();
+            workers
+                .into_iter()
+                .flat_map(|worker| worker.join().unwrap())
+                .inspect(|((user, issuer), blind_tokens)| {
+                    assert_eq!(blind_tokens, &expected[*user][*issuer]);
+                })
+                .collect()
+        });
+        for service in &services {
+            assert!((1..=3).contains(&service.peak_concurrency()));
+        }
+
+        // the blind tokens of the concurrent requests aggregate into valid tokens
+        for (user, (blind_request, pending)) in registrations.into_iter().enumerate() {
+            let blind_tokens = (0..T)
+                .map(|issuer| issued[&(user, issuer)].clone())
+                .collect();
+            let (_, token, token_proof) =
+                spirit_register_finish(&domain, pending, &blind_tokens, &pp, &mut OsRng)
+                    .expect("registration failed");
+            assert!(verify(&token, &token_proof, &blind_request, &pp, &mut OsRng).is_ok());
+        }
+    }
+
+    #[test]
+    fn services_bound_their_concurrency() {
+        let SetupOutput {
+            pp,
+            mut issuers,
+            domain,
+            ..
+        } = spirit_setup_osrng(T, N, NUM_ISSUERS, b"test").expect("setup failed");
+        let SetupOutput {
+            pp: stale,
+            domain: stale_domain,
+            ..
+        } = spirit_setup_osrng(T, N, NUM_ISSUERS, b"stale").expect("setup failed");
+        let pp = Arc::new(pp);
+        let (blind_request, _) = spirit_register_begin(
+            &domain,
+            &UserSecret::random(OsRng).id_u,
+            &pp,
+            EXPIRY,
+            &mut OsRng,
+        )
+        .unwrap();
+
+        // 0 permits are 1
+        for permits in [0, 1, 2] {
+            let service = IssuerService::new(issuers.remove(0), pp.clone(), permits);
+            thread::scope(|scope| {
+                for _ in 0..8 {
+                    scope.spawn(|| {
+                        for _ in 0..4 {
+                            service.handle(&blind_request).expect("issuance failed");
+                        }
+                    });
+                }
+            });
+            assert!((1..=permits.max(1)).contains(&service.peak_concurrency()));
+        }
+
+        // requests for other parameters are rejected before they wait for a permit
+        let (stale_request, _) = spirit_register_begin(
+            &stale_domain,
+            &UserSecret::random(OsRng).id_u,
+            &stale,
+            EXPIRY,
+            &mut OsRng,
+        )
+        .unwrap();
+        assert_eq!(stale_request.fingerprint(), stale.fingerprint());
+        assert_eq!(
+            tissue(&stale_request, &issuers[1], &pp),
+            Err(AtACTError::StaleParameters)
+        );
+        let service = IssuerService::new(issuers.remove(0), pp.clone(), 1);
+        assert_eq!(service.fingerprint(), pp.fingerprint());
+        assert_eq!(
+            service.handle(&stale_request),
+            Err(IssueError::StaleParameters)
+        );
+        assert_eq!(service.peak_concurrency(), 0);
+    }
+}
@@ -1,7 +1,13 @@
-use std::{iter::Sum, ops::Mul};
+use alloc::vec::Vec;
+use core::{iter::Sum, ops::Mul};
+#[cfg(feature = "std")]
+use std::{
+    collections::{BTreeMap, VecDeque},
+    sync::{Arc, Mutex, PoisonError},
+};
 
 pub use crate::bls381_helpers::Scalar;
-use ark_ff::{Field, One};
+use ark_ff::{Field, One, Zero, batch_inversion};
 
 pub type Lagrange = GenericLagrange<Scalar>;
 
@@ -24,8 +30,8 @@ fn ell_j(x: Scalar, xs: &[Scalar], j: usize) -> Scalar {
     num * denom.inverse().unwrap()
 }
 
-/// implementation of the Lagrange base polynomial for x = 0
-fn ell_j_0(xs: &[Scalar], j: usize) -> Scalar {
+/// numerator and denominator of the Lagrange base polynomial for x = 0
+fn ell_j_0_fraction(xs: &[Scalar], j: usize) -> (Scalar, Scalar) {
     debug_assert!(j < xs.len());
 
     let xj = xs[j];
@@ -39,6 +45,12 @@ fn ell_j_0(xs: &[Scalar], j: usize) -> Scalar {
                     (num * xm, denom * (*xm - xj))
                 }
             });
+    (num, denom)
+}
+
+/// implementation of the Lagrange base polynomial for x = 0
+fn ell_j_0(xs: &[Scalar], j: usize) -> Scalar {
+    let (num, denom) = ell_j_0_fraction(xs, j);
     // SAFETY: denom is always != 0
     num * denom.inverse().unwrap()
 }
@@ -50,13 +62,29 @@ pub struct GenericLagrange<T> {
 }
 
 impl GenericLagrange<Scalar> {
+    /// Interpolation at the distinct `points`, with one field inversion for all of their base
+    /// polynomials by Montgomery's trick.
     pub fn new(points: &[Scalar]) -> Self {
+        let (nums, mut denoms): (Vec<_>, Vec<_>) = (0..points.len())
+            .map(|j| ell_j_0_fraction(points, j))
+            .unzip();
+        // the inversion skips zeros, which only distinct points rule out
+        debug_assert!(denoms.iter().all(|denom| !denom.is_zero()));
+        batch_inversion(&mut denoms);
         Self {
             xs: points.into(),
-            evaluated_ell_j_0: (0..points.len()).map(|j| ell_j_0(points, j)).collect(),
+            evaluated_ell_j_0: nums
+                .iter()
+                .zip(denoms)
+                .map(|(num, inv)| inv * num)
+                .collect(),
         }
     }
 
+    pub(crate) fn points(&self) -> &[Scalar] {
+        &self.xs
+    }
+
     pub fn eval_j(&self, x: Scalar, j: usize) -> Scalar {
         ell_j(x, self.xs.as_ref(), j)
     }
@@ -99,6 +127,11 @@ impl GenericLagrange<Scalar> {
         self.evaluated_ell_j_0[j]
     }
 
+    /// The base polynomials evaluated at 0, which weigh the shares in [GenericLagrange::eval_0].
+    pub(crate) fn coefficients_0(&self) -> &[Scalar] {
+        &self.evaluated_ell_j_0
+    }
+
     pub fn update_point(&mut self, k: usize, new_value: Scalar) {
         if self.xs[k] == new_value {
             return;
@@ -120,6 +153,59 @@ impl GenericLagrange<Scalar> {
     }
 }
 
+/// Number of index sets a [LagrangeCache] holds before it drops the oldest.
+#[cfg(feature = "std")]
+pub(crate) const LAGRANGE_CACHE_CAPACITY: usize = 32;
+
+/// Memoized [Lagrange] interpolations of the shares of sets of issuers, keyed by the sorted
+/// indices of the issuers in the committee, so that registrations with the same quorum do not
+/// recompute the coefficients.
+#[cfg(feature = "std")]
+#[derive(Debug, Default)]
+pub(crate) struct LagrangeCache {
+    sets: Mutex<CachedSets>,
+}
+
+#[cfg(feature = "std")]
+#[derive(Debug, Default)]
+struct CachedSets {
+    lagranges: BTreeMap<Vec<usize>, Arc<Lagrange>>,
+    // keys in the order of insertion
+    order: VecDeque<Vec<usize>>,
+}
+
+#[cfg(feature = "std")]
+impl LagrangeCache {
+    /// Interpolation at the points `index + 1` of the sorted, distinct `indices`.
+    pub(crate) fn get(&self, indices: &[usize]) -> Arc<Lagrange> {
+        debug_assert!(indices.is_sorted_by(|a, b| a < b));
+        let mut sets = self.sets.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(lagrange) = sets.lagranges.get(indices) {
+            return lagrange.clone();
+        }
+        let points: Vec<_> = indices
+            .iter()
+            .map(|index| Scalar::from(*index as u64 + 1))
+            .collect();
+        let lagrange = Arc::new(Lagrange::new(&points));
+        if sets.order.len() == LAGRANGE_CACHE_CAPACITY {
+            let oldest = sets.order.pop_front().unwrap();
+            sets.lagranges.remove(&oldest);
+        }
+        sets.order.push_back(indices.to_vec());
+        sets.lagranges.insert(indices.to_vec(), lagrange.clone());
+        lagrange
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.sets
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .order
+            .len()
+    }
+}
+
 /*
 #[cfg(test)]
 mod test {
@@ -1,3 +1,4 @@
+#![cfg_attr(not(feature = "std"), no_std)]
 #![warn(
     clippy::if_not_else,
     clippy::default_trait_access,
@@ -6,11 +7,84 @@
     clippy::large_types_passed_by_value
 )]
 
+extern crate alloc;
+
+pub mod advertisement;
+#[cfg(test)]
+mod arbitrary;
+#[cfg_attr(not(test), deny(clippy::unwrap_used, clippy::expect_used))]
 pub mod atact;
+#[cfg(feature = "std")]
+pub mod audit;
+#[cfg(feature = "backup")]
+#[cfg_attr(not(test), deny(clippy::unwrap_used, clippy::expect_used))]
+pub mod backup;
 mod bls381_helpers;
+#[cfg(test)]
+mod compat;
+#[cfg(feature = "std")]
+pub mod conformance;
+pub mod constants;
+#[cfg(feature = "std")]
+pub mod dkg;
+pub mod elgamal;
+pub mod envelope;
+#[cfg(feature = "std")]
+pub mod events;
+#[cfg(feature = "std")]
+pub mod exposure;
+#[cfg(feature = "std")]
+pub mod federation;
+mod fiat_shamir;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(any(test, feature = "fuzzing"))]
+pub mod fuzz;
+#[cfg(feature = "async")]
+pub mod issuer;
 mod lagrange;
 pub mod pedersen;
+#[cfg(feature = "std")]
+pub mod pipeline;
+#[cfg(feature = "std")]
+pub mod pool;
+#[cfg(feature = "std")]
+pub mod psi;
+#[cfg(feature = "std")]
+#[cfg_attr(not(test), deny(clippy::unwrap_used, clippy::expect_used))]
+pub mod receipt;
+#[cfg(feature = "std")]
+#[cfg_attr(not(test), deny(clippy::unwrap_used, clippy::expect_used))]
+pub mod registry;
+#[cfg(feature = "std")]
+#[cfg_attr(not(test), deny(clippy::unwrap_used, clippy::expect_used))]
+pub mod retention;
+#[cfg(feature = "std")]
 pub mod s3id;
+#[cfg(feature = "testkit")]
+pub mod simulation;
+#[cfg_attr(not(test), deny(clippy::unwrap_used, clippy::expect_used))]
+pub mod spirit;
+#[cfg(feature = "std")]
+pub mod stats;
+#[cfg(feature = "std")]
+#[cfg_attr(not(test), deny(clippy::unwrap_used, clippy::expect_used))]
+pub mod sync;
+#[cfg(feature = "telemetry")]
+pub mod telemetry;
+#[cfg(feature = "testkit")]
+pub mod testkit;
+#[cfg(feature = "testvectors")]
+pub mod testvectors;
+#[cfg(any(test, feature = "testkit", feature = "testvectors"))]
+pub mod transcript;
 pub mod tsw;
+#[cfg(feature = "std")]
+pub mod verifiable;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+pub mod wire;
 
-pub use bls381_helpers::Scalar;
+pub use bls381_helpers::{
+    DecodeError, G1G2, HashBackend, HashBackendId, HashToCurveSuite, Precomputed, Scalar, msm,
+};
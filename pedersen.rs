@@ -1,37 +1,69 @@
-use std::{
-    iter::Sum,
-    ops::{Add, Index, Mul, Sub},
-    sync::OnceLock,
+use alloc::{collections::BTreeMap, vec::Vec};
+use core::{
+    iter::{self, Sum},
+    ops::{Add, Index, Mul, Range, Sub},
 };
 
-use ark_ff::{Field, UniformRand};
-use ark_serialize::CanonicalSerialize;
-use rand::{RngCore, SeedableRng};
+use ark_ff::{Field, UniformRand, Zero};
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
+use rand::{CryptoRng, Rng, RngCore, SeedableRng};
 // use sha3::{Digest, Sha3_512 as Hasher};
 use sha2::{Digest, Sha256 as Hasher, digest::consts::U32};
+use subtle::{Choice, ConstantTimeEq};
 use thiserror::Error;
 
-use crate::bls381_helpers::{G1G2, Scalar, gs::CProof, hash_with_domain_separation};
+use crate::{
+    bls381_helpers::{
+        G1G2, OnceGlobal, Precomputed, Scalar, gs::CProof, hash_with_domain_separation, msm,
+    },
+    constants::{
+        DISCLOSURE_PROTOCOL, MESSAGE_OPENING_PROTOCOL, MULTI_PEDERSEN_DST, OPENING_PROTOCOL,
+        PEDERSEN_DST, PEDERSEN_G_SEED, PEDERSEN_PROOF_HASH_DST, PEDERSEN_U_SEED,
+        PROOF_2PK_PROTOCOL,
+    },
+    fiat_shamir::ProofTranscript,
+};
 
 pub struct PublicParameters {
     pub g: G1G2,
     pub u: G1G2,
+    /// Tables of `g` and `u`, built by [precompute] or for the first commitment.
+    tables: OnceGlobal<[Precomputed<G1G2>; 2]>,
 }
 
 impl PublicParameters {
     fn new() -> Self {
         Self {
-            g: hash_with_domain_separation(b"g", b"Pedersen-PP"),
-            u: hash_with_domain_separation(b"u", b"Pedersen-PP"),
+            g: hash_with_domain_separation(PEDERSEN_G_SEED, PEDERSEN_DST),
+            u: hash_with_domain_separation(PEDERSEN_U_SEED, PEDERSEN_DST),
+            tables: OnceGlobal::new(),
         }
     }
+
+    fn tables(&self) -> &[Precomputed<G1G2>; 2] {
+        self.tables
+            .get_or_init(|| [Precomputed::new(&self.g), Precomputed::new(&self.u)])
+    }
+
+    /// `g^r u^message` from the precomputed tables of `g` and `u`.
+    fn commit_base(&self, r: &Scalar, message: &Scalar) -> G1G2 {
+        let [g, u] = self.tables();
+        g.mul(r) + u.mul(message)
+    }
 }
 
 pub fn get_parameters() -> &'static PublicParameters {
-    static INSTANCE: OnceLock<PublicParameters> = OnceLock::new();
+    static INSTANCE: OnceGlobal<PublicParameters> = OnceGlobal::new();
     INSTANCE.get_or_init(PublicParameters::new)
 }
 
+/// Builds the [Precomputed] tables of the generators `g` and `u` for commitments, so the first
+/// commitment does not pay for them. They are derived from the fixed generators, so every
+/// process builds the same tables.
+pub fn precompute() {
+    get_parameters().tables();
+}
+
 #[derive(Error, Debug, PartialEq, Eq, Clone)]
 pub enum Error {
     #[error("Invalid opening for commitment.")]
@@ -40,6 +72,21 @@ pub enum Error {
     InvalidProof,
 }
 
+/// Failure of [Proof2PK::verify_batch].
+#[derive(Error, Debug, PartialEq, Eq, Clone)]
+pub enum BatchError {
+    #[error("{proofs} proofs for {statements} statements.")]
+    LengthMismatch { statements: usize, proofs: usize },
+    /// The proof at `index` is the first one of the batch that does not verify.
+    #[error("Proof {index} of the batch is invalid.")]
+    InvalidProof { index: usize },
+    /// The next check of the batch or of its bisection would exceed the budget of
+    /// [Proof2PK::verify_batch_within].
+    #[error("The verification of the batch exceeds its budget.")]
+    CostExceeded,
+}
+
+#[derive(PartialEq, Eq)]
 pub struct MultiBasePublicParameters {
     us: Vec<G1G2>,
 }
@@ -49,11 +96,19 @@ impl MultiBasePublicParameters {
         Self {
             us: (0..l)
                 .map(|idx| {
-                    hash_with_domain_separation(&(idx as u64).to_le_bytes(), b"Multi-Pedersen-PP")
+                    hash_with_domain_separation(&(idx as u64).to_le_bytes(), MULTI_PEDERSEN_DST)
                 })
                 .collect(),
         }
     }
+
+    pub fn len(&self) -> usize {
+        self.us.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.us.is_empty()
+    }
 }
 
 impl Index<usize> for MultiBasePublicParameters {
@@ -65,17 +120,36 @@ impl Index<usize> for MultiBasePublicParameters {
     }
 }
 
-#[derive(Clone, PartialEq, Eq)]
+#[derive(
+    Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, CanonicalSerialize, CanonicalDeserialize,
+)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Commitment(pub(crate) G1G2);
 
+impl ConstantTimeEq for Commitment {
+    fn ct_eq(&self, other: &Self) -> Choice {
+        self.0.ct_eq(&other.0)
+    }
+}
+
 pub struct Opening {
     pub(crate) r: Scalar,
 }
 
+#[derive(CanonicalSerialize, CanonicalDeserialize)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Proof {
-    t: G1G2,
-    s_1: Scalar,
-    s_2: Scalar,
+    pub(crate) t: G1G2,
+    #[cfg_attr(
+        feature = "serde",
+        serde(with = "crate::bls381_helpers::serde_compressed")
+    )]
+    pub(crate) s_1: Scalar,
+    #[cfg_attr(
+        feature = "serde",
+        serde(with = "crate::bls381_helpers::serde_compressed")
+    )]
+    pub(crate) s_2: Scalar,
 }
 
 pub struct ProofIndexCommit {
@@ -85,10 +159,79 @@ pub struct ProofIndexCommit {
     s_3: Scalar,
 }
 
+#[derive(CanonicalSerialize, CanonicalDeserialize)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Proof2PK {
-    pi_1: Proof,
-    pi_2: Proof,
-    t3: G1G2,
+    pub(crate) pi_1: Proof,
+    pub(crate) pi_2: Proof,
+    pub(crate) t3: G1G2,
+}
+
+/// Proof of knowledge of an opening of a commitment, see [Commitment::prove_opening].
+#[derive(CanonicalSerialize, CanonicalDeserialize)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct OpeningProof {
+    pub(crate) t: G1G2,
+    #[cfg_attr(
+        feature = "serde",
+        serde(with = "crate::bls381_helpers::serde_compressed")
+    )]
+    pub(crate) s_r: Scalar,
+    #[cfg_attr(
+        feature = "serde",
+        serde(with = "crate::bls381_helpers::serde_compressed")
+    )]
+    pub(crate) s_m: Scalar,
+}
+
+/// Proof that a commitment opens to a message the verifier knows, with knowledge of the
+/// randomness, see [Commitment::prove_opening_to].
+#[derive(CanonicalSerialize, CanonicalDeserialize)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MessageOpeningProof {
+    pub(crate) t: G1G2,
+    #[cfg_attr(
+        feature = "serde",
+        serde(with = "crate::bls381_helpers::serde_compressed")
+    )]
+    pub(crate) s_r: Scalar,
+}
+
+/// Proof that a vector commitment of [Commitment::commit_vec] opens to the `revealed` messages,
+/// with the first message committed to in `identity` under fresh randomness, and knowledge of
+/// the messages that stay hidden.
+#[derive(CanonicalSerialize, CanonicalDeserialize)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DisclosureProof {
+    pub(crate) identity: Commitment,
+    #[cfg_attr(
+        feature = "serde",
+        serde(with = "crate::bls381_helpers::serde_compressed")
+    )]
+    pub(crate) revealed: BTreeMap<usize, Scalar>,
+    pub(crate) t: G1G2,
+    #[cfg_attr(
+        feature = "serde",
+        serde(with = "crate::bls381_helpers::serde_compressed")
+    )]
+    pub(crate) s: Vec<Scalar>,
+}
+
+impl DisclosureProof {
+    /// The commitment `g^r' u^m_0` to the first message.
+    pub fn identity(&self) -> &Commitment {
+        &self.identity
+    }
+
+    /// The revealed messages by their index in the vector.
+    pub fn revealed(&self) -> &BTreeMap<usize, Scalar> {
+        &self.revealed
+    }
+
+    /// Number of messages of the vector commitment, including the first one.
+    pub fn num_messages(&self) -> usize {
+        self.revealed.len() + self.s.len()
+    }
 }
 
 pub struct ProofMultiBase {
@@ -182,11 +325,11 @@ where
 }
 
 fn hash_context() -> Hasher {
-    static INSTANCE: OnceLock<Hasher> = OnceLock::new();
+    static INSTANCE: OnceGlobal<Hasher> = OnceGlobal::new();
     INSTANCE
         .get_or_init(|| {
             let mut hasher = Hasher::new();
-            hasher.update(b"hash-pedersen-proof");
+            hasher.update(PEDERSEN_PROOF_HASH_DST);
             hash_base(&mut hasher);
             hasher
         })
@@ -200,14 +343,500 @@ fn hash_pedersen_proof(commitment: &Commitment, t: &G1G2) -> Scalar {
     hash_extract_scalar(hasher)
 }
 
+/// Transcript of a standalone [Proof2PK] bound to `context`.
+fn proof_2_pk_transcript(context: &[u8]) -> ProofTranscript {
+    let mut transcript = ProofTranscript::new(PROOF_2PK_PROTOCOL);
+    transcript.append_message(b"context", context);
+    transcript
+}
+
+/// Transcript of an [OpeningProof] for `commitment`, or of a [MessageOpeningProof] if the
+/// `message` is known, up to the prover's first message.
+fn opening_transcript(commitment: &Commitment, message: Option<&Scalar>) -> ProofTranscript {
+    let pp = get_parameters();
+    let mut transcript = match message {
+        None => ProofTranscript::new(OPENING_PROTOCOL),
+        Some(message) => {
+            let mut transcript = ProofTranscript::new(MESSAGE_OPENING_PROTOCOL);
+            transcript.append_scalar(b"message", message);
+            transcript
+        }
+    };
+    transcript.append_point(b"g", &pp.g);
+    transcript.append_point(b"u", &pp.u);
+    transcript.append_point(b"cm", &commitment.0);
+    transcript
+}
+
+/// Transcript of a [DisclosureProof] for the vector commitment `commitment`, up to the prover's
+/// first message. The generators are determined by the number of messages.
+fn disclosure_transcript(
+    commitment: &Commitment,
+    identity: &Commitment,
+    revealed: &BTreeMap<usize, Scalar>,
+    num_messages: usize,
+    context: &[u8],
+) -> ProofTranscript {
+    let pp = get_parameters();
+    let mut transcript = ProofTranscript::new(DISCLOSURE_PROTOCOL);
+    transcript.append_message(b"context", context);
+    transcript.append_point(b"g", &pp.g);
+    transcript.append_point(b"u", &pp.u);
+    transcript.append_point(b"cm", &commitment.0);
+    transcript.append_point(b"identity", &identity.0);
+    transcript.append_u64(b"n", num_messages as u64);
+    transcript.append_u64(b"revealed", revealed.len() as u64);
+    for (index, message) in revealed {
+        transcript.append_u64(b"index", *index as u64);
+        transcript.append_scalar(b"message", message);
+    }
+    transcript
+}
+
+#[allow(clippy::too_many_arguments)]
+fn challenge_proof_2_pk(
+    transcript: &mut ProofTranscript,
+    commitment: &Commitment,
+    commitment_2: &Commitment,
+    base: &G1G2,
+    pk: &G1G2,
+    t1: &G1G2,
+    t2: &G1G2,
+    t3: &G1G2,
+) -> Scalar {
+    let pp = get_parameters();
+    transcript.append_point(b"g", &pp.g);
+    transcript.append_point(b"u", &pp.u);
+    transcript.append_point(b"base", base);
+    transcript.append_point(b"cm", &commitment.0);
+    transcript.append_point(b"cm_2", &commitment_2.0);
+    transcript.append_point(b"pk", pk);
+    transcript.append_point(b"t1", t1);
+    transcript.append_point(b"t2", t2);
+    transcript.append_point(b"t3", t3);
+    transcript.challenge_scalar(b"c")
+}
+
+/// Statement of a [Proof2PK]: the two commitments, the base, the public key and the context the
+/// proof is bound to.
+pub type Proof2PKStatement<'a> = (&'a Commitment, &'a Commitment, &'a G1G2, &'a G1G2, &'a [u8]);
+
+// challenges of the proofs of `statements`
+fn challenges_proof_2_pk(
+    statements: &[Proof2PKStatement<'_>],
+    proofs: &[&Proof2PK],
+) -> Vec<Scalar> {
+    statements
+        .iter()
+        .zip(proofs)
+        .map(|((commitment, commitment_2, base, pk, context), proof)| {
+            challenge_proof_2_pk(
+                &mut proof_2_pk_transcript(context),
+                commitment,
+                commitment_2,
+                base,
+                pk,
+                &proof.pi_1.t,
+                &proof.pi_2.t,
+                &proof.t3,
+            )
+        })
+        .collect()
+}
+
+/// Random linear combination `Σ_i points_i * scalars_i` of verification equations, each of which
+/// is the identity for valid proofs. The coefficients of `g` and `u` are summed up, so that they
+/// are multiplied only once.
+struct EquationBatch {
+    g_scalar: Scalar,
+    u_scalar: Scalar,
+    points: Vec<G1G2>,
+    scalars: Vec<Scalar>,
+}
+
+impl EquationBatch {
+    fn with_capacity(num_proofs: usize) -> Self {
+        Self {
+            g_scalar: Scalar::zero(),
+            u_scalar: Scalar::zero(),
+            points: Vec::with_capacity(7 * num_proofs + 2),
+            scalars: Vec::with_capacity(7 * num_proofs + 2),
+        }
+    }
+
+    /// Adds the three equations of a [Proof2PK] for the challenge `c`, each scaled by a random
+    /// 128-bit scalar from `rng`.
+    fn push_proof_2_pk(
+        &mut self,
+        (commitment, commitment_2, base, pk): (&Commitment, &Commitment, &G1G2, &G1G2),
+        c: Scalar,
+        proof: &Proof2PK,
+        rng: &mut (impl RngCore + CryptoRng),
+    ) {
+        let rho_1 = Scalar::from(rng.r#gen::<u128>());
+        let rho_2 = Scalar::from(rng.r#gen::<u128>());
+        let rho_3 = Scalar::from(rng.r#gen::<u128>());
+
+        // rho_1 * (g * s1_1 + u * s1_2 - cm * c - t1)
+        self.g_scalar += rho_1 * proof.pi_1.s_1;
+        self.u_scalar += rho_1 * proof.pi_1.s_2;
+        self.points
+            .extend([commitment.0.clone(), proof.pi_1.t.clone()]);
+        self.scalars.extend([-(rho_1 * c), -rho_1]);
+
+        // rho_2 * (g * s2_1 + u * s2_2 - cm_2 * c - t2)
+        self.g_scalar += rho_2 * proof.pi_2.s_1;
+        self.u_scalar += rho_2 * proof.pi_2.s_2;
+        self.points
+            .extend([commitment_2.0.clone(), proof.pi_2.t.clone()]);
+        self.scalars.extend([-(rho_2 * c), -rho_2]);
+
+        // rho_3 * (base * s2_2 - pk * c - t3)
+        self.points
+            .extend([base.clone(), pk.clone(), proof.t3.clone()]);
+        self.scalars
+            .extend([rho_3 * proof.pi_2.s_2, -(rho_3 * c), -rho_3]);
+    }
+
+    /// Batch of the equations of the [Proof2PK]s of `statements` in `range`, for their
+    /// precomputed challenges `cs`.
+    fn of_proofs_2_pk(
+        statements: &[Proof2PKStatement<'_>],
+        cs: &[Scalar],
+        proofs: &[&Proof2PK],
+        range: Range<usize>,
+        rng: &mut (impl RngCore + CryptoRng),
+    ) -> Self {
+        let mut batch = Self::with_capacity(range.len());
+        for i in range {
+            let (commitment, commitment_2, base, pk, _) = statements[i];
+            batch.push_proof_2_pk((commitment, commitment_2, base, pk), cs[i], proofs[i], rng);
+        }
+        batch
+    }
+
+    /// Whether all equations hold. If one of them does not, the combination is the identity
+    /// with probability at most 2^-128 over the random scalars.
+    fn holds(mut self) -> bool {
+        let pp = get_parameters();
+        self.points.extend([pp.g.clone(), pp.u.clone()]);
+        self.scalars.extend([self.g_scalar, self.u_scalar]);
+        msm(&self.points, &self.scalars) == G1G2::default()
+    }
+}
+
+impl Proof2PK {
+    /// Size in bytes of the compressed encoding of every proof: three points and four scalars.
+    pub const ENCODED_SIZE: usize = 3 * G1G2::ENCODED_SIZE + 4 * 32;
+
+    pub fn encoded_size(&self) -> usize {
+        self.compressed_size()
+    }
+
+    /// Verifies the proofs of `statements` at once like [Commitment::verify_proof_2_pk_batch],
+    /// with one multi-scalar multiplication and no pairing, and the random scalars from `rng`.
+    ///
+    /// If the batch fails, the first invalid proof is located by bisection: the first half of
+    /// the failing range is checked with fresh random scalars, and the search continues in the
+    /// first half if it fails and in the second one otherwise. The challenges are computed only
+    /// once, so locating the proof costs about as much as checking the whole batch again.
+    pub fn verify_batch(
+        statements: &[Proof2PKStatement<'_>],
+        proofs: &[&Self],
+        rng: &mut (impl RngCore + CryptoRng),
+    ) -> Result<(), BatchError> {
+        Self::verify_batch_within(statements, proofs, &mut usize::MAX, rng)
+    }
+
+    /// Like [Self::verify_batch], but every check of the batch and of the bisection takes the
+    /// terms of its multi-scalar multiplication, two and seven per proof, from `budget`. A check
+    /// that would take more terms than are left fails with [BatchError::CostExceeded] before it
+    /// is computed.
+    pub fn verify_batch_within(
+        statements: &[Proof2PKStatement<'_>],
+        proofs: &[&Self],
+        budget: &mut usize,
+        rng: &mut (impl RngCore + CryptoRng),
+    ) -> Result<(), BatchError> {
+        if statements.len() != proofs.len() {
+            return Err(BatchError::LengthMismatch {
+                statements: statements.len(),
+                proofs: proofs.len(),
+            });
+        }
+        Proof2PKBatch::new(statements, proofs).verify_from(0, budget, rng)
+    }
+}
+
+/// [Proof2PK]s of a batch with their challenges, which are computed once, however often the
+/// proofs are checked again after an invalid one.
+pub(crate) struct Proof2PKBatch<'a> {
+    statements: &'a [Proof2PKStatement<'a>],
+    proofs: &'a [&'a Proof2PK],
+    cs: Vec<Scalar>,
+}
+
+impl<'a> Proof2PKBatch<'a> {
+    /// The batch of `proofs` of `statements`, of which there are as many.
+    pub(crate) fn new(statements: &'a [Proof2PKStatement<'a>], proofs: &'a [&'a Proof2PK]) -> Self {
+        debug_assert_eq!(statements.len(), proofs.len());
+        Self {
+            statements,
+            proofs,
+            cs: challenges_proof_2_pk(statements, proofs),
+        }
+    }
+
+    /// Checks the proofs from `start` on like [Proof2PK::verify_batch_within]. The index of an
+    /// invalid proof is its index in the whole batch.
+    pub(crate) fn verify_from(
+        &self,
+        start: usize,
+        budget: &mut usize,
+        rng: &mut (impl RngCore + CryptoRng),
+    ) -> Result<(), BatchError> {
+        let mut holds = |range: Range<usize>| {
+            let terms = 7 * range.len() + 2;
+            if terms > *budget {
+                return Err(BatchError::CostExceeded);
+            }
+            *budget -= terms;
+            let batch =
+                EquationBatch::of_proofs_2_pk(self.statements, &self.cs, self.proofs, range, rng);
+            Ok(batch.holds())
+        };
+        let mut failing = start..self.proofs.len();
+        if holds(failing.clone())? {
+            return Ok(());
+        }
+        while failing.len() > 1 {
+            let mid = failing.start + failing.len() / 2;
+            if holds(failing.start..mid)? {
+                failing.start = mid;
+            } else {
+                failing.end = mid;
+            }
+        }
+        Err(BatchError::InvalidProof {
+            index: failing.start,
+        })
+    }
+}
+
 impl Commitment {
+    #[cfg(feature = "std")]
     pub fn commit(message: &Scalar) -> (Self, Opening) {
         Self::commit_with_randomness(message, &Scalar::rand(&mut rand::thread_rng()))
     }
 
     pub fn commit_with_randomness(message: &Scalar, r: &Scalar) -> (Self, Opening) {
         let pp = get_parameters();
-        (Self(&pp.g * *r + &pp.u * *message), Opening { r: *r })
+        (Self(pp.commit_base(r, message)), Opening { r: *r })
+    }
+
+    /// Commitment `g^r u^m_0 Π_i generators[i - 1]^m_i` to the vector `messages`. For a single
+    /// message, this is the commitment of [Commitment::commit_with_randomness], and missing
+    /// trailing messages are treated as zero.
+    pub fn commit_vec(
+        messages: &[Scalar],
+        r: &Scalar,
+        generators: &MultiBasePublicParameters,
+    ) -> (Self, Opening) {
+        debug_assert!(!messages.is_empty() && messages.len() <= generators.len() + 1);
+
+        let pp = get_parameters();
+        let cm = pp.commit_base(r, &messages[0])
+            + msm(&generators.us[..messages.len() - 1], &messages[1..]);
+        (Self(cm), Opening { r: *r })
+    }
+
+    pub fn verify_vec(
+        &self,
+        messages: &[Scalar],
+        opening: &Opening,
+        generators: &MultiBasePublicParameters,
+    ) -> Result<(), Error> {
+        if messages.is_empty() || messages.len() > generators.len() + 1 {
+            return Err(Error::InvalidOpening);
+        }
+        if Self::commit_vec(messages, &opening.r, generators).0 == *self {
+            Ok(())
+        } else {
+            Err(Error::InvalidOpening)
+        }
+    }
+
+    /// [DisclosureProof] for the vector commitment `self` to `messages` with `opening` that
+    /// reveals the messages at the indices `disclose`, bound to `context`. The first message is
+    /// never revealed, so the indices have to be in `1..messages.len()`. Returns the proof with
+    /// the opening of its commitment to the first message.
+    pub fn prove_disclosure(
+        &self,
+        messages: &[Scalar],
+        opening: &Opening,
+        disclose: &[usize],
+        generators: &MultiBasePublicParameters,
+        context: &[u8],
+        rng: &mut (impl RngCore + CryptoRng),
+    ) -> (DisclosureProof, Opening) {
+        debug_assert!(
+            disclose
+                .iter()
+                .all(|index| (1..messages.len()).contains(index))
+        );
+
+        let pp = get_parameters();
+        let (identity, identity_opening) =
+            Self::commit_with_randomness(&messages[0], &Scalar::rand(rng));
+        let revealed: BTreeMap<_, _> = disclose
+            .iter()
+            .map(|index| (*index, messages[*index]))
+            .collect();
+
+        // self / identity / Π_revealed h_i^m_i = g^(r - r') Π_hidden h_i^m_i
+        let witnesses: Vec<_> = iter::once((&pp.g, opening.r - identity_opening.r))
+            .chain(
+                (1..messages.len())
+                    .filter(|index| !revealed.contains_key(index))
+                    .map(|index| (&generators[index - 1], messages[index])),
+            )
+            .collect();
+        let randoms: Vec<_> = witnesses.iter().map(|_| Scalar::rand(rng)).collect();
+        let t: G1G2 = witnesses
+            .iter()
+            .zip(&randoms)
+            .map(|((base, _), random)| *base * *random)
+            .sum();
+
+        let mut transcript =
+            disclosure_transcript(self, &identity, &revealed, messages.len(), context);
+        transcript.append_point(b"t", &t);
+        let c = transcript.challenge_scalar(b"c");
+
+        let s = witnesses
+            .iter()
+            .zip(randoms)
+            .map(|((_, witness), random)| random + *witness * c)
+            .collect();
+        (
+            DisclosureProof {
+                identity,
+                revealed,
+                t,
+                s,
+            },
+            identity_opening,
+        )
+    }
+
+    /// Verifies a [DisclosureProof] for the vector commitment `self` with the verifier's
+    /// `generators` and `context`. Proofs for another number of messages than the generators
+    /// are for are rejected.
+    pub fn verify_disclosure(
+        &self,
+        proof: &DisclosureProof,
+        generators: &MultiBasePublicParameters,
+        context: &[u8],
+    ) -> Result<(), Error> {
+        let num_messages = generators.len() + 1;
+        if proof.num_messages() != num_messages
+            || proof.s.is_empty()
+            || proof
+                .revealed
+                .keys()
+                .any(|index| !(1..num_messages).contains(index))
+        {
+            return Err(Error::InvalidProof);
+        }
+
+        let pp = get_parameters();
+        let statement = proof.revealed.iter().fold(
+            &self.0 - &proof.identity.0,
+            |statement, (index, message)| statement - &generators[index - 1] * *message,
+        );
+        let bases = iter::once(&pp.g).chain(
+            (1..num_messages)
+                .filter(|index| !proof.revealed.contains_key(index))
+                .map(|index| &generators[index - 1]),
+        );
+
+        let mut transcript = disclosure_transcript(
+            self,
+            &proof.identity,
+            &proof.revealed,
+            num_messages,
+            context,
+        );
+        transcript.append_point(b"t", &proof.t);
+        let c = transcript.challenge_scalar(b"c");
+
+        let response: G1G2 = bases.zip(&proof.s).map(|(base, s)| base * *s).sum();
+        if response == &proof.t + statement * c {
+            Ok(())
+        } else {
+            Err(Error::InvalidProof)
+        }
+    }
+
+    /// Proves knowledge of the `message` and `opening` of the commitment without revealing
+    /// them.
+    pub fn prove_opening(
+        &self,
+        message: &Scalar,
+        opening: &Opening,
+        rng: &mut (impl RngCore + CryptoRng),
+    ) -> OpeningProof {
+        let pp = get_parameters();
+        let (r_r, r_m) = (Scalar::rand(rng), Scalar::rand(rng));
+        let t = &pp.g * r_r + &pp.u * r_m;
+
+        let mut transcript = opening_transcript(self, None);
+        transcript.append_point(b"t", &t);
+        let c = transcript.challenge_scalar(b"c");
+        OpeningProof {
+            t,
+            s_r: r_r + opening.r * c,
+            s_m: r_m + *message * c,
+        }
+    }
+
+    pub fn verify_opening(&self, proof: &OpeningProof) -> bool {
+        let pp = get_parameters();
+        let mut transcript = opening_transcript(self, None);
+        transcript.append_point(b"t", &proof.t);
+        let c = transcript.challenge_scalar(b"c");
+        &pp.g * proof.s_r + &pp.u * proof.s_m == &proof.t + &self.0 * c
+    }
+
+    /// Proves that the commitment opens to `message`, which the verifier knows, with knowledge
+    /// of the randomness of `opening`, which stays hidden.
+    pub fn prove_opening_to(
+        &self,
+        message: &Scalar,
+        opening: &Opening,
+        rng: &mut (impl RngCore + CryptoRng),
+    ) -> MessageOpeningProof {
+        let pp = get_parameters();
+        let r_r = Scalar::rand(rng);
+        let t = &pp.g * r_r;
+
+        let mut transcript = opening_transcript(self, Some(message));
+        transcript.append_point(b"t", &t);
+        let c = transcript.challenge_scalar(b"c");
+        MessageOpeningProof {
+            t,
+            s_r: r_r + opening.r * c,
+        }
+    }
+
+    /// Verifies a [MessageOpeningProof] that the commitment opens to `message`, i.e. that
+    /// `self / u^message` is a known power of `g`.
+    pub fn verify_opening_to(&self, message: &Scalar, proof: &MessageOpeningProof) -> bool {
+        let pp = get_parameters();
+        let mut transcript = opening_transcript(self, Some(message));
+        transcript.append_point(b"t", &proof.t);
+        let c = transcript.challenge_scalar(b"c");
+        &pp.g * proof.s_r == &proof.t + (&self.0 - &pp.u * *message) * c
     }
 
     pub fn verify(&self, message: &Scalar, opening: &Opening) -> Result<(), Error> {
@@ -219,6 +848,7 @@ impl Commitment {
         }
     }
 
+    #[cfg(feature = "std")]
     pub fn proof(&self, message: &Scalar, opening: &Opening) -> Proof {
         let mut rng = rand::thread_rng();
         let pp = get_parameters();
@@ -236,7 +866,8 @@ impl Commitment {
 
     fn verify_proof_with_challenge(&self, c: &Scalar, proof: &Proof) -> Result<(), Error> {
         let pp = get_parameters();
-        if &pp.g * proof.s_1 + &pp.u * proof.s_2 != &self.0 * *c + &proof.t {
+        let lhs = &pp.g * proof.s_1 + &pp.u * proof.s_2;
+        if !bool::from(lhs.ct_eq(&(&self.0 * *c + &proof.t))) {
             return Err(Error::InvalidProof);
         }
         Ok(())
@@ -257,29 +888,77 @@ impl Commitment {
         opening_2: &Opening,
         base: &G1G2,
         pk: &G1G2,
+        rng: &mut (impl RngCore + CryptoRng),
+    ) -> Proof2PK {
+        self.proof_2_pk_with_context(
+            message,
+            opening,
+            commitment_2,
+            message_2,
+            opening_2,
+            base,
+            pk,
+            &[],
+            rng,
+        )
+    }
+
+    /// Like [Commitment::proof_2_pk], but with `context` appended to the transcript, so that
+    /// the proof only verifies for the same context.
+    #[allow(clippy::too_many_arguments)]
+    pub fn proof_2_pk_with_context(
+        &self,
+        message: &Scalar,
+        opening: &Opening,
+        commitment_2: &Self,
+        message_2: &Scalar,
+        opening_2: &Opening,
+        base: &G1G2,
+        pk: &G1G2,
+        context: &[u8],
+        rng: &mut (impl RngCore + CryptoRng),
+    ) -> Proof2PK {
+        self.proof_2_pk_with_transcript(
+            message,
+            opening,
+            commitment_2,
+            message_2,
+            opening_2,
+            base,
+            pk,
+            &mut proof_2_pk_transcript(context),
+            rng,
+        )
+    }
+
+    /// Like [Commitment::proof_2_pk], but with the challenge derived from `transcript`, which
+    /// holds the public inputs of an enclosing proof.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn proof_2_pk_with_transcript(
+        &self,
+        message: &Scalar,
+        opening: &Opening,
+        commitment_2: &Self,
+        message_2: &Scalar,
+        opening_2: &Opening,
+        base: &G1G2,
+        pk: &G1G2,
+        transcript: &mut ProofTranscript,
+        rng: &mut (impl RngCore + CryptoRng),
     ) -> Proof2PK {
         let pp = get_parameters();
-        let mut rng = rand::thread_rng();
 
-        let r1_1 = Scalar::rand(&mut rng);
-        let r1_2 = Scalar::rand(&mut rng);
+        let r1_1 = Scalar::rand(rng);
+        let r1_2 = Scalar::rand(rng);
         let t1 = &pp.g * r1_1 + &pp.u * r1_2;
 
-        let r2_1 = Scalar::rand(&mut rng);
-        let r2_2 = Scalar::rand(&mut rng);
+        let r2_1 = Scalar::rand(rng);
+        let r2_2 = Scalar::rand(rng);
         let t2 = &pp.g * r2_1 + &pp.u * r2_2;
 
         let t3 = base * r2_2;
 
-        let mut hasher = hash_context();
-        hash_g1g2(&mut hasher, base);
-        hash_commitment(&mut hasher, self);
-        hash_commitment(&mut hasher, commitment_2);
-        hash_g1g2(&mut hasher, pk);
-        hash_g1g2(&mut hasher, &t1);
-        hash_g1g2(&mut hasher, &t2);
-        hash_g1g2(&mut hasher, &t3);
-        let c = hash_extract_scalar(hasher);
+        let c = challenge_proof_2_pk(transcript, self, commitment_2, base, pk, &t1, &t2, &t3);
 
         let s1_1 = r1_1 + opening.r * c;
         let s1_2 = r1_2 + *message * c;
@@ -308,26 +987,99 @@ impl Commitment {
         pk: &G1G2,
         proof: &Proof2PK,
     ) -> Result<(), Error> {
-        let mut hasher = hash_context();
-        hash_g1g2(&mut hasher, base);
-        hash_commitment(&mut hasher, self);
-        hash_commitment(&mut hasher, commitment_2);
-        hash_g1g2(&mut hasher, pk);
-        hash_g1g2(&mut hasher, &proof.pi_1.t);
-        hash_g1g2(&mut hasher, &proof.pi_2.t);
-        hash_g1g2(&mut hasher, &proof.t3);
-        let c = hash_extract_scalar(hasher);
+        self.verify_proof_2_pk_with_context(commitment_2, base, pk, &[], proof)
+    }
+
+    /// Verifies a proof of [Commitment::proof_2_pk_with_context] for `context`.
+    pub fn verify_proof_2_pk_with_context(
+        &self,
+        commitment_2: &Self,
+        base: &G1G2,
+        pk: &G1G2,
+        context: &[u8],
+        proof: &Proof2PK,
+    ) -> Result<(), Error> {
+        let c = challenge_proof_2_pk(
+            &mut proof_2_pk_transcript(context),
+            self,
+            commitment_2,
+            base,
+            pk,
+            &proof.pi_1.t,
+            &proof.pi_2.t,
+            &proof.t3,
+        );
 
         self.verify_proof_with_challenge(&c, &proof.pi_1)?;
         commitment_2.verify_proof_with_challenge(&c, &proof.pi_2)?;
 
-        if base * proof.pi_2.s_2 == pk * c + &proof.t3 {
+        // the verification equations are compared in constant time
+        if bool::from((base * proof.pi_2.s_2).ct_eq(&(pk * c + &proof.t3))) {
             Ok(())
         } else {
             Err(Error::InvalidProof)
         }
     }
 
+    /// Verifies a proof of [Commitment::proof_2_pk_with_transcript] for a `transcript` in the
+    /// state the prover started from. The three equations are combined with random 128-bit
+    /// scalars from `rng` into a single multi-scalar multiplication.
+    pub(crate) fn verify_proof_2_pk_with_transcript(
+        &self,
+        commitment_2: &Self,
+        base: &G1G2,
+        pk: &G1G2,
+        transcript: &mut ProofTranscript,
+        proof: &Proof2PK,
+        rng: &mut (impl RngCore + CryptoRng),
+    ) -> Result<(), Error> {
+        let c = challenge_proof_2_pk(
+            transcript,
+            self,
+            commitment_2,
+            base,
+            pk,
+            &proof.pi_1.t,
+            &proof.pi_2.t,
+            &proof.t3,
+        );
+
+        let mut batch = EquationBatch::with_capacity(1);
+        batch.push_proof_2_pk((self, commitment_2, base, pk), c, proof, rng);
+        if batch.holds() {
+            Ok(())
+        } else {
+            Err(Error::InvalidProof)
+        }
+    }
+
+    /// Verifies several [Proof2PK]s at once. The verification equations are combined with
+    /// random 128-bit scalars into a single multi-scalar multiplication, so the batch is accepted
+    /// only if all proofs verify (except with negligible probability). A failing batch does not
+    /// identify the invalid proofs, which [Proof2PK::verify_batch] does.
+    #[cfg(feature = "std")]
+    pub fn verify_proof_2_pk_batch(
+        statements: &[Proof2PKStatement<'_>],
+        proofs: &[&Proof2PK],
+    ) -> Result<(), Error> {
+        debug_assert_eq!(statements.len(), proofs.len());
+
+        let cs = challenges_proof_2_pk(statements, proofs);
+        let batch = EquationBatch::of_proofs_2_pk(
+            statements,
+            &cs,
+            proofs,
+            0..proofs.len(),
+            &mut rand::thread_rng(),
+        );
+        if batch.holds() {
+            Ok(())
+        } else {
+            Err(Error::InvalidProof)
+        }
+    }
+
+    #[cfg(feature = "std")]
     pub fn index_commit(
         value_0: &Scalar,
         idx: usize,
@@ -339,7 +1091,7 @@ impl Commitment {
         let r = Scalar::rand(&mut rand::thread_rng());
         let pp = get_parameters();
         (
-            Self(&pp.g * r + &pp.u * *value_0 + &multi_pp[idx] * *value_i),
+            Self(pp.commit_base(&r, value_0) + &multi_pp[idx] * *value_i),
             Opening { r },
         )
     }
@@ -363,6 +1115,7 @@ impl Commitment {
     }
 
     #[allow(clippy::too_many_arguments)]
+    #[cfg(feature = "std")]
     pub fn proof_index_commit(
         &self,
         message: &Scalar,
@@ -442,6 +1195,7 @@ impl Commitment {
         }
     }
 
+    #[cfg(feature = "std")]
     pub fn multi_index_commit<I>(
         value_0: &Scalar,
         iter: I,
@@ -454,7 +1208,7 @@ impl Commitment {
         let pp = get_parameters();
         let r = Scalar::rand(&mut rand::thread_rng());
         let cm = iter.fold(
-            &pp.g * r + &pp.u * (*value_0 * len),
+            pp.commit_base(&r, &(*value_0 * len)),
             |cm, (idx, value_i)| {
                 debug_assert!(idx < multi_pp.us.len());
                 cm + &multi_pp[idx] * value_i
@@ -492,6 +1246,7 @@ impl Commitment {
     }
 
     #[allow(clippy::too_many_arguments)]
+    #[cfg(feature = "std")]
     pub fn proof_multi_index_commit<I>(
         &self,
         value_0: &Scalar,
@@ -661,7 +1416,10 @@ impl Mul<Scalar> for &Commitment {
 
 #[cfg(test)]
 mod test {
+    use proptest::{prop_assert_eq, proptest};
+
     use super::*;
+    use crate::arbitrary;
 
     #[test]
     fn pedersen() {
@@ -670,6 +1428,20 @@ mod test {
         assert!(cm.verify(&msg, &o).is_ok());
     }
 
+    #[test]
+    fn precomputed_commitments_are_bit_exact() {
+        let mut rng = rand::thread_rng();
+        let pp = get_parameters();
+        let (msg, r) = (Scalar::rand(&mut rng), Scalar::rand(&mut rng));
+        let (cm, _) = Commitment::commit_with_randomness(&msg, &r);
+        assert_eq!(cm.0.to_bytes(), (&pp.g * r + &pp.u * msg).to_bytes());
+
+        let generators = MultiBasePublicParameters::new(2);
+        let messages = [msg, Scalar::rand(&mut rng)];
+        let (cm_vec, _) = Commitment::commit_vec(&messages, &r, &generators);
+        assert_eq!(cm_vec.0, cm.0 + &generators[0] * messages[1]);
+    }
+
     #[test]
     fn pedersen_proof() {
         let msg = Scalar::rand(&mut rand::thread_rng());
@@ -680,6 +1452,30 @@ mod test {
         assert!(cm.verify_proof(&proof).is_ok());
     }
 
+    #[test]
+    fn opening_proofs() {
+        let mut rng = rand::thread_rng();
+        let msg = Scalar::rand(&mut rng);
+        let (cm, o) = Commitment::commit(&msg);
+        assert!(cm.verify_opening(&cm.prove_opening(&msg, &o, &mut rng)));
+        assert!(cm.verify_opening_to(&msg, &cm.prove_opening_to(&msg, &o, &mut rng)));
+
+        // a wrong blinding, or a proof for another commitment
+        let wrong = Opening {
+            r: o.r + Scalar::from(1u64),
+        };
+        assert!(!cm.verify_opening(&cm.prove_opening(&msg, &wrong, &mut rng)));
+        assert!(!cm.verify_opening_to(&msg, &cm.prove_opening_to(&msg, &wrong, &mut rng)));
+        let (other, other_o) = Commitment::commit(&msg);
+        assert!(!cm.verify_opening(&other.prove_opening(&msg, &other_o, &mut rng)));
+
+        // a wrong public message, whether the prover uses it or not
+        let proof = cm.prove_opening_to(&msg, &o, &mut rng);
+        let other_msg = msg + Scalar::from(1u64);
+        assert!(!cm.verify_opening_to(&other_msg, &proof));
+        assert!(!cm.verify_opening_to(&other_msg, &cm.prove_opening_to(&other_msg, &o, &mut rng)));
+    }
+
     #[test]
     fn pedersen_proof_2_pk() {
         let mut rng = rand::thread_rng();
@@ -694,8 +1490,310 @@ mod test {
         let o_3 = msg_2;
         let pk = &pp.g * o_3;
 
-        let proof = cm_1.proof_2_pk(&msg_1, &o_1, &cm_2, &msg_2, &o_2, &pp.g, &pk);
+        let proof = cm_1.proof_2_pk(&msg_1, &o_1, &cm_2, &msg_2, &o_2, &pp.g, &pk, &mut rng);
         assert!(cm_1.verify_proof_2_pk(&cm_2, &pp.g, &pk, &proof).is_ok());
+        assert!(
+            cm_1.verify_proof_2_pk_with_context(&cm_2, &pp.g, &pk, &[], &proof)
+                .is_ok()
+        );
+
+        // a proof bound to a context verifies only for it
+        let proof = cm_1.proof_2_pk_with_context(
+            &msg_1, &o_1, &cm_2, &msg_2, &o_2, &pp.g, &pk, b"context", &mut rng,
+        );
+        assert!(
+            cm_1.verify_proof_2_pk_with_context(&cm_2, &pp.g, &pk, b"context", &proof)
+                .is_ok()
+        );
+        for context in [&b"other"[..], &[]] {
+            assert_eq!(
+                cm_1.verify_proof_2_pk_with_context(&cm_2, &pp.g, &pk, context, &proof),
+                Err(Error::InvalidProof)
+            );
+        }
+
+        // a prover transcript without the context or with an additional element is rejected
+        let omitting = ProofTranscript::new(PROOF_2PK_PROTOCOL);
+        let mut extended = proof_2_pk_transcript(b"context");
+        extended.append_point(b"pk", &pk);
+        for transcript in [omitting, extended] {
+            let proof = cm_1.proof_2_pk_with_transcript(
+                &msg_1,
+                &o_1,
+                &cm_2,
+                &msg_2,
+                &o_2,
+                &pp.g,
+                &pk,
+                &mut transcript.clone(),
+                &mut rng,
+            );
+            assert!(
+                cm_1.verify_proof_2_pk_with_transcript(
+                    &cm_2,
+                    &pp.g,
+                    &pk,
+                    &mut transcript,
+                    &proof,
+                    &mut rng
+                )
+                .is_ok()
+            );
+            assert_eq!(
+                cm_1.verify_proof_2_pk_with_context(&cm_2, &pp.g, &pk, b"context", &proof),
+                Err(Error::InvalidProof)
+            );
+        }
+    }
+
+    #[test]
+    fn batched_equations_reject_any_failing_one() {
+        let mut rng = rand::thread_rng();
+        let msg_1 = Scalar::rand(&mut rng);
+        let (cm_1, o_1) = Commitment::commit(&msg_1);
+        let msg_2 = Scalar::rand(&mut rng);
+        let (cm_2, o_2) = Commitment::commit(&msg_2);
+        let base = G1G2::random(&mut rng);
+        let pk = &base * msg_2;
+        // a key of another message only fails the third equation
+        let other_pk = &base * (msg_2 + Scalar::from(1u64));
+        let prove = |pk: &G1G2, rng: &mut _| {
+            cm_1.proof_2_pk(&msg_1, &o_1, &cm_2, &msg_2, &o_2, &base, pk, rng)
+        };
+
+        let proof = prove(&pk, &mut rng);
+        let mut first = prove(&pk, &mut rng);
+        first.pi_1.s_1 += Scalar::from(1u64);
+        let mut second = prove(&pk, &mut rng);
+        second.pi_2.s_1 += Scalar::from(1u64);
+        let third = prove(&other_pk, &mut rng);
+        for (key, invalid) in [(&pk, first), (&pk, second), (&other_pk, third)] {
+            for _ in 0..32 {
+                assert_eq!(
+                    cm_1.verify_proof_2_pk_with_transcript(
+                        &cm_2,
+                        &base,
+                        key,
+                        &mut proof_2_pk_transcript(&[]),
+                        &invalid,
+                        &mut rng
+                    ),
+                    Err(Error::InvalidProof)
+                );
+                assert_eq!(
+                    Commitment::verify_proof_2_pk_batch(
+                        &[
+                            (&cm_1, &cm_2, &base, &pk, &[][..]),
+                            (&cm_1, &cm_2, &base, key, &[][..])
+                        ],
+                        &[&proof, &invalid]
+                    ),
+                    Err(Error::InvalidProof)
+                );
+            }
+        }
+        assert_eq!(
+            cm_1.verify_proof_2_pk_with_transcript(
+                &cm_2,
+                &base,
+                &pk,
+                &mut proof_2_pk_transcript(&[]),
+                &proof,
+                &mut rng
+            ),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn pedersen_proof_2_pk_batch() {
+        let mut rng = rand::thread_rng();
+        let pp = get_parameters();
+
+        let statements: Vec<_> = (0..5)
+            .map(|_| {
+                let msg_1 = Scalar::rand(&mut rng);
+                let (cm_1, o_1) = Commitment::commit(&msg_1);
+                let msg_2 = Scalar::rand(&mut rng);
+                let (cm_2, o_2) = Commitment::commit(&msg_2);
+                let base = G1G2::random(&mut rng);
+                let pk = &base * msg_2;
+                let proof =
+                    cm_1.proof_2_pk(&msg_1, &o_1, &cm_2, &msg_2, &o_2, &base, &pk, &mut rng);
+                (cm_1, cm_2, base, pk, proof)
+            })
+            .collect();
+        let mut batch: Vec<_> = statements
+            .iter()
+            .map(|(cm_1, cm_2, base, pk, _)| (cm_1, cm_2, base, pk, &[][..]))
+            .collect();
+        let proofs: Vec<_> = statements.iter().map(|(.., proof)| proof).collect();
+        assert!(Commitment::verify_proof_2_pk_batch(&batch, &proofs).is_ok());
+
+        let other_pk = &pp.g * Scalar::rand(&mut rng);
+        batch[3].3 = &other_pk;
+        assert_eq!(
+            Commitment::verify_proof_2_pk_batch(&batch, &proofs),
+            Err(Error::InvalidProof)
+        );
+    }
+
+    #[test]
+    fn proof_2_pk_batch_locates_first_invalid_proof() {
+        let mut rng = rand::thread_rng();
+
+        let statements: Vec<_> = (0..7)
+            .map(|_| {
+                let msg_1 = Scalar::rand(&mut rng);
+                let (cm_1, o_1) = Commitment::commit(&msg_1);
+                let msg_2 = Scalar::rand(&mut rng);
+                let (cm_2, o_2) = Commitment::commit(&msg_2);
+                let base = G1G2::random(&mut rng);
+                let pk = &base * msg_2;
+                let proof =
+                    cm_1.proof_2_pk(&msg_1, &o_1, &cm_2, &msg_2, &o_2, &base, &pk, &mut rng);
+                (cm_1, cm_2, base, pk, proof)
+            })
+            .collect();
+        let batch: Vec<_> = statements
+            .iter()
+            .map(|(cm_1, cm_2, base, pk, _)| (cm_1, cm_2, base, pk, &[][..]))
+            .collect();
+        let proofs: Vec<_> = statements.iter().map(|(.., proof)| proof).collect();
+        assert_eq!(Proof2PK::verify_batch(&batch, &proofs, &mut rng), Ok(()));
+        assert_eq!(Proof2PK::verify_batch(&[], &[], &mut rng), Ok(()));
+        assert_eq!(
+            Proof2PK::verify_batch(&batch, &proofs[1..], &mut rng),
+            Err(BatchError::LengthMismatch {
+                statements: 7,
+                proofs: 6
+            })
+        );
+
+        // the proof of another statement at the first, middle and last position, and at two
+        for invalid in [&[0][..], &[3], &[6], &[2, 5]] {
+            let mut proofs = proofs.clone();
+            for &index in invalid {
+                proofs[index] = proofs[(index + 1) % proofs.len()];
+            }
+            assert_eq!(
+                Proof2PK::verify_batch(&batch, &proofs, &mut rng),
+                Err(BatchError::InvalidProof { index: invalid[0] })
+            );
+        }
+
+        // the batch takes 51 terms, and locating the last proof checks 3, 2 and 1 proofs
+        let mut budget = 51;
+        assert_eq!(
+            Proof2PK::verify_batch_within(&batch, &proofs, &mut budget, &mut rng),
+            Ok(())
+        );
+        assert_eq!(budget, 0);
+        let mut invalid = proofs.clone();
+        invalid[6] = proofs[0];
+        for (mut budget, result) in [
+            (50, Err(BatchError::CostExceeded)),
+            (98, Err(BatchError::CostExceeded)),
+            (99, Err(BatchError::InvalidProof { index: 6 })),
+        ] {
+            assert_eq!(
+                Proof2PK::verify_batch_within(&batch, &invalid, &mut budget, &mut rng),
+                result
+            );
+        }
+    }
+
+    #[test]
+    fn vector_pedersen() {
+        let mut rng = rand::thread_rng();
+        let generators = MultiBasePublicParameters::new(2);
+        let r = Scalar::rand(&mut rng);
+        let messages: Vec<_> = (0..3).map(|_| Scalar::rand(&mut rng)).collect();
+
+        let (cm, o) = Commitment::commit_vec(&messages, &r, &generators);
+        assert!(cm.verify_vec(&messages, &o, &generators).is_ok());
+        for i in 0..messages.len() {
+            let mut other = messages.clone();
+            other[i] += Scalar::from(1u64);
+            assert_eq!(
+                cm.verify_vec(&other, &o, &generators),
+                Err(Error::InvalidOpening)
+            );
+        }
+        assert_eq!(
+            cm.verify_vec(&[messages.clone(), messages].concat(), &o, &generators),
+            Err(Error::InvalidOpening)
+        );
+
+        // a single message is committed to as by commit_with_randomness, missing messages are
+        // zero
+        let (single, _) = Commitment::commit_vec(&[r], &r, &generators);
+        assert_eq!(single, Commitment::commit_with_randomness(&r, &r).0);
+        let zeros = [r, Scalar::zero(), Scalar::zero()];
+        assert!(single.verify_vec(&zeros, &o, &generators).is_ok());
+    }
+
+    #[test]
+    fn disclosure_proof() {
+        let mut rng = rand::thread_rng();
+        let generators = MultiBasePublicParameters::new(2);
+        let messages: Vec<_> = (0..3).map(|_| Scalar::rand(&mut rng)).collect();
+        let (cm, o) = Commitment::commit_vec(&messages, &Scalar::rand(&mut rng), &generators);
+
+        let disclosures: [&[usize]; 4] = [&[], &[1], &[2], &[1, 2]];
+        for disclose in disclosures {
+            let (proof, identity_opening) =
+                cm.prove_disclosure(&messages, &o, disclose, &generators, b"ctx", &mut rng);
+            assert_eq!(cm.verify_disclosure(&proof, &generators, b"ctx"), Ok(()));
+            assert!(
+                proof
+                    .identity()
+                    .verify(&messages[0], &identity_opening)
+                    .is_ok()
+            );
+            assert_eq!(proof.num_messages(), 3);
+            assert!(
+                disclose
+                    .iter()
+                    .all(|index| proof.revealed()[index] == messages[*index])
+            );
+            assert_eq!(
+                cm.verify_disclosure(&proof, &generators, b"other"),
+                Err(Error::InvalidProof)
+            );
+        }
+
+        let (mut proof, _) = cm.prove_disclosure(&messages, &o, &[1], &generators, b"", &mut rng);
+        *proof.revealed.get_mut(&1).unwrap() += Scalar::from(1u64);
+        assert_eq!(
+            cm.verify_disclosure(&proof, &generators, b""),
+            Err(Error::InvalidProof)
+        );
+
+        // the identity cannot be revealed, and the indices have to be in range
+        for index in [0, 3] {
+            let (mut proof, _) =
+                cm.prove_disclosure(&messages, &o, &[], &generators, b"", &mut rng);
+            proof.revealed.insert(index, messages[0]);
+            proof.s.pop();
+            assert_eq!(
+                cm.verify_disclosure(&proof, &generators, b""),
+                Err(Error::InvalidProof)
+            );
+        }
+
+        // the number of messages is the verifier's
+        let (mut proof, _) = cm.prove_disclosure(&messages, &o, &[], &generators, b"", &mut rng);
+        let more = MultiBasePublicParameters::new(3);
+        assert_eq!(
+            cm.verify_disclosure(&proof, &more, b""),
+            Err(Error::InvalidProof)
+        );
+        proof.s.pop();
+        assert_eq!(
+            cm.verify_disclosure(&proof, &generators, b""),
+            Err(Error::InvalidProof)
+        );
     }
 
     #[test]
@@ -795,4 +1893,11 @@ mod test {
                 .is_ok()
         );
     }
+
+    proptest! {
+        #[test]
+        fn commitment_encoding_round_trip(cm in arbitrary::commitment()) {
+            prop_assert_eq!(arbitrary::round_trip(&cm).unwrap(), cm);
+        }
+    }
 }
@@ -0,0 +1,393 @@
+//! Typed stages of the protocol, for integrators who wire it up for the first time. A
+//! [Deployment] registers users, who are then [Registered], broadcast as [Broadcasting], are
+//! [Diagnosed] and have their reports [Verified]. Every stage is only produced from the one before
+//! it, so the compiler enforces the order of the free functions of [spirit](crate::spirit), and
+//! [run_demo] runs the whole flow in memory.
+//!
+//! A user has to register before they are diagnosed:
+//!
+//! ```compile_fail
+//! use rand::rngs::OsRng;
+//! use spirit::{pipeline::Deployment, spirit::UserSecret};
+//!
+//! let mut deployment = Deployment::setup(3, 4, 4, b"demo", &mut OsRng).unwrap();
+//! let user = UserSecret::random(OsRng);
+//! user.diagnose(&mut deployment, 1, &mut OsRng);
+//! ```
+//!
+//! and only verified reports are traced:
+//!
+//! ```compile_fail
+//! use rand::rngs::OsRng;
+//! use spirit::{pipeline::Deployment, spirit::UserSecret};
+//!
+//! let mut deployment = Deployment::setup(3, 4, 4, b"demo", &mut OsRng).unwrap();
+//! let user = UserSecret::random(OsRng);
+//! let user = deployment.register(user, 2, &mut OsRng).unwrap().broadcasting();
+//! let diagnosed = user.diagnose(&mut deployment, 1, &mut OsRng).unwrap();
+//! user.trace(&[diagnosed], 1);
+//! ```
+//!
+//! whereas in order, the stages compile:
+//!
+//! ```no_run
+//! use rand::rngs::OsRng;
+//! use spirit::{
+//!     pipeline::Deployment,
+//!     spirit::{Interval, UserSecret},
+//! };
+//!
+//! let mut deployment = Deployment::setup(3, 4, 4, b"demo", &mut OsRng).unwrap();
+//! let mut alice = deployment
+//!     .register(UserSecret::random(OsRng), 2, &mut OsRng)
+//!     .unwrap()
+//!     .broadcasting();
+//! let mut bob = deployment
+//!     .register(UserSecret::random(OsRng), 2, &mut OsRng)
+//!     .unwrap()
+//!     .broadcasting();
+//! alice.broadcast(&deployment, 0, Interval(0), &mut bob, &mut OsRng);
+//! let diagnosed = alice.diagnose(&mut deployment, 1, &mut OsRng).unwrap();
+//! let verified = deployment.verify(diagnosed, 1).unwrap();
+//! assert!(bob.trace(&[verified], 1).alarm);
+//! ```
+
+use std::collections::HashSet;
+
+use rand::{CryptoRng, RngCore};
+use thiserror::Error;
+
+use crate::{
+    atact::{Issuer, PublicParameters, StRG},
+    elgamal::EncryptionKey,
+    registry::RevocationList,
+    spirit::{
+        ChallengeSet, DomainConfig, ElID, EncounterTable, Epoch, Interval, Nullifier, SetupOutput,
+        SpiritError, Token, TokenId, TraceReport, TraceResult, UserSecret, VerifyRejection,
+        spirit_broadcast, spirit_diagnosis, spirit_register, spirit_setup, spirit_trace,
+        spirit_verify,
+    },
+};
+
+const T: usize = 3;
+const N: usize = 4;
+const NUM_ISSUERS: usize = 4;
+
+/// The issuers, the registrar, the health authority and the verifier of a deployment in one
+/// place, with the issuers' keys and the registrar's state in memory.
+pub struct Deployment {
+    domain: DomainConfig,
+    pp: PublicParameters,
+    issuers: Vec<Issuer>,
+    pk_ha: EncryptionKey,
+    t_rgstr: HashSet<Token>,
+    revoked: RevocationList,
+    nullifiers: HashSet<Nullifier>,
+    challenges: ChallengeSet,
+}
+
+impl Deployment {
+    /// A deployment of [spirit_setup] with `num_issuers` issuers, `t` of which sign a token.
+    pub fn setup(
+        t: usize,
+        n: usize,
+        num_issuers: usize,
+        app_id: &[u8],
+        rng: &mut (impl RngCore + CryptoRng),
+    ) -> Result<Self, SpiritError> {
+        let SetupOutput {
+            pp,
+            issuers,
+            pk_ha,
+            t_rgstr,
+            domain,
+            ..
+        } = spirit_setup(t, n, num_issuers, app_id, rng)?;
+        Ok(Self {
+            domain,
+            pp,
+            issuers,
+            pk_ha,
+            t_rgstr,
+            revoked: RevocationList::new(),
+            nullifiers: HashSet::new(),
+            challenges: ChallengeSet::new(0),
+        })
+    }
+
+    pub fn domain(&self) -> &DomainConfig {
+        &self.domain
+    }
+
+    /// Registers `user` with [spirit_register], for a token that expires at the start of epoch
+    /// `expiry`.
+    pub fn register(
+        &mut self,
+        user: UserSecret,
+        expiry: Epoch,
+        rng: &mut (impl RngCore + CryptoRng),
+    ) -> Result<Registered, SpiritError> {
+        let ppu = spirit_register(
+            &self.domain,
+            &user,
+            &self.issuers,
+            &self.pp,
+            expiry,
+            &mut self.t_rgstr,
+            rng,
+        )?;
+        Ok(Registered { user, ppu })
+    }
+
+    /// Verifies the report of `diagnosed` with [spirit_verify] in the verifier's current epoch
+    /// `current_epoch`.
+    pub fn verify(
+        &mut self,
+        diagnosed: Diagnosed,
+        current_epoch: Epoch,
+    ) -> Result<Verified, VerifyRejection> {
+        let mut el_ids = HashSet::new();
+        spirit_verify(
+            &self.domain,
+            &diagnosed.report,
+            &self.t_rgstr,
+            &self.revoked,
+            current_epoch,
+            &mut self.nullifiers,
+            &self.challenges,
+            &mut el_ids,
+        )?;
+        Ok(Verified { el_ids })
+    }
+}
+
+/// A user with a registered token, see [Deployment::register].
+pub struct Registered {
+    user: UserSecret,
+    ppu: (Token, StRG),
+}
+
+impl Registered {
+    pub fn token_id(&self) -> TokenId {
+        self.ppu.0.id()
+    }
+
+    /// The user with an empty contact period and encounter table, ready to broadcast.
+    pub fn broadcasting(self) -> Broadcasting {
+        Broadcasting {
+            registered: self,
+            cp: HashSet::new(),
+            t_el: EncounterTable::new(),
+        }
+    }
+}
+
+/// A registered user who broadcasts their ElIDs, with the ElIDs they sent and the encounters they
+/// received.
+pub struct Broadcasting {
+    registered: Registered,
+    cp: HashSet<(Epoch, Interval)>,
+    t_el: EncounterTable,
+}
+
+impl Broadcasting {
+    /// Broadcasts the ElID of interval `i` of `epoch` to `receiver` with [spirit_broadcast], and
+    /// adds it to the user's contact period.
+    pub fn broadcast(
+        &mut self,
+        deployment: &Deployment,
+        epoch: Epoch,
+        i: Interval,
+        receiver: &mut Broadcasting,
+        rng: &mut (impl RngCore + CryptoRng),
+    ) -> ElID {
+        let (el_id, _) = spirit_broadcast(
+            &deployment.domain,
+            epoch,
+            i,
+            self.registered.user.keys(),
+            &deployment.pk_ha,
+            &mut receiver.t_el,
+            rng,
+        );
+        self.cp.insert((epoch, i));
+        el_id
+    }
+
+    /// The encounters the user received.
+    pub fn encounters(&self) -> &EncounterTable {
+        &self.t_el
+    }
+
+    /// The trace report of the user's contact period in `report_epoch` with [spirit_diagnosis],
+    /// for a challenge that `deployment` issues. The user keeps broadcasting and tracing.
+    pub fn diagnose(
+        &self,
+        deployment: &mut Deployment,
+        report_epoch: Epoch,
+        rng: &mut (impl RngCore + CryptoRng),
+    ) -> Result<Diagnosed, SpiritError> {
+        let challenge = deployment.challenges.issue(report_epoch, rng);
+        let report = spirit_diagnosis(
+            &deployment.domain,
+            &self.registered.ppu,
+            self.registered.user.keys(),
+            None,
+            &self.cp,
+            report_epoch,
+            &challenge,
+            &[],
+            rng,
+        )?;
+        Ok(Diagnosed { report })
+    }
+
+    /// Traces the user's encounters against the ElIDs of the `verified` reports with
+    /// [spirit_trace].
+    pub fn trace(&self, verified: &[Verified], exposure_limit: usize) -> TraceResult {
+        let cf = verified.iter().flat_map(|verified| &verified.el_ids);
+        spirit_trace(cf, &self.t_el, exposure_limit)
+    }
+}
+
+/// The trace report of a diagnosed user, see [Broadcasting::diagnose].
+pub struct Diagnosed {
+    report: TraceReport,
+}
+
+impl Diagnosed {
+    pub fn report(&self) -> &TraceReport {
+        &self.report
+    }
+}
+
+/// The confirmed ElIDs of an accepted report, see [Deployment::verify].
+pub struct Verified {
+    el_ids: HashSet<ElID>,
+}
+
+impl Verified {
+    pub fn el_ids(&self) -> &HashSet<ElID> {
+        &self.el_ids
+    }
+}
+
+#[derive(Error, Debug, PartialEq, Clone)]
+pub enum DemoError {
+    #[error("Protocol failure: {0}")]
+    Protocol(#[from] SpiritError),
+    #[error("The trace report of the diagnosed user is rejected: {0}")]
+    Rejected(#[from] VerifyRejection),
+}
+
+/// Runs the protocol for `users` users over `days` days in memory, and returns the trace result
+/// of every user with an exposure limit of 1. On every day, users `u` and `u + 1` meet and
+/// broadcast the ElID of index 0 of the day to each other. User 0 is then diagnosed with the
+/// ElIDs of all days, so over more than 0 days, user 1 has a match for every day and is the
+/// only one with an alarm.
+pub fn run_demo(
+    users: usize,
+    days: usize,
+    rng: &mut (impl RngCore + CryptoRng),
+) -> Result<Vec<TraceResult>, DemoError> {
+    let mut deployment = Deployment::setup(T, N, NUM_ISSUERS, b"demo", rng)?;
+    let report_epoch = days as Epoch;
+    let mut broadcasting = (0..users)
+        .map(|_| {
+            let user = UserSecret::random(&mut *rng);
+            let registered = deployment.register(user, report_epoch + 1, rng)?;
+            Ok(registered.broadcasting())
+        })
+        .collect::<Result<Vec<_>, SpiritError>>()?;
+
+    for day in 0..report_epoch {
+        for u in 1..users {
+            let (met, rest) = broadcasting.split_at_mut(u);
+            let (a, b) = (&mut met[u - 1], &mut rest[0]);
+            a.broadcast(&deployment, day, Interval(0), b, rng);
+            b.broadcast(&deployment, day, Interval(0), a, rng);
+        }
+    }
+
+    let verified = match broadcasting.first() {
+        Some(diagnosed) => {
+            let diagnosed = diagnosed.diagnose(&mut deployment, report_epoch, rng)?;
+            vec![deployment.verify(diagnosed, report_epoch)?]
+        }
+        None => Vec::new(),
+    };
+    Ok(broadcasting
+        .iter()
+        .map(|user| user.trace(&verified, 1))
+        .collect())
+}
+
+#[cfg(test)]
+mod test {
+    use rand::rngs::OsRng;
+
+    use super::*;
+
+    #[test]
+    fn the_demo_alarms_the_contact_of_the_diagnosed_user() {
+        let results = run_demo(3, 2, &mut OsRng).expect("demo failed");
+        let counts: Vec<_> = results.iter().map(|result| result.count).collect();
+        assert_eq!(counts, [0, 2, 0]);
+        let alarms: Vec<_> = results.iter().map(|result| result.alarm).collect();
+        assert_eq!(alarms, [false, true, false]);
+
+        assert_eq!(run_demo(0, 2, &mut OsRng), Ok(Vec::new()));
+        let results = run_demo(2, 0, &mut OsRng).expect("demo failed");
+        assert!(results.iter().all(|result| !result.alarm));
+    }
+
+    #[test]
+    fn replayed_and_revoked_reports_are_rejected() {
+        let mut deployment =
+            Deployment::setup(T, N, NUM_ISSUERS, b"test", &mut OsRng).expect("setup failed");
+        let mut users: Vec<_> = (0..2)
+            .map(|_| {
+                deployment
+                    .register(UserSecret::random(OsRng), 2, &mut OsRng)
+                    .expect("registration failed")
+                    .broadcasting()
+            })
+            .collect();
+        let [alice, bob] = &mut users[..] else {
+            unreachable!()
+        };
+        alice.broadcast(&deployment, 0, Interval(0), bob, &mut OsRng);
+        alice.broadcast(&deployment, 0, Interval(1), bob, &mut OsRng);
+        bob.broadcast(&deployment, 0, Interval(0), alice, &mut OsRng);
+        assert_eq!(bob.encounters().len(), 2);
+
+        // a second report of the same epoch reuses the nullifier
+        let diagnose = |user: &Broadcasting, deployment: &mut Deployment| {
+            user.diagnose(deployment, 1, &mut OsRng)
+                .expect("diagnosis failed")
+        };
+        let report = diagnose(alice, &mut deployment);
+        let replayed = diagnose(alice, &mut deployment);
+        let verified = deployment.verify(report, 1).expect("verification failed");
+        assert_eq!(verified.el_ids().len(), 2);
+        assert_eq!(bob.trace(&[verified], 2).count, 2);
+        assert!(matches!(
+            deployment.verify(replayed, 1),
+            Err(VerifyRejection::ReplayedNullifier { .. })
+        ));
+
+        // a report verified after the token expired
+        let report = diagnose(bob, &mut deployment);
+        assert!(matches!(
+            deployment.verify(report, 2),
+            Err(VerifyRejection::Expired { expiry: 2, .. })
+        ));
+
+        deployment.revoked.revoke_id(bob.registered.token_id());
+        let report = diagnose(bob, &mut deployment);
+        assert!(matches!(
+            deployment.verify(report, 1),
+            Err(VerifyRejection::Revoked { .. })
+        ));
+    }
+}
@@ -0,0 +1,304 @@
+//! Pre-issued tokens, so that a registrar-side service hides the latency of threshold issuance
+//! from its users: [preissue_pool] runs the issuance for placeholder identities ahead of time,
+//! and [claim_token] hands one of the tokens to a user and only then registers it.
+//!
+//! The issuers' signature cannot be moved to another commitment, so a pre-issued token commits
+//! to a placeholder identity that the pool draws, not to the user's `id_u`. The service thus
+//! knows the opening of every token it hands out and has to be trusted to forget it: whoever
+//! keeps an opening can submit and link the trace reports of the token's user. Users who do not
+//! trust the service register with [spirit_register](crate::spirit::spirit_register). The user
+//! proves knowledge of `id_u` in a [TokenClaim] for the token, and the claim binds the token to
+//! `id_u` in a [ClaimIndex], by which every identity claims one token.
+//!
+//! Unclaimed tokens are not registered, so [spirit_verify](crate::spirit::spirit_verify) rejects
+//! their reports. Deployments that verify the issuers' signature instead, see
+//! [spirit_verify_rerandomized](crate::spirit::spirit_verify_rerandomized), accept any issued
+//! token and must not pre-issue tokens.
+
+use std::collections::HashSet;
+
+use ark_ff::UniformRand;
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
+use rand::{CryptoRng, RngCore};
+
+use crate::{
+    G1G2, Scalar,
+    atact::{Issuer, PublicParameters, StRG},
+    constants::TOKEN_CLAIM_PROTOCOL,
+    fiat_shamir::ProofTranscript,
+    registry::TokenRegistry,
+    spirit::{
+        DomainConfig, Epoch, SpiritError, Token, TokenId, UserSecret, identity_tag,
+        issue_verified_blind_tokens, spirit_register_begin, spirit_register_finish_from,
+        verify_registration,
+    },
+};
+
+/// Token issued for a placeholder identity by [preissue_pool], which is registered when it is
+/// [claimed](claim_token). It gives no access to the token or its opening before.
+pub struct PendingToken {
+    ppu: (Token, StRG),
+}
+
+impl PendingToken {
+    /// Identifier of the token that the claim registers.
+    pub fn id(&self) -> TokenId {
+        self.ppu.0.id()
+    }
+}
+
+/// Claim of the pre-issued token with the [id](PendingToken::id) `token` by a user: the tag
+/// `H_id^id_u` of the user's identity with a Schnorr proof of knowledge of `id_u`, bound to the
+/// token, so the claim of one token cannot be replayed for another. The registrar also checks
+/// one for a registered token before it issues its
+/// [receipt](crate::registry::RegistrarKeypair::issue_receipt).
+#[derive(CanonicalSerialize, CanonicalDeserialize)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TokenClaim {
+    pub(crate) tag: G1G2,
+    pub(crate) t: G1G2,
+    #[cfg_attr(
+        feature = "serde",
+        serde(with = "crate::bls381_helpers::serde_compressed")
+    )]
+    pub(crate) s: Scalar,
+}
+
+impl TokenClaim {
+    pub fn prove(
+        domain: &DomainConfig,
+        token: &TokenId,
+        user: &UserSecret,
+        rng: &mut (impl RngCore + CryptoRng),
+    ) -> Self {
+        let tag = identity_tag(domain, &user.id_u);
+        let rho = Scalar::rand(rng);
+        let t = identity_tag(domain, &rho);
+        let c = token_claim_challenge(domain, token, &tag, &t);
+        Self {
+            tag,
+            t,
+            s: rho + c * user.id_u,
+        }
+    }
+
+    /// Checks the proof of knowledge of the identity of the tag, for the token `token`.
+    pub fn verify(&self, domain: &DomainConfig, token: &TokenId) -> Result<(), SpiritError> {
+        let c = token_claim_challenge(domain, token, &self.tag, &self.t);
+        if identity_tag(domain, &self.s) == &self.t + &self.tag * c {
+            Ok(())
+        } else {
+            Err(SpiritError::InvalidTokenClaim)
+        }
+    }
+}
+
+fn token_claim_challenge(domain: &DomainConfig, token: &TokenId, tag: &G1G2, t: &G1G2) -> Scalar {
+    let mut transcript = ProofTranscript::new(TOKEN_CLAIM_PROTOCOL);
+    transcript.append_message(b"deployment", &domain.app_id);
+    transcript.append_message(b"token", token);
+    transcript.append_point(b"tag", tag);
+    transcript.append_point(b"t", t);
+    transcript.challenge_scalar(b"c")
+}
+
+/// The identities that claimed a pre-issued token, by their tag `H_id^id_u` as in an
+/// [IdentityClaim](crate::spirit::IdentityClaim).
+#[derive(Debug, Clone, Default)]
+pub struct ClaimIndex {
+    tags: HashSet<G1G2>,
+}
+
+impl ClaimIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn contains(&self, domain: &DomainConfig, id_u: &Scalar) -> bool {
+        self.tags.contains(&identity_tag(domain, id_u))
+    }
+
+    /// Number of claimed tokens.
+    pub fn len(&self) -> usize {
+        self.tags.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tags.is_empty()
+    }
+}
+
+/// Runs the registration of `count` placeholder identities with `issuers`, including the check
+/// of the token proofs, for tokens that expire at the start of epoch `expiry`. Issuers that
+/// return invalid blind tokens are replaced as in
+/// [spirit_register_with_issuers](crate::spirit::spirit_register_with_issuers).
+pub fn preissue_pool(
+    domain: &DomainConfig,
+    issuers: &[Issuer],
+    pp: &PublicParameters,
+    expiry: Epoch,
+    count: usize,
+    rng: &mut (impl RngCore + CryptoRng),
+) -> Result<Vec<PendingToken>, SpiritError> {
+    (0..count)
+        .map(|_| {
+            let placeholder = Scalar::rand(rng);
+            let (blind_request, pending) =
+                spirit_register_begin(domain, &placeholder, pp, expiry, rng)?;
+            let blind_tokens = issue_verified_blind_tokens(&blind_request, issuers, pp)?;
+            let (ppu, token, token_proof) =
+                spirit_register_finish_from(domain, pending, &blind_tokens, pp, rng)?;
+            verify_registration(
+                domain,
+                &blind_request,
+                &[],
+                &token,
+                &token_proof,
+                pp,
+                expiry,
+            )?;
+            Ok(PendingToken { ppu })
+        })
+        .collect()
+}
+
+/// Hands `pending` to the user who made `claim` for it: records the user's identity in
+/// `claims`, registers the token in `t_rgstr`, and returns the user's `(Token, StRG)` pair. The
+/// registrar learns the tag of the identity, but not `id_u`. Fails with
+/// [SpiritError::InvalidTokenClaim] if the claim does not verify for the token, and with
+/// [SpiritError::AlreadyRegistered] if the identity claimed a token before or the token is
+/// registered, e.g. when a persisted pool is claimed from twice.
+pub fn claim_token(
+    domain: &DomainConfig,
+    pending: PendingToken,
+    claim: &TokenClaim,
+    claims: &mut ClaimIndex,
+    t_rgstr: &mut impl TokenRegistry,
+) -> Result<(Token, StRG), SpiritError> {
+    claim.verify(domain, &pending.id())?;
+    if claims.tags.contains(&claim.tag) {
+        return Err(SpiritError::AlreadyRegistered);
+    }
+    let registered = t_rgstr
+        .insert(pending.ppu.0.clone())
+        .map_err(SpiritError::RegistryUpdateFailed)?;
+    if !registered {
+        return Err(SpiritError::AlreadyRegistered);
+    }
+    claims.tags.insert(claim.tag.clone());
+    Ok(pending.ppu)
+}
+
+#[cfg(test)]
+mod test {
+    use rand::rngs::OsRng;
+
+    use super::*;
+    use crate::{
+        registry::RevocationList,
+        spirit::{
+            ChallengeSet, SetupOutput, VerifyRejection, spirit_diagnosis_osrng, spirit_setup_osrng,
+            spirit_verify,
+        },
+    };
+
+    const NOW: Epoch = 10;
+    const EXPIRY: Epoch = 100;
+
+    #[test]
+    fn claimed_tokens_are_registered_once() {
+        let SetupOutput {
+            pp,
+            issuers,
+            mut t_rgstr,
+            domain,
+            ..
+        } = spirit_setup_osrng(3, 4, 4, b"test").expect("setup failed");
+        let mut pool = preissue_pool(&domain, &issuers, &pp, EXPIRY, 3, &mut OsRng)
+            .expect("pre-issuance failed");
+        assert_eq!(pool.len(), 3);
+        assert!(t_rgstr.is_empty());
+
+        // an unclaimed token is rejected, as it is not registered
+        let mut challenges = ChallengeSet::new(0);
+        let challenge = challenges.issue(NOW, &mut OsRng);
+        let cp = HashSet::from([(NOW, 0)]);
+        let user = UserSecret::random(OsRng);
+        let unclaimed = pool.pop().unwrap();
+        let report = spirit_diagnosis_osrng(
+            &domain,
+            &unclaimed.ppu,
+            user.keys(),
+            &cp,
+            NOW,
+            &challenge,
+            &[],
+        )
+        .expect("diagnosis failed");
+        let verify = |t_rgstr: &HashSet<Token>| {
+            spirit_verify(
+                &domain,
+                &report,
+                t_rgstr,
+                &RevocationList::new(),
+                NOW,
+                &mut HashSet::new(),
+                &challenges,
+                &mut HashSet::new(),
+            )
+        };
+        assert_eq!(
+            verify(&t_rgstr),
+            Err(VerifyRejection::Unregistered {
+                token: unclaimed.id()
+            })
+        );
+
+        // a claim made for another token, and one without knowledge of the identity, are
+        // rejected
+        let mut claims = ClaimIndex::new();
+        let token = unclaimed.id();
+        let misdirected = TokenClaim::prove(&domain, &pool[0].id(), &user, &mut OsRng);
+        let forged = TokenClaim {
+            tag: identity_tag(&domain, &user.id_u),
+            ..TokenClaim::prove(&domain, &token, &UserSecret::random(OsRng), &mut OsRng)
+        };
+        for claim in [&misdirected, &forged] {
+            let pending = PendingToken {
+                ppu: unclaimed.ppu.clone(),
+            };
+            assert!(matches!(
+                claim_token(&domain, pending, claim, &mut claims, &mut t_rgstr),
+                Err(SpiritError::InvalidTokenClaim)
+            ));
+        }
+        assert!(claims.is_empty() && t_rgstr.is_empty());
+
+        // the claimed token is accepted
+        let claim = TokenClaim::prove(&domain, &token, &user, &mut OsRng);
+        let (claimed, _) = claim_token(&domain, unclaimed, &claim, &mut claims, &mut t_rgstr)
+            .expect("claim failed");
+        assert_eq!(claimed.id(), token);
+        assert!(claims.contains(&domain, &user.id_u));
+        assert!(verify(&t_rgstr).is_ok());
+
+        // a second claim of the identity, and of a registered token, are rejected
+        let second = pool.pop().unwrap();
+        let claim = TokenClaim::prove(&domain, &second.id(), &user, &mut OsRng);
+        assert!(matches!(
+            claim_token(&domain, second, &claim, &mut claims, &mut t_rgstr),
+            Err(SpiritError::AlreadyRegistered)
+        ));
+        let replayed = PendingToken {
+            ppu: (claimed.clone(), pool[0].ppu.1.clone()),
+        };
+        let other = UserSecret::random(OsRng);
+        let claim = TokenClaim::prove(&domain, &token, &other, &mut OsRng);
+        assert!(matches!(
+            claim_token(&domain, replayed, &claim, &mut claims, &mut t_rgstr),
+            Err(SpiritError::AlreadyRegistered)
+        ));
+        assert!(!claims.contains(&domain, &other.id_u));
+        assert_eq!((claims.len(), t_rgstr.len()), (1, 1));
+    }
+}
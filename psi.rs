@@ -0,0 +1,482 @@
+use std::collections::{HashMap, HashSet};
+
+use ark_ff::{Field, UniformRand, Zero};
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
+use rand::{CryptoRng, RngCore, seq::SliceRandom};
+use thiserror::Error;
+
+use crate::{
+    G1G2, Scalar,
+    registry::{RegistryView, RevocationList},
+    spirit::{
+        ChallengeSet, DomainConfig, ElID, EncounterTable, Epoch, TokenId, TraceReport,
+        VerifyRejection, spirit_verify,
+    },
+};
+
+/// Nonzero scalar, so that blinding is a permutation of the group.
+fn random_exponent(rng: &mut (impl RngCore + CryptoRng)) -> Scalar {
+    loop {
+        let exponent = Scalar::rand(rng);
+        if !exponent.is_zero() {
+            return exponent;
+        }
+    }
+}
+
+/// Client's encounter ElIDs `el_id^c` under its secret exponent `c`, shuffled.
+#[derive(Debug, Clone, PartialEq, Eq, CanonicalSerialize, CanonicalDeserialize)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ClientQuery {
+    pub(crate) blinded_el_ids: Vec<G1G2>,
+}
+
+/// Server's answer to a [ClientQuery]: the confirmed ElIDs `el_id^s` under the server's secret
+/// exponent `s`, and the query's ElIDs `el_id^(c s)` raised to `s` as well, both shuffled.
+#[derive(Debug, Clone, PartialEq, Eq, CanonicalSerialize, CanonicalDeserialize)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ServerResponse {
+    pub(crate) blinded_cf: Vec<G1G2>,
+    pub(crate) blinded_query: Vec<G1G2>,
+}
+
+/// Server side of the private trace: instead of publishing the confirmed ElIDs `cf`, the
+/// server answers [ClientQuery]s, from which a client learns only how many of its encounters
+/// are confirmed. The client learns `el_id^s` for the confirmed ElIDs, which do not reveal
+/// them, and the server learns only the size of the client's encounter table.
+///
+/// Privacy holds against a semi-honest server and client under the decisional
+/// Diffie-Hellman assumption in G1 and G2.
+pub struct TraceServer {
+    s: Scalar,
+    blinded_cf: Vec<G1G2>,
+}
+
+impl TraceServer {
+    /// Blinds the distinct ElIDs of `cf` with a fresh exponent `s`.
+    pub fn prepare<'a>(
+        cf: impl IntoIterator<Item = &'a ElID>,
+        rng: &mut (impl RngCore + CryptoRng),
+    ) -> Self {
+        let s = random_exponent(rng);
+        let cf: HashSet<_> = cf.into_iter().collect();
+        let mut blinded_cf: Vec<_> = cf.into_iter().map(|el_id| el_id * s).collect();
+        blinded_cf.shuffle(rng);
+        Self { s, blinded_cf }
+    }
+
+    pub fn respond(
+        &self,
+        query: &ClientQuery,
+        rng: &mut (impl RngCore + CryptoRng),
+    ) -> ServerResponse {
+        let mut blinded_query: Vec<_> = query
+            .blinded_el_ids
+            .iter()
+            .map(|el_id| el_id * self.s)
+            .collect();
+        blinded_query.shuffle(rng);
+        ServerResponse {
+            blinded_cf: self.blinded_cf.clone(),
+            blinded_query,
+        }
+    }
+}
+
+/// Limits of the queries a [TraceService] answers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TraceLimits {
+    /// Queries a client can make at once, the capacity of its bucket.
+    pub burst: u32,
+    /// Queries added to a client's bucket at the start of every epoch, up to `burst`. With 0,
+    /// a client has no queries left after its first `burst` ones.
+    pub queries_per_epoch: u32,
+    /// ElIDs of a query, which has to fit the encounter table of a user.
+    pub max_el_ids: usize,
+}
+
+impl Default for TraceLimits {
+    fn default() -> Self {
+        Self {
+            burst: 4,
+            queries_per_epoch: 2,
+            max_el_ids: 10_000,
+        }
+    }
+}
+
+#[derive(Error, Debug, PartialEq, Eq, Clone)]
+pub enum TraceServiceError {
+    #[error("Query of {count} ElIDs, at most {max} are answered.")]
+    TooManyElIds { count: usize, max: usize },
+    #[error("The client's report is rejected: {0}")]
+    Unauthorized(#[source] VerifyRejection),
+    #[error("Token {token:02x?} has no queries left before epoch {retry_at}.")]
+    RateLimited { token: TokenId, retry_at: Epoch },
+}
+
+// the token bucket of a client, last refilled at the start of `epoch`
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    queries: u32,
+    epoch: Epoch,
+}
+
+/// [TraceServer] of the confirmed ElIDs that answers registered clients only, within
+/// [TraceLimits]. Without limits, a client can find the confirmed ElIDs among any candidates,
+/// e.g. ElIDs it overheard, by querying them one at a time.
+///
+/// A client authenticates every query with a [TraceReport] that [spirit_verify] accepts for the
+/// current epoch, e.g. of one of its own broadcasts, and its queries are counted against the
+/// report's token. The service thus learns which registered token queries, and the report's
+/// disclosures are not confirmed. Reports are bound to the challenges of the service, so reports
+/// submitted elsewhere do not authenticate clients.
+///
+/// The server's exponent is drawn anew in the first query of every epoch, so the answers of
+/// different epochs are not comparable and clients cannot accumulate them.
+pub struct TraceService {
+    cf: HashSet<ElID>,
+    limits: TraceLimits,
+    epoch: Epoch,
+    server: TraceServer,
+    buckets: HashMap<TokenId, Bucket>,
+}
+
+impl TraceService {
+    /// Service of the distinct ElIDs of `cf` in epoch `epoch`.
+    pub fn new(
+        cf: impl IntoIterator<Item = ElID>,
+        limits: TraceLimits,
+        epoch: Epoch,
+        rng: &mut (impl RngCore + CryptoRng),
+    ) -> Self {
+        let cf: HashSet<_> = cf.into_iter().collect();
+        Self {
+            server: TraceServer::prepare(&cf, rng),
+            cf,
+            limits,
+            epoch,
+            buckets: HashMap::new(),
+        }
+    }
+
+    pub fn limits(&self) -> &TraceLimits {
+        &self.limits
+    }
+
+    /// Adds newly confirmed ElIDs, which the following queries are answered for.
+    pub fn confirm(
+        &mut self,
+        el_ids: impl IntoIterator<Item = ElID>,
+        rng: &mut (impl RngCore + CryptoRng),
+    ) {
+        for el_id in el_ids {
+            if self.cf.insert(el_id.clone()) {
+                self.server.blinded_cf.push(el_id * self.server.s);
+            }
+        }
+        self.server.blinded_cf.shuffle(rng);
+    }
+
+    /// Answers `query` of the client authenticated by `report` at `current_epoch`, with the
+    /// registry, revocations and challenges of [spirit_verify]. Fails with
+    /// [TraceServiceError::TooManyElIds] for a query beyond [TraceLimits::max_el_ids], with
+    /// [TraceServiceError::Unauthorized] if the report is rejected, and with
+    /// [TraceServiceError::RateLimited] if the report's token has no queries left.
+    #[allow(clippy::too_many_arguments)]
+    pub fn respond<'a>(
+        &mut self,
+        domain: &DomainConfig,
+        report: &TraceReport,
+        t_rgstr: impl Into<RegistryView<'a>>,
+        revoked: &RevocationList,
+        challenges: &ChallengeSet,
+        query: &ClientQuery,
+        current_epoch: Epoch,
+        rng: &mut (impl RngCore + CryptoRng),
+    ) -> Result<ServerResponse, TraceServiceError> {
+        let count = query.blinded_el_ids.len();
+        if count > self.limits.max_el_ids {
+            return Err(TraceServiceError::TooManyElIds {
+                count,
+                max: self.limits.max_el_ids,
+            });
+        }
+        if current_epoch > self.epoch {
+            self.epoch = current_epoch;
+            self.server = TraceServer::prepare(&self.cf, rng);
+        }
+        // the report authenticates the client, its disclosures and nullifier are not kept
+        spirit_verify(
+            domain,
+            report,
+            t_rgstr,
+            revoked,
+            self.epoch,
+            &mut HashSet::new(),
+            challenges,
+            &mut HashSet::new(),
+        )
+        .map_err(TraceServiceError::Unauthorized)?;
+
+        let token = report.token.id();
+        let (limits, epoch) = (self.limits, self.epoch);
+        let bucket = self.buckets.entry(token).or_insert(Bucket {
+            queries: limits.burst,
+            epoch,
+        });
+        let refilled = (epoch - bucket.epoch).saturating_mul(u64::from(limits.queries_per_epoch));
+        bucket.queries = (u64::from(bucket.queries) + refilled).min(u64::from(limits.burst)) as u32;
+        bucket.epoch = epoch;
+        if bucket.queries == 0 {
+            return Err(TraceServiceError::RateLimited {
+                token,
+                retry_at: epoch + 1,
+            });
+        }
+        bucket.queries -= 1;
+        Ok(self.server.respond(query, rng))
+    }
+}
+
+/// Client side of the private trace, see [TraceServer]. A client is used for one query.
+pub struct TraceClient {
+    c: Scalar,
+    exposure_limit: usize,
+}
+
+impl TraceClient {
+    /// Client that raises the alarm if at least `exposure_limit` encounters are confirmed; a limit
+    /// of 0 is treated like 1, as in [spirit_trace](crate::spirit::spirit_trace).
+    pub fn new(exposure_limit: usize, rng: &mut (impl RngCore + CryptoRng)) -> Self {
+        Self {
+            c: random_exponent(rng),
+            exposure_limit,
+        }
+    }
+
+    pub fn query(
+        &self,
+        t_el: &EncounterTable,
+        rng: &mut (impl RngCore + CryptoRng),
+    ) -> ClientQuery {
+        let mut blinded_el_ids: Vec<_> = t_el.el_ids().map(|el_id| el_id * self.c).collect();
+        blinded_el_ids.shuffle(rng);
+        ClientQuery { blinded_el_ids }
+    }
+
+    /// Removes the client's exponent from the answer to its query and returns the number of
+    /// confirmed encounters and whether the alarm is raised.
+    pub fn finish(self, response: &ServerResponse) -> (usize, bool) {
+        // c is nonzero
+        let c_inv = self.c.inverse().unwrap_or_default();
+        let blinded_cf: HashSet<_> = response.blinded_cf.iter().collect();
+        let count = response
+            .blinded_query
+            .iter()
+            .map(|el_id| el_id * c_inv)
+            .collect::<HashSet<_>>()
+            .iter()
+            .filter(|el_id| blinded_cf.contains(el_id))
+            .count();
+        (count, count >= self.exposure_limit.max(1))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rand::rngs::OsRng;
+
+    use super::*;
+    use crate::{
+        elgamal::DecryptionKey,
+        spirit::{
+            Interval, SetupOutput, UserKeys, UserSecret, spirit_broadcast_osrng,
+            spirit_diagnosis_osrng, spirit_register_osrng, spirit_setup_osrng, spirit_trace,
+        },
+    };
+
+    const NOW: Epoch = 10;
+    const EXPIRY: Epoch = 100;
+
+    /// Encounter table of a user's broadcasts `0..num_entries` of epoch 0, and its ElIDs in
+    /// index order.
+    fn encounter_table(keys: &UserKeys, num_entries: usize) -> (EncounterTable, Vec<ElID>) {
+        let domain = DomainConfig::default();
+        let pk_ha = DecryptionKey::random(&mut OsRng).encryption_key();
+        let mut t_el = EncounterTable::new();
+        let el_ids = (0..num_entries)
+            .map(|i| {
+                spirit_broadcast_osrng(&domain, 0, Interval::from(i), keys, &pk_ha, &mut t_el).0
+            })
+            .collect();
+        (t_el, el_ids)
+    }
+
+    fn private_trace(cf: &[ElID], t_el: &EncounterTable, exposure_limit: usize) -> (usize, bool) {
+        let server = TraceServer::prepare(cf, &mut OsRng);
+        let client = TraceClient::new(exposure_limit, &mut OsRng);
+        let query = client.query(t_el, &mut OsRng);
+        client.finish(&server.respond(&query, &mut OsRng))
+    }
+
+    #[test]
+    fn count_matches_plain_trace() {
+        let (t_el, own) = encounter_table(&UserKeys::random(OsRng), 8);
+        let (_, others) = encounter_table(&UserKeys::random(OsRng), 5);
+
+        let cf: Vec<_> = own[2..5]
+            .iter()
+            .chain(&own[3..6])
+            .chain(&others)
+            .cloned()
+            .collect();
+        for exposure_limit in 0..6 {
+            let plain = spirit_trace(&cf, &t_el, exposure_limit);
+            assert_eq!(
+                private_trace(&cf, &t_el, exposure_limit),
+                (plain.count, plain.alarm)
+            );
+        }
+        assert_eq!(private_trace(&cf, &t_el, 4), (4, true));
+        assert_eq!(private_trace(&others, &t_el, 1), (0, false));
+        assert_eq!(private_trace(&[], &EncounterTable::new(), 0), (0, false));
+    }
+
+    #[test]
+    fn messages_hide_the_elids() {
+        let (t_el, own) = encounter_table(&UserKeys::random(OsRng), 8);
+        let (_, others) = encounter_table(&UserKeys::random(OsRng), 5);
+        let cf: Vec<_> = own[..2].iter().chain(&others).cloned().collect();
+
+        let server = TraceServer::prepare(&cf, &mut OsRng);
+        let client = TraceClient::new(1, &mut OsRng);
+        let query = client.query(&t_el, &mut OsRng);
+        let response = server.respond(&query, &mut OsRng);
+
+        // neither side's ElIDs, nor the server's blinding of the client's ones, occur in the
+        // messages
+        let hidden: HashSet<_> = own
+            .iter()
+            .chain(&others)
+            .cloned()
+            .chain(own.iter().map(|el_id| el_id * server.s))
+            .collect();
+        assert!(
+            query
+                .blinded_el_ids
+                .iter()
+                .chain(&response.blinded_query)
+                .all(|el_id| !hidden.contains(el_id))
+        );
+        assert!(
+            response
+                .blinded_cf
+                .iter()
+                .all(|el_id| !own.contains(el_id) && !others.contains(el_id))
+        );
+
+        // queries of the same table under different exponents are unlinkable
+        let other_query = TraceClient::new(1, &mut OsRng).query(&t_el, &mut OsRng);
+        assert!(
+            other_query
+                .blinded_el_ids
+                .iter()
+                .all(|el_id| !query.blinded_el_ids.contains(el_id))
+        );
+
+        assert_eq!(client.finish(&response), (2, true));
+    }
+
+    #[test]
+    fn trace_service_limits_its_clients() {
+        let SetupOutput {
+            pp,
+            issuers,
+            mut t_rgstr,
+            domain,
+            ..
+        } = spirit_setup_osrng(3, 4, 4, b"test").expect("setup failed");
+        let user = UserSecret::random(OsRng);
+        let ppu = spirit_register_osrng(&domain, &user, &issuers, &pp, EXPIRY, &mut t_rgstr)
+            .expect("registration failed");
+        let mut challenges = ChallengeSet::new(0);
+        let revoked = RevocationList::new();
+        let mut authenticate = |epoch| {
+            let challenge = challenges.issue(epoch, &mut OsRng);
+            let cp = HashSet::from([(epoch, Interval(0))]);
+            spirit_diagnosis_osrng(&domain, &ppu, user.keys(), &cp, epoch, &challenge, &[])
+                .expect("diagnosis failed")
+        };
+        let (report, next_report) = (authenticate(NOW), authenticate(NOW + 1));
+
+        let (t_el, own) = encounter_table(&UserKeys::random(OsRng), 8);
+        let limits = TraceLimits {
+            burst: 2,
+            queries_per_epoch: 1,
+            max_el_ids: 8,
+        };
+        let mut service = TraceService::new(own[..3].to_vec(), limits, NOW, &mut OsRng);
+        service.confirm(own[2..4].to_vec(), &mut OsRng);
+        let mut query = |report: &TraceReport, t_el: &EncounterTable, epoch| {
+            let client = TraceClient::new(1, &mut OsRng);
+            let query = client.query(t_el, &mut OsRng);
+            service
+                .respond(
+                    &domain,
+                    report,
+                    &t_rgstr,
+                    &revoked,
+                    &challenges,
+                    &query,
+                    epoch,
+                    &mut OsRng,
+                )
+                .map(|response| (client.finish(&response), response))
+        };
+
+        // a burst of queries, then one per epoch
+        let token = ppu.0.id();
+        let (count, response) = query(&report, &t_el, NOW).unwrap();
+        assert_eq!(count, (4, true));
+        assert!(query(&report, &t_el, NOW).is_ok());
+        let limited = Err(TraceServiceError::RateLimited {
+            token,
+            retry_at: NOW + 1,
+        });
+        assert_eq!(query(&report, &t_el, NOW).map(|(count, _)| count), limited);
+
+        // the answers of the next epoch are under another exponent
+        let (count, next_response) = query(&next_report, &t_el, NOW + 1).unwrap();
+        assert_eq!(count, (4, true));
+        assert!(
+            next_response
+                .blinded_cf
+                .iter()
+                .all(|el_id| !response.blinded_cf.contains(el_id))
+        );
+        assert_eq!(
+            query(&next_report, &t_el, NOW + 1).map(|(count, _)| count),
+            Err(TraceServiceError::RateLimited {
+                token,
+                retry_at: NOW + 2
+            })
+        );
+
+        // oversized queries, and reports of a past epoch, are rejected before they are counted
+        let (large, _) = encounter_table(&UserKeys::random(OsRng), 9);
+        assert_eq!(
+            query(&next_report, &large, NOW + 2).map(|(count, _)| count),
+            Err(TraceServiceError::TooManyElIds { count: 9, max: 8 })
+        );
+        assert_eq!(
+            query(&next_report, &t_el, NOW + 2).map(|(count, _)| count),
+            Err(TraceServiceError::Unauthorized(
+                VerifyRejection::OtherEpoch {
+                    token,
+                    report_epoch: NOW + 1
+                }
+            ))
+        );
+    }
+}
@@ -0,0 +1,610 @@
+//! Receipts of registration, for users who prove that they are registered, e.g. to their
+//! workplace, without showing their token, which would let the verifier recognize their trace
+//! reports. A user registered with
+//! [spirit_register_with_receipt](crate::spirit::spirit_register_with_receipt) holds a
+//! [RegistrationReceipt], the registrar's signature `T^sk_R` on the tag
+//! `T = B^t · H_id^id_u · V_e` of its token. `B` and `V_e` are bases hashed under [RECEIPT_DST],
+//! the latter from the token's expiry `e`, `H_id^id_u` is the user's identity tag, which the user
+//! proves to know the identity of in a [TokenClaim](crate::pool::TokenClaim), and `t` is an
+//! exponent the registrar derives from the token's [id](Token::id) with its receipt key. The
+//! receipt key `sk_R` is derived from
+//! the registrar's signing key and signs nothing but receipts, see
+//! [RegistrarKeypair::receipt_key](crate::registry::RegistrarKeypair::receipt_key).
+//!
+//! A [ReceiptPresentation] raises the tag and the signature to a fresh random power `r` and
+//! proves knowledge of `1/r`, `t` and `id_u` for the new tag, so that only the holder of the
+//! user secret can present the receipt. It shows the expiry, and carries the revocation handle
+//! `R^t` for a fresh base `R`, which verifiers compare with the exponents of the revoked receipts
+//! in [ReceiptRevocations]. Both are checked with [verify_receipt].
+//!
+//! The presented tag, signature and handle are uniform multiples of their bases, so
+//! presentations are unlinkable to each other and to the token, also for verifiers that know
+//! the registered tokens. The registrar, which derives the exponents, can link presentations to
+//! tokens by their handles. A presentation shows the [KeyId] of the registrar's key, which only
+//! tells under which key the receipt was issued, and receipts of a retired key stop verifying
+//! after the overlap of its rotation, during which users fetch a receipt under the new key.
+//!
+//! Users who share their identity secrets can combine receipts of the same expiry into one whose
+//! handle is none of theirs, so the revocation of a receipt holds against single users.
+
+use ark_ff::{Field, PrimeField, UniformRand};
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
+use rand::{CryptoRng, RngCore};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    bls381_helpers::{G1G2, Scalar, append_compressed, hash_with_domain_separation},
+    constants::{
+        RECEIPT_BASE_SEED, RECEIPT_DST, RECEIPT_EXPIRY_SEED, RECEIPT_EXPONENT_DST,
+        RECEIPT_HANDLE_SEED, RECEIPT_KEY_DST, RECEIPT_PRESENTATION_PROTOCOL,
+    },
+    fiat_shamir::ProofTranscript,
+    registry::{KeyId, RegistrarKeys, verify_signed_point},
+    spirit::{DomainConfig, Epoch, Token, UserSecret, identity_base, identity_tag},
+    tsw::{PublicKey, SecretKey, Signature},
+};
+
+/// The base `B` of the receipt tags.
+fn receipt_base() -> G1G2 {
+    hash_with_domain_separation(RECEIPT_BASE_SEED, RECEIPT_DST)
+}
+
+/// The base `V_e` of the receipt tags of tokens that expire at the start of epoch `expiry`.
+fn expiry_base(expiry: Epoch) -> G1G2 {
+    hash_with_domain_separation(
+        &[RECEIPT_EXPIRY_SEED, &expiry.to_le_bytes()].concat(),
+        RECEIPT_DST,
+    )
+}
+
+/// The base `R` of the revocation handle of a presentation with `nonce`.
+fn handle_base(nonce: &[u8; 32]) -> G1G2 {
+    hash_with_domain_separation(&[RECEIPT_HANDLE_SEED, nonce].concat(), RECEIPT_DST)
+}
+
+/// The tag `B^t · H_id^id_u · V_e` of a receipt with the exponent `t`, for the identity tag
+/// `identity` and the expiry `expiry`.
+pub(crate) fn receipt_tag(exponent: &Scalar, identity: &G1G2, expiry: Epoch) -> G1G2 {
+    receipt_base() * exponent + identity + expiry_base(expiry)
+}
+
+/// The receipt key of the registrar with the signing key `sk`.
+pub(crate) fn receipt_secret_key(sk: &SecretKey) -> SecretKey {
+    let mut bytes = RECEIPT_KEY_DST.to_vec();
+    append_compressed(&mut bytes, &sk.sk);
+    SecretKey {
+        sk: Scalar::from_le_bytes_mod_order(&Sha256::digest(&bytes)),
+    }
+}
+
+/// The exponent `t` of the receipt tag of `token` under the receipt key `receipt_sk`, which is
+/// also the revocation handle of its receipt.
+pub(crate) fn receipt_exponent(receipt_sk: &SecretKey, token: &Token) -> Scalar {
+    let mut bytes = RECEIPT_EXPONENT_DST.to_vec();
+    append_compressed(&mut bytes, &receipt_sk.sk);
+    bytes.extend_from_slice(&token.id());
+    Scalar::from_le_bytes_mod_order(&Sha256::digest(&bytes))
+}
+
+/// The registrar's signature on the tag of a registered token, see
+/// [RegistrarKeypair::issue_receipt](crate::registry::RegistrarKeypair::issue_receipt). It is
+/// linked to the token, so the user keeps it and shows [presentations](Self::present) of it.
+#[derive(Debug, Clone, PartialEq, Eq, CanonicalSerialize, CanonicalDeserialize)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RegistrationReceipt {
+    #[cfg_attr(
+        feature = "serde",
+        serde(with = "crate::bls381_helpers::serde_compressed")
+    )]
+    pub(crate) exponent: Scalar,
+    pub(crate) tag: G1G2,
+    pub(crate) signature: Signature,
+    pub(crate) key_id: KeyId,
+    pub(crate) expiry: Epoch,
+}
+
+impl RegistrationReceipt {
+    /// Checks that the receipt is the signature of the registrar's `receipt_key` on the tag of
+    /// the identity of `user`, e.g. when the user receives it.
+    pub fn verify(
+        &self,
+        domain: &DomainConfig,
+        receipt_key: &PublicKey,
+        user: &UserSecret,
+    ) -> bool {
+        self.tag
+            == receipt_tag(
+                &self.exponent,
+                &identity_tag(domain, &user.id_u),
+                self.expiry,
+            )
+            && verify_signed_point(receipt_key, &self.tag, &self.signature)
+    }
+
+    /// Expiry epoch of the token of the receipt, which its presentations show.
+    pub fn expiry(&self) -> Epoch {
+        self.expiry
+    }
+
+    /// A fresh presentation of the receipt by `user`, whose identity it was issued to, bound to
+    /// the verifier's `context`, e.g. a nonce it chose, so that verifiers expecting another
+    /// context reject it.
+    pub fn present(
+        &self,
+        domain: &DomainConfig,
+        user: &UserSecret,
+        context: &[u8],
+        rng: &mut (impl RngCore + CryptoRng),
+    ) -> ReceiptPresentation {
+        let r = loop {
+            let r = Scalar::rand(rng);
+            if let Some(inverse) = r.inverse() {
+                break (r, inverse);
+            }
+        };
+        let tag = &self.tag * r.0;
+        let signature = Signature(&self.signature.0 * r.0);
+        let mut nonce = [0u8; 32];
+        rng.fill_bytes(&mut nonce);
+        let handle_base = handle_base(&nonce);
+        let handle = &handle_base * self.exponent;
+
+        let (k_rho, k_t, k_u) = (Scalar::rand(rng), Scalar::rand(rng), Scalar::rand(rng));
+        let t_tag = &tag * k_rho - receipt_base() * k_t - identity_tag(domain, &k_u);
+        let t_handle = &handle_base * k_t;
+        let statement = PresentationStatement {
+            domain,
+            tag: &tag,
+            signature: &signature,
+            key_id: self.key_id,
+            expiry: self.expiry,
+            nonce: &nonce,
+            handle: &handle,
+            context,
+        };
+        let c = statement.challenge(&t_tag, &t_handle);
+
+        ReceiptPresentation {
+            tag,
+            signature,
+            key_id: self.key_id,
+            expiry: self.expiry,
+            context: context.to_vec(),
+            nonce,
+            handle,
+            c,
+            s_rho: k_rho + c * r.1,
+            s_t: k_t + c * self.exponent,
+            s_u: k_u + c * user.id_u,
+        }
+    }
+}
+
+/// Rerandomized [RegistrationReceipt] with its revocation handle and a Schnorr proof of
+/// knowledge of the randomness, the exponent and the identity of its tag, see [verify_receipt].
+#[derive(Debug, Clone, PartialEq, Eq, CanonicalSerialize, CanonicalDeserialize)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ReceiptPresentation {
+    pub(crate) tag: G1G2,
+    pub(crate) signature: Signature,
+    /// Id of the registrar's key that issued the receipt.
+    pub key_id: KeyId,
+    /// Expiry epoch of the token of the receipt.
+    pub expiry: Epoch,
+    /// The context the presentation was made for, which the verifier compares to its own.
+    pub context: Vec<u8>,
+    pub(crate) nonce: [u8; 32],
+    pub(crate) handle: G1G2,
+    #[cfg_attr(
+        feature = "serde",
+        serde(with = "crate::bls381_helpers::serde_compressed")
+    )]
+    pub(crate) c: Scalar,
+    #[cfg_attr(
+        feature = "serde",
+        serde(with = "crate::bls381_helpers::serde_compressed")
+    )]
+    pub(crate) s_rho: Scalar,
+    #[cfg_attr(
+        feature = "serde",
+        serde(with = "crate::bls381_helpers::serde_compressed")
+    )]
+    pub(crate) s_t: Scalar,
+    #[cfg_attr(
+        feature = "serde",
+        serde(with = "crate::bls381_helpers::serde_compressed")
+    )]
+    pub(crate) s_u: Scalar,
+}
+
+/// The revocation handles of the receipts of revoked tokens, which the registrar adds with
+/// [RegistrarKeypair::revoke_receipt](crate::registry::RegistrarKeypair::revoke_receipt) when it
+/// revokes a token and publishes to the verifiers of receipts. A verification compares the
+/// handle of a presentation with every revoked one.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ReceiptRevocations {
+    #[cfg_attr(
+        feature = "serde",
+        serde(with = "crate::bls381_helpers::serde_compressed")
+    )]
+    pub(crate) exponents: Vec<Scalar>,
+}
+
+impl ReceiptRevocations {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.exponents.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.exponents.is_empty()
+    }
+
+    fn is_revoked(&self, presentation: &ReceiptPresentation) -> bool {
+        let base = handle_base(&presentation.nonce);
+        self.exponents
+            .iter()
+            .any(|exponent| &base * exponent == presentation.handle)
+    }
+}
+
+/// Checks that `presentation` is a rerandomized receipt of the `registrar`'s receipt key of the
+/// presentation's [KeyId], which has to be valid in `current_epoch`, see
+/// [RegistrarKeys::receipt_key], presented by the holder of the identity it was issued to. The
+/// token of the receipt must not have expired by `current_epoch` nor its receipt be in
+/// `revoked`. The proof of knowledge rules out signatures that are rerandomized from the
+/// registrar's public key rather than from a receipt, and presentations of a receipt without
+/// the user secret.
+pub fn verify_receipt(
+    domain: &DomainConfig,
+    presentation: &ReceiptPresentation,
+    registrar: &(impl RegistrarKeys + ?Sized),
+    revoked: &ReceiptRevocations,
+    current_epoch: Epoch,
+) -> bool {
+    let Ok(receipt_key) = registrar.receipt_key(presentation.key_id, current_epoch) else {
+        return false;
+    };
+    if presentation.expiry <= current_epoch
+        || !presentation.tag.is_valid_element()
+        || presentation.tag.is_identity()
+    {
+        return false;
+    }
+
+    let t_tag = &presentation.tag * presentation.s_rho
+        - receipt_base() * presentation.s_t
+        - identity_tag(domain, &presentation.s_u)
+        - expiry_base(presentation.expiry) * presentation.c;
+    let t_handle =
+        handle_base(&presentation.nonce) * presentation.s_t - &presentation.handle * presentation.c;
+    let statement = PresentationStatement {
+        domain,
+        tag: &presentation.tag,
+        signature: &presentation.signature,
+        key_id: presentation.key_id,
+        expiry: presentation.expiry,
+        nonce: &presentation.nonce,
+        handle: &presentation.handle,
+        context: &presentation.context,
+    };
+    statement.challenge(&t_tag, &t_handle) == presentation.c
+        && verify_signed_point(receipt_key, &presentation.tag, &presentation.signature)
+        && !revoked.is_revoked(presentation)
+}
+
+/// The public values of a [ReceiptPresentation] that its challenge covers.
+struct PresentationStatement<'a> {
+    domain: &'a DomainConfig,
+    tag: &'a G1G2,
+    signature: &'a Signature,
+    key_id: KeyId,
+    expiry: Epoch,
+    nonce: &'a [u8; 32],
+    handle: &'a G1G2,
+    context: &'a [u8],
+}
+
+impl PresentationStatement<'_> {
+    fn challenge(&self, t_tag: &G1G2, t_handle: &G1G2) -> Scalar {
+        let mut transcript = ProofTranscript::new(RECEIPT_PRESENTATION_PROTOCOL);
+        transcript.append_point(b"base", &receipt_base());
+        transcript.append_point(b"identity_base", &identity_base(self.domain));
+        transcript.append_point(b"tag", self.tag);
+        transcript.append_point(b"signature", &self.signature.0);
+        transcript.append_u64(b"key_id", self.key_id.into());
+        transcript.append_u64(b"expiry", self.expiry);
+        transcript.append_message(b"nonce", self.nonce);
+        transcript.append_point(b"handle", self.handle);
+        transcript.append_message(b"context", self.context);
+        transcript.append_point(b"t_tag", t_tag);
+        transcript.append_point(b"t_handle", t_handle);
+        transcript.challenge_scalar(b"c")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rand::rngs::OsRng;
+
+    use super::*;
+    use crate::{
+        pedersen::get_parameters,
+        pool::TokenClaim,
+        registry::{RegistrarKeyChain, RegistrarKeypair},
+        spirit::{SetupOutput, spirit_register_with_receipt, spirit_setup_osrng},
+    };
+
+    const EXPIRY: u64 = 100;
+
+    struct Registered {
+        domain: DomainConfig,
+        user: UserSecret,
+        token: Token,
+        receipt: RegistrationReceipt,
+    }
+
+    fn registered(registrar: &RegistrarKeypair) -> Registered {
+        let SetupOutput {
+            pp,
+            issuers,
+            mut t_rgstr,
+            domain,
+            ..
+        } = spirit_setup_osrng(3, 4, 4, b"test").expect("setup failed");
+        let user = UserSecret::random(OsRng);
+        let ((token, _), receipt) = spirit_register_with_receipt(
+            &domain,
+            &user,
+            &issuers,
+            &pp,
+            EXPIRY,
+            &mut t_rgstr,
+            registrar,
+            &mut OsRng,
+        )
+        .expect("registration failed");
+        assert!(t_rgstr.contains(&token));
+        Registered {
+            domain,
+            user,
+            token,
+            receipt,
+        }
+    }
+
+    fn to_bytes(presentation: &ReceiptPresentation) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        presentation.serialize_compressed(&mut bytes).unwrap();
+        bytes
+    }
+
+    fn verify(
+        domain: &DomainConfig,
+        presentation: &ReceiptPresentation,
+        registrar: &impl RegistrarKeys,
+    ) -> bool {
+        verify_receipt(
+            domain,
+            presentation,
+            registrar,
+            &ReceiptRevocations::new(),
+            1,
+        )
+    }
+
+    #[test]
+    fn presentations_verify_and_are_unlinkable() {
+        let registrar = RegistrarKeypair::random(&mut OsRng);
+        let pk = registrar.receipt_key();
+        let Registered {
+            domain,
+            user,
+            receipt,
+            ..
+        } = registered(&registrar);
+        assert!(receipt.verify(&domain, pk, &user));
+        assert!(!receipt.verify(&domain, registrar.public_key(), &user));
+        assert!(!receipt.verify(&domain, pk, &UserSecret::random(OsRng)));
+        assert_eq!(receipt.expiry(), EXPIRY);
+
+        let first = receipt.present(&domain, &user, b"workplace", &mut OsRng);
+        let second = receipt.present(&domain, &user, b"workplace", &mut OsRng);
+        for presentation in [&first, &second] {
+            assert!(verify(&domain, presentation, pk));
+            assert_eq!(presentation.context, b"workplace");
+            assert_ne!(presentation.tag, receipt.tag);
+            assert_ne!(presentation.signature, receipt.signature);
+        }
+        assert_ne!(to_bytes(&first), to_bytes(&second));
+        assert_ne!(first.tag, second.tag);
+        assert_ne!(first.handle, second.handle);
+
+        // another registrar's key, the signing key of the registrar, and another context
+        let other_registrar = RegistrarKeypair::random(&mut OsRng);
+        assert!(!verify(&domain, &first, other_registrar.receipt_key()));
+        assert!(!verify(&domain, &first, registrar.public_key()));
+        let moved = ReceiptPresentation {
+            context: b"another workplace".to_vec(),
+            ..first
+        };
+        assert!(!verify(&domain, &moved, pk));
+    }
+
+    #[test]
+    fn presentations_without_a_receipt_fail() {
+        let registrar = RegistrarKeypair::random(&mut OsRng);
+        let pk = registrar.receipt_key();
+        let Registered {
+            domain,
+            user,
+            token,
+            receipt,
+        } = registered(&registrar);
+
+        // a receipt on a tag of a known exponent, with a signature that is not the registrar's
+        let exponent = Scalar::rand(&mut OsRng);
+        let forged = RegistrationReceipt {
+            exponent,
+            tag: receipt_tag(&exponent, &identity_tag(&domain, &user.id_u), EXPIRY),
+            signature: Signature(G1G2::random(OsRng)),
+            key_id: 0,
+            expiry: EXPIRY,
+        };
+        assert!(!verify(
+            &domain,
+            &forged.present(&domain, &user, b"", &mut OsRng),
+            pk
+        ));
+
+        // the registrar's receipt key is a valid signature on g, but nobody knows the exponents
+        // of g to the bases of the tags
+        let r = Scalar::rand(&mut OsRng);
+        let mut presentation = receipt.present(&domain, &user, b"", &mut OsRng);
+        presentation.tag = &get_parameters().g * r;
+        presentation.signature = Signature(&pk.0 * r);
+        assert!(verify_signed_point(
+            pk,
+            &presentation.tag,
+            &presentation.signature
+        ));
+        assert!(!verify(&domain, &presentation, pk));
+
+        // the identity signs itself
+        let zero = Scalar::from(0u64);
+        let mut identity = receipt.present(&domain, &user, b"", &mut OsRng);
+        identity.tag = receipt_base() * zero;
+        identity.signature = Signature(receipt_base() * zero);
+        assert!(!verify(&domain, &identity, pk));
+
+        // a receipt presented without the user secret it was issued to
+        let thief = UserSecret::random(OsRng);
+        assert!(!verify(
+            &domain,
+            &receipt.present(&domain, &thief, b"", &mut OsRng),
+            pk
+        ));
+
+        // the registrar does not sign a receipt for a claim of another token, or of a forged one
+        let Registered { token: other, .. } = registered(&registrar);
+        let claim = TokenClaim::prove(&domain, &other.id(), &user, &mut OsRng);
+        assert!(registrar.issue_receipt(&domain, &token, &claim).is_err());
+        let claim = TokenClaim::prove(&domain, &token.id(), &user, &mut OsRng);
+        assert!(registrar.issue_receipt(&domain, &token, &claim).is_ok());
+
+        // the receipt of another registrar
+        let foreign_registrar = RegistrarKeypair::random(&mut OsRng);
+        let foreign = registered(&foreign_registrar);
+        let presentation = foreign
+            .receipt
+            .present(&foreign.domain, &foreign.user, b"", &mut OsRng);
+        assert!(!verify(&foreign.domain, &presentation, pk));
+    }
+
+    #[test]
+    fn revoked_and_expired_receipts_stop_verifying() {
+        let registrar = RegistrarKeypair::random(&mut OsRng);
+        let pk = registrar.receipt_key();
+        let Registered {
+            domain,
+            user,
+            token,
+            receipt,
+        } = registered(&registrar);
+        let revoked_other = registered(&registrar);
+        let presentation = receipt.present(&domain, &user, b"", &mut OsRng);
+
+        let mut revoked = ReceiptRevocations::new();
+        registrar.revoke_receipt(&revoked_other.token, &mut revoked);
+        assert_eq!(revoked.len(), 1);
+        assert!(verify_receipt(&domain, &presentation, pk, &revoked, 1));
+        registrar.revoke_receipt(&token, &mut revoked);
+        assert!(!verify_receipt(&domain, &presentation, pk, &revoked, 1));
+        let fresh = receipt.present(&domain, &user, b"", &mut OsRng);
+        assert!(!verify_receipt(&domain, &fresh, pk, &revoked, 1));
+
+        // the token expires at the start of its expiry epoch, and the expiry is bound to the
+        // proof
+        let none = ReceiptRevocations::new();
+        assert!(verify_receipt(
+            &domain,
+            &presentation,
+            pk,
+            &none,
+            EXPIRY - 1
+        ));
+        assert!(!verify_receipt(&domain, &presentation, pk, &none, EXPIRY));
+        let extended = ReceiptPresentation {
+            expiry: EXPIRY + 10,
+            ..presentation
+        };
+        assert!(!verify_receipt(&domain, &extended, pk, &none, EXPIRY));
+    }
+
+    #[test]
+    fn receipts_of_retired_keys_stop_verifying() {
+        let first = RegistrarKeypair::random(&mut OsRng);
+        let mut chain = RegistrarKeyChain::new(&first, 0, 2);
+        let second = chain.rotate(SecretKey::random(&mut OsRng), 10).unwrap();
+        let old = registered(&first);
+        let new = registered(&second);
+        assert!(
+            new.receipt
+                .verify(&new.domain, second.receipt_key(), &new.user)
+        );
+        let none = ReceiptRevocations::new();
+
+        // both keys verify during the overlap
+        let presentation = old.receipt.present(&old.domain, &old.user, b"", &mut OsRng);
+        assert!(verify_receipt(
+            &old.domain,
+            &presentation,
+            &chain.at(11),
+            &none,
+            11
+        ));
+        assert!(!verify_receipt(
+            &old.domain,
+            &presentation,
+            &chain.at(12),
+            &none,
+            12
+        ));
+        let presentation = new.receipt.present(&new.domain, &new.user, b"", &mut OsRng);
+        assert_eq!(presentation.key_id, 1);
+        assert!(verify_receipt(
+            &new.domain,
+            &presentation,
+            &chain.at(12),
+            &none,
+            12
+        ));
+        assert!(!verify_receipt(
+            &new.domain,
+            &presentation,
+            &chain.at(9),
+            &none,
+            9
+        ));
+
+        // the key id is bound to the proof, and unknown ids fail
+        for key_id in [0, 2] {
+            let relabelled = ReceiptPresentation {
+                key_id,
+                ..presentation.clone()
+            };
+            assert!(!verify_receipt(
+                &new.domain,
+                &relabelled,
+                &chain.at(11),
+                &none,
+                11
+            ));
+            assert!(!verify(&new.domain, &relabelled, second.receipt_key()));
+        }
+    }
+}
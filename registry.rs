@@ -0,0 +1,2906 @@
+#[cfg(feature = "mmap")]
+use std::cmp::Ordering;
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    fs::{self, File, OpenOptions},
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+    sync::{PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard},
+};
+
+use ark_ff::Zero;
+#[cfg(feature = "mmap")]
+use memmap2::Mmap;
+use rand::{CryptoRng, RngCore};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+use crate::{
+    bls381_helpers::{DecodeError, G1G2, hash_with_domain_separation, multi_pairing, split_array},
+    constants::{
+        BROADCAST_ANCHOR_DST, KEY_ROTATION_DST, REGISTRY_FILTER_DST, REGISTRY_ROOT_DST,
+        REGISTRY_SNAPSHOT_DST,
+    },
+    envelope::{self, ArtifactType, HEADER_SIZE, LoadMode, LoadWarning},
+    pedersen::get_parameters,
+    pool::TokenClaim,
+    receipt::{
+        ReceiptRevocations, RegistrationReceipt, receipt_exponent, receipt_secret_key, receipt_tag,
+    },
+    spirit::{BroadcastAnchor, DomainConfig, Epoch, KeyRotation, RegistrarId, SpiritError, Token},
+    tsw::{PublicKey, SecretKey, Signature},
+};
+
+/// Storage backend of the registrar's token registry `t_rgstr`.
+pub trait TokenRegistry {
+    /// Adds `token` to the registry. Returns `false` if it was already registered.
+    fn insert(&mut self, token: Token) -> Result<bool, RegistryError>;
+
+    fn contains(&self, token: &Token) -> bool;
+
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// All registered tokens, e.g. for [export_registry]. The tokens are cloned, so registries
+    /// behind locks can return them without holding the locks.
+    fn tokens(&self) -> Box<dyn Iterator<Item = Token> + '_>;
+
+    /// Removes all tokens expired at `current_epoch` and returns how many were removed.
+    fn purge_expired(&mut self, current_epoch: Epoch) -> Result<usize, RegistryError>;
+}
+
+impl TokenRegistry for HashSet<Token> {
+    fn insert(&mut self, token: Token) -> Result<bool, RegistryError> {
+        Ok(Self::insert(self, token))
+    }
+
+    fn contains(&self, token: &Token) -> bool {
+        Self::contains(self, token)
+    }
+
+    fn len(&self) -> usize {
+        Self::len(self)
+    }
+
+    fn tokens(&self) -> Box<dyn Iterator<Item = Token> + '_> {
+        Box::new(self.iter().cloned())
+    }
+
+    fn purge_expired(&mut self, current_epoch: Epoch) -> Result<usize, RegistryError> {
+        let len = Self::len(self);
+        self.retain(|token| !token.is_expired(current_epoch));
+        Ok(len - Self::len(self))
+    }
+}
+
+/// Token registry for registrars and verifiers serving concurrent requests. The tokens are
+/// spread over shards by their [id](Token::id), each behind its own [RwLock], so lookups never
+/// block each other and a registration only blocks the lookups of its shard, while all pairing
+/// work runs outside the locks.
+///
+/// [TokenRegistry] is implemented for `&SharedRegistrar`, so threads sharing a registrar pass
+/// `&mut &registrar` to [spirit_register](crate::spirit::spirit_register) and `&registrar` to
+/// [spirit_verify_batch](crate::spirit::spirit_verify_batch). The nullifiers and confirmed ElIDs
+/// of a verification stay the caller's.
+#[derive(Debug)]
+pub struct SharedRegistrar {
+    shards: Vec<RwLock<HashSet<Token>>>,
+}
+
+impl SharedRegistrar {
+    pub const DEFAULT_SHARDS: usize = 64;
+
+    pub fn new() -> Self {
+        Self::with_shards(Self::DEFAULT_SHARDS)
+    }
+
+    /// Registry with `shards` shards, at least one.
+    pub fn with_shards(shards: usize) -> Self {
+        Self {
+            shards: (0..shards.max(1)).map(|_| RwLock::default()).collect(),
+        }
+    }
+
+    /// Adds `token` to the registry. Returns `false` if it was already registered.
+    pub fn insert(&self, token: Token) -> bool {
+        write(self.shard(&token)).insert(token)
+    }
+
+    pub fn contains(&self, token: &Token) -> bool {
+        read(self.shard(token)).contains(token)
+    }
+
+    /// Number of registered tokens, which may be outdated as soon as it is returned if other
+    /// threads register or purge tokens.
+    pub fn len(&self) -> usize {
+        self.shards.iter().map(|shard| read(shard).len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Removes all tokens expired at `current_epoch`, one shard at a time, and returns how many
+    /// were removed.
+    pub fn purge_expired(&self, current_epoch: Epoch) -> usize {
+        self.shards
+            .iter()
+            .map(|shard| {
+                let mut tokens = write(shard);
+                let len = tokens.len();
+                tokens.retain(|token| !token.is_expired(current_epoch));
+                len - tokens.len()
+            })
+            .sum()
+    }
+
+    /// Copies of the registered tokens, taken one shard at a time.
+    pub fn tokens(&self) -> Vec<Token> {
+        self.shards
+            .iter()
+            .flat_map(|shard| read(shard).iter().cloned().collect::<Vec<_>>())
+            .collect()
+    }
+
+    fn shard(&self, token: &Token) -> &RwLock<HashSet<Token>> {
+        let id = token.id();
+        &self.shards[(digest_word(&id, 0) % self.shards.len() as u64) as usize]
+    }
+}
+
+impl Default for SharedRegistrar {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// A thread that panicked while holding a lock cannot leave a shard half-updated, so poisoned
+// locks are entered anyway.
+fn read(shard: &RwLock<HashSet<Token>>) -> RwLockReadGuard<'_, HashSet<Token>> {
+    shard.read().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn write(shard: &RwLock<HashSet<Token>>) -> RwLockWriteGuard<'_, HashSet<Token>> {
+    shard.write().unwrap_or_else(PoisonError::into_inner)
+}
+
+// the little-endian integer of the 8 bytes at `offset` of a token ID or a SHA-256 digest
+fn digest_word(digest: &[u8], offset: usize) -> u64 {
+    let mut word = [0; 8];
+    word.copy_from_slice(&digest[offset..offset + 8]);
+    u64::from_le_bytes(word)
+}
+
+impl TokenRegistry for &SharedRegistrar {
+    fn insert(&mut self, token: Token) -> Result<bool, RegistryError> {
+        Ok(SharedRegistrar::insert(self, token))
+    }
+
+    fn contains(&self, token: &Token) -> bool {
+        SharedRegistrar::contains(self, token)
+    }
+
+    fn len(&self) -> usize {
+        SharedRegistrar::len(self)
+    }
+
+    fn tokens(&self) -> Box<dyn Iterator<Item = Token> + '_> {
+        Box::new(SharedRegistrar::tokens(self).into_iter())
+    }
+
+    fn purge_expired(&mut self, current_epoch: Epoch) -> Result<usize, RegistryError> {
+        Ok(SharedRegistrar::purge_expired(self, current_epoch))
+    }
+}
+
+impl TokenRegistry for SharedRegistrar {
+    fn insert(&mut self, token: Token) -> Result<bool, RegistryError> {
+        Ok(Self::insert(self, token))
+    }
+
+    fn contains(&self, token: &Token) -> bool {
+        Self::contains(self, token)
+    }
+
+    fn len(&self) -> usize {
+        Self::len(self)
+    }
+
+    fn tokens(&self) -> Box<dyn Iterator<Item = Token> + '_> {
+        Box::new(Self::tokens(self).into_iter())
+    }
+
+    fn purge_expired(&mut self, current_epoch: Epoch) -> Result<usize, RegistryError> {
+        Ok(Self::purge_expired(self, current_epoch))
+    }
+}
+
+/// Token registry persisted as an append-only log behind an [envelope](crate::envelope) header,
+/// with the tokens' [Token::to_bytes] payloads as entries. The log is read on
+/// [FileRegistry::open] to rebuild an in-memory index, lookups never touch the file.
+pub struct FileRegistry {
+    path: PathBuf,
+    log: File,
+    index: HashSet<Token>,
+}
+
+impl FileRegistry {
+    /// Opens the registry log at `path`, creating it if it does not exist.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, RegistryError> {
+        let path = path.as_ref().to_path_buf();
+        let mut log = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(&path)?;
+
+        let mut bytes = Vec::new();
+        log.read_to_end(&mut bytes)?;
+        if bytes.is_empty() {
+            log.write_all(&envelope::header(ArtifactType::RegistryLog))?;
+            log.sync_data()?;
+            let index = HashSet::new();
+            return Ok(Self { path, log, index });
+        }
+
+        let index = match envelope::open(&bytes, ArtifactType::RegistryLog)
+            .map_err(RegistryError::InvalidEncoding)?
+        {
+            (1, entries) => read_log_v1(entries)?,
+            (version, _) => {
+                return Err(RegistryError::InvalidEncoding(
+                    DecodeError::UnsupportedVersion(version),
+                ));
+            }
+        };
+
+        Ok(Self { path, log, index })
+    }
+}
+
+/// Tokens of the entries of a version-1 log, which are their payloads of fixed size.
+fn read_log_v1(entries: &[u8]) -> Result<HashSet<Token>, RegistryError> {
+    if entries.len() % Token::PAYLOAD_SIZE != 0 {
+        return Err(RegistryError::TruncatedLog {
+            length: entries.len(),
+        });
+    }
+
+    entries
+        .chunks_exact(Token::PAYLOAD_SIZE)
+        .enumerate()
+        .map(|(entry, chunk)| {
+            Token::from_payload(chunk)
+                .map_err(|source| RegistryError::CorruptEntry { entry, source })
+        })
+        .collect()
+}
+
+impl TokenRegistry for FileRegistry {
+    fn insert(&mut self, token: Token) -> Result<bool, RegistryError> {
+        if self.index.contains(&token) {
+            return Ok(false);
+        }
+
+        self.log.write_all(&token.payload())?;
+        self.log.sync_data()?;
+        Ok(self.index.insert(token))
+    }
+
+    fn contains(&self, token: &Token) -> bool {
+        self.index.contains(token)
+    }
+
+    fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    fn tokens(&self) -> Box<dyn Iterator<Item = Token> + '_> {
+        Box::new(self.index.iter().cloned())
+    }
+
+    /// Rewrites the log without the expired tokens and atomically replaces the old log.
+    fn purge_expired(&mut self, current_epoch: Epoch) -> Result<usize, RegistryError> {
+        let len = self.index.len();
+        self.index.retain(|token| !token.is_expired(current_epoch));
+        let purged = len - self.index.len();
+        if purged == 0 {
+            return Ok(0);
+        }
+
+        let compacted = self.path.with_extension("compact");
+        let mut log = File::create(&compacted)?;
+        log.write_all(&envelope::header(ArtifactType::RegistryLog))?;
+        for token in &self.index {
+            log.write_all(&token.payload())?;
+        }
+        log.sync_all()?;
+        fs::rename(&compacted, &self.path)?;
+
+        self.log = OpenOptions::new().append(true).open(&self.path)?;
+        Ok(purged)
+    }
+}
+
+/// Read-only token registry for national-scale deployments, with the `mmap` feature: a
+/// memory-mapped file of the tokens' [Token::to_bytes] payloads in ascending order behind an
+/// [envelope](crate::envelope) header. Lookups binary-search the mapped entries without
+/// allocating, and the operating system keeps only the pages in use in memory.
+///
+/// The file is written offline by [MmapRegistry::build_from_iter], e.g. from the tokens of a
+/// [FileRegistry] log, and rebuilt with the tokens registered since by [MmapRegistry::merge].
+/// [TokenRegistry::insert] and [TokenRegistry::purge_expired] fail with
+/// [RegistryError::ReadOnly]. Unlike the other registries, lookups compare the encodings and are
+/// not constant-time.
+#[cfg(feature = "mmap")]
+pub struct MmapRegistry {
+    entries: Mmap,
+    len: usize,
+}
+
+#[cfg(feature = "mmap")]
+impl MmapRegistry {
+    /// Maps the registry file at `path`. The file must not be modified while it is mapped, which
+    /// [Self::build_from_iter] and [Self::merge] ensure by replacing it atomically. Fails with
+    /// [RegistryError::UnsortedIndex] unless the entries are strictly ascending, which reads the
+    /// whole file once.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, RegistryError> {
+        let file = File::open(path)?;
+        // SAFETY: the file is only ever replaced by a rename, never written in place, so the
+        // mapping does not change under the registry
+        let entries = unsafe { Mmap::map(&file)? };
+        let (version, payload) = envelope::open(&entries, ArtifactType::RegistryIndex)
+            .map_err(RegistryError::InvalidEncoding)?;
+        let length = payload.len();
+        let registry = match version {
+            1 if length % Token::PAYLOAD_SIZE == 0 => Self {
+                entries,
+                len: length / Token::PAYLOAD_SIZE,
+            },
+            1 => return Err(RegistryError::TruncatedIndex { length }),
+            version => {
+                return Err(RegistryError::InvalidEncoding(
+                    DecodeError::UnsupportedVersion(version),
+                ));
+            }
+        };
+        registry.check_sorted()?;
+        Ok(registry)
+    }
+
+    // the binary search of the lookups relies on the order of the entries
+    fn check_sorted(&self) -> Result<(), RegistryError> {
+        let unsorted = self
+            .entries()
+            .zip(self.entries().skip(1))
+            .position(|(entry, next)| entry >= next);
+        match unsorted {
+            Some(entry) => Err(RegistryError::UnsortedIndex { entry: entry + 1 }),
+            None => Ok(()),
+        }
+    }
+
+    /// Writes the registry file of `tokens` to `path` and maps it. Duplicate tokens are written
+    /// once.
+    pub fn build_from_iter(
+        path: impl AsRef<Path>,
+        tokens: impl IntoIterator<Item = Token>,
+    ) -> Result<Self, RegistryError> {
+        let entries = sorted_payloads(tokens);
+        write_index(path.as_ref(), entries.iter().map(|entry| &entry[..]))?;
+        Self::open(path)
+    }
+
+    /// Writes the registry file of the tokens of the registry and of `delta`, e.g. the tokens
+    /// logged since the registry was built, to `path` and maps it. Only `delta` is sorted in
+    /// memory, the entries of the registry are merged into it as they are read. `path` may be
+    /// the registry's own file, which the registry keeps reading until it is dropped.
+    pub fn merge(
+        &self,
+        path: impl AsRef<Path>,
+        delta: impl IntoIterator<Item = Token>,
+    ) -> Result<Self, RegistryError> {
+        let sorted = sorted_payloads(delta);
+        let mut entries = self.entries().peekable();
+        let mut delta = sorted.iter().map(|entry| &entry[..]).peekable();
+        let merged = std::iter::from_fn(|| {
+            let order = match (entries.peek(), delta.peek()) {
+                (Some(entry), Some(other)) => entry.cmp(other),
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (None, None) => return None,
+            };
+            match order {
+                Ordering::Less => entries.next(),
+                Ordering::Greater => delta.next(),
+                Ordering::Equal => {
+                    delta.next();
+                    entries.next()
+                }
+            }
+        });
+        write_index(path.as_ref(), merged)?;
+        Self::open(path)
+    }
+
+    fn entries(&self) -> impl Iterator<Item = &[u8]> {
+        self.entries[HEADER_SIZE..].chunks_exact(Token::PAYLOAD_SIZE)
+    }
+
+    fn entry(&self, index: usize) -> &[u8] {
+        let start = HEADER_SIZE + index * Token::PAYLOAD_SIZE;
+        &self.entries[start..start + Token::PAYLOAD_SIZE]
+    }
+}
+
+// the payloads of `tokens` in ascending order, without duplicates
+#[cfg(feature = "mmap")]
+fn sorted_payloads(tokens: impl IntoIterator<Item = Token>) -> Vec<[u8; Token::PAYLOAD_SIZE]> {
+    let mut entries: Vec<_> = tokens.into_iter().map(|token| token.payload()).collect();
+    entries.sort_unstable();
+    entries.dedup();
+    entries
+}
+
+// writes the registry file of the sorted `entries` next to `path` and moves it to `path`
+#[cfg(feature = "mmap")]
+fn write_index<'a>(
+    path: &Path,
+    entries: impl Iterator<Item = &'a [u8]>,
+) -> Result<(), RegistryError> {
+    let built = path.with_extension("build");
+    let mut writer = BufWriter::new(File::create(&built)?);
+    writer.write_all(&envelope::header(ArtifactType::RegistryIndex))?;
+    for entry in entries {
+        writer.write_all(entry)?;
+    }
+    writer
+        .into_inner()
+        .map_err(|error| error.into_error())?
+        .sync_all()?;
+    fs::rename(&built, path)?;
+    Ok(())
+}
+
+#[cfg(feature = "mmap")]
+impl TokenRegistry for MmapRegistry {
+    fn insert(&mut self, _token: Token) -> Result<bool, RegistryError> {
+        Err(RegistryError::ReadOnly)
+    }
+
+    fn contains(&self, token: &Token) -> bool {
+        let payload = token.payload();
+        let (mut low, mut high) = (0, self.len);
+        while low < high {
+            let mid = low + (high - low) / 2;
+            match self.entry(mid).cmp(&payload[..]) {
+                Ordering::Less => low = mid + 1,
+                Ordering::Greater => high = mid,
+                Ordering::Equal => return true,
+            }
+        }
+        false
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    /// The tokens in the order of their encodings. Entries that do not decode, which only a
+    /// corrupted file has, are skipped.
+    fn tokens(&self) -> Box<dyn Iterator<Item = Token> + '_> {
+        Box::new(self.entries().flat_map(Token::from_payload))
+    }
+
+    fn purge_expired(&mut self, _current_epoch: Epoch) -> Result<usize, RegistryError> {
+        Err(RegistryError::ReadOnly)
+    }
+}
+
+/// Reader or writer that hashes all bytes passing through it, for the checksum of registry
+/// snapshots.
+struct Checksummed<T> {
+    inner: T,
+    digest: Sha256,
+}
+
+impl<T> Checksummed<T> {
+    fn new(inner: T) -> Self {
+        Self {
+            inner,
+            digest: Sha256::new(),
+        }
+    }
+
+    fn checksum(&self) -> [u8; 32] {
+        self.digest.clone().finalize().into()
+    }
+}
+
+impl<W: Write> Write for Checksummed<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.digest.update(&buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<R: Read> Read for Checksummed<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.digest.update(&buf[..read]);
+        Ok(read)
+    }
+}
+
+/// Version of the registry snapshots written by [export_registry]. Version-1 snapshots carry
+/// neither a sequence number nor a signature and are no longer imported. Version 2 adds both,
+/// and version 3 the epoch and the [KeyId] of the signature. Version-2 snapshots are only
+/// imported by [import_legacy_registry].
+pub const SNAPSHOT_VERSION: u16 = 3;
+/// Version of the snapshots signed without a key id, see [import_legacy_registry].
+const LEGACY_SNAPSHOT_VERSION: u16 = 2;
+/// Size of the fields `seq || epoch || key_id || len` between the header of a snapshot and its
+/// entries.
+pub const SNAPSHOT_PREFIX_SIZE: usize = 8 + 8 + 4 + 8;
+
+/// Writes a snapshot of `registry` at the sequence number `seq`, taken in `epoch`, to `writer`:
+/// an [envelope](crate::envelope) header of [SNAPSHOT_VERSION], `seq` and `epoch` as
+/// little-endian `u64`s, the registrar's [KeyId] as little-endian `u32`, the number of tokens
+/// as little-endian `u64`, the tokens' [Token::to_bytes] payloads, the SHA-256 checksum of all
+/// of the above and the registrar's signature on the checksum. Only the tokens returned by
+/// [TokenRegistry::tokens] are written and counted, so entries that do not decode are left out.
+pub fn export_registry(
+    registry: &impl TokenRegistry,
+    registrar: &RegistrarKeypair,
+    seq: u64,
+    epoch: Epoch,
+    writer: impl Write,
+) -> Result<(), RegistryError> {
+    let mut writer = Checksummed::new(BufWriter::new(writer));
+    writer.write_all(&envelope::versioned_header(
+        ArtifactType::RegistrySnapshot,
+        SNAPSHOT_VERSION,
+    ))?;
+    writer.write_all(&seq.to_le_bytes())?;
+    writer.write_all(&epoch.to_le_bytes())?;
+    writer.write_all(&registrar.key_id.to_le_bytes())?;
+    let tokens: Vec<_> = registry.tokens().collect();
+    writer.write_all(&(tokens.len() as u64).to_le_bytes())?;
+    for token in tokens {
+        writer.write_all(&token.payload())?;
+    }
+
+    let checksum = writer.checksum();
+    let mut writer = writer.inner;
+    writer.write_all(&checksum)?;
+    writer.write_all(
+        &registrar
+            .sign(REGISTRY_SNAPSHOT_DST, seq, &checksum)
+            .0
+            .to_bytes(),
+    )?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Reads a snapshot written by [export_registry] and returns its tokens in the exported order,
+/// to be inserted into the restored registry. Truncated snapshots, snapshots whose checksum
+/// does not match or that are not signed by `registrar` are rejected as a whole, so a failed
+/// import never yields part of the tokens. So are snapshots older than the one at
+/// `latest_seq`, which is advanced to the sequence number of the imported snapshot.
+///
+/// With a [RegistrarKeyChain], the snapshot is checked with the key of its [KeyId], which has
+/// to be valid in the epoch of the snapshot, see [RegistrarKeyChain::at]. Version-2 snapshots
+/// predate key ids and are rejected, see [import_legacy_registry].
+pub fn import_registry(
+    reader: impl Read,
+    registrar: &impl RegistrarKeys,
+    latest_seq: &mut u64,
+) -> Result<Vec<Token>, RegistryError> {
+    import_registry_with(reader, registrar, latest_seq, LoadMode::Strict).map(|(tokens, _)| tokens)
+}
+
+/// Like [import_registry], but only imports version-2 snapshots, e.g. once while a deployment
+/// migrates to key ids. Their signatures carry neither a key id nor an epoch, so they are
+/// checked with the single key `registrar`, which has to be the key that signed them, and not
+/// with a [RegistrarKeyChain].
+pub fn import_legacy_registry(
+    reader: impl Read,
+    registrar: &PublicKey,
+    latest_seq: &mut u64,
+) -> Result<Vec<Token>, RegistryError> {
+    import_snapshot(
+        reader,
+        registrar,
+        latest_seq,
+        LoadMode::Strict,
+        LEGACY_SNAPSHOT_VERSION,
+    )
+    .map(|(tokens, _)| tokens)
+}
+
+/// Like [import_registry], but with [LoadMode::Lenient] the tokens that do not decode are
+/// skipped instead of failing the import, e.g. of a snapshot of a registry whose storage was
+/// corrupted before the export, and returned as warnings with the offsets of their entries.
+/// The rest of the snapshot is checked as in [import_registry], so snapshots that are
+/// truncated, or whose checksum or signature is invalid, are still rejected as a whole.
+pub fn import_registry_with(
+    reader: impl Read,
+    registrar: &impl RegistrarKeys,
+    latest_seq: &mut u64,
+    mode: LoadMode,
+) -> Result<(Vec<Token>, Vec<LoadWarning>), RegistryError> {
+    import_snapshot(reader, registrar, latest_seq, mode, SNAPSHOT_VERSION)
+}
+
+/// Imports a snapshot of the `expected` version, see [import_registry_with].
+fn import_snapshot(
+    reader: impl Read,
+    registrar: &impl RegistrarKeys,
+    latest_seq: &mut u64,
+    mode: LoadMode,
+    expected: u16,
+) -> Result<(Vec<Token>, Vec<LoadWarning>), RegistryError> {
+    let mut reader = Checksummed::new(BufReader::new(reader));
+    let mut header = [0u8; HEADER_SIZE];
+    read_snapshot(&mut reader, &mut header)?;
+    let (version, _) = envelope::open(&header, ArtifactType::RegistrySnapshot)
+        .map_err(RegistryError::InvalidEncoding)?;
+    if version != expected {
+        return Err(RegistryError::InvalidEncoding(
+            DecodeError::UnsupportedVersion(version),
+        ));
+    }
+    let seq = read_u64(&mut reader)?;
+    let (epoch, key_id, prefix_size) = match version {
+        LEGACY_SNAPSHOT_VERSION => (0, 0, 16),
+        _ => {
+            let epoch = read_u64(&mut reader)?;
+            let mut key_id = [0u8; 4];
+            read_snapshot(&mut reader, &mut key_id)?;
+            (epoch, KeyId::from_le_bytes(key_id), SNAPSHOT_PREFIX_SIZE)
+        }
+    };
+    let (tokens, warnings) = read_snapshot_entries(&mut reader, HEADER_SIZE + prefix_size, mode)?;
+
+    let expected = reader.checksum();
+    let mut checksum = [0u8; 32];
+    read_snapshot(&mut reader, &mut checksum)?;
+    if checksum != expected {
+        return Err(RegistryError::ChecksumMismatch);
+    }
+    let mut signature = [0u8; G1G2::ENCODED_SIZE];
+    read_snapshot(&mut reader, &mut signature)?;
+    if reader.inner.read(&mut [0u8])? != 0 {
+        return Err(RegistryError::TrailingSnapshotBytes);
+    }
+
+    let signature =
+        Signature(G1G2::from_bytes(&signature).map_err(RegistryError::InvalidEncoding)?);
+    let registrar = registrar
+        .verifying_key(key_id, epoch)
+        .map_err(RegistryError::InvalidKey)?;
+    if !verify_registrar_signature(registrar, REGISTRY_SNAPSHOT_DST, seq, &checksum, &signature) {
+        return Err(RegistryError::InvalidSignature);
+    }
+    if seq < *latest_seq {
+        return Err(RegistryError::StaleSnapshot {
+            seq,
+            latest: *latest_seq,
+        });
+    }
+    *latest_seq = seq;
+    Ok((tokens, warnings))
+}
+
+/// Tokens of the entries of a snapshot, which are their payloads in every version, and the
+/// warnings of the entries skipped in `mode`. The entries follow their number and start at
+/// `offset` from the start of the snapshot.
+fn read_snapshot_entries(
+    reader: &mut impl Read,
+    offset: usize,
+    mode: LoadMode,
+) -> Result<(Vec<Token>, Vec<LoadWarning>), RegistryError> {
+    let len = read_u64(reader)?;
+
+    // the length is only trusted once the checksum is checked
+    let mut tokens = Vec::new();
+    let mut warnings = Vec::new();
+    let mut payload = [0u8; Token::PAYLOAD_SIZE];
+    for entry in 0..len as usize {
+        read_snapshot(reader, &mut payload)?;
+        match (Token::from_payload(&payload), mode) {
+            (Ok(token), _) => tokens.push(token),
+            (Err(reason), LoadMode::Lenient) => warnings.push(LoadWarning {
+                offset: (offset + entry * Token::PAYLOAD_SIZE) as u64,
+                reason,
+            }),
+            (Err(source), LoadMode::Strict) => {
+                return Err(RegistryError::CorruptEntry { entry, source });
+            }
+        }
+    }
+    Ok((tokens, warnings))
+}
+
+fn read_u64(reader: &mut impl Read) -> Result<u64, RegistryError> {
+    let mut bytes = [0u8; 8];
+    read_snapshot(reader, &mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn read_snapshot(reader: &mut impl Read, buf: &mut [u8]) -> Result<(), RegistryError> {
+    reader.read_exact(buf).map_err(|error| match error.kind() {
+        io::ErrorKind::UnexpectedEof => RegistryError::TruncatedSnapshot,
+        _ => error.into(),
+    })
+}
+
+pub type MerkleHash = [u8; 32];
+
+fn merkle_leaf(token: &Token) -> MerkleHash {
+    let mut digest = Sha256::new();
+    digest.update([0u8]);
+    digest.update(token.payload());
+    digest.finalize().into()
+}
+
+pub(crate) fn merkle_node(left: &MerkleHash, right: &MerkleHash) -> MerkleHash {
+    let mut digest = Sha256::new();
+    digest.update([1u8]);
+    digest.update(left);
+    digest.update(right);
+    digest.finalize().into()
+}
+
+/// The levels of the tree over `leaves`, from the leaves to the root, with the last node of a
+/// level without a sibling promoted as in [MerkleRegistry].
+pub(crate) fn merkle_levels(leaves: Vec<MerkleHash>) -> Vec<Vec<MerkleHash>> {
+    let mut levels = vec![leaves];
+    while levels[levels.len() - 1].len() > 1 {
+        let parents = levels[levels.len() - 1]
+            .chunks(2)
+            .map(|pair| match pair {
+                [left, right] => merkle_node(left, right),
+                [node] => *node,
+                _ => unreachable!(),
+            })
+            .collect();
+        levels.push(parents);
+    }
+    levels
+}
+
+/// The authentication path of the leaf at `leaf` in the tree of `levels`.
+pub(crate) fn merkle_path(levels: &[Vec<MerkleHash>], leaf: usize) -> MembershipProof {
+    let mut index = leaf;
+    let siblings = levels
+        .iter()
+        .filter_map(|level| {
+            let sibling = level.get(index ^ 1).copied();
+            index /= 2;
+            sibling
+        })
+        .collect();
+
+    MembershipProof {
+        index: leaf,
+        siblings,
+    }
+}
+
+pub(crate) fn registrar_message(domain: &[u8], seq: u64, msg: &[u8]) -> G1G2 {
+    let mut bytes = seq.to_le_bytes().to_vec();
+    bytes.extend_from_slice(msg);
+    hash_with_domain_separation(&bytes, domain)
+}
+
+/// Keypair of the registrar, which signs the [SignedRoot]s, snapshots and deltas of its
+/// registry. Every signed update carries a sequence number that grows with the registry, so
+/// verifiers reject an old update served again in place of a later one.
+///
+/// Receipts of registration are signed with a separate receipt key that is derived from the
+/// signing key, so a receipt is no signature on a hashed update message, nor the other way
+/// round.
+pub struct RegistrarKeypair {
+    sk: SecretKey,
+    pk: PublicKey,
+    receipt_sk: SecretKey,
+    receipt_pk: PublicKey,
+    key_id: KeyId,
+}
+
+impl RegistrarKeypair {
+    /// A keypair with the key id 0, the first key of a [RegistrarKeyChain].
+    pub fn random(rng: &mut (impl RngCore + CryptoRng)) -> Self {
+        Self::with_key_id(SecretKey::random(rng), 0)
+    }
+
+    fn with_key_id(sk: SecretKey, key_id: KeyId) -> Self {
+        let receipt_sk = receipt_secret_key(&sk);
+        Self {
+            pk: sk.to_public_key(),
+            sk,
+            receipt_pk: receipt_sk.to_public_key(),
+            receipt_sk,
+            key_id,
+        }
+    }
+
+    pub fn public_key(&self) -> &PublicKey {
+        &self.pk
+    }
+
+    /// The public key of the [receipts](Self::issue_receipt) of the keypair, which verifiers of
+    /// receipts check presentations with.
+    pub fn receipt_key(&self) -> &PublicKey {
+        &self.receipt_pk
+    }
+
+    /// Id of the key in the registrar's [RegistrarKeyChain], which its signatures carry.
+    pub fn key_id(&self) -> KeyId {
+        self.key_id
+    }
+
+    /// BLS signature `H(seq || msg)^sk`, with the hash separated by `domain` for every kind of
+    /// update.
+    pub(crate) fn sign(&self, domain: &[u8], seq: u64, msg: &[u8]) -> Signature {
+        Signature(registrar_message(domain, seq, msg) * self.sk.sk)
+    }
+
+    /// Anchors the `root` of a device's [BroadcastBatch](crate::spirit::BroadcastBatch) of `size`
+    /// broadcasts in `epoch`, the registrar's current one. The receipt is signed with the epoch
+    /// as its sequence number, so the anchor cannot be moved to an earlier epoch.
+    pub fn anchor_broadcasts(
+        &self,
+        root: MerkleHash,
+        size: usize,
+        epoch: Epoch,
+    ) -> BroadcastAnchor {
+        BroadcastAnchor {
+            root,
+            size,
+            epoch,
+            key_id: self.key_id,
+            signature: self.sign(
+                BROADCAST_ANCHOR_DST,
+                epoch,
+                &anchor_message(&root, size, self.key_id),
+            ),
+        }
+    }
+
+    /// Records that the token `old` was replaced by `new` from `epoch` on, see
+    /// [spirit_rotate](crate::spirit::spirit_rotate). The record is signed with the epoch as its
+    /// sequence number.
+    pub fn sign_rotation(&self, old: TokenId, new: TokenId, epoch: Epoch) -> KeyRotation {
+        KeyRotation {
+            old,
+            new,
+            epoch,
+            key_id: self.key_id,
+            signature: self.sign(
+                KEY_ROTATION_DST,
+                epoch,
+                &rotation_message(&old, &new, self.key_id),
+            ),
+        }
+    }
+
+    /// Signs the receipt tag of the registered `token` for the user of the identity that
+    /// `claim` proves to know for the token's id, see
+    /// [spirit_register_with_receipt](crate::spirit::spirit_register_with_receipt). The tag is
+    /// signed without a sequence number, so receipts of different tokens can be rerandomized
+    /// into each other's presentations. Fails with [SpiritError::InvalidTokenClaim] if the
+    /// claim does not verify.
+    pub fn issue_receipt(
+        &self,
+        domain: &DomainConfig,
+        token: &Token,
+        claim: &TokenClaim,
+    ) -> Result<RegistrationReceipt, SpiritError> {
+        claim.verify(domain, &token.id())?;
+        let exponent = receipt_exponent(&self.receipt_sk, token);
+        let tag = receipt_tag(&exponent, &claim.tag, token.2);
+        Ok(RegistrationReceipt {
+            exponent,
+            signature: Signature(&tag * self.receipt_sk.sk),
+            tag,
+            key_id: self.key_id,
+            expiry: token.2,
+        })
+    }
+
+    /// Adds the receipt of the revoked `token` to `revoked`, so its presentations stop
+    /// verifying.
+    pub fn revoke_receipt(&self, token: &Token, revoked: &mut ReceiptRevocations) {
+        revoked
+            .exponents
+            .push(receipt_exponent(&self.receipt_sk, token));
+    }
+}
+
+pub use crate::spirit::KeyId;
+
+/// Public key of the registrar in a [RegistrarKeyChain], which verifies the signatures on
+/// artifacts of the epochs from `valid_from` up to, but not including, `valid_to`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RegistrarKey {
+    pub key_id: KeyId,
+    pub public_key: PublicKey,
+    /// The [receipt key](RegistrarKeypair::receipt_key) of the key.
+    pub receipt_key: PublicKey,
+    pub valid_from: Epoch,
+    /// [Epoch::MAX] until the key is retired by a [rotation](RegistrarKeyChain::rotate).
+    pub valid_to: Epoch,
+}
+
+impl RegistrarKey {
+    pub fn is_valid_at(&self, epoch: Epoch) -> bool {
+        self.valid_from <= epoch && epoch < self.valid_to
+    }
+}
+
+/// The registrar's public keys over time, for verifiers of artifacts signed before and after a
+/// key rotation. Every registrar signature carries the [KeyId] of its key, and a signature only
+/// verifies if it is valid under the key and the key is valid in the epoch of the signed
+/// artifact, so that a retired key cannot sign artifacts of later epochs. Verifiers check with
+/// the chain [at](Self::at) their current epoch, so neither can it sign backdated ones.
+///
+/// A [rotation](Self::rotate) activates a new key and retires the current one `overlap` epochs
+/// later, so both keys verify while the deployment switches over, e.g. while replicas with
+/// deltas of the old key catch up.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RegistrarKeyChain {
+    keys: Vec<RegistrarKey>,
+    overlap: Epoch,
+}
+
+impl RegistrarKeyChain {
+    /// Chain of the key of `registrar`, valid from `valid_from` on.
+    pub fn new(registrar: &RegistrarKeypair, valid_from: Epoch, overlap: Epoch) -> Self {
+        Self {
+            keys: vec![RegistrarKey {
+                key_id: registrar.key_id,
+                public_key: registrar.pk.clone(),
+                receipt_key: registrar.receipt_pk.clone(),
+                valid_from,
+                valid_to: Epoch::MAX,
+            }],
+            overlap,
+        }
+    }
+
+    /// The keys in the order of their activation, with the current key last.
+    pub fn keys(&self) -> &[RegistrarKey] {
+        &self.keys
+    }
+
+    pub fn key(&self, key_id: KeyId) -> Option<&RegistrarKey> {
+        self.keys.iter().find(|key| key.key_id == key_id)
+    }
+
+    /// The key that signs from now on.
+    pub fn current(&self) -> &RegistrarKey {
+        &self.keys[self.keys.len() - 1]
+    }
+
+    /// The chain as a verifier in `current_epoch` checks signatures with it. The epoch of an
+    /// artifact is set by its signer, so a retired key could still sign artifacts dated back
+    /// into its validity. The chain therefore rejects the signatures of a key that is retired
+    /// by `current_epoch`, whatever their epoch, and those of artifacts dated after
+    /// `current_epoch`.
+    pub fn at(&self, current_epoch: Epoch) -> KeyChainAt<'_> {
+        KeyChainAt {
+            chain: self,
+            current_epoch,
+        }
+    }
+
+    /// Activates `new_sk` as the key from `activation_epoch` on, under the next key id, and
+    /// retires the current key after the overlap. Returns the keypair of the new key, whose
+    /// signatures carry its id. Fails if `activation_epoch` is not after the epoch from which
+    /// the current key is valid, so the windows of the keys stay in order.
+    pub fn rotate(
+        &mut self,
+        new_sk: SecretKey,
+        activation_epoch: Epoch,
+    ) -> Result<RegistrarKeypair, KeyChainError> {
+        let overlap = self.overlap;
+        let current = &mut self.keys[self.keys.len() - 1];
+        if activation_epoch <= current.valid_from {
+            return Err(KeyChainError::EarlyActivation {
+                activation_epoch,
+                valid_from: current.valid_from,
+            });
+        }
+        current.valid_to = activation_epoch.saturating_add(overlap);
+
+        let keypair = RegistrarKeypair::with_key_id(new_sk, current.key_id + 1);
+        self.keys.push(RegistrarKey {
+            key_id: keypair.key_id,
+            public_key: keypair.pk.clone(),
+            receipt_key: keypair.receipt_pk.clone(),
+            valid_from: activation_epoch,
+            valid_to: Epoch::MAX,
+        });
+        Ok(keypair)
+    }
+}
+
+/// A [RegistrarKeyChain] in the current epoch of a verifier, see [RegistrarKeyChain::at].
+#[derive(Debug, Clone, Copy)]
+pub struct KeyChainAt<'a> {
+    chain: &'a RegistrarKeyChain,
+    current_epoch: Epoch,
+}
+
+/// The registrar's keys that its signatures are checked with, either a [RegistrarKeyChain] in
+/// the verifier's current epoch or a single [PublicKey], which checks the signatures of every
+/// key id and epoch.
+pub trait RegistrarKeys {
+    /// The key `key_id`, if it is valid in `epoch`, the epoch of the signed artifact.
+    fn verifying_key(&self, key_id: KeyId, epoch: Epoch) -> Result<&PublicKey, KeyChainError>;
+
+    /// The [receipt key](RegistrarKeypair::receipt_key) of the key `key_id`, if it is valid in
+    /// `epoch`.
+    fn receipt_key(&self, key_id: KeyId, epoch: Epoch) -> Result<&PublicKey, KeyChainError>;
+}
+
+/// A single key, which is the receipt key for the verifiers of receipts.
+impl RegistrarKeys for PublicKey {
+    fn verifying_key(&self, _: KeyId, _: Epoch) -> Result<&PublicKey, KeyChainError> {
+        Ok(self)
+    }
+
+    fn receipt_key(&self, _: KeyId, _: Epoch) -> Result<&PublicKey, KeyChainError> {
+        Ok(self)
+    }
+}
+
+impl KeyChainAt<'_> {
+    fn valid_key(&self, key_id: KeyId, epoch: Epoch) -> Result<&RegistrarKey, KeyChainError> {
+        let key = self
+            .chain
+            .key(key_id)
+            .ok_or(KeyChainError::UnknownKey { key_id })?;
+        if epoch > self.current_epoch {
+            return Err(KeyChainError::FutureEpoch {
+                epoch,
+                current_epoch: self.current_epoch,
+            });
+        }
+        if self.current_epoch >= key.valid_to {
+            return Err(KeyChainError::RetiredKey {
+                key_id,
+                valid_to: key.valid_to,
+            });
+        }
+        if !key.is_valid_at(epoch) {
+            return Err(KeyChainError::OutsideValidity { key_id, epoch });
+        }
+        Ok(key)
+    }
+}
+
+impl RegistrarKeys for KeyChainAt<'_> {
+    fn verifying_key(&self, key_id: KeyId, epoch: Epoch) -> Result<&PublicKey, KeyChainError> {
+        Ok(&self.valid_key(key_id, epoch)?.public_key)
+    }
+
+    fn receipt_key(&self, key_id: KeyId, epoch: Epoch) -> Result<&PublicKey, KeyChainError> {
+        Ok(&self.valid_key(key_id, epoch)?.receipt_key)
+    }
+}
+
+/// Message of a [BroadcastAnchor] that the registrar signs.
+pub(crate) fn anchor_message(root: &MerkleHash, size: usize, key_id: KeyId) -> Vec<u8> {
+    let mut msg = root.to_vec();
+    msg.extend_from_slice(&(size as u64).to_le_bytes());
+    msg.extend_from_slice(&key_id.to_le_bytes());
+    msg
+}
+
+/// Message of a [KeyRotation] that the registrar signs.
+pub(crate) fn rotation_message(old: &TokenId, new: &TokenId, key_id: KeyId) -> Vec<u8> {
+    [&old[..], &new[..], &key_id.to_le_bytes()].concat()
+}
+
+/// Checks a [RegistrarKeypair::sign] signature in both source groups, like
+/// [PublicKey::verify_pop].
+pub(crate) fn verify_registrar_signature(
+    registrar: &PublicKey,
+    domain: &[u8],
+    seq: u64,
+    msg: &[u8],
+    signature: &Signature,
+) -> bool {
+    verify_signed_point(registrar, &registrar_message(domain, seq, msg), signature)
+}
+
+/// Checks that `signature` is `point^sk` of the registrar, in both source groups.
+pub(crate) fn verify_signed_point(
+    registrar: &PublicKey,
+    point: &G1G2,
+    signature: &Signature,
+) -> bool {
+    let g = &get_parameters().g;
+    let check = -point;
+
+    multi_pairing(&[(&check, &registrar.0), (&signature.0, g)]).is_zero()
+        && multi_pairing(&[(&registrar.0, &check), (g, &signature.0)]).is_zero()
+}
+
+/// Merkle root over the first `size` registered tokens of the registrar `registrar_id`, signed
+/// in `epoch` with the registrar's key `key_id` at the sequence number `seq` of the tree. The
+/// signature covers `registrar_id`, so the root of one shard is not accepted for another, even
+/// if both are signed under the same key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SignedRoot {
+    pub registrar_id: RegistrarId,
+    pub root: MerkleHash,
+    pub size: usize,
+    pub seq: u64,
+    pub epoch: Epoch,
+    pub key_id: KeyId,
+    pub signature: Signature,
+}
+
+/// `registrar_id || root || size || epoch || key_id`, the message of a [SignedRoot], with its
+/// `seq` signed along.
+fn root_message(
+    registrar_id: RegistrarId,
+    root: &MerkleHash,
+    size: usize,
+    epoch: Epoch,
+    key_id: KeyId,
+) -> Vec<u8> {
+    let mut msg = registrar_id.to_le_bytes().to_vec();
+    msg.extend_from_slice(root);
+    msg.extend_from_slice(&(size as u64).to_le_bytes());
+    msg.extend_from_slice(&epoch.to_le_bytes());
+    msg.extend_from_slice(&key_id.to_le_bytes());
+    msg
+}
+
+impl SignedRoot {
+    fn message(&self) -> Vec<u8> {
+        root_message(
+            self.registrar_id,
+            &self.root,
+            self.size,
+            self.epoch,
+            self.key_id,
+        )
+    }
+
+    /// Checks the signature with the registrar's key [Self::key_id]. With a
+    /// [RegistrarKeyChain], the key has to be valid in the epoch of the root.
+    pub fn verify(&self, registrar: &(impl RegistrarKeys + ?Sized)) -> bool {
+        registrar
+            .verifying_key(self.key_id, self.epoch)
+            .is_ok_and(|registrar| {
+                verify_registrar_signature(
+                    registrar,
+                    REGISTRY_ROOT_DST,
+                    self.seq,
+                    &self.message(),
+                    &self.signature,
+                )
+            })
+    }
+
+    /// [Self::verify] that also rejects roots older than the one at `latest_seq`, which is
+    /// advanced to [Self::seq] if the root is accepted.
+    pub fn verify_fresh(
+        &self,
+        registrar: &(impl RegistrarKeys + ?Sized),
+        latest_seq: &mut u64,
+    ) -> bool {
+        if self.seq < *latest_seq || !self.verify(registrar) {
+            return false;
+        }
+        *latest_seq = self.seq;
+        true
+    }
+}
+
+/// Authentication path of the leaf at `index` in a tree with `SignedRoot::size` leaves.
+/// Levels at which the path node has no sibling are skipped, so `siblings` holds at most
+/// `ceil(log2(size))` hashes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MembershipProof {
+    pub index: usize,
+    pub siblings: Vec<MerkleHash>,
+}
+
+impl MembershipProof {
+    /// Checks that `token` is the leaf at `self.index` of the tree committed to by `root`. The
+    /// signature on `root` is not checked, see [SignedRoot::verify].
+    pub fn verify(&self, root: &SignedRoot, token: &Token) -> bool {
+        self.verify_leaf(&root.root, root.size, merkle_leaf(token))
+    }
+
+    /// Checks that `leaf` is the leaf at `self.index` of the tree of `size` leaves with the
+    /// root `root`.
+    pub(crate) fn verify_leaf(&self, root: &MerkleHash, size: usize, leaf: MerkleHash) -> bool {
+        if self.index >= size {
+            return false;
+        }
+
+        let mut siblings = self.siblings.iter();
+        let mut hash = leaf;
+        let (mut index, mut width) = (self.index, size);
+        while width > 1 {
+            if index % 2 == 1 {
+                let Some(sibling) = siblings.next() else {
+                    return false;
+                };
+                hash = merkle_node(sibling, &hash);
+            } else if index + 1 < width {
+                let Some(sibling) = siblings.next() else {
+                    return false;
+                };
+                hash = merkle_node(&hash, sibling);
+            }
+            index /= 2;
+            width = width.div_ceil(2);
+        }
+
+        siblings.next().is_none() && hash == *root
+    }
+}
+
+/// Token registry maintaining a Merkle tree over the [Token::to_bytes] encodings of the
+/// registered tokens in insertion order. The last node of a level without a sibling is
+/// promoted to the next level unchanged, so inserting a token only recomputes the path from
+/// the new leaf to the root.
+pub struct MerkleRegistry {
+    registrar: RegistrarKeypair,
+    registrar_id: RegistrarId,
+    /// `levels[0]` holds the leaves, the last level holds the root.
+    levels: Vec<Vec<MerkleHash>>,
+    index: HashMap<Token, usize>,
+    /// Number of insertions and purges, the sequence number of [Self::signed_root].
+    seq: u64,
+}
+
+impl MerkleRegistry {
+    pub fn new(registrar: RegistrarKeypair) -> Self {
+        Self::for_shard(registrar, 0)
+    }
+
+    /// Registry of the shard of the registrar `registrar_id` of a sharded deployment, whose
+    /// [SignedRoot]s carry the identifier.
+    pub fn for_shard(registrar: RegistrarKeypair, registrar_id: RegistrarId) -> Self {
+        Self {
+            registrar,
+            registrar_id,
+            levels: vec![Vec::new()],
+            index: HashMap::new(),
+            seq: 0,
+        }
+    }
+
+    /// Key under which the [SignedRoot]s of the registry are signed.
+    pub fn verifying_key(&self) -> PublicKey {
+        self.registrar.public_key().clone()
+    }
+
+    pub fn registrar_id(&self) -> RegistrarId {
+        self.registrar_id
+    }
+
+    pub fn root(&self) -> MerkleHash {
+        self.levels
+            .last()
+            .and_then(|level| level.first())
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// [Self::root] signed in `epoch` with the registrar's key.
+    pub fn signed_root(&self, epoch: Epoch) -> SignedRoot {
+        let (root, size, key_id) = (self.root(), self.index.len(), self.registrar.key_id);
+        let message = root_message(self.registrar_id, &root, size, epoch, key_id);
+        SignedRoot {
+            registrar_id: self.registrar_id,
+            root,
+            size,
+            seq: self.seq,
+            epoch,
+            key_id,
+            signature: self.registrar.sign(REGISTRY_ROOT_DST, self.seq, &message),
+        }
+    }
+
+    /// Returns the membership proof of `token` against the current [MerkleRegistry::root].
+    pub fn prove(&self, token: &Token) -> Option<MembershipProof> {
+        let leaf = *self.index.get(token)?;
+        Some(merkle_path(&self.levels, leaf))
+    }
+
+    fn push_leaf(&mut self, leaf: MerkleHash) {
+        self.levels[0].push(leaf);
+
+        let mut index = self.levels[0].len() - 1;
+        let mut level = 0;
+        while self.levels[level].len() > 1 {
+            let parent = index / 2;
+            let nodes = &self.levels[level];
+            let hash = match nodes.get(2 * parent + 1) {
+                Some(right) => merkle_node(&nodes[2 * parent], right),
+                None => nodes[2 * parent],
+            };
+
+            if self.levels.len() == level + 1 {
+                self.levels.push(Vec::new());
+            }
+            let parents = &mut self.levels[level + 1];
+            if parent == parents.len() {
+                parents.push(hash);
+            } else {
+                parents[parent] = hash;
+            }
+
+            index = parent;
+            level += 1;
+        }
+    }
+}
+
+impl TokenRegistry for MerkleRegistry {
+    fn insert(&mut self, token: Token) -> Result<bool, RegistryError> {
+        if self.index.contains_key(&token) {
+            return Ok(false);
+        }
+
+        self.push_leaf(merkle_leaf(&token));
+        self.index.insert(token, self.levels[0].len() - 1);
+        self.seq += 1;
+        Ok(true)
+    }
+
+    fn contains(&self, token: &Token) -> bool {
+        self.index.contains_key(token)
+    }
+
+    fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    /// The tokens in their insertion order, so a registry imported from them has the same
+    /// tree.
+    fn tokens(&self) -> Box<dyn Iterator<Item = Token> + '_> {
+        let mut tokens: Vec<_> = self.index.iter().collect();
+        tokens.sort_unstable_by_key(|(_, leaf)| **leaf);
+        Box::new(tokens.into_iter().map(|(token, _)| token.clone()))
+    }
+
+    /// Rebuilds the tree from the remaining tokens in their insertion order. Roots and
+    /// membership proofs issued before purging become stale.
+    fn purge_expired(&mut self, current_epoch: Epoch) -> Result<usize, RegistryError> {
+        let len = self.index.len();
+        let mut tokens: Vec<_> = self
+            .index
+            .drain()
+            .filter(|(token, _)| !token.is_expired(current_epoch))
+            .collect();
+        tokens.sort_unstable_by_key(|(_, leaf)| *leaf);
+
+        self.levels = vec![Vec::new()];
+        for (token, _) in tokens {
+            self.push_leaf(merkle_leaf(&token));
+            self.index.insert(token, self.levels[0].len() - 1);
+        }
+        self.seq += 1;
+        Ok(len - self.index.len())
+    }
+}
+
+/// Bloom filter over the [Token::to_bytes] encodings of registered tokens, for verifiers that
+/// only need a cheap pre-filter before contacting the registrar. It never reports a registered
+/// token as absent, but reports an unregistered one as present with roughly the configured
+/// false-positive rate.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegistryFilter {
+    num_hashes: u32,
+    bits: Vec<u64>,
+}
+
+impl RegistryFilter {
+    /// Maximum number of hash functions of a filter, which bounds the work of a lookup in a
+    /// decoded filter. It is reached at false-positive rates around `2^-64`.
+    pub const MAX_HASHES: u32 = 64;
+
+    /// Builds a filter over `tokens` with the given target false-positive rate, which must be in
+    /// the open interval (0, 1) and need at most [Self::MAX_HASHES] hash functions.
+    pub fn new<'a>(
+        tokens: impl ExactSizeIterator<Item = &'a Token>,
+        false_positive_rate: f64,
+    ) -> Result<Self, RegistryError> {
+        let mut filter = Self::with_capacity(tokens.len(), false_positive_rate)?;
+        for token in tokens {
+            filter.insert_encoding(&token.payload());
+        }
+        Ok(filter)
+    }
+
+    fn with_capacity(capacity: usize, false_positive_rate: f64) -> Result<Self, RegistryError> {
+        if !(0.0 < false_positive_rate && false_positive_rate < 1.0) {
+            return Err(RegistryError::InvalidFalsePositiveRate);
+        }
+
+        let capacity = capacity.max(1) as f64;
+        let ln2 = std::f64::consts::LN_2;
+        let num_bits = (-capacity * false_positive_rate.ln() / (ln2 * ln2)).ceil();
+        let num_words = (num_bits / 64.0).ceil().max(1.0) as usize;
+        let num_hashes = ((num_words * 64) as f64 / capacity * ln2).round().max(1.0);
+        if num_hashes > f64::from(Self::MAX_HASHES) {
+            return Err(RegistryError::InvalidFalsePositiveRate);
+        }
+
+        Ok(Self {
+            num_hashes: num_hashes as u32,
+            bits: vec![0; num_words],
+        })
+    }
+
+    fn bit_indices(&self, encoding: &[u8]) -> impl Iterator<Item = usize> + use<> {
+        let digest = Sha256::new()
+            .chain_update(REGISTRY_FILTER_DST)
+            .chain_update(encoding)
+            .finalize();
+        let h1 = digest_word(&digest, 0);
+        let h2 = digest_word(&digest, 8) | 1;
+        let num_bits = (self.bits.len() * 64) as u64;
+
+        (0..u64::from(self.num_hashes))
+            .map(move |i| (h1.wrapping_add(i.wrapping_mul(h2)) % num_bits) as usize)
+    }
+
+    fn insert_encoding(&mut self, encoding: &[u8]) {
+        for index in self.bit_indices(encoding) {
+            self.bits[index / 64] |= 1 << (index % 64);
+        }
+    }
+
+    fn contains_encoding(&self, encoding: &[u8]) -> bool {
+        self.bit_indices(encoding)
+            .all(|index| self.bits[index / 64] & (1 << (index % 64)) != 0)
+    }
+
+    pub fn maybe_contains(&self, token: &Token) -> bool {
+        self.contains_encoding(&token.payload())
+    }
+
+    /// Encodes the filter as the number of hash functions (`u32`) followed by the bit vector
+    /// as `u64` words, all little-endian, behind an [envelope](crate::envelope) header.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(HEADER_SIZE + 4 + 8 * self.bits.len());
+        bytes.extend_from_slice(&envelope::header(ArtifactType::RegistryFilter));
+        bytes.extend_from_slice(&self.num_hashes.to_le_bytes());
+        for word in &self.bits {
+            bytes.extend_from_slice(&word.to_le_bytes());
+        }
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, RegistryError> {
+        match envelope::open(bytes, ArtifactType::RegistryFilter)
+            .map_err(RegistryError::InvalidEncoding)?
+        {
+            (1, payload) => Self::from_payload_v1(payload),
+            (version, _) => Err(RegistryError::InvalidEncoding(
+                DecodeError::UnsupportedVersion(version),
+            )),
+        }
+    }
+
+    fn from_payload_v1(bytes: &[u8]) -> Result<Self, RegistryError> {
+        if bytes.len() < 4 + 8 || (bytes.len() - 4) % 8 != 0 {
+            return Err(RegistryError::InvalidFilter);
+        }
+
+        let (num_hashes, bits) = split_array::<4>(bytes).map_err(RegistryError::InvalidEncoding)?;
+        let num_hashes = u32::from_le_bytes(*num_hashes);
+        if num_hashes == 0 || num_hashes > Self::MAX_HASHES {
+            return Err(RegistryError::InvalidFilter);
+        }
+        let bits = bits
+            .chunks_exact(8)
+            .map(|word| word.try_into().map(u64::from_le_bytes))
+            .collect::<Result<_, _>>()
+            .map_err(|_| RegistryError::InvalidFilter)?;
+
+        Ok(Self { num_hashes, bits })
+    }
+}
+
+pub use crate::spirit::TokenId;
+
+/// Identifiers ([Token::id]) of revoked tokens. Verifiers reject revoked tokens even if they are
+/// still registered.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RevocationList {
+    revoked: BTreeSet<TokenId>,
+}
+
+impl RevocationList {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Revokes `token`. Returns `false` if it was already revoked.
+    pub fn revoke(&mut self, token: &Token) -> bool {
+        self.revoked.insert(token.id())
+    }
+
+    /// Revokes the token of identifier `id`, e.g. of a revocation synced from another registry.
+    /// Returns `false` if it was already revoked.
+    pub fn revoke_id(&mut self, id: TokenId) -> bool {
+        self.revoked.insert(id)
+    }
+
+    pub fn is_revoked(&self, token: &Token) -> bool {
+        self.revoked.contains(&token.id())
+    }
+
+    pub fn len(&self) -> usize {
+        self.revoked.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.revoked.is_empty()
+    }
+
+    /// Encodes the list as the concatenation of the sorted token identifiers, behind an
+    /// [envelope](crate::envelope) header.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let header = envelope::header(ArtifactType::RevocationList);
+        header
+            .into_iter()
+            .chain(self.revoked.iter().flatten().copied())
+            .collect()
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, RegistryError> {
+        match envelope::open(bytes, ArtifactType::RevocationList)
+            .map_err(RegistryError::InvalidEncoding)?
+        {
+            (1, payload) => Self::from_payload_v1(payload),
+            (version, _) => Err(RegistryError::InvalidEncoding(
+                DecodeError::UnsupportedVersion(version),
+            )),
+        }
+    }
+
+    fn from_payload_v1(bytes: &[u8]) -> Result<Self, RegistryError> {
+        if bytes.len() % size_of::<TokenId>() != 0 {
+            return Err(RegistryError::InvalidRevocationList);
+        }
+
+        let ids: Vec<TokenId> = bytes
+            .chunks_exact(size_of::<TokenId>())
+            .map(TokenId::try_from)
+            .collect::<Result<_, _>>()
+            .map_err(|_| RegistryError::InvalidRevocationList)?;
+        if !ids.is_sorted_by(|lhs, rhs| lhs < rhs) {
+            return Err(RegistryError::InvalidRevocationList);
+        }
+
+        Ok(Self {
+            revoked: ids.into_iter().collect(),
+        })
+    }
+}
+
+/// Registry membership as seen by a verifier: either an exact registry, a [RegistryFilter], or
+/// the shards of a registry sharded over several registrars.
+#[derive(Clone, Copy)]
+pub enum RegistryView<'a> {
+    Exact(&'a dyn TokenRegistry),
+    /// Membership is only advisory: unregistered tokens pass with the filter's
+    /// false-positive rate.
+    Filter(&'a RegistryFilter),
+    /// The shards by their registrar. [spirit_verify](crate::spirit::spirit_verify) checks the
+    /// token of a report in the shard of its [registrar](crate::spirit::TraceReport::registrar),
+    /// and rejects reports of other registrars with
+    /// [VerifyRejection::UnknownRegistrar](crate::spirit::VerifyRejection::UnknownRegistrar).
+    Sharded(&'a HashMap<RegistrarId, RegistryHandle<'a>>),
+}
+
+impl RegistryView<'_> {
+    /// Whether `token` is in the registry, or in any of its shards.
+    pub fn contains(&self, token: &Token) -> bool {
+        match self {
+            Self::Exact(t_rgstr) => t_rgstr.contains(token),
+            Self::Filter(filter) => filter.maybe_contains(token),
+            Self::Sharded(shards) => shards.values().any(|shard| shard.contains(token)),
+        }
+    }
+}
+
+/// One registrar's shard of a sharded registry, as known to a verifier.
+#[derive(Clone, Copy)]
+pub enum RegistryHandle<'a> {
+    /// The shard itself, or a filter of it.
+    View(RegistryView<'a>),
+    /// A root of the shard signed by its `registrar`, and the [MembershipProof] of the token of
+    /// the report to check, as for
+    /// [spirit_verify_with_proof](crate::spirit::spirit_verify_with_proof). Unlike there, the
+    /// freshness of the root is not checked.
+    Root {
+        root: &'a SignedRoot,
+        registrar: &'a dyn RegistrarKeys,
+        membership: &'a MembershipProof,
+    },
+}
+
+impl RegistryHandle<'_> {
+    pub fn contains(&self, token: &Token) -> bool {
+        match self {
+            Self::View(view) => view.contains(token),
+            Self::Root {
+                root,
+                registrar,
+                membership,
+            } => root.verify(*registrar) && membership.verify(root, token),
+        }
+    }
+}
+
+impl<'a, R: TokenRegistry> From<&'a R> for RegistryView<'a> {
+    fn from(t_rgstr: &'a R) -> Self {
+        Self::Exact(t_rgstr)
+    }
+}
+
+impl<'a> From<&'a RegistryFilter> for RegistryView<'a> {
+    fn from(filter: &'a RegistryFilter) -> Self {
+        Self::Filter(filter)
+    }
+}
+
+impl<'a, 'b: 'a> From<&'a HashMap<RegistrarId, RegistryHandle<'b>>> for RegistryView<'a> {
+    fn from(shards: &'a HashMap<RegistrarId, RegistryHandle<'b>>) -> Self {
+        Self::Sharded(shards)
+    }
+}
+
+#[derive(Error, Debug, PartialEq, Clone)]
+pub enum RegistryError {
+    #[error("Registry I/O failed ({kind}): {message}")]
+    Io {
+        kind: io::ErrorKind,
+        message: String,
+    },
+    #[error("Registry log has length {length}, which is not a multiple of the token size.")]
+    TruncatedLog { length: usize },
+    #[error("Registry log entry {entry} is invalid: {source}")]
+    CorruptEntry { entry: usize, source: DecodeError },
+    #[error("Invalid registry filter encoding.")]
+    InvalidFilter,
+    #[error("Invalid revocation list encoding.")]
+    InvalidRevocationList,
+    #[error("Invalid registry encoding: {0}")]
+    InvalidEncoding(#[source] DecodeError),
+    #[error("Registry snapshot is truncated.")]
+    TruncatedSnapshot,
+    #[error("Registry snapshot checksum does not match its contents.")]
+    ChecksumMismatch,
+    #[error("Registry snapshot has trailing bytes after the signature.")]
+    TrailingSnapshotBytes,
+    #[error("Registry snapshot is not signed by the registrar.")]
+    InvalidSignature,
+    #[error("Registry snapshot {seq} is older than the imported snapshot {latest}.")]
+    StaleSnapshot { seq: u64, latest: u64 },
+    #[error("Registry index has length {length}, which is not a multiple of the token size.")]
+    TruncatedIndex { length: usize },
+    #[error("Registry is read-only.")]
+    ReadOnly,
+    #[error("Registry snapshot is signed with an invalid key: {0}")]
+    InvalidKey(#[source] KeyChainError),
+    #[error(
+        "Registry filter false-positive rate must be in (0, 1) and need at most {} hash \
+         functions.",
+        RegistryFilter::MAX_HASHES
+    )]
+    InvalidFalsePositiveRate,
+    #[error("Registry index entry {entry} is not after the entry before it.")]
+    UnsortedIndex { entry: usize },
+}
+
+#[derive(Error, Debug, PartialEq, Eq, Clone)]
+pub enum KeyChainError {
+    #[error("Unknown registrar key {key_id}.")]
+    UnknownKey { key_id: KeyId },
+    #[error("Registrar key {key_id} is not valid in epoch {epoch}.")]
+    OutsideValidity { key_id: KeyId, epoch: Epoch },
+    #[error(
+        "Activation epoch {activation_epoch} is not after epoch {valid_from}, from which the \
+         current key is valid."
+    )]
+    EarlyActivation {
+        activation_epoch: Epoch,
+        valid_from: Epoch,
+    },
+    #[error("Registrar key {key_id} was retired in epoch {valid_to}.")]
+    RetiredKey { key_id: KeyId, valid_to: Epoch },
+    #[error("Epoch {epoch} of the signed artifact is after the current epoch {current_epoch}.")]
+    FutureEpoch { epoch: Epoch, current_epoch: Epoch },
+}
+
+impl From<io::Error> for RegistryError {
+    fn from(error: io::Error) -> Self {
+        Self::Io {
+            kind: error.kind(),
+            message: error.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{env, fs, path::PathBuf, thread};
+
+    use proptest::{collection::btree_set, prelude::any, prop_assert_eq, proptest};
+    use rand::{Rng, rngs::OsRng};
+
+    use super::*;
+    use crate::{
+        bls381_helpers::test::generator_multiple,
+        pedersen::Commitment,
+        spirit::{
+            Accepted, ChallengeSet, EncounterTable, Interval, SetupOutput, UserKeys, UserSecret,
+            spirit_broadcast_osrng, spirit_diagnosis_osrng, spirit_register_osrng,
+            spirit_setup_osrng, spirit_verify, spirit_verify_batch,
+        },
+        tsw::Signature,
+    };
+
+    fn registrar() -> RegistrarKeypair {
+        RegistrarKeypair::random(&mut OsRng)
+    }
+
+    fn temp_log() -> PathBuf {
+        env::temp_dir().join(format!("spirit-registry-{:016x}.log", OsRng.r#gen::<u64>()))
+    }
+
+    #[test]
+    fn file_registry_reopen() {
+        let path = temp_log();
+        let SetupOutput {
+            pp,
+            issuers,
+            pk_ha,
+            domain,
+            ..
+        } = spirit_setup_osrng(4, 8, 8, b"test").expect("setup failed");
+        let keys = UserKeys::random(OsRng);
+
+        let ppu = {
+            let mut t_rgstr = FileRegistry::open(&path).unwrap();
+            let (token, strg) = spirit_register_osrng(
+                &domain,
+                &UserSecret::random(OsRng),
+                &issuers,
+                &pp,
+                100,
+                &mut t_rgstr,
+            )
+            .expect("registration failed");
+            assert_eq!(t_rgstr.insert(token.clone()), Ok(false));
+            (token, strg)
+        };
+
+        let t_rgstr = FileRegistry::open(&path).unwrap();
+        assert_eq!(t_rgstr.len(), 1);
+        assert!(t_rgstr.contains(&ppu.0));
+
+        let mut t_el = EncounterTable::new();
+        spirit_broadcast_osrng(&domain, 0, Interval(3), &keys, &pk_ha, &mut t_el);
+        let mut challenges = ChallengeSet::new(0);
+        let challenge = challenges.issue(0, &mut OsRng);
+        let cp = HashSet::from([(0, Interval(3))]);
+        let report = spirit_diagnosis_osrng(&domain, &ppu, &keys, &cp, 0, &challenge, &[])
+            .expect("diagnosis failed");
+        let mut confirmed = HashSet::new();
+        assert_eq!(
+            spirit_verify(
+                &domain,
+                &report,
+                &t_rgstr,
+                &RevocationList::new(),
+                0,
+                &mut HashSet::new(),
+                &challenges,
+                &mut confirmed
+            ),
+            Ok(Accepted {
+                verdicts: vec![true]
+            })
+        );
+        assert!(t_el.el_ids().all(|el_id| confirmed.contains(&el_id)));
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn file_registry_truncated_log() {
+        let path = temp_log();
+        let log = |entries: &[u8]| envelope::seal(ArtifactType::RegistryLog, entries);
+        fs::write(&path, log(&[0u8; Token::PAYLOAD_SIZE + 1])).unwrap();
+
+        assert!(matches!(
+            FileRegistry::open(&path),
+            Err(RegistryError::TruncatedLog { length }) if length == Token::PAYLOAD_SIZE + 1
+        ));
+
+        fs::write(&path, log(&[0u8; Token::PAYLOAD_SIZE])).unwrap();
+        assert!(matches!(
+            FileRegistry::open(&path),
+            Err(RegistryError::CorruptEntry { entry: 0, .. })
+        ));
+
+        let mut later_version = log(&[]);
+        later_version[4] = 2;
+        fs::write(&path, later_version).unwrap();
+        assert!(matches!(
+            FileRegistry::open(&path),
+            Err(RegistryError::InvalidEncoding(
+                DecodeError::UnsupportedVersion(2)
+            ))
+        ));
+
+        fs::write(&path, [0u8; Token::PAYLOAD_SIZE]).unwrap();
+        assert!(matches!(
+            FileRegistry::open(&path),
+            Err(RegistryError::InvalidEncoding(DecodeError::InvalidHeader))
+        ));
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn mmap_registry_merges_a_delta_log() {
+        let (path, log_path) = (temp_log(), temp_log());
+        let SetupOutput {
+            pp,
+            issuers,
+            domain,
+            ..
+        } = spirit_setup_osrng(4, 8, 8, b"test").expect("setup failed");
+        let user = UserSecret::random(OsRng);
+
+        // the delta log holds a registered token, a new one and one of the built registry
+        let tokens = consecutive_tokens(64);
+        let t_rgstr = MmapRegistry::build_from_iter(&path, tokens.iter().cloned()).unwrap();
+        assert_eq!(t_rgstr.len(), 64);
+        let ppu = {
+            let mut log = FileRegistry::open(&log_path).unwrap();
+            let ppu = spirit_register_osrng(&domain, &user, &issuers, &pp, 100, &mut log)
+                .expect("registration failed");
+            log.insert(random_token()).unwrap();
+            log.insert(tokens.iter().next().unwrap().clone()).unwrap();
+            ppu
+        };
+        let delta = FileRegistry::open(&log_path).unwrap();
+        let merged = t_rgstr.merge(&path, delta.tokens()).unwrap();
+        assert_eq!(merged.len(), 66);
+        assert!(tokens.iter().all(|token| merged.contains(token)));
+        assert!(merged.contains(&ppu.0) && !t_rgstr.contains(&ppu.0));
+        assert!(!merged.contains(&random_token()));
+        assert_eq!(merged.tokens().count(), 66);
+        // the old registry keeps reading the replaced file
+        assert!(tokens.iter().all(|token| t_rgstr.contains(token)));
+        assert_eq!(t_rgstr.tokens().count(), 64);
+
+        // an incremental rebuild writes the file of a full one
+        let rebuilt = fs::read(&path).unwrap();
+        let full = temp_log();
+        MmapRegistry::build_from_iter(&full, merged.tokens().chain(delta.tokens())).unwrap();
+        assert_eq!(fs::read(&full).unwrap(), rebuilt);
+
+        // reports are batch-verified against the mapped registry
+        let mut challenges = ChallengeSet::new(0);
+        let challenge = challenges.issue(0, &mut OsRng);
+        let cp = HashSet::from([(0, Interval(2))]);
+        let report = spirit_diagnosis_osrng(&domain, &ppu, user.keys(), &cp, 0, &challenge, &[])
+            .expect("diagnosis failed");
+        let verify = |t_rgstr: &MmapRegistry| {
+            spirit_verify_batch(
+                &domain,
+                std::slice::from_ref(&report),
+                t_rgstr,
+                &RevocationList::new(),
+                0,
+                &mut HashSet::new(),
+                &challenges,
+                &mut HashSet::new(),
+            )
+        };
+        assert!(verify(&merged)[0].is_ok());
+        assert!(verify(&t_rgstr)[0].is_err());
+
+        let mut merged = merged;
+        assert_eq!(merged.insert(random_token()), Err(RegistryError::ReadOnly));
+        assert_eq!(merged.purge_expired(0), Err(RegistryError::ReadOnly));
+
+        for path in [path, log_path, full] {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn mmap_registry_invalid_files() {
+        let path = temp_log();
+        let index = |entries: &[u8]| envelope::seal(ArtifactType::RegistryIndex, entries);
+        fs::write(&path, index(&[0u8; Token::PAYLOAD_SIZE + 1])).unwrap();
+        assert!(matches!(
+            MmapRegistry::open(&path),
+            Err(RegistryError::TruncatedIndex { length }) if length == Token::PAYLOAD_SIZE + 1
+        ));
+
+        fs::write(&path, envelope::seal(ArtifactType::RegistryLog, &[])).unwrap();
+        assert!(matches!(
+            MmapRegistry::open(&path),
+            Err(RegistryError::InvalidEncoding(
+                DecodeError::UnexpectedArtifactType { .. }
+            ))
+        ));
+
+        fs::write(&path, index(&[])).unwrap();
+        assert!(MmapRegistry::open(&path).unwrap().is_empty());
+
+        // entries out of order or duplicated, which the binary search would miss
+        let mut entries: Vec<_> = consecutive_tokens(3)
+            .iter()
+            .map(|token| token.payload().to_vec())
+            .collect();
+        entries.sort();
+        fs::write(&path, index(&entries.concat())).unwrap();
+        assert_eq!(MmapRegistry::open(&path).unwrap().len(), 3);
+        entries.swap(1, 2);
+        fs::write(&path, index(&entries.concat())).unwrap();
+        assert!(matches!(
+            MmapRegistry::open(&path),
+            Err(RegistryError::UnsortedIndex { entry: 2 })
+        ));
+        entries[2] = entries[1].clone();
+        fs::write(&path, index(&entries.concat())).unwrap();
+        assert!(matches!(
+            MmapRegistry::open(&path),
+            Err(RegistryError::UnsortedIndex { entry: 2 })
+        ));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn exports_skip_corrupted_entries() {
+        let path = temp_log();
+        let keypair = registrar();
+        let tokens = consecutive_tokens(4);
+        drop(MmapRegistry::build_from_iter(&path, tokens.iter().cloned()).unwrap());
+        // the G2 point of the commitment of the second entry is the identity, which does not
+        // decode, and the order of the entries is unchanged
+        let mut file = fs::read(&path).unwrap();
+        let start = HEADER_SIZE + Token::PAYLOAD_SIZE + 48;
+        file[start..start + 96].fill(0);
+        file[start] = 0xc0;
+        fs::write(&path, file).unwrap();
+
+        let t_rgstr = MmapRegistry::open(&path).unwrap();
+        assert_eq!((t_rgstr.len(), t_rgstr.tokens().count()), (4, 3));
+        let mut snapshot = Vec::new();
+        export_registry(&t_rgstr, &keypair, 1, 0, &mut snapshot).unwrap();
+        let imported = import_registry(&snapshot[..], keypair.public_key(), &mut 0).unwrap();
+        assert_eq!(imported.len(), 3);
+        assert!(imported.iter().all(|token| tokens.contains(token)));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[cfg(feature = "mmap")]
+    #[test]
+    #[ignore = "builds and merges 1M entries, run in release mode"]
+    fn mmap_registry_1m() {
+        const ENTRIES: Epoch = 1_000_000;
+        let path = temp_log();
+        let (cm, sig) = (generator_multiple(2), generator_multiple(3));
+        let token = |expiry| Token(Commitment(cm.clone()), Signature(sig.clone()), expiry);
+
+        // every other expiry, so the misses fall between the hits
+        let t_rgstr =
+            MmapRegistry::build_from_iter(&path, (0..ENTRIES).map(|i| token(2 * i))).unwrap();
+        assert_eq!(t_rgstr.len(), ENTRIES as usize);
+        for i in [0, 1, ENTRIES / 2, ENTRIES - 1] {
+            assert!(t_rgstr.contains(&token(2 * i)));
+            assert!(!t_rgstr.contains(&token(2 * i + 1)));
+        }
+        assert!(!t_rgstr.contains(&random_token()));
+
+        let merged = t_rgstr
+            .merge(&path, (0..1000).map(|i| token(2 * i + 1)))
+            .unwrap();
+        assert_eq!(merged.len(), ENTRIES as usize + 1000);
+        assert!(merged.contains(&token(999)) && !merged.contains(&token(2001)));
+        fs::remove_file(path).unwrap();
+    }
+
+    fn random_token() -> Token {
+        random_token_expiring(Epoch::MAX)
+    }
+
+    fn random_token_expiring(expiry: Epoch) -> Token {
+        Token(
+            Commitment(G1G2::random(&mut OsRng)),
+            Signature(G1G2::random(&mut OsRng)),
+            expiry,
+        )
+    }
+
+    #[test]
+    fn purge_expired_file_registry() {
+        let path = temp_log();
+        let tokens: Vec<_> = [5, 10, 20].map(random_token_expiring).into();
+
+        {
+            let mut t_rgstr = FileRegistry::open(&path).unwrap();
+            for token in &tokens {
+                t_rgstr.insert(token.clone()).unwrap();
+            }
+            assert_eq!(t_rgstr.purge_expired(4), Ok(0));
+            assert_eq!(t_rgstr.purge_expired(10), Ok(2));
+            assert_eq!(t_rgstr.insert(random_token()), Ok(true));
+        }
+
+        let t_rgstr = FileRegistry::open(&path).unwrap();
+        assert_eq!(t_rgstr.len(), 2);
+        assert!(t_rgstr.contains(&tokens[2]));
+        assert!(!t_rgstr.contains(&tokens[0]) && !t_rgstr.contains(&tokens[1]));
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn purge_expired_merkle_registry() {
+        let mut t_rgstr = MerkleRegistry::new(registrar());
+        let tokens: Vec<_> = [5, 20, 10, 20].map(random_token_expiring).into();
+        for token in &tokens {
+            t_rgstr.insert(token.clone()).unwrap();
+        }
+        let stale_root = t_rgstr.signed_root(0);
+
+        assert_eq!(t_rgstr.purge_expired(10), Ok(2));
+        let root = t_rgstr.signed_root(0);
+        assert_eq!(root.size, 2);
+        assert!(root.seq > stale_root.seq);
+        assert_eq!(
+            t_rgstr.root(),
+            reference_root(&[merkle_leaf(&tokens[1]), merkle_leaf(&tokens[3])])
+        );
+        for token in [&tokens[1], &tokens[3]] {
+            let proof = t_rgstr.prove(token).unwrap();
+            assert!(proof.verify(&root, token));
+            assert!(!proof.verify(&stale_root, token));
+        }
+        assert!(t_rgstr.prove(&tokens[0]).is_none());
+    }
+
+    fn reference_root(leaves: &[MerkleHash]) -> MerkleHash {
+        if leaves.len() == 1 {
+            return leaves[0];
+        }
+        let parents: Vec<_> = leaves
+            .chunks(2)
+            .map(|pair| match pair {
+                [left, right] => merkle_node(left, right),
+                [node] => *node,
+                _ => unreachable!(),
+            })
+            .collect();
+        reference_root(&parents)
+    }
+
+    #[test]
+    fn merkle_proofs() {
+        let mut t_rgstr = MerkleRegistry::new(registrar());
+        let vk = t_rgstr.verifying_key();
+        let tokens: Vec<_> = (0..9).map(|_| random_token()).collect();
+
+        for (size, token) in (1..).zip(&tokens) {
+            assert_eq!(t_rgstr.insert(token.clone()), Ok(true));
+            assert_eq!(t_rgstr.insert(token.clone()), Ok(false));
+
+            let leaves: Vec<_> = tokens[..size].iter().map(merkle_leaf).collect();
+            assert_eq!(t_rgstr.root(), reference_root(&leaves));
+
+            let root = t_rgstr.signed_root(0);
+            assert_eq!(root.size, size);
+            assert_eq!(root.seq, size as u64);
+            assert!(root.verify(&vk));
+            for (index, token) in tokens[..size].iter().enumerate() {
+                let proof = t_rgstr.prove(token).unwrap();
+                assert_eq!(proof.index, index);
+                assert!(proof.siblings.len() <= size.next_power_of_two().ilog2() as usize);
+                assert!(proof.verify(&root, token));
+                assert!(!proof.verify(&root, &tokens[(index + 1) % tokens.len()]));
+            }
+        }
+        assert!(t_rgstr.prove(&random_token()).is_none());
+    }
+
+    #[test]
+    fn merkle_stale_root() {
+        let mut t_rgstr = MerkleRegistry::new(registrar());
+        let tokens: Vec<_> = (0..3).map(|_| random_token()).collect();
+        t_rgstr.insert(tokens[0].clone()).unwrap();
+        t_rgstr.insert(tokens[1].clone()).unwrap();
+        let stale_root = t_rgstr.signed_root(0);
+        let stale_proof = t_rgstr.prove(&tokens[0]).unwrap();
+
+        t_rgstr.insert(tokens[2].clone()).unwrap();
+        let root = t_rgstr.signed_root(0);
+        assert!(t_rgstr.prove(&tokens[0]).unwrap().verify(&root, &tokens[0]));
+
+        // proofs for the current tree against the stale root and vice versa
+        assert!(
+            !t_rgstr
+                .prove(&tokens[0])
+                .unwrap()
+                .verify(&stale_root, &tokens[0])
+        );
+        assert!(
+            !t_rgstr
+                .prove(&tokens[2])
+                .unwrap()
+                .verify(&stale_root, &tokens[2])
+        );
+        assert!(!stale_proof.verify(&root, &tokens[0]));
+
+        // roots signed by a different registrar
+        let vk = t_rgstr.verifying_key();
+        let other = MerkleRegistry::new(registrar());
+        assert!(!root.verify(&other.verifying_key()));
+        let relabelled = SignedRoot {
+            size: 4,
+            ..root.clone()
+        };
+        assert!(!relabelled.verify(&vk));
+        let renumbered = SignedRoot {
+            seq: 4,
+            ..root.clone()
+        };
+        assert!(!renumbered.verify(&vk));
+        let reassigned = SignedRoot {
+            registrar_id: 1,
+            ..root.clone()
+        };
+        assert!(!reassigned.verify(&vk));
+        let redated = SignedRoot {
+            epoch: 1,
+            ..root.clone()
+        };
+        assert!(!redated.verify(&vk));
+        let rekeyed = SignedRoot {
+            key_id: 1,
+            ..root.clone()
+        };
+        assert!(!rekeyed.verify(&vk));
+
+        // the stale root is validly signed, but older than the latest accepted one
+        let mut latest_seq = 0;
+        assert!(stale_root.verify_fresh(&vk, &mut latest_seq));
+        assert!(root.verify_fresh(&vk, &mut latest_seq));
+        assert_eq!(latest_seq, 3);
+        assert!(stale_root.verify(&vk));
+        assert!(!stale_root.verify_fresh(&vk, &mut latest_seq));
+        assert!(root.verify_fresh(&vk, &mut latest_seq));
+
+        // a matching stale pair is consistent, so only the freshness check above rejects it
+        assert!(stale_proof.verify(&stale_root, &tokens[0]));
+    }
+
+    #[test]
+    fn roots_are_checked_with_the_key_of_their_epoch() {
+        let first = registrar();
+        let mut chain = RegistrarKeyChain::new(&first, 0, 2);
+        let second = chain.rotate(SecretKey::random(&mut OsRng), 10).unwrap();
+        let mut old = MerkleRegistry::new(first);
+        let mut new = MerkleRegistry::new(second);
+        for t_rgstr in [&mut old, &mut new] {
+            t_rgstr.insert(random_token()).unwrap();
+        }
+        assert_eq!(
+            (old.signed_root(3).key_id, new.signed_root(10).key_id),
+            (0, 1)
+        );
+
+        // the old key verifies its roots until the end of the overlap, the new one from its
+        // activation on
+        let mut latest_seq = 0;
+        assert!(old.signed_root(3).verify(&chain.at(11)));
+        assert!(
+            old.signed_root(11)
+                .verify_fresh(&chain.at(11), &mut latest_seq)
+        );
+        assert!(!old.signed_root(12).verify(&chain.at(12)));
+        assert!(
+            !old.signed_root(12)
+                .verify_fresh(&chain.at(12), &mut latest_seq)
+        );
+        assert!(new.signed_root(10).verify(&chain.at(10)));
+        assert!(!new.signed_root(9).verify(&chain.at(10)));
+
+        // once retired, the old key verifies no roots, not even those dated back into its
+        // validity, and roots dated after the verifier's epoch are rejected
+        assert!(!old.signed_root(3).verify(&chain.at(12)));
+        assert!(!new.signed_root(11).verify(&chain.at(10)));
+        assert_eq!(
+            chain.at(12).verifying_key(0, 3),
+            Err(KeyChainError::RetiredKey {
+                key_id: 0,
+                valid_to: 12
+            })
+        );
+        assert_eq!(
+            chain.at(10).verifying_key(1, 11),
+            Err(KeyChainError::FutureEpoch {
+                epoch: 11,
+                current_epoch: 10
+            })
+        );
+
+        // a root of the retired key relabelled with the epoch or key id of a valid one
+        let retired = old.signed_root(12);
+        let redated = SignedRoot {
+            epoch: 11,
+            ..retired.clone()
+        };
+        assert!(!redated.verify(&chain.at(11)));
+        let rekeyed = SignedRoot {
+            key_id: 1,
+            ..retired
+        };
+        assert!(!rekeyed.verify(&chain.at(12)));
+
+        let unknown = MerkleRegistry::new(RegistrarKeypair::with_key_id(
+            SecretKey::random(&mut OsRng),
+            7,
+        ));
+        assert!(!unknown.signed_root(10).verify(&chain.at(10)));
+    }
+
+    #[test]
+    fn filter_false_positive_rate() {
+        const REGISTERED: usize = 100_000;
+        const PROBES: usize = 100_000;
+
+        // random encodings stand in for tokens, sampling 200k valid tokens would dominate the
+        // test's run time without changing the hash inputs' distribution
+        let random_encoding = || {
+            let mut encoding = [0u8; Token::PAYLOAD_SIZE];
+            OsRng.fill_bytes(&mut encoding);
+            encoding
+        };
+
+        for false_positive_rate in [0.01, 0.001] {
+            let mut filter =
+                RegistryFilter::with_capacity(REGISTERED, false_positive_rate).unwrap();
+            let registered: Vec<_> = (0..REGISTERED).map(|_| random_encoding()).collect();
+            for encoding in &registered {
+                filter.insert_encoding(encoding);
+            }
+
+            assert!(
+                registered
+                    .iter()
+                    .all(|encoding| filter.contains_encoding(encoding))
+            );
+            let false_positives = (0..PROBES)
+                .filter(|_| filter.contains_encoding(&random_encoding()))
+                .count();
+            let measured = false_positives as f64 / PROBES as f64;
+            assert!(
+                measured <= 1.5 * false_positive_rate,
+                "measured {measured}, target {false_positive_rate}"
+            );
+        }
+    }
+
+    #[test]
+    fn filter_registered_tokens() {
+        let t_rgstr: HashSet<_> = (0..64).map(|_| random_token()).collect();
+        let filter = RegistryFilter::new(t_rgstr.iter(), 0.01).unwrap();
+        assert!(t_rgstr.iter().all(|token| filter.maybe_contains(token)));
+
+        let bytes = filter.to_bytes();
+        let decoded = RegistryFilter::from_bytes(&bytes).unwrap();
+        assert_eq!(decoded, filter);
+        assert!(t_rgstr.iter().all(|token| decoded.maybe_contains(token)));
+
+        assert_eq!(
+            RegistryFilter::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(RegistryError::InvalidFilter)
+        );
+        assert_eq!(
+            RegistryFilter::from_bytes(&envelope::seal(ArtifactType::RegistryFilter, &[0u8; 12])),
+            Err(RegistryError::InvalidFilter)
+        );
+        assert_eq!(
+            RegistryFilter::from_bytes(&RevocationList::new().to_bytes()),
+            Err(RegistryError::InvalidEncoding(
+                DecodeError::UnexpectedArtifactType {
+                    expected: ArtifactType::RegistryFilter,
+                    actual: ArtifactType::RevocationList as u8
+                }
+            ))
+        );
+
+        let empty = RegistryFilter::new(std::iter::empty(), 0.01).unwrap();
+        assert!(!empty.maybe_contains(&random_token()));
+
+        // rates outside (0, 1) or too small for the hash bound are rejected
+        for false_positive_rate in [0.0, 1.0, -0.5, f64::NAN, 1e-30] {
+            assert_eq!(
+                RegistryFilter::new(t_rgstr.iter(), false_positive_rate),
+                Err(RegistryError::InvalidFalsePositiveRate)
+            );
+        }
+        assert!(RegistryFilter::new(t_rgstr.iter(), 1e-15).is_ok());
+
+        // decoded filters are bounded in their number of hash functions
+        let mut payload = (RegistryFilter::MAX_HASHES + 1).to_le_bytes().to_vec();
+        payload.extend_from_slice(&[0xff; 8]);
+        assert_eq!(
+            RegistryFilter::from_bytes(&envelope::seal(ArtifactType::RegistryFilter, &payload)),
+            Err(RegistryError::InvalidFilter)
+        );
+        payload[..4].copy_from_slice(&RegistryFilter::MAX_HASHES.to_le_bytes());
+        assert!(
+            RegistryFilter::from_bytes(&envelope::seal(ArtifactType::RegistryFilter, &payload))
+                .unwrap()
+                .maybe_contains(&random_token())
+        );
+    }
+
+    #[test]
+    fn revocation_list_bytes() {
+        let tokens: Vec<_> = (0..3).map(|_| random_token()).collect();
+        let mut revoked = RevocationList::new();
+        assert_eq!(
+            revoked.to_bytes(),
+            envelope::header(ArtifactType::RevocationList)
+        );
+        for token in &tokens {
+            assert!(revoked.revoke(token));
+        }
+        assert!(!revoked.revoke(&tokens[0]));
+
+        let bytes = revoked.to_bytes();
+        assert_eq!(bytes.len(), HEADER_SIZE + 3 * size_of::<TokenId>());
+        let decoded = RevocationList::from_bytes(&bytes).unwrap();
+        assert_eq!(decoded, revoked);
+        assert!(tokens.iter().all(|token| decoded.is_revoked(token)));
+        assert!(!decoded.is_revoked(&random_token()));
+
+        assert_eq!(
+            RevocationList::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(RegistryError::InvalidRevocationList)
+        );
+        let ids = &bytes[HEADER_SIZE..];
+        let unsorted = envelope::seal(
+            ArtifactType::RevocationList,
+            &[&ids[32..64], &ids[..32]].concat(),
+        );
+        assert_eq!(
+            RevocationList::from_bytes(&unsorted),
+            Err(RegistryError::InvalidRevocationList)
+        );
+    }
+
+    /// `num_tokens` distinct tokens, whose points are consecutive multiples of the generators
+    /// as sampling random points would dominate the run time of large registries.
+    fn consecutive_tokens(num_tokens: usize) -> HashSet<Token> {
+        let generator = generator_multiple(1);
+        let mut point = generator.clone();
+        (0..num_tokens as Epoch)
+            .map(|expiry| {
+                point = &point + &generator;
+                Token(Commitment(point.clone()), Signature(point.clone()), expiry)
+            })
+            .collect()
+    }
+
+    const SIGNATURE_SIZE: usize = G1G2::ENCODED_SIZE;
+
+    fn assert_snapshot_round_trip(num_tokens: usize) {
+        let t_rgstr = consecutive_tokens(num_tokens);
+        let keypair = registrar();
+        let mut snapshot = Vec::new();
+        export_registry(&t_rgstr, &keypair, 1, 0, &mut snapshot).unwrap();
+        assert_eq!(
+            snapshot.len(),
+            HEADER_SIZE
+                + SNAPSHOT_PREFIX_SIZE
+                + num_tokens * Token::PAYLOAD_SIZE
+                + 32
+                + SIGNATURE_SIZE
+        );
+
+        // the expiry of the last entry, which still decodes
+        let mut tampered = snapshot.clone();
+        tampered[snapshot.len() - SIGNATURE_SIZE - 32 - 1] ^= 0x01;
+        assert_eq!(
+            import_registry(&tampered[..], keypair.public_key(), &mut 0),
+            Err(RegistryError::ChecksumMismatch)
+        );
+
+        let restored: HashSet<_> = import_registry(&snapshot[..], keypair.public_key(), &mut 0)
+            .unwrap()
+            .into_iter()
+            .collect();
+        assert_eq!(restored, t_rgstr);
+        assert!(t_rgstr.iter().all(|token| restored.contains(token)));
+        assert!(!restored.contains(&random_token()));
+    }
+
+    #[test]
+    fn registry_snapshot_round_trip() {
+        assert_snapshot_round_trip(256);
+    }
+
+    #[test]
+    #[ignore = "decodes 100k tokens, run in release mode"]
+    fn registry_snapshot_round_trip_100k() {
+        assert_snapshot_round_trip(100_000);
+    }
+
+    #[test]
+    fn invalid_registry_snapshots_are_rejected() {
+        let keypair = registrar();
+        let pk = keypair.public_key();
+        let import = |snapshot: &[u8]| import_registry(snapshot, pk, &mut 0);
+        let mut snapshot = Vec::new();
+        export_registry(&consecutive_tokens(4), &keypair, 1, 0, &mut snapshot).unwrap();
+        assert_eq!(import(&snapshot).unwrap().len(), 4);
+
+        for len in [
+            0,
+            HEADER_SIZE,
+            HEADER_SIZE + SNAPSHOT_PREFIX_SIZE + 100,
+            snapshot.len() - 1,
+        ] {
+            assert_eq!(
+                import(&snapshot[..len]),
+                Err(RegistryError::TruncatedSnapshot)
+            );
+        }
+        let mut trailing = snapshot.clone();
+        trailing.push(0);
+        assert_eq!(import(&trailing), Err(RegistryError::TrailingSnapshotBytes));
+        let mut checksum = snapshot.clone();
+        checksum[snapshot.len() - SIGNATURE_SIZE - 1] ^= 0x01;
+        assert_eq!(import(&checksum), Err(RegistryError::ChecksumMismatch));
+        let mut point = snapshot.clone();
+        point[HEADER_SIZE + SNAPSHOT_PREFIX_SIZE + 20] ^= 0x01;
+        assert!(matches!(
+            import(&point),
+            Err(RegistryError::CorruptEntry { entry: 0, .. })
+        ));
+
+        let mut foreign = Vec::new();
+        export_registry(&consecutive_tokens(4), &registrar(), 1, 0, &mut foreign).unwrap();
+        assert_eq!(import(&foreign), Err(RegistryError::InvalidSignature));
+        let mut unsigned = snapshot.clone();
+        unsigned[snapshot.len() - SIGNATURE_SIZE..].copy_from_slice(&G1G2::default().to_bytes());
+        assert!(matches!(
+            import(&unsigned),
+            Err(RegistryError::InvalidEncoding(_))
+        ));
+        assert_eq!(
+            import(&RevocationList::new().to_bytes()),
+            Err(RegistryError::InvalidEncoding(
+                DecodeError::UnexpectedArtifactType {
+                    expected: ArtifactType::RegistrySnapshot,
+                    actual: ArtifactType::RevocationList as u8
+                }
+            ))
+        );
+    }
+
+    #[test]
+    fn lenient_imports_skip_corrupted_entries() {
+        let keypair = registrar();
+        let pk = keypair.public_key();
+        let mut snapshot = Vec::new();
+        export_registry(&consecutive_tokens(5), &keypair, 1, 0, &mut snapshot).unwrap();
+        let entry = |snapshot: &[u8], index: usize| {
+            let offset = HEADER_SIZE + SNAPSHOT_PREFIX_SIZE + index * Token::PAYLOAD_SIZE;
+            (
+                offset,
+                snapshot[offset..offset + Token::PAYLOAD_SIZE].to_vec(),
+            )
+        };
+
+        // entries corrupted in the storage of the registry, before the checksum and signature
+        let mut corrupted = snapshot.clone();
+        for index in [1, 3] {
+            corrupted[entry(&snapshot, index).0 + 20] ^= 0x01;
+        }
+        let signed = corrupted.len() - SIGNATURE_SIZE - 32;
+        let checksum: [u8; 32] = Sha256::digest(&corrupted[..signed]).into();
+        corrupted[signed..signed + 32].copy_from_slice(&checksum);
+        let signature = keypair.sign(REGISTRY_SNAPSHOT_DST, 1, &checksum);
+        corrupted[signed + 32..].copy_from_slice(&signature.0.to_bytes());
+
+        assert!(matches!(
+            import_registry(&corrupted[..], pk, &mut 0),
+            Err(RegistryError::CorruptEntry { entry: 1, .. })
+        ));
+        let (tokens, warnings) =
+            import_registry_with(&corrupted[..], pk, &mut 0, LoadMode::Lenient).unwrap();
+        let intact: Vec<_> = [0, 2, 4]
+            .map(|index| Token::from_payload(&entry(&snapshot, index).1).unwrap())
+            .into();
+        assert_eq!(tokens, intact);
+        assert_eq!(
+            warnings
+                .iter()
+                .map(|warning| warning.offset as usize)
+                .collect::<Vec<_>>(),
+            [entry(&snapshot, 1).0, entry(&snapshot, 3).0]
+        );
+        assert!(
+            warnings
+                .iter()
+                .all(|warning| warning.reason == DecodeError::InvalidPoint)
+        );
+
+        // the checksum, the signature and the header are still checked
+        let lenient =
+            |snapshot: &[u8]| import_registry_with(snapshot, pk, &mut 0, LoadMode::Lenient);
+        let mut unsealed = snapshot.clone();
+        unsealed[entry(&snapshot, 1).0 + 20] ^= 0x01;
+        assert_eq!(lenient(&unsealed), Err(RegistryError::ChecksumMismatch));
+        let mut foreign = corrupted.clone();
+        foreign[signed + 32..].copy_from_slice(
+            &registrar()
+                .sign(REGISTRY_SNAPSHOT_DST, 1, &checksum)
+                .0
+                .to_bytes(),
+        );
+        assert_eq!(lenient(&foreign), Err(RegistryError::InvalidSignature));
+        let mut header = corrupted;
+        header[0] ^= 0x01;
+        assert_eq!(
+            lenient(&header),
+            Err(RegistryError::InvalidEncoding(DecodeError::InvalidHeader))
+        );
+        assert_eq!(
+            lenient(&snapshot).map(|(tokens, warnings)| (tokens.len(), warnings)),
+            Ok((5, Vec::new()))
+        );
+    }
+
+    #[test]
+    fn stale_registry_snapshots_are_rejected() {
+        let keypair = registrar();
+        let mut t_rgstr = consecutive_tokens(2);
+        let mut old = Vec::new();
+        export_registry(&t_rgstr, &keypair, 2, 0, &mut old).unwrap();
+        t_rgstr.extend(consecutive_tokens(3));
+        let mut new = Vec::new();
+        export_registry(&t_rgstr, &keypair, 3, 0, &mut new).unwrap();
+
+        let pk = keypair.public_key();
+        let import = |snapshot: &[u8], latest_seq: &mut u64| {
+            import_registry(snapshot, pk, latest_seq).map(|tokens| tokens.len())
+        };
+        let mut latest_seq = 0;
+        assert_eq!(import(&old, &mut latest_seq), Ok(2));
+        assert_eq!(import(&new, &mut latest_seq), Ok(3));
+        assert_eq!(latest_seq, 3);
+        assert_eq!(
+            import(&old, &mut latest_seq),
+            Err(RegistryError::StaleSnapshot { seq: 2, latest: 3 })
+        );
+        assert_eq!(import(&new, &mut latest_seq), Ok(3));
+
+        // the sequence number is covered by the checksum and the signature
+        let mut renumbered = old.clone();
+        renumbered[HEADER_SIZE] = 4;
+        assert_eq!(
+            import(&renumbered, &mut latest_seq),
+            Err(RegistryError::ChecksumMismatch)
+        );
+    }
+
+    #[test]
+    fn anchors_and_rotations_are_checked_with_their_key() {
+        let first = registrar();
+        let mut chain = RegistrarKeyChain::new(&first, 0, 2);
+        let second = chain.rotate(SecretKey::random(&mut OsRng), 10).unwrap();
+
+        let anchor = second.anchor_broadcasts([7; 32], 3, 10);
+        assert_eq!(anchor.key_id, 1);
+        assert!(anchor.verify(&chain.at(10)));
+        let relabelled = BroadcastAnchor {
+            key_id: 0,
+            ..anchor
+        };
+        assert!(!relabelled.verify(&chain.at(10)));
+        let retired = first.anchor_broadcasts([7; 32], 3, 5);
+        assert!(!retired.verify(&chain.at(12)));
+
+        let rotation = second.sign_rotation([1; 32], [2; 32], 11);
+        assert_eq!(rotation.key_id, 1);
+        assert!(rotation.verify(&chain.at(11)));
+        let relabelled = KeyRotation {
+            key_id: 0,
+            ..rotation
+        };
+        assert!(!relabelled.verify(&chain.at(11)));
+        let retired = first.sign_rotation([1; 32], [2; 32], 5);
+        assert!(!retired.verify(&chain.at(12)));
+    }
+
+    #[test]
+    fn snapshots_are_checked_with_the_key_of_their_epoch() {
+        let first = registrar();
+        let mut chain = RegistrarKeyChain::new(&first, 0, 2);
+        assert!(matches!(
+            chain.rotate(SecretKey::random(&mut OsRng), 0),
+            Err(KeyChainError::EarlyActivation {
+                activation_epoch: 0,
+                valid_from: 0
+            })
+        ));
+        let second = chain.rotate(SecretKey::random(&mut OsRng), 10).unwrap();
+        assert_eq!(second.key_id(), 1);
+        assert_eq!(
+            chain
+                .keys()
+                .iter()
+                .map(|key| (key.valid_from, key.valid_to))
+                .collect::<Vec<_>>(),
+            [(0, 12), (10, Epoch::MAX)]
+        );
+        assert_eq!(chain.current().public_key, *second.public_key());
+        #[cfg(feature = "serde")]
+        assert_eq!(crate::bls381_helpers::serde_round_trip(&chain), chain);
+
+        let tokens = consecutive_tokens(3);
+        let import = |keypair: &RegistrarKeypair, epoch: Epoch, current_epoch: Epoch| {
+            let mut snapshot = Vec::new();
+            export_registry(&tokens, keypair, 1, epoch, &mut snapshot).unwrap();
+            import_registry(&snapshot[..], &chain.at(current_epoch), &mut 0)
+                .map(|tokens| tokens.len())
+        };
+        // the old key verifies its snapshots until the end of the overlap, the new one from its
+        // activation on
+        assert_eq!(import(&first, 3, 3), Ok(3));
+        assert_eq!(import(&first, 11, 11), Ok(3));
+        assert_eq!(
+            import(&first, 12, 12),
+            Err(RegistryError::InvalidKey(KeyChainError::RetiredKey {
+                key_id: 0,
+                valid_to: 12
+            }))
+        );
+        assert_eq!(import(&second, 10, 10), Ok(3));
+        assert_eq!(
+            import(&second, 9, 10),
+            Err(RegistryError::InvalidKey(KeyChainError::OutsideValidity {
+                key_id: 1,
+                epoch: 9
+            }))
+        );
+        let unknown = RegistrarKeypair::with_key_id(SecretKey::random(&mut OsRng), 7);
+        assert_eq!(
+            import(&unknown, 12, 12),
+            Err(RegistryError::InvalidKey(KeyChainError::UnknownKey {
+                key_id: 7
+            }))
+        );
+
+        // the retired key cannot backdate snapshots, nor the new key date them ahead
+        assert_eq!(
+            import(&first, 3, 12),
+            Err(RegistryError::InvalidKey(KeyChainError::RetiredKey {
+                key_id: 0,
+                valid_to: 12
+            }))
+        );
+        assert_eq!(
+            import(&second, 11, 10),
+            Err(RegistryError::InvalidKey(KeyChainError::FutureEpoch {
+                epoch: 11,
+                current_epoch: 10
+            }))
+        );
+
+        // the key id is covered by the checksum and the signature
+        let mut snapshot = Vec::new();
+        export_registry(&tokens, &first, 1, 3, &mut snapshot).unwrap();
+        let mut relabelled = snapshot.clone();
+        relabelled[HEADER_SIZE + 16] = 1;
+        assert_eq!(
+            import_registry(&relabelled[..], &chain.at(3), &mut 0),
+            Err(RegistryError::ChecksumMismatch)
+        );
+
+        // version-2 snapshots are only imported as legacy snapshots, with a single key, and
+        // the unsigned snapshots of version 1 not at all
+        let entries = |version: u16, seq: Option<u64>| {
+            let mut bytes =
+                envelope::versioned_header(ArtifactType::RegistrySnapshot, version).to_vec();
+            bytes.extend(seq.map(u64::to_le_bytes).into_iter().flatten());
+            bytes.extend_from_slice(&(tokens.len() as u64).to_le_bytes());
+            for token in &tokens {
+                bytes.extend_from_slice(&token.payload());
+            }
+            let checksum: [u8; 32] = Sha256::digest(&bytes).into();
+            bytes.extend_from_slice(&checksum);
+            (bytes, checksum)
+        };
+        let (mut v2, checksum) = entries(2, Some(1));
+        v2.extend_from_slice(&first.sign(REGISTRY_SNAPSHOT_DST, 1, &checksum).0.to_bytes());
+        let (v1, _) = entries(1, None);
+        for rejected in [
+            import_registry(&v2[..], &chain.at(3), &mut 0),
+            import_registry(&v2[..], first.public_key(), &mut 0),
+            import_legacy_registry(&snapshot[..], first.public_key(), &mut 0),
+            import_legacy_registry(&v1[..], first.public_key(), &mut 0),
+        ] {
+            assert!(matches!(
+                rejected,
+                Err(RegistryError::InvalidEncoding(
+                    DecodeError::UnsupportedVersion(_)
+                ))
+            ));
+        }
+        assert_eq!(
+            import_legacy_registry(&v2[..], first.public_key(), &mut 0).map(|tokens| tokens.len()),
+            Ok(3)
+        );
+        assert_eq!(
+            import_legacy_registry(&v2[..], second.public_key(), &mut 0),
+            Err(RegistryError::InvalidSignature)
+        );
+        // nor do legacy snapshots set the sequence number back
+        let mut latest_seq = 0;
+        import_registry(&snapshot[..], &chain.at(3), &mut latest_seq).unwrap();
+        let mut later = Vec::new();
+        export_registry(&tokens, &first, 2, 3, &mut later).unwrap();
+        import_registry(&later[..], &chain.at(3), &mut latest_seq).unwrap();
+        assert_eq!(
+            import_legacy_registry(&v2[..], first.public_key(), &mut latest_seq),
+            Err(RegistryError::StaleSnapshot { seq: 1, latest: 2 })
+        );
+        assert_eq!(latest_seq, 2);
+    }
+
+    #[test]
+    fn merkle_registry_snapshot_keeps_the_tree() {
+        let keypair = registrar();
+        let mut registry = MerkleRegistry::new(registrar());
+        for token in consecutive_tokens(5) {
+            registry.insert(token).unwrap();
+        }
+        let mut snapshot = Vec::new();
+        export_registry(&registry, &keypair, 5, 0, &mut snapshot).unwrap();
+
+        let mut restored = MerkleRegistry::new(registrar());
+        for token in import_registry(&snapshot[..], keypair.public_key(), &mut 0).unwrap() {
+            restored.insert(token).unwrap();
+        }
+        assert_eq!(restored.root(), registry.root());
+    }
+
+    #[test]
+    fn shared_registrar_purge_and_export() {
+        let shared = SharedRegistrar::with_shards(4);
+        let tokens: Vec<_> = [5, 20, 10, 20].map(random_token_expiring).into();
+        for token in &tokens {
+            assert!(shared.insert(token.clone()));
+        }
+        assert!(!shared.insert(tokens[0].clone()));
+        assert_eq!(shared.len(), 4);
+
+        let keypair = registrar();
+        let mut snapshot = Vec::new();
+        export_registry(&shared, &keypair, 1, 0, &mut snapshot).unwrap();
+        let imported = import_registry(&snapshot[..], keypair.public_key(), &mut 0).unwrap();
+        assert_eq!(
+            imported.into_iter().collect::<HashSet<_>>(),
+            tokens.iter().cloned().collect()
+        );
+
+        assert_eq!(shared.purge_expired(10), 2);
+        assert!(shared.contains(&tokens[1]) && shared.contains(&tokens[3]));
+        assert!(!shared.contains(&tokens[0]) && !shared.contains(&tokens[2]));
+    }
+
+    #[test]
+    fn shared_registrar_under_concurrent_use() {
+        const THREADS: usize = 16;
+        const ROUNDS: usize = 2;
+        const NOW: Epoch = 10;
+
+        let SetupOutput {
+            pp,
+            issuers,
+            domain,
+            ..
+        } = spirit_setup_osrng(4, 8, 8, b"test").expect("setup failed");
+        let shared = SharedRegistrar::with_shards(4);
+        thread::scope(|scope| {
+            for _ in 0..THREADS {
+                scope.spawn(|| {
+                    let mut challenges = ChallengeSet::new(0);
+                    let mut nullifiers = HashSet::new();
+                    let mut cf = HashSet::new();
+                    for _ in 0..ROUNDS {
+                        let user = UserSecret::random(OsRng);
+                        let ppu =
+                            spirit_register_osrng(&domain, &user, &issuers, &pp, 100, &mut &shared)
+                                .expect("registration failed");
+                        let challenge = challenges.issue(NOW, &mut OsRng);
+                        let cp = HashSet::from([(0, Interval(2))]);
+                        let report = spirit_diagnosis_osrng(
+                            &domain,
+                            &ppu,
+                            user.keys(),
+                            &cp,
+                            NOW,
+                            &challenge,
+                            &[],
+                        )
+                        .expect("diagnosis failed");
+                        let verified = spirit_verify_batch(
+                            &domain,
+                            &[report],
+                            &shared,
+                            &RevocationList::new(),
+                            NOW,
+                            &mut nullifiers,
+                            &challenges,
+                            &mut cf,
+                        );
+                        assert_eq!(
+                            verified,
+                            [Ok(Accepted {
+                                verdicts: vec![true]
+                            })]
+                        );
+                    }
+                });
+            }
+        });
+        assert_eq!(shared.len(), THREADS * ROUNDS);
+    }
+
+    proptest! {
+        #[test]
+        fn revocation_list_round_trip(revoked in btree_set(any::<TokenId>(), 0..8)) {
+            let list = RevocationList { revoked };
+            prop_assert_eq!(RevocationList::from_bytes(&list.to_bytes()), Ok(list));
+        }
+    }
+}
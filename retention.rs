@@ -0,0 +1,971 @@
+//! Canonical JSON of trace reports, for health authorities that retain verified reports in a
+//! form auditors can read, with the `std` feature. [TraceReport::to_canonical_json] writes the
+//! one canonical document of a report, in the style of RFC 8785: no whitespace, the keys of
+//! every object in ascending order, integers in decimal without sign, exponent or leading zeros,
+//! and every group element, scalar and byte string in lowercase hex. Points and scalars are in
+//! their compressed encodings, and so are the proofs, each as one string.
+//!
+//! The registrar signs the bytes of the document with [sign_canonical], and the signature is
+//! stored next to it. [verify_canonical] checks a stored document before it is used: a document
+//! that [TraceReport::from_canonical_json] parses, e.g. with whitespace or reordered keys, is
+//! still rejected unless it is the canonical one, so that a signature vouches for exactly one
+//! sequence of bytes. With a [RegistrarKeyChain](crate::registry::RegistrarKeyChain) at the
+//! verifier's epoch, a document is checked with the key of its signature as of the report
+//! epoch. Documents outlive the registrar's keys, but those of a retired key no longer verify,
+//! as the key could sign backdated ones, so the registrar signs its documents again with the
+//! new key during the overlap of a rotation.
+//!
+//! The maps of a report are arrays of objects in the order of their keys, e.g. `cm_prv` is
+//! `[{"commitment":"..","epoch":3}]`. Epochs above `2^53` are written exactly, but parsers that
+//! read JSON numbers as doubles round them. The `rotation` of a report is left out rather than
+//! `null` if it has none, so the documents of reports from before rotations stay canonical.
+
+use std::{collections::BTreeMap, fmt::Write};
+
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
+use thiserror::Error;
+
+use crate::{
+    bls381_helpers::append_compressed,
+    constants::REPORT_RETENTION_DST,
+    registry::{KeyChainError, KeyId, RegistrarKeypair, RegistrarKeys, verify_registrar_signature},
+    spirit::{Disclosure, Interval, ReportChallenge, ShowTag, Token, TraceReport},
+    tsw::Signature,
+};
+
+/// Depth of nesting beyond which a document is rejected. Canonical documents nest three deep.
+const MAX_DEPTH: usize = 8;
+
+#[derive(Error, Debug, PartialEq, Eq, Clone)]
+pub enum CanonicalJsonError {
+    #[error("Malformed JSON at byte {0}.")]
+    Malformed(usize),
+    #[error("Field {0} appears twice.")]
+    DuplicateField(String),
+    #[error("Missing field {0}.")]
+    MissingField(String),
+    #[error("Unknown field {0}.")]
+    UnknownField(String),
+    #[error("Invalid value of field {0}.")]
+    InvalidValue(String),
+    #[error("The document is not the canonical JSON of its report.")]
+    NotCanonical,
+    #[error("The registrar's signature on the document does not verify.")]
+    InvalidSignature,
+    #[error("The document is signed with an invalid key: {0}")]
+    InvalidKey(#[source] KeyChainError),
+}
+
+impl TraceReport {
+    /// The canonical JSON document of the report, see the [module](self) documentation.
+    pub fn to_canonical_json(&self) -> String {
+        let challenge = object([
+            ("issued_at", Value::Number(self.challenge.issued_at)),
+            ("nonce", hex(&self.challenge.nonce)),
+        ]);
+        let cm_prv = self
+            .cm_prv
+            .iter()
+            .map(|(epoch, cm_prv)| {
+                object([
+                    ("commitment", encoded(cm_prv)),
+                    ("epoch", Value::Number(*epoch)),
+                ])
+            })
+            .collect();
+        let prf_pk = self
+            .prf_pk
+            .iter()
+            .map(|(epoch, prf_pk)| {
+                object([
+                    ("epoch", Value::Number(*epoch)),
+                    ("prf_pk", encoded(prf_pk)),
+                ])
+            })
+            .collect();
+        let device_keys = self
+            .device_keys
+            .iter()
+            .map(|((device, epoch), (cm_prv, prf_pk))| {
+                object([
+                    ("cm_prv", encoded(cm_prv)),
+                    ("device", Value::Number((*device).into())),
+                    ("epoch", Value::Number(*epoch)),
+                    ("prf_pk", encoded(prf_pk)),
+                ])
+            })
+            .collect();
+        let show = optional(&self.show, |show| {
+            object([
+                ("index", Value::Number(show.index as u64)),
+                ("proof", encoded(&show.proof)),
+                ("tag", encoded(&show.tag)),
+            ])
+        });
+        let token = object([
+            ("commitment", encoded(&self.token.0)),
+            ("expiry", Value::Number(self.token.2)),
+            ("signature", encoded(&self.token.1)),
+        ]);
+        let mut report = object([
+            ("attestation", optional(&self.attestation, encoded)),
+            ("attributes", optional(&self.attributes, encoded)),
+            ("authorization", optional(&self.authorization, encoded)),
+            ("challenge", challenge),
+            ("cm_prv", Value::Array(cm_prv)),
+            ("deployment", hex(&self.deployment)),
+            ("device_keys", Value::Array(device_keys)),
+            (
+                "disclosures",
+                Value::Array(self.disclosures.iter().map(disclosure_json).collect()),
+            ),
+            ("key_binding", optional(&self.key_binding, encoded)),
+            ("nullifier", encoded(&self.nullifier)),
+            ("nullifier_proof", encoded(&self.nullifier_proof)),
+            ("prf_pk", Value::Array(prf_pk)),
+            ("registrar", Value::Number(self.registrar.into())),
+            ("report_epoch", Value::Number(self.report_epoch)),
+            ("show", show),
+            ("token", token),
+        ]);
+        if let (Value::Object(fields), Some(rotation)) = (&mut report, &self.rotation) {
+            fields.insert("rotation".to_string(), encoded(rotation));
+        }
+        let mut json = String::new();
+        report.write(&mut json);
+        json
+    }
+
+    /// Parses a JSON document of a report, in which whitespace, the order of the keys and the
+    /// case of the hex digits are free. Stored documents are checked with [verify_canonical]
+    /// instead, which only accepts the canonical one.
+    pub fn from_canonical_json(json: &str) -> Result<Self, CanonicalJsonError> {
+        let mut report = Fields::of(Parser::parse(json)?, "report")?;
+
+        let mut challenge = Fields::of(report.take("challenge")?, "challenge")?;
+        let issued_at = challenge.number("issued_at")?;
+        let nonce: [u8; 32] = challenge
+            .bytes("nonce")?
+            .try_into()
+            .map_err(|_| CanonicalJsonError::InvalidValue("challenge.nonce".to_string()))?;
+        challenge.finish()?;
+
+        let cm_prv = map(report.take("cm_prv")?, "cm_prv", |entry| {
+            Ok((entry.number("epoch")?, entry.decoded("commitment")?))
+        })?;
+        let prf_pk = map(report.take("prf_pk")?, "prf_pk", |entry| {
+            Ok((entry.number("epoch")?, entry.decoded("prf_pk")?))
+        })?;
+        let device_keys = map(report.take("device_keys")?, "device_keys", |entry| {
+            let key = (entry.number("device")?, entry.number("epoch")?);
+            Ok((key, (entry.decoded("cm_prv")?, entry.decoded("prf_pk")?)))
+        })?;
+        let disclosures = array(report.take("disclosures")?, "report.disclosures")?
+            .into_iter()
+            .map(disclosure_from_json)
+            .collect::<Result<_, _>>()?;
+        let show = match report.take("show")? {
+            Value::Null => None,
+            value => {
+                let mut show = Fields::of(value, "show")?;
+                let tag = ShowTag {
+                    index: show.number("index")?,
+                    tag: show.decoded("tag")?,
+                    proof: show.decoded("proof")?,
+                };
+                show.finish()?;
+                Some(tag)
+            }
+        };
+        let mut token = Fields::of(report.take("token")?, "token")?;
+        let decoded_token = Token(
+            token.decoded("commitment")?,
+            token.decoded("signature")?,
+            token.number("expiry")?,
+        );
+        token.finish()?;
+
+        let decoded_report = TraceReport {
+            deployment: report.bytes("deployment")?,
+            registrar: report.number("registrar")?,
+            token: decoded_token,
+            cm_prv,
+            prf_pk,
+            device_keys,
+            disclosures,
+            report_epoch: report.number("report_epoch")?,
+            challenge: ReportChallenge { nonce, issued_at },
+            nullifier: report.decoded("nullifier")?,
+            nullifier_proof: report.decoded("nullifier_proof")?,
+            show,
+            attributes: report.optional("attributes", decoded)?,
+            key_binding: report.optional("key_binding", decoded)?,
+            authorization: report.optional("authorization", decoded)?,
+            attestation: report.optional("attestation", decoded)?,
+            rotation: report.omittable("rotation", decoded)?,
+        };
+        report.finish()?;
+        Ok(decoded_report)
+    }
+}
+
+/// Detached signature of [sign_canonical], with the id of the registrar's key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CanonicalSignature {
+    pub key_id: KeyId,
+    pub signature: Signature,
+}
+
+/// The registrar's detached signature on the canonical JSON of `report`, with the report epoch
+/// as its sequence number. It is stored next to the document and checked by
+/// [verify_canonical].
+pub fn sign_canonical(registrar: &RegistrarKeypair, report: &TraceReport) -> CanonicalSignature {
+    let json = report.to_canonical_json();
+    CanonicalSignature {
+        key_id: registrar.key_id(),
+        signature: registrar.sign(REPORT_RETENTION_DST, report.report_epoch, json.as_bytes()),
+    }
+}
+
+/// Parses a stored document and checks that it is the canonical JSON of its report and that
+/// `signature` is the registrar's [sign_canonical] signature on it, under a key valid in the
+/// report epoch. A document with any other bytes than the signed ones is rejected, even if it
+/// parses to the same report.
+pub fn verify_canonical(
+    json: &str,
+    registrar: &impl RegistrarKeys,
+    signature: &CanonicalSignature,
+) -> Result<TraceReport, CanonicalJsonError> {
+    let report = TraceReport::from_canonical_json(json)?;
+    if report.to_canonical_json() != json {
+        return Err(CanonicalJsonError::NotCanonical);
+    }
+    let registrar = registrar
+        .verifying_key(signature.key_id, report.report_epoch)
+        .map_err(CanonicalJsonError::InvalidKey)?;
+    if !verify_registrar_signature(
+        registrar,
+        REPORT_RETENTION_DST,
+        report.report_epoch,
+        json.as_bytes(),
+        &signature.signature,
+    ) {
+        return Err(CanonicalJsonError::InvalidSignature);
+    }
+    Ok(report)
+}
+
+fn disclosure_json(disclosure: &Disclosure) -> Value {
+    object([
+        (
+            "device",
+            optional(&disclosure.device, |device| Value::Number((*device).into())),
+        ),
+        ("el_id", encoded(&disclosure.el_id)),
+        ("epoch", Value::Number(disclosure.epoch)),
+        ("index", Value::Number(disclosure.index.0)),
+        ("prf_proof", encoded(&disclosure.prf_proof)),
+        ("proof", encoded(&disclosure.proof)),
+        ("venue", optional(&disclosure.venue, |venue| hex(venue))),
+    ])
+}
+
+fn disclosure_from_json(value: Value) -> Result<Disclosure, CanonicalJsonError> {
+    let mut fields = Fields::of(value, "disclosures")?;
+    let disclosure = Disclosure {
+        device: fields.optional("device", number)?,
+        el_id: fields.decoded("el_id")?,
+        epoch: fields.number("epoch")?,
+        index: Interval(fields.number("index")?),
+        prf_proof: fields.decoded("prf_proof")?,
+        proof: fields.decoded("proof")?,
+        venue: fields.optional("venue", bytes)?,
+    };
+    fields.finish()?;
+    Ok(disclosure)
+}
+
+/// The values of the documents: integers are unsigned, and strings hold no escapes, as those of
+/// a report are hex.
+enum Value {
+    Null,
+    Number(u64),
+    String(String),
+    Array(Vec<Value>),
+    Object(BTreeMap<String, Value>),
+}
+
+impl Value {
+    fn write(&self, json: &mut String) {
+        match self {
+            Value::Null => json.push_str("null"),
+            // infallible for a String
+            Value::Number(number) => {
+                let _ = write!(json, "{number}");
+            }
+            Value::String(string) => {
+                json.push('"');
+                json.push_str(string);
+                json.push('"');
+            }
+            Value::Array(values) => {
+                json.push('[');
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        json.push(',');
+                    }
+                    value.write(json);
+                }
+                json.push(']');
+            }
+            // the keys are ASCII, so their byte order is the order of RFC 8785
+            Value::Object(fields) => {
+                json.push('{');
+                for (i, (key, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        json.push(',');
+                    }
+                    json.push('"');
+                    json.push_str(key);
+                    json.push_str("\":");
+                    value.write(json);
+                }
+                json.push('}');
+            }
+        }
+    }
+}
+
+fn object<const N: usize>(fields: [(&str, Value); N]) -> Value {
+    Value::Object(
+        fields
+            .into_iter()
+            .map(|(key, value)| (key.to_string(), value))
+            .collect(),
+    )
+}
+
+fn hex(bytes: &[u8]) -> Value {
+    let mut string = String::with_capacity(2 * bytes.len());
+    for byte in bytes {
+        let _ = write!(string, "{byte:02x}");
+    }
+    Value::String(string)
+}
+
+fn encoded(value: &impl CanonicalSerialize) -> Value {
+    let mut bytes = Vec::with_capacity(value.compressed_size());
+    append_compressed(&mut bytes, value);
+    hex(&bytes)
+}
+
+fn optional<T>(value: &Option<T>, json: impl FnOnce(&T) -> Value) -> Value {
+    value.as_ref().map_or(Value::Null, json)
+}
+
+/// The fields of an object of a document, which are taken one by one. Errors name a field by
+/// the object and its key, e.g. `token.expiry`.
+struct Fields {
+    name: &'static str,
+    fields: BTreeMap<String, Value>,
+}
+
+impl Fields {
+    fn of(value: Value, name: &'static str) -> Result<Self, CanonicalJsonError> {
+        match value {
+            Value::Object(fields) => Ok(Self { name, fields }),
+            _ => Err(CanonicalJsonError::InvalidValue(name.to_string())),
+        }
+    }
+
+    fn path(&self, key: &str) -> String {
+        format!("{}.{key}", self.name)
+    }
+
+    fn take(&mut self, key: &str) -> Result<Value, CanonicalJsonError> {
+        self.fields
+            .remove(key)
+            .ok_or_else(|| CanonicalJsonError::MissingField(self.path(key)))
+    }
+
+    fn number<T: TryFrom<u64>>(&mut self, key: &str) -> Result<T, CanonicalJsonError> {
+        number(self.take(key)?, &self.path(key))
+    }
+
+    fn bytes(&mut self, key: &str) -> Result<Vec<u8>, CanonicalJsonError> {
+        bytes(self.take(key)?, &self.path(key))
+    }
+
+    fn decoded<T: CanonicalDeserialize>(&mut self, key: &str) -> Result<T, CanonicalJsonError> {
+        decoded(self.take(key)?, &self.path(key))
+    }
+
+    /// Parses the field `key` with `parse` unless it is `null`.
+    fn optional<T>(
+        &mut self,
+        key: &str,
+        parse: impl FnOnce(Value, &str) -> Result<T, CanonicalJsonError>,
+    ) -> Result<Option<T>, CanonicalJsonError> {
+        match self.take(key)? {
+            Value::Null => Ok(None),
+            value => parse(value, &self.path(key)).map(Some),
+        }
+    }
+
+    /// Fails if a field was not taken.
+    // like `optional`, for a key that is left out if the value is missing
+    fn omittable<T>(
+        &mut self,
+        key: &str,
+        parse: impl FnOnce(Value, &str) -> Result<T, CanonicalJsonError>,
+    ) -> Result<Option<T>, CanonicalJsonError> {
+        match self.fields.remove(key) {
+            None => Ok(None),
+            Some(value) => parse(value, &self.path(key)).map(Some),
+        }
+    }
+
+    fn finish(self) -> Result<(), CanonicalJsonError> {
+        match self.fields.keys().next() {
+            Some(key) => Err(CanonicalJsonError::UnknownField(self.path(key))),
+            None => Ok(()),
+        }
+    }
+}
+
+fn number<T: TryFrom<u64>>(value: Value, field: &str) -> Result<T, CanonicalJsonError> {
+    match value {
+        Value::Number(number) => T::try_from(number).ok(),
+        _ => None,
+    }
+    .ok_or_else(|| CanonicalJsonError::InvalidValue(field.to_string()))
+}
+
+fn bytes(value: Value, field: &str) -> Result<Vec<u8>, CanonicalJsonError> {
+    let invalid = || CanonicalJsonError::InvalidValue(field.to_string());
+    let Value::String(string) = value else {
+        return Err(invalid());
+    };
+    if string.len() % 2 != 0 || !string.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+        return Err(invalid());
+    }
+    (0..string.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&string[i..i + 2], 16).map_err(|_| invalid()))
+        .collect()
+}
+
+/// Decodes the compressed encoding in the hex string `value`, which has to be used up.
+fn decoded<T: CanonicalDeserialize>(value: Value, field: &str) -> Result<T, CanonicalJsonError> {
+    let bytes = bytes(value, field)?;
+    let mut reader = &bytes[..];
+    match T::deserialize_compressed(&mut reader) {
+        Ok(decoded) if reader.is_empty() => Ok(decoded),
+        _ => Err(CanonicalJsonError::InvalidValue(field.to_string())),
+    }
+}
+
+fn array(value: Value, field: &str) -> Result<Vec<Value>, CanonicalJsonError> {
+    match value {
+        Value::Array(values) => Ok(values),
+        _ => Err(CanonicalJsonError::InvalidValue(field.to_string())),
+    }
+}
+
+/// The entries of the map `name` in an array, each parsed from the fields of its object, which
+/// fails if a key appears twice.
+fn map<K: Ord, V>(
+    value: Value,
+    name: &'static str,
+    mut entry: impl FnMut(&mut Fields) -> Result<(K, V), CanonicalJsonError>,
+) -> Result<BTreeMap<K, V>, CanonicalJsonError> {
+    let values = array(value, &format!("report.{name}"))?;
+    let len = values.len();
+    let map = values
+        .into_iter()
+        .map(|value| {
+            let mut fields = Fields::of(value, name)?;
+            let (key, value) = entry(&mut fields)?;
+            fields.finish()?;
+            Ok((key, value))
+        })
+        .collect::<Result<BTreeMap<_, _>, CanonicalJsonError>>()?;
+    if map.len() != len {
+        return Err(CanonicalJsonError::InvalidValue(format!("report.{name}")));
+    }
+    Ok(map)
+}
+
+/// Parser of the JSON of [Value]s: `null`, unsigned integers, strings without escapes, arrays
+/// and objects, with any whitespace between them.
+struct Parser<'a> {
+    json: &'a [u8],
+    position: usize,
+}
+
+impl Parser<'_> {
+    fn parse(json: &str) -> Result<Value, CanonicalJsonError> {
+        let mut parser = Parser {
+            json: json.as_bytes(),
+            position: 0,
+        };
+        let value = parser.value(0)?;
+        parser.skip_whitespace();
+        if parser.position != parser.json.len() {
+            return Err(parser.malformed());
+        }
+        Ok(value)
+    }
+
+    fn malformed(&self) -> CanonicalJsonError {
+        CanonicalJsonError::Malformed(self.position)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(b' ' | b'\t' | b'\n' | b'\r') = self.json.get(self.position) {
+            self.position += 1;
+        }
+    }
+
+    /// Skips whitespace and the byte `expected`, and returns whether it was there.
+    fn consume(&mut self, expected: u8) -> bool {
+        self.skip_whitespace();
+        let found = self.json.get(self.position) == Some(&expected);
+        if found {
+            self.position += 1;
+        }
+        found
+    }
+
+    fn value(&mut self, depth: usize) -> Result<Value, CanonicalJsonError> {
+        self.skip_whitespace();
+        if depth > MAX_DEPTH {
+            return Err(self.malformed());
+        }
+        match self.json.get(self.position) {
+            Some(b'n') if self.json[self.position..].starts_with(b"null") => {
+                self.position += 4;
+                Ok(Value::Null)
+            }
+            Some(b'0'..=b'9') => self.number(),
+            Some(b'"') => self.string().map(Value::String),
+            Some(b'[') => {
+                self.position += 1;
+                let mut values = Vec::new();
+                if !self.consume(b']') {
+                    loop {
+                        values.push(self.value(depth + 1)?);
+                        if self.consume(b']') {
+                            break;
+                        }
+                        if !self.consume(b',') {
+                            return Err(self.malformed());
+                        }
+                    }
+                }
+                Ok(Value::Array(values))
+            }
+            Some(b'{') => {
+                self.position += 1;
+                let mut fields = BTreeMap::new();
+                if !self.consume(b'}') {
+                    loop {
+                        self.skip_whitespace();
+                        let key = self.string()?;
+                        if !self.consume(b':') {
+                            return Err(self.malformed());
+                        }
+                        let value = self.value(depth + 1)?;
+                        if fields.contains_key(&key) {
+                            return Err(CanonicalJsonError::DuplicateField(key));
+                        }
+                        fields.insert(key, value);
+                        if self.consume(b'}') {
+                            break;
+                        }
+                        if !self.consume(b',') {
+                            return Err(self.malformed());
+                        }
+                    }
+                }
+                Ok(Value::Object(fields))
+            }
+            _ => Err(self.malformed()),
+        }
+    }
+
+    fn number(&mut self) -> Result<Value, CanonicalJsonError> {
+        let start = self.position;
+        let digits = self.json[start..]
+            .iter()
+            .take_while(|byte| byte.is_ascii_digit())
+            .count();
+        self.position += digits;
+        // leading zeros are not JSON, and fractions and exponents are not integers
+        if (digits > 1 && self.json[start] == b'0')
+            || matches!(self.json.get(self.position), Some(b'.' | b'e' | b'E'))
+        {
+            return Err(CanonicalJsonError::Malformed(start));
+        }
+        std::str::from_utf8(&self.json[start..self.position])
+            .map_err(|_| CanonicalJsonError::Malformed(start))?
+            .parse()
+            .map(Value::Number)
+            .map_err(|_| CanonicalJsonError::Malformed(start))
+    }
+
+    fn string(&mut self) -> Result<String, CanonicalJsonError> {
+        if self.json.get(self.position) != Some(&b'"') {
+            return Err(self.malformed());
+        }
+        let start = self.position + 1;
+        let len = self.json[start..]
+            .iter()
+            .position(|byte| *byte == b'"')
+            .ok_or_else(|| self.malformed())?;
+        let string = &self.json[start..start + len];
+        if let Some(offset) = string
+            .iter()
+            .position(|byte| *byte == b'\\' || *byte < 0x20)
+        {
+            return Err(CanonicalJsonError::Malformed(start + offset));
+        }
+        self.position = start + len + 1;
+        std::str::from_utf8(string)
+            .map(str::to_string)
+            .map_err(|_| CanonicalJsonError::Malformed(start))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashSet;
+
+    use rand::rngs::OsRng;
+
+    use super::*;
+    use crate::{
+        Scalar,
+        bls381_helpers::test::generator_multiple,
+        pedersen::{Commitment, Proof, Proof2PK},
+        registry::RegistrarKeyChain,
+        spirit::{NullifierProof, PrfProof, SpiritSystem, UserSecret, spirit_diagnosis_osrng},
+        tsw::SecretKey,
+    };
+
+    const GOLDEN: &str = include_str!("tests/golden/trace_report.json");
+    const GOLDEN_EMPTY: &str = include_str!("tests/golden/trace_report_empty.json");
+
+    fn scalar(k: u64) -> Scalar {
+        Scalar::from(k)
+    }
+
+    fn proof_2pk() -> Proof2PK {
+        Proof2PK {
+            pi_1: Proof {
+                t: generator_multiple(5),
+                s_1: scalar(1),
+                s_2: scalar(2),
+            },
+            pi_2: Proof {
+                t: generator_multiple(7),
+                s_1: scalar(3),
+                s_2: scalar(4),
+            },
+            t3: generator_multiple(5),
+        }
+    }
+
+    fn nullifier_proof(c: u64) -> NullifierProof {
+        NullifierProof {
+            c: scalar(c),
+            s_1: scalar(c + 1),
+            s_2: scalar(c + 2),
+        }
+    }
+
+    /// Report of the golden files, of points that are multiples of the generator and scalars
+    /// that are small integers, whose encodings are easy to check by hand. Its proofs do not
+    /// verify.
+    fn golden_report(empty: bool) -> TraceReport {
+        let g5 = generator_multiple(5);
+        let g7 = generator_multiple(7);
+        let disclosure = |index, device, venue: Option<&[u8]>, el_id| Disclosure {
+            epoch: 3,
+            index,
+            device,
+            venue: venue.map(<[u8]>::to_vec),
+            el_id,
+            proof: proof_2pk(),
+            prf_proof: PrfProof {
+                c: scalar(5),
+                s: scalar(6),
+            },
+        };
+        let mut report = TraceReport {
+            deployment: b"golden".to_vec(),
+            registrar: 2,
+            token: Token(Commitment(g5), Signature(g7), 90),
+            cm_prv: BTreeMap::from([(3, Commitment(g7))]),
+            prf_pk: BTreeMap::from([(3, g5)]),
+            device_keys: BTreeMap::from([((1, 3), (Commitment(g5), g7))]),
+            disclosures: vec![
+                disclosure(Interval(0), None, None, g5),
+                disclosure(Interval(1), Some(1), Some(&b"venue"[..]), g7),
+            ],
+            report_epoch: 10,
+            challenge: ReportChallenge {
+                nonce: [7; 32],
+                issued_at: 10,
+            },
+            nullifier: g7,
+            nullifier_proof: nullifier_proof(1),
+            show: Some(ShowTag {
+                index: 0,
+                tag: g5,
+                proof: nullifier_proof(4),
+            }),
+            attributes: None,
+            key_binding: None,
+            authorization: None,
+            attestation: None,
+            rotation: None,
+        };
+        if empty {
+            report.cm_prv.clear();
+            report.prf_pk.clear();
+            report.device_keys.clear();
+            report.disclosures.clear();
+            report.show = None;
+        }
+        report
+    }
+
+    /// The report of a diagnosis that a system accepts.
+    fn verified_report() -> TraceReport {
+        let (mut system, issuers) = SpiritSystem::setup(3, 4, 4).expect("setup failed");
+        let domain = system.domain().clone();
+        let user = UserSecret::random(OsRng);
+        let ppu = system
+            .register(&user, &issuers, 100)
+            .expect("registration failed");
+        let challenge = system.issue_challenge(1);
+        let cp = HashSet::from([(0, Interval(0))]);
+        let report = spirit_diagnosis_osrng(&domain, &ppu, user.keys(), &cp, 1, &challenge, &[])
+            .expect("diagnosis failed");
+        assert!(
+            system
+                .verify_report(&report, 1, &mut HashSet::new())
+                .is_ok()
+        );
+        report
+    }
+
+    /// Range of the value of the string field `key` that appears first in `json`.
+    fn string_value(json: &str, key: &str) -> std::ops::Range<usize> {
+        let prefix = format!("\"{key}\":\"");
+        let start = json.find(&prefix).unwrap() + prefix.len();
+        start..start + json[start..].find('"').unwrap()
+    }
+
+    #[test]
+    fn canonical_json_matches_the_golden_files() {
+        for (empty, golden) in [(false, GOLDEN), (true, GOLDEN_EMPTY)] {
+            let golden = golden.strip_suffix('\n').unwrap();
+            assert_eq!(golden_report(empty).to_canonical_json(), golden);
+            let parsed = TraceReport::from_canonical_json(golden).expect("parsing failed");
+            assert_eq!(parsed.to_canonical_json(), golden);
+        }
+
+        // a rotation is written only if there is one
+        let registrar = RegistrarKeypair::random(&mut OsRng);
+        let report = TraceReport {
+            rotation: Some(registrar.sign_rotation([1; 32], [2; 32], 3)),
+            ..golden_report(true)
+        };
+        let json = report.to_canonical_json();
+        assert!(json.contains(",\"rotation\":\""));
+        let parsed = TraceReport::from_canonical_json(&json).expect("parsing failed");
+        assert_eq!(parsed.rotation, report.rotation);
+        assert_eq!(parsed.to_canonical_json(), json);
+    }
+
+    #[test]
+    fn canonical_json_round_trips() {
+        let report = verified_report();
+        let json = report.to_canonical_json();
+        let parsed = TraceReport::from_canonical_json(&json).expect("parsing failed");
+        let mut bytes = Vec::new();
+        let mut parsed_bytes = Vec::new();
+        report.serialize_compressed(&mut bytes).unwrap();
+        parsed.serialize_compressed(&mut parsed_bytes).unwrap();
+        assert_eq!(parsed_bytes, bytes);
+        assert_eq!(parsed.to_canonical_json(), json);
+    }
+
+    #[test]
+    fn stored_reports_are_tamper_evident() {
+        let report = verified_report();
+        let registrar = RegistrarKeypair::random(&mut OsRng);
+        let pk = registrar.public_key();
+        let json = report.to_canonical_json();
+        let signature = sign_canonical(&registrar, &report);
+        assert!(verify_canonical(&json, pk, &signature).is_ok());
+
+        // one hex digit of the nonce changed, which still parses
+        let nonce = string_value(&json, "nonce").start;
+        let mut tampered = json.clone();
+        let digit = if json.as_bytes()[nonce] == b'0' {
+            "1"
+        } else {
+            "0"
+        };
+        tampered.replace_range(nonce..nonce + 1, digit);
+        assert!(TraceReport::from_canonical_json(&tampered).is_ok());
+        assert_eq!(
+            verify_canonical(&tampered, pk, &signature),
+            Err(CanonicalJsonError::InvalidSignature)
+        );
+        let other = RegistrarKeypair::random(&mut OsRng);
+        assert_eq!(
+            verify_canonical(&json, other.public_key(), &signature),
+            Err(CanonicalJsonError::InvalidSignature)
+        );
+
+        // documents of the same report that are not canonical parse, but are rejected
+        let spaced = json.replacen(',', ", ", 1);
+        // the token is the last field, and moves to the front
+        let token = json.find(",\"token\":").unwrap();
+        let reordered = format!(
+            "{{{},{}}}",
+            &json[token + 1..json.len() - 1],
+            &json[1..token]
+        );
+        let nullifier = string_value(&json, "nullifier");
+        let mut uppercase = json.clone();
+        uppercase.replace_range(nullifier.clone(), &json[nullifier].to_uppercase());
+        for document in [spaced, reordered, uppercase] {
+            assert_ne!(document, json);
+            let parsed = TraceReport::from_canonical_json(&document).expect("parsing failed");
+            assert_eq!(parsed.to_canonical_json(), json);
+            assert_eq!(
+                verify_canonical(&document, pk, &signature),
+                Err(CanonicalJsonError::NotCanonical)
+            );
+        }
+    }
+
+    #[test]
+    fn documents_are_checked_with_the_key_of_their_epoch() {
+        let first = RegistrarKeypair::random(&mut OsRng);
+        let mut chain = RegistrarKeyChain::new(&first, 0, 2);
+        let second = chain.rotate(SecretKey::random(&mut OsRng), 10).unwrap();
+        let signed = |registrar: &RegistrarKeypair, report_epoch| {
+            let report = TraceReport {
+                report_epoch,
+                ..golden_report(true)
+            };
+            (
+                report.to_canonical_json(),
+                sign_canonical(registrar, &report),
+            )
+        };
+
+        // documents of the old key verify until it is retired
+        for (registrar, report_epoch) in [(&first, 3), (&first, 11), (&second, 11)] {
+            let (json, signature) = signed(registrar, report_epoch);
+            assert_eq!(signature.key_id, registrar.key_id());
+            assert!(verify_canonical(&json, &chain.at(11), &signature).is_ok());
+        }
+        let (json, signature) = signed(&second, 12);
+        assert!(verify_canonical(&json, &chain.at(12), &signature).is_ok());
+        assert_eq!(
+            verify_canonical(&json, &chain.at(11), &signature),
+            Err(CanonicalJsonError::InvalidKey(KeyChainError::FutureEpoch {
+                epoch: 12,
+                current_epoch: 11
+            }))
+        );
+        // after the retirement, including those dated back into its validity
+        for report_epoch in [3, 12] {
+            let (json, signature) = signed(&first, report_epoch);
+            assert_eq!(
+                verify_canonical(&json, &chain.at(12), &signature),
+                Err(CanonicalJsonError::InvalidKey(KeyChainError::RetiredKey {
+                    key_id: 0,
+                    valid_to: 12
+                }))
+            );
+        }
+        let (json, signature) = signed(&first, 12);
+        let unknown = CanonicalSignature {
+            key_id: 2,
+            ..signature
+        };
+        assert_eq!(
+            verify_canonical(&json, &chain.at(12), &unknown),
+            Err(CanonicalJsonError::InvalidKey(KeyChainError::UnknownKey {
+                key_id: 2
+            }))
+        );
+        // the signature of one key does not verify under the id of another
+        let (json, signature) = signed(&second, 11);
+        let relabelled = CanonicalSignature {
+            key_id: 0,
+            ..signature
+        };
+        assert_eq!(
+            verify_canonical(&json, &chain.at(11), &relabelled),
+            Err(CanonicalJsonError::InvalidSignature)
+        );
+    }
+
+    #[test]
+    fn malformed_documents_are_rejected() {
+        let json = golden_report(true).to_canonical_json();
+        let parse = |json: &str| TraceReport::from_canonical_json(json).err();
+        assert_eq!(parse(""), Some(CanonicalJsonError::Malformed(0)));
+        assert_eq!(
+            parse(&format!("{json} x")),
+            Some(CanonicalJsonError::Malformed(json.len() + 1))
+        );
+        assert_eq!(
+            parse(&json.replacen("\"registrar\":2", "\"registrar\":02", 1)),
+            Some(CanonicalJsonError::Malformed(
+                json.find("\"registrar\":").unwrap() + "\"registrar\":".len()
+            ))
+        );
+        assert_eq!(
+            parse(&json.replacen("\"registrar\":2", "\"registrar\":2,\"registrar\":2", 1)),
+            Some(CanonicalJsonError::DuplicateField("registrar".to_string()))
+        );
+        assert_eq!(
+            parse(&json.replacen("\"registrar\":2,", "", 1)),
+            Some(CanonicalJsonError::MissingField(
+                "report.registrar".to_string()
+            ))
+        );
+        assert_eq!(
+            parse(&json.replacen("\"registrar\":2", "\"registrar\":2,\"extra\":null", 1)),
+            Some(CanonicalJsonError::UnknownField("report.extra".to_string()))
+        );
+        assert_eq!(
+            parse(&json.replacen("\"registrar\":2", "\"registrar\":4294967296", 1)),
+            Some(CanonicalJsonError::InvalidValue(
+                "report.registrar".to_string()
+            ))
+        );
+        assert_eq!(
+            parse(&json.replacen(
+                "\"deployment\":\"676f6c64656e\"",
+                "\"deployment\":\"676\"",
+                1
+            )),
+            Some(CanonicalJsonError::InvalidValue(
+                "report.deployment".to_string()
+            ))
+        );
+        assert!(parse(&"[".repeat(MAX_DEPTH + 2)).is_some());
+    }
+}
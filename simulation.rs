@@ -0,0 +1,315 @@
+//! End-to-end simulation of a deployment with many users, with the `testkit` feature. A
+//! [Scenario] describes who meets whom on which day and who is diagnosed, and [Scenario::run]
+//! drives the whole protocol: setup, registration, broadcasts, diagnosis, verification of the
+//! trace reports and tracing by every user, with all randomness derived from the scenario's
+//! seed.
+//!
+//! Every user broadcasts one ElID per day, index 0 of the day's epoch, to everyone they meet that
+//! day, and a diagnosed user discloses the ElIDs of all days. A user thus has one match for every
+//! day on which they met a diagnosed user, however often they met that day.
+
+use std::collections::{BTreeSet, HashSet};
+
+use rand::SeedableRng;
+use rand_chacha::ChaCha20Rng;
+use thiserror::Error;
+
+use crate::{
+    exposure::{ExposurePolicy, RiskLevel},
+    registry::RevocationList,
+    spirit::{
+        ChallengeSet, EncounterTable, Epoch, Interval, SetupOutput, SpiritError, UserSecret,
+        VerifyRejection, spirit_broadcast, spirit_diagnosis, spirit_register_batch, spirit_setup,
+        spirit_trace_with_policy, spirit_verify_batch,
+    },
+};
+
+const T: usize = 3;
+const N: usize = 4;
+const NUM_ISSUERS: usize = 4;
+
+/// Meeting of the users `a` and `b` on `day`, in which both receive the other's ElID.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Contact {
+    pub a: usize,
+    pub b: usize,
+    pub day: Epoch,
+}
+
+/// Users, their contacts and diagnoses, built with [Scenario::builder].
+#[derive(Debug, Clone)]
+pub struct Scenario {
+    num_users: usize,
+    days: Epoch,
+    contacts: Vec<Contact>,
+    diagnosed: BTreeSet<usize>,
+    policy: ExposurePolicy,
+    seed: u64,
+}
+
+/// Builder of a [Scenario], see [Scenario::builder].
+#[derive(Debug, Clone)]
+pub struct ScenarioBuilder {
+    scenario: Scenario,
+}
+
+/// Outcome of the trace of a user in a [Report].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UserOutcome {
+    pub diagnosed: bool,
+    /// Number of distinct confirmed ElIDs the user received.
+    pub score: u64,
+    pub risk: RiskLevel,
+}
+
+impl UserOutcome {
+    /// Whether the user's risk is above [RiskLevel::None].
+    pub fn alarm(&self) -> bool {
+        self.risk > RiskLevel::None
+    }
+}
+
+/// Outcome of [Scenario::run].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Report {
+    /// Outcome of every user, by user index.
+    pub outcomes: Vec<UserOutcome>,
+    /// Number of ElIDs confirmed by the verifier.
+    pub confirmed: usize,
+}
+
+impl Report {
+    /// Indices of the users whose [alarm](UserOutcome::alarm) is raised, in increasing order.
+    pub fn alarmed(&self) -> Vec<usize> {
+        self.outcomes
+            .iter()
+            .enumerate()
+            .filter(|(_, outcome)| outcome.alarm())
+            .map(|(user, _)| user)
+            .collect()
+    }
+}
+
+#[derive(Error, Debug, PartialEq, Clone)]
+pub enum SimulationError {
+    #[error("User {user} of a scenario with {num_users} users.")]
+    UnknownUser { user: usize, num_users: usize },
+    #[error("Contact on day {day} of a scenario of {days} days.")]
+    UnknownDay { day: Epoch, days: Epoch },
+    #[error("User {user} met themselves.")]
+    SelfContact { user: usize },
+    #[error("Protocol failure: {0}")]
+    Protocol(#[from] SpiritError),
+    #[error("The trace report of user {user} is rejected: {rejection}")]
+    Rejected {
+        user: usize,
+        rejection: VerifyRejection,
+    },
+}
+
+impl Scenario {
+    /// Scenario of `num_users` users over 14 days, without contacts or diagnoses, and with a
+    /// policy that grades any match as [RiskLevel::High].
+    pub fn builder(num_users: usize) -> ScenarioBuilder {
+        ScenarioBuilder {
+            scenario: Self {
+                num_users,
+                days: 14,
+                contacts: Vec::new(),
+                diagnosed: BTreeSet::new(),
+                policy: ExposurePolicy::builder()
+                    .tier(1, RiskLevel::High)
+                    .build()
+                    .expect("valid policy"),
+                seed: 0,
+            },
+        }
+    }
+
+    pub fn num_users(&self) -> usize {
+        self.num_users
+    }
+
+    pub fn days(&self) -> Epoch {
+        self.days
+    }
+
+    pub fn contacts(&self) -> &[Contact] {
+        &self.contacts
+    }
+
+    pub fn diagnosed(&self) -> &BTreeSet<usize> {
+        &self.diagnosed
+    }
+
+    /// Sets up a deployment, registers all users with tokens that stay valid for the whole
+    /// scenario, and runs the days of broadcasts. The diagnosed users then submit their trace
+    /// reports in the epoch after the last day, which are batch-verified, and every user traces
+    /// the confirmed ElIDs under the scenario's policy.
+    ///
+    /// Fails if a registration or diagnosis fails or a trace report is rejected, none of which
+    /// happens in an honest run.
+    pub fn run(&self) -> Result<Report, SimulationError> {
+        let mut rng = ChaCha20Rng::seed_from_u64(self.seed);
+        let SetupOutput {
+            pp,
+            issuers,
+            pk_ha,
+            mut t_rgstr,
+            domain,
+            ..
+        } = spirit_setup(T, N, NUM_ISSUERS, b"simulation", &mut rng)?;
+        let report_epoch = self.days;
+
+        let users: Vec<_> = (0..self.num_users)
+            .map(|_| UserSecret::random(&mut rng))
+            .collect();
+        let ids: Vec<_> = users.iter().map(|user| user.id_u).collect();
+        let ppus = spirit_register_batch(
+            &domain,
+            &ids,
+            &issuers,
+            &pp,
+            report_epoch + 1,
+            &mut t_rgstr,
+            &mut rng,
+        )
+        .into_iter()
+        .collect::<Result<Vec<_>, _>>()?;
+
+        let mut tables = vec![EncounterTable::new(); self.num_users];
+        for &Contact { a, b, day } in &self.contacts {
+            for (sender, receiver) in [(a, b), (b, a)] {
+                let keys = users[sender].keys();
+                spirit_broadcast(
+                    &domain,
+                    day,
+                    Interval(0),
+                    keys,
+                    &pk_ha,
+                    &mut tables[receiver],
+                    &mut rng,
+                );
+            }
+        }
+
+        let mut challenges = ChallengeSet::new(0);
+        let cp: HashSet<_> = (0..self.days).map(|day| (day, Interval(0))).collect();
+        let reports = self
+            .diagnosed
+            .iter()
+            .map(|&user| {
+                let challenge = challenges.issue(report_epoch, &mut rng);
+                spirit_diagnosis(
+                    &domain,
+                    &ppus[user],
+                    users[user].keys(),
+                    None,
+                    &cp,
+                    report_epoch,
+                    &challenge,
+                    &[],
+                    &mut rng,
+                )
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut cf = HashSet::new();
+        let verified = spirit_verify_batch(
+            &domain,
+            &reports,
+            &t_rgstr,
+            &RevocationList::new(),
+            report_epoch,
+            &mut HashSet::new(),
+            &challenges,
+            &mut cf,
+        );
+        for (&user, verified) in self.diagnosed.iter().zip(verified) {
+            verified.map_err(|rejection| SimulationError::Rejected { user, rejection })?;
+        }
+
+        let outcomes = tables
+            .iter()
+            .enumerate()
+            .map(|(user, t_el)| {
+                let assessment = spirit_trace_with_policy(&cf, t_el, &self.policy);
+                UserOutcome {
+                    diagnosed: self.diagnosed.contains(&user),
+                    score: assessment.score,
+                    risk: assessment.risk,
+                }
+            })
+            .collect();
+        Ok(Report {
+            outcomes,
+            confirmed: cf.len(),
+        })
+    }
+}
+
+impl ScenarioBuilder {
+    /// Sets the number of days of broadcasts, the epochs `0..days`.
+    pub fn days(mut self, days: Epoch) -> Self {
+        self.scenario.days = days;
+        self
+    }
+
+    /// Adds a meeting of the users `a` and `b` on `day`.
+    pub fn contact(mut self, a: usize, b: usize, day: Epoch) -> Self {
+        self.scenario.contacts.push(Contact { a, b, day });
+        self
+    }
+
+    pub fn contacts(mut self, contacts: impl IntoIterator<Item = Contact>) -> Self {
+        self.scenario.contacts.extend(contacts);
+        self
+    }
+
+    /// Adds `users` to the diagnosed users.
+    pub fn diagnosed(mut self, users: impl IntoIterator<Item = usize>) -> Self {
+        self.scenario.diagnosed.extend(users);
+        self
+    }
+
+    pub fn policy(mut self, policy: ExposurePolicy) -> Self {
+        self.scenario.policy = policy;
+        self
+    }
+
+    /// Seed of all randomness of the run.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.scenario.seed = seed;
+        self
+    }
+
+    /// Checks that all contacts and diagnoses are of users of the scenario, and all contacts
+    /// within its days.
+    pub fn build(self) -> Result<Scenario, SimulationError> {
+        let Scenario {
+            num_users, days, ..
+        } = self.scenario;
+        let check_user = |user| {
+            if user < num_users {
+                Ok(())
+            } else {
+                Err(SimulationError::UnknownUser { user, num_users })
+            }
+        };
+        for &Contact { a, b, day } in &self.scenario.contacts {
+            check_user(a)?;
+            check_user(b)?;
+            if a == b {
+                return Err(SimulationError::SelfContact { user: a });
+            }
+            if day >= days {
+                return Err(SimulationError::UnknownDay { day, days });
+            }
+        }
+        self.scenario
+            .diagnosed
+            .iter()
+            .try_for_each(|&user| check_user(user))?;
+        Ok(self.scenario)
+    }
+}
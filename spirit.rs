@@ -1,159 +1,13484 @@
-use std::collections::{HashMap, HashSet};
-use rand::{rngs::OsRng, RngCore};
-use crate::{
-    bls381_helpers::{Scalar, G1G2, hash_with_domain_separation, multi_pairing},
-    pedersen::{Commitment, Proof2PK},
-    tsw::{PublicKey, SecretKey, Signature},
-    tACT::{PublicParameters as tACTPublicParameters, Issuer, setup as tACT_setup, register, token_request, tissue, aggregate_unblind, prove, verify, BlindRequest, Rand, Token, TokenProof},
-};
-
-// Define types for clarity
-type Fp = Scalar; 
-type Token = (Commitment, Signature); 
-type ElID = G1G2; 
-
-// NPR PRF: H(i)^k
-fn prf(k: &Scalar, i: usize) -> G1G2 {
-    let hashed_i = hash_with_domain_separation(&i.to_le_bytes(), b"PRF-domain");
-    hashed_i * *k
-}
-
-pub fn spirit_setup(t: usize, n: usize, num_issuers: usize) -> (tACTPublicParameters, Vec<Issuer>, G1G2, Fp, HashSet<Token>) {
-    let mut rng = OsRng;
-
-    let (pp_prime, issuers) = tACT_setup(num_issuers, n, t, t - 1, 1).expect("tACT setup failed");
-
-    
-    let h: G1G2 = G1G2::random(&mut rng);
-    let hash_fp: Fp = Scalar::random(&mut rng);
-
-    
-    let t_rgstr: HashSet<Token> = HashSet::new();
-
-    
-    (pp_prime, issuers, h, hash_fp, t_rgstr)
-}
-
-
-pub fn spirit_register(
-    id_u: Scalar,
-    issuers: &[Issuer],
-    pp: &tACTPublicParameters,
-    t_rgstr: &mut HashSet<Token>,
-) -> Option<(Token, Scalar, HashSet<Token>)> {
-    let mut rng = OsRng;
-
-    
-    let (strg, cm) = register(&id_u, pp).ok()?;
-
-    
-    let (blind_request, rand) = token_request(&strg, &cm, pp).ok()?;
-
-    
-    let mut blind_tokens = Vec::new();
-    for issuer in issuers.iter().take(pp.t) {
-        let blind_token = tissue(&blind_request, issuer, pp).ok()?;
-        blind_tokens.push(blind_token);
-    }
-
-    
-    let token = aggregate_unblind(&blind_tokens, &rand, pp);
-
-    
-    let token_proof = prove(&token, &rand, pp);
-    if verify(&token, &token_proof, &blind_request, pp).is_err() {
-        return None;
-    }
-
-    
-    let cmk = cm.clone();
-    let final_token = (cmk, token.s.clone());
-    t_rgstr.insert(final_token.clone());
-
-    
-    Some((final_token, id_u, t_rgstr.clone()))
-}
-
-
-pub fn spirit_broadcast(i: usize, prv: &Scalar, t_el: &mut HashMap<ElID, Scalar>) -> HashMap<ElID, Scalar> {
-    let mut rng = OsRng;
-
-    
-    let el_id = prf(prv, i);
-
-    
-    let es_i = Scalar::random(&mut rng); 
-    t_el.insert(el_id, es_i);
-
-    
-    t_el.clone()
-}
-
-
-pub fn spirit_diagnosis(
-    ppu: (Token, Scalar),
-    prv: &Scalar,
-    cp: &HashSet<usize>,
-) -> Option<((Token, Scalar), Scalar, ElID)> {
-    let (token, _id_u) = ppu;
-    let (cmk, token_sig) = token;
-
-    
-    let tr: Vec<ElID> = cp.iter().map(|i| prf(prv, *i)).collect();
-
-    
-    let pi_r = Proof2PK::zk_proof(&cmk.0, &token_sig.0, &el_id); 
-
-    
-    Some((ppu, pi_r, el_id))
-}
-
-
-pub fn spirit_verify(
-    tr: ((Token, Scalar), Scalar, ElID),
-    t_rgstr: &HashSet<Token>,
-    cp: &mut HashSet<ElID>,
-) -> (HashSet<ElID>, bool) {
-    let ((ppu, _), pi_r, el_id) = tr;
-    let (token, _) = ppu;
-    let (cmk, token_sig) = token;
-
-    
-    let bit = if t_rgstr.contains(&token) && Proof2PK::zk_verify(&cmk.0, &pi_r) {
-        1
-    } else {
-        0
-    };
-
-    
-    if bit == 1 && cp.contains(&el_id) {
-        cp.insert(el_id);
-    } else {
-        cp.remove(&el_id);
-    }
-
-    
-    (cp.clone(), bit == 1)
-}
-
-
-pub fn spirit_trace(
-    cf: &HashSet<ElID>,
-    t_el: &HashMap<ElID, Scalar>,
-    exposure_limit: usize,
-) -> (usize, bool) {
-    let mut int_cnt = 0;
-
-    
-    for el_id in cf.iter() {
-        if t_el.contains_key(el_id) {
-            int_cnt += 1;
-        }
-    }
-
-    
-    let alarm_bit = int_cnt >= exposure_limit;
-
-    
-    (int_cnt, alarm_bit)
-}
\ No newline at end of file
+use alloc::{
+    collections::{BTreeMap, BTreeSet},
+    format,
+    string::String,
+    vec::Vec,
+};
+use core::{
+    cmp::Ordering,
+    fmt,
+    hash::{Hash, Hasher},
+    iter,
+    ops::Range,
+};
+#[cfg(feature = "telemetry")]
+use std::time::Instant;
+#[cfg(feature = "std")]
+use std::{
+    collections::{HashMap, HashSet, hash_map::Entry},
+    sync::{Arc, Mutex, PoisonError},
+};
+
+#[cfg(feature = "std")]
+use ark_ff::Zero;
+use ark_ff::{PrimeField, UniformRand};
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize, Compress, Validate};
+#[cfg(feature = "async")]
+use futures::stream::{FuturesUnordered, StreamExt};
+#[cfg(not(feature = "std"))]
+use hashbrown::{HashMap, HashSet};
+use rand::{CryptoRng, RngCore, seq::SliceRandom};
+#[cfg(feature = "std")]
+use rand::{Rng, rngs::OsRng};
+use sha2::{Digest, Sha256};
+use subtle::{Choice, ConstantTimeEq};
+use thiserror::Error;
+#[cfg(feature = "zeroize")]
+use zeroize::{Zeroize, ZeroizeOnDrop};
+
+#[cfg(feature = "std")]
+use crate::advertisement::ReceivedTable;
+#[cfg(feature = "async")]
+use crate::issuer::{IssuerClient, probe_quorum};
+#[cfg(feature = "telemetry")]
+use crate::telemetry::{self, Metrics};
+#[cfg(all(
+    feature = "std",
+    any(test, feature = "testkit", feature = "testvectors")
+))]
+use crate::transcript::{RecordingRng, Transcript};
+use crate::{
+    atact::{
+        AtACTError, BlindRequest, BlindToken, ParamError, PublicParameters as tACTPublicParameters,
+        Rand, StRG, Token as tACTToken, TokenProof, UNIQUE_ATTRIBUTE_INDEX, aggregate_unblind,
+        aggregate_unblind_from, check_parameters, prove, register_attributes, token_request,
+    },
+    bls381_helpers::{
+        DecodeError, G1G2, HashBackendId, HashToCurveSuite, Scalar, append_compressed,
+        hash_to_curve_with, split_array,
+    },
+    constants::{
+        AUTHORIZATION_SEED, BROADCAST_LEAF_DST, COMPACT_EL_ID_DST, DEFAULT_APP_ID, DEFAULT_PRF_DST,
+        DEFAULT_PROOF_DST, DEFAULT_REGISTRATION_DST, DIAGNOSIS_BUNDLE_PROTOCOL,
+        ENCOUNTER_SECRET_DST, EPOCH_KEY_DST, EXPIRY_BASE_SEED, IDENTITY_BASE_SEED,
+        KEY_BINDING_PROTOCOL, KEY_CLAIM_PROTOCOL, PRF_BROADCAST_TAG, PRF_CHECKIN_TAG,
+        PRF_DEVICE_TAG, PRF_GENERATOR_SEED, PRF_NULLIFIER_TAG, SHOW_SEED, TOKEN_ID_DST,
+    },
+    elgamal::{self, Ciphertext, DecryptionKey, EncryptionKey, PartialDecryption},
+    envelope::{self, ArtifactType, HEADER_SIZE, LoadMode, LoadWarning},
+    fiat_shamir::ProofTranscript,
+    pedersen::{
+        Commitment, DisclosureProof, MessageOpeningProof, MultiBasePublicParameters, Opening,
+        Proof2PK, Proof2PKBatch, get_parameters,
+    },
+    tsw::{self, PublicKey, SecretKey, Signature},
+};
+#[cfg(feature = "std")]
+use crate::{
+    atact::{
+        Issuer, reshare, select_committee, setup as tACT_setup, tissue, tissue_batch,
+        verify_blind_token, verify_with_identity,
+    },
+    bls381_helpers::{Precomputed, metered, msm},
+    constants::{BROADCAST_ANCHOR_DST, KEY_ROTATION_DST},
+    events::{Event, EventSink},
+    exposure::{ExposurePolicy, RiskLevel, RiskModel},
+    pedersen,
+    pool::TokenClaim,
+    receipt::RegistrationReceipt,
+    registry::{
+        MembershipProof, RegistrarKeypair, RegistrarKeys, RegistryError, RegistryHandle,
+        RegistryView, RevocationList, SignedRoot, TokenRegistry, anchor_message, merkle_levels,
+        merkle_path, rotation_message, verify_registrar_signature,
+    },
+    stats::{EpochStats, StatsDigest},
+};
+
+// Define types for clarity
+pub type ElID = G1G2;
+pub type Epoch = u64;
+/// Identifier of one of a user's devices, see [UserKeys::device_keys].
+pub type DeviceId = u32;
+/// Id of a key of the registrar, see [RegistrarKeyChain](crate::registry::RegistrarKeyChain).
+pub type KeyId = u32;
+
+/// Index of a broadcast interval, e.g. of the 15-minute intervals since the Unix epoch, see
+/// [Interval::from_unix]. It is encoded as a little-endian `u64` on every target, so inputs of
+/// the PRF, leaves of broadcast batches and encoded reports do not depend on the width of
+/// `usize`.
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    CanonicalSerialize,
+    CanonicalDeserialize,
+)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
+pub struct Interval(pub u64);
+
+impl Interval {
+    /// Size of [Self::to_le_bytes].
+    pub const ENCODED_SIZE: usize = 8;
+
+    /// The interval of `interval_len` seconds that holds the Unix time `secs`, counting from
+    /// the interval that starts at the Unix epoch. An interval holds its start, but not its end.
+    /// `None` for an `interval_len` of 0.
+    pub const fn from_unix(secs: u64, interval_len: u64) -> Option<Self> {
+        match secs.checked_div(interval_len) {
+            Some(index) => Some(Self(index)),
+            None => None,
+        }
+    }
+
+    /// Unix time at which the interval of `interval_len` seconds starts. `None` for an
+    /// `interval_len` of 0 and for starts beyond `u64::MAX`.
+    pub const fn start_unix(self, interval_len: u64) -> Option<u64> {
+        if interval_len == 0 {
+            return None;
+        }
+        self.0.checked_mul(interval_len)
+    }
+
+    pub const fn to_le_bytes(self) -> [u8; 8] {
+        self.0.to_le_bytes()
+    }
+
+    pub const fn from_le_bytes(bytes: [u8; 8]) -> Self {
+        Self(u64::from_le_bytes(bytes))
+    }
+
+    /// The intervals of `range` in ascending order, e.g. of a window.
+    pub fn range(range: Range<Self>) -> impl DoubleEndedIterator<Item = Self> + Clone {
+        (range.start.0..range.end.0).map(Self)
+    }
+
+    /// Number of intervals from `earlier` to `self`, or 0 if `earlier` is later.
+    pub const fn since(self, earlier: Self) -> u64 {
+        self.0.saturating_sub(earlier.0)
+    }
+
+    /// The interval `n` intervals later, or `None` beyond the last interval.
+    pub const fn checked_add(self, n: u64) -> Option<Self> {
+        match self.0.checked_add(n) {
+            Some(index) => Some(Self(index)),
+            None => None,
+        }
+    }
+
+    /// The interval `n` intervals earlier, or `None` before the first interval.
+    pub const fn checked_sub(self, n: u64) -> Option<Self> {
+        match self.0.checked_sub(n) {
+            Some(index) => Some(Self(index)),
+            None => None,
+        }
+    }
+
+    /// Number of intervals from `earlier` to `self`, or `None` if `earlier` is later, see
+    /// [Self::since].
+    pub const fn checked_since(self, earlier: Self) -> Option<u64> {
+        self.0.checked_sub(earlier.0)
+    }
+}
+
+impl fmt::Display for Interval {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// Indices of releases before `Interval`, which were `usize`.
+impl From<usize> for Interval {
+    fn from(index: usize) -> Self {
+        Self(index as u64)
+    }
+}
+
+impl From<Interval> for u64 {
+    fn from(interval: Interval) -> Self {
+        interval.0
+    }
+}
+
+/// Identifier of a [Token], see [Token::id].
+pub type TokenId = [u8; 32];
+/// Identifier of one of the registrars of a deployment whose registry is sharded, e.g. by
+/// province. Deployments with a single registrar use 0.
+pub type RegistrarId = u32;
+
+/// Domain-separation tags of a deployment. Every tag is prefixed with the length-prefixed
+/// `app_id`, so two deployments with different `app_id`s derive unrelated ElIDs, token
+/// commitments and proof challenges from the same key material.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DomainConfig {
+    pub app_id: Vec<u8>,
+    /// Tag of the PRF, i.e. of the bases `H(purpose, input)` of every [PrfPurpose].
+    pub prf_dst: Vec<u8>,
+    /// Tag of the [PrfProof] generator and transcript.
+    pub proof_dst: Vec<u8>,
+    /// Tag of the token expiry base and the tACT issuance transcript.
+    pub registration_dst: Vec<u8>,
+    /// Hash-to-curve of the hashes under these tags. The default [HashToCurveSuite::Legacy] is
+    /// the one of the deployments set up by releases before [HashToCurveSuite::Rfc9380], which
+    /// new deployments that interoperate with other implementations select.
+    pub hash_to_curve: HashToCurveSuite,
+    /// Hash function of the hash-to-curve, and of the seed of [HashToCurveSuite::Legacy].
+    pub hash_backend: HashBackendId,
+    /// Whether verifiers require a [KeyBindingProof] in every trace report, for deployments
+    /// whose tokens commit to the users' PRF keys, see [spirit_register_key_bound].
+    pub require_key_binding: bool,
+    /// Number of epochs after its [report epoch](TraceReport::report_epoch) in which verifiers
+    /// accept a trace report, so reports cannot be hoarded and submitted late. A report made in
+    /// epoch `e` is accepted from `e` through `e + reporting_window`, and with the default of 0
+    /// only in `e`. Its [ReportChallenge] has to be fresh as well, see [ChallengeSet].
+    pub reporting_window: usize,
+    /// Key of the health authority whose [DiagnosisAuthorization] verifiers require in every
+    /// trace report, so only users with a positive test can report. Without a key, reports
+    /// need no authorization and the ones they carry are not checked.
+    pub diagnosis_authority: Option<PublicKey>,
+    /// Public beacon of the issuance committees, for deployments in which every epoch's
+    /// registrations are issued by the committee that
+    /// [select_committee](crate::atact::select_committee) assigns to the epoch rather than by
+    /// issuers the users pick, see [spirit_register_with_committee].
+    pub committee_beacon: Option<Vec<u8>>,
+    /// Key of the registrar whose [BroadcastAnchor]s verifiers require for every ElID of a
+    /// trace report, so a diagnosed user cannot upload ElIDs that were never broadcast, e.g. to
+    /// implicate bystanders. Without a key, reports need no [BroadcastAttestation] and the ones
+    /// they carry are not checked.
+    pub anchoring_registrar: Option<PublicKey>,
+    /// Key of the registrar whose [KeyRotation]s verifiers accept in the trace reports of
+    /// rotated tokens, see [spirit_rotate]. Without a key, reports that carry a rotation are
+    /// rejected.
+    pub rotation_registrar: Option<PublicKey>,
+    /// Number of epochs after a [KeyRotation] in which verifiers accept reports that disclose
+    /// ElIDs under the previous keys. A rotation of epoch `e` is accepted in reports of the
+    /// epochs `e` through `e + rotation_overlap`.
+    pub rotation_overlap: Epoch,
+    /// Number of attributes the tokens of the deployment carry, including the identity, as set
+    /// up by [spirit_setup_with_attributes]. Verifiers check the [DisclosureProof]s of trace
+    /// reports against the generators of this many attributes.
+    pub num_attributes: usize,
+    /// Limit `k` on the shows of a token, for deployments under a k-show policy: verifiers
+    /// require a [ShowTag] of an index below the limit in every trace report, see
+    /// [spirit_diagnosis_with_show], and [spirit_verify_with_shows] rejects replayed tags.
+    /// Without a limit, reports need no tag and the ones they carry are not checked.
+    pub show_limit: Option<usize>,
+}
+
+impl Default for DomainConfig {
+    fn default() -> Self {
+        Self::new(DEFAULT_APP_ID.to_vec())
+    }
+}
+
+impl DomainConfig {
+    /// Creates a configuration for `app_id` with the default tags.
+    pub fn new(app_id: Vec<u8>) -> Self {
+        Self {
+            app_id,
+            prf_dst: DEFAULT_PRF_DST.to_vec(),
+            proof_dst: DEFAULT_PROOF_DST.to_vec(),
+            registration_dst: DEFAULT_REGISTRATION_DST.to_vec(),
+            hash_to_curve: HashToCurveSuite::default(),
+            hash_backend: HashBackendId::default(),
+            require_key_binding: false,
+            reporting_window: 0,
+            diagnosis_authority: None,
+            committee_beacon: None,
+            anchoring_registrar: None,
+            rotation_registrar: None,
+            rotation_overlap: 0,
+            num_attributes: 1,
+            show_limit: None,
+        }
+    }
+
+    /// The configuration with the hash-to-curve `suite`, e.g. [HashToCurveSuite::Rfc9380] for a
+    /// deployment whose points other implementations derive as well.
+    pub fn with_hash_to_curve(mut self, suite: HashToCurveSuite) -> Self {
+        self.hash_to_curve = suite;
+        self
+    }
+
+    /// The configuration with the hash function `backend`, e.g. [HashBackendId::Sha3_256] for
+    /// a deployment that mandates SHA-3.
+    pub fn with_hash_backend(mut self, backend: HashBackendId) -> Self {
+        self.hash_backend = backend;
+        self
+    }
+
+    /// The configuration with [Self::require_key_binding] set, so reports whose ElIDs are not
+    /// proven to derive from the key committed in the token are rejected.
+    pub fn with_key_binding(mut self) -> Self {
+        self.require_key_binding = true;
+        self
+    }
+
+    /// The configuration with the [reporting window](Self::reporting_window) `window`.
+    pub fn with_reporting_window(mut self, window: usize) -> Self {
+        self.reporting_window = window;
+        self
+    }
+
+    /// The configuration with the [diagnosis authority](Self::diagnosis_authority) `ha_pk`.
+    pub fn with_diagnosis_authority(mut self, ha_pk: PublicKey) -> Self {
+        self.diagnosis_authority = Some(ha_pk);
+        self
+    }
+
+    /// The configuration with the [committee beacon](Self::committee_beacon) `beacon`.
+    pub fn with_committee_beacon(mut self, beacon: Vec<u8>) -> Self {
+        self.committee_beacon = Some(beacon);
+        self
+    }
+
+    /// The configuration with the [anchoring registrar](Self::anchoring_registrar)
+    /// `registrar`.
+    pub fn with_anchoring_registrar(mut self, registrar: PublicKey) -> Self {
+        self.anchoring_registrar = Some(registrar);
+        self
+    }
+
+    /// The configuration with the [rotation registrar](Self::rotation_registrar) `registrar`
+    /// and the [rotation overlap](Self::rotation_overlap) `overlap`.
+    pub fn with_rotation_registrar(mut self, registrar: PublicKey, overlap: Epoch) -> Self {
+        self.rotation_registrar = Some(registrar);
+        self.rotation_overlap = overlap;
+        self
+    }
+
+    /// The configuration with the [show limit](Self::show_limit) `limit`.
+    pub fn with_show_limit(mut self, limit: usize) -> Self {
+        self.show_limit = Some(limit);
+        self
+    }
+
+    /// Whether a report of `report_epoch` is within the reporting window at `current_epoch`.
+    pub(crate) fn accepts_report_epoch(&self, report_epoch: Epoch, current_epoch: Epoch) -> bool {
+        report_epoch <= current_epoch
+            && current_epoch - report_epoch <= self.reporting_window as Epoch
+    }
+
+    pub(crate) fn tag(&self, dst: &[u8]) -> Vec<u8> {
+        let mut tag = (self.app_id.len() as u64).to_le_bytes().to_vec();
+        tag.extend_from_slice(&self.app_id);
+        tag.extend_from_slice(dst);
+        tag
+    }
+
+    /// Hashes `msg` under the [tag](Self::tag) of `dst` with [Self::hash_to_curve] and
+    /// [Self::hash_backend].
+    pub(crate) fn hash(&self, msg: &[u8], dst: &[u8]) -> G1G2 {
+        self.hash_tagged(msg, &self.tag(dst))
+    }
+
+    /// Like [Self::hash], but with the full tag `tag`.
+    fn hash_tagged(&self, msg: &[u8], tag: &[u8]) -> G1G2 {
+        hash_to_curve_with(self.hash_to_curve, self.hash_backend, msg, tag)
+    }
+}
+
+/// Part `v_expiry` of the commitment of a blind request and of a token that expires at the
+/// start of epoch `expiry`. Each expiry has its own hashed base `v_expiry` rather than a power
+/// `v^expiry` of one base, so an affine combination of tokens with different expiries does not
+/// commit to any expiry the issuers signed.
+fn expiry_term(domain: &DomainConfig, expiry: Epoch) -> G1G2 {
+    let seed = [EXPIRY_BASE_SEED, &expiry.to_le_bytes()].concat();
+    domain.hash(&seed, &domain.registration_dst)
+}
+
+pub(crate) fn identity_base(domain: &DomainConfig) -> G1G2 {
+    domain.hash(IDENTITY_BASE_SEED, &domain.registration_dst)
+}
+
+/// Tag `H_id^id_u` of the identity `id_u`, see [IdentityClaim].
+pub(crate) fn identity_tag(domain: &DomainConfig, id_u: &Scalar) -> G1G2 {
+    identity_base(domain) * *id_u
+}
+
+/// Registered token: the commitment to the user's identity, attributes and expiry epoch, the
+/// issuers' signature on it, and the expiry epoch. The token commitment is the tACT commitment
+/// `g^r u^a Π_i h_i^m_i` times `v_expiry`, which is the commitment of the blind request, so the
+/// issuers sign the expiry together with the identity and a token cannot be given another one.
+///
+/// Equality of tokens is constant-time, and tokens are hashed through their fixed-length
+/// [Token::id], so registry lookups in [spirit_verify] do not leak through timing how much of
+/// a presented token matches a registered one.
+#[derive(Debug, Clone, CanonicalSerialize, CanonicalDeserialize)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Token(pub Commitment, pub Signature, pub Epoch);
+
+impl ConstantTimeEq for Token {
+    fn ct_eq(&self, other: &Self) -> Choice {
+        self.0.ct_eq(&other.0) & self.1.ct_eq(&other.1) & self.2.ct_eq(&other.2)
+    }
+}
+
+impl PartialEq for Token {
+    fn eq(&self, other: &Self) -> bool {
+        self.ct_eq(other).into()
+    }
+}
+
+impl Eq for Token {}
+
+impl Hash for Token {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.id().hash(state);
+    }
+}
+
+/// Orders tokens by their [payload](Token::to_bytes), like the points in it by their
+/// encoding. Unlike equality, the comparison is not constant-time.
+impl Ord for Token {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.payload().cmp(&other.payload())
+    }
+}
+
+impl PartialOrd for Token {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Token {
+    /// Size of the payload: the commitment and the signature, compressed, and the expiry epoch
+    /// as little-endian `u64`.
+    pub const PAYLOAD_SIZE: usize = 2 * G1G2::ENCODED_SIZE + 8;
+    /// Size of the payload behind its [envelope] header.
+    pub const ENCODED_SIZE: usize = HEADER_SIZE + Self::PAYLOAD_SIZE;
+
+    /// Token for the tACT commitment `cm` with the issuers' signature `s`, expiring at the start
+    /// of epoch `expiry`.
+    pub fn new(domain: &DomainConfig, cm: &Commitment, s: Signature, expiry: Epoch) -> Self {
+        Self(Commitment(&cm.0 + expiry_term(domain, expiry)), s, expiry)
+    }
+
+    /// The tACT commitment `g^r u^a Π_i h_i^m_i` to the user's identity and attributes, which
+    /// is the commitment `g^r u^a` to the identity for a token without attributes.
+    pub fn identity_commitment(&self, domain: &DomainConfig) -> Commitment {
+        Commitment(&self.0.0 - expiry_term(domain, self.2))
+    }
+
+    /// Identifier of the token on a [RevocationList]: the hash of its canonical encoding.
+    pub fn id(&self) -> TokenId {
+        Sha256::new()
+            .chain_update(TOKEN_ID_DST)
+            .chain_update(self.payload())
+            .finalize()
+            .into()
+    }
+
+    /// Tokens expire at the start of their expiry epoch.
+    pub fn is_expired(&self, current_epoch: Epoch) -> bool {
+        self.2 <= current_epoch
+    }
+
+    /// Presentation of the registered token `self` with the opening `st_rg` that cannot be
+    /// linked to the token or to other presentations of it, and the shift `delta` of the
+    /// commitment's randomness, whose opening is `st_rg.r + delta`, see [spirit_rerandomize].
+    ///
+    /// The issuers' signature `s` of a registered token is the signature `(H_u cm)^sk` on its
+    /// commitment `cm = g^r u^a v_expiry` divided by `pk^r`. The presentation carries the
+    /// signature `(H_u cm g^delta)^sk = s pk^(r + delta)` on the shifted commitment `cm g^delta`,
+    /// which [Self::verify_signature] checks against the issuers' key `pk`. Only the randomness
+    /// `g^r` is shifted: the identity, the attributes and the expiry stay as signed, and the
+    /// disclosure proofs open the commitment with them. Only registered tokens are
+    /// rerandomized, a presentation is not.
+    pub fn rerandomize(
+        &self,
+        st_rg: &StRG,
+        pk: &PublicKey,
+        rng: &mut (impl RngCore + CryptoRng),
+    ) -> (Self, Scalar) {
+        let delta = Scalar::rand(rng);
+        let presentation = Self(
+            Commitment(&self.0.0 + &get_parameters().g * delta),
+            Signature(&self.1.0 + &pk.0 * (st_rg.r + delta)),
+            self.2,
+        );
+        (presentation, delta)
+    }
+
+    /// Checks that the signature of a [rerandomized](Self::rerandomize) token is the issuers'
+    /// signature on its commitment, which includes the expiry, under the [prepared
+    /// key](tACTPublicParameters::prepared_key) of `pp`.
+    pub fn verify_signature(&self, pp: &tACTPublicParameters) -> bool {
+        pp.verify_under_pk(&self.0, UNIQUE_ATTRIBUTE_INDEX, &self.1)
+            .is_ok()
+    }
+
+    /// The payload of [Self::to_bytes], which does not depend on the envelope version, e.g. for
+    /// hashing the token.
+    pub(crate) fn payload(&self) -> [u8; Self::PAYLOAD_SIZE] {
+        const SIGNATURE_END: usize = 2 * G1G2::ENCODED_SIZE;
+
+        let mut bytes = [0u8; Self::PAYLOAD_SIZE];
+        bytes[..G1G2::ENCODED_SIZE].copy_from_slice(&self.0.0.to_bytes());
+        bytes[G1G2::ENCODED_SIZE..SIGNATURE_END].copy_from_slice(&self.1.0.to_bytes());
+        bytes[SIGNATURE_END..].copy_from_slice(&self.2.to_le_bytes());
+        bytes
+    }
+
+    /// Decodes a [Self::payload] of version 1.
+    pub(crate) fn from_payload(bytes: &[u8]) -> Result<Self, DecodeError> {
+        if bytes.len() != Self::PAYLOAD_SIZE {
+            return Err(DecodeError::InvalidLength {
+                expected: Self::PAYLOAD_SIZE,
+                actual: bytes.len(),
+            });
+        }
+
+        let (cm, rest) = split_array::<{ G1G2::ENCODED_SIZE }>(bytes)?;
+        let (sig, rest) = split_array::<{ G1G2::ENCODED_SIZE }>(rest)?;
+        let (expiry, _) = split_array::<8>(rest)?;
+        Ok(Self(
+            Commitment(G1G2::from_bytes(cm)?),
+            Signature(G1G2::from_bytes(sig)?),
+            Epoch::from_le_bytes(*expiry),
+        ))
+    }
+
+    pub fn to_bytes(&self) -> [u8; Self::ENCODED_SIZE] {
+        let mut bytes = [0u8; Self::ENCODED_SIZE];
+        bytes[..HEADER_SIZE].copy_from_slice(&envelope::header(ArtifactType::Token));
+        bytes[HEADER_SIZE..].copy_from_slice(&self.payload());
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, DecodeError> {
+        match envelope::open(bytes, ArtifactType::Token)? {
+            (1, payload) => Self::from_payload(payload),
+            (version, _) => Err(DecodeError::UnsupportedVersion(version)),
+        }
+    }
+}
+
+/// Purpose of an evaluation of the [prf]. The purpose is hashed into the input of the PRF, so
+/// the evaluations for different purposes are unrelated even under the same key and input, and
+/// e.g. a nullifier never equals an ElID.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PrfPurpose {
+    /// ElIDs of the broadcasts, on the [broadcast_input] of the epoch and index.
+    Broadcast,
+    /// [Nullifier]s of the trace reports, on the report epoch.
+    Nullifier,
+    /// ElIDs of venue check-ins, see [spirit_checkin].
+    Checkin,
+    /// Keys of a user's devices, see [UserKeys::device_keys].
+    DeviceDerivation,
+}
+
+impl PrfPurpose {
+    /// Tag of the purpose, which is length-prefixed in front of the input of the PRF.
+    pub fn tag(self) -> &'static [u8] {
+        match self {
+            Self::Broadcast => PRF_BROADCAST_TAG,
+            Self::Nullifier => PRF_NULLIFIER_TAG,
+            Self::Checkin => PRF_CHECKIN_TAG,
+            Self::DeviceDerivation => PRF_DEVICE_TAG,
+        }
+    }
+}
+
+/// Input `epoch || i` of the PRF for the ElID of interval `i` of `epoch`.
+pub fn broadcast_input(epoch: Epoch, i: Interval) -> [u8; 16] {
+    let mut input = [0u8; 16];
+    input[..8].copy_from_slice(&epoch.to_le_bytes());
+    input[8..].copy_from_slice(&i.to_le_bytes());
+    input
+}
+
+#[inline]
+fn purpose_base(domain: &DomainConfig, purpose: PrfPurpose, input: &[u8]) -> G1G2 {
+    purpose_base_tagged(domain, &domain.tag(&domain.prf_dst), purpose, input)
+}
+
+#[inline]
+fn purpose_base_tagged(
+    domain: &DomainConfig,
+    tag: &[u8],
+    purpose: PrfPurpose,
+    input: &[u8],
+) -> G1G2 {
+    let purpose_tag = purpose.tag();
+    let mut msg = (purpose_tag.len() as u64).to_le_bytes().to_vec();
+    msg.extend_from_slice(purpose_tag);
+    msg.extend_from_slice(input);
+    domain.hash_tagged(&msg, tag)
+}
+
+#[inline]
+fn prf_base(domain: &DomainConfig, epoch: Epoch, i: Interval) -> G1G2 {
+    prf_base_tagged(domain, &domain.tag(&domain.prf_dst), epoch, i)
+}
+
+#[inline]
+fn prf_base_tagged(domain: &DomainConfig, tag: &[u8], epoch: Epoch, i: Interval) -> G1G2 {
+    purpose_base_tagged(
+        domain,
+        tag,
+        PrfPurpose::Broadcast,
+        &broadcast_input(epoch, i),
+    )
+}
+
+/// Base `H(venue_id, slot)` of the ElIDs of check-ins into `slot` of the venue `venue_id`, see
+/// [spirit_checkin]. The venue id is length-prefixed, so the inputs of two venues never
+/// coincide.
+fn venue_base(domain: &DomainConfig, venue_id: &[u8], slot: Interval) -> G1G2 {
+    let mut input = (venue_id.len() as u64).to_le_bytes().to_vec();
+    input.extend_from_slice(venue_id);
+    input.extend_from_slice(&slot.to_le_bytes());
+    purpose_base(domain, PrfPurpose::Checkin, &input)
+}
+
+/// The NPR PRF `H(purpose, input)^k` for the [PrfPurpose] `purpose`.
+///
+/// Releases before the purposes hashed `(epoch, i)` alone for ElIDs, so the ElIDs, nullifiers
+/// and check-ins of a deployment set up by such a release do not match the ones derived now.
+/// The [wire version](crate::wire::VERSION) was raised with the change, so registrations
+/// between releases of both derivations fail instead of issuing tokens whose reports would not
+/// verify.
+pub fn prf(domain: &DomainConfig, k: &Scalar, purpose: PrfPurpose, input: &[u8]) -> G1G2 {
+    let value = purpose_base(domain, purpose, input) * *k;
+    // only the key 0, which is never drawn, maps to the identity
+    debug_assert!(!value.is_identity());
+    value
+}
+
+/// The [prf] for [PrfPurpose::Broadcast], i.e. the ElID `H(epoch, i)^k` of interval `i` of
+/// `epoch` for the epoch key `k`.
+pub fn prf_broadcast(domain: &DomainConfig, k: &Scalar, epoch: Epoch, i: Interval) -> ElID {
+    prf(domain, k, PrfPurpose::Broadcast, &broadcast_input(epoch, i))
+}
+
+// the PRF for all indices of `indices`, deriving the tag only once
+#[cfg(not(feature = "rayon"))]
+fn prf_range(
+    domain: &DomainConfig,
+    k: &Scalar,
+    epoch: Epoch,
+    indices: Range<Interval>,
+) -> Vec<G1G2> {
+    let tag = domain.tag(&domain.prf_dst);
+    Interval::range(indices)
+        .map(|i| prf_base_tagged(domain, &tag, epoch, i) * *k)
+        .collect()
+}
+
+#[cfg(feature = "rayon")]
+fn prf_range(
+    domain: &DomainConfig,
+    k: &Scalar,
+    epoch: Epoch,
+    indices: Range<Interval>,
+) -> Vec<G1G2> {
+    use rayon::iter::{IntoParallelIterator, ParallelIterator};
+
+    let tag = domain.tag(&domain.prf_dst);
+    // collected first, as ranges of u64 are not indexed parallel iterators on every target
+    let indices: Vec<_> = Interval::range(indices).collect();
+    indices
+        .into_par_iter()
+        .map(|i| prf_base_tagged(domain, &tag, epoch, i) * *k)
+        .collect()
+}
+
+fn prf_generator(domain: &DomainConfig) -> G1G2 {
+    domain.hash(PRF_GENERATOR_SEED, &domain.proof_dst)
+}
+
+/// Public key `g^k` under which evaluations of the PRF with key `k` are verified.
+pub fn prf_public_key(domain: &DomainConfig, k: &Scalar) -> G1G2 {
+    prf_generator(domain) * *k
+}
+
+/// Chaum-Pedersen proof that an ElID `H(epoch, i)^k` and the public key `g^k` share the
+/// discrete logarithm `k`, turning the PRF into a VRF.
+#[derive(Debug, Clone, PartialEq, Eq, CanonicalSerialize, CanonicalDeserialize)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PrfProof {
+    #[cfg_attr(
+        feature = "serde",
+        serde(with = "crate::bls381_helpers::serde_compressed")
+    )]
+    pub(crate) c: Scalar,
+    #[cfg_attr(
+        feature = "serde",
+        serde(with = "crate::bls381_helpers::serde_compressed")
+    )]
+    pub(crate) s: Scalar,
+}
+
+fn hash_prf_proof(
+    domain: &DomainConfig,
+    generator: &G1G2,
+    pk: &G1G2,
+    base: &G1G2,
+    el_id: &ElID,
+    t1: &G1G2,
+    t2: &G1G2,
+) -> Scalar {
+    let mut storage = Vec::new();
+    for point in [generator, pk, base, el_id, t1, t2] {
+        append_compressed(&mut storage, point);
+    }
+
+    let digest = Sha256::new()
+        .chain_update(domain.tag(&domain.proof_dst))
+        .chain_update(storage)
+        .finalize();
+    Scalar::from_le_bytes_mod_order(&digest)
+}
+
+/// Evaluates the PRF with key `k` on `(epoch, i)` and proves the evaluation against
+/// [prf_public_key]`(k)`.
+pub fn prf_prove(
+    domain: &DomainConfig,
+    k: &Scalar,
+    epoch: Epoch,
+    i: Interval,
+    rng: &mut (impl RngCore + CryptoRng),
+) -> (ElID, PrfProof) {
+    prf_prove_with_base(domain, k, &prf_base(domain, epoch, i), rng)
+}
+
+/// [prf_prove] for the evaluation `base^k` on any base, e.g. of a venue check-in.
+fn prf_prove_with_base(
+    domain: &DomainConfig,
+    k: &Scalar,
+    base: &G1G2,
+    rng: &mut (impl RngCore + CryptoRng),
+) -> (ElID, PrfProof) {
+    let generator = prf_generator(domain);
+    let pk = &generator * *k;
+    let el_id = base * *k;
+
+    let r = Scalar::rand(rng);
+    let t1 = &generator * r;
+    let t2 = base * r;
+    let c = hash_prf_proof(domain, &generator, &pk, base, &el_id, &t1, &t2);
+
+    (el_id, PrfProof { c, s: r + c * k })
+}
+
+pub fn prf_verify(
+    domain: &DomainConfig,
+    pk: &G1G2,
+    epoch: Epoch,
+    i: Interval,
+    el_id: &ElID,
+    proof: &PrfProof,
+) -> bool {
+    prf_verify_with_base(domain, pk, &prf_base(domain, epoch, i), el_id, proof)
+}
+
+fn prf_verify_with_base(
+    domain: &DomainConfig,
+    pk: &G1G2,
+    base: &G1G2,
+    el_id: &ElID,
+    proof: &PrfProof,
+) -> bool {
+    let generator = prf_generator(domain);
+    let t1 = &generator * proof.s - pk * proof.c;
+    let t2 = base * proof.s - el_id * proof.c;
+    hash_prf_proof(domain, &generator, pk, base, el_id, &t1, &t2) == proof.c
+}
+
+/// Nullifier `H(report_epoch)^id_u` of the trace reports of the user `id_u` submitted in
+/// `report_epoch`, see [TraceReport::nullifier].
+pub type Nullifier = G1G2;
+
+fn nullifier_base(domain: &DomainConfig, report_epoch: Epoch) -> G1G2 {
+    purpose_base(domain, PrfPurpose::Nullifier, &report_epoch.to_le_bytes())
+}
+
+/// Proof that a [Nullifier] is derived from the identity committed to in a token.
+#[derive(Debug, Clone, PartialEq, Eq, CanonicalSerialize, CanonicalDeserialize)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct NullifierProof {
+    #[cfg_attr(
+        feature = "serde",
+        serde(with = "crate::bls381_helpers::serde_compressed")
+    )]
+    pub(crate) c: Scalar,
+    #[cfg_attr(
+        feature = "serde",
+        serde(with = "crate::bls381_helpers::serde_compressed")
+    )]
+    pub(crate) s_1: Scalar,
+    #[cfg_attr(
+        feature = "serde",
+        serde(with = "crate::bls381_helpers::serde_compressed")
+    )]
+    pub(crate) s_2: Scalar,
+}
+
+#[allow(clippy::too_many_arguments)]
+fn hash_tag_proof(
+    domain: &DomainConfig,
+    label: &[u8],
+    token: &Token,
+    report_epoch: Epoch,
+    challenge: &ReportChallenge,
+    tag: &G1G2,
+    t1: &G1G2,
+    t2: &G1G2,
+) -> Scalar {
+    let mut storage = Vec::new();
+    append_compressed(&mut storage, token);
+    append_compressed(&mut storage, &report_epoch);
+    append_compressed(&mut storage, challenge);
+    for point in [tag, t1, t2] {
+        append_compressed(&mut storage, point);
+    }
+
+    let digest = Sha256::new()
+        .chain_update(domain.tag(&domain.proof_dst))
+        .chain_update(label)
+        .chain_update(storage)
+        .finalize();
+    Scalar::from_le_bytes_mod_order(&digest)
+}
+
+// proves knowledge of the opening `(r, a)` of the identity commitment `g^r u^a` of the report
+// with `tag = base^a`, under the transcript label `label`
+#[allow(clippy::too_many_arguments)]
+fn tag_prove(
+    domain: &DomainConfig,
+    label: &[u8],
+    base: &G1G2,
+    token: &Token,
+    a: &Scalar,
+    opening: &Opening,
+    report_epoch: Epoch,
+    challenge: &ReportChallenge,
+    rng: &mut (impl RngCore + CryptoRng),
+) -> (G1G2, NullifierProof) {
+    let pp = get_parameters();
+    let tag = base * *a;
+
+    let r_1 = Scalar::rand(rng);
+    let r_2 = Scalar::rand(rng);
+    let t1 = &pp.g * r_1 + &pp.u * r_2;
+    let t2 = base * r_2;
+    let c = hash_tag_proof(
+        domain,
+        label,
+        token,
+        report_epoch,
+        challenge,
+        &tag,
+        &t1,
+        &t2,
+    );
+
+    let proof = NullifierProof {
+        c,
+        s_1: r_1 + c * opening.r,
+        s_2: r_2 + c * *a,
+    };
+    (tag, proof)
+}
+
+#[cfg(feature = "std")]
+fn tag_verify(
+    domain: &DomainConfig,
+    label: &[u8],
+    base: &G1G2,
+    tr: &TraceReport,
+    tag: &G1G2,
+    proof: &NullifierProof,
+) -> bool {
+    let pp = get_parameters();
+    let cm = tr.identity_commitment(domain);
+
+    let t1 = &pp.g * proof.s_1 + &pp.u * proof.s_2 - &cm.0 * proof.c;
+    let t2 = base * proof.s_2 - tag * proof.c;
+    hash_tag_proof(
+        domain,
+        label,
+        &tr.token,
+        tr.report_epoch,
+        &tr.challenge,
+        tag,
+        &t1,
+        &t2,
+    ) == proof.c
+}
+
+fn nullifier_prove(
+    domain: &DomainConfig,
+    token: &Token,
+    a: &Scalar,
+    opening: &Opening,
+    report_epoch: Epoch,
+    challenge: &ReportChallenge,
+    rng: &mut (impl RngCore + CryptoRng),
+) -> (Nullifier, NullifierProof) {
+    let base = nullifier_base(domain, report_epoch);
+    tag_prove(
+        domain,
+        PRF_NULLIFIER_TAG,
+        &base,
+        token,
+        a,
+        opening,
+        report_epoch,
+        challenge,
+        rng,
+    )
+}
+
+#[cfg(feature = "std")]
+fn nullifier_verify(domain: &DomainConfig, tr: &TraceReport) -> bool {
+    let base = nullifier_base(domain, tr.report_epoch);
+    tag_verify(
+        domain,
+        PRF_NULLIFIER_TAG,
+        &base,
+        tr,
+        &tr.nullifier,
+        &tr.nullifier_proof,
+    )
+}
+
+/// Base `H(expiry, index)` of the [ShowTag] of index `index` of a token expiring at `expiry`.
+fn show_base(domain: &DomainConfig, expiry: Epoch, index: usize) -> G1G2 {
+    let mut msg = SHOW_SEED.to_vec();
+    msg.extend_from_slice(&expiry.to_le_bytes());
+    msg.extend_from_slice(&(index as u64).to_le_bytes());
+    domain.hash(&msg, &domain.proof_dst)
+}
+
+fn show_label(index: usize) -> Vec<u8> {
+    let mut label = SHOW_SEED.to_vec();
+    label.extend_from_slice(&(index as u64).to_le_bytes());
+    label
+}
+
+/// One-time tag `H(expiry, index)^a` of the show of index `index` of a token under a k-show
+/// policy, with a proof that it is derived from the identity `a` committed to in the token, see
+/// [spirit_diagnosis_with_show]. The identity is the seed the user committed to at
+/// registration, so a token has exactly one tag per index, and tags of different indices are
+/// unlinkable. The index itself is public, but drawn at random from the unused ones for every
+/// show, see [ShowCounter], so it does not tell how often the token was shown before.
+#[derive(Debug, Clone, PartialEq, Eq, CanonicalSerialize, CanonicalDeserialize)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ShowTag {
+    pub index: usize,
+    pub tag: G1G2,
+    pub proof: NullifierProof,
+}
+
+/// The indices of the shows of a user's token under a k-show policy of limit `k`, kept on the
+/// user's device. Every show draws its index at random from the unused ones.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShowCounter {
+    limit: usize,
+    used: BTreeSet<usize>,
+}
+
+impl ShowCounter {
+    pub fn new(limit: usize) -> Self {
+        Self::with_used(limit, [])
+    }
+
+    /// The counter of `limit` of which the indices `used` were shown, e.g. restored from the
+    /// device's storage. Indices from the limit on are ignored.
+    pub fn with_used(limit: usize, used: impl IntoIterator<Item = usize>) -> Self {
+        Self {
+            limit,
+            used: used.into_iter().filter(|index| *index < limit).collect(),
+        }
+    }
+
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    /// The indices of the shows made so far, in ascending order.
+    pub fn used(&self) -> impl Iterator<Item = usize> + '_ {
+        self.used.iter().copied()
+    }
+
+    /// Number of the shows left.
+    pub fn remaining(&self) -> usize {
+        self.limit - self.used.len()
+    }
+
+    // a uniformly random index among the unused ones
+    fn draw(&self, rng: &mut impl RngCore) -> Option<usize> {
+        let unused: Vec<_> = (0..self.limit)
+            .filter(|index| !self.used.contains(index))
+            .collect();
+        unused.choose(rng).copied()
+    }
+}
+
+/// Verifier's state under a k-show policy: the limit `k` on the shows of a token, and the tags
+/// of the reports accepted so far.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone)]
+pub struct ShowRegistry {
+    limit: usize,
+    seen: HashSet<G1G2>,
+}
+
+#[cfg(feature = "std")]
+impl ShowRegistry {
+    pub fn new(limit: usize) -> Self {
+        Self {
+            limit,
+            seen: HashSet::new(),
+        }
+    }
+
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    /// Number of the tags seen.
+    pub fn len(&self) -> usize {
+        self.seen.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.seen.is_empty()
+    }
+
+    /// Checks that `tr` carries a valid [ShowTag] of an index below the limit that was not seen
+    /// before.
+    fn check(&self, domain: &DomainConfig, tr: &TraceReport) -> Result<(), VerifyRejection> {
+        let show = check_show_tag(domain, tr, self.limit)?;
+        if self.seen.contains(&show.tag) {
+            return Err(VerifyRejection::ReplayedShow {
+                token: tr.token.id(),
+            });
+        }
+        Ok(())
+    }
+}
+
+// the report carries a ShowTag of an index below `limit` whose proof verifies
+#[cfg(feature = "std")]
+fn check_show_tag<'a>(
+    domain: &DomainConfig,
+    tr: &'a TraceReport,
+    limit: usize,
+) -> Result<&'a ShowTag, VerifyRejection> {
+    let token = tr.token.id();
+    let Some(show) = &tr.show else {
+        return Err(VerifyRejection::MissingShow { token });
+    };
+    if show.index >= limit {
+        return Err(VerifyRejection::ShowLimitExceeded {
+            token,
+            index: show.index,
+            limit,
+        });
+    }
+    let is_valid = tag_verify(
+        domain,
+        &show_label(show.index),
+        &show_base(domain, tr.token.2, show.index),
+        tr,
+        &show.tag,
+        &show.proof,
+    );
+    if !is_valid {
+        return Err(VerifyRejection::InvalidShow { token });
+    }
+    Ok(show)
+}
+
+/// Fresh nonce chosen by a verifier in epoch `issued_at`, to which a reporting user binds the
+/// proofs of its trace report, see [spirit_diagnosis]. A report can thus not be precomputed,
+/// and a stolen report does not verify under any other challenge.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, CanonicalSerialize, CanonicalDeserialize)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ReportChallenge {
+    pub(crate) nonce: [u8; 32],
+    pub(crate) issued_at: Epoch,
+}
+
+impl ReportChallenge {
+    pub fn new(issued_at: Epoch, rng: &mut (impl RngCore + CryptoRng)) -> Self {
+        let mut nonce = [0u8; 32];
+        rng.fill_bytes(&mut nonce);
+        Self { nonce, issued_at }
+    }
+
+    pub fn nonce(&self) -> &[u8; 32] {
+        &self.nonce
+    }
+
+    pub fn issued_at(&self) -> Epoch {
+        self.issued_at
+    }
+
+    /// Context of the [Proof2PK]s bound to the challenge.
+    fn context(&self) -> Vec<u8> {
+        let mut context = Vec::new();
+        append_compressed(&mut context, self);
+        context
+    }
+}
+
+/// Master PRF key `prv` of a user. ElIDs are derived from the per-epoch keys
+/// `prv_e = H(prv, epoch)`, so disclosing the ElIDs of some epochs keeps the ElIDs of all other
+/// epochs unlinkable. [Key-bound](Self::key_bound) keys use `prv` in every epoch instead.
+#[derive(Clone)]
+pub struct UserKeys {
+    pub(crate) prv: Scalar,
+    pub(crate) bound: bool,
+}
+
+impl UserKeys {
+    pub fn new(prv: Scalar) -> Self {
+        Self { prv, bound: false }
+    }
+
+    /// The keys with `prv` itself as the key of every epoch, for a token that commits to `prv`,
+    /// see [spirit_register_key_bound]. Their trace reports carry a [KeyBindingProof] that the
+    /// ElIDs derive from the committed key, but the PRF public key of the report is the same in
+    /// every epoch, so a report links all ElIDs of the user, not only the disclosed ones.
+    pub fn key_bound(&self) -> Self {
+        Self {
+            prv: self.prv,
+            bound: true,
+        }
+    }
+
+    pub fn random(mut rng: impl RngCore) -> Self {
+        Self::new(Scalar::rand(&mut rng))
+    }
+
+    pub fn epoch_key(&self, epoch: Epoch) -> Scalar {
+        if self.bound {
+            return self.prv;
+        }
+        let mut storage = Vec::new();
+        append_compressed(&mut storage, &self.prv);
+
+        let digest = Sha256::new()
+            .chain_update(EPOCH_KEY_DST)
+            .chain_update(storage)
+            .chain_update(epoch.to_le_bytes())
+            .finalize();
+        Scalar::from_le_bytes_mod_order(&digest)
+    }
+
+    /// Keys of the device `device_id` with the master key hashed from the [prf] evaluation
+    /// `H(DeviceDerivation, device_id)^prv` in `domain`, so that every device of the user
+    /// broadcasts its own ElIDs and a compromised device does not reveal the ElIDs of the
+    /// others. [spirit_diagnosis_with_devices] discloses the ElIDs of the devices from the
+    /// master key. The keys of a device are never key-bound.
+    pub fn device_keys(&self, domain: &DomainConfig, device_id: DeviceId) -> UserKeys {
+        let purpose = PrfPurpose::DeviceDerivation;
+        let value = prf(domain, &self.prv, purpose, &device_id.to_le_bytes());
+        let digest = Sha256::new()
+            .chain_update(purpose.tag())
+            .chain_update(value.to_bytes())
+            .finalize();
+        UserKeys::new(Scalar::from_le_bytes_mod_order(&digest))
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl Zeroize for UserKeys {
+    fn zeroize(&mut self) {
+        self.prv.zeroize();
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl Drop for UserKeys {
+    fn drop(&mut self) {
+        self.zeroize();
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl ZeroizeOnDrop for UserKeys {}
+
+/// Long-term secrets of a user: the identity `id_u` registered with the issuers and the PRF
+/// keys. With the `zeroize` feature, both are cleared when the value is dropped.
+#[derive(Clone)]
+pub struct UserSecret {
+    pub(crate) id_u: Scalar,
+    pub(crate) keys: UserKeys,
+}
+
+impl UserSecret {
+    pub fn new(id_u: Scalar, prv: Scalar) -> Self {
+        Self {
+            id_u,
+            keys: UserKeys::new(prv),
+        }
+    }
+
+    pub fn random(mut rng: impl RngCore) -> Self {
+        Self::new(Scalar::rand(&mut rng), Scalar::rand(&mut rng))
+    }
+
+    pub fn keys(&self) -> &UserKeys {
+        &self.keys
+    }
+
+    /// The master PRF key of the device `device_id` in `domain`, see [UserKeys::device_keys].
+    pub fn derive_device_key(&self, domain: &DomainConfig, device_id: DeviceId) -> Scalar {
+        self.keys.device_keys(domain, device_id).prv
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl Zeroize for UserSecret {
+    fn zeroize(&mut self) {
+        self.id_u.zeroize();
+        self.keys.zeroize();
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl Drop for UserSecret {
+    fn drop(&mut self) {
+        self.zeroize();
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl ZeroizeOnDrop for UserSecret {}
+
+/// Keys that a user replaced with [spirit_rotate], kept for the epochs before the
+/// [rotation](KeyRotation::epoch) in which the user broadcast with them, with the registrar's
+/// record of the rotation. A diagnosis of the new token with [spirit_diagnosis] discloses the
+/// ElIDs of those epochs under the previous keys, and carries the record for the verifiers.
+#[derive(Clone)]
+pub struct PreviousKeys {
+    pub(crate) keys: UserKeys,
+    pub(crate) record: KeyRotation,
+}
+
+impl PreviousKeys {
+    pub fn new(keys: UserKeys, record: KeyRotation) -> Self {
+        Self { keys, record }
+    }
+
+    /// First epoch of the keys that replaced the previous ones.
+    pub fn until(&self) -> Epoch {
+        self.record.epoch
+    }
+
+    /// The registrar's record of the rotation.
+    pub fn record(&self) -> &KeyRotation {
+        &self.record
+    }
+}
+
+// the master keys of `epoch`: the previous keys before their rotation, `keys` after it
+fn master_keys<'a>(
+    keys: &'a UserKeys,
+    previous: Option<&'a PreviousKeys>,
+    epoch: Epoch,
+) -> &'a UserKeys {
+    match previous {
+        Some(previous) if epoch < previous.until() => &previous.keys,
+        _ => keys,
+    }
+}
+
+/// ElID disclosed for broadcast `index` of `epoch`, or for the check-in into slot `index` of a
+/// [venue](Self::venue) in `epoch`, together with a proof that it was derived
+/// from the committed epoch key by the holder of the token commitment, and a [PrfProof]
+/// against the epoch's PRF public key.
+#[derive(CanonicalSerialize, CanonicalDeserialize)]
+pub struct Disclosure {
+    pub epoch: Epoch,
+    pub index: Interval,
+    /// The device whose [subkeys](UserKeys::device_keys) derive the ElID, or `None` for the
+    /// user's master keys.
+    pub device: Option<DeviceId>,
+    /// The venue of a check-in, see [spirit_checkin], or `None` for a broadcast.
+    pub venue: Option<Vec<u8>>,
+    pub el_id: ElID,
+    pub proof: Proof2PK,
+    pub prf_proof: PrfProof,
+}
+
+impl Disclosure {
+    /// The PRF base that the ElID is derived from.
+    fn base(&self, domain: &DomainConfig) -> G1G2 {
+        match &self.venue {
+            None => prf_base(domain, self.epoch, self.index),
+            Some(venue_id) => venue_base(domain, venue_id, self.index),
+        }
+    }
+}
+
+/// Tag `H(token, diagnosis_epoch)` that a [DiagnosisAuthorization] signs.
+fn authorization_tag(domain: &DomainConfig, token: &Token, diagnosis_epoch: Epoch) -> Commitment {
+    let mut msg = AUTHORIZATION_SEED.to_vec();
+    msg.extend_from_slice(&token.id());
+    msg.extend_from_slice(&diagnosis_epoch.to_le_bytes());
+    Commitment(domain.hash(&msg, &domain.registration_dst))
+}
+
+// parameters of the health authority's signatures, which sign at the first index
+fn authorization_parameters() -> tsw::PublicParameters {
+    tsw::PublicParameters::new(1)
+}
+
+/// Authorization tag of a token and diagnosis epoch blinded by the user, which the health
+/// authority signs with [authorize_diagnosis] after a positive test. The blinded tag is
+/// uniformly random, so the health authority cannot link it to the report that carries the
+/// authorization later.
+#[derive(Debug, Clone, PartialEq, Eq, CanonicalSerialize, CanonicalDeserialize)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BlindedTag(pub(crate) Commitment);
+
+/// Blinding of a [BlindedTag], which the user keeps to [unblind](unblind_authorization) the
+/// health authority's signature.
+pub struct AuthorizationBlinding {
+    tag: Commitment,
+    beta: Scalar,
+    diagnosis_epoch: Epoch,
+}
+
+/// Health authority's signature on the [authorization tag](BlindedTag) of a token for the
+/// diagnosis in `diagnosis_epoch`. A deployment with a
+/// [diagnosis authority](DomainConfig::diagnosis_authority) only accepts trace reports of the
+/// token whose [report epoch](TraceReport::report_epoch) is `diagnosis_epoch`.
+#[derive(Debug, Clone, PartialEq, Eq, CanonicalSerialize, CanonicalDeserialize)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DiagnosisAuthorization {
+    pub diagnosis_epoch: Epoch,
+    pub(crate) signature: Signature,
+}
+
+/// User side of an authorization: blinds the authorization tag of `token` for the diagnosis in
+/// `diagnosis_epoch` as `H(token, diagnosis_epoch) g^beta`.
+pub fn blind_authorization_tag(
+    domain: &DomainConfig,
+    token: &Token,
+    diagnosis_epoch: Epoch,
+    rng: &mut (impl RngCore + CryptoRng),
+) -> (BlindedTag, AuthorizationBlinding) {
+    let tag = authorization_tag(domain, token, diagnosis_epoch);
+    let beta = Scalar::rand(rng);
+    let blinded = BlindedTag(Commitment(&tag.0 + &get_parameters().g * beta));
+    let blinding = AuthorizationBlinding {
+        tag,
+        beta,
+        diagnosis_epoch,
+    };
+    (blinded, blinding)
+}
+
+/// Health authority side of an authorization: signs the `blinded_tag` of a user with a positive
+/// test under the health authority's key `ha_sk`.
+pub fn authorize_diagnosis(ha_sk: &SecretKey, blinded_tag: &BlindedTag) -> Signature {
+    ha_sk.sign_pedersen_commitment(&blinded_tag.0, 0, &authorization_parameters())
+}
+
+/// Removes the blinding from the health authority's `blind_signature` and checks the
+/// authorization under the health authority's key `ha_pk`. Fails with
+/// [SpiritError::InvalidAuthorization] if the signature is not on the blinded tag of `blinding`
+/// under `ha_pk`.
+pub fn unblind_authorization(
+    blinding: AuthorizationBlinding,
+    blind_signature: &Signature,
+    ha_pk: &PublicKey,
+) -> Result<DiagnosisAuthorization, SpiritError> {
+    let signature = blind_signature.clone() - &(ha_pk * blinding.beta);
+    ha_pk
+        .verify_pedersen_commitment(&blinding.tag, 0, &signature, &authorization_parameters())
+        .map_err(|_| SpiritError::InvalidAuthorization)?;
+    Ok(DiagnosisAuthorization {
+        diagnosis_epoch: blinding.diagnosis_epoch,
+        signature,
+    })
+}
+
+/// Leaf of the broadcast `el_id` of `epoch` and `index` in a [BroadcastBatch], prefixed like the
+/// leaves of a [MerkleRegistry](crate::registry::MerkleRegistry).
+fn broadcast_leaf(epoch: Epoch, index: Interval, el_id: &ElID) -> [u8; 32] {
+    Sha256::new()
+        .chain_update([0u8])
+        .chain_update(BROADCAST_LEAF_DST)
+        .chain_update(epoch.to_le_bytes())
+        .chain_update(index.to_le_bytes())
+        .chain_update(el_id.to_bytes())
+        .finalize()
+        .into()
+}
+
+/// The registrar's timestamped receipt for the Merkle root of a device's [BroadcastBatch] of
+/// `size` broadcasts, signed in `epoch` with the registrar's key `key_id`, see
+/// [RegistrarKeypair::anchor_broadcasts](crate::registry::RegistrarKeypair::anchor_broadcasts).
+#[derive(Debug, Clone, PartialEq, Eq, CanonicalSerialize, CanonicalDeserialize)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BroadcastAnchor {
+    pub root: [u8; 32],
+    pub size: usize,
+    /// Epoch in which the registrar anchored the root.
+    pub epoch: Epoch,
+    pub key_id: KeyId,
+    pub(crate) signature: Signature,
+}
+
+/// Authentication path of a disclosed ElID in the batch of one of the anchors of a
+/// [BroadcastAttestation], like a [MembershipProof](crate::registry::MembershipProof).
+#[derive(Debug, Clone, PartialEq, Eq, CanonicalSerialize, CanonicalDeserialize)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AnchorPath {
+    /// Index of the anchor in [BroadcastAttestation::anchors].
+    pub anchor: usize,
+    /// Index of the ElID's leaf in the anchored batch.
+    pub index: usize,
+    pub siblings: Vec<[u8; 32]>,
+}
+
+/// Proof that the ElIDs of a trace report were broadcast before the diagnosis: the anchors of
+/// the batches they were broadcast in, and a path for every disclosure of the report, in the
+/// order of the disclosures. See [attest_broadcasts].
+#[derive(Debug, Clone, PartialEq, Eq, CanonicalSerialize, CanonicalDeserialize)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BroadcastAttestation {
+    pub anchors: Vec<BroadcastAnchor>,
+    pub paths: Vec<AnchorPath>,
+}
+
+/// The broadcasts of a device since its last anchor. The device periodically has the registrar
+/// anchor the batch's [root](Self::root), with
+/// [RegistrarKeypair::anchor_broadcasts](crate::registry::RegistrarKeypair::anchor_broadcasts),
+/// and keeps the batch and its anchor to attest its broadcasts when diagnosed.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Default)]
+pub struct BroadcastBatch {
+    leaves: Vec<[u8; 32]>,
+    positions: HashMap<CompactElId, usize>,
+}
+
+#[cfg(feature = "std")]
+impl BroadcastBatch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the broadcast `el_id` of `epoch` and `index`, e.g. of [spirit_broadcast].
+    pub fn push(&mut self, epoch: Epoch, index: Interval, el_id: &ElID) {
+        self.positions
+            .insert(CompactElId::new(el_id), self.leaves.len());
+        self.leaves.push(broadcast_leaf(epoch, index, el_id));
+    }
+
+    pub fn len(&self) -> usize {
+        self.leaves.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.leaves.is_empty()
+    }
+
+    /// Merkle root over the broadcasts in the order they were added.
+    pub fn root(&self) -> [u8; 32] {
+        merkle_levels(self.leaves.clone())
+            .last()
+            .and_then(|level| level.first())
+            .copied()
+            .unwrap_or_default()
+    }
+
+    fn path(&self, el_id: &ElID) -> Option<MembershipProof> {
+        let leaf = *self.positions.get(&CompactElId::new(el_id))?;
+        Some(merkle_path(&merkle_levels(self.leaves.clone()), leaf))
+    }
+}
+
+#[cfg(feature = "std")]
+impl BroadcastAnchor {
+    /// Checks the registrar's signature on the anchor with its key [Self::key_id], which has
+    /// to be valid in the epoch of the anchor.
+    pub fn verify(&self, registrar: &(impl RegistrarKeys + ?Sized)) -> bool {
+        registrar
+            .verifying_key(self.key_id, self.epoch)
+            .is_ok_and(|registrar| {
+                verify_registrar_signature(
+                    registrar,
+                    BROADCAST_ANCHOR_DST,
+                    self.epoch,
+                    &anchor_message(&self.root, self.size, self.key_id),
+                    &self.signature,
+                )
+            })
+    }
+}
+
+/// Attests the ElIDs of the disclosures of `report` with the device's `batches` and their
+/// anchors, for deployments with an [anchoring registrar](DomainConfig::anchoring_registrar).
+/// Every ElID is proven in the first batch that holds it and was anchored before the report's
+/// epoch. Reports are made without an attestation, and users set it before submitting a report.
+/// Fails with [SpiritError::UnanchoredBroadcast] for the first ElID that is in no such batch.
+#[cfg(feature = "std")]
+pub fn attest_broadcasts(
+    report: &TraceReport,
+    batches: &[(BroadcastBatch, BroadcastAnchor)],
+) -> Result<BroadcastAttestation, SpiritError> {
+    let mut anchors = Vec::new();
+    let mut anchor_indices = HashMap::new();
+    let paths = report
+        .disclosures
+        .iter()
+        .map(|disclosure| {
+            let (batch, path) = batches
+                .iter()
+                .enumerate()
+                .filter(|(_, (_, anchor))| anchor.epoch < report.report_epoch)
+                .find_map(|(batch, (broadcasts, _))| {
+                    Some((batch, broadcasts.path(&disclosure.el_id)?))
+                })
+                .ok_or(SpiritError::UnanchoredBroadcast {
+                    epoch: disclosure.epoch,
+                    index: disclosure.index,
+                })?;
+            let anchor = *anchor_indices.entry(batch).or_insert_with(|| {
+                anchors.push(batches[batch].1.clone());
+                anchors.len() - 1
+            });
+            Ok(AnchorPath {
+                anchor,
+                index: path.index,
+                siblings: path.siblings,
+            })
+        })
+        .collect::<Result<_, SpiritError>>()?;
+    Ok(BroadcastAttestation { anchors, paths })
+}
+
+/// Trace report of a diagnosed user. It carries the user's token, but neither the identity
+/// `id_u` nor the [StRG] opening the token: `id_u` only enters the report as the exponent of the
+/// [Nullifier] and as a witness of its proofs.
+#[derive(CanonicalSerialize, CanonicalDeserialize)]
+pub struct TraceReport {
+    /// The `app_id` of the [DomainConfig] of the deployment the token is registered in, by which
+    /// a federated verifier selects the deployment, see
+    /// [FederationRegistry](crate::federation::FederationRegistry).
+    pub deployment: Vec<u8>,
+    /// The registrar of a sharded registry that registered the token, by which a verifier
+    /// selects the shard to check the token in, see [RegistryView::Sharded]. Reports are made
+    /// for registrar 0, and users of a sharded deployment set their registrar before submitting
+    /// a report. It is not bound to the proofs: a report naming another registrar fails the
+    /// check of that registrar's shard.
+    pub registrar: RegistrarId,
+    pub token: Token,
+    /// Commitments to the keys of the disclosed epochs.
+    pub cm_prv: BTreeMap<Epoch, Commitment>,
+    /// PRF public keys of the disclosed epochs. Together with the [Proof2PK]s, a valid
+    /// [PrfProof] implies that the public key belongs to the committed epoch key.
+    pub prf_pk: BTreeMap<Epoch, G1G2>,
+    /// Like `cm_prv` and `prf_pk`, for the epoch keys of the devices of the disclosures with a
+    /// [Disclosure::device].
+    pub device_keys: BTreeMap<(DeviceId, Epoch), (Commitment, G1G2)>,
+    pub disclosures: Vec<Disclosure>,
+    /// Epoch of the diagnosis, in which the report is made. The nullifier proof is bound to it,
+    /// and verifiers only accept the report within the
+    /// [reporting window](DomainConfig::reporting_window) after it.
+    pub report_epoch: Epoch,
+    /// The verifier's challenge that all proofs of the report are bound to.
+    pub challenge: ReportChallenge,
+    /// The same for all reports of the user in `report_epoch` and unlinkable across epochs.
+    /// Verifiers accept one report per nullifier, so a report cannot be replayed or submitted
+    /// twice.
+    pub nullifier: Nullifier,
+    pub nullifier_proof: NullifierProof,
+    /// The tag of the token's show under a k-show policy, see [spirit_diagnosis_with_show].
+    pub show: Option<ShowTag>,
+    /// For a token with attributes, the attributes the user chose to disclose, and the
+    /// commitment to the identity the report's proofs are about.
+    pub attributes: Option<DisclosureProof>,
+    /// For [key-bound](UserKeys::key_bound) keys, the proof that the PRF public keys are of the
+    /// key the token commits to.
+    pub key_binding: Option<KeyBindingProof>,
+    /// The health authority's authorization of the diagnosis, for deployments with a
+    /// [diagnosis authority](DomainConfig::diagnosis_authority). Reports are made without one,
+    /// and users set it before submitting a report. It is bound to the token and the report
+    /// epoch, not to the proofs.
+    pub authorization: Option<DiagnosisAuthorization>,
+    /// The proof that the disclosed ElIDs were broadcast before the diagnosis, for deployments
+    /// with an [anchoring registrar](DomainConfig::anchoring_registrar). Like the
+    /// authorization, it is set by the user, see [attest_broadcasts].
+    pub attestation: Option<BroadcastAttestation>,
+    /// For a report of a rotated token that discloses ElIDs under the previous keys, the
+    /// registrar's record of the rotation, which verifiers check against the
+    /// [rotation registrar](DomainConfig::rotation_registrar).
+    pub rotation: Option<KeyRotation>,
+}
+
+impl TraceReport {
+    /// Size in bytes of the compressed encoding of the report, in which it is uploaded.
+    pub fn encoded_size(&self) -> usize {
+        self.compressed_size()
+    }
+
+    /// The commitment `g^r u^a` to the user's identity that the proofs of the report are about:
+    /// the token's [identity commitment](Token::identity_commitment), or the one of the
+    /// [DisclosureProof] for a token with attributes.
+    pub fn identity_commitment(&self, domain: &DomainConfig) -> Commitment {
+        match &self.attributes {
+            Some(proof) => proof.identity().clone(),
+            None => self.token.identity_commitment(domain),
+        }
+    }
+
+    /// The disclosed attributes by their index, see [spirit_diagnosis]. They are only authentic
+    /// if [spirit_verify] accepts the report.
+    pub fn disclosed_attributes(&self) -> Option<&BTreeMap<usize, Scalar>> {
+        self.attributes.as_ref().map(DisclosureProof::revealed)
+    }
+
+    /// The commitment to the epoch key and the PRF public key of `disclosure`, of the master
+    /// keys or of its device.
+    fn disclosure_keys(&self, disclosure: &Disclosure) -> Option<(&Commitment, &G1G2)> {
+        match disclosure.device {
+            None => self
+                .cm_prv
+                .get(&disclosure.epoch)
+                .zip(self.prf_pk.get(&disclosure.epoch)),
+            Some(device) => self
+                .device_keys
+                .get(&(device, disclosure.epoch))
+                .map(|(cm_prv, pk)| (cm_prv, pk)),
+        }
+    }
+}
+
+/// A deployment created by [spirit_setup].
+#[cfg(feature = "std")]
+pub struct SetupOutput {
+    /// Public parameters of the issuers' committee, published to users and verifiers.
+    pub pp: tACTPublicParameters,
+    /// The issuers with their secret key shares, each handed to the operator of the issuer.
+    pub issuers: Vec<Issuer>,
+    /// The health authority's key, to which users encrypt their encounter secrets.
+    pub pk_ha: EncryptionKey,
+    /// The health authority's secret key, see [decrypt_encounter].
+    pub sk_ha: DecryptionKey,
+    /// The registrar's token registry, empty.
+    pub t_rgstr: HashSet<Token>,
+    pub domain: DomainConfig,
+}
+
+/// Free-function form of [SpiritSystem::setup_with_domain], kept for backward compatibility.
+/// The deployment's [DomainConfig] is created from `app_id` with the default tags, and all
+/// randomness is drawn from `rng`. Besides the issuers, it generates the health authority's key
+/// pair, to whose [EncryptionKey] the users encrypt their encounter secrets, see
+/// [spirit_broadcast]. Fails unless `3 <= t < num_issuers` and `t <= n`, like
+/// [SpiritSystem::setup].
+#[cfg(feature = "std")]
+pub fn spirit_setup(
+    t: usize,
+    n: usize,
+    num_issuers: usize,
+    app_id: &[u8],
+    rng: &mut (impl RngCore + CryptoRng),
+) -> Result<SetupOutput, SpiritError> {
+    spirit_setup_with_attributes(t, n, num_issuers, 1, app_id, rng)
+}
+
+/// Like [spirit_setup], but for tokens that carry `num_attributes` attributes, see
+/// [spirit_register_with_attributes].
+#[cfg(feature = "std")]
+pub fn spirit_setup_with_attributes(
+    t: usize,
+    n: usize,
+    num_issuers: usize,
+    num_attributes: usize,
+    app_id: &[u8],
+    rng: &mut (impl RngCore + CryptoRng),
+) -> Result<SetupOutput, SpiritError> {
+    SpiritConfig::new()
+        .threshold(t)
+        .committee_size(num_issuers)
+        .request_size(n)
+        .attribute_count(num_attributes)
+        .domain(DomainConfig::new(app_id.to_vec()))
+        .setup(rng)
+        .map_err(SpiritError::from)
+}
+
+/// [spirit_setup] with randomness from [OsRng].
+#[cfg(feature = "std")]
+pub fn spirit_setup_osrng(
+    t: usize,
+    n: usize,
+    num_issuers: usize,
+    app_id: &[u8],
+) -> Result<SetupOutput, SpiritError> {
+    spirit_setup(t, n, num_issuers, app_id, &mut OsRng)
+}
+
+/// The tACT public parameters of a deployment set up by [spirit_setup] with `t` and `n`, rebuilt
+/// on a user's device from the issuers' public key `pk`. Users do not know the number of
+/// issuers, so any number above `t` is accepted.
+pub fn spirit_client_parameters(
+    domain: &DomainConfig,
+    pk: PublicKey,
+    n: usize,
+    t: usize,
+) -> Result<tACTPublicParameters, SpiritError> {
+    spirit_client_parameters_with_attributes(domain, pk, n, t, 1)
+}
+
+/// [spirit_client_parameters] of a deployment set up by [spirit_setup_with_attributes].
+pub fn spirit_client_parameters_with_attributes(
+    domain: &DomainConfig,
+    pk: PublicKey,
+    n: usize,
+    t: usize,
+    num_attributes: usize,
+) -> Result<tACTPublicParameters, SpiritError> {
+    check_parameters(t + 1, n, t, t.saturating_sub(1)).map_err(SpiritError::SetupFailed)?;
+    if num_attributes == 0 {
+        return Err(SpiritError::SetupFailed(AtACTError::InvalidParameters));
+    }
+    let mut pp = tACTPublicParameters::new(pk, n, t, t - 1, num_attributes);
+    pp.dst = domain.tag(&domain.registration_dst);
+    pp.hash_to_curve = domain.hash_to_curve;
+    pp.hash_backend = domain.hash_backend;
+    Ok(pp)
+}
+
+#[cfg(feature = "std")]
+fn issue_blind_token(
+    blind_request: &BlindRequest,
+    issuer_index: usize,
+    issuer: &Issuer,
+    pp: &tACTPublicParameters,
+) -> Result<Vec<BlindToken>, SpiritError> {
+    tissue(blind_request, issuer, pp).map_err(|source| SpiritError::IssuerRejected {
+        issuer_index,
+        source,
+    })
+}
+
+#[cfg(feature = "std")]
+#[cfg_attr(feature = "rayon", allow(dead_code))]
+fn issue_blind_tokens_sequential(
+    blind_request: &BlindRequest,
+    issuers: &[Issuer],
+    pp: &tACTPublicParameters,
+) -> Result<Vec<Vec<BlindToken>>, SpiritError> {
+    issuers
+        .iter()
+        .enumerate()
+        .map(|(issuer_index, issuer)| issue_blind_token(blind_request, issuer_index, issuer, pp))
+        .collect()
+}
+
+#[cfg(feature = "rayon")]
+fn issue_blind_tokens_parallel(
+    blind_request: &BlindRequest,
+    issuers: &[Issuer],
+    pp: &tACTPublicParameters,
+) -> Result<Vec<Vec<BlindToken>>, SpiritError> {
+    use rayon::iter::{IndexedParallelIterator, IntoParallelRefIterator, ParallelIterator};
+
+    // collecting an indexed parallel iterator preserves the issuer order
+    issuers
+        .par_iter()
+        .enumerate()
+        .map(|(issuer_index, issuer)| issue_blind_token(blind_request, issuer_index, issuer, pp))
+        .collect()
+}
+
+/// Requests blind tokens from the first `pp.t` issuers. With the `rayon` feature, the issuers
+/// are queried in parallel.
+#[cfg(feature = "std")]
+fn issue_blind_tokens(
+    blind_request: &BlindRequest,
+    issuers: &[Issuer],
+    pp: &tACTPublicParameters,
+) -> Result<Vec<Vec<BlindToken>>, SpiritError> {
+    if issuers.len() < pp.t {
+        return Err(SpiritError::NotEnoughIssuers {
+            required: pp.t,
+            available: issuers.len(),
+        });
+    }
+
+    #[cfg(feature = "rayon")]
+    {
+        issue_blind_tokens_parallel(blind_request, &issuers[..pp.t], pp)
+    }
+    #[cfg(not(feature = "rayon"))]
+    {
+        issue_blind_tokens_sequential(blind_request, &issuers[..pp.t], pp)
+    }
+}
+
+/// Requests blind tokens like [issue_blind_tokens] and checks the tokens of every issuer against
+/// its key in [tACTPublicParameters::issuer_keys], if known. The tokens of a misbehaving issuer
+/// are skipped and the next issuer after the first `pp.t` is asked instead. Returns the tokens of
+/// `pp.t` issuers with their indices, or the indices of the misbehaving issuers if not enough
+/// others are available.
+#[cfg(feature = "std")]
+pub(crate) fn issue_verified_blind_tokens(
+    blind_request: &BlindRequest,
+    issuers: &[Issuer],
+    pp: &tACTPublicParameters,
+) -> Result<Vec<(usize, Vec<BlindToken>)>, SpiritError> {
+    let first = issue_blind_tokens(blind_request, issuers, pp)?;
+    let replacements = issuers
+        .iter()
+        .enumerate()
+        .skip(pp.t)
+        .map(|(index, issuer)| {
+            issue_blind_token(blind_request, index, issuer, pp).map(|tokens| (index, tokens))
+        });
+    verified_blind_tokens(
+        blind_request,
+        pp,
+        first.into_iter().enumerate().map(Ok).chain(replacements),
+    )
+}
+
+/// Like [issue_verified_blind_tokens], but asks the chosen issuers of `indices`, in order, and
+/// no others.
+#[cfg(feature = "std")]
+fn issue_chosen_blind_tokens(
+    blind_request: &BlindRequest,
+    issuers: &[Issuer],
+    indices: &[usize],
+    pp: &tACTPublicParameters,
+) -> Result<Vec<(usize, Vec<BlindToken>)>, SpiritError> {
+    if let Some(issuer_index) = indices
+        .iter()
+        .copied()
+        .find(|index| *index >= issuers.len())
+    {
+        return Err(SpiritError::InvalidIssuerIndex {
+            issuer_index,
+            num_issuers: issuers.len(),
+        });
+    }
+    check_distinct_issuers(indices.iter().copied())?;
+    if indices.len() < pp.t {
+        return Err(SpiritError::NotEnoughIssuers {
+            required: pp.t,
+            available: indices.len(),
+        });
+    }
+
+    let responses = indices.iter().map(|index| {
+        issue_blind_token(blind_request, *index, &issuers[*index], pp)
+            .map(|tokens| (*index, tokens))
+    });
+    verified_blind_tokens(blind_request, pp, responses)
+}
+
+/// Like [issue_verified_blind_tokens], but asks the committee of `epoch` only if the domain has a
+/// [committee beacon](DomainConfig::committee_beacon).
+#[cfg(feature = "std")]
+fn issue_committee_blind_tokens(
+    domain: &DomainConfig,
+    epoch: Epoch,
+    blind_request: &BlindRequest,
+    issuers: &[Issuer],
+    pp: &tACTPublicParameters,
+) -> Result<Vec<(usize, Vec<BlindToken>)>, SpiritError> {
+    match &domain.committee_beacon {
+        Some(beacon) => {
+            let committee = select_committee(beacon, epoch, issuers.len(), pp.t);
+            issue_chosen_blind_tokens(blind_request, issuers, &committee, pp)
+        }
+        None => issue_verified_blind_tokens(blind_request, issuers, pp),
+    }
+}
+
+/// Collects `responses` until `pp.t` of them pass [verify_blind_token] against the issuer's key
+/// in [tACTPublicParameters::issuer_keys], if known, and fails with the indices of the others if
+/// the responses run out first.
+#[cfg(feature = "std")]
+fn verified_blind_tokens(
+    blind_request: &BlindRequest,
+    pp: &tACTPublicParameters,
+    mut responses: impl Iterator<Item = Result<(usize, Vec<BlindToken>), SpiritError>>,
+) -> Result<Vec<(usize, Vec<BlindToken>)>, SpiritError> {
+    let mut misbehaving = Vec::new();
+    let mut blind_tokens = Vec::with_capacity(pp.t);
+    while blind_tokens.len() < pp.t {
+        let Some(response) = responses.next() else {
+            return Err(SpiritError::MisbehavingIssuers {
+                issuer_indices: misbehaving,
+            });
+        };
+        let (index, tokens) = response?;
+        let is_valid = pp
+            .issuer_keys
+            .get(index)
+            .is_none_or(|issuer_pk| verify_blind_token(&tokens, issuer_pk, blind_request, pp));
+        if is_valid {
+            blind_tokens.push((index, tokens));
+        } else {
+            misbehaving.push(index);
+        }
+    }
+    Ok(blind_tokens)
+}
+
+/// Fails with [SpiritError::HashToCurveMismatch] or [SpiritError::HashBackendMismatch] if `pp`
+/// are the parameters of a deployment of another hash-to-curve suite or hash function than
+/// `domain`, whose tokens and proofs would not verify.
+pub(crate) fn check_hash_to_curve(
+    domain: &DomainConfig,
+    pp: &tACTPublicParameters,
+) -> Result<(), SpiritError> {
+    if domain.hash_to_curve != pp.hash_to_curve {
+        return Err(SpiritError::HashToCurveMismatch {
+            domain: domain.hash_to_curve,
+            parameters: pp.hash_to_curve,
+        });
+    }
+    if domain.hash_backend != pp.hash_backend {
+        return Err(SpiritError::HashBackendMismatch {
+            domain: domain.hash_backend,
+            parameters: pp.hash_backend,
+        });
+    }
+    Ok(())
+}
+
+/// Fails with [SpiritError::DuplicateIssuer] for the first repeated index of `indices`, whose
+/// shares could not be interpolated.
+fn check_distinct_issuers(indices: impl Iterator<Item = usize>) -> Result<(), SpiritError> {
+    let mut seen = BTreeSet::new();
+    for issuer_index in indices {
+        if !seen.insert(issuer_index) {
+            return Err(SpiritError::DuplicateIssuer { issuer_index });
+        }
+    }
+    Ok(())
+}
+
+/// Registers the user `id_u` of `user` with a token that expires at the start of epoch
+/// `expiry`. With the `zeroize` feature, the blinding randomness of the token request is
+/// cleared before returning.
+///
+/// The returned [StRG] opens the token's commitment to `id_u`, so it stays on the user's device
+/// like the [UserSecret]. Only the token is ever sent, see [TraceReport].
+///
+/// Registrations are not checked against earlier ones of the same identity, which
+/// [SpiritSystem::register] does with an [IdentityIndex].
+///
+/// The blind tokens of every issuer are checked before they are aggregated, see
+/// [verify_blind_token]. A misbehaving issuer among the first `pp.t` is replaced by the next one,
+/// and registration fails with [SpiritError::MisbehavingIssuers] only if fewer than `pp.t`
+/// issuers behave.
+#[cfg(feature = "std")]
+pub fn spirit_register(
+    domain: &DomainConfig,
+    user: &UserSecret,
+    issuers: &[Issuer],
+    pp: &tACTPublicParameters,
+    expiry: Epoch,
+    t_rgstr: &mut impl TokenRegistry,
+    rng: &mut (impl RngCore + CryptoRng),
+) -> Result<(Token, StRG), SpiritError> {
+    spirit_register_with_attributes(domain, user, &[], issuers, pp, expiry, t_rgstr, rng)
+}
+
+/// Like [spirit_register], for a registrar in audit mode that knows the identity `id_u` of
+/// every user it registers: the user proves that the blind request commits to `id_u` with a
+/// [PendingRegistration::audit_proof], see [spirit_complete_registration_audited].
+#[cfg(feature = "std")]
+pub fn spirit_register_audited(
+    domain: &DomainConfig,
+    user: &UserSecret,
+    issuers: &[Issuer],
+    pp: &tACTPublicParameters,
+    expiry: Epoch,
+    t_rgstr: &mut impl TokenRegistry,
+    rng: &mut (impl RngCore + CryptoRng),
+) -> Result<(Token, StRG), SpiritError> {
+    let (blind_request, pending) = spirit_register_begin(domain, &user.id_u, pp, expiry, rng)?;
+    let audit_proof = pending.audit_proof(rng);
+    let blind_tokens = issue_verified_blind_tokens(&blind_request, issuers, pp)?;
+    let (ppu, token, token_proof) =
+        spirit_register_finish_from(domain, pending, &blind_tokens, pp, rng)?;
+    spirit_complete_registration_audited(
+        domain,
+        &blind_request,
+        &user.id_u,
+        &audit_proof,
+        &token,
+        &token_proof,
+        pp,
+        expiry,
+        t_rgstr,
+    )?;
+
+    Ok(ppu)
+}
+
+/// Like [spirit_register], for a deployment whose tokens also commit to the user's master PRF
+/// key, so that the trace reports of the [key-bound](UserKeys::key_bound) keys prove that their
+/// ElIDs derive from the registered key, see [DomainConfig::require_key_binding]. The
+/// parameters need an attribute after the identity, see [spirit_setup_with_attributes], which
+/// holds the key. Neither the issuers nor the registrar learn the key.
+#[cfg(feature = "std")]
+pub fn spirit_register_key_bound(
+    domain: &DomainConfig,
+    user: &UserSecret,
+    issuers: &[Issuer],
+    pp: &tACTPublicParameters,
+    expiry: Epoch,
+    t_rgstr: &mut impl TokenRegistry,
+    rng: &mut (impl RngCore + CryptoRng),
+) -> Result<(Token, StRG), SpiritError> {
+    let (blind_request, pending, key_claim) =
+        spirit_register_begin_key_bound(domain, &user.id_u, &user.keys, pp, expiry, rng)?;
+    let blind_tokens = issue_verified_blind_tokens(&blind_request, issuers, pp)?;
+    let (ppu, token, token_proof) =
+        spirit_register_finish_from(domain, pending, &blind_tokens, pp, rng)?;
+    spirit_complete_registration_key_bound(
+        domain,
+        &blind_request,
+        &key_claim,
+        &token,
+        &token_proof,
+        pp,
+        expiry,
+        t_rgstr,
+    )?;
+
+    Ok(ppu)
+}
+
+/// Like [spirit_register], but the issuers' signature also covers the `attributes`, e.g. a
+/// jurisdiction code, which are committed to after the user's identity and disclosed to the
+/// issuers and the registrar. The registered token commits to the identity only, so trace
+/// reports do not reveal the attributes.
+#[cfg(feature = "std")]
+#[allow(clippy::too_many_arguments)]
+pub fn spirit_register_with_attributes(
+    domain: &DomainConfig,
+    user: &UserSecret,
+    attributes: &[Scalar],
+    issuers: &[Issuer],
+    pp: &tACTPublicParameters,
+    expiry: Epoch,
+    t_rgstr: &mut impl TokenRegistry,
+    rng: &mut (impl RngCore + CryptoRng),
+) -> Result<(Token, StRG), SpiritError> {
+    register_with(
+        domain,
+        user,
+        attributes,
+        pp,
+        expiry,
+        t_rgstr,
+        rng,
+        |blind_request| issue_verified_blind_tokens(blind_request, issuers, pp),
+    )
+}
+
+/// Like [spirit_register], but requests the blind tokens from the issuers of `indices` only, e.g.
+/// the quorum that is available, instead of the first `pp.t`. The indices have to be distinct,
+/// and at least `pp.t` of the chosen issuers have to behave, as misbehaving ones are not
+/// replaced by others.
+#[cfg(feature = "std")]
+#[allow(clippy::too_many_arguments)]
+pub fn spirit_register_with_issuers(
+    domain: &DomainConfig,
+    user: &UserSecret,
+    issuers: &[Issuer],
+    indices: &[usize],
+    pp: &tACTPublicParameters,
+    expiry: Epoch,
+    t_rgstr: &mut impl TokenRegistry,
+    rng: &mut (impl RngCore + CryptoRng),
+) -> Result<(Token, StRG), SpiritError> {
+    register_with(
+        domain,
+        user,
+        &[],
+        pp,
+        expiry,
+        t_rgstr,
+        rng,
+        |blind_request| issue_chosen_blind_tokens(blind_request, issuers, indices, pp),
+    )
+}
+
+/// Like [spirit_register], in `epoch` of a deployment with a
+/// [committee beacon](DomainConfig::committee_beacon): the blind tokens are requested from the
+/// epoch's committee of [select_committee] and no other issuers, so misbehaving members are not
+/// replaced and registration fails with [SpiritError::MisbehavingIssuers]. Issuers with a
+/// [committee seat](Issuer::with_committee) reject requests outside their committees. Without a
+/// beacon, the issuers are asked like in [spirit_register].
+#[cfg(feature = "std")]
+#[allow(clippy::too_many_arguments)]
+pub fn spirit_register_with_committee(
+    domain: &DomainConfig,
+    user: &UserSecret,
+    issuers: &[Issuer],
+    epoch: Epoch,
+    pp: &tACTPublicParameters,
+    expiry: Epoch,
+    t_rgstr: &mut impl TokenRegistry,
+    rng: &mut (impl RngCore + CryptoRng),
+) -> Result<(Token, StRG), SpiritError> {
+    register_with(
+        domain,
+        user,
+        &[],
+        pp,
+        expiry,
+        t_rgstr,
+        rng,
+        |blind_request| issue_committee_blind_tokens(domain, epoch, blind_request, issuers, pp),
+    )
+}
+
+/// Registers the users `ids` like [spirit_register], but in one round of issuance: the blind
+/// requests of all users are sent to each of the first `pp.t` issuers in a single message, see
+/// [tissue_batch], and the blind tokens are checked, aggregated and unblinded per user. Returns
+/// the result of every user in the order of `ids`, so a user whose request or tokens are
+/// invalid fails alone.
+///
+/// Unlike [spirit_register], a misbehaving issuer is not replaced by another, which would take
+/// another round, and the users it misbehaved for fail with [SpiritError::MisbehavingIssuers].
+#[cfg(feature = "std")]
+pub fn spirit_register_batch(
+    domain: &DomainConfig,
+    ids: &[Scalar],
+    issuers: &[Issuer],
+    pp: &tACTPublicParameters,
+    expiry: Epoch,
+    t_rgstr: &mut impl TokenRegistry,
+    rng: &mut (impl RngCore + CryptoRng),
+) -> Vec<Result<(Token, StRG), SpiritError>> {
+    if issuers.len() < pp.t {
+        let error = SpiritError::NotEnoughIssuers {
+            required: pp.t,
+            available: issuers.len(),
+        };
+        return ids.iter().map(|_| Err(error.clone())).collect();
+    }
+
+    let mut results = Vec::with_capacity(ids.len());
+    let mut blind_requests = Vec::with_capacity(ids.len());
+    let mut pending = Vec::with_capacity(ids.len());
+    for id_u in ids {
+        results.push(spirit_register_begin(domain, id_u, pp, expiry, rng).map(
+            |(blind_request, registration)| {
+                blind_requests.push(blind_request);
+                pending.push(registration);
+            },
+        ));
+    }
+
+    // the responses of every issuer, transposed to the responses for every user
+    let mut responses: Vec<_> = blind_requests
+        .iter()
+        .map(|_| Vec::with_capacity(pp.t))
+        .collect();
+    for (issuer_index, issuer) in issuers[..pp.t].iter().enumerate() {
+        let batch = tissue_batch(&blind_requests, issuer, pp);
+        for (user_responses, response) in responses.iter_mut().zip(batch) {
+            user_responses.push(
+                response
+                    .map(|tokens| (issuer_index, tokens))
+                    .map_err(|source| SpiritError::IssuerRejected {
+                        issuer_index,
+                        source,
+                    }),
+            );
+        }
+    }
+
+    let mut registered = blind_requests.iter().zip(pending).zip(responses).map(
+        |((blind_request, registration), user_responses)| -> Result<_, SpiritError> {
+            let blind_tokens =
+                verified_blind_tokens(blind_request, pp, user_responses.into_iter())?;
+            let (ppu, token, token_proof) =
+                spirit_register_finish_from(domain, registration, &blind_tokens, pp, rng)?;
+            spirit_complete_registration(
+                domain,
+                blind_request,
+                &token,
+                &token_proof,
+                pp,
+                expiry,
+                t_rgstr,
+            )?;
+            Ok(ppu)
+        },
+    );
+    results
+        .into_iter()
+        .map(|begun| {
+            // one result per begun registration
+            begun.and_then(|()| {
+                registered
+                    .next()
+                    .unwrap_or(Err(SpiritError::RegistrationFailed(
+                        AtACTError::UnknownError,
+                    )))
+            })
+        })
+        .collect()
+}
+
+/// Registration with the blind tokens `issue` returns for the blind request, together with
+/// their issuers' indices.
+#[cfg(feature = "std")]
+#[cfg_attr(
+    feature = "telemetry",
+    tracing::instrument(
+        name = "spirit_register",
+        level = "debug",
+        skip_all,
+        fields(expiry = expiry, attributes = attributes.len()),
+        err(level = "debug")
+    )
+)]
+#[allow(clippy::too_many_arguments)]
+fn register_with(
+    domain: &DomainConfig,
+    user: &UserSecret,
+    attributes: &[Scalar],
+    pp: &tACTPublicParameters,
+    expiry: Epoch,
+    t_rgstr: &mut impl TokenRegistry,
+    rng: &mut (impl RngCore + CryptoRng),
+    issue: impl FnOnce(&BlindRequest) -> Result<Vec<(usize, Vec<BlindToken>)>, SpiritError>,
+) -> Result<(Token, StRG), SpiritError> {
+    let (blind_request, pending) =
+        spirit_register_begin_with_attributes(domain, &user.id_u, attributes, pp, expiry, rng)?;
+    let blind_tokens = issue(&blind_request)?;
+    let (ppu, token, token_proof) =
+        spirit_register_finish_from(domain, pending, &blind_tokens, pp, rng)?;
+    spirit_complete_registration_with_attributes(
+        domain,
+        &blind_request,
+        attributes,
+        &token,
+        &token_proof,
+        pp,
+        expiry,
+        t_rgstr,
+    )?;
+
+    Ok(ppu)
+}
+
+/// Registration state the user keeps between [spirit_register_begin] and
+/// [spirit_register_finish], including the expiry epoch the blind request commits to. With the
+/// `zeroize` feature, the blinding randomness is cleared when it is dropped.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PendingRegistration {
+    strg: StRG,
+    cm: Commitment,
+    rand: Rand,
+    expiry: Epoch,
+}
+
+/// User side of [spirit_register] with remote issuers: registers `id_u` with tACT and returns
+/// the blind request to send to the issuers, for a token that expires at the start of epoch
+/// `expiry`.
+///
+/// The parameters may come from an untrusted source, e.g. [spirit_client_parameters] with a
+/// downloaded key, so they are [validated](tACTPublicParameters::validate_cached) first.
+pub fn spirit_register_begin(
+    domain: &DomainConfig,
+    id_u: &Scalar,
+    pp: &tACTPublicParameters,
+    expiry: Epoch,
+    rng: &mut (impl RngCore + CryptoRng),
+) -> Result<(BlindRequest, PendingRegistration), SpiritError> {
+    spirit_register_begin_with_attributes(domain, id_u, &[], pp, expiry, rng)
+}
+
+/// User side of [spirit_register_with_attributes] with remote issuers, like
+/// [spirit_register_begin]. The [IdentityClaim] of the registration is for the identity
+/// commitment of the blind request, see [BlindRequest::identity_commitment].
+///
+/// The blind request commits to the tACT commitment times `v_expiry`, so the issuers' signature
+/// covers the expiry, and the registrar only accepts the token proof for the same expiry.
+pub fn spirit_register_begin_with_attributes(
+    domain: &DomainConfig,
+    id_u: &Scalar,
+    attributes: &[Scalar],
+    pp: &tACTPublicParameters,
+    expiry: Epoch,
+    rng: &mut (impl RngCore + CryptoRng),
+) -> Result<(BlindRequest, PendingRegistration), SpiritError> {
+    pp.validate_cached()
+        .map_err(SpiritError::InvalidParameters)?;
+    check_hash_to_curve(domain, pp)?;
+    let attributes: Vec<_> = iter::once(*id_u)
+        .chain(attributes.iter().copied())
+        .collect();
+    let (strg, cm) =
+        register_attributes(&attributes, pp, rng).map_err(SpiritError::RegistrationFailed)?;
+    let cm = Commitment(cm.0 + expiry_term(domain, expiry));
+    let (blind_request, rand) =
+        token_request(&strg, &cm, pp, rng).map_err(SpiritError::TokenRequestFailed)?;
+    // the user's proofs are about the identity alone
+    let cm = Commitment::commit_with_randomness(&strg.a, &strg.r).0;
+    let pending = PendingRegistration {
+        strg,
+        cm,
+        rand,
+        expiry,
+    };
+    Ok((blind_request, pending))
+}
+
+/// User side of [spirit_register_key_bound] with remote issuers, like [spirit_register_begin].
+/// The blind request commits to the master PRF key of `keys` as the first attribute after the
+/// identity, and the returned [KeyClaim] proves knowledge of it to the registrar.
+pub fn spirit_register_begin_key_bound(
+    domain: &DomainConfig,
+    id_u: &Scalar,
+    keys: &UserKeys,
+    pp: &tACTPublicParameters,
+    expiry: Epoch,
+    rng: &mut (impl RngCore + CryptoRng),
+) -> Result<(BlindRequest, PendingRegistration, KeyClaim), SpiritError> {
+    let (blind_request, pending) =
+        spirit_register_begin_with_attributes(domain, id_u, &[keys.prv], pp, expiry, rng)?;
+    let key_claim = KeyClaim::prove(domain, &blind_request, &keys.prv, rng);
+    Ok((blind_request, pending, key_claim))
+}
+
+impl PendingRegistration {
+    /// [IdentityClaim] for the identity of the blind request this registration was started
+    /// with, which the issuers and the registrar check before accepting the request.
+    pub fn identity_claim(
+        &self,
+        domain: &DomainConfig,
+        rng: &mut (impl RngCore + CryptoRng),
+    ) -> IdentityClaim {
+        let base = identity_base(domain);
+        let tag = identity_tag(domain, &self.strg.a);
+        let opening = Opening { r: self.strg.r };
+        let proof = self.cm.proof_2_pk(
+            &self.strg.a,
+            &opening,
+            &self.cm,
+            &self.strg.a,
+            &opening,
+            &base,
+            &tag,
+            rng,
+        );
+        IdentityClaim {
+            tag,
+            proof,
+            expiry: self.expiry,
+        }
+    }
+
+    /// Proof for a registrar in audit mode that the blind request of this registration commits
+    /// to the user's identity, which the registrar knows, see
+    /// [spirit_complete_registration_audited]. The proof reveals nothing else.
+    pub fn audit_proof(&self, rng: &mut (impl RngCore + CryptoRng)) -> MessageOpeningProof {
+        self.cm
+            .prove_opening_to(&self.strg.a, &Opening { r: self.strg.r }, rng)
+    }
+}
+
+/// Tag `H_id^id_u` of the identity committed to in a blind request, with a [Proof2PK] that it
+/// is derived from the committed identity, and the expiry epoch the request commits to. The tag
+/// is deterministic, so a second registration of an identity is caught by its tag even if the
+/// commitment is re-randomized, see [IdentityIndex].
+#[derive(CanonicalSerialize, CanonicalDeserialize)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct IdentityClaim {
+    pub(crate) tag: G1G2,
+    pub(crate) proof: Proof2PK,
+    pub(crate) expiry: Epoch,
+}
+
+impl IdentityClaim {
+    /// Checks that the tag is derived from the identity committed to in `blind_request`, whose
+    /// commitment is the one to the identity times `v_expiry`.
+    pub fn verify(
+        &self,
+        domain: &DomainConfig,
+        blind_request: &BlindRequest,
+    ) -> Result<(), SpiritError> {
+        let identity = Commitment(&blind_request.cm.0 - expiry_term(domain, self.expiry));
+        identity
+            .verify_proof_2_pk(&identity, &identity_base(domain), &self.tag, &self.proof)
+            .map_err(|_| SpiritError::InvalidIdentityClaim)
+    }
+
+    /// The expiry epoch of the token the claimed request is for.
+    pub fn expiry(&self) -> Epoch {
+        self.expiry
+    }
+}
+
+/// Part `h_1^prv` of the commitment of a key-bound blind request for the master PRF key `prv`,
+/// see [spirit_register_begin_key_bound], with a Schnorr proof of knowledge of `prv`. The
+/// registrar checks the token proof against the commitment without this part, which is the one
+/// to the identity.
+#[derive(CanonicalSerialize, CanonicalDeserialize)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct KeyClaim {
+    pub(crate) key: G1G2,
+    pub(crate) t: G1G2,
+    #[cfg_attr(
+        feature = "serde",
+        serde(with = "crate::bls381_helpers::serde_compressed")
+    )]
+    pub(crate) s: Scalar,
+}
+
+impl KeyClaim {
+    fn prove(
+        domain: &DomainConfig,
+        blind_request: &BlindRequest,
+        prv: &Scalar,
+        rng: &mut (impl RngCore + CryptoRng),
+    ) -> Self {
+        let base = key_base();
+        let key = &base * *prv;
+        let rho = Scalar::rand(rng);
+        let t = base * rho;
+        let c = key_claim_challenge(domain, blind_request, &key, &t);
+        Self {
+            key,
+            t,
+            s: rho + c * prv,
+        }
+    }
+
+    /// Checks the proof of knowledge of the key part of `blind_request`.
+    pub fn verify(
+        &self,
+        domain: &DomainConfig,
+        blind_request: &BlindRequest,
+    ) -> Result<(), SpiritError> {
+        let c = key_claim_challenge(domain, blind_request, &self.key, &self.t);
+        if key_base() * self.s == &self.t + &self.key * c {
+            Ok(())
+        } else {
+            Err(SpiritError::InvalidKeyClaim)
+        }
+    }
+}
+
+// generator `h_1` of the first attribute after the identity, which holds the key of key-bound
+// tokens
+fn key_base() -> G1G2 {
+    MultiBasePublicParameters::new(1)[0].clone()
+}
+
+fn key_claim_challenge(
+    domain: &DomainConfig,
+    blind_request: &BlindRequest,
+    key: &G1G2,
+    t: &G1G2,
+) -> Scalar {
+    let mut transcript = ProofTranscript::new(KEY_CLAIM_PROTOCOL);
+    transcript.append_message(b"deployment", &domain.app_id);
+    transcript.append_point(b"base", &key_base());
+    transcript.append_point(b"cm", &blind_request.cm.0);
+    transcript.append_point(b"key", key);
+    transcript.append_point(b"t", t);
+    transcript.challenge_scalar(b"c")
+}
+
+/// Unblinds the blind tokens of the first `pp.t` issuers, in issuer order, into the user's
+/// token that expires at the start of the epoch `pending` was begun with. Returns the user's
+/// `(Token, StRG)` pair, and the unblinded tACT token with its proof for
+/// [spirit_complete_registration].
+#[allow(clippy::type_complexity)]
+pub fn spirit_register_finish(
+    domain: &DomainConfig,
+    pending: PendingRegistration,
+    blind_tokens: &Vec<Vec<BlindToken>>,
+    pp: &tACTPublicParameters,
+    rng: &mut (impl RngCore + CryptoRng),
+) -> Result<((Token, StRG), tACTToken, TokenProof), SpiritError> {
+    check_hash_to_curve(domain, pp)?;
+    check_request_size(&pending, pp)?;
+    if blind_tokens.len() < pp.t {
+        return Err(SpiritError::NotEnoughIssuers {
+            required: pp.t,
+            available: blind_tokens.len(),
+        });
+    }
+    if let Some((issuer_index, tokens)) = blind_tokens
+        .iter()
+        .enumerate()
+        .find(|(_, tokens)| tokens.len() != pp.n)
+    {
+        return Err(SpiritError::InvalidBlindTokens {
+            issuer_index,
+            expected: pp.n,
+            actual: tokens.len(),
+        });
+    }
+
+    unblind_registration(domain, pending, pp, rng, |rand| {
+        aggregate_unblind(blind_tokens, rand, pp)
+    })
+}
+
+/// Like [spirit_register_finish], but for the blind tokens of any `pp.t` distinct issuers, given
+/// together with the index of the issuer in the committee as in [aggregate_unblind_from].
+#[allow(clippy::type_complexity)]
+pub fn spirit_register_finish_from(
+    domain: &DomainConfig,
+    pending: PendingRegistration,
+    blind_tokens: &[(usize, Vec<BlindToken>)],
+    pp: &tACTPublicParameters,
+    rng: &mut (impl RngCore + CryptoRng),
+) -> Result<((Token, StRG), tACTToken, TokenProof), SpiritError> {
+    check_hash_to_curve(domain, pp)?;
+    check_request_size(&pending, pp)?;
+    check_blind_tokens_from(blind_tokens, pp)?;
+
+    unblind_registration(domain, pending, pp, rng, |rand| {
+        aggregate_unblind_from(blind_tokens, rand, pp)
+    })
+}
+
+// the blind tokens of at least `pp.t` distinct issuers, each for `pp.n` commitments
+fn check_blind_tokens_from(
+    blind_tokens: &[(usize, Vec<BlindToken>)],
+    pp: &tACTPublicParameters,
+) -> Result<(), SpiritError> {
+    check_distinct_issuers(blind_tokens.iter().map(|(index, _)| *index))?;
+    if blind_tokens.len() < pp.t {
+        return Err(SpiritError::NotEnoughIssuers {
+            required: pp.t,
+            available: blind_tokens.len(),
+        });
+    }
+    if let Some((issuer_index, tokens)) =
+        blind_tokens.iter().find(|(_, tokens)| tokens.len() != pp.n)
+    {
+        return Err(SpiritError::InvalidBlindTokens {
+            issuer_index: *issuer_index,
+            expected: pp.n,
+            actual: tokens.len(),
+        });
+    }
+    Ok(())
+}
+
+// the blinding randomness of `pending` is for `pp.n` tokens, unless the request was begun with
+// parameters of another request size
+fn check_request_size(
+    pending: &PendingRegistration,
+    pp: &tACTPublicParameters,
+) -> Result<(), SpiritError> {
+    let actual = pending.rand.request_size();
+    if actual != pp.n {
+        return Err(SpiritError::TokenRequestFailed(
+            AtACTError::InvalidRequestLength {
+                expected: pp.n,
+                actual,
+            },
+        ));
+    }
+    Ok(())
+}
+
+/// Unblinds the token with `aggregate` and proves the unblinding, see [spirit_register_finish].
+#[allow(clippy::type_complexity)]
+fn unblind_registration(
+    domain: &DomainConfig,
+    pending: PendingRegistration,
+    pp: &tACTPublicParameters,
+    rng: &mut (impl RngCore + CryptoRng),
+    aggregate: impl FnOnce(&Rand) -> Result<tACTToken, AtACTError>,
+) -> Result<((Token, StRG), tACTToken, TokenProof), SpiritError> {
+    let PendingRegistration {
+        strg, rand, expiry, ..
+    } = pending;
+    let token = aggregate(&rand).map_err(unblinding_error)?;
+    let token_proof = prove(&token, &rand, pp, rng);
+    drop(rand);
+
+    let final_token = Token::new(domain, &strg.commitment(pp), token.s.clone(), expiry);
+    Ok(((final_token, strg), token, token_proof))
+}
+
+// the errors of the unblinding as the same checks of spirit_register_finish_from report them
+fn unblinding_error(error: AtACTError) -> SpiritError {
+    match error {
+        AtACTError::NotEnoughIssuers {
+            required,
+            available,
+        } => SpiritError::NotEnoughIssuers {
+            required,
+            available,
+        },
+        AtACTError::InvalidBlindTokens {
+            issuer_index,
+            expected,
+            actual,
+        } => SpiritError::InvalidBlindTokens {
+            issuer_index,
+            expected,
+            actual,
+        },
+        AtACTError::DuplicateIssuer { issuer_index } => {
+            SpiritError::DuplicateIssuer { issuer_index }
+        }
+        error @ AtACTError::InvalidRequestLength { .. } => SpiritError::TokenRequestFailed(error),
+        error => SpiritError::RegistrationFailed(error),
+    }
+}
+
+/// Like [spirit_register], but requests the blind tokens through `clients`, one per issuer and
+/// ordered by issuer index. The blind request is sent to `pp.t` clients concurrently, and every
+/// client that fails or returns malformed blind tokens is replaced by the next unused one, so
+/// registration succeeds as long as `pp.t` clients respond. Outstanding requests are dropped
+/// once `pp.t` clients have responded.
+#[cfg(feature = "async")]
+pub async fn spirit_register_async(
+    domain: &DomainConfig,
+    user: &UserSecret,
+    clients: &[impl IssuerClient],
+    pp: &tACTPublicParameters,
+    expiry: Epoch,
+    t_rgstr: &mut impl TokenRegistry,
+    rng: &mut (impl RngCore + CryptoRng),
+) -> Result<(Token, StRG), SpiritError> {
+    register_async_from(
+        domain,
+        user,
+        clients,
+        0..clients.len(),
+        pp,
+        expiry,
+        t_rgstr,
+        rng,
+    )
+    .await
+}
+
+/// Like [spirit_register_async], but [probes](probe_quorum) the issuers first and fails fast
+/// with [SpiritError::QuorumUnavailable] if fewer than `pp.t` of them are live, serve `pp` and
+/// are in `current_epoch`. The blind request is then only sent to the live issuers.
+#[cfg(feature = "async")]
+#[allow(clippy::too_many_arguments)]
+pub async fn spirit_register_async_probed(
+    domain: &DomainConfig,
+    user: &UserSecret,
+    clients: &[impl IssuerClient],
+    pp: &tACTPublicParameters,
+    expiry: Epoch,
+    current_epoch: Epoch,
+    t_rgstr: &mut impl TokenRegistry,
+    rng: &mut (impl RngCore + CryptoRng),
+) -> Result<(Token, StRG), SpiritError> {
+    let status = probe_quorum(clients, pp, pp.t, current_epoch, rng).await;
+    if !status.is_available() {
+        return Err(SpiritError::QuorumUnavailable {
+            required: pp.t,
+            live: status.live.len(),
+            inconsistent: status.inconsistent,
+        });
+    }
+    register_async_from(domain, user, clients, status.live, pp, expiry, t_rgstr, rng).await
+}
+
+/// Registers through the `candidates` among `clients`, see [spirit_register_async].
+#[cfg(feature = "async")]
+#[allow(clippy::too_many_arguments)]
+async fn register_async_from(
+    domain: &DomainConfig,
+    user: &UserSecret,
+    clients: &[impl IssuerClient],
+    candidates: impl IntoIterator<Item = usize>,
+    pp: &tACTPublicParameters,
+    expiry: Epoch,
+    t_rgstr: &mut impl TokenRegistry,
+    rng: &mut (impl RngCore + CryptoRng),
+) -> Result<(Token, StRG), SpiritError> {
+    let (blind_request, pending) = spirit_register_begin(domain, &user.id_u, pp, expiry, rng)?;
+    let blind_tokens = issue_blind_tokens_async(&blind_request, clients, candidates, pp).await?;
+    let (ppu, token, token_proof) = unblind_registration(domain, pending, pp, rng, |rand| {
+        aggregate_unblind_from(&blind_tokens, rand, pp)
+    })?;
+    spirit_complete_registration(
+        domain,
+        &blind_request,
+        &token,
+        &token_proof,
+        pp,
+        expiry,
+        t_rgstr,
+    )?;
+
+    Ok(ppu)
+}
+
+/// Collects the blind tokens of the first `pp.t` of the `candidates` among `clients` to
+/// respond, together with their indices, see [spirit_register_async].
+#[cfg(feature = "async")]
+async fn issue_blind_tokens_async(
+    blind_request: &BlindRequest,
+    clients: &[impl IssuerClient],
+    candidates: impl IntoIterator<Item = usize>,
+    pp: &tACTPublicParameters,
+) -> Result<Vec<(usize, Vec<BlindToken>)>, SpiritError> {
+    let issue =
+        move |index: usize| async move { (index, clients[index].issue(blind_request).await) };
+    let mut unused = candidates.into_iter();
+    let mut requests: FuturesUnordered<_> = unused.by_ref().take(pp.t).map(issue).collect();
+
+    let mut blind_tokens = Vec::with_capacity(pp.t);
+    while let Some((index, result)) = requests.next().await {
+        match result {
+            Ok(tokens) if tokens.len() == pp.n => {
+                blind_tokens.push((index, tokens));
+                if blind_tokens.len() == pp.t {
+                    return Ok(blind_tokens);
+                }
+            }
+            _ => requests.extend(unused.next().map(issue)),
+        }
+    }
+
+    Err(SpiritError::NotEnoughIssuers {
+        required: pp.t,
+        available: blind_tokens.len(),
+    })
+}
+
+/// Registration through remote issuers that survives a stalled attempt: the blind request, the
+/// user's [PendingRegistration], and the blind tokens collected so far with the indices of their
+/// issuers. A session is [collected](Self::collect) into until an issuer times out, stored, e.g.
+/// serialized with the `serde` feature, and [resumed](Self::resume) later, which only asks the
+/// issuers whose blind tokens are missing.
+///
+/// A session can be resumed for `max_age` epochs after it began, after which the registration has
+/// to start over with a fresh blind request.
+#[cfg(feature = "async")]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RegistrationSession {
+    blind_request: BlindRequest,
+    pending: PendingRegistration,
+    blind_tokens: Vec<(usize, Vec<BlindToken>)>,
+    started: Epoch,
+    max_age: Epoch,
+}
+
+#[cfg(feature = "async")]
+impl RegistrationSession {
+    /// Starts the registration of `id_u` in `current_epoch` for a token that expires at the
+    /// start of epoch `expiry`, like [spirit_register_begin].
+    pub fn begin(
+        domain: &DomainConfig,
+        id_u: &Scalar,
+        pp: &tACTPublicParameters,
+        current_epoch: Epoch,
+        max_age: Epoch,
+        expiry: Epoch,
+        rng: &mut (impl RngCore + CryptoRng),
+    ) -> Result<Self, SpiritError> {
+        let (blind_request, pending) = spirit_register_begin(domain, id_u, pp, expiry, rng)?;
+        Ok(Self {
+            blind_request,
+            pending,
+            blind_tokens: Vec::new(),
+            started: current_epoch,
+            max_age,
+        })
+    }
+
+    pub fn blind_request(&self) -> &BlindRequest {
+        &self.blind_request
+    }
+
+    /// Indices of the issuers whose blind tokens were collected, in the order they arrived.
+    pub fn issuers(&self) -> impl Iterator<Item = usize> + '_ {
+        self.blind_tokens.iter().map(|(index, _)| *index)
+    }
+
+    /// Last epoch in which the session can be resumed.
+    pub fn last_epoch(&self) -> Epoch {
+        self.started.saturating_add(self.max_age)
+    }
+
+    /// Adds the blind tokens that the issuer `issuer_index` returned for the session's blind
+    /// request. Fails with [SpiritError::ForeignBlindTokens] if they do not verify against the
+    /// issuer's key in [tACTPublicParameters::issuer_keys] for the session's blind request, e.g.
+    /// when they answer the request of another session, and with
+    /// [SpiritError::DuplicateIssuer] if the issuer's tokens were collected before.
+    pub fn add_blind_tokens(
+        &mut self,
+        issuer_index: usize,
+        tokens: Vec<BlindToken>,
+        pp: &tACTPublicParameters,
+    ) -> Result<(), SpiritError> {
+        add_session_tokens(
+            &self.blind_request,
+            &mut self.blind_tokens,
+            issuer_index,
+            tokens,
+            pp,
+        )
+    }
+
+    /// Requests the missing blind tokens through `clients`, one per issuer and ordered by issuer
+    /// index as for [spirit_register_async], but skipping the issuers whose tokens were
+    /// collected. Clients that fail or return tokens that [Self::add_blind_tokens] refuses are
+    /// replaced by the next unused one. Fails with [SpiritError::NotEnoughIssuers] if fewer than
+    /// `pp.t` issuers have answered once the clients run out; the session keeps the tokens
+    /// collected so far.
+    pub async fn collect(
+        &mut self,
+        clients: &[impl IssuerClient],
+        pp: &tACTPublicParameters,
+    ) -> Result<(), SpiritError> {
+        let blind_request = &self.blind_request;
+        let blind_tokens = &mut self.blind_tokens;
+        let issue =
+            move |index: usize| async move { (index, clients[index].issue(blind_request).await) };
+        let collected: Vec<_> = blind_tokens.iter().map(|(index, _)| *index).collect();
+        let mut unused = (0..clients.len()).filter(|index| !collected.contains(index));
+        let missing = pp.t.saturating_sub(blind_tokens.len());
+        let mut requests: FuturesUnordered<_> = unused.by_ref().take(missing).map(issue).collect();
+
+        while blind_tokens.len() < pp.t {
+            let Some((index, result)) = requests.next().await else {
+                return Err(SpiritError::NotEnoughIssuers {
+                    required: pp.t,
+                    available: blind_tokens.len(),
+                });
+            };
+            let added = result.is_ok_and(|tokens| {
+                add_session_tokens(blind_request, blind_tokens, index, tokens, pp).is_ok()
+            });
+            if !added {
+                requests.extend(unused.next().map(issue));
+            }
+        }
+        Ok(())
+    }
+
+    /// Collects the missing blind tokens like [Self::collect] in `current_epoch`, then unblinds
+    /// the token and registers it in `t_rgstr` like [spirit_register_async]. Fails with
+    /// [SpiritError::SessionExpired] after the [last epoch](Self::last_epoch) of the session, and
+    /// like [spirit_register_finish_from] if the session's request or blind tokens are not for
+    /// `pp`. The session is kept on failure, so it can be resumed again, and should be dropped
+    /// once the registration succeeded.
+    pub async fn resume(
+        &mut self,
+        domain: &DomainConfig,
+        clients: &[impl IssuerClient],
+        pp: &tACTPublicParameters,
+        current_epoch: Epoch,
+        t_rgstr: &mut impl TokenRegistry,
+        rng: &mut (impl RngCore + CryptoRng),
+    ) -> Result<(Token, StRG), SpiritError> {
+        if current_epoch > self.last_epoch() {
+            return Err(SpiritError::SessionExpired {
+                started: self.started,
+            });
+        }
+        check_hash_to_curve(domain, pp)?;
+        check_request_size(&self.pending, pp)?;
+        self.collect(clients, pp).await?;
+        check_blind_tokens_from(&self.blind_tokens, pp)?;
+
+        let PendingRegistration {
+            strg, rand, expiry, ..
+        } = &self.pending;
+        let token =
+            aggregate_unblind_from(&self.blind_tokens, rand, pp).map_err(unblinding_error)?;
+        let token_proof = prove(&token, rand, pp, rng);
+        spirit_complete_registration(
+            domain,
+            &self.blind_request,
+            &token,
+            &token_proof,
+            pp,
+            *expiry,
+            t_rgstr,
+        )?;
+        let final_token = Token::new(domain, &strg.commitment(pp), token.s, *expiry);
+        Ok((final_token, strg.clone()))
+    }
+}
+
+// see RegistrationSession::add_blind_tokens
+#[cfg(feature = "async")]
+fn add_session_tokens(
+    blind_request: &BlindRequest,
+    blind_tokens: &mut Vec<(usize, Vec<BlindToken>)>,
+    issuer_index: usize,
+    tokens: Vec<BlindToken>,
+    pp: &tACTPublicParameters,
+) -> Result<(), SpiritError> {
+    if blind_tokens.iter().any(|(index, _)| *index == issuer_index) {
+        return Err(SpiritError::DuplicateIssuer { issuer_index });
+    }
+    let is_valid = pp
+        .issuer_keys
+        .get(issuer_index)
+        .is_some_and(|issuer_pk| verify_blind_token(&tokens, issuer_pk, blind_request, pp));
+    if !is_valid {
+        return Err(SpiritError::ForeignBlindTokens { issuer_index });
+    }
+    blind_tokens.push((issuer_index, tokens));
+    Ok(())
+}
+
+/// Registrar side of a registration whose token request and unblinding ran on the user's
+/// device: checks `token_proof` for the unblinded `token` against the user's `blind_request`,
+/// and registers the token for the request's commitment that expires at the start of epoch
+/// `expiry`. The parameters are [validated](tACTPublicParameters::validate_cached) first. The
+/// proof only verifies if the request commits to `expiry`, which the issuers' signature covers.
+///
+/// Registrars that issue at most one token per identity check the request's [IdentityClaim]
+/// against an [IdentityIndex] first, like [SpiritSystem::complete_registration].
+#[cfg(feature = "std")]
+pub fn spirit_complete_registration(
+    domain: &DomainConfig,
+    blind_request: &BlindRequest,
+    token: &tACTToken,
+    token_proof: &TokenProof,
+    pp: &tACTPublicParameters,
+    expiry: Epoch,
+    t_rgstr: &mut impl TokenRegistry,
+) -> Result<Token, SpiritError> {
+    spirit_complete_registration_with_attributes(
+        domain,
+        blind_request,
+        &[],
+        token,
+        token_proof,
+        pp,
+        expiry,
+        t_rgstr,
+    )
+}
+
+/// Registrar side of [spirit_register_with_attributes], like [spirit_complete_registration]
+/// for a `blind_request` that commits to the disclosed `attributes` after the identity.
+#[cfg(feature = "std")]
+#[allow(clippy::too_many_arguments)]
+pub fn spirit_complete_registration_with_attributes(
+    domain: &DomainConfig,
+    blind_request: &BlindRequest,
+    attributes: &[Scalar],
+    token: &tACTToken,
+    token_proof: &TokenProof,
+    pp: &tACTPublicParameters,
+    expiry: Epoch,
+    t_rgstr: &mut impl TokenRegistry,
+) -> Result<Token, SpiritError> {
+    pp.validate_cached()
+        .map_err(SpiritError::InvalidParameters)?;
+    check_hash_to_curve(domain, pp)?;
+    verify_registration(
+        domain,
+        blind_request,
+        attributes,
+        token,
+        token_proof,
+        pp,
+        expiry,
+    )?;
+
+    let final_token = Token(blind_request.cm.clone(), token.s.clone(), expiry);
+    t_rgstr
+        .insert(final_token.clone())
+        .map_err(SpiritError::RegistryUpdateFailed)?;
+
+    Ok(final_token)
+}
+
+/// Checks `token_proof` for the unblinded `token` against `blind_request` with the disclosed
+/// `attributes`, for a request that commits to the expiry epoch `expiry`.
+#[cfg(feature = "std")]
+pub(crate) fn verify_registration(
+    domain: &DomainConfig,
+    blind_request: &BlindRequest,
+    attributes: &[Scalar],
+    token: &tACTToken,
+    token_proof: &TokenProof,
+    pp: &tACTPublicParameters,
+    expiry: Epoch,
+) -> Result<(), SpiritError> {
+    let identity = blind_request
+        .identity_commitment(attributes, pp)
+        .map_err(SpiritError::ProofVerificationFailed)?;
+    let identity = Commitment(identity.0 - expiry_term(domain, expiry));
+    verify_with_identity(token, token_proof, blind_request, &identity, pp, &mut OsRng)
+        .map_err(SpiritError::ProofVerificationFailed)
+}
+
+/// Registrar side of [spirit_register_audited], like [spirit_complete_registration], but the
+/// token is only registered if `audit_proof` shows that `blind_request` commits to the identity
+/// `id_u`. Fails with [SpiritError::InvalidAuditProof] otherwise.
+#[cfg(feature = "std")]
+#[allow(clippy::too_many_arguments)]
+pub fn spirit_complete_registration_audited(
+    domain: &DomainConfig,
+    blind_request: &BlindRequest,
+    id_u: &Scalar,
+    audit_proof: &MessageOpeningProof,
+    token: &tACTToken,
+    token_proof: &TokenProof,
+    pp: &tACTPublicParameters,
+    expiry: Epoch,
+    t_rgstr: &mut impl TokenRegistry,
+) -> Result<Token, SpiritError> {
+    let identity = blind_request
+        .identity_commitment(&[], pp)
+        .map_err(SpiritError::RegistrationFailed)?;
+    let identity = Commitment(identity.0 - expiry_term(domain, expiry));
+    if !identity.verify_opening_to(id_u, audit_proof) {
+        return Err(SpiritError::InvalidAuditProof);
+    }
+    spirit_complete_registration(
+        domain,
+        blind_request,
+        token,
+        token_proof,
+        pp,
+        expiry,
+        t_rgstr,
+    )
+}
+
+/// Registrar side of [spirit_register_key_bound], like [spirit_complete_registration], but the
+/// token proof is checked against the commitment of `blind_request` without the key part of
+/// `key_claim`. Fails with [SpiritError::InvalidKeyClaim] if the claim does not verify.
+#[cfg(feature = "std")]
+#[allow(clippy::too_many_arguments)]
+pub fn spirit_complete_registration_key_bound(
+    domain: &DomainConfig,
+    blind_request: &BlindRequest,
+    key_claim: &KeyClaim,
+    token: &tACTToken,
+    token_proof: &TokenProof,
+    pp: &tACTPublicParameters,
+    expiry: Epoch,
+    t_rgstr: &mut impl TokenRegistry,
+) -> Result<Token, SpiritError> {
+    pp.validate_cached()
+        .map_err(SpiritError::InvalidParameters)?;
+    check_hash_to_curve(domain, pp)?;
+    key_claim.verify(domain, blind_request)?;
+    let identity = Commitment(&blind_request.cm.0 - &key_claim.key - expiry_term(domain, expiry));
+    verify_with_identity(token, token_proof, blind_request, &identity, pp, &mut OsRng)
+        .map_err(SpiritError::ProofVerificationFailed)?;
+
+    let final_token = Token(blind_request.cm.clone(), token.s.clone(), expiry);
+    t_rgstr
+        .insert(final_token.clone())
+        .map_err(SpiritError::RegistryUpdateFailed)?;
+
+    Ok(final_token)
+}
+
+/// A [rerandomized](Token::rerandomize) presentation of the registered token of `ppu` under the
+/// issuers' key of `pp`, with the opening adjusted to it. Diagnoses made with the presentation
+/// are verified with [spirit_verify_rerandomized].
+pub fn spirit_rerandomize(
+    ppu: &(Token, StRG),
+    pp: &tACTPublicParameters,
+    rng: &mut (impl RngCore + CryptoRng),
+) -> (Token, StRG) {
+    let (token, st_rg) = ppu;
+    let (presentation, delta) = token.rerandomize(st_rg, &pp.pk, rng);
+    let st_rg = StRG {
+        r: st_rg.r + delta,
+        ..st_rg.clone()
+    };
+    (presentation, st_rg)
+}
+
+/// [spirit_register] with randomness from [OsRng].
+#[cfg(feature = "std")]
+pub fn spirit_register_osrng(
+    domain: &DomainConfig,
+    user: &UserSecret,
+    issuers: &[Issuer],
+    pp: &tACTPublicParameters,
+    expiry: Epoch,
+    t_rgstr: &mut impl TokenRegistry,
+) -> Result<(Token, StRG), SpiritError> {
+    spirit_register(domain, user, issuers, pp, expiry, t_rgstr, &mut OsRng)
+}
+
+/// [spirit_register] that records all randomness drawn from `rng` in a [Transcript], from which
+/// a [ReplayRng](crate::transcript::ReplayRng) reproduces the same token and proofs, e.g. to
+/// debug a failed registration. Like the [transcript](crate::transcript) module, only built for
+/// tests and with the `testkit` or `testvectors` feature.
+#[cfg(all(
+    feature = "std",
+    any(test, feature = "testkit", feature = "testvectors")
+))]
+#[allow(clippy::type_complexity)]
+pub fn spirit_register_recorded(
+    domain: &DomainConfig,
+    user: &UserSecret,
+    issuers: &[Issuer],
+    pp: &tACTPublicParameters,
+    expiry: Epoch,
+    t_rgstr: &mut impl TokenRegistry,
+    rng: &mut (impl RngCore + CryptoRng),
+) -> (Result<(Token, StRG), SpiritError>, Transcript) {
+    let mut rng = RecordingRng::new(rng);
+    let result = spirit_register(domain, user, issuers, pp, expiry, t_rgstr, &mut rng);
+    (result, rng.into_transcript())
+}
+
+/// The registrar's signed record that the token `old` was replaced by `new` from `epoch` on,
+/// signed with the registrar's key `key_id`, see
+/// [RegistrarKeypair::sign_rotation](crate::registry::RegistrarKeypair::sign_rotation). The
+/// user keeps it with the [PreviousKeys], and only the reports of the new token that disclose
+/// ElIDs of the epochs before the rotation carry it, as it links the user's two tokens.
+#[derive(Debug, Clone, PartialEq, Eq, CanonicalSerialize, CanonicalDeserialize)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct KeyRotation {
+    pub old: TokenId,
+    pub new: TokenId,
+    /// First epoch of the new keys.
+    pub epoch: Epoch,
+    pub key_id: KeyId,
+    pub(crate) signature: Signature,
+}
+
+#[cfg(feature = "std")]
+impl KeyRotation {
+    /// Checks the registrar's signature on the record with its key [Self::key_id], which has
+    /// to be valid in the epoch of the rotation.
+    pub fn verify(&self, registrar: &(impl RegistrarKeys + ?Sized)) -> bool {
+        registrar
+            .verifying_key(self.key_id, self.epoch)
+            .is_ok_and(|registrar| {
+                verify_registrar_signature(
+                    registrar,
+                    KEY_ROTATION_DST,
+                    self.epoch,
+                    &rotation_message(&self.old, &self.new, self.key_id),
+                    &self.signature,
+                )
+            })
+    }
+}
+
+/// Like [spirit_register], and has the `registrar` issue a [RegistrationReceipt] of the
+/// registered token, with which the user proves to be registered without showing the token, see
+/// [verify_receipt](crate::receipt::verify_receipt).
+#[cfg(feature = "std")]
+#[allow(clippy::too_many_arguments)]
+pub fn spirit_register_with_receipt(
+    domain: &DomainConfig,
+    user: &UserSecret,
+    issuers: &[Issuer],
+    pp: &tACTPublicParameters,
+    expiry: Epoch,
+    t_rgstr: &mut impl TokenRegistry,
+    registrar: &RegistrarKeypair,
+    rng: &mut (impl RngCore + CryptoRng),
+) -> Result<((Token, StRG), RegistrationReceipt), SpiritError> {
+    let ppu = spirit_register(domain, user, issuers, pp, expiry, t_rgstr, rng)?;
+    let claim = TokenClaim::prove(domain, &ppu.0.id(), user, rng);
+    let receipt = registrar.issue_receipt(domain, &ppu.0, &claim)?;
+    Ok((ppu, receipt))
+}
+
+/// Outcome of [spirit_rotate]: the user's new secret and registered token, and the replaced
+/// keys with the registrar's record of the rotation.
+#[cfg(feature = "std")]
+pub struct Rotation {
+    pub secret: UserSecret,
+    pub ppu: (Token, StRG),
+    /// The keys of `old` with the record, for the diagnoses of the new token, see
+    /// [spirit_diagnosis].
+    pub previous: PreviousKeys,
+}
+
+/// Recovers a user whose device was compromised: registers a fresh [UserSecret] with a new
+/// identity and PRF key as in [spirit_register], revokes the `old_token` of `old` in `revoked`,
+/// and has the `registrar` sign the [KeyRotation] from the old token to the new one. The new
+/// keys are used from `epoch` on, and the keys of `old` remain the [PreviousKeys] of the epochs
+/// before, so that encounters with the user's earlier ElIDs stay traceable while reports of the
+/// old token are rejected. Verifiers accept the ElIDs of the previous keys in the reports of
+/// the [rotation overlap](DomainConfig::rotation_overlap) after `epoch`.
+#[cfg(feature = "std")]
+#[allow(clippy::too_many_arguments)]
+pub fn spirit_rotate(
+    domain: &DomainConfig,
+    old: &UserSecret,
+    old_token: &Token,
+    issuers: &[Issuer],
+    pp: &tACTPublicParameters,
+    expiry: Epoch,
+    epoch: Epoch,
+    t_rgstr: &mut impl TokenRegistry,
+    revoked: &mut RevocationList,
+    registrar: &RegistrarKeypair,
+    rng: &mut (impl RngCore + CryptoRng),
+) -> Result<Rotation, SpiritError> {
+    let secret = UserSecret::random(&mut *rng);
+    let ppu = spirit_register(domain, &secret, issuers, pp, expiry, t_rgstr, rng)?;
+    revoked.revoke(old_token);
+    let record = registrar.sign_rotation(old_token.id(), ppu.0.id(), epoch);
+    Ok(Rotation {
+        secret,
+        ppu,
+        previous: PreviousKeys::new(old.keys.clone(), record),
+    })
+}
+
+/// Entry of the encounter table `t_el`: the per-encounter secret `es_i` and its encryption
+/// `Enc_pk_HA(es_i)` to the health authority, the encounter's weight (e.g. derived from duration
+/// or signal strength) and the broadcast the ElID was sent in.
+///
+/// Before uploading `encrypted_secret`, a user [rerandomizes](Ciphertext::rerandomize) it, so
+/// the upload cannot be linked to the stored ciphertext.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EncounterRecord {
+    pub secret: Scalar,
+    pub encrypted_secret: Ciphertext,
+    pub weight: u32,
+    pub epoch: Epoch,
+    pub index: Interval,
+}
+
+/// The user's encounter table `t_el` of its broadcast ElIDs, keyed by their [CompactElId]s.
+/// Every entry holds the compressed ElID and ciphertext in a record of fixed size, about a third
+/// of an ElID and an [EncounterRecord] in memory, and is expanded when it is traced. A two-week
+/// table of a broadcast every 15 minutes takes less than 700 KB.
+///
+/// Traces look the confirmed ElIDs up by their compact IDs. Two ElIDs with the same compact ID,
+/// which occur with probability `2^-128`, are both kept, and a trace by the compact ID matches
+/// both.
+///
+/// The encounter secrets of the table must not leave the device, so the table records
+/// broadcasts with [insert](Self::insert), is traced with
+/// [spirit_trace] and its variants and [pruned](Self::prune), but does not hand out its
+/// records. It is neither [Clone] nor iterable, its [Debug] output and
+/// [redacted_debug](Self::redacted_debug) only show counts, and the only way to take the
+/// records off the device is an encrypted [backup](Self::export_for_backup). Neither compiles:
+///
+/// ```compile_fail
+/// use spirit::spirit::EncounterTable;
+///
+/// let t_el = EncounterTable::new();
+/// for (el_id, encounter) in t_el.iter() {
+///     println!("{el_id:?}: {:?}", encounter.secret);
+/// }
+/// ```
+///
+/// ```compile_fail
+/// use spirit::spirit::EncounterTable;
+///
+/// let t_el = EncounterTable::new();
+/// let copy = t_el.clone();
+/// ```
+pub struct EncounterTable {
+    entries: HashMap<CompactElId, PackedEncounter>,
+    // the records of ElIDs whose compact ID is taken by the entry of another ElID, only under
+    // keys of `entries`
+    collisions: HashMap<CompactElId, Vec<PackedEncounter>>,
+    // CompactElId::of_encoding, except in tests
+    compact: fn(&[u8; G1G2::ENCODED_SIZE]) -> CompactElId,
+}
+
+impl Default for EncounterTable {
+    fn default() -> Self {
+        Self::with_compaction(CompactElId::of_encoding)
+    }
+}
+
+impl PartialEq for EncounterTable {
+    fn eq(&self, other: &Self) -> bool {
+        self.entries == other.entries && self.collisions == other.collisions
+    }
+}
+
+impl Eq for EncounterTable {}
+
+// an EncounterRecord and its ElID with the points compressed
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct PackedEncounter {
+    el_id: [u8; G1G2::ENCODED_SIZE],
+    secret: Scalar,
+    encrypted_secret: [u8; 2 * G1G2::ENCODED_SIZE],
+    weight: u32,
+    epoch: Epoch,
+    index: Interval,
+}
+
+// the points were valid when they were packed, so they are expanded without the subgroup checks
+fn unpack_point(bytes: &[u8]) -> Option<G1G2> {
+    G1G2::deserialize_with_mode(bytes, Compress::Yes, Validate::No).ok()
+}
+
+impl PackedEncounter {
+    /// Size of an entry of [EncounterTable::to_bytes].
+    const ENCODED_SIZE: usize = 3 * G1G2::ENCODED_SIZE + 32 + 4 + 8 + 8;
+
+    fn new(el_id: &ElID, encounter: EncounterRecord) -> Self {
+        let mut encrypted_secret = [0; 2 * G1G2::ENCODED_SIZE];
+        let (c1, c2) = encrypted_secret.split_at_mut(G1G2::ENCODED_SIZE);
+        c1.copy_from_slice(&encounter.encrypted_secret.c1.to_bytes());
+        c2.copy_from_slice(&encounter.encrypted_secret.c2.to_bytes());
+        Self {
+            el_id: el_id.to_bytes(),
+            secret: encounter.secret,
+            encrypted_secret,
+            weight: encounter.weight,
+            epoch: encounter.epoch,
+            index: encounter.index,
+        }
+    }
+
+    fn el_id(&self) -> Option<ElID> {
+        unpack_point(&self.el_id)
+    }
+
+    fn unpack(&self) -> Option<EncounterRecord> {
+        let (c1, c2) = self.encrypted_secret.split_at(G1G2::ENCODED_SIZE);
+        Some(EncounterRecord {
+            secret: self.secret,
+            encrypted_secret: Ciphertext {
+                c1: unpack_point(c1)?,
+                c2: unpack_point(c2)?,
+            },
+            weight: self.weight,
+            epoch: self.epoch,
+            index: self.index,
+        })
+    }
+
+    // the ElID and the encounter, of which tables skip entries that do not expand rather than
+    // panic
+    fn expand(&self) -> Option<(ElID, EncounterRecord)> {
+        Some((self.el_id()?, self.unpack()?))
+    }
+
+    fn write(&self, bytes: &mut Vec<u8>) {
+        bytes.extend_from_slice(&self.el_id);
+        append_compressed(bytes, &self.secret);
+        bytes.extend_from_slice(&self.encrypted_secret);
+        bytes.extend_from_slice(&self.weight.to_le_bytes());
+        bytes.extend_from_slice(&self.epoch.to_le_bytes());
+        bytes.extend_from_slice(&self.index.to_le_bytes());
+    }
+
+    /// Reads an entry of [Self::ENCODED_SIZE] bytes, whose points are checked like in
+    /// [G1G2::from_bytes] as they are expanded without the checks.
+    fn read(entry: &[u8]) -> Result<Self, DecodeError> {
+        let (el_id, rest) = split_array::<{ G1G2::ENCODED_SIZE }>(entry)?;
+        let (secret, rest) = split_array::<32>(rest)?;
+        let (encrypted_secret, rest) = split_array::<{ 2 * G1G2::ENCODED_SIZE }>(rest)?;
+        let (c1, c2) = encrypted_secret.split_at(G1G2::ENCODED_SIZE);
+        for point in [&el_id[..], c1, c2] {
+            G1G2::from_bytes(point)?;
+        }
+        let secret =
+            Scalar::deserialize_compressed(&secret[..]).map_err(|_| DecodeError::InvalidScalar)?;
+        let (weight, rest) = split_array::<4>(rest)?;
+        let (epoch, rest) = split_array::<8>(rest)?;
+        let (index, _) = split_array::<8>(rest)?;
+        Ok(Self {
+            el_id: *el_id,
+            secret,
+            encrypted_secret: *encrypted_secret,
+            weight: u32::from_le_bytes(*weight),
+            epoch: u64::from_le_bytes(*epoch),
+            index: Interval::from_le_bytes(*index),
+        })
+    }
+}
+
+impl EncounterTable {
+    /// Size of an entry in memory, without the overhead of the hash map.
+    pub const RECORD_SIZE: usize = size_of::<CompactElId>() + size_of::<PackedEncounter>();
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            entries: HashMap::with_capacity(capacity),
+            ..Self::default()
+        }
+    }
+
+    /// A table that keys its entries by `compact` instead of [CompactElId::new], e.g. to force
+    /// collisions in tests.
+    pub(crate) fn with_compaction(compact: fn(&[u8; G1G2::ENCODED_SIZE]) -> CompactElId) -> Self {
+        Self {
+            entries: HashMap::new(),
+            collisions: HashMap::new(),
+            compact,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len() + self.collisions.values().map(Vec::len).sum::<usize>()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The numbers of entries and of their epochs, e.g. `EncounterTable { entries: 1344,
+    /// epochs: 14 }`, to log the table without its records. It is its [Debug] output.
+    pub fn redacted_debug(&self) -> String {
+        format!("{self:?}")
+    }
+
+    /// Records the broadcast `encounter` of `el_id`, replacing an earlier one of `el_id`.
+    /// Returns whether `el_id` was not in the table.
+    pub fn insert(&mut self, el_id: &ElID, encounter: EncounterRecord) -> bool {
+        self.insert_packed(PackedEncounter::new(el_id, encounter))
+    }
+
+    fn insert_packed(&mut self, packed: PackedEncounter) -> bool {
+        let compact_id = (self.compact)(&packed.el_id);
+        let entry = match self.entries.entry(compact_id) {
+            Entry::Vacant(vacant) => {
+                vacant.insert(packed);
+                return true;
+            }
+            Entry::Occupied(occupied) => occupied.into_mut(),
+        };
+        if entry.el_id == packed.el_id {
+            *entry = packed;
+            return false;
+        }
+
+        let colliding = self.collisions.entry(compact_id).or_default();
+        match colliding
+            .iter_mut()
+            .find(|other| other.el_id == packed.el_id)
+        {
+            Some(other) => {
+                *other = packed;
+                false
+            }
+            None => {
+                colliding.push(packed);
+                true
+            }
+        }
+    }
+
+    pub fn contains(&self, el_id: &ElID) -> bool {
+        self.get(el_id).is_some()
+    }
+
+    pub(crate) fn lookup(&self, el_id: &ElID) -> Option<EncounterRecord> {
+        self.get(el_id).and_then(PackedEncounter::unpack)
+    }
+
+    // the entry of `el_id`, and not of another ElID of the same compact ID
+    fn get(&self, el_id: &ElID) -> Option<&PackedEncounter> {
+        let encoding = el_id.to_bytes();
+        self.records((self.compact)(&encoding))
+            .find(|packed| packed.el_id == encoding)
+    }
+
+    // the records of all ElIDs of the compact ID `compact_id`
+    fn records(&self, compact_id: CompactElId) -> impl Iterator<Item = &PackedEncounter> {
+        self.entries
+            .get(&compact_id)
+            .into_iter()
+            .chain(self.collisions.get(&compact_id).into_iter().flatten())
+    }
+
+    // the records of all ElIDs, in no particular order
+    fn all_records(&self) -> impl Iterator<Item = &PackedEncounter> {
+        self.entries
+            .values()
+            .chain(self.collisions.values().flatten())
+    }
+
+    /// The ElID and the encounter of the compact ID `compact_id`, or of one of the ElIDs that
+    /// collide on it.
+    pub(crate) fn lookup_compact(
+        &self,
+        compact_id: &CompactElId,
+    ) -> Option<(ElID, EncounterRecord)> {
+        self.entries
+            .get(compact_id)
+            .and_then(PackedEncounter::expand)
+    }
+
+    /// Removes the encounters of epochs before `oldest_allowed` and returns their number, see
+    /// [prune_encounters].
+    pub fn prune(&mut self, oldest_allowed: Epoch) -> usize {
+        let len = self.len();
+        self.entries
+            .retain(|_, packed| packed.epoch >= oldest_allowed);
+        // a colliding record takes the place of a pruned entry of its compact ID
+        let entries = &mut self.entries;
+        self.collisions.retain(|compact_id, colliding| {
+            colliding.retain(|packed| packed.epoch >= oldest_allowed);
+            if !entries.contains_key(compact_id) {
+                entries.extend(colliding.pop().map(|packed| (*compact_id, packed)));
+            }
+            !colliding.is_empty()
+        });
+        len - self.len()
+    }
+
+    /// The ElIDs of the table and their encounters, in no particular order.
+    pub(crate) fn iter(&self) -> impl Iterator<Item = (ElID, EncounterRecord)> {
+        self.all_records().filter_map(PackedEncounter::expand)
+    }
+
+    /// The ElIDs of the table, in no particular order, without expanding their encounters.
+    pub fn el_ids(&self) -> impl Iterator<Item = ElID> {
+        self.all_records().filter_map(PackedEncounter::el_id)
+    }
+
+    /// Removes all encounters and returns them, see [RetainedEncounters::append].
+    pub(crate) fn drain(&mut self) -> impl Iterator<Item = (ElID, EncounterRecord)> {
+        self.entries
+            .drain()
+            .map(|(_, packed)| packed)
+            .chain(self.collisions.drain().flat_map(|(_, colliding)| colliding))
+            .filter_map(|packed| packed.expand())
+    }
+
+    /// The table as an [envelope] artifact of type [ArtifactType::EncounterTable], which is the
+    /// plaintext of a [backup](Self::export_for_backup). The payload is the number of entries as
+    /// little-endian `u64`, the entries and the SHA-256 checksum of everything in front of it. An
+    /// entry is the compressed ElID, the secret, the compressed ciphertext, and the weight,
+    /// epoch, index, duration and attenuation as little-endian integers, with the index as `u64`.
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes =
+            Vec::with_capacity(HEADER_SIZE + 8 + self.len() * PackedEncounter::ENCODED_SIZE + 32);
+        bytes.extend_from_slice(&envelope::header(ArtifactType::EncounterTable));
+        bytes.extend_from_slice(&(self.len() as u64).to_le_bytes());
+        for packed in self.all_records() {
+            packed.write(&mut bytes);
+        }
+        let checksum = Sha256::digest(&bytes);
+        bytes.extend_from_slice(&checksum);
+        bytes
+    }
+
+    /// Reads a table of [Self::to_bytes]. An entry that does not decode, e.g. with a point that
+    /// is not on the curve, fails the whole table, see [Self::from_bytes_with].
+    pub(crate) fn from_bytes(bytes: &[u8]) -> Result<Self, DecodeError> {
+        Self::from_bytes_with(bytes, LoadMode::Strict).map(|(table, _)| table)
+    }
+
+    /// Like [Self::from_bytes], but with [LoadMode::Lenient] the entries that do not decode are
+    /// skipped and returned as warnings with their offsets, so the other encounters survive the
+    /// corruption of one. Tables whose header, length or checksum is invalid are rejected in
+    /// both modes.
+    pub(crate) fn from_bytes_with(
+        bytes: &[u8],
+        mode: LoadMode,
+    ) -> Result<(Self, Vec<LoadWarning>), DecodeError> {
+        match envelope::open(bytes, ArtifactType::EncounterTable)? {
+            (1, _) => Self::from_bytes_v1(bytes, mode),
+            (version, _) => Err(DecodeError::UnsupportedVersion(version)),
+        }
+    }
+
+    /// Table of a version-1 artifact `bytes`, including the header.
+    fn from_bytes_v1(
+        bytes: &[u8],
+        mode: LoadMode,
+    ) -> Result<(Self, Vec<LoadWarning>), DecodeError> {
+        let Some((len, rest)) = bytes[HEADER_SIZE..].split_first_chunk() else {
+            return Err(DecodeError::InvalidLength {
+                expected: HEADER_SIZE + 8 + 32,
+                actual: bytes.len(),
+            });
+        };
+        let len = u64::from_le_bytes(*len) as usize;
+        let expected = len
+            .saturating_mul(PackedEncounter::ENCODED_SIZE)
+            .saturating_add(HEADER_SIZE + 8 + 32);
+        if bytes.len() != expected {
+            return Err(DecodeError::InvalidLength {
+                expected,
+                actual: bytes.len(),
+            });
+        }
+        let (entries, checksum) = rest.split_at(rest.len() - 32);
+        if Sha256::digest(&bytes[..bytes.len() - 32])[..] != *checksum {
+            return Err(DecodeError::ChecksumMismatch);
+        }
+
+        let mut table = Self::with_capacity(len);
+        let mut warnings = Vec::new();
+        for (entry, bytes) in entries
+            .chunks_exact(PackedEncounter::ENCODED_SIZE)
+            .enumerate()
+        {
+            match (PackedEncounter::read(bytes), mode) {
+                (Ok(packed), _) => {
+                    table.insert_packed(packed);
+                }
+                (Err(reason), LoadMode::Lenient) => warnings.push(LoadWarning {
+                    offset: (HEADER_SIZE + 8 + entry * PackedEncounter::ENCODED_SIZE) as u64,
+                    reason,
+                }),
+                (Err(reason), LoadMode::Strict) => return Err(reason),
+            }
+        }
+        Ok((table, warnings))
+    }
+}
+
+impl Extend<(ElID, EncounterRecord)> for EncounterTable {
+    fn extend<I: IntoIterator<Item = (ElID, EncounterRecord)>>(&mut self, iter: I) {
+        for (el_id, encounter) in iter {
+            self.insert(&el_id, encounter);
+        }
+    }
+}
+
+impl fmt::Debug for EncounterTable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let epochs: BTreeSet<_> = self.all_records().map(|packed| packed.epoch).collect();
+        f.debug_struct("EncounterTable")
+            .field("entries", &self.len())
+            .field("epochs", &epochs.len())
+            .finish()
+    }
+}
+
+/// Removes the encounters of `t_el` of epochs before `oldest_allowed`, e.g. the encounters older
+/// than the retention period of a privacy regulation, and returns the number of removed
+/// encounters. Removed encounters no longer match in [spirit_trace].
+pub fn prune_encounters(t_el: &mut EncounterTable, oldest_allowed: Epoch) -> usize {
+    t_el.prune(oldest_allowed)
+}
+
+/// Encounter table that retains the encounters of the last `retention` epochs only, up to the
+/// latest epoch inserted or [advanced](Self::advance) to. Every insert of a later epoch prunes
+/// the encounters that fell out of the window, as in [prune_encounters]. A retention of 0 is
+/// treated like 1.
+///
+/// Encounters are broadcast into a scratch [EncounterTable] and moved over with
+/// [append](Self::append), and traced in [Self::table].
+#[derive(Debug)]
+pub struct RetainedEncounters {
+    table: EncounterTable,
+    retention: Epoch,
+    latest: Epoch,
+}
+
+impl RetainedEncounters {
+    pub fn new(retention: Epoch) -> Self {
+        Self {
+            table: EncounterTable::default(),
+            retention: retention.max(1),
+            latest: 0,
+        }
+    }
+
+    /// First epoch of the window.
+    pub fn oldest_allowed(&self) -> Epoch {
+        (self.latest + 1).saturating_sub(self.retention)
+    }
+
+    /// Inserts the encounter `el_id`, unless its epoch is before the window. Returns whether it
+    /// was inserted.
+    pub fn insert(&mut self, el_id: ElID, encounter: EncounterRecord) -> bool {
+        self.advance(encounter.epoch);
+        if encounter.epoch < self.oldest_allowed() {
+            return false;
+        }
+        self.table.insert(&el_id, encounter);
+        true
+    }
+
+    /// Moves the window to end at `epoch`, e.g. at the start of a new day without encounters,
+    /// and returns the number of pruned encounters. Earlier epochs leave the window as it is.
+    pub fn advance(&mut self, epoch: Epoch) -> usize {
+        if epoch <= self.latest {
+            return 0;
+        }
+        self.latest = epoch;
+        prune_encounters(&mut self.table, self.oldest_allowed())
+    }
+
+    /// Moves the encounters of `scratch` into the window, leaving it empty.
+    pub fn append(&mut self, scratch: &mut EncounterTable) {
+        self.extend(scratch.drain());
+    }
+
+    pub fn table(&self) -> &EncounterTable {
+        &self.table
+    }
+
+    pub fn len(&self) -> usize {
+        self.table.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.table.is_empty()
+    }
+}
+
+impl Extend<(ElID, EncounterRecord)> for RetainedEncounters {
+    fn extend<I: IntoIterator<Item = (ElID, EncounterRecord)>>(&mut self, iter: I) {
+        for (el_id, encounter) in iter {
+            self.insert(el_id, encounter);
+        }
+    }
+}
+
+/// The encounter secret `es_i` encoded by the group element `m`.
+fn encounter_secret(m: &G1G2) -> Scalar {
+    let digest = Sha256::new()
+        .chain_update(ENCOUNTER_SECRET_DST)
+        .chain_update(m.to_bytes())
+        .finalize();
+    Scalar::from_le_bytes_mod_order(&digest)
+}
+
+/// A fresh encounter secret `es_i` and its encryption to `pk_ha`. The group element that
+/// encodes `es_i` is encrypted, as ElGamal encrypts group elements.
+fn encrypted_encounter_secret(
+    pk_ha: &EncryptionKey,
+    rng: &mut (impl RngCore + CryptoRng),
+) -> (Scalar, Ciphertext) {
+    let m = G1G2::random(&mut *rng);
+    (encounter_secret(&m), pk_ha.encrypt(&m, rng))
+}
+
+/// The encounter secret `es_i` of a ciphertext [EncounterRecord::encrypted_secret], or of any
+/// rerandomization of it, for the health authority's decryption key `sk_ha`.
+pub fn decrypt_encounter(sk_ha: &DecryptionKey, ciphertext: &Ciphertext) -> Scalar {
+    encounter_secret(&sk_ha.decrypt(ciphertext))
+}
+
+/// Share of the decryption of an uploaded encounter ciphertext by `issuer`, the issuer of index
+/// `index` in the committee as in [aggregate_unblind_from].
+/// The ciphertext has to be encrypted to the committee's key [tACTPublicParameters::decryption_key]
+/// instead of a single health authority's key. Shares are checked with
+/// [PartialDecryption::verify] against the issuer's key in
+/// [tACTPublicParameters::decryption_keys] and combined with [combine_decryptions]. Fails for
+/// issuers without a share of the decryption key, e.g. of a committee set up by the
+/// [dkg](crate::dkg).
+#[cfg(feature = "std")]
+pub fn partial_decrypt(
+    issuer: &Issuer,
+    index: usize,
+    ciphertext: &Ciphertext,
+    rng: &mut (impl RngCore + CryptoRng),
+) -> Result<PartialDecryption, SpiritError> {
+    let dk = issuer
+        .decryption_share()
+        .ok_or(SpiritError::NoDecryptionShare)?;
+    Ok(PartialDecryption::new(&dk.0, index, ciphertext, rng))
+}
+
+/// The encounter secret `es_i` of the ciphertext of `partials`, interpolated from the shares of
+/// `pp.t` distinct issuers as in [aggregate_unblind]. With shares of fewer issuers, or with
+/// shares that do not verify, the result is unrelated to `es_i`.
+pub fn combine_decryptions(partials: &[PartialDecryption]) -> Scalar {
+    encounter_secret(&elgamal::combine(partials))
+}
+
+/// [spirit_broadcast_weighted] with weight 1.
+pub fn spirit_broadcast(
+    domain: &DomainConfig,
+    epoch: Epoch,
+    i: Interval,
+    keys: &UserKeys,
+    pk_ha: &EncryptionKey,
+    t_el: &mut EncounterTable,
+    rng: &mut (impl RngCore + CryptoRng),
+) -> (ElID, Scalar) {
+    spirit_broadcast_weighted(domain, epoch, i, 1, keys, pk_ha, t_el, rng)
+}
+
+/// [spirit_broadcast] with randomness from [OsRng].
+#[cfg(feature = "std")]
+pub fn spirit_broadcast_osrng(
+    domain: &DomainConfig,
+    epoch: Epoch,
+    i: Interval,
+    keys: &UserKeys,
+    pk_ha: &EncryptionKey,
+    t_el: &mut EncounterTable,
+) -> (ElID, Scalar) {
+    spirit_broadcast(domain, epoch, i, keys, pk_ha, t_el, &mut OsRng)
+}
+
+/// Broadcasts the ElID of interval `i` of `epoch`, records it in `t_el` together with a fresh
+/// secret `es_i`, its encryption to the health authority's key `pk_ha` and `weight`, and returns
+/// the new `(ElID, es_i)` pair.
+#[allow(clippy::too_many_arguments)]
+pub fn spirit_broadcast_weighted(
+    domain: &DomainConfig,
+    epoch: Epoch,
+    i: Interval,
+    weight: u32,
+    keys: &UserKeys,
+    pk_ha: &EncryptionKey,
+    t_el: &mut EncounterTable,
+    rng: &mut (impl RngCore + CryptoRng),
+) -> (ElID, Scalar) {
+    let el_id = prf_broadcast(domain, &keys.epoch_key(epoch), epoch, i);
+
+    let (es_i, encrypted_secret) = encrypted_encounter_secret(pk_ha, rng);
+    t_el.insert(
+        &el_id,
+        EncounterRecord {
+            secret: es_i,
+            encrypted_secret,
+            weight,
+            epoch,
+            index: i,
+        },
+    );
+
+    (el_id, es_i)
+}
+
+/// [spirit_broadcast_weighted] with randomness from [OsRng].
+#[cfg(feature = "std")]
+pub fn spirit_broadcast_weighted_osrng(
+    domain: &DomainConfig,
+    epoch: Epoch,
+    i: Interval,
+    weight: u32,
+    keys: &UserKeys,
+    pk_ha: &EncryptionKey,
+    t_el: &mut EncounterTable,
+) -> (ElID, Scalar) {
+    spirit_broadcast_weighted(domain, epoch, i, weight, keys, pk_ha, t_el, &mut OsRng)
+}
+
+/// Broadcasts the ElIDs of the indices `start..end` of `epoch` with weight 1, e.g. of all
+/// intervals of a day, and returns the newly generated `(index, ElID)` pairs in index order.
+/// Indices whose ElID is already in `t_el` are skipped and keep their secret `es_i`. New secrets
+/// are encrypted to `pk_ha` as in [spirit_broadcast_weighted].
+///
+/// The epoch key and the PRF tag are derived once for the whole range. With the `rayon`
+/// feature, the hash-to-curve and scalar multiplications run in parallel.
+#[allow(clippy::too_many_arguments)]
+pub fn spirit_broadcast_range(
+    domain: &DomainConfig,
+    epoch: Epoch,
+    start: Interval,
+    end: Interval,
+    keys: &UserKeys,
+    pk_ha: &EncryptionKey,
+    t_el: &mut EncounterTable,
+    rng: &mut (impl RngCore + CryptoRng),
+) -> Vec<(Interval, ElID)> {
+    let el_ids = prf_range(domain, &keys.epoch_key(epoch), epoch, start..end);
+    Interval::range(start..end)
+        .zip(el_ids)
+        .filter_map(|(index, el_id)| {
+            if t_el.contains(&el_id) {
+                return None;
+            }
+            let (secret, encrypted_secret) = encrypted_encounter_secret(pk_ha, rng);
+            t_el.insert(
+                &el_id,
+                EncounterRecord {
+                    secret,
+                    encrypted_secret,
+                    weight: 1,
+                    epoch,
+                    index,
+                },
+            );
+            Some((index, el_id))
+        })
+        .collect()
+}
+
+/// [spirit_broadcast_range] with randomness from [OsRng].
+#[cfg(feature = "std")]
+pub fn spirit_broadcast_range_osrng(
+    domain: &DomainConfig,
+    epoch: Epoch,
+    start: Interval,
+    end: Interval,
+    keys: &UserKeys,
+    pk_ha: &EncryptionKey,
+    t_el: &mut EncounterTable,
+) -> Vec<(Interval, ElID)> {
+    spirit_broadcast_range(domain, epoch, start, end, keys, pk_ha, t_el, &mut OsRng)
+}
+
+/// Truncated hash of an ElID that fits into a BLE advertisement, unlike the ElID's 144 bytes.
+/// The health authority compacts the diagnosed users' ElIDs before publishing `cf`, which is
+/// traced with [spirit_trace_compact].
+///
+/// Different ElIDs collide with probability `2^-128`, so accidental collisions are negligible.
+/// A collision only ever causes a false positive match, never a missed one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CompactElId(pub(crate) [u8; 16]);
+
+impl CompactElId {
+    pub const SIZE: usize = 16;
+
+    pub fn new(el_id: &ElID) -> Self {
+        Self::of_encoding(&el_id.to_bytes())
+    }
+
+    fn of_encoding(encoding: &[u8; G1G2::ENCODED_SIZE]) -> Self {
+        let digest = Sha256::new()
+            .chain_update(COMPACT_EL_ID_DST)
+            .chain_update(encoding)
+            .finalize();
+        let mut id = [0; Self::SIZE];
+        id.copy_from_slice(&digest[..Self::SIZE]);
+        Self(id)
+    }
+
+    pub fn to_bytes(&self) -> [u8; 16] {
+        self.0
+    }
+
+    pub fn from_bytes(bytes: [u8; 16]) -> Self {
+        Self(bytes)
+    }
+}
+
+impl From<&ElID> for CompactElId {
+    fn from(el_id: &ElID) -> Self {
+        Self::new(el_id)
+    }
+}
+
+/// [spirit_broadcast], but returns the [CompactElId] to advertise instead of the ElID. `t_el`
+/// still records the full ElID.
+pub fn spirit_broadcast_compact(
+    domain: &DomainConfig,
+    epoch: Epoch,
+    i: Interval,
+    keys: &UserKeys,
+    pk_ha: &EncryptionKey,
+    t_el: &mut EncounterTable,
+    rng: &mut (impl RngCore + CryptoRng),
+) -> (CompactElId, Scalar) {
+    let (el_id, es_i) = spirit_broadcast(domain, epoch, i, keys, pk_ha, t_el, rng);
+    (CompactElId::new(&el_id), es_i)
+}
+
+/// ElID of a decoy broadcast in interval `i` of `epoch`: the PRF output `H(epoch, i)^r` for a
+/// random key `r`. Like a real ElID, both of its points are the same power of the public base
+/// `H(epoch, i)`, so it passes the pairing check `e(ElID_1, H_2) = e(H_1, ElID_2)` that tells
+/// uniformly random points apart, and without the user's keys it is indistinguishable from a
+/// real broadcast. Decoys thus hide how many broadcasts of a device are real. They are not
+/// recorded in any encounter table and their keys are discarded, so they never match in a trace
+/// and are never disclosed in a diagnosis.
+pub fn spirit_broadcast_decoy(
+    domain: &DomainConfig,
+    epoch: Epoch,
+    i: Interval,
+    rng: &mut (impl RngCore + CryptoRng),
+) -> ElID {
+    prf_broadcast(domain, &Scalar::rand(rng), epoch, i)
+}
+
+/// A broadcast of a [BroadcastScheduler]. Whether it is a decoy is only known to the
+/// broadcasting device; the radio sends the ElID either way.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScheduledBroadcast {
+    Real { index: Interval, el_id: ElID },
+    Decoy(ElID),
+}
+
+impl ScheduledBroadcast {
+    pub fn el_id(&self) -> &ElID {
+        match self {
+            Self::Real { el_id, .. } | Self::Decoy(el_id) => el_id,
+        }
+    }
+
+    pub fn is_decoy(&self) -> bool {
+        matches!(self, Self::Decoy(_))
+    }
+}
+
+/// Interleaves the real broadcasts of a device with `decoys_per_broadcast` decoys each, of the
+/// same interval, see [spirit_broadcast_decoy].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BroadcastScheduler {
+    decoys_per_broadcast: usize,
+}
+
+impl BroadcastScheduler {
+    pub fn new(decoys_per_broadcast: usize) -> Self {
+        Self {
+            decoys_per_broadcast,
+        }
+    }
+
+    pub fn decoys_per_broadcast(&self) -> usize {
+        self.decoys_per_broadcast
+    }
+
+    /// Broadcasts the ElIDs of the `indices` of `epoch` with [spirit_broadcast], which records
+    /// them in `t_el`, and returns them together with their decoys in random order, to be sent
+    /// in that order.
+    #[allow(clippy::too_many_arguments)]
+    pub fn schedule(
+        &self,
+        domain: &DomainConfig,
+        epoch: Epoch,
+        indices: Range<Interval>,
+        keys: &UserKeys,
+        pk_ha: &EncryptionKey,
+        t_el: &mut EncounterTable,
+        rng: &mut (impl RngCore + CryptoRng),
+    ) -> Vec<ScheduledBroadcast> {
+        let count = indices.end.since(indices.start) as usize;
+        let mut broadcasts = Vec::with_capacity(count * (self.decoys_per_broadcast + 1));
+        for index in Interval::range(indices) {
+            let (el_id, _) = spirit_broadcast(domain, epoch, index, keys, pk_ha, t_el, rng);
+            broadcasts.push(ScheduledBroadcast::Real { index, el_id });
+            broadcasts.extend(
+                iter::repeat_with(|| {
+                    ScheduledBroadcast::Decoy(spirit_broadcast_decoy(domain, epoch, index, rng))
+                })
+                .take(self.decoys_per_broadcast),
+            );
+        }
+        broadcasts.shuffle(rng);
+        broadcasts
+    }
+}
+
+/// Check-in of a user into `slot` of the venue `venue_id` in `epoch`, e.g. by scanning the
+/// venue's QR code. The user keeps the check-ins it may disclose in a diagnosis, see
+/// [spirit_diagnosis_with_checkins].
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct CheckIn {
+    pub epoch: Epoch,
+    pub venue_id: Vec<u8>,
+    pub slot: Interval,
+}
+
+/// Checks into `slot` of the venue `venue_id` in `epoch`: derives the ElID `H(venue_id, slot)^k`
+/// from the epoch key `k`, records it in `t_el` with weight 1 like [spirit_broadcast], and
+/// returns the new `(ElID, es_i)` pair.
+///
+/// The venue only publishes `venue_id` and its slots, so it never learns `k`. Users checking
+/// into the same slot derive unrelated ElIDs, which a diagnosed user discloses like broadcast
+/// ones and which are traced against the venue's log of check-ins.
+#[allow(clippy::too_many_arguments)]
+pub fn spirit_checkin(
+    domain: &DomainConfig,
+    epoch: Epoch,
+    venue_id: &[u8],
+    slot: Interval,
+    keys: &UserKeys,
+    pk_ha: &EncryptionKey,
+    t_el: &mut EncounterTable,
+    rng: &mut (impl RngCore + CryptoRng),
+) -> (ElID, Scalar) {
+    let el_id = venue_base(domain, venue_id, slot) * keys.epoch_key(epoch);
+
+    let (es_i, encrypted_secret) = encrypted_encounter_secret(pk_ha, rng);
+    t_el.insert(
+        &el_id,
+        EncounterRecord {
+            secret: es_i,
+            encrypted_secret,
+            weight: 1,
+            epoch,
+            index: slot,
+        },
+    );
+
+    (el_id, es_i)
+}
+
+/// Discloses the ElIDs of the `(epoch, index)` pairs in the contact period `cp`. Only the keys of
+/// the epochs occurring in `cp` are derived and committed to.
+/// The report is submitted in `report_epoch` and carries the user's [Nullifier] of that epoch.
+/// Its proofs are bound to the `challenge` the verifier handed out for it.
+///
+/// For a token with attributes, the report reveals the attributes at the indices `disclose`
+/// in a [DisclosureProof], and keeps the others hidden. The identity at index 0 is never
+/// disclosed.
+///
+/// For a user who rotated their keys with [spirit_rotate], the ElIDs of the epochs of `cp`
+/// before the rotation are disclosed with the `previous` keys, and the report carries the
+/// record of the rotation. Key-bound keys prove a single key for every epoch, so they fail with
+/// [SpiritError::RotatedBoundKey] if `previous` is given.
+#[allow(clippy::too_many_arguments)]
+pub fn spirit_diagnosis(
+    domain: &DomainConfig,
+    ppu: &(Token, StRG),
+    keys: &UserKeys,
+    previous: Option<&PreviousKeys>,
+    cp: &HashSet<(Epoch, Interval)>,
+    report_epoch: Epoch,
+    challenge: &ReportChallenge,
+    disclose: &[usize],
+    rng: &mut (impl RngCore + CryptoRng),
+) -> Result<TraceReport, SpiritError> {
+    diagnose(
+        domain,
+        ppu,
+        keys,
+        previous,
+        &[],
+        cp,
+        &[],
+        None,
+        report_epoch,
+        challenge,
+        disclose,
+        rng,
+    )
+}
+
+/// Like [spirit_diagnosis], but also discloses the ElIDs that the devices `devices` broadcast
+/// in the contact period `cp` with their [subkeys](UserKeys::device_keys) of the master keys
+/// `keys`, so that a single report covers all of the user's devices.
+#[allow(clippy::too_many_arguments)]
+pub fn spirit_diagnosis_with_devices(
+    domain: &DomainConfig,
+    ppu: &(Token, StRG),
+    keys: &UserKeys,
+    devices: &[DeviceId],
+    cp: &HashSet<(Epoch, Interval)>,
+    report_epoch: Epoch,
+    challenge: &ReportChallenge,
+    disclose: &[usize],
+    rng: &mut (impl RngCore + CryptoRng),
+) -> Result<TraceReport, SpiritError> {
+    spirit_diagnosis_with_checkins(
+        domain,
+        ppu,
+        keys,
+        devices,
+        cp,
+        &[],
+        report_epoch,
+        challenge,
+        disclose,
+        rng,
+    )
+}
+
+/// Like [spirit_diagnosis_with_devices], but also discloses the ElIDs of the `checkins` made
+/// with the master keys `keys`, see [spirit_checkin]. They follow the master keys' broadcasts
+/// in the report, and are verified and traced like them.
+#[allow(clippy::too_many_arguments)]
+pub fn spirit_diagnosis_with_checkins(
+    domain: &DomainConfig,
+    ppu: &(Token, StRG),
+    keys: &UserKeys,
+    devices: &[DeviceId],
+    cp: &HashSet<(Epoch, Interval)>,
+    checkins: &[CheckIn],
+    report_epoch: Epoch,
+    challenge: &ReportChallenge,
+    disclose: &[usize],
+    rng: &mut (impl RngCore + CryptoRng),
+) -> Result<TraceReport, SpiritError> {
+    diagnose(
+        domain,
+        ppu,
+        keys,
+        None,
+        devices,
+        cp,
+        checkins,
+        None,
+        report_epoch,
+        challenge,
+        disclose,
+        rng,
+    )
+}
+
+/// Like [spirit_diagnosis], under a k-show policy: the report carries the [ShowTag] of an index
+/// drawn at random from the unused ones of `shows`, which is marked as used if the diagnosis
+/// succeeds. Fails with [SpiritError::ShowsExhausted] once all indices are used. Verifiers
+/// accept the report with [spirit_verify_with_shows] if the index is below their limit and the
+/// tag is new.
+#[allow(clippy::too_many_arguments)]
+pub fn spirit_diagnosis_with_show(
+    domain: &DomainConfig,
+    ppu: &(Token, StRG),
+    keys: &UserKeys,
+    cp: &HashSet<(Epoch, Interval)>,
+    shows: &mut ShowCounter,
+    report_epoch: Epoch,
+    challenge: &ReportChallenge,
+    disclose: &[usize],
+    rng: &mut (impl RngCore + CryptoRng),
+) -> Result<TraceReport, SpiritError> {
+    let index = shows
+        .draw(rng)
+        .ok_or(SpiritError::ShowsExhausted { limit: shows.limit })?;
+    let report = diagnose(
+        domain,
+        ppu,
+        keys,
+        None,
+        &[],
+        cp,
+        &[],
+        Some(index),
+        report_epoch,
+        challenge,
+        disclose,
+        rng,
+    )?;
+    shows.used.insert(index);
+    Ok(report)
+}
+
+// the DisclosureProof of a token with attributes, and the commitment to the identity that the
+// report's proofs are about with its opening
+fn report_identity(
+    domain: &DomainConfig,
+    (token, strg): &(Token, StRG),
+    disclose: &[usize],
+    context: &[u8],
+    rng: &mut (impl RngCore + CryptoRng),
+) -> Result<(Option<DisclosureProof>, Commitment, Opening), SpiritError> {
+    let num_attributes = strg.attributes.len() + 1;
+    if let Some(&index) = disclose
+        .iter()
+        .find(|&&index| index == 0 || index >= num_attributes)
+    {
+        return Err(SpiritError::InvalidAttributeIndex {
+            index,
+            num_attributes,
+        });
+    }
+
+    if strg.attributes.is_empty() {
+        return Ok((
+            None,
+            token.identity_commitment(domain),
+            Opening { r: strg.r },
+        ));
+    }
+    let (proof, opening) = token.identity_commitment(domain).prove_disclosure(
+        &strg.messages(),
+        &Opening { r: strg.r },
+        disclose,
+        &MultiBasePublicParameters::new(strg.attributes.len()),
+        context,
+        rng,
+    );
+    let cm = proof.identity().clone();
+    Ok((Some(proof), cm, opening))
+}
+
+/// Proof that the PRF public key of a report of [key-bound](UserKeys::key_bound) keys is `g^prv`
+/// for the key `prv` that the token commits to after the identity, i.e. that the token
+/// commitment is `identity * g^r h_1^prv` for the report's commitment `identity` to the
+/// identity, see [spirit_register_key_bound]. With the [PrfProof]s of the disclosures, the
+/// disclosed ElIDs are `H(epoch, i)^prv`. It carries the challenge instead of the prover's
+/// commitments, which the verifier recomputes from the responses.
+#[derive(CanonicalSerialize, CanonicalDeserialize)]
+pub struct KeyBindingProof {
+    pub(crate) c: Scalar,
+    pub(crate) s_r: Scalar,
+    pub(crate) s_k: Scalar,
+}
+
+// fails unless the token of `ppu` commits to the key of key-bound `keys`, which is never
+// disclosed
+fn check_bound_key(
+    (_, strg): &(Token, StRG),
+    keys: &UserKeys,
+    disclose: &[usize],
+) -> Result<(), SpiritError> {
+    if !keys.bound {
+        return Ok(());
+    }
+    if strg.attributes[..] != [keys.prv] {
+        return Err(SpiritError::UnboundKey);
+    }
+    match disclose.first() {
+        Some(&index) => Err(SpiritError::InvalidAttributeIndex {
+            index,
+            num_attributes: 2,
+        }),
+        None => Ok(()),
+    }
+}
+
+// the KeyBindingProof of a report about `identity` with `identity_opening`, for key-bound keys
+fn prove_key_binding(
+    domain: &DomainConfig,
+    (token, strg): &(Token, StRG),
+    keys: &UserKeys,
+    challenge: &ReportChallenge,
+    identity: &Commitment,
+    identity_opening: &Opening,
+    rng: &mut (impl RngCore + CryptoRng),
+) -> Option<KeyBindingProof> {
+    if !keys.bound {
+        return None;
+    }
+    let pp = get_parameters();
+    let generator = prf_generator(domain);
+    let (rho_r, rho_k) = (Scalar::rand(rng), Scalar::rand(rng));
+    let t = &pp.g * rho_r + key_base() * rho_k;
+    let t_pk = &generator * rho_k;
+    let c = key_binding_challenge(
+        domain,
+        token,
+        challenge,
+        identity,
+        &(generator * keys.prv),
+        &t,
+        &t_pk,
+    );
+    Some(KeyBindingProof {
+        c,
+        s_r: rho_r + c * (strg.r - identity_opening.r),
+        s_k: rho_k + c * keys.prv,
+    })
+}
+
+fn key_binding_challenge(
+    domain: &DomainConfig,
+    token: &Token,
+    challenge: &ReportChallenge,
+    identity: &Commitment,
+    pk: &G1G2,
+    t: &G1G2,
+    t_pk: &G1G2,
+) -> Scalar {
+    let pp = get_parameters();
+    let mut transcript = ProofTranscript::new(KEY_BINDING_PROTOCOL);
+    transcript.append_message(b"deployment", &domain.app_id);
+    transcript.append_message(b"context", &challenge.context());
+    transcript.append_point(b"g", &pp.g);
+    transcript.append_point(b"base", &key_base());
+    transcript.append_point(b"generator", &prf_generator(domain));
+    transcript.append_point(b"cm", &token.identity_commitment(domain).0);
+    transcript.append_point(b"identity", &identity.0);
+    transcript.append_point(b"pk", pk);
+    transcript.append_point(b"t", t);
+    transcript.append_point(b"t_pk", t_pk);
+    transcript.challenge_scalar(b"c")
+}
+
+// the epoch keys of a diagnosis by epoch, with their commitments and openings
+type EpochKeys = BTreeMap<Epoch, (Scalar, Commitment, Opening)>;
+
+// what the disclosures of a diagnosis are proven with: the report's identity commitment with
+// its opening, the context of its challenge, and the epoch keys of the master keys and of every
+// device
+struct DiagnosisSecrets {
+    cm: Commitment,
+    opening: Opening,
+    context: Vec<u8>,
+    master: EpochKeys,
+    devices: Vec<(DeviceId, EpochKeys)>,
+}
+
+impl DiagnosisSecrets {
+    #[allow(clippy::too_many_arguments)]
+    fn disclose(
+        &self,
+        domain: &DomainConfig,
+        strg: &StRG,
+        device: Option<DeviceId>,
+        epoch_keys: &EpochKeys,
+        epoch: Epoch,
+        index: Interval,
+        venue: Option<&Vec<u8>>,
+        rng: &mut (impl RngCore + CryptoRng),
+    ) -> Disclosure {
+        let (prv, cm_prv, o_prv) = &epoch_keys[&epoch];
+        let base = match venue {
+            None => prf_base(domain, epoch, index),
+            Some(venue_id) => venue_base(domain, venue_id, index),
+        };
+        let (el_id, prf_proof) = prf_prove_with_base(domain, prv, &base, rng);
+        let proof = self.cm.proof_2_pk_with_context(
+            &strg.a,
+            &self.opening,
+            cm_prv,
+            prv,
+            o_prv,
+            &base,
+            &el_id,
+            &self.context,
+            rng,
+        );
+        Disclosure {
+            epoch,
+            index,
+            device,
+            venue: venue.cloned(),
+            el_id,
+            proof,
+            prf_proof,
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn diagnose(
+    domain: &DomainConfig,
+    ppu: &(Token, StRG),
+    keys: &UserKeys,
+    previous: Option<&PreviousKeys>,
+    devices: &[DeviceId],
+    cp: &HashSet<(Epoch, Interval)>,
+    checkins: &[CheckIn],
+    show: Option<usize>,
+    report_epoch: Epoch,
+    challenge: &ReportChallenge,
+    disclose: &[usize],
+    rng: &mut (impl RngCore + CryptoRng),
+) -> Result<TraceReport, SpiritError> {
+    let epochs: BTreeSet<_> = cp.iter().map(|(epoch, _)| *epoch).collect();
+    let mut indices: Vec<_> = cp.iter().copied().collect();
+    indices.sort_unstable();
+    let mut sorted: Vec<_> = checkins.iter().collect();
+    sorted.sort_unstable();
+    sorted.dedup();
+
+    let disclosures = |secrets: &DiagnosisSecrets, rng: &mut _| {
+        let key_sets = iter::once((None, &secrets.master)).chain(
+            secrets
+                .devices
+                .iter()
+                .map(|(device, epoch_keys)| (Some(*device), epoch_keys)),
+        );
+        key_sets
+            .flat_map(|(device, epoch_keys)| {
+                let checkins: &[&CheckIn] = if device.is_none() { &sorted } else { &[] };
+                indices
+                    .iter()
+                    .map(move |&(epoch, index)| (device, epoch_keys, epoch, index, None))
+                    .chain(checkins.iter().map(move |checkin| {
+                        let venue = Some(&checkin.venue_id);
+                        (device, epoch_keys, checkin.epoch, checkin.slot, venue)
+                    }))
+            })
+            .map(|(device, epoch_keys, epoch, index, venue)| {
+                secrets.disclose(domain, &ppu.1, device, epoch_keys, epoch, index, venue, rng)
+            })
+            .collect()
+    };
+    let (report, _) = diagnosis_header(
+        domain,
+        ppu,
+        keys,
+        previous,
+        devices,
+        &epochs,
+        checkins,
+        show,
+        report_epoch,
+        challenge,
+        disclose,
+        disclosures,
+        rng,
+    )?;
+    Ok(report)
+}
+
+// the randomness of a disclosure: a scalar for its PRF proof and four for its Proof2PK
+const DISCLOSURE_SCALARS: usize = 5;
+
+// the report of a diagnosis, and the secrets its disclosures are proven with. Only the keys of
+// `epochs`, and for the master keys also of the epochs of `checkins`, are derived and committed
+// to. The disclosures are proven by `disclosures` after the key commitments and before the
+// nullifier, which keeps the order in which the randomness is drawn.
+#[allow(clippy::too_many_arguments)]
+fn diagnosis_header<R: RngCore + CryptoRng>(
+    domain: &DomainConfig,
+    ppu: &(Token, StRG),
+    keys: &UserKeys,
+    previous: Option<&PreviousKeys>,
+    devices: &[DeviceId],
+    epochs: &BTreeSet<Epoch>,
+    checkins: &[CheckIn],
+    show: Option<usize>,
+    report_epoch: Epoch,
+    challenge: &ReportChallenge,
+    disclose: &[usize],
+    disclosures: impl FnOnce(&DiagnosisSecrets, &mut R) -> Vec<Disclosure>,
+    rng: &mut R,
+) -> Result<(TraceReport, DiagnosisSecrets), SpiritError> {
+    let (token, strg) = ppu;
+    let context = challenge.context();
+    if keys.bound && previous.is_some() {
+        return Err(SpiritError::RotatedBoundKey);
+    }
+    check_bound_key(ppu, keys, disclose)?;
+    let (attributes, cm, opening) = report_identity(domain, ppu, disclose, &context, rng)?;
+    let key_binding = prove_key_binding(domain, ppu, keys, challenge, &cm, &opening, rng);
+
+    let master_epochs: BTreeSet<_> = checkins
+        .iter()
+        .map(|checkin| checkin.epoch)
+        .chain(epochs.iter().copied())
+        .collect();
+    // the epoch keys of the master keys, and of every device, derived from the previous keys in
+    // the epochs before a rotation
+    let mut epoch_keys_of = |device: Option<DeviceId>| -> EpochKeys {
+        let epochs = if device.is_none() {
+            &master_epochs
+        } else {
+            epochs
+        };
+        epochs
+            .iter()
+            .map(|&epoch| {
+                let master = master_keys(keys, previous, epoch);
+                let prv = match device {
+                    None => master.epoch_key(epoch),
+                    Some(device) => master.device_keys(domain, device).epoch_key(epoch),
+                };
+                let (cm_prv, o_prv) = Commitment::commit_with_randomness(&prv, &Scalar::rand(rng));
+                (epoch, (prv, cm_prv, o_prv))
+            })
+            .collect()
+    };
+    let master = epoch_keys_of(None);
+    let device_key_sets: Vec<_> = devices
+        .iter()
+        .map(|&device| (device, epoch_keys_of(Some(device))))
+        .collect();
+    let secrets = DiagnosisSecrets {
+        cm,
+        opening,
+        context,
+        master,
+        devices: device_key_sets,
+    };
+    let disclosures = disclosures(&secrets, rng);
+
+    let (nullifier, nullifier_proof) = nullifier_prove(
+        domain,
+        token,
+        &strg.a,
+        &secrets.opening,
+        report_epoch,
+        challenge,
+        rng,
+    );
+    let show = show.map(|index| {
+        let (tag, proof) = tag_prove(
+            domain,
+            &show_label(index),
+            &show_base(domain, token.2, index),
+            token,
+            &strg.a,
+            &secrets.opening,
+            report_epoch,
+            challenge,
+            rng,
+        );
+        ShowTag { index, tag, proof }
+    });
+
+    let report = TraceReport {
+        deployment: domain.app_id.clone(),
+        registrar: 0,
+        token: token.clone(),
+        prf_pk: secrets
+            .master
+            .iter()
+            .map(|(epoch, (prv, _, _))| (*epoch, prf_public_key(domain, prv)))
+            .collect(),
+        cm_prv: secrets
+            .master
+            .iter()
+            .map(|(epoch, (_, cm_prv, _))| (*epoch, cm_prv.clone()))
+            .collect(),
+        device_keys: secrets
+            .devices
+            .iter()
+            .flat_map(|(device, epoch_keys)| {
+                let device = *device;
+                epoch_keys.iter().map(move |(epoch, (prv, cm_prv, _))| {
+                    (
+                        (device, *epoch),
+                        (cm_prv.clone(), prf_public_key(domain, prv)),
+                    )
+                })
+            })
+            .collect(),
+        disclosures,
+        report_epoch,
+        challenge: challenge.clone(),
+        nullifier,
+        nullifier_proof,
+        show,
+        attributes,
+        key_binding,
+        authorization: None,
+        attestation: None,
+        // the record only goes with the ElIDs of the previous keys, as it links the tokens
+        rotation: previous
+            .filter(|previous| {
+                master_epochs
+                    .first()
+                    .is_some_and(|epoch| *epoch < previous.until())
+            })
+            .map(|previous| previous.record.clone()),
+    };
+    Ok((report, secrets))
+}
+
+/// [spirit_diagnosis] of keys that were never rotated, with randomness from [OsRng].
+#[cfg(feature = "std")]
+pub fn spirit_diagnosis_osrng(
+    domain: &DomainConfig,
+    ppu: &(Token, StRG),
+    keys: &UserKeys,
+    cp: &HashSet<(Epoch, Interval)>,
+    report_epoch: Epoch,
+    challenge: &ReportChallenge,
+    disclose: &[usize],
+) -> Result<TraceReport, SpiritError> {
+    spirit_diagnosis(
+        domain,
+        ppu,
+        keys,
+        None,
+        cp,
+        report_epoch,
+        challenge,
+        disclose,
+        &mut OsRng,
+    )
+}
+
+/// Disclosure yielded by a [DiagnosisStream], proven like those of a [TraceReport].
+pub type DiagnosisItem = Disclosure;
+
+/// [spirit_diagnosis] of a contact period whose report is too large to hold at once, e.g. on a
+/// phone, see [spirit_diagnose_iter]. The stream yields the disclosures one at a time, in the
+/// order of the report's, to be uploaded in chunks after the [header](Self::header) and verified
+/// with [spirit_verify_stream]. Besides the header, it holds the indices of the contact period
+/// and the keys of its epochs, but no disclosures.
+pub struct DiagnosisStream<'a, R> {
+    domain: &'a DomainConfig,
+    strg: &'a StRG,
+    header: TraceReport,
+    secrets: DiagnosisSecrets,
+    indices: alloc::vec::IntoIter<(Epoch, Interval)>,
+    rng: R,
+}
+
+impl<R> DiagnosisStream<'_, R> {
+    /// The report without its disclosures.
+    pub fn header(&self) -> &TraceReport {
+        &self.header
+    }
+}
+
+impl<R: RngCore + CryptoRng> Iterator for DiagnosisStream<'_, R> {
+    type Item = Result<DiagnosisItem, SpiritError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (epoch, index) = self.indices.next()?;
+        Some(Ok(self.secrets.disclose(
+            self.domain,
+            self.strg,
+            None,
+            &self.secrets.master,
+            epoch,
+            index,
+            None,
+            &mut self.rng,
+        )))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.indices.size_hint()
+    }
+}
+
+impl<R: RngCore + CryptoRng> ExactSizeIterator for DiagnosisStream<'_, R> {}
+
+/// Like [spirit_diagnosis], but returns the report as a [DiagnosisStream] of its header and its
+/// disclosures. With the same randomness, the header with the streamed disclosures is the report
+/// of [spirit_diagnosis]: the nullifier of the header is proven with the randomness left after
+/// the disclosures, which the stream skips over on a clone of `rng` without proving them.
+#[allow(clippy::too_many_arguments)]
+pub fn spirit_diagnose_iter<'a, R: RngCore + CryptoRng + Clone>(
+    domain: &'a DomainConfig,
+    ppu: &'a (Token, StRG),
+    keys: &UserKeys,
+    cp: &HashSet<(Epoch, Interval)>,
+    report_epoch: Epoch,
+    challenge: &ReportChallenge,
+    disclose: &[usize],
+    mut rng: R,
+) -> Result<DiagnosisStream<'a, R>, SpiritError> {
+    let epochs: BTreeSet<_> = cp.iter().map(|(epoch, _)| *epoch).collect();
+    let mut indices: Vec<_> = cp.iter().copied().collect();
+    indices.sort_unstable();
+    let mut disclosure_rng = None;
+    let skip = |_: &DiagnosisSecrets, rng: &mut R| {
+        disclosure_rng = Some(rng.clone());
+        for _ in 0..indices.len() * DISCLOSURE_SCALARS {
+            Scalar::rand(rng);
+        }
+        Vec::new()
+    };
+    let (header, secrets) = diagnosis_header(
+        domain,
+        ppu,
+        keys,
+        None,
+        &[],
+        &epochs,
+        &[],
+        None,
+        report_epoch,
+        challenge,
+        disclose,
+        skip,
+        &mut rng,
+    )?;
+    Ok(DiagnosisStream {
+        domain,
+        strg: &ppu.1,
+        header,
+        secrets,
+        indices: indices.into_iter(),
+        rng: disclosure_rng.unwrap_or(rng),
+    })
+}
+
+/// ElID of a [DiagnosisBundle], with the commitment of the bundle's proof for it.
+#[derive(CanonicalSerialize, CanonicalDeserialize)]
+pub struct BundledElId {
+    pub epoch: Epoch,
+    pub index: Interval,
+    pub el_id: ElID,
+    pub(crate) t: G1G2,
+}
+
+/// Bucket sizes of the number of ElIDs and of the number of epochs of a [DiagnosisBundle],
+/// which [spirit_diagnosis_bundle_padded] fills up with dummies. Bundles whose ElIDs and epochs
+/// are padded to the same sizes encode to the same number of bytes, so the size of an upload
+/// tells neither how many encounters its user had nor over how many epochs.
+///
+/// The dummy epochs are unused epochs next to the real ones, each with a fresh random key, like
+/// the keys of [decoys](spirit_broadcast_decoy), and the dummy ElIDs are the PRF outputs of
+/// these keys. Dummies are thus proved like the real entries, and verifiers cannot tell them
+/// apart, but as their keys are discarded, they match no broadcast in a trace.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BundlePadding {
+    buckets: Vec<usize>,
+    epoch_buckets: Vec<usize>,
+}
+
+impl BundlePadding {
+    /// Padding of the ElIDs to the `buckets` and of the epochs to the `epoch_buckets`, in any
+    /// order. Buckets of 0 are ignored.
+    pub fn new(
+        buckets: impl IntoIterator<Item = usize>,
+        epoch_buckets: impl IntoIterator<Item = usize>,
+    ) -> Self {
+        Self {
+            buckets: sorted_buckets(buckets),
+            epoch_buckets: sorted_buckets(epoch_buckets),
+        }
+    }
+
+    /// The buckets of the ElIDs in ascending order.
+    pub fn buckets(&self) -> &[usize] {
+        &self.buckets
+    }
+
+    /// The buckets of the epochs in ascending order.
+    pub fn epoch_buckets(&self) -> &[usize] {
+        &self.epoch_buckets
+    }
+
+    /// Number of ElIDs of a padded bundle of `len` ElIDs: the smallest bucket that holds them,
+    /// or, for more ElIDs than the largest bucket, the next multiple of the largest bucket.
+    pub fn padded_len(&self, len: usize) -> usize {
+        padded_to(&self.buckets, len)
+    }
+
+    /// Numbers of ElIDs and of epochs of a padded bundle of `len` ElIDs of `epochs` epochs. A
+    /// bundle gets at least one dummy epoch, and every dummy epoch at least one dummy ElID, as
+    /// every epoch of a real bundle has ElIDs. A bundle without ElIDs is not padded, as verifiers
+    /// reject it anyway.
+    pub fn padded_sizes(&self, len: usize, epochs: usize) -> (usize, usize) {
+        if len == 0 {
+            return (len, epochs);
+        }
+        let padded_epochs = padded_to(&self.epoch_buckets, epochs + 1);
+        (self.padded_len(len + padded_epochs - epochs), padded_epochs)
+    }
+
+    // the dummy epochs of a padded bundle of the ElIDs of `cp`, with the indices of their
+    // ElIDs, which are taken from the indices of the real ElIDs as long as there are enough
+    fn dummies(
+        &self,
+        cp: &HashSet<(Epoch, Interval)>,
+        rng: &mut (impl RngCore + CryptoRng),
+    ) -> BTreeMap<Epoch, Vec<Interval>> {
+        let epochs: BTreeSet<_> = cp.iter().map(|(epoch, _)| *epoch).collect();
+        let (Some(first), Some(last)) = (epochs.first(), epochs.last()) else {
+            return BTreeMap::new();
+        };
+        let (len, padded_epochs) = self.padded_sizes(cp.len(), epochs.len());
+        let dummy_epochs: Vec<_> = (0..*first)
+            .rev()
+            .chain(*last..=Epoch::MAX)
+            .filter(|epoch| !epochs.contains(epoch))
+            .take(padded_epochs - epochs.len())
+            .collect();
+        let indices: Vec<_> = cp
+            .iter()
+            .map(|(_, index)| *index)
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect();
+        let dummies = len - cp.len();
+        dummy_epochs
+            .iter()
+            .enumerate()
+            .map(|(k, epoch)| {
+                let count =
+                    dummies / dummy_epochs.len() + usize::from(k < dummies % dummy_epochs.len());
+                let mut chosen: Vec<_> = indices
+                    .choose_multiple(rng, count.min(indices.len()))
+                    .copied()
+                    .collect();
+                let largest = indices[indices.len() - 1];
+                chosen.extend(
+                    (1..)
+                        .map(|step| Interval(largest.0.saturating_add(step)))
+                        .take(count.saturating_sub(indices.len())),
+                );
+                (*epoch, chosen)
+            })
+            .collect()
+    }
+}
+
+fn sorted_buckets(buckets: impl IntoIterator<Item = usize>) -> Vec<usize> {
+    let mut buckets: Vec<_> = buckets.into_iter().filter(|bucket| *bucket > 0).collect();
+    buckets.sort_unstable();
+    buckets.dedup();
+    buckets
+}
+
+// the smallest of the ascending `buckets` that holds `len`, or the next multiple of the largest
+fn padded_to(buckets: &[usize], len: usize) -> usize {
+    match (
+        buckets.iter().find(|bucket| **bucket >= len),
+        buckets.last(),
+    ) {
+        (Some(bucket), _) => *bucket,
+        (None, Some(largest)) => len.div_ceil(*largest) * largest,
+        (None, None) => len,
+    }
+}
+
+/// Commitments and responses of a [BundleProof] for the key of one epoch.
+#[derive(CanonicalSerialize, CanonicalDeserialize)]
+pub struct EpochKeyProof {
+    pub(crate) t_cm: G1G2,
+    pub(crate) t_pk: G1G2,
+    pub(crate) s_o: Scalar,
+    pub(crate) s_k: Scalar,
+}
+
+/// Proof of a [DiagnosisBundle] under a single challenge: of the opening of the identity
+/// commitment, and for every epoch of the opening of its key commitment, that the PRF public
+/// key is of the same key, and that every ElID of the epoch is derived from it.
+#[derive(CanonicalSerialize, CanonicalDeserialize)]
+pub struct BundleProof {
+    pub(crate) t_id: G1G2,
+    pub(crate) s_r: Scalar,
+    pub(crate) s_a: Scalar,
+    pub(crate) epochs: BTreeMap<Epoch, EpochKeyProof>,
+}
+
+/// Trace report whose ElIDs share one proof, see [spirit_diagnosis_bundle]. Per ElID, it
+/// carries a single point besides the ElID instead of a [Proof2PK] and a [PrfProof], so a
+/// bundle takes less than half the space of the [TraceReport] of the same contact period, and
+/// is verified with one multi-scalar multiplication.
+#[derive(CanonicalSerialize, CanonicalDeserialize)]
+pub struct DiagnosisBundle {
+    /// The report's token, epoch keys, nullifier and challenge. Its `disclosures` and
+    /// `device_keys` are empty, and it carries no show tag.
+    pub report: TraceReport,
+    /// The disclosed ElIDs, with the dummies of a padded bundle, ordered by epoch and index.
+    pub el_ids: Vec<BundledElId>,
+    pub proof: BundleProof,
+}
+
+impl DiagnosisBundle {
+    /// Size in bytes of the compressed encoding of the bundle, see [TraceReport::encoded_size].
+    pub fn encoded_size(&self) -> usize {
+        self.compressed_size()
+    }
+}
+
+/// Like [spirit_diagnosis], but discloses the ElIDs of `cp` in a [DiagnosisBundle]. Verifiers
+/// accept a bundle with [spirit_verify_bundle] only as a whole.
+#[allow(clippy::too_many_arguments)]
+pub fn spirit_diagnosis_bundle(
+    domain: &DomainConfig,
+    ppu: &(Token, StRG),
+    keys: &UserKeys,
+    cp: &HashSet<(Epoch, Interval)>,
+    report_epoch: Epoch,
+    challenge: &ReportChallenge,
+    disclose: &[usize],
+    rng: &mut (impl RngCore + CryptoRng),
+) -> Result<DiagnosisBundle, SpiritError> {
+    diagnose_bundle(
+        domain,
+        ppu,
+        keys,
+        cp,
+        report_epoch,
+        challenge,
+        disclose,
+        None,
+        rng,
+    )
+}
+
+/// Like [spirit_diagnosis_bundle], but pads the ElIDs and epochs with dummies to the
+/// [padded sizes](BundlePadding::padded_sizes) of `padding`.
+#[allow(clippy::too_many_arguments)]
+pub fn spirit_diagnosis_bundle_padded(
+    domain: &DomainConfig,
+    ppu: &(Token, StRG),
+    keys: &UserKeys,
+    cp: &HashSet<(Epoch, Interval)>,
+    report_epoch: Epoch,
+    challenge: &ReportChallenge,
+    disclose: &[usize],
+    padding: &BundlePadding,
+    rng: &mut (impl RngCore + CryptoRng),
+) -> Result<DiagnosisBundle, SpiritError> {
+    diagnose_bundle(
+        domain,
+        ppu,
+        keys,
+        cp,
+        report_epoch,
+        challenge,
+        disclose,
+        Some(padding),
+        rng,
+    )
+}
+
+#[allow(clippy::too_many_arguments)]
+fn diagnose_bundle(
+    domain: &DomainConfig,
+    ppu: &(Token, StRG),
+    keys: &UserKeys,
+    cp: &HashSet<(Epoch, Interval)>,
+    report_epoch: Epoch,
+    challenge: &ReportChallenge,
+    disclose: &[usize],
+    padding: Option<&BundlePadding>,
+    rng: &mut (impl RngCore + CryptoRng),
+) -> Result<DiagnosisBundle, SpiritError> {
+    let (token, strg) = ppu;
+    check_bound_key(ppu, keys, disclose)?;
+    let (attributes, cm, opening) =
+        report_identity(domain, ppu, disclose, &challenge.context(), rng)?;
+    let key_binding = prove_key_binding(domain, ppu, keys, challenge, &cm, &opening, rng);
+    let pp = get_parameters();
+    let generator = prf_generator(domain);
+
+    // the key of every epoch, the opening of its commitment and the nonces of both, with the
+    // fresh keys of the dummy epochs, whose ElIDs are disclosed like the real ones
+    let dummies = padding.map_or_else(BTreeMap::new, |padding| padding.dummies(cp, rng));
+    let epochs: BTreeSet<_> = cp.iter().map(|(epoch, _)| *epoch).collect();
+    let mut prvs: Vec<_> = epochs
+        .into_iter()
+        .map(|epoch| (epoch, keys.epoch_key(epoch)))
+        .collect();
+    prvs.extend(dummies.keys().map(|epoch| (*epoch, Scalar::rand(rng))));
+    let epoch_keys: BTreeMap<_, _> = prvs
+        .into_iter()
+        .map(|(epoch, prv)| {
+            let nonces = (Scalar::rand(rng), Scalar::rand(rng));
+            (epoch, (prv, Scalar::rand(rng), nonces))
+        })
+        .collect();
+    let mut indices: Vec<_> = cp.iter().copied().collect();
+    indices.extend(
+        dummies
+            .into_iter()
+            .flat_map(|(epoch, indices)| indices.into_iter().map(move |index| (epoch, index))),
+    );
+    indices.sort_unstable();
+    let el_ids: Vec<_> = indices
+        .into_iter()
+        .map(|(epoch, index)| {
+            let (prv, _, (_, rho_k)) = &epoch_keys[&epoch];
+            let base = prf_base(domain, epoch, index);
+            BundledElId {
+                epoch,
+                index,
+                el_id: &base * *prv,
+                t: base * *rho_k,
+            }
+        })
+        .collect();
+
+    let (nullifier, nullifier_proof) = nullifier_prove(
+        domain,
+        token,
+        &strg.a,
+        &opening,
+        report_epoch,
+        challenge,
+        rng,
+    );
+    let report = TraceReport {
+        deployment: domain.app_id.clone(),
+        registrar: 0,
+        token: token.clone(),
+        cm_prv: epoch_keys
+            .iter()
+            .map(|(epoch, (prv, o_prv, _))| {
+                (*epoch, Commitment::commit_with_randomness(prv, o_prv).0)
+            })
+            .collect(),
+        prf_pk: epoch_keys
+            .iter()
+            .map(|(epoch, (prv, _, _))| (*epoch, &generator * *prv))
+            .collect(),
+        device_keys: BTreeMap::new(),
+        disclosures: Vec::new(),
+        report_epoch,
+        challenge: challenge.clone(),
+        nullifier,
+        nullifier_proof,
+        show: None,
+        attributes,
+        key_binding,
+        authorization: None,
+        attestation: None,
+        rotation: None,
+    };
+
+    let (rho_r, rho_a) = (Scalar::rand(rng), Scalar::rand(rng));
+    let t_id = &pp.g * rho_r + &pp.u * rho_a;
+    let t_epochs: Vec<_> = epoch_keys
+        .values()
+        .map(|(_, _, (rho_o, rho_k))| (&pp.g * *rho_o + &pp.u * *rho_k, &generator * *rho_k))
+        .collect();
+    let c = bundle_challenge(
+        domain,
+        &report,
+        &cm,
+        &el_ids,
+        &t_id,
+        t_epochs.iter().map(|(t_cm, t_pk)| (t_cm, t_pk)),
+    );
+
+    let epochs = epoch_keys
+        .into_iter()
+        .zip(t_epochs)
+        .map(|((epoch, (prv, o_prv, (rho_o, rho_k))), (t_cm, t_pk))| {
+            let proof = EpochKeyProof {
+                t_cm,
+                t_pk,
+                s_o: rho_o + c * o_prv,
+                s_k: rho_k + c * prv,
+            };
+            (epoch, proof)
+        })
+        .collect();
+    Ok(DiagnosisBundle {
+        report,
+        el_ids,
+        proof: BundleProof {
+            t_id,
+            s_r: rho_r + c * opening.r,
+            s_a: rho_a + c * strg.a,
+            epochs,
+        },
+    })
+}
+
+// challenge of the BundleProof of `report` and `el_ids`, with the commitments of the epochs in
+// the order of `report.cm_prv`
+fn bundle_challenge<'a>(
+    domain: &DomainConfig,
+    report: &TraceReport,
+    cm: &Commitment,
+    el_ids: &[BundledElId],
+    t_id: &G1G2,
+    t_epochs: impl Iterator<Item = (&'a G1G2, &'a G1G2)>,
+) -> Scalar {
+    let pp = get_parameters();
+    let mut transcript = ProofTranscript::new(DIAGNOSIS_BUNDLE_PROTOCOL);
+    transcript.append_message(b"deployment", &domain.app_id);
+    transcript.append_message(b"context", &report.challenge.context());
+    transcript.append_u64(b"report_epoch", report.report_epoch);
+    transcript.append_point(b"g", &pp.g);
+    transcript.append_point(b"u", &pp.u);
+    transcript.append_point(b"generator", &prf_generator(domain));
+    transcript.append_point(b"cm", &cm.0);
+    transcript.append_point(b"nullifier", &report.nullifier);
+    transcript.append_point(b"t_id", t_id);
+    for (((epoch, cm_prv), pk), (t_cm, t_pk)) in report
+        .cm_prv
+        .iter()
+        .zip(report.prf_pk.values())
+        .zip(t_epochs)
+    {
+        transcript.append_u64(b"epoch", *epoch);
+        transcript.append_point(b"cm_prv", &cm_prv.0);
+        transcript.append_point(b"pk", pk);
+        transcript.append_point(b"t_cm", t_cm);
+        transcript.append_point(b"t_pk", t_pk);
+    }
+    transcript.append_u64(b"el_ids", el_ids.len() as u64);
+    for el_id in el_ids {
+        transcript.append_u64(b"epoch", el_id.epoch);
+        transcript.append_u64(b"index", el_id.index.0);
+        transcript.append_point(b"el_id", &el_id.el_id);
+        transcript.append_point(b"t", &el_id.t);
+    }
+    transcript.challenge_scalar(b"c")
+}
+
+#[cfg(feature = "std")]
+fn verify_prf_proof(domain: &DomainConfig, tr: &TraceReport, disclosure: &Disclosure) -> bool {
+    tr.disclosure_keys(disclosure).is_some_and(|(_, pk)| {
+        prf_verify_with_base(
+            domain,
+            pk,
+            &disclosure.base(domain),
+            &disclosure.el_id,
+            &disclosure.prf_proof,
+        )
+    })
+}
+
+#[cfg(feature = "std")]
+fn verify_disclosure(domain: &DomainConfig, tr: &TraceReport, disclosure: &Disclosure) -> bool {
+    let Some((cm_prv, _)) = tr.disclosure_keys(disclosure) else {
+        return false;
+    };
+    verify_prf_proof(domain, tr, disclosure)
+        && tr
+            .identity_commitment(domain)
+            .verify_proof_2_pk_with_context(
+                cm_prv,
+                &disclosure.base(domain),
+                &disclosure.el_id,
+                &tr.challenge.context(),
+                &disclosure.proof,
+            )
+            .is_ok()
+}
+
+/// Outcome of a trace report that [spirit_verify] accepts: at least one of its disclosures
+/// verifies.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Accepted {
+    /// Whether each disclosure is accepted, in the order of `tr.disclosures`. The proofs of the
+    /// rejected ones do not verify, see [VerifyRejection::InvalidProof].
+    pub verdicts: Vec<bool>,
+}
+
+/// Work of a verification by [spirit_verify_bundle_metered] or [spirit_verify_batch_metered]:
+/// the pairings and the terms of the multi-scalar multiplications computed, and the compressed
+/// size of the verified bundle or reports.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CostReport {
+    pub pairings: usize,
+    pub msm_terms: usize,
+    pub bytes: usize,
+}
+
+impl CostReport {
+    /// Whether any of the costs is beyond its budget in `limit`.
+    pub fn exceeds(&self, limit: &CostLimit) -> bool {
+        self.pairings > limit.pairings
+            || self.msm_terms > limit.msm_terms
+            || self.bytes > limit.bytes
+    }
+
+    fn add(&mut self, other: &CostReport) {
+        self.pairings += other.pairings;
+        self.msm_terms += other.msm_terms;
+        self.bytes += other.bytes;
+    }
+}
+
+/// Budget of a verification in the costs of a [CostReport], e.g. the quota left to a client.
+/// The default is [CostLimit::UNLIMITED].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CostLimit {
+    pub pairings: usize,
+    pub msm_terms: usize,
+    pub bytes: usize,
+}
+
+impl CostLimit {
+    pub const UNLIMITED: Self = Self {
+        pairings: usize::MAX,
+        msm_terms: usize::MAX,
+        bytes: usize::MAX,
+    };
+}
+
+impl Default for CostLimit {
+    fn default() -> Self {
+        Self::UNLIMITED
+    }
+}
+
+/// Cause for which [spirit_verify] and its variants reject a trace report as a whole. The
+/// causes carry the [identifier](Token::id) of the report's token or the offending ElID, which
+/// are public parts of the report, and never secret data.
+#[derive(Error, Debug, PartialEq, Eq, Clone)]
+pub enum VerifyRejection {
+    #[error("Token {token:02x?} expired at epoch {expiry}.")]
+    Expired { token: TokenId, expiry: Epoch },
+    #[error("Token {token:02x?} is revoked.")]
+    Revoked { token: TokenId },
+    #[error("Token {token:02x?} is not registered.")]
+    Unregistered { token: TokenId },
+    #[error("The registry root is not the registrar's, or older than the latest one.")]
+    InvalidRoot { token: TokenId },
+    #[error("Invalid membership proof for token {token:02x?}.")]
+    InvalidMembership { token: TokenId },
+    #[error("Invalid issuers' signature on token {token:02x?}.")]
+    InvalidSignature { token: TokenId },
+    #[error("Unknown deployment {deployment:?}.")]
+    UnknownDeployment { token: TokenId, deployment: Vec<u8> },
+    #[error("Unknown registrar {registrar}.")]
+    UnknownRegistrar {
+        token: TokenId,
+        registrar: RegistrarId,
+    },
+    #[error("Report of deployment {deployment:?} for another deployment.")]
+    OtherDeployment { token: TokenId, deployment: Vec<u8> },
+    #[error("Report for epoch {report_epoch} outside its reporting window.")]
+    OtherEpoch { token: TokenId, report_epoch: Epoch },
+    #[error("The challenge of the report is not accepted.")]
+    InvalidChallenge { token: TokenId },
+    #[error("Invalid nullifier proof.")]
+    InvalidNullifier { token: TokenId },
+    #[error("The nullifier of the report was accepted before.")]
+    ReplayedNullifier { token: TokenId },
+    #[error("Invalid proof of the disclosed attributes.")]
+    InvalidAttributes { token: TokenId },
+    #[error("The report does not prove that its ElIDs derive from the committed key.")]
+    InvalidKeyBinding { token: TokenId },
+    #[error("The report carries no show tag.")]
+    MissingShow { token: TokenId },
+    #[error("Show {index} is beyond the limit of {limit} shows.")]
+    ShowLimitExceeded {
+        token: TokenId,
+        index: usize,
+        limit: usize,
+    },
+    #[error("The show tag of the report was accepted before.")]
+    ReplayedShow { token: TokenId },
+    #[error("Invalid show tag proof.")]
+    InvalidShow { token: TokenId },
+    #[error("The report has no disclosures.")]
+    NoDisclosures { token: TokenId },
+    #[error("Invalid proof of ElID {el_id:?}.")]
+    InvalidProof { el_id: ElID },
+    #[error("The report carries the identity or a point outside the prime-order subgroup.")]
+    MalformedPoint { token: TokenId },
+    #[error("The report carries no valid authorization of its diagnosis.")]
+    Unauthorized { token: TokenId },
+    #[error("ElID {el_id:?} is not in a broadcast batch anchored before the diagnosis.")]
+    Unanchored { el_id: ElID },
+    #[error("Verifying the report of token {token:02x?} would exceed the cost limit.")]
+    CostExceeded { token: TokenId },
+    #[error("The report of token {token:02x?} carries no valid rotation within the overlap.")]
+    InvalidRotation { token: TokenId },
+}
+
+impl VerifyRejection {
+    /// The [reasons](Self::reason) of all rejections.
+    pub const REASONS: [&'static str; 25] = [
+        "expired",
+        "revoked",
+        "unregistered",
+        "invalid_root",
+        "invalid_membership",
+        "invalid_signature",
+        "unknown_deployment",
+        "other_deployment",
+        "other_epoch",
+        "invalid_challenge",
+        "invalid_nullifier",
+        "replayed_nullifier",
+        "invalid_attributes",
+        "invalid_key_binding",
+        "missing_show",
+        "show_limit_exceeded",
+        "replayed_show",
+        "invalid_show",
+        "no_disclosures",
+        "invalid_proof",
+        "malformed_point",
+        "unknown_registrar",
+        "unauthorized",
+        "unanchored",
+        "cost_exceeded",
+    ];
+
+    /// Name of the cause of the rejection without its fields, e.g. for metrics and logs.
+    pub fn reason(&self) -> &'static str {
+        Self::REASONS[self.reason_index()]
+    }
+
+    // index of the reason in `REASONS`
+    pub(crate) fn reason_index(&self) -> usize {
+        match self {
+            Self::Expired { .. } => 0,
+            Self::Revoked { .. } => 1,
+            Self::Unregistered { .. } => 2,
+            Self::InvalidRoot { .. } => 3,
+            Self::InvalidMembership { .. } => 4,
+            Self::InvalidSignature { .. } => 5,
+            Self::UnknownDeployment { .. } => 6,
+            Self::OtherDeployment { .. } => 7,
+            Self::OtherEpoch { .. } => 8,
+            Self::InvalidChallenge { .. } => 9,
+            Self::InvalidNullifier { .. } => 10,
+            Self::ReplayedNullifier { .. } => 11,
+            Self::InvalidAttributes { .. } => 12,
+            Self::InvalidKeyBinding { .. } => 13,
+            Self::MissingShow { .. } => 14,
+            Self::ShowLimitExceeded { .. } => 15,
+            Self::ReplayedShow { .. } => 16,
+            Self::InvalidShow { .. } => 17,
+            Self::NoDisclosures { .. } => 18,
+            Self::InvalidProof { .. } => 19,
+            Self::MalformedPoint { .. } => 20,
+            Self::UnknownRegistrar { .. } => 21,
+            Self::Unauthorized { .. } => 22,
+            Self::Unanchored { .. } => 23,
+            Self::CostExceeded { .. } => 24,
+        }
+    }
+}
+
+/// Verifies every disclosure of the trace report and adds the accepted ElIDs to the confirmed
+/// contact set `cp`. A disclosure is accepted if the report's token is registered, not revoked
+/// and not expired at `current_epoch`, and its proof verifies; rejected disclosures leave `cp`
+/// untouched. The report is rejected with the first failing check as [VerifyRejection] if no
+/// disclosure is accepted, with [VerifyRejection::InvalidProof] for the first disclosure if
+/// none of the proofs verify.
+///
+/// All disclosures are rejected unless `current_epoch` is within the
+/// [reporting window](DomainConfig::reporting_window) of the report's epoch and its
+/// [Nullifier] is valid and not in `nullifiers`, the nullifiers of the reports accepted so far.
+/// If the report is accepted, its nullifier is added to `nullifiers`. The report's proofs have
+/// to be bound to a [ReportChallenge] that `challenges` accepts at `current_epoch`. Under a
+/// [show limit](DomainConfig::show_limit) of the domain, they are also rejected unless the report
+/// carries a valid [ShowTag] of an index below the limit; replayed tags are only caught by
+/// [spirit_verify_with_shows].
+///
+/// `t_rgstr` is either an exact [TokenRegistry] or a [RegistryFilter](crate::registry::RegistryFilter).
+/// For a registry sharded over several registrars, it is a `HashMap` of the [RegistryHandle]s
+/// of the shards by their [RegistrarId], see [RegistryView::Sharded].
+/// Acceptance against a filter is advisory: a report with an unregistered token passes with the
+/// filter's false-positive rate, so it has to be confirmed against the registrar before acting
+/// on it.
+///
+/// The lookup of the token in an exact registry compares tokens in constant time (see
+/// [Token]), as does the check of the disclosures' [Proof2PK]s. The [DiagnosisAuthorization]
+/// is checked under the [prepared](tsw::PreparedPublicKey) key of the diagnosis authority,
+/// which is prepared once for the reports of a deployment.
+#[cfg(feature = "std")]
+#[cfg_attr(
+    feature = "telemetry",
+    tracing::instrument(
+        level = "debug",
+        skip_all,
+        fields(epoch = current_epoch, disclosures = tr.disclosures.len())
+    )
+)]
+#[allow(clippy::too_many_arguments)]
+pub fn spirit_verify<'a>(
+    domain: &DomainConfig,
+    tr: &TraceReport,
+    t_rgstr: impl Into<RegistryView<'a>>,
+    revoked: &RevocationList,
+    current_epoch: Epoch,
+    nullifiers: &mut HashSet<Nullifier>,
+    challenges: &ChallengeSet,
+    cp: &mut HashSet<ElID>,
+) -> Result<Accepted, VerifyRejection> {
+    let registered = check_points(tr)
+        .and_then(|()| check_valid_at(&tr.token, revoked, current_epoch))
+        .and_then(|()| check_registered(tr, t_rgstr.into()));
+    let verified = verify_disclosures(
+        domain,
+        tr,
+        registered,
+        current_epoch,
+        nullifiers,
+        challenges,
+        cp,
+    );
+    #[cfg(feature = "telemetry")]
+    telemetry::verified(&verified);
+    verified
+}
+
+/// Verification of a trace report uploaded in chunks, see [spirit_verify_stream]. It keeps the
+/// verdicts of the disclosures and the ElIDs accepted so far, but not the chunks.
+#[cfg(feature = "std")]
+pub struct StreamVerifier<'a> {
+    domain: &'a DomainConfig,
+    header: &'a TraceReport,
+    nullifiers: &'a mut HashSet<Nullifier>,
+    cp: &'a mut HashSet<ElID>,
+    // whether each anchor of the header's attestation verifies, if the deployment anchors
+    anchored: Option<Vec<bool>>,
+    verdicts: Vec<bool>,
+    first: Option<ElID>,
+    accepted: Vec<ElID>,
+    // the rejection of the whole report for one of its disclosures
+    rejection: Option<VerifyRejection>,
+}
+
+#[cfg(feature = "std")]
+impl StreamVerifier<'_> {
+    /// Verifies the next `chunk` of the report's disclosures and returns their verdicts. Once a
+    /// disclosure rejects the report, e.g. an ElID outside the prime-order subgroup, the proofs
+    /// of the later ones are no longer checked.
+    pub fn verify_chunk(&mut self, chunk: &[DiagnosisItem]) -> &[bool] {
+        let start = self.verdicts.len();
+        for disclosure in chunk {
+            let i = self.verdicts.len();
+            if !disclosure.el_id.is_valid_element() {
+                if !matches!(self.rejection, Some(VerifyRejection::MalformedPoint { .. })) {
+                    self.rejection = Some(VerifyRejection::MalformedPoint {
+                        token: self.header.token.id(),
+                    });
+                }
+            } else if self.rejection.is_none()
+                && self
+                    .anchored
+                    .as_ref()
+                    .is_some_and(|anchored| !is_anchored(self.header, anchored, i, disclosure))
+            {
+                self.rejection = Some(VerifyRejection::Unanchored {
+                    el_id: disclosure.el_id.clone(),
+                });
+            }
+
+            let verdict =
+                self.rejection.is_none() && verify_disclosure(self.domain, self.header, disclosure);
+            if verdict {
+                self.accepted.push(disclosure.el_id.clone());
+            }
+            if self.first.is_none() {
+                self.first = Some(disclosure.el_id.clone());
+            }
+            self.verdicts.push(verdict);
+        }
+        &self.verdicts[start..]
+    }
+
+    /// Accepts or rejects the report after its last chunk, like [spirit_verify] the whole
+    /// report. If it is accepted, its nullifier is added to the nullifiers and its accepted ElIDs
+    /// to the confirmed contact set.
+    pub fn finish(self) -> Result<Accepted, VerifyRejection> {
+        let verified = match (self.rejection, self.first) {
+            (Some(rejection), _) => Err(rejection),
+            (None, None) => Err(VerifyRejection::NoDisclosures {
+                token: self.header.token.id(),
+            }),
+            (None, Some(first)) if self.accepted.is_empty() => {
+                Err(VerifyRejection::InvalidProof { el_id: first })
+            }
+            (None, Some(_)) => {
+                self.cp.extend(self.accepted);
+                self.nullifiers.insert(self.header.nullifier.clone());
+                Ok(Accepted {
+                    verdicts: self.verdicts,
+                })
+            }
+        };
+        #[cfg(feature = "telemetry")]
+        telemetry::verified(&verified);
+        verified
+    }
+}
+
+/// Like [spirit_verify], for a report uploaded in chunks, e.g. from a [DiagnosisStream]: checks
+/// the `header` of the report, and returns a [StreamVerifier] that verifies the chunks of its
+/// disclosures as they arrive. Disclosures in the header are verified as its first chunk. The
+/// verifier borrows `nullifiers` and `cp` until it [finishes](StreamVerifier::finish), and
+/// changes them only then, if the report is accepted.
+///
+/// The report is accepted or rejected as by [spirit_verify], except that a report whose header
+/// fails a check is rejected for it before any disclosure is seen.
+#[cfg(feature = "std")]
+#[allow(clippy::too_many_arguments)]
+pub fn spirit_verify_stream<'a, 'b>(
+    domain: &'a DomainConfig,
+    header: &'a TraceReport,
+    t_rgstr: impl Into<RegistryView<'b>>,
+    revoked: &RevocationList,
+    current_epoch: Epoch,
+    nullifiers: &'a mut HashSet<Nullifier>,
+    challenges: &ChallengeSet,
+    cp: &'a mut HashSet<ElID>,
+) -> Result<StreamVerifier<'a>, VerifyRejection> {
+    let checked = check_points(header)
+        .and_then(|()| check_valid_at(&header.token, revoked, current_epoch))
+        .and_then(|()| check_registered(header, t_rgstr.into()))
+        .and_then(|()| check_fresh(domain, header, current_epoch, nullifiers, challenges))
+        .and_then(|()| check_attributes(domain, header))
+        .and_then(|()| check_key_binding(domain, header))
+        .and_then(|()| check_authorization(domain, header))
+        .and_then(|()| check_rotation(domain, header));
+    if let Err(rejection) = checked {
+        #[cfg(feature = "telemetry")]
+        telemetry::verified(&Err(rejection.clone()));
+        return Err(rejection);
+    }
+
+    let mut verifier = StreamVerifier {
+        domain,
+        header,
+        nullifiers,
+        cp,
+        anchored: domain
+            .anchoring_registrar
+            .as_ref()
+            .map(|registrar| verified_anchors(header, registrar)),
+        verdicts: Vec::new(),
+        first: None,
+        accepted: Vec::new(),
+        rejection: None,
+    };
+    verifier.verify_chunk(&header.disclosures);
+    Ok(verifier)
+}
+
+/// Like [spirit_verify], but checks registration of the report's token with a membership proof
+/// against a signed Merkle root instead of the whole registry. A root not signed by the
+/// `registrar` with a key valid in the root's epoch, of another registrar than the report's
+/// [registrar](TraceReport::registrar), or older than the root at `latest_root_seq` is rejected
+/// with [VerifyRejection::InvalidRoot], see [SignedRoot::verify_fresh], and a proof for another
+/// root with [VerifyRejection::InvalidMembership].
+#[cfg(feature = "std")]
+#[allow(clippy::too_many_arguments)]
+pub fn spirit_verify_with_proof(
+    domain: &DomainConfig,
+    tr: &TraceReport,
+    root: &SignedRoot,
+    registrar: &impl RegistrarKeys,
+    latest_root_seq: &mut u64,
+    membership: &MembershipProof,
+    revoked: &RevocationList,
+    current_epoch: Epoch,
+    nullifiers: &mut HashSet<Nullifier>,
+    challenges: &ChallengeSet,
+    cp: &mut HashSet<ElID>,
+) -> Result<Accepted, VerifyRejection> {
+    let registered = check_points(tr)
+        .and_then(|()| check_valid_at(&tr.token, revoked, current_epoch))
+        .and_then(|()| check_membership(tr, root, registrar, latest_root_seq, membership));
+    verify_disclosures(
+        domain,
+        tr,
+        registered,
+        current_epoch,
+        nullifiers,
+        challenges,
+        cp,
+    )
+}
+
+/// Like [spirit_verify], for a report made with a [rerandomized](spirit_rerandomize) token.
+/// Instead of looking the token up in a registry, the issuers' signature on it is checked
+/// against the public parameters `pp`, so verifiers cannot link the reports of a user across
+/// epochs. The token is not checked against a [RevocationList], which identifies tokens by
+/// their registered encoding.
+///
+/// The signatures on the commitments `cm_1`, `cm_2` and `cm_3` of three tokens combine to a
+/// signature on `cm_1 cm_2 / cm_3`, which the three holders can open together if the tokens
+/// share their expiry: each expiry has its own base, so the combination of tokens with
+/// different expiries commits to none. Deployments where registered users may collude to
+/// obtain further identities keep verifying registered tokens with [spirit_verify].
+///
+/// The signature is checked under the [prepared key](tACTPublicParameters::prepared_key) of
+/// `pp`, which is prepared on the first verification and reused as long as `pp` is kept.
+#[cfg(feature = "std")]
+#[allow(clippy::too_many_arguments)]
+pub fn spirit_verify_rerandomized(
+    domain: &DomainConfig,
+    tr: &TraceReport,
+    pp: &tACTPublicParameters,
+    current_epoch: Epoch,
+    nullifiers: &mut HashSet<Nullifier>,
+    challenges: &ChallengeSet,
+    cp: &mut HashSet<ElID>,
+) -> Result<Accepted, VerifyRejection> {
+    let registered = check_points(tr)
+        .and_then(|()| check_unexpired(&tr.token, current_epoch))
+        .and_then(|()| check_signature(tr, pp));
+    verify_disclosures(
+        domain,
+        tr,
+        registered,
+        current_epoch,
+        nullifiers,
+        challenges,
+        cp,
+    )
+}
+
+/// Like [spirit_verify], under the k-show policy of `shows`: all disclosures are rejected unless
+/// the report carries a valid [ShowTag] of an index below the limit that is not in `shows`. If
+/// the report is accepted, the tag is added to `shows`.
+#[cfg(feature = "std")]
+#[allow(clippy::too_many_arguments)]
+pub fn spirit_verify_with_shows<'a>(
+    domain: &DomainConfig,
+    tr: &TraceReport,
+    t_rgstr: impl Into<RegistryView<'a>>,
+    revoked: &RevocationList,
+    current_epoch: Epoch,
+    nullifiers: &mut HashSet<Nullifier>,
+    challenges: &ChallengeSet,
+    shows: &mut ShowRegistry,
+    cp: &mut HashSet<ElID>,
+) -> Result<Accepted, VerifyRejection> {
+    let registered = check_points(tr)
+        .and_then(|()| check_valid_at(&tr.token, revoked, current_epoch))
+        .and_then(|()| check_registered(tr, t_rgstr.into()))
+        .and_then(|()| shows.check(domain, tr));
+    let accepted = verify_disclosures(
+        domain,
+        tr,
+        registered,
+        current_epoch,
+        nullifiers,
+        challenges,
+        cp,
+    )?;
+    if let Some(show) = &tr.show {
+        shows.seen.insert(show.tag.clone());
+    }
+    Ok(accepted)
+}
+
+/// Like [spirit_verify], for a [DiagnosisBundle]. The bundle is accepted only as a whole: if
+/// its proof does not verify, it is rejected with [VerifyRejection::InvalidProof] for its first
+/// ElID, as a tampered ElID or commitment changes the challenge of the proof for all of them.
+/// If it is accepted, all of its ElIDs are added to `cp`, and the verdicts are all `true`. The
+/// dummies of a [padded](BundlePadding) bundle cannot be told apart from its real ElIDs and are
+/// added with them, but they match no broadcast, so they never count toward an exposure.
+#[cfg(feature = "std")]
+#[allow(clippy::too_many_arguments)]
+pub fn spirit_verify_bundle<'a>(
+    domain: &DomainConfig,
+    bundle: &DiagnosisBundle,
+    t_rgstr: impl Into<RegistryView<'a>>,
+    revoked: &RevocationList,
+    current_epoch: Epoch,
+    nullifiers: &mut HashSet<Nullifier>,
+    challenges: &ChallengeSet,
+    cp: &mut HashSet<ElID>,
+) -> Result<Accepted, VerifyRejection> {
+    let limit = CostLimit::UNLIMITED;
+    spirit_verify_bundle_metered(
+        domain,
+        bundle,
+        t_rgstr,
+        revoked,
+        current_epoch,
+        nullifiers,
+        challenges,
+        cp,
+        &limit,
+    )
+    .0
+}
+
+/// Like [spirit_verify_bundle], with the [CostReport] of the verification. The costs of the
+/// bundle are bounded from its size before it is verified, and a bundle whose bound exceeds
+/// `limit` is rejected with [VerifyRejection::CostExceeded] without computing any pairing or
+/// multi-scalar multiplication, at no cost.
+#[cfg(feature = "std")]
+#[allow(clippy::too_many_arguments)]
+pub fn spirit_verify_bundle_metered<'a>(
+    domain: &DomainConfig,
+    bundle: &DiagnosisBundle,
+    t_rgstr: impl Into<RegistryView<'a>>,
+    revoked: &RevocationList,
+    current_epoch: Epoch,
+    nullifiers: &mut HashSet<Nullifier>,
+    challenges: &ChallengeSet,
+    cp: &mut HashSet<ElID>,
+    limit: &CostLimit,
+) -> (Result<Accepted, VerifyRejection>, CostReport) {
+    let tr = &bundle.report;
+    let t_rgstr = t_rgstr.into();
+    // the single multi-scalar multiplication of the BundleProof
+    let bound = CostReport {
+        pairings: report_pairings(domain, tr, matches!(t_rgstr, RegistryView::Sharded(_))),
+        msm_terms: 5 + 4 * bundle.proof.epochs.len() + 3 * bundle.el_ids.len(),
+        bytes: bundle.compressed_size(),
+    };
+    if bound.exceeds(limit) {
+        let rejected = Err(VerifyRejection::CostExceeded {
+            token: tr.token.id(),
+        });
+        #[cfg(feature = "telemetry")]
+        telemetry::verified(&rejected);
+        return (rejected, CostReport::default());
+    }
+
+    let (verified, pairings, msm_terms) = metered(|| {
+        check_points(tr)
+            .and_then(|()| check_bundle_points(bundle))
+            .and_then(|()| check_valid_at(&tr.token, revoked, current_epoch))
+            .and_then(|()| check_registered(tr, t_rgstr))
+            .and_then(|()| check_fresh(domain, tr, current_epoch, nullifiers, challenges))
+            .and_then(|()| check_attributes(domain, tr))
+            .and_then(|()| check_key_binding(domain, tr))
+            .and_then(|()| check_authorization(domain, tr))
+            .and_then(|()| check_attestation(domain, tr))
+            .and_then(|()| check_rotation(domain, tr))
+            .and_then(|()| check_show(domain, tr))
+            .and_then(|()| verify_bundle_proof(domain, bundle))
+    });
+    let verified = verified.map(|()| {
+        cp.extend(bundle.el_ids.iter().map(|el_id| el_id.el_id.clone()));
+        nullifiers.insert(tr.nullifier.clone());
+        Accepted {
+            verdicts: vec![true; bundle.el_ids.len()],
+        }
+    });
+    #[cfg(feature = "telemetry")]
+    telemetry::verified(&verified);
+    let cost = CostReport {
+        pairings,
+        msm_terms,
+        bytes: bound.bytes,
+    };
+    (verified, cost)
+}
+
+// bound of the pairings of verifying `tr`: of the authorization, of every anchor of the
+// attestation, of the rotation, and of the registry root of a sharded registry
+#[cfg(feature = "std")]
+fn report_pairings(domain: &DomainConfig, tr: &TraceReport, sharded: bool) -> usize {
+    // each signature is checked with two products of two pairings
+    const SIGNATURE_PAIRINGS: usize = 4;
+
+    let authorization = usize::from(domain.diagnosis_authority.is_some());
+    let anchors = match (&domain.anchoring_registrar, &tr.attestation) {
+        (Some(_), Some(attestation)) => attestation.anchors.len(),
+        _ => 0,
+    };
+    let rotation = usize::from(domain.rotation_registrar.is_some() && tr.rotation.is_some());
+    SIGNATURE_PAIRINGS * (authorization + anchors + rotation + usize::from(sharded))
+}
+
+// the equations of the BundleProof, combined with random 128-bit scalars into a single
+// multi-scalar multiplication
+#[cfg(feature = "std")]
+fn verify_bundle_proof(
+    domain: &DomainConfig,
+    bundle: &DiagnosisBundle,
+) -> Result<(), VerifyRejection> {
+    let DiagnosisBundle {
+        report: tr,
+        el_ids,
+        proof,
+    } = bundle;
+    let Some(first) = el_ids.first() else {
+        return Err(VerifyRejection::NoDisclosures {
+            token: tr.token.id(),
+        });
+    };
+    let invalid = || VerifyRejection::InvalidProof {
+        el_id: first.el_id.clone(),
+    };
+    if !proof.epochs.keys().eq(tr.cm_prv.keys())
+        || !proof.epochs.keys().eq(tr.prf_pk.keys())
+        || el_ids
+            .iter()
+            .any(|el_id| !proof.epochs.contains_key(&el_id.epoch))
+    {
+        return Err(invalid());
+    }
+
+    let pp = get_parameters();
+    let generator = prf_generator(domain);
+    let cm = tr.identity_commitment(domain);
+    let c = bundle_challenge(
+        domain,
+        tr,
+        &cm,
+        el_ids,
+        &proof.t_id,
+        proof
+            .epochs
+            .values()
+            .map(|epoch| (&epoch.t_cm, &epoch.t_pk)),
+    );
+
+    let rho = || Scalar::from(OsRng.r#gen::<u128>());
+    let mut points = Vec::with_capacity(3 * el_ids.len() + 4 * proof.epochs.len() + 5);
+    let mut scalars = Vec::with_capacity(points.capacity());
+
+    // rho * (g * s_r + u * s_a - cm * c - t_id)
+    let rho_id = rho();
+    let mut g_scalar = rho_id * proof.s_r;
+    let mut u_scalar = rho_id * proof.s_a;
+    let mut generator_scalar = Scalar::zero();
+    points.extend([cm.0, proof.t_id.clone()]);
+    scalars.extend([-(rho_id * c), -rho_id]);
+    for (epoch, epoch_proof) in &proof.epochs {
+        // rho_cm * (g * s_o + u * s_k - cm_prv * c - t_cm)
+        let rho_cm = rho();
+        g_scalar += rho_cm * epoch_proof.s_o;
+        u_scalar += rho_cm * epoch_proof.s_k;
+        points.extend([tr.cm_prv[epoch].0.clone(), epoch_proof.t_cm.clone()]);
+        scalars.extend([-(rho_cm * c), -rho_cm]);
+
+        // rho_pk * (generator * s_k - pk * c - t_pk)
+        let rho_pk = rho();
+        generator_scalar += rho_pk * epoch_proof.s_k;
+        points.extend([tr.prf_pk[epoch].clone(), epoch_proof.t_pk.clone()]);
+        scalars.extend([-(rho_pk * c), -rho_pk]);
+    }
+    for el_id in el_ids {
+        // rho_el_id * (base * s_k - el_id * c - t)
+        let rho_el_id = rho();
+        let s_k = proof.epochs[&el_id.epoch].s_k;
+        points.extend([
+            prf_base(domain, el_id.epoch, el_id.index),
+            el_id.el_id.clone(),
+            el_id.t.clone(),
+        ]);
+        scalars.extend([rho_el_id * s_k, -(rho_el_id * c), -rho_el_id]);
+    }
+    points.extend([pp.g.clone(), pp.u.clone(), generator]);
+    scalars.extend([g_scalar, u_scalar, generator_scalar]);
+
+    if msm(&points, &scalars) == G1G2::default() {
+        Ok(())
+    } else {
+        Err(invalid())
+    }
+}
+
+/// Rejects a report with a point that is the identity or outside the prime-order subgroup,
+/// before any pairing or proof is computed with it. With such points, e.g. from a report
+/// decoded without [G1G2::from_bytes], the equations of the proofs may hold for any key. The
+/// points of the [DisclosureProof] are checked too, as its identity commitment also enters the
+/// [KeyBindingProof] with the PRF public keys.
+#[cfg(feature = "std")]
+pub(crate) fn check_points(tr: &TraceReport) -> Result<(), VerifyRejection> {
+    let Token(cm, sigma, _) = &tr.token;
+    let mut points = [&cm.0, &sigma.0, &tr.nullifier]
+        .into_iter()
+        .chain(tr.cm_prv.values().map(|cm| &cm.0))
+        .chain(tr.prf_pk.values())
+        .chain(tr.device_keys.values().flat_map(|(cm, pk)| [&cm.0, pk]))
+        .chain(tr.disclosures.iter().map(|disclosure| &disclosure.el_id))
+        .chain(
+            tr.attributes
+                .iter()
+                .flat_map(|proof| [&proof.identity.0, &proof.t]),
+        );
+    if points.all(G1G2::is_valid_element) {
+        Ok(())
+    } else {
+        Err(VerifyRejection::MalformedPoint {
+            token: tr.token.id(),
+        })
+    }
+}
+
+// the same for the ElIDs of a bundle, which are not disclosures of its report
+#[cfg(feature = "std")]
+fn check_bundle_points(bundle: &DiagnosisBundle) -> Result<(), VerifyRejection> {
+    if bundle
+        .el_ids
+        .iter()
+        .all(|el_id| el_id.el_id.is_valid_element())
+    {
+        Ok(())
+    } else {
+        Err(VerifyRejection::MalformedPoint {
+            token: bundle.report.token.id(),
+        })
+    }
+}
+
+#[cfg(feature = "std")]
+pub(crate) fn check_valid_at(
+    token: &Token,
+    revoked: &RevocationList,
+    current_epoch: Epoch,
+) -> Result<(), VerifyRejection> {
+    check_unexpired(token, current_epoch)?;
+    if revoked.is_revoked(token) {
+        return Err(VerifyRejection::Revoked { token: token.id() });
+    }
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+fn check_unexpired(token: &Token, current_epoch: Epoch) -> Result<(), VerifyRejection> {
+    if token.is_expired(current_epoch) {
+        return Err(VerifyRejection::Expired {
+            token: token.id(),
+            expiry: token.2,
+        });
+    }
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+pub(crate) fn check_signature(
+    tr: &TraceReport,
+    pp: &tACTPublicParameters,
+) -> Result<(), VerifyRejection> {
+    if !tr.token.verify_signature(pp) {
+        return Err(VerifyRejection::InvalidSignature {
+            token: tr.token.id(),
+        });
+    }
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+fn check_registered(tr: &TraceReport, t_rgstr: RegistryView<'_>) -> Result<(), VerifyRejection> {
+    let token = tr.token.id();
+    let shard = match t_rgstr {
+        RegistryView::Sharded(shards) => {
+            shards
+                .get(&tr.registrar)
+                .copied()
+                .ok_or(VerifyRejection::UnknownRegistrar {
+                    token,
+                    registrar: tr.registrar,
+                })?
+        }
+        view => RegistryHandle::View(view),
+    };
+    match shard {
+        RegistryHandle::View(view) if !view.contains(&tr.token) => {
+            Err(VerifyRejection::Unregistered { token })
+        }
+        RegistryHandle::View(_) => Ok(()),
+        RegistryHandle::Root {
+            root,
+            registrar,
+            membership,
+        } => check_membership(tr, root, registrar, &mut 0, membership),
+    }
+}
+
+#[cfg(feature = "std")]
+pub(crate) fn check_membership(
+    tr: &TraceReport,
+    root: &SignedRoot,
+    registrar: &(impl RegistrarKeys + ?Sized),
+    latest_root_seq: &mut u64,
+    membership: &MembershipProof,
+) -> Result<(), VerifyRejection> {
+    let token = tr.token.id();
+    if root.registrar_id != tr.registrar || !root.verify_fresh(registrar, latest_root_seq) {
+        return Err(VerifyRejection::InvalidRoot { token });
+    }
+    if !membership.verify(root, &tr.token) {
+        return Err(VerifyRejection::InvalidMembership { token });
+    }
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+fn check_fresh(
+    domain: &DomainConfig,
+    tr: &TraceReport,
+    current_epoch: Epoch,
+    nullifiers: &HashSet<Nullifier>,
+    challenges: &ChallengeSet,
+) -> Result<(), VerifyRejection> {
+    check_unspent(tr, nullifiers)?;
+    check_current(domain, tr, current_epoch, challenges)
+}
+
+// the report's nullifier is not among the spent `nullifiers`
+#[cfg(feature = "std")]
+fn check_unspent(tr: &TraceReport, nullifiers: &HashSet<Nullifier>) -> Result<(), VerifyRejection> {
+    if nullifiers.contains(&tr.nullifier) {
+        return Err(VerifyRejection::ReplayedNullifier {
+            token: tr.token.id(),
+        });
+    }
+    Ok(())
+}
+
+// the checks of check_fresh that do not depend on the reports accepted before
+#[cfg(feature = "std")]
+fn check_current(
+    domain: &DomainConfig,
+    tr: &TraceReport,
+    current_epoch: Epoch,
+    challenges: &ChallengeSet,
+) -> Result<(), VerifyRejection> {
+    let token = tr.token.id();
+    if tr.deployment != domain.app_id {
+        return Err(VerifyRejection::OtherDeployment {
+            token,
+            deployment: tr.deployment.clone(),
+        });
+    }
+    if !domain.accepts_report_epoch(tr.report_epoch, current_epoch) {
+        return Err(VerifyRejection::OtherEpoch {
+            token,
+            report_epoch: tr.report_epoch,
+        });
+    }
+    if !challenges.accepts(&tr.challenge, current_epoch) {
+        return Err(VerifyRejection::InvalidChallenge { token });
+    }
+    if !nullifier_verify(domain, tr) {
+        return Err(VerifyRejection::InvalidNullifier { token });
+    }
+    Ok(())
+}
+
+// the report's DisclosureProof, if any, opens the token commitment
+#[cfg(feature = "std")]
+fn check_attributes(domain: &DomainConfig, tr: &TraceReport) -> Result<(), VerifyRejection> {
+    let is_valid = tr.attributes.as_ref().is_none_or(|proof| {
+        let generators = MultiBasePublicParameters::new(domain.num_attributes.saturating_sub(1));
+        tr.token
+            .identity_commitment(domain)
+            .verify_disclosure(proof, &generators, &tr.challenge.context())
+            .is_ok()
+    });
+    if !is_valid {
+        return Err(VerifyRejection::InvalidAttributes {
+            token: tr.token.id(),
+        });
+    }
+    Ok(())
+}
+
+// the report's KeyBindingProof, if any or required by the deployment, binds its PRF public keys
+// to the key the token commits to
+#[cfg(feature = "std")]
+fn check_key_binding(domain: &DomainConfig, tr: &TraceReport) -> Result<(), VerifyRejection> {
+    let is_valid = match &tr.key_binding {
+        None => !domain.require_key_binding,
+        Some(proof) => verify_key_binding(domain, tr, proof),
+    };
+    if !is_valid {
+        return Err(VerifyRejection::InvalidKeyBinding {
+            token: tr.token.id(),
+        });
+    }
+    Ok(())
+}
+
+// the prepared key of the diagnosis authority of the last checked authorization, so that a
+// verifier of the reports of one deployment prepares the authority's key only once
+#[cfg(feature = "std")]
+fn prepared_authority(ha_pk: &PublicKey) -> Arc<tsw::PreparedPublicKey> {
+    static LAST: Mutex<Option<Arc<tsw::PreparedPublicKey>>> = Mutex::new(None);
+    let mut last = LAST.lock().unwrap_or_else(PoisonError::into_inner);
+    match &*last {
+        Some(prepared) if prepared.public_key() == ha_pk => prepared.clone(),
+        _ => {
+            let prepared = Arc::new(tsw::PreparedPublicKey::new(ha_pk));
+            *last = Some(prepared.clone());
+            prepared
+        }
+    }
+}
+
+// the report carries an authorization of the token for its report epoch by the deployment's
+// diagnosis authority, if it has one
+#[cfg(feature = "std")]
+fn check_authorization(domain: &DomainConfig, tr: &TraceReport) -> Result<(), VerifyRejection> {
+    let Some(ha_pk) = &domain.diagnosis_authority else {
+        return Ok(());
+    };
+    let is_valid = tr.authorization.as_ref().is_some_and(|authorization| {
+        authorization.diagnosis_epoch == tr.report_epoch
+            && authorization
+                .signature
+                .verify_prepared(
+                    &prepared_authority(ha_pk),
+                    &authorization_tag(domain, &tr.token, tr.report_epoch),
+                    0,
+                    &authorization_parameters(),
+                )
+                .is_ok()
+    });
+    if !is_valid {
+        return Err(VerifyRejection::Unauthorized {
+            token: tr.token.id(),
+        });
+    }
+    Ok(())
+}
+
+// every disclosed ElID is in a batch that the deployment's anchoring registrar, if it has one,
+// anchored before the report's epoch
+#[cfg(feature = "std")]
+fn check_attestation(domain: &DomainConfig, tr: &TraceReport) -> Result<(), VerifyRejection> {
+    let Some(registrar) = &domain.anchoring_registrar else {
+        return Ok(());
+    };
+    let anchored = verified_anchors(tr, registrar);
+    for (i, disclosure) in tr.disclosures.iter().enumerate() {
+        if !is_anchored(tr, &anchored, i, disclosure) {
+            return Err(VerifyRejection::Unanchored {
+                el_id: disclosure.el_id.clone(),
+            });
+        }
+    }
+    Ok(())
+}
+
+// a report that carries a rotation is of the rotation's new token, made within the overlap
+// after the rotation, and the rotation is signed by the deployment's rotation registrar
+#[cfg(feature = "std")]
+fn check_rotation(domain: &DomainConfig, tr: &TraceReport) -> Result<(), VerifyRejection> {
+    let Some(rotation) = &tr.rotation else {
+        return Ok(());
+    };
+    let is_valid = domain.rotation_registrar.as_ref().is_some_and(|registrar| {
+        rotation.new == tr.token.id()
+            && rotation.epoch <= tr.report_epoch
+            && tr.report_epoch - rotation.epoch <= domain.rotation_overlap
+            && rotation.verify(registrar)
+    });
+    if !is_valid {
+        return Err(VerifyRejection::InvalidRotation {
+            token: tr.token.id(),
+        });
+    }
+    Ok(())
+}
+
+// under the deployment's k-show policy, a report carries a valid show tag of an index below the
+// limit
+#[cfg(feature = "std")]
+fn check_show(domain: &DomainConfig, tr: &TraceReport) -> Result<(), VerifyRejection> {
+    match domain.show_limit {
+        Some(limit) => check_show_tag(domain, tr, limit).map(|_| ()),
+        None => Ok(()),
+    }
+}
+
+// whether each anchor of the report's attestation is the registrar's and from before the
+// report's epoch; each anchor's signature is checked once, however many ElIDs it holds
+#[cfg(feature = "std")]
+fn verified_anchors(tr: &TraceReport, registrar: &PublicKey) -> Vec<bool> {
+    tr.attestation
+        .iter()
+        .flat_map(|attestation| &attestation.anchors)
+        .map(|anchor| anchor.epoch < tr.report_epoch && anchor.verify(registrar))
+        .collect()
+}
+
+// whether the `i`-th disclosure of the report is in one of the `anchored` batches of its
+// attestation
+#[cfg(feature = "std")]
+fn is_anchored(tr: &TraceReport, anchored: &[bool], i: usize, disclosure: &Disclosure) -> bool {
+    let Some(attestation) = &tr.attestation else {
+        return false;
+    };
+    let anchors = &attestation.anchors;
+    attestation.paths.get(i).is_some_and(|path| {
+        let leaf = broadcast_leaf(disclosure.epoch, disclosure.index, &disclosure.el_id);
+        let proof = MembershipProof {
+            index: path.index,
+            siblings: path.siblings.clone(),
+        };
+        anchored.get(path.anchor) == Some(&true)
+            && proof.verify_leaf(&anchors[path.anchor].root, anchors[path.anchor].size, leaf)
+    })
+}
+
+// the proof is for a token that commits to the identity and the key only, and for a single PRF
+// public key of all epochs; device keys are never bound
+#[cfg(feature = "std")]
+fn verify_key_binding(domain: &DomainConfig, tr: &TraceReport, proof: &KeyBindingProof) -> bool {
+    let Some(attributes) = &tr.attributes else {
+        return false;
+    };
+    let mut keys = tr.prf_pk.values();
+    let Some(pk) = keys.next() else {
+        return false;
+    };
+    if attributes.num_messages() != 2
+        || !attributes.revealed().is_empty()
+        || !tr.device_keys.is_empty()
+        || keys.any(|other| other != pk)
+    {
+        return false;
+    }
+
+    let pp = get_parameters();
+    let generator = prf_generator(domain);
+    let identity = attributes.identity();
+    let statement = &tr.token.identity_commitment(domain).0 - &identity.0;
+    let t = &pp.g * proof.s_r + key_base() * proof.s_k - statement * proof.c;
+    let t_pk = &generator * proof.s_k - pk * proof.c;
+    let c = key_binding_challenge(domain, &tr.token, &tr.challenge, identity, pk, &t, &t_pk);
+    c == proof.c
+}
+
+// the verdicts of the report's disclosures, if any of them is accepted
+#[cfg(feature = "std")]
+fn verify_any_disclosure(
+    domain: &DomainConfig,
+    tr: &TraceReport,
+) -> Result<Vec<bool>, VerifyRejection> {
+    let Some(first) = tr.disclosures.first() else {
+        return Err(VerifyRejection::NoDisclosures {
+            token: tr.token.id(),
+        });
+    };
+    let verdicts: Vec<_> = tr
+        .disclosures
+        .iter()
+        .map(|disclosure| verify_disclosure(domain, tr, disclosure))
+        .collect();
+    if !verdicts.contains(&true) {
+        return Err(VerifyRejection::InvalidProof {
+            el_id: first.el_id.clone(),
+        });
+    }
+    Ok(verdicts)
+}
+
+#[cfg(feature = "std")]
+pub(crate) fn verify_disclosures(
+    domain: &DomainConfig,
+    tr: &TraceReport,
+    registered: Result<(), VerifyRejection>,
+    current_epoch: Epoch,
+    nullifiers: &mut HashSet<Nullifier>,
+    challenges: &ChallengeSet,
+    cp: &mut HashSet<ElID>,
+) -> Result<Accepted, VerifyRejection> {
+    registered?;
+    check_fresh(domain, tr, current_epoch, nullifiers, challenges)?;
+    check_attributes(domain, tr)?;
+    check_key_binding(domain, tr)?;
+    check_authorization(domain, tr)?;
+    check_attestation(domain, tr)?;
+    check_rotation(domain, tr)?;
+    check_show(domain, tr)?;
+    let verdicts = verify_any_disclosure(domain, tr)?;
+    accept_disclosures(tr, &verdicts, cp);
+    nullifiers.insert(tr.nullifier.clone());
+    Ok(Accepted { verdicts })
+}
+
+// adds the ElIDs of the accepted disclosures of `tr`
+#[cfg(feature = "std")]
+fn accept_disclosures(tr: &TraceReport, verdicts: &[bool], cp: &mut HashSet<ElID>) {
+    cp.extend(
+        tr.disclosures
+            .iter()
+            .zip(verdicts)
+            .filter(|(_, accepted)| **accepted)
+            .map(|(disclosure, _)| disclosure.el_id.clone()),
+    );
+}
+
+/// Verifies a batch of trace reports. A report is accepted if its token is registered, not
+/// revoked and not expired at `current_epoch`, its [Nullifier] and [ReportChallenge] are fresh
+/// as in [spirit_verify], and all of its disclosures verify, in which case all of its ElIDs are
+/// added to `cp` and its nullifier to `nullifiers`. Of several reports with the same nullifier,
+/// at most the first one whose proofs verify is accepted, and the later ones are rejected with
+/// [VerifyRejection::ReplayedNullifier].
+///
+/// The proofs of all reports with a registered token are checked together with
+/// [Proof2PK::verify_batch]. If this check fails, the first invalid proof is located by
+/// bisection and its report rejected, and the proofs of the later reports are checked again the
+/// same way, with the challenges of the first check. The results are returned in the order of
+/// `reports`.
+#[cfg(feature = "std")]
+#[cfg_attr(
+    feature = "telemetry",
+    tracing::instrument(
+        level = "debug",
+        skip_all,
+        fields(epoch = current_epoch, reports = reports.len())
+    )
+)]
+#[allow(clippy::too_many_arguments)]
+pub fn spirit_verify_batch(
+    domain: &DomainConfig,
+    reports: &[TraceReport],
+    t_rgstr: &impl TokenRegistry,
+    revoked: &RevocationList,
+    current_epoch: Epoch,
+    nullifiers: &mut HashSet<Nullifier>,
+    challenges: &ChallengeSet,
+    cp: &mut HashSet<ElID>,
+) -> Vec<Result<Accepted, VerifyRejection>> {
+    let limit = CostLimit::UNLIMITED;
+    spirit_verify_batch_metered(
+        domain,
+        reports,
+        t_rgstr,
+        revoked,
+        current_epoch,
+        nullifiers,
+        challenges,
+        cp,
+        &limit,
+    )
+    .0
+}
+
+/// Like [spirit_verify_batch], with the [CostReport] of the verification. The costs of every
+/// report are bounded from its size, and the reports are taken in order while the sum of their
+/// bounds is within `limit`. The first report that does not fit and all later ones are rejected
+/// with [VerifyRejection::CostExceeded] before any of their proofs is verified. The proofs of a
+/// batch with an invalid proof are verified again in the bisection, whose multi-scalar
+/// multiplications are charged against `limit` as well: the reports whose proofs are left
+/// when the next check would exceed it are rejected with [VerifyRejection::CostExceeded].
+#[cfg(feature = "std")]
+#[allow(clippy::too_many_arguments)]
+pub fn spirit_verify_batch_metered(
+    domain: &DomainConfig,
+    reports: &[TraceReport],
+    t_rgstr: &impl TokenRegistry,
+    revoked: &RevocationList,
+    current_epoch: Epoch,
+    nullifiers: &mut HashSet<Nullifier>,
+    challenges: &ChallengeSet,
+    cp: &mut HashSet<ElID>,
+    limit: &CostLimit,
+) -> (Vec<Result<Accepted, VerifyRejection>>, CostReport) {
+    // the generators g and u of the batch's multi-scalar multiplication, and seven terms of
+    // every Proof2PK
+    let mut bound = CostReport {
+        msm_terms: 2,
+        ..CostReport::default()
+    };
+    let mut within = true;
+    let affordable: Vec<_> = reports
+        .iter()
+        .map(|tr| {
+            let mut total = bound;
+            total.add(&CostReport {
+                pairings: report_pairings(domain, tr, false),
+                msm_terms: 7 * tr.disclosures.len(),
+                bytes: tr.compressed_size(),
+            });
+            within = within && !total.exceeds(limit);
+            if within {
+                bound = total;
+            }
+            within
+        })
+        .collect();
+
+    let (verified, pairings, msm_terms) = metered(|| {
+        let (registered, _, candidate_terms) = metered(|| {
+            reports
+                .iter()
+                .zip(&affordable)
+                .map(|(tr, affordable)| {
+                    if !affordable {
+                        return Err(VerifyRejection::CostExceeded {
+                            token: tr.token.id(),
+                        });
+                    }
+                    check_batch_candidate(domain, tr, t_rgstr, revoked, current_epoch, challenges)?;
+                    check_unspent(tr, nullifiers)
+                })
+                .collect::<Vec<_>>()
+        });
+        // the batch and its bisection take the terms left by the checks of the candidates
+        let mut budget = limit.msm_terms.saturating_sub(candidate_terms);
+        verify_batch_proofs(domain, reports, registered, &mut budget)
+    });
+    let accepted = claim_batch_nullifiers(reports, verified);
+    accept_reports(reports, &accepted, nullifiers, cp);
+    #[cfg(feature = "telemetry")]
+    telemetry::verified_batch(&accepted);
+    let cost = CostReport {
+        pairings,
+        msm_terms,
+        bytes: bound.bytes,
+    };
+    (accepted, cost)
+}
+
+/// Verifies `reports` like [spirit_verify_batch], with the reports split into chunks of
+/// `chunk_size` that are batch-verified in parallel. Of several reports with the same nullifier,
+/// at most the first is accepted as before, also if they are in different chunks, so the
+/// results, `nullifiers` and `cp` are the same as of [spirit_verify_batch].
+#[cfg(feature = "rayon")]
+#[allow(clippy::too_many_arguments)]
+pub fn spirit_verify_batch_parallel(
+    domain: &DomainConfig,
+    reports: &[TraceReport],
+    t_rgstr: &(impl TokenRegistry + Sync),
+    revoked: &RevocationList,
+    current_epoch: Epoch,
+    nullifiers: &mut HashSet<Nullifier>,
+    challenges: &ChallengeSet,
+    cp: &mut HashSet<ElID>,
+    chunk_size: usize,
+) -> Vec<Result<Accepted, VerifyRejection>> {
+    use rayon::{iter::ParallelIterator, slice::ParallelSlice};
+
+    // collecting an indexed parallel iterator preserves the order of the chunks
+    let chunks: Vec<Vec<_>> = reports
+        .par_chunks(chunk_size.max(1))
+        .map(|chunk| {
+            let candidates: Vec<_> = chunk
+                .iter()
+                .map(|tr| {
+                    check_batch_candidate(domain, tr, t_rgstr, revoked, current_epoch, challenges)
+                })
+                .collect();
+            let verified = verify_batch_proofs(domain, chunk, candidates.clone(), &mut usize::MAX);
+            candidates.into_iter().zip(verified).collect()
+        })
+        .collect();
+
+    let verified: Vec<_> = reports
+        .iter()
+        .zip(chunks.into_iter().flatten())
+        .map(|(tr, (candidate, verified))| {
+            candidate?;
+            check_unspent(tr, nullifiers)?;
+            verified
+        })
+        .collect();
+    let accepted = claim_batch_nullifiers(reports, verified);
+    accept_reports(reports, &accepted, nullifiers, cp);
+    accepted
+}
+
+/// Verifies `reports` like calls of [spirit_verify] in the order of `reports`, and returns
+/// their results. The reports are checked in parallel, and only the updates of `nullifiers` and
+/// `cp` are applied serially in the order of `reports`, so the results, `nullifiers` and `cp`
+/// are the same as of the sequential calls.
+#[cfg(feature = "rayon")]
+#[allow(clippy::too_many_arguments)]
+pub fn spirit_verify_parallel(
+    domain: &DomainConfig,
+    reports: &[TraceReport],
+    t_rgstr: &(impl TokenRegistry + Sync),
+    revoked: &RevocationList,
+    current_epoch: Epoch,
+    nullifiers: &mut HashSet<Nullifier>,
+    challenges: &ChallengeSet,
+    cp: &mut HashSet<ElID>,
+) -> Vec<Result<Accepted, VerifyRejection>> {
+    use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+
+    // the checks before the nullifier's, and the ones after it
+    let checked: Vec<_> = reports
+        .par_iter()
+        .map(|tr| -> Result<_, VerifyRejection> {
+            check_points(tr)?;
+            check_valid_at(&tr.token, revoked, current_epoch)?;
+            check_registered(tr, RegistryView::from(t_rgstr))?;
+            Ok(check_current(domain, tr, current_epoch, challenges)
+                .and_then(|()| check_attributes(domain, tr))
+                .and_then(|()| check_key_binding(domain, tr))
+                .and_then(|()| check_authorization(domain, tr))
+                .and_then(|()| check_attestation(domain, tr))
+                .and_then(|()| check_rotation(domain, tr))
+                .and_then(|()| check_show(domain, tr))
+                .and_then(|()| verify_any_disclosure(domain, tr)))
+        })
+        .collect();
+
+    reports
+        .iter()
+        .zip(checked)
+        .map(|(tr, checked)| -> Result<_, VerifyRejection> {
+            let verdicts = checked?;
+            check_unspent(tr, nullifiers)?;
+            let verdicts = verdicts?;
+            accept_disclosures(tr, &verdicts, cp);
+            nullifiers.insert(tr.nullifier.clone());
+            Ok(Accepted { verdicts })
+        })
+        .collect()
+}
+
+// the checks of spirit_verify_batch of a single report, except for its nullifier and its
+// Proof2PKs; reports with a disclosure for an epoch without key commitment or with an invalid
+// PRF proof are rejected with VerifyRejection::InvalidProof right away
+#[cfg(feature = "std")]
+fn check_batch_candidate(
+    domain: &DomainConfig,
+    tr: &TraceReport,
+    t_rgstr: &impl TokenRegistry,
+    revoked: &RevocationList,
+    current_epoch: Epoch,
+    challenges: &ChallengeSet,
+) -> Result<(), VerifyRejection> {
+    check_points(tr)?;
+    check_valid_at(&tr.token, revoked, current_epoch)?;
+    check_registered(tr, RegistryView::from(t_rgstr))?;
+    check_current(domain, tr, current_epoch, challenges)?;
+    check_attributes(domain, tr)?;
+    check_key_binding(domain, tr)?;
+    check_authorization(domain, tr)?;
+    check_attestation(domain, tr)?;
+    check_rotation(domain, tr)?;
+    check_show(domain, tr)?;
+    if tr.disclosures.is_empty() {
+        return Err(VerifyRejection::NoDisclosures {
+            token: tr.token.id(),
+        });
+    }
+    check_all_disclosures(tr, |disclosure| verify_prf_proof(domain, tr, disclosure))
+}
+
+// rejects the verified reports of a batch after the first with the same nullifier; only
+// reports whose proofs verified claim their nullifier, so an invalid report cannot spend the
+// nullifier of a valid one
+#[cfg(feature = "std")]
+fn claim_batch_nullifiers(
+    reports: &[TraceReport],
+    verified: Vec<Result<Accepted, VerifyRejection>>,
+) -> Vec<Result<Accepted, VerifyRejection>> {
+    let mut batch_nullifiers = HashSet::new();
+    reports
+        .iter()
+        .zip(verified)
+        .map(|(tr, verified)| {
+            let accepted = verified?;
+            if !batch_nullifiers.insert(&tr.nullifier) {
+                return Err(VerifyRejection::ReplayedNullifier {
+                    token: tr.token.id(),
+                });
+            }
+            Ok(accepted)
+        })
+        .collect()
+}
+
+// fails with the ElID of the first disclosure of `tr` that `verify` rejects
+#[cfg(feature = "std")]
+fn check_all_disclosures(
+    tr: &TraceReport,
+    verify: impl Fn(&Disclosure) -> bool,
+) -> Result<(), VerifyRejection> {
+    match tr.disclosures.iter().find(|disclosure| !verify(disclosure)) {
+        Some(disclosure) => Err(VerifyRejection::InvalidProof {
+            el_id: disclosure.el_id.clone(),
+        }),
+        None => Ok(()),
+    }
+}
+
+// `registered` with the reports whose disclosures' Proof2PKs do not verify rejected
+#[cfg(feature = "std")]
+fn verify_batch_proofs(
+    domain: &DomainConfig,
+    reports: &[TraceReport],
+    registered: Vec<Result<(), VerifyRejection>>,
+    budget: &mut usize,
+) -> Vec<Result<Accepted, VerifyRejection>> {
+    // check_batch_candidate found the keys of every disclosure, which is checked again rather
+    // than assumed by the statements below
+    let registered: Vec<_> = reports
+        .iter()
+        .zip(registered)
+        .map(|(tr, registered)| {
+            registered.and_then(|()| {
+                check_all_disclosures(tr, |disclosure| tr.disclosure_keys(disclosure).is_some())
+            })
+        })
+        .collect();
+    let identities: Vec<_> = reports
+        .iter()
+        .map(|tr| tr.identity_commitment(domain))
+        .collect();
+    let contexts: Vec<_> = reports.iter().map(|tr| tr.challenge.context()).collect();
+    let bases: Vec<Vec<_>> = reports
+        .iter()
+        .map(|tr| {
+            tr.disclosures
+                .iter()
+                .map(|disclosure| disclosure.base(domain))
+                .collect()
+        })
+        .collect();
+    // the index of the report of every statement
+    let (owners, (statements, proofs)): (Vec<_>, (Vec<_>, Vec<_>)) = reports
+        .iter()
+        .zip(&identities)
+        .zip(&contexts)
+        .zip(&bases)
+        .zip(&registered)
+        .enumerate()
+        .filter(|(_, (_, registered))| registered.is_ok())
+        .flat_map(|(owner, ((((tr, identity), context), bases), _))| {
+            tr.disclosures
+                .iter()
+                .zip(bases)
+                .filter_map(move |(disclosure, base)| {
+                    let (cm_prv, _) = tr.disclosure_keys(disclosure)?;
+                    Some((
+                        owner,
+                        (
+                            (identity, cm_prv, base, &disclosure.el_id, &context[..]),
+                            &disclosure.proof,
+                        ),
+                    ))
+                })
+        })
+        .unzip();
+
+    // the first invalid proof of every report, located by bisection after the previous one, and
+    // the first report whose proofs are left unchecked when the budget runs out
+    let mut invalid = vec![None; reports.len()];
+    let mut unchecked = reports.len();
+    let mut start = 0;
+    // the challenges of the proofs are computed once for all checks
+    let batch = Proof2PKBatch::new(&statements, &proofs);
+    loop {
+        match batch.verify_from(start, budget, &mut OsRng) {
+            Err(pedersen::BatchError::InvalidProof { index }) => {
+                let owner = owners[index];
+                invalid[owner] = Some(statements[index].3);
+                start = owners.partition_point(|other| *other <= owner);
+            }
+            Err(pedersen::BatchError::CostExceeded) => {
+                unchecked = owners.get(start).copied().unwrap_or(reports.len());
+                break;
+            }
+            _ => break,
+        }
+    }
+    reports
+        .iter()
+        .zip(registered)
+        .zip(invalid)
+        .enumerate()
+        .map(|(owner, ((tr, registered), invalid))| {
+            registered?;
+            match invalid {
+                Some(el_id) => Err(VerifyRejection::InvalidProof {
+                    el_id: el_id.clone(),
+                }),
+                None if owner >= unchecked => Err(VerifyRejection::CostExceeded {
+                    token: tr.token.id(),
+                }),
+                None => Ok(Accepted {
+                    verdicts: vec![true; tr.disclosures.len()],
+                }),
+            }
+        })
+        .collect()
+}
+
+// adds the ElIDs and nullifiers of the accepted reports
+#[cfg(feature = "std")]
+fn accept_reports(
+    reports: &[TraceReport],
+    accepted: &[Result<Accepted, VerifyRejection>],
+    nullifiers: &mut HashSet<Nullifier>,
+    cp: &mut HashSet<ElID>,
+) {
+    for (tr, _) in reports
+        .iter()
+        .zip(accepted)
+        .filter(|(_, accepted)| accepted.is_ok())
+    {
+        cp.extend(
+            tr.disclosures
+                .iter()
+                .map(|disclosure| disclosure.el_id.clone()),
+        );
+        nullifiers.insert(tr.nullifier.clone());
+    }
+}
+
+/// Outcome of [spirit_trace].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceResult {
+    /// The user's own encounters that were confirmed, in the order of their first occurrence
+    /// in `cf`.
+    pub matched: Vec<ElID>,
+    /// Number of distinct matched ElIDs, i.e. `matched.len()`.
+    pub count: usize,
+    pub alarm: bool,
+}
+
+impl From<DetailedTraceResult> for TraceResult {
+    fn from(result: DetailedTraceResult) -> Self {
+        let matched: Vec<_> = result
+            .exposures
+            .into_iter()
+            .map(|exposure| exposure.el_id)
+            .collect();
+        Self {
+            count: matched.len(),
+            matched,
+            alarm: result.alarm,
+        }
+    }
+}
+
+/// Matches the confirmed ElIDs `cf` against the user's encounter table `t_el`. Every distinct
+/// ElID counts once, no matter how often it occurs in `cf`. The alarm is raised if the count
+/// reaches `exposure_limit`; a limit of 0 is treated like 1, so the alarm is never raised
+/// without a match.
+///
+/// This is [spirit_trace_detailed] without the encounter metadata.
+#[cfg_attr(
+    feature = "telemetry",
+    tracing::instrument(level = "debug", skip_all, fields(exposure_limit = exposure_limit))
+)]
+pub fn spirit_trace<'a>(
+    cf: impl IntoIterator<Item = &'a ElID>,
+    t_el: &EncounterTable,
+    exposure_limit: usize,
+) -> TraceResult {
+    let result = spirit_trace_detailed(cf, t_el, exposure_limit).into();
+    #[cfg(feature = "telemetry")]
+    telemetry::traced(&result);
+    result
+}
+
+/// Like [spirit_trace], for confirmed ElIDs that are streamed, e.g. from disk or the network,
+/// instead of held in memory. `cf` is consumed once, and only the matches are kept, so memory use
+/// is bounded by the size of `t_el` and not by the length of `cf`.
+pub fn spirit_trace_stream(
+    cf: impl IntoIterator<Item = ElID>,
+    t_el: &EncounterTable,
+    exposure_limit: usize,
+) -> TraceResult {
+    let matched: Vec<_> = streamed_matches(cf, t_el).collect();
+    TraceResult {
+        count: matched.len(),
+        alarm: matched.len() >= exposure_limit.max(1),
+        matched,
+    }
+}
+
+/// Whether [spirit_trace_stream] raises the alarm. `cf` is only consumed up to the distinct
+/// match that reaches `exposure_limit`, so the rest of the stream is never read.
+pub fn spirit_trace_stream_alarm(
+    cf: impl IntoIterator<Item = ElID>,
+    t_el: &EncounterTable,
+    exposure_limit: usize,
+) -> bool {
+    streamed_matches(cf, t_el)
+        .nth(exposure_limit.max(1) - 1)
+        .is_some()
+}
+
+// the matches are kept by their encodings, as ElIDs of the same compact ID may both match
+fn streamed_matches(
+    cf: impl IntoIterator<Item = ElID>,
+    t_el: &EncounterTable,
+) -> impl Iterator<Item = ElID> {
+    let mut seen = HashSet::new();
+    cf.into_iter()
+        .filter(move |el_id| t_el.contains(el_id) && seen.insert(el_id.to_bytes()))
+}
+
+/// Like [spirit_trace], for confirmed ElIDs in their [CompactElId] form, which are looked up in
+/// `t_el` directly. `matched` holds the full ElIDs of `t_el`.
+pub fn spirit_trace_compact<'a>(
+    cf: impl IntoIterator<Item = &'a CompactElId>,
+    t_el: &EncounterTable,
+    exposure_limit: usize,
+) -> TraceResult {
+    let mut seen = HashSet::new();
+    let matched: Vec<_> = cf
+        .into_iter()
+        .filter(|compact_id| seen.insert(**compact_id))
+        .flat_map(|compact_id| t_el.records(*compact_id))
+        .filter_map(PackedEncounter::el_id)
+        .collect();
+    TraceResult {
+        count: matched.len(),
+        alarm: matched.len() >= exposure_limit.max(1),
+        matched,
+    }
+}
+
+/// A confirmed encounter of the user, with the metadata of its [EncounterRecord] that tells the
+/// user when it happened. The encounter secret is left out.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Exposure {
+    pub el_id: ElID,
+    pub epoch: Epoch,
+    pub index: Interval,
+    pub weight: u32,
+}
+
+/// Outcome of [spirit_trace_detailed].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DetailedTraceResult {
+    /// The user's own encounters that were confirmed, in the order of their first occurrence
+    /// in `cf`.
+    pub exposures: Vec<Exposure>,
+    pub alarm: bool,
+}
+
+/// Like [spirit_trace], but returns the epoch, index and weight that `t_el` records for every
+/// match. Entries of `t_el` that are not confirmed do not show up in the result.
+pub fn spirit_trace_detailed<'a>(
+    cf: impl IntoIterator<Item = &'a ElID>,
+    t_el: &EncounterTable,
+    exposure_limit: usize,
+) -> DetailedTraceResult {
+    let exposures: Vec<_> = distinct_matches(cf, t_el)
+        .map(|(el_id, encounter)| Exposure {
+            el_id: el_id.clone(),
+            epoch: encounter.epoch,
+            index: encounter.index,
+            weight: encounter.weight,
+        })
+        .collect();
+
+    DetailedTraceResult {
+        alarm: exposures.len() >= exposure_limit.max(1),
+        exposures,
+    }
+}
+
+// the metadata of the matches is read from their packed entries, without expanding the points
+fn distinct_matches<'a, 'b>(
+    cf: impl IntoIterator<Item = &'a ElID>,
+    t_el: &'b EncounterTable,
+) -> impl Iterator<Item = (&'a ElID, &'b PackedEncounter)> {
+    let mut seen = HashSet::new();
+    cf.into_iter().filter_map(move |el_id| {
+        let encounter = t_el.get(el_id)?;
+        seen.insert(el_id).then_some((el_id, encounter))
+    })
+}
+
+/// Outcome of [spirit_trace_weighted].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WeightedTraceResult {
+    /// The user's own encounters that were confirmed, in the order of their first occurrence
+    /// in `cf`.
+    pub matched: Vec<ElID>,
+    /// Sum of the weights of the distinct matched ElIDs.
+    pub score: u64,
+    pub alarm: bool,
+}
+
+/// Like [spirit_trace], but sums the [EncounterRecord::weight]s of the distinct matches instead
+/// of counting them. The alarm is raised if the sum reaches `threshold`; a threshold of 0 is
+/// treated like 1.
+pub fn spirit_trace_weighted<'a>(
+    cf: impl IntoIterator<Item = &'a ElID>,
+    t_el: &EncounterTable,
+    threshold: u64,
+) -> WeightedTraceResult {
+    let (matched, weights): (Vec<_>, Vec<_>) = distinct_matches(cf, t_el)
+        .map(|(el_id, record)| (el_id.clone(), u64::from(record.weight)))
+        .unzip();
+
+    let score = weights.into_iter().sum();
+    WeightedTraceResult {
+        matched,
+        score,
+        alarm: score >= threshold.max(1),
+    }
+}
+
+/// Outcome of [spirit_trace_with_policy] and [spirit_trace_weighted_with_policy].
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RiskAssessment {
+    /// The user's own encounters that were confirmed, in the order of their first occurrence
+    /// in `cf`.
+    pub matched: Vec<ElID>,
+    /// Number of distinct matched ElIDs, or the sum of their weights.
+    pub score: u64,
+    pub risk: RiskLevel,
+}
+
+/// Like [spirit_trace], but grades the number of distinct matches by the tiers of `policy`
+/// instead of comparing it with a single limit.
+#[cfg(feature = "std")]
+pub fn spirit_trace_with_policy<'a>(
+    cf: impl IntoIterator<Item = &'a ElID>,
+    t_el: &EncounterTable,
+    policy: &ExposurePolicy,
+) -> RiskAssessment {
+    let matched: Vec<_> = distinct_matches(cf, t_el)
+        .map(|(el_id, _)| el_id.clone())
+        .collect();
+    let score = matched.len() as u64;
+    RiskAssessment {
+        matched,
+        score,
+        risk: policy.risk(score),
+    }
+}
+
+/// Outcome of [spirit_trace_risk].
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RiskScore {
+    /// The heard ElIDs that were confirmed, in the order of their first occurrence in `cf`.
+    pub matched: Vec<ElID>,
+    /// Sum of the weights the risk model assigns to the distinct matches.
+    pub score: u64,
+    /// Whether the score reaches the risk model's threshold, and at least one match weighs more
+    /// than 0.
+    pub at_risk: bool,
+}
+
+/// Like [trace_received](crate::advertisement::trace_received), but looks the confirmed ElIDs
+/// up by their [CompactElId]s and weighs the distinct matches by the duration and the
+/// attenuation the receiver measured while scanning them, see
+/// [BroadcastPayload::receive](crate::advertisement::BroadcastPayload::receive), under
+/// `risk_model`, e.g. the [BucketRiskModel::default](crate::exposure::BucketRiskModel).
+#[cfg(feature = "std")]
+pub fn spirit_trace_risk<'a>(
+    cf: impl IntoIterator<Item = &'a ElID>,
+    received: &ReceivedTable,
+    risk_model: &impl RiskModel,
+) -> RiskScore {
+    let mut seen = HashSet::new();
+    let (matched, weights): (Vec<_>, Vec<_>) = cf
+        .into_iter()
+        .filter_map(|el_id| {
+            let compact_id = CompactElId::new(el_id);
+            let encounter = received.get(&compact_id)?;
+            seen.insert(compact_id).then(|| {
+                let weight = risk_model.weight(encounter.duration_secs, encounter.attenuation_db);
+                (el_id.clone(), weight)
+            })
+        })
+        .unzip();
+
+    let score = weights.into_iter().sum();
+    RiskScore {
+        matched,
+        score,
+        at_risk: score >= risk_model.threshold().max(1),
+    }
+}
+
+/// Like [spirit_trace_weighted], but grades the sum of the weights by the tiers of `policy`.
+#[cfg(feature = "std")]
+pub fn spirit_trace_weighted_with_policy<'a>(
+    cf: impl IntoIterator<Item = &'a ElID>,
+    t_el: &EncounterTable,
+    policy: &ExposurePolicy,
+) -> RiskAssessment {
+    let WeightedTraceResult { matched, score, .. } = spirit_trace_weighted(cf, t_el, 1);
+    RiskAssessment {
+        matched,
+        score,
+        risk: policy.risk(score),
+    }
+}
+
+/// Like [spirit_trace], but counts the matches inside the window of `window` consecutive
+/// broadcast intervals with the most matches, e.g. "`exposure_limit` contacts within `window`
+/// days". Matches are ordered by [EncounterRecord::epoch] and [EncounterRecord::index], and a
+/// window only holds matches of one epoch.
+/// `matched` holds the ElIDs of that window in index order; a `window` of 0 is treated like 1.
+pub fn spirit_trace_windowed<'a>(
+    cf: impl IntoIterator<Item = &'a ElID>,
+    t_el: &EncounterTable,
+    window: u64,
+    exposure_limit: usize,
+) -> TraceResult {
+    let mut matches: Vec<_> = distinct_matches(cf, t_el).collect();
+    matches.sort_by_key(|(_, encounter)| (encounter.epoch, encounter.index));
+
+    let window = window.max(1);
+    let (mut best, mut start) = (0..0, 0);
+    for (end, (_, encounter)) in matches.iter().enumerate() {
+        while matches[start].1.epoch != encounter.epoch
+            || encounter.index.since(matches[start].1.index) >= window
+        {
+            start += 1;
+        }
+        if end + 1 - start > best.len() {
+            best = start..end + 1;
+        }
+    }
+
+    let matched: Vec<_> = matches[best]
+        .iter()
+        .map(|(el_id, _)| (*el_id).clone())
+        .collect();
+    let count = matched.len();
+    TraceResult {
+        matched,
+        count,
+        alarm: count >= exposure_limit.max(1),
+    }
+}
+
+/// Identities registered with a registrar: the commitments of their blind requests and their
+/// [IdentityClaim] tags, so that every identity holds at most one token.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Default)]
+pub struct IdentityIndex {
+    commitments: HashSet<Commitment>,
+    tags: HashSet<G1G2>,
+}
+
+#[cfg(feature = "std")]
+impl IdentityIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Checks `claim` for `blind_request`, and that neither the request's commitment nor the
+    /// claim's tag is registered.
+    pub fn check(
+        &self,
+        domain: &DomainConfig,
+        blind_request: &BlindRequest,
+        claim: &IdentityClaim,
+    ) -> Result<(), SpiritError> {
+        claim.verify(domain, blind_request)?;
+        if self.commitments.contains(&blind_request.cm) || self.tags.contains(&claim.tag) {
+            return Err(SpiritError::AlreadyRegistered);
+        }
+        Ok(())
+    }
+
+    /// Records the identity of `blind_request`, whose `claim` has been checked. Returns `false`
+    /// if it was already registered.
+    pub fn insert(&mut self, blind_request: &BlindRequest, claim: &IdentityClaim) -> bool {
+        let new_commitment = self.commitments.insert(blind_request.cm.clone());
+        let new_tag = self.tags.insert(claim.tag.clone());
+        new_commitment && new_tag
+    }
+
+    /// Number of registered identities.
+    pub fn len(&self) -> usize {
+        self.tags.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tags.is_empty()
+    }
+}
+
+/// Window of a [SpiritSystem]'s [ChallengeSet]: a challenge is accepted in the epoch it is
+/// issued in and the next one.
+#[cfg(feature = "std")]
+pub const DEFAULT_CHALLENGE_WINDOW: Epoch = 1;
+
+/// [ReportChallenge]s a verifier has handed out. A challenge is accepted from the epoch it is
+/// issued in until `window` epochs later.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone)]
+pub struct ChallengeSet {
+    issued: HashSet<ReportChallenge>,
+    window: Epoch,
+}
+
+#[cfg(feature = "std")]
+impl ChallengeSet {
+    pub fn new(window: Epoch) -> Self {
+        Self {
+            issued: HashSet::new(),
+            window,
+        }
+    }
+
+    pub fn window(&self) -> Epoch {
+        self.window
+    }
+
+    /// Changes the freshness window, also for the challenges issued so far.
+    pub fn set_window(&mut self, window: Epoch) {
+        self.window = window;
+    }
+
+    /// Issues a fresh challenge in `current_epoch`.
+    pub fn issue(
+        &mut self,
+        current_epoch: Epoch,
+        rng: &mut (impl RngCore + CryptoRng),
+    ) -> ReportChallenge {
+        let challenge = ReportChallenge::new(current_epoch, rng);
+        self.issued.insert(challenge.clone());
+        challenge
+    }
+
+    /// Whether `challenge` was issued here and is still fresh at `current_epoch`.
+    pub fn accepts(&self, challenge: &ReportChallenge, current_epoch: Epoch) -> bool {
+        challenge.issued_at <= current_epoch
+            && current_epoch - challenge.issued_at <= self.window
+            && self.issued.contains(challenge)
+    }
+
+    /// Drops the challenges that are no longer fresh at `current_epoch` and returns their
+    /// number.
+    pub fn purge_expired(&mut self, current_epoch: Epoch) -> usize {
+        let len = self.issued.len();
+        let window = self.window;
+        self.issued
+            .retain(|challenge| current_epoch.saturating_sub(challenge.issued_at) <= window);
+        len - self.issued.len()
+    }
+
+    /// Number of issued challenges that have not been purged.
+    pub fn len(&self) -> usize {
+        self.issued.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.issued.is_empty()
+    }
+}
+
+/// Public state of a SPiRiT deployment: the tACT public parameters, the generator `h`, the
+/// [DomainConfig], the token registry and the [IdentityIndex] of the registered users. It holds
+/// no issuer secrets and can be shared with verifiers. Its verifications and traces are reported
+/// to the [EventSink] set with [SpiritSystem::set_event_sink], and counted by epoch in its
+/// [stats](SpiritSystem::stats).
+#[cfg(feature = "std")]
+pub struct SpiritSystem<R = HashSet<Token>> {
+    pp: tACTPublicParameters,
+    h: Precomputed<G1G2>,
+    domain: DomainConfig,
+    t_rgstr: R,
+    identities: IdentityIndex,
+    revoked: RevocationList,
+    nullifiers: HashSet<Nullifier>,
+    challenges: ChallengeSet,
+    #[cfg(feature = "telemetry")]
+    metrics: Metrics,
+    events: Option<Box<dyn EventSink>>,
+    epoch: Epoch,
+    stats: Mutex<StatsDigest>,
+}
+
+/// Secret key shares of the issuers, kept separate from the public [SpiritSystem].
+#[cfg(feature = "std")]
+pub struct IssuerHandle {
+    issuers: Vec<Issuer>,
+}
+
+#[cfg(feature = "std")]
+impl IssuerHandle {
+    pub fn new(issuers: Vec<Issuer>) -> Self {
+        Self { issuers }
+    }
+
+    pub fn issuers(&self) -> &[Issuer] {
+        &self.issuers
+    }
+}
+
+#[cfg(feature = "std")]
+impl SpiritSystem {
+    /// Sets up a deployment with the default [DomainConfig], in which `t` of the `num_issuers`
+    /// issuers sign a token from blind requests of `n` commitments. The issuance proof opens
+    /// `t - 1` of the commitments, so setup fails unless `t < num_issuers` and
+    /// `2 <= t - 1 < n`, i.e. `3 <= t <= n`, see [SpiritConfig].
+    pub fn setup(
+        t: usize,
+        n: usize,
+        num_issuers: usize,
+    ) -> Result<(Self, IssuerHandle), SpiritError> {
+        Self::setup_with_domain(t, n, num_issuers, DomainConfig::default())
+    }
+
+    pub fn setup_with_domain(
+        t: usize,
+        n: usize,
+        num_issuers: usize,
+        domain: DomainConfig,
+    ) -> Result<(Self, IssuerHandle), SpiritError> {
+        Self::with_registry(t, n, num_issuers, domain, HashSet::new())
+    }
+}
+
+/// Configuration of a deployment, checked before any key is generated: the threshold `t` of
+/// the issuers' committee of `committee_size` issuers, the number of attributes of the tokens,
+/// and the [DomainConfig]. A blind request holds `n` commitments, of which the issuance proof
+/// opens `t - 1`; `n` is the committee size unless set with [Self::request_size].
+///
+/// The committee needs more issuers than its threshold, and the issuance proof at least two
+/// opened commitments, so `3 <= t < committee_size` and `t <= n`.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Default)]
+pub struct SpiritConfig {
+    threshold: usize,
+    committee_size: usize,
+    request_size: Option<usize>,
+    attribute_count: Option<usize>,
+    domain: DomainConfig,
+}
+
+#[cfg(feature = "std")]
+impl SpiritConfig {
+    /// Smallest threshold, at which the issuance proof opens two commitments.
+    pub const MIN_THRESHOLD: usize = 3;
+
+    /// Configuration without a committee, with tokens of one attribute and the default
+    /// [DomainConfig].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number `t` of issuers that have to sign a token.
+    pub fn threshold(mut self, t: usize) -> Self {
+        self.threshold = t;
+        self
+    }
+
+    /// Number of issuers holding a share of the issuer key.
+    pub fn committee_size(mut self, num_issuers: usize) -> Self {
+        self.committee_size = num_issuers;
+        self
+    }
+
+    /// Number `n` of commitments of a blind request.
+    pub fn request_size(mut self, n: usize) -> Self {
+        self.request_size = Some(n);
+        self
+    }
+
+    pub fn attribute_count(mut self, num_attributes: usize) -> Self {
+        self.attribute_count = Some(num_attributes);
+        self
+    }
+
+    pub fn domain(mut self, domain: DomainConfig) -> Self {
+        self.domain = domain;
+        self
+    }
+
+    /// Hash function of the deployment, replacing the [DomainConfig::hash_backend] of a domain
+    /// set before.
+    pub fn hash_backend(mut self, backend: HashBackendId) -> Self {
+        self.domain.hash_backend = backend;
+        self
+    }
+
+    /// Number of epochs after their diagnosis in which reports are accepted, replacing the
+    /// [DomainConfig::reporting_window] of a domain set before.
+    pub fn reporting_window(mut self, window: usize) -> Self {
+        self.domain.reporting_window = window;
+        self
+    }
+
+    /// Key of the health authority that has to authorize every report, replacing the
+    /// [DomainConfig::diagnosis_authority] of a domain set before.
+    pub fn diagnosis_authority(mut self, ha_pk: PublicKey) -> Self {
+        self.domain.diagnosis_authority = Some(ha_pk);
+        self
+    }
+
+    /// Beacon of the issuance committees of the deployment, replacing the
+    /// [DomainConfig::committee_beacon] of a domain set before.
+    pub fn committee_beacon(mut self, beacon: Vec<u8>) -> Self {
+        self.domain.committee_beacon = Some(beacon);
+        self
+    }
+
+    /// Key of the registrar that has to anchor the ElIDs of every report, replacing the
+    /// [DomainConfig::anchoring_registrar] of a domain set before.
+    pub fn anchoring_registrar(mut self, registrar: PublicKey) -> Self {
+        self.domain.anchoring_registrar = Some(registrar);
+        self
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let (t, committee_size) = (self.threshold, self.committee_size);
+        let request_size = self.request_size.unwrap_or(committee_size);
+        if t == 0 {
+            return Err(ConfigError::ZeroThreshold);
+        }
+        if t < Self::MIN_THRESHOLD {
+            return Err(ConfigError::ThresholdTooLow { threshold: t });
+        }
+        if t >= committee_size {
+            return Err(ConfigError::ThresholdNotBelowCommitteeSize {
+                threshold: t,
+                committee_size,
+            });
+        }
+        if t > request_size {
+            return Err(ConfigError::ThresholdAboveRequestSize {
+                threshold: t,
+                request_size,
+            });
+        }
+        if self.attribute_count == Some(0) {
+            return Err(ConfigError::NoAttributes);
+        }
+        let (suite, backend) = (self.domain.hash_to_curve, self.domain.hash_backend);
+        if suite == HashToCurveSuite::Rfc9380 && backend != HashBackendId::Sha256 {
+            return Err(ConfigError::UnsupportedHashSuite { suite, backend });
+        }
+        Ok(())
+    }
+
+    /// Sets up the deployment as a [SpiritSystem] with an empty registry, and the issuers.
+    pub fn build(
+        self,
+        rng: &mut (impl RngCore + CryptoRng),
+    ) -> Result<(SpiritSystem, IssuerHandle), ConfigError> {
+        self.build_with_registry(HashSet::new(), rng)
+    }
+
+    /// [Self::build], but stores registered tokens in `t_rgstr`.
+    pub fn build_with_registry<R: TokenRegistry>(
+        self,
+        t_rgstr: R,
+        rng: &mut (impl RngCore + CryptoRng),
+    ) -> Result<(SpiritSystem<R>, IssuerHandle), ConfigError> {
+        let (pp, issuers) = self.committee(rng)?;
+        Ok((
+            SpiritSystem {
+                pp,
+                h: Precomputed::new(&G1G2::random(&mut *rng)),
+                domain: self.into_domain(),
+                t_rgstr,
+                identities: IdentityIndex::new(),
+                revoked: RevocationList::new(),
+                nullifiers: HashSet::new(),
+                challenges: ChallengeSet::new(DEFAULT_CHALLENGE_WINDOW),
+                #[cfg(feature = "telemetry")]
+                metrics: Metrics::new(),
+                events: None,
+                epoch: 0,
+                stats: Mutex::new(StatsDigest::new()),
+            },
+            IssuerHandle::new(issuers),
+        ))
+    }
+
+    /// Sets up the deployment in the form of [spirit_setup], with the health authority's key
+    /// pair.
+    pub fn setup(self, rng: &mut (impl RngCore + CryptoRng)) -> Result<SetupOutput, ConfigError> {
+        let (pp, issuers) = self.committee(rng)?;
+        let sk_ha = DecryptionKey::random(rng);
+        Ok(SetupOutput {
+            pp,
+            issuers,
+            pk_ha: sk_ha.encryption_key(),
+            sk_ha,
+            t_rgstr: HashSet::new(),
+            domain: self.into_domain(),
+        })
+    }
+
+    // the domain, with the number of attributes of the deployment
+    fn into_domain(self) -> DomainConfig {
+        DomainConfig {
+            num_attributes: self.attribute_count.unwrap_or(1),
+            ..self.domain
+        }
+    }
+
+    fn committee(
+        &self,
+        rng: &mut (impl RngCore + CryptoRng),
+    ) -> Result<(tACTPublicParameters, Vec<Issuer>), ConfigError> {
+        self.validate()?;
+        let t = self.threshold;
+        let n = self.request_size.unwrap_or(self.committee_size);
+        let num_attributes = self.attribute_count.unwrap_or(1);
+        let (mut pp, issuers) = tACT_setup(self.committee_size, n, t, t - 1, num_attributes, rng)
+            .map_err(ConfigError::SetupFailed)?;
+        pp.dst = self.domain.tag(&self.domain.registration_dst);
+        pp.hash_to_curve = self.domain.hash_to_curve;
+        pp.hash_backend = self.domain.hash_backend;
+        // fixed-base tables for the commitments and blinding steps of every registration
+        pedersen::precompute();
+        pp.precompute();
+        Ok((pp, issuers))
+    }
+}
+
+#[cfg(feature = "std")]
+impl<R: TokenRegistry> SpiritSystem<R> {
+    /// Like [SpiritSystem::setup_with_domain], but stores registered tokens in `t_rgstr`.
+    pub fn with_registry(
+        t: usize,
+        n: usize,
+        num_issuers: usize,
+        domain: DomainConfig,
+        t_rgstr: R,
+    ) -> Result<(Self, IssuerHandle), SpiritError> {
+        SpiritConfig::new()
+            .threshold(t)
+            .committee_size(num_issuers)
+            .request_size(n)
+            .domain(domain)
+            .build_with_registry(t_rgstr, &mut OsRng)
+            .map_err(SpiritError::from)
+    }
+
+    pub fn public_parameters(&self) -> &tACTPublicParameters {
+        &self.pp
+    }
+
+    pub fn h(&self) -> &G1G2 {
+        self.h.base()
+    }
+
+    /// Table of [Self::h] for fixed-base multiplications.
+    pub fn h_table(&self) -> &Precomputed<G1G2> {
+        &self.h
+    }
+
+    pub fn domain(&self) -> &DomainConfig {
+        &self.domain
+    }
+
+    pub fn registry(&self) -> &R {
+        &self.t_rgstr
+    }
+
+    pub fn identities(&self) -> &IdentityIndex {
+        &self.identities
+    }
+
+    pub fn revocations(&self) -> &RevocationList {
+        &self.revoked
+    }
+
+    /// Nullifiers of the reports accepted so far.
+    pub fn nullifiers(&self) -> &HashSet<Nullifier> {
+        &self.nullifiers
+    }
+
+    pub fn challenges(&self) -> &ChallengeSet {
+        &self.challenges
+    }
+
+    /// Counters of the registrations, verifications and traces of the system.
+    #[cfg(feature = "telemetry")]
+    pub fn metrics(&self) -> &Metrics {
+        &self.metrics
+    }
+
+    /// Sets the sink of the [Event]s of the system, replacing the previous one.
+    pub fn set_event_sink(&mut self, sink: impl EventSink + 'static) {
+        self.events = Some(Box::new(sink));
+    }
+
+    fn emit(&self, event: Event) {
+        if let Some(sink) = &self.events {
+            sink.emit(event);
+        }
+    }
+
+    /// Counts of the registrations, verified reports, traces and alarms of the system by epoch.
+    /// Registrations and traces are counted in the current epoch, see
+    /// [SpiritSystem::advance_epoch], and reports in the epoch they are verified in.
+    pub fn stats(&self) -> StatsDigest {
+        self.stats
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Sets the current epoch of the system. It is also set by the calls that take the current
+    /// epoch, such as [SpiritSystem::issue_challenge] and [SpiritSystem::verify_report].
+    pub fn advance_epoch(&mut self, current_epoch: Epoch) {
+        self.epoch = current_epoch;
+    }
+
+    fn count(&self, epoch: Epoch, update: impl FnOnce(&mut EpochStats)) {
+        update(
+            self.stats
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .at(epoch),
+        );
+    }
+
+    fn count_verified<'a>(
+        &self,
+        epoch: Epoch,
+        verified: impl IntoIterator<Item = &'a Result<Accepted, VerifyRejection>>,
+    ) {
+        self.count(epoch, |stats| {
+            for verified in verified {
+                match verified {
+                    Ok(_) => stats.reports_accepted += 1,
+                    Err(_) => stats.reports_rejected += 1,
+                }
+            }
+        });
+    }
+
+    /// See [ChallengeSet::set_window].
+    pub fn set_challenge_window(&mut self, window: Epoch) {
+        self.challenges.set_window(window);
+    }
+
+    /// Issues the [ReportChallenge] for a user's trace report in `current_epoch`.
+    pub fn issue_challenge(&mut self, current_epoch: Epoch) -> ReportChallenge {
+        self.advance_epoch(current_epoch);
+        self.challenges.issue(current_epoch, &mut OsRng)
+    }
+
+    /// See [spirit_register]. Fails with [SpiritError::AlreadyRegistered] if the user's identity
+    /// is already registered. With a [committee beacon](DomainConfig::committee_beacon), the
+    /// committee of the system's epoch issues the token, see [spirit_register_with_committee].
+    pub fn register(
+        &mut self,
+        user: &UserSecret,
+        issuers: &IssuerHandle,
+        expiry: Epoch,
+    ) -> Result<(Token, StRG), SpiritError> {
+        #[cfg(feature = "telemetry")]
+        let start = Instant::now();
+        let registered = self.register_unrecorded(user, issuers, expiry);
+        #[cfg(feature = "telemetry")]
+        self.metrics
+            .record_registration(registered.is_ok(), start.elapsed());
+        if registered.is_ok() {
+            self.count(self.epoch, |stats| stats.registrations += 1);
+        }
+        registered
+    }
+
+    #[cfg_attr(
+        feature = "telemetry",
+        tracing::instrument(
+            name = "spirit_system_register",
+            level = "debug",
+            skip_all,
+            fields(expiry = expiry),
+            err(level = "debug")
+        )
+    )]
+    fn register_unrecorded(
+        &mut self,
+        user: &UserSecret,
+        issuers: &IssuerHandle,
+        expiry: Epoch,
+    ) -> Result<(Token, StRG), SpiritError> {
+        let (blind_request, pending) =
+            spirit_register_begin(&self.domain, &user.id_u, &self.pp, expiry, &mut OsRng)?;
+        let claim = pending.identity_claim(&self.domain, &mut OsRng);
+        self.identities
+            .check(&self.domain, &blind_request, &claim)?;
+        let blind_tokens = issue_committee_blind_tokens(
+            &self.domain,
+            self.epoch,
+            &blind_request,
+            &issuers.issuers,
+            &self.pp,
+        )?;
+        let (ppu, token, token_proof) = spirit_register_finish_from(
+            &self.domain,
+            pending,
+            &blind_tokens,
+            &self.pp,
+            &mut OsRng,
+        )?;
+        self.complete_registration(&blind_request, &claim, &token, &token_proof, expiry)?;
+        Ok(ppu)
+    }
+
+    /// Issues blind tokens for a registration started on the user's device with
+    /// [spirit_register_begin], if `claim` is valid and the identity is not registered yet, see
+    /// [IdentityIndex::check].
+    pub fn issue(
+        &self,
+        issuers: &IssuerHandle,
+        blind_request: &BlindRequest,
+        claim: &IdentityClaim,
+    ) -> Result<Vec<Vec<BlindToken>>, SpiritError> {
+        self.identities.check(&self.domain, blind_request, claim)?;
+        issue_blind_tokens(blind_request, &issuers.issuers, &self.pp)
+    }
+
+    /// See [spirit_complete_registration]. Checks `claim` like [SpiritSystem::issue], since the
+    /// identity may have been registered in between, and records the identity.
+    pub fn complete_registration(
+        &mut self,
+        blind_request: &BlindRequest,
+        claim: &IdentityClaim,
+        token: &tACTToken,
+        token_proof: &TokenProof,
+        expiry: Epoch,
+    ) -> Result<Token, SpiritError> {
+        self.identities.check(&self.domain, blind_request, claim)?;
+        let token = spirit_complete_registration(
+            &self.domain,
+            blind_request,
+            token,
+            token_proof,
+            &self.pp,
+            expiry,
+            &mut self.t_rgstr,
+        )?;
+        self.identities.insert(blind_request, claim);
+        Ok(token)
+    }
+
+    /// Hands the issuer key over from the committee `issuers` to a new committee of
+    /// `new_num_issuers` issuers with threshold `new_t`, see [reshare]. Registered tokens stay
+    /// valid and new tokens are issued by the returned committee.
+    pub fn reshare(
+        &mut self,
+        issuers: &IssuerHandle,
+        new_num_issuers: usize,
+        new_t: usize,
+    ) -> Result<IssuerHandle, SpiritError> {
+        if issuers.issuers.len() < self.pp.t {
+            return Err(SpiritError::NotEnoughIssuers {
+                required: self.pp.t,
+                available: issuers.issuers.len(),
+            });
+        }
+        reshare(&mut self.pp, &issuers.issuers, new_num_issuers, new_t)
+            .map(IssuerHandle::new)
+            .map_err(SpiritError::ResharingFailed)
+    }
+
+    /// See [TokenRegistry::purge_expired]. Expired challenges are dropped as well, see
+    /// [ChallengeSet::purge_expired].
+    pub fn purge_expired(&mut self, current_epoch: Epoch) -> Result<usize, RegistryError> {
+        self.advance_epoch(current_epoch);
+        self.challenges.purge_expired(current_epoch);
+        self.t_rgstr.purge_expired(current_epoch)
+    }
+
+    /// Revokes `token`. Returns `false` if it was already revoked.
+    pub fn revoke(&mut self, token: &Token) -> bool {
+        self.revoked.revoke(token)
+    }
+
+    /// See [spirit_verify]. The nullifiers of the accepted reports are kept in the system.
+    pub fn verify_report(
+        &mut self,
+        tr: &TraceReport,
+        current_epoch: Epoch,
+        cp: &mut HashSet<ElID>,
+    ) -> Result<Accepted, VerifyRejection> {
+        #[cfg(feature = "telemetry")]
+        let start = Instant::now();
+        let verified = spirit_verify(
+            &self.domain,
+            tr,
+            &self.t_rgstr,
+            &self.revoked,
+            current_epoch,
+            &mut self.nullifiers,
+            &self.challenges,
+            cp,
+        );
+        #[cfg(feature = "telemetry")]
+        self.metrics.record_verification(&verified, start.elapsed());
+        self.advance_epoch(current_epoch);
+        self.count_verified(current_epoch, [&verified]);
+        self.emit(Event::verified(&verified));
+        verified
+    }
+
+    /// See [spirit_verify_batch]. The nullifiers of the accepted reports are kept in the system.
+    pub fn verify_reports(
+        &mut self,
+        reports: &[TraceReport],
+        current_epoch: Epoch,
+        cp: &mut HashSet<ElID>,
+    ) -> Vec<Result<Accepted, VerifyRejection>> {
+        #[cfg(feature = "telemetry")]
+        let start = Instant::now();
+        let verified = spirit_verify_batch(
+            &self.domain,
+            reports,
+            &self.t_rgstr,
+            &self.revoked,
+            current_epoch,
+            &mut self.nullifiers,
+            &self.challenges,
+            cp,
+        );
+        #[cfg(feature = "telemetry")]
+        self.metrics.record_batch(&verified, start.elapsed());
+        self.advance_epoch(current_epoch);
+        self.count_verified(current_epoch, &verified);
+        for verified in &verified {
+            self.emit(Event::verified(verified));
+        }
+        verified
+    }
+
+    /// See [spirit_verify_rerandomized]. The issuers' key is prepared once for all
+    /// verifications of the system.
+    pub fn verify_rerandomized_report(
+        &mut self,
+        tr: &TraceReport,
+        current_epoch: Epoch,
+        cp: &mut HashSet<ElID>,
+    ) -> Result<Accepted, VerifyRejection> {
+        #[cfg(feature = "telemetry")]
+        let start = Instant::now();
+        let verified = spirit_verify_rerandomized(
+            &self.domain,
+            tr,
+            &self.pp,
+            current_epoch,
+            &mut self.nullifiers,
+            &self.challenges,
+            cp,
+        );
+        #[cfg(feature = "telemetry")]
+        self.metrics.record_verification(&verified, start.elapsed());
+        self.advance_epoch(current_epoch);
+        self.count_verified(current_epoch, [&verified]);
+        self.emit(Event::verified(&verified));
+        verified
+    }
+
+    /// See [spirit_verify_bundle]. The nullifier of an accepted bundle is kept in the system.
+    pub fn verify_bundle(
+        &mut self,
+        bundle: &DiagnosisBundle,
+        current_epoch: Epoch,
+        cp: &mut HashSet<ElID>,
+    ) -> Result<Accepted, VerifyRejection> {
+        #[cfg(feature = "telemetry")]
+        let start = Instant::now();
+        let verified = spirit_verify_bundle(
+            &self.domain,
+            bundle,
+            &self.t_rgstr,
+            &self.revoked,
+            current_epoch,
+            &mut self.nullifiers,
+            &self.challenges,
+            cp,
+        );
+        #[cfg(feature = "telemetry")]
+        self.metrics.record_verification(&verified, start.elapsed());
+        self.advance_epoch(current_epoch);
+        self.count_verified(current_epoch, [&verified]);
+        self.emit(Event::verified(&verified));
+        verified
+    }
+
+    /// See [spirit_trace]. A raised alarm is reported as an [Event::ExposureAlarm] of
+    /// [RiskLevel::High].
+    pub fn trace<'a>(
+        &self,
+        cf: impl IntoIterator<Item = &'a ElID>,
+        t_el: &EncounterTable,
+        exposure_limit: usize,
+    ) -> TraceResult {
+        #[cfg(feature = "telemetry")]
+        let start = Instant::now();
+        let result = spirit_trace(cf, t_el, exposure_limit);
+        #[cfg(feature = "telemetry")]
+        self.metrics.record_trace(start.elapsed());
+        self.alarm(result.alarm, result.count as u64, RiskLevel::High);
+        result
+    }
+
+    /// See [spirit_trace_detailed], and [SpiritSystem::trace] for the event.
+    pub fn trace_detailed<'a>(
+        &self,
+        cf: impl IntoIterator<Item = &'a ElID>,
+        t_el: &EncounterTable,
+        exposure_limit: usize,
+    ) -> DetailedTraceResult {
+        #[cfg(feature = "telemetry")]
+        let start = Instant::now();
+        let result = spirit_trace_detailed(cf, t_el, exposure_limit);
+        #[cfg(feature = "telemetry")]
+        self.metrics.record_trace(start.elapsed());
+        let count = result.exposures.len() as u64;
+        self.alarm(result.alarm, count, RiskLevel::High);
+        result
+    }
+
+    /// See [spirit_trace_with_policy]. A risk above [RiskLevel::None] is reported as an
+    /// [Event::ExposureAlarm] with the score.
+    pub fn trace_with_policy<'a>(
+        &self,
+        cf: impl IntoIterator<Item = &'a ElID>,
+        t_el: &EncounterTable,
+        policy: &ExposurePolicy,
+    ) -> RiskAssessment {
+        #[cfg(feature = "telemetry")]
+        let start = Instant::now();
+        let assessment = spirit_trace_with_policy(cf, t_el, policy);
+        #[cfg(feature = "telemetry")]
+        self.metrics.record_trace(start.elapsed());
+        let raised = assessment.risk > RiskLevel::None;
+        self.alarm(raised, assessment.score, assessment.risk);
+        assessment
+    }
+
+    fn alarm(&self, raised: bool, count: u64, risk: RiskLevel) {
+        self.count(self.epoch, |stats| {
+            stats.traces += 1;
+            stats.alarms += u64::from(raised);
+        });
+        if raised {
+            self.emit(Event::ExposureAlarm { count, risk });
+        }
+    }
+}
+
+/// Invalid [SpiritConfig].
+#[cfg(feature = "std")]
+#[derive(Error, Debug, PartialEq, Clone)]
+pub enum ConfigError {
+    #[error("The threshold is 0.")]
+    ZeroThreshold,
+    #[error("Threshold {threshold} is below the minimum of 3.")]
+    ThresholdTooLow { threshold: usize },
+    #[error("Threshold {threshold} is not below the committee size {committee_size}.")]
+    ThresholdNotBelowCommitteeSize {
+        threshold: usize,
+        committee_size: usize,
+    },
+    #[error("Threshold {threshold} is above the request size {request_size}.")]
+    ThresholdAboveRequestSize {
+        threshold: usize,
+        request_size: usize,
+    },
+    #[error("Tokens without attributes.")]
+    NoAttributes,
+    #[error("Setup failed: {0}")]
+    SetupFailed(#[source] AtACTError),
+    #[error("Hash-to-curve suite {suite:?} is not defined with the hash function {backend:?}.")]
+    UnsupportedHashSuite {
+        suite: HashToCurveSuite,
+        backend: HashBackendId,
+    },
+}
+
+/// Setups of the free functions and [SpiritSystem] fail with [SpiritError::SetupFailed], and
+/// with [AtACTError::InvalidParameters] for an invalid configuration.
+#[cfg(feature = "std")]
+impl From<ConfigError> for SpiritError {
+    fn from(error: ConfigError) -> Self {
+        match error {
+            ConfigError::SetupFailed(error) => Self::SetupFailed(error),
+            _ => Self::SetupFailed(AtACTError::InvalidParameters),
+        }
+    }
+}
+
+#[derive(Error, Debug, PartialEq, Clone)]
+pub enum SpiritError {
+    #[error("Setup failed: {0}")]
+    SetupFailed(#[source] AtACTError),
+    #[error("Registration failed: {0}")]
+    RegistrationFailed(#[source] AtACTError),
+    #[error("Invalid public parameters: {0}")]
+    InvalidParameters(#[source] ParamError),
+    #[error("Token request failed: {0}")]
+    TokenRequestFailed(#[source] AtACTError),
+    #[error("Not enough issuers: {available} available, {required} required.")]
+    NotEnoughIssuers { required: usize, available: usize },
+    #[error("Issuer {issuer_index} rejected the blind request: {source}")]
+    IssuerRejected {
+        issuer_index: usize,
+        source: AtACTError,
+    },
+    #[error("Issuer {issuer_index} returned {actual} blind tokens, {expected} expected.")]
+    InvalidBlindTokens {
+        issuer_index: usize,
+        expected: usize,
+        actual: usize,
+    },
+    #[error("Token proof verification failed: {0}")]
+    ProofVerificationFailed(#[source] AtACTError),
+    #[cfg(feature = "std")]
+    #[error("Storing the token in the registry failed: {0}")]
+    RegistryUpdateFailed(#[source] RegistryError),
+    #[error("Resharing the issuer key failed: {0}")]
+    ResharingFailed(#[source] AtACTError),
+    #[error("Invalid identity claim.")]
+    InvalidIdentityClaim,
+    #[error("The identity is already registered.")]
+    AlreadyRegistered,
+    #[error("The audit proof does not show that the request commits to the identity.")]
+    InvalidAuditProof,
+    #[error("Invalid key claim.")]
+    InvalidKeyClaim,
+    #[error("The token does not commit to the PRF key.")]
+    UnboundKey,
+    #[error("The health authority's authorization does not verify.")]
+    InvalidAuthorization,
+    #[error("Broadcast {index} of epoch {epoch} is in no batch anchored before the diagnosis.")]
+    UnanchoredBroadcast { epoch: Epoch, index: Interval },
+    #[error("Invalid attribute index {index} for {num_attributes} attributes.")]
+    InvalidAttributeIndex { index: usize, num_attributes: usize },
+    #[error("Issuers {issuer_indices:?} returned invalid blind tokens, too few others are left.")]
+    MisbehavingIssuers { issuer_indices: Vec<usize> },
+    #[error("Issuer {issuer_index} is chosen more than once.")]
+    DuplicateIssuer { issuer_index: usize },
+    #[error("Issuer {issuer_index} returned blind tokens that are not for the blind request.")]
+    ForeignBlindTokens { issuer_index: usize },
+    #[error("The registration session of epoch {started} expired.")]
+    SessionExpired { started: Epoch },
+    #[error("Invalid issuer index {issuer_index} for {num_issuers} issuers.")]
+    InvalidIssuerIndex {
+        issuer_index: usize,
+        num_issuers: usize,
+    },
+    #[error("Hash-to-curve suite {domain:?} differs from {parameters:?} of the parameters.")]
+    HashToCurveMismatch {
+        domain: HashToCurveSuite,
+        parameters: HashToCurveSuite,
+    },
+    #[error("Hash backend {domain:?} differs from {parameters:?} of the parameters.")]
+    HashBackendMismatch {
+        domain: HashBackendId,
+        parameters: HashBackendId,
+    },
+    #[error("Unknown deployment {0:?}.")]
+    UnknownDeployment(Vec<u8>),
+    #[error(
+        "Not enough live issuers: {live} live, {required} required, issuers {inconsistent:?} \
+         serve other parameters."
+    )]
+    QuorumUnavailable {
+        required: usize,
+        live: usize,
+        inconsistent: Vec<usize>,
+    },
+    #[error("Key-bound keys cannot disclose the ElIDs of previous keys.")]
+    RotatedBoundKey,
+    #[error("The issuer holds no share of the committee's decryption key.")]
+    NoDecryptionShare,
+    #[error("Invalid claim of a pre-issued token.")]
+    InvalidTokenClaim,
+    #[error("All {limit} shows of the token are used.")]
+    ShowsExhausted { limit: usize },
+}
+
+#[cfg(test)]
+mod test {
+    use core::slice;
+
+    use proptest::{
+        collection::btree_set,
+        prelude::{ProptestConfig, Strategy, any},
+        prop_assert, prop_assert_eq, proptest,
+        sample::Index,
+    };
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha20Rng;
+
+    use super::*;
+    use crate::{
+        advertisement::BroadcastPayload,
+        arbitrary::{self, Diagnosis},
+        bls381_helpers::{pairing, test::generator_multiple},
+        exposure::BucketRiskModel,
+        registry::{MerkleRegistry, RegistrarKeypair, RegistryFilter},
+    };
+
+    const T: usize = 4;
+    const N: usize = 8;
+    const NUM_ISSUERS: usize = 8;
+    const NOW: Epoch = 10;
+    const EXPIRY: Epoch = 100;
+
+    /// Encryption key of a fresh health authority.
+    fn ha_key() -> EncryptionKey {
+        DecryptionKey::random(&mut OsRng).encryption_key()
+    }
+
+    /// Outcome of an accepted report with the `verdicts` of its disclosures.
+    fn accepted(verdicts: &[bool]) -> Result<Accepted, VerifyRejection> {
+        Ok(Accepted {
+            verdicts: verdicts.to_vec(),
+        })
+    }
+
+    #[test]
+    fn token_bytes_kat() {
+        const KAT: &str = "53505253010003b0e7791fb972fe014159aa33a98622da3cdc98ff707965e536d8636b5fcc5ac7a91a8c46e59a00dca575af0f18fb13dc80fb837804dba8213329db46608b6c121d973363c1234a86dd183baff112709cf97096c5e9a1a770ee9d7dc641a894d60411a5de6730ffece671a9f21d65028cc0f1102378de124562cb1ff49db6f004fcd14d683024b0548eff3d1468df2688b928f3beb93519eecf0145da903b40a4c97dca00b21f12ac0df3be9116ef2ef27b2ae6bcd4c5bc2d54ef5a70627efcb78d0273f6bf31ed37c3b8d68083ec3d8e20b5f2cc170fa24b9b5be35b34ed013f9a921f1cad1644d4bdb14674247234c8049cd1dbb2d2c3581e54c088135fef36505a6823d61b859437bfc79b617030dc8b40e32bad1fa85b9c0f368af6d38d3c5a00000000000000";
+
+        let token = Token(
+            Commitment(generator_multiple(5)),
+            Signature(generator_multiple(7)),
+            90,
+        );
+        let bytes = token.to_bytes();
+        assert_eq!(hex::encode(bytes), KAT);
+        assert_eq!(Token::from_bytes(&bytes), Ok(token));
+        assert_eq!(
+            Token::from_bytes(&bytes[..Token::ENCODED_SIZE - 1]),
+            Err(DecodeError::InvalidLength {
+                expected: Token::PAYLOAD_SIZE,
+                actual: Token::PAYLOAD_SIZE - 1
+            })
+        );
+
+        let mut tampered = bytes;
+        tampered[HEADER_SIZE + G1G2::ENCODED_SIZE + 20] ^= 0x01;
+        assert_eq!(Token::from_bytes(&tampered), Err(DecodeError::InvalidPoint));
+    }
+
+    #[test]
+    fn tokens_are_ordered_by_payload() {
+        let token =
+            |cm: G1G2, expiry| Token(Commitment(cm), Signature(generator_multiple(7)), expiry);
+        let step = G1G2::random(&mut OsRng);
+        // the same commitment with other projective coordinates
+        let (a, b) = (
+            token(generator_multiple(5), 90),
+            token(&generator_multiple(5) + &step - &step, 90),
+        );
+        assert_eq!(a, b);
+        assert_eq!(a.cmp(&b), Ordering::Equal);
+        assert_eq!(HashSet::from([a.clone(), b]).len(), 1);
+
+        let others = [
+            token(generator_multiple(6), 90),
+            token(generator_multiple(5), 91),
+        ];
+        for other in &others {
+            assert_eq!(a.cmp(other), a.payload().cmp(&other.payload()));
+        }
+        let tokens: BTreeSet<_> = others.iter().cloned().chain([a]).collect();
+        assert!(
+            tokens
+                .iter()
+                .is_sorted_by(|lhs, rhs| lhs.payload() < rhs.payload())
+        );
+    }
+
+    #[test]
+    fn token_ct_eq() {
+        let token = Token(
+            Commitment(G1G2::random(OsRng)),
+            Signature(G1G2::random(OsRng)),
+            EXPIRY,
+        );
+        let others = [
+            token.clone(),
+            Token(Commitment(G1G2::random(OsRng)), token.1.clone(), EXPIRY),
+            Token(token.0.clone(), Signature(G1G2::random(OsRng)), EXPIRY),
+            Token(token.0.clone(), token.1.clone(), EXPIRY + 1),
+        ];
+        for other in &others {
+            let eq = token.0 == other.0 && token.1 == other.1 && token.2 == other.2;
+            assert_eq!(bool::from(token.0.ct_eq(&other.0)), token.0 == other.0);
+            assert_eq!(bool::from(token.1.ct_eq(&other.1)), token.1 == other.1);
+            assert_eq!(bool::from(token.ct_eq(other)), eq);
+            assert_eq!(token == *other, eq);
+        }
+
+        let t_rgstr = HashSet::from([token.clone()]);
+        assert!(t_rgstr.contains(&others[0]));
+        assert!(others[1..].iter().all(|other| !t_rgstr.contains(other)));
+    }
+
+    #[test]
+    fn deterministic_setup_and_registration() {
+        let run = || {
+            let mut rng = ChaCha20Rng::seed_from_u64(29);
+            let SetupOutput {
+                pp,
+                issuers,
+                pk_ha,
+                sk_ha,
+                mut t_rgstr,
+                domain,
+            } = spirit_setup(T, N, NUM_ISSUERS, b"test", &mut rng).expect("setup failed");
+            let user = UserSecret::random(&mut rng);
+            let (token, _) = spirit_register(
+                &domain,
+                &user,
+                &issuers,
+                &pp,
+                EXPIRY,
+                &mut t_rgstr,
+                &mut rng,
+            )
+            .expect("registration failed");
+
+            let shares: Vec<_> = issuers.iter().map(|issuer| issuer.as_ref().sk).collect();
+            (
+                pp.pk.0.to_bytes(),
+                shares,
+                pk_ha.0.to_bytes(),
+                sk_ha.0,
+                token.to_bytes(),
+            )
+        };
+        assert_eq!(run(), run());
+    }
+
+    #[test]
+    fn registration() {
+        let (mut system, issuers) = SpiritSystem::setup(T, N, NUM_ISSUERS).expect("setup failed");
+        let user = UserSecret::random(OsRng);
+
+        let (token, _) = system
+            .register(&user, &issuers, EXPIRY)
+            .expect("registration failed");
+        assert!(system.registry().contains(&token));
+    }
+
+    #[test]
+    fn remote_registration() {
+        let (mut system, issuers) = SpiritSystem::setup(T, N, NUM_ISSUERS).expect("setup failed");
+        let domain = system.domain().clone();
+        let pp = spirit_client_parameters(&domain, system.public_parameters().pk.clone(), N, T)
+            .expect("invalid parameters");
+
+        let (blind_request, pending) =
+            spirit_register_begin(&domain, &Scalar::rand(&mut OsRng), &pp, EXPIRY, &mut OsRng)
+                .expect("token request failed");
+        let claim = pending.identity_claim(&domain, &mut OsRng);
+        let blind_tokens = system
+            .issue(&issuers, &blind_request, &claim)
+            .expect("issuance failed");
+        let ((token, _), tact_token, token_proof) =
+            spirit_register_finish(&domain, pending, &blind_tokens, &pp, &mut OsRng)
+                .expect("unblinding failed");
+
+        // the issuers signed the expiry of the request, which the registrar cannot change
+        assert!(matches!(
+            system.complete_registration(
+                &blind_request,
+                &claim,
+                &tact_token,
+                &token_proof,
+                EXPIRY + 1
+            ),
+            Err(SpiritError::ProofVerificationFailed(_))
+        ));
+        assert_eq!(
+            system
+                .complete_registration(&blind_request, &claim, &tact_token, &token_proof, EXPIRY)
+                .expect("registration failed"),
+            token
+        );
+        assert!(system.registry().contains(&token));
+        assert_eq!(system.identities().len(), 1);
+    }
+
+    #[test]
+    fn hash_to_curve_suites_are_not_mixed() {
+        assert_eq!(
+            DomainConfig::default().hash_to_curve,
+            HashToCurveSuite::Legacy
+        );
+        let rfc_9380 = DomainConfig::default().with_hash_to_curve(HashToCurveSuite::Rfc9380);
+        let (mut system, issuers) =
+            SpiritSystem::setup_with_domain(T, N, NUM_ISSUERS, rfc_9380.clone())
+                .expect("setup failed");
+        let pp = system.public_parameters();
+        assert_eq!(pp.hash_to_curve(), HashToCurveSuite::Rfc9380);
+        let k = Scalar::rand(&mut OsRng);
+        assert_ne!(
+            prf_broadcast(&rfc_9380, &k, 0, Interval(0)),
+            prf_broadcast(&DomainConfig::default(), &k, 0, Interval(0))
+        );
+
+        let mismatch = SpiritError::HashToCurveMismatch {
+            domain: HashToCurveSuite::Legacy,
+            parameters: HashToCurveSuite::Rfc9380,
+        };
+        let user = UserSecret::random(OsRng);
+        let begin = || {
+            let (blind_request, pending) =
+                spirit_register_begin(&rfc_9380, &user.id_u, pp, EXPIRY, &mut OsRng)
+                    .expect("token request failed");
+            let blind_tokens =
+                issue_verified_blind_tokens(&blind_request, issuers.issuers(), pp).unwrap();
+            (blind_request, pending, blind_tokens)
+        };
+        assert_eq!(
+            spirit_register_begin(&DomainConfig::default(), &user.id_u, pp, EXPIRY, &mut OsRng)
+                .err(),
+            Some(mismatch.clone())
+        );
+        let (_, pending, blind_tokens) = begin();
+        assert_eq!(
+            spirit_register_finish_from(
+                &DomainConfig::default(),
+                pending,
+                &blind_tokens,
+                pp,
+                &mut OsRng
+            )
+            .err(),
+            Some(mismatch.clone())
+        );
+
+        let (blind_request, pending, blind_tokens) = begin();
+        let ((token, _), tact_token, token_proof) =
+            spirit_register_finish_from(&rfc_9380, pending, &blind_tokens, pp, &mut OsRng)
+                .expect("unblinding failed");
+        let mut t_rgstr = HashSet::new();
+        let mut complete = |domain: &DomainConfig| {
+            spirit_complete_registration(
+                domain,
+                &blind_request,
+                &tact_token,
+                &token_proof,
+                pp,
+                EXPIRY,
+                &mut t_rgstr,
+            )
+        };
+        assert_eq!(complete(&DomainConfig::default()), Err(mismatch));
+        assert_eq!(complete(&rfc_9380), Ok(token.clone()));
+
+        system
+            .register(&user, &issuers, EXPIRY)
+            .expect("registration failed");
+    }
+
+    #[test]
+    fn hash_backends_are_not_mixed() {
+        let sha3 = DomainConfig::default().with_hash_backend(HashBackendId::Sha3_256);
+        let (mut system, issuers) =
+            SpiritSystem::setup_with_domain(T, N, NUM_ISSUERS, sha3.clone()).expect("setup failed");
+        let pp = system.public_parameters();
+        assert_eq!(pp.hash_backend(), HashBackendId::Sha3_256);
+        let k = Scalar::rand(&mut OsRng);
+        assert_ne!(
+            prf_broadcast(&sha3, &k, 0, Interval(0)),
+            prf_broadcast(&DomainConfig::default(), &k, 0, Interval(0))
+        );
+
+        // parameters of the SHA3-256 deployment fail loudly with the default domain
+        let user = UserSecret::random(OsRng);
+        let (blind_request, pending) =
+            spirit_register_begin(&sha3, &user.id_u, pp, EXPIRY, &mut OsRng)
+                .expect("token request failed");
+        let blind_tokens =
+            issue_verified_blind_tokens(&blind_request, issuers.issuers(), pp).unwrap();
+        assert_eq!(
+            spirit_register_finish_from(
+                &DomainConfig::default(),
+                pending,
+                &blind_tokens,
+                pp,
+                &mut OsRng
+            )
+            .err(),
+            Some(SpiritError::HashBackendMismatch {
+                domain: HashBackendId::Sha256,
+                parameters: HashBackendId::Sha3_256,
+            })
+        );
+
+        let ppu = system
+            .register(&user, &issuers, EXPIRY)
+            .expect("registration failed");
+        let challenge = system.issue_challenge(NOW);
+        let cp = HashSet::from([(NOW, Interval(0))]);
+        let report = spirit_diagnosis_osrng(&sha3, &ppu, user.keys(), &cp, NOW, &challenge, &[])
+            .expect("diagnosis failed");
+        assert!(
+            system
+                .verify_report(&report, NOW, &mut HashSet::new())
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn misbehaving_issuers_are_replaced() {
+        let SetupOutput {
+            pp,
+            mut issuers,
+            mut t_rgstr,
+            domain,
+            ..
+        } = spirit_setup_osrng(T, N, NUM_ISSUERS, b"test").expect("setup failed");
+        // an issuer with a share that does not match its key in pp
+        issuers[1] = Issuer::new(crate::tsw::SecretKey::random(&mut OsRng));
+
+        let user = UserSecret::random(OsRng);
+        let (token, _) = spirit_register(
+            &domain,
+            &user,
+            &issuers,
+            &pp,
+            EXPIRY,
+            &mut t_rgstr,
+            &mut OsRng,
+        )
+        .expect("registration failed");
+        assert!(t_rgstr.contains(&token));
+
+        let (blind_request, _) =
+            spirit_register_begin(&domain, &user.id_u, &pp, EXPIRY, &mut OsRng)
+                .expect("token request failed");
+        let blind_tokens = issue_verified_blind_tokens(&blind_request, &issuers, &pp).unwrap();
+        assert_eq!(
+            blind_tokens
+                .iter()
+                .map(|(index, _)| *index)
+                .collect::<Vec<_>>(),
+            [0, 2, 3, T]
+        );
+
+        // too few issuers are left
+        for index in [3, T + 1, T + 2, T + 3] {
+            issuers[index] = Issuer::new(crate::tsw::SecretKey::random(&mut OsRng));
+        }
+        assert_eq!(
+            spirit_register(
+                &domain,
+                &UserSecret::random(OsRng),
+                &issuers,
+                &pp,
+                EXPIRY,
+                &mut t_rgstr,
+                &mut OsRng,
+            )
+            .err(),
+            Some(SpiritError::MisbehavingIssuers {
+                issuer_indices: vec![1, 3, T + 1, T + 2, T + 3],
+            })
+        );
+    }
+
+    #[test]
+    fn batch_registration() {
+        let SetupOutput {
+            pp,
+            mut issuers,
+            mut t_rgstr,
+            domain,
+            ..
+        } = spirit_setup_osrng(T, N, NUM_ISSUERS, b"test").expect("setup failed");
+        let ids: Vec<_> = (0..50).map(|_| Scalar::rand(&mut OsRng)).collect();
+
+        let results = spirit_register_batch(
+            &domain,
+            &ids,
+            &issuers,
+            &pp,
+            EXPIRY,
+            &mut t_rgstr,
+            &mut OsRng,
+        );
+        assert_eq!(results.len(), ids.len());
+        assert_eq!(t_rgstr.len(), ids.len());
+        let indices: Vec<_> = (0..ids.len()).collect();
+        for &index in indices.choose_multiple(&mut OsRng, 5) {
+            let ppu = results[index].as_ref().expect("registration failed");
+            assert_eq!(ppu.1.a, ids[index]);
+            assert_eq!(ppu.0.2, EXPIRY);
+            assert!(t_rgstr.contains(&ppu.0));
+            let (presentation, _) = spirit_rerandomize(ppu, &pp, &mut OsRng);
+            assert!(presentation.verify_signature(&pp));
+        }
+
+        // a misbehaving issuer is not replaced within the batch
+        issuers[1] = Issuer::new(crate::tsw::SecretKey::random(&mut OsRng));
+        let results = spirit_register_batch(
+            &domain,
+            &ids[..2],
+            &issuers,
+            &pp,
+            EXPIRY,
+            &mut t_rgstr,
+            &mut OsRng,
+        );
+        let misbehaving = SpiritError::MisbehavingIssuers {
+            issuer_indices: vec![1],
+        };
+        assert_eq!(
+            results.into_iter().map(Result::err).collect::<Vec<_>>(),
+            [Some(misbehaving.clone()), Some(misbehaving)]
+        );
+        let results = spirit_register_batch(
+            &domain,
+            &ids[..1],
+            &issuers[..T - 1],
+            &pp,
+            EXPIRY,
+            &mut t_rgstr,
+            &mut OsRng,
+        );
+        assert_eq!(
+            results.into_iter().map(Result::err).collect::<Vec<_>>(),
+            [Some(SpiritError::NotEnoughIssuers {
+                required: T,
+                available: T - 1,
+            })]
+        );
+    }
+
+    #[test]
+    fn registration_with_chosen_issuers() {
+        let SetupOutput {
+            pp,
+            issuers,
+            mut t_rgstr,
+            domain,
+            ..
+        } = spirit_setup_osrng(T, N, 12, b"test").expect("setup failed");
+        let user = UserSecret::random(OsRng);
+        let (token, strg) = spirit_register_with_issuers(
+            &domain,
+            &user,
+            &issuers,
+            &[2, 5, 7, 11],
+            &pp,
+            EXPIRY,
+            &mut t_rgstr,
+            &mut OsRng,
+        )
+        .expect("registration failed");
+        assert!(t_rgstr.contains(&token));
+
+        // the token is accepted in a trace report
+        let mut challenges = ChallengeSet::new(0);
+        let challenge = challenges.issue(NOW, &mut OsRng);
+        let report = spirit_diagnosis_osrng(
+            &domain,
+            &(token, strg),
+            &user.keys(),
+            &HashSet::from([(0, Interval(0))]),
+            NOW,
+            &challenge,
+            &[],
+        )
+        .expect("diagnosis failed");
+        assert_eq!(
+            spirit_verify(
+                &domain,
+                &report,
+                &t_rgstr,
+                &RevocationList::new(),
+                NOW,
+                &mut HashSet::new(),
+                &challenges,
+                &mut HashSet::new(),
+            ),
+            accepted(&[true])
+        );
+
+        for (indices, error) in [
+            (
+                &[2, 5, 5, 7][..],
+                SpiritError::DuplicateIssuer { issuer_index: 5 },
+            ),
+            (
+                &[2, 5, 7, 12],
+                SpiritError::InvalidIssuerIndex {
+                    issuer_index: 12,
+                    num_issuers: 12,
+                },
+            ),
+            (
+                &[2, 5, 7],
+                SpiritError::NotEnoughIssuers {
+                    required: T,
+                    available: 3,
+                },
+            ),
+        ] {
+            assert_eq!(
+                spirit_register_with_issuers(
+                    &domain,
+                    &UserSecret::random(OsRng),
+                    &issuers,
+                    indices,
+                    &pp,
+                    EXPIRY,
+                    &mut t_rgstr,
+                    &mut OsRng,
+                )
+                .err(),
+                Some(error)
+            );
+        }
+
+        // repeated blind tokens do not stand in for another issuer's
+        let (blind_request, pending) =
+            spirit_register_begin(&domain, &user.id_u, &pp, EXPIRY, &mut OsRng)
+                .expect("token request failed");
+        let blind_tokens: Vec<_> = [2, 5, 5, 7]
+            .into_iter()
+            .map(|index| (index, tissue(&blind_request, &issuers[index], &pp).unwrap()))
+            .collect();
+        assert_eq!(
+            spirit_register_finish_from(&domain, pending, &blind_tokens, &pp, &mut OsRng).err(),
+            Some(SpiritError::DuplicateIssuer { issuer_index: 5 })
+        );
+    }
+
+    #[test]
+    fn registration_with_committee() {
+        const NUM_ISSUERS: usize = 12;
+
+        let SetupOutput {
+            pp,
+            issuers,
+            mut t_rgstr,
+            domain,
+            ..
+        } = spirit_setup_osrng(T, N, NUM_ISSUERS, b"test").expect("setup failed");
+        let domain = domain.with_committee_beacon(b"beacon".to_vec());
+        // an epoch whose committee is not the first issuers, which spirit_register asks
+        let first: Vec<_> = (0..T).collect();
+        let epoch = (NOW..)
+            .find(|epoch| select_committee(b"beacon", *epoch, NUM_ISSUERS, T) != first)
+            .unwrap();
+        let issuers: Vec<_> = issuers
+            .into_iter()
+            .enumerate()
+            .map(|(index, issuer)| {
+                let mut issuer = issuer.with_committee(b"beacon".to_vec(), index, NUM_ISSUERS);
+                issuer.advance_epoch(epoch);
+                issuer
+            })
+            .collect();
+
+        let (token, _) = spirit_register_with_committee(
+            &domain,
+            &UserSecret::random(OsRng),
+            &issuers,
+            epoch,
+            &pp,
+            EXPIRY,
+            &mut t_rgstr,
+            &mut OsRng,
+        )
+        .expect("registration failed");
+        assert!(t_rgstr.contains(&token));
+
+        let rejected = spirit_register(
+            &domain,
+            &UserSecret::random(OsRng),
+            &issuers,
+            &pp,
+            EXPIRY,
+            &mut t_rgstr,
+            &mut OsRng,
+        );
+        assert!(matches!(
+            rejected,
+            Err(SpiritError::IssuerRejected {
+                source: AtACTError::NotInCommittee { epoch: rejected },
+                ..
+            }) if rejected == epoch
+        ));
+        assert_eq!(t_rgstr.len(), 1);
+    }
+
+    #[test]
+    fn audited_registration() {
+        let SetupOutput {
+            pp,
+            issuers,
+            mut t_rgstr,
+            domain,
+            ..
+        } = spirit_setup_osrng(T, N, NUM_ISSUERS, b"test").expect("setup failed");
+        let user = UserSecret::random(OsRng);
+        let ppu = spirit_register_audited(
+            &domain,
+            &user,
+            &issuers,
+            &pp,
+            EXPIRY,
+            &mut t_rgstr,
+            &mut OsRng,
+        )
+        .expect("registration failed");
+        assert!(t_rgstr.contains(&ppu.0));
+
+        // the registrar rejects the proof for another identity, and a proof of another request
+        let (blind_request, pending) =
+            spirit_register_begin(&domain, &user.id_u, &pp, EXPIRY, &mut OsRng)
+                .expect("token request failed");
+        let audit_proof = pending.audit_proof(&mut OsRng);
+        let (_, other) = spirit_register_begin(&domain, &user.id_u, &pp, EXPIRY, &mut OsRng)
+            .expect("token request failed");
+        let other_proof = other.audit_proof(&mut OsRng);
+        let blind_tokens = issue_blind_tokens(&blind_request, &issuers, &pp).unwrap();
+        let (_, token, token_proof) =
+            spirit_register_finish(&domain, pending, &blind_tokens, &pp, &mut OsRng)
+                .expect("unblinding failed");
+        let other_id = user.id_u + Scalar::from(1u64);
+        for (id_u, proof) in [(&other_id, &audit_proof), (&user.id_u, &other_proof)] {
+            assert!(matches!(
+                spirit_complete_registration_audited(
+                    &domain,
+                    &blind_request,
+                    id_u,
+                    proof,
+                    &token,
+                    &token_proof,
+                    &pp,
+                    EXPIRY,
+                    &mut t_rgstr,
+                ),
+                Err(SpiritError::InvalidAuditProof)
+            ));
+        }
+        assert_eq!(t_rgstr.len(), 1);
+
+        spirit_complete_registration_audited(
+            &domain,
+            &blind_request,
+            &user.id_u,
+            &audit_proof,
+            &token,
+            &token_proof,
+            &pp,
+            EXPIRY,
+            &mut t_rgstr,
+        )
+        .expect("registration failed");
+        assert_eq!(t_rgstr.len(), 2);
+    }
+
+    #[test]
+    fn key_bound_reports_prove_the_registered_key() {
+        let SetupOutput {
+            pp,
+            issuers,
+            mut t_rgstr,
+            domain,
+            ..
+        } = spirit_setup_with_attributes(T, N, NUM_ISSUERS, 2, b"test", &mut OsRng)
+            .expect("setup failed");
+        let domain = domain.with_key_binding();
+        let user = UserSecret::random(OsRng);
+        let ppu = spirit_register_key_bound(
+            &domain,
+            &user,
+            &issuers,
+            &pp,
+            EXPIRY,
+            &mut t_rgstr,
+            &mut OsRng,
+        )
+        .expect("registration failed");
+        assert!(t_rgstr.contains(&ppu.0));
+
+        let mut challenges = ChallengeSet::new(0);
+        let challenge = challenges.issue(NOW, &mut OsRng);
+        let cp = HashSet::from([(NOW - 1, 3), (NOW, Interval(1))]);
+        let diagnose = |keys: &UserKeys| {
+            spirit_diagnosis_osrng(&domain, &ppu, keys, &cp, NOW, &challenge, &[])
+                .expect("diagnosis failed")
+        };
+        let verify = |domain: &DomainConfig, report: &TraceReport| {
+            spirit_verify(
+                domain,
+                report,
+                &t_rgstr,
+                &RevocationList::new(),
+                NOW,
+                &mut HashSet::new(),
+                &challenges,
+                &mut HashSet::new(),
+            )
+        };
+
+        // the disclosed ElIDs are the ones broadcast with the registered key
+        let keys = user.keys().key_bound();
+        let mut report = diagnose(&keys);
+        assert_eq!(verify(&domain, &report), accepted(&[true, true]));
+        // the proof carries its challenge and responses only
+        assert_eq!(
+            report.key_binding.as_ref().unwrap().compressed_size(),
+            3 * 32
+        );
+        // the points of the attributes' proof are checked before the key binding
+        let malformed = Err(VerifyRejection::MalformedPoint { token: ppu.0.id() });
+        let mut encoded = Vec::new();
+        report.serialize_compressed(&mut encoded).unwrap();
+        let degenerate: [fn(&mut DisclosureProof); 2] = [
+            |proof| proof.identity = Commitment(G1G2::default()),
+            |proof| proof.t = G1G2::default(),
+        ];
+        for make_degenerate in degenerate {
+            let mut tr = TraceReport::deserialize_compressed(&encoded[..]).unwrap();
+            make_degenerate(tr.attributes.as_mut().unwrap());
+            assert_eq!(verify(&domain, &tr), malformed);
+        }
+        for disclosure in &report.disclosures {
+            let el_id = prf_broadcast(&domain, &user.keys.prv, disclosure.epoch, disclosure.index);
+            assert_eq!(disclosure.el_id, el_id);
+        }
+
+        // ElIDs of another key, without a key binding or with the one of the registered key
+        let rejected = Err(VerifyRejection::InvalidKeyBinding { token: ppu.0.id() });
+        let other = UserKeys::random(OsRng);
+        let mut forged = diagnose(&other);
+        assert_eq!(verify(&domain, &forged), rejected);
+        forged.key_binding = report.key_binding.take();
+        assert_eq!(verify(&domain, &forged), rejected);
+        assert_eq!(verify(&domain, &diagnose(user.keys())), rejected);
+        assert!(matches!(
+            spirit_diagnosis_osrng(&domain, &ppu, &other.key_bound(), &cp, NOW, &challenge, &[]),
+            Err(SpiritError::UnboundKey)
+        ));
+
+        // deployments that do not require the binding still accept reports without one
+        let unbound = DomainConfig {
+            require_key_binding: false,
+            ..domain.clone()
+        };
+        assert_eq!(verify(&unbound, &diagnose(&other)), accepted(&[true, true]));
+
+        // the registrar rejects the key claim of another request, and parameters without an
+        // attribute for the key are rejected
+        let (blind_request, pending, _) = spirit_register_begin_key_bound(
+            &domain,
+            &user.id_u,
+            user.keys(),
+            &pp,
+            EXPIRY,
+            &mut OsRng,
+        )
+        .expect("token request failed");
+        let (_, _, other_claim) =
+            spirit_register_begin_key_bound(&domain, &user.id_u, &other, &pp, EXPIRY, &mut OsRng)
+                .expect("token request failed");
+        let blind_tokens = issue_blind_tokens(&blind_request, &issuers, &pp).unwrap();
+        let (_, token, token_proof) =
+            spirit_register_finish(&domain, pending, &blind_tokens, &pp, &mut OsRng)
+                .expect("unblinding failed");
+        assert!(matches!(
+            spirit_complete_registration_key_bound(
+                &domain,
+                &blind_request,
+                &other_claim,
+                &token,
+                &token_proof,
+                &pp,
+                EXPIRY,
+                &mut t_rgstr,
+            ),
+            Err(SpiritError::InvalidKeyClaim)
+        ));
+        let SetupOutput { pp, .. } =
+            spirit_setup_osrng(T, N, NUM_ISSUERS, b"test").expect("setup failed");
+        assert!(matches!(
+            spirit_register_begin_key_bound(
+                &domain,
+                &user.id_u,
+                user.keys(),
+                &pp,
+                EXPIRY,
+                &mut OsRng
+            ),
+            Err(SpiritError::RegistrationFailed(
+                AtACTError::InvalidAttribute
+            ))
+        ));
+    }
+
+    #[test]
+    fn registration_with_attributes() {
+        // the identity, a jurisdiction code and an expiry epoch
+        let SetupOutput {
+            pp,
+            issuers,
+            mut t_rgstr,
+            domain,
+            ..
+        } = spirit_setup_with_attributes(3, 4, 4, 3, b"test", &mut OsRng).expect("setup failed");
+        let user = UserSecret::random(OsRng);
+        let attributes = [Scalar::from(276u64), Scalar::from(EXPIRY)];
+
+        let ppu = spirit_register_with_attributes(
+            &domain,
+            &user,
+            &attributes,
+            &issuers,
+            &pp,
+            EXPIRY,
+            &mut t_rgstr,
+            &mut OsRng,
+        )
+        .expect("registration failed");
+        assert!(t_rgstr.contains(&ppu.0));
+
+        // the token is used like one without attributes
+        let mut challenges = ChallengeSet::new(0);
+        let challenge = challenges.issue(NOW, &mut OsRng);
+        let cp = HashSet::from([(NOW, Interval(1))]);
+        let report = spirit_diagnosis_osrng(
+            &domain,
+            &ppu,
+            &UserKeys::random(OsRng),
+            &cp,
+            NOW,
+            &challenge,
+            &[],
+        )
+        .expect("diagnosis failed");
+        assert_eq!(
+            spirit_verify(
+                &domain,
+                &report,
+                &t_rgstr,
+                &RevocationList::new(),
+                NOW,
+                &mut HashSet::new(),
+                &challenges,
+                &mut HashSet::new(),
+            ),
+            accepted(&[true])
+        );
+
+        // the registrar accepts only the committed attributes
+        let (blind_request, pending) = spirit_register_begin_with_attributes(
+            &domain,
+            &user.id_u,
+            &attributes,
+            &pp,
+            EXPIRY,
+            &mut OsRng,
+        )
+        .expect("token request failed");
+        let blind_tokens = issue_blind_tokens(&blind_request, &issuers, &pp).unwrap();
+        let (_, token, token_proof) =
+            spirit_register_finish(&domain, pending, &blind_tokens, &pp, &mut OsRng)
+                .expect("unblinding failed");
+        let other = [Scalar::from(840u64), Scalar::from(EXPIRY)];
+        for disclosed in [&other[..], &attributes[..1], &[]] {
+            assert!(matches!(
+                spirit_complete_registration_with_attributes(
+                    &domain,
+                    &blind_request,
+                    disclosed,
+                    &token,
+                    &token_proof,
+                    &pp,
+                    EXPIRY,
+                    &mut t_rgstr,
+                ),
+                Err(SpiritError::ProofVerificationFailed(
+                    AtACTError::InvalidZKProof
+                ))
+            ));
+        }
+        assert_eq!(t_rgstr.len(), 1);
+
+        assert!(matches!(
+            spirit_register_begin_with_attributes(
+                &domain,
+                &user.id_u,
+                &[Scalar::from(1u64); 3],
+                &pp,
+                EXPIRY,
+                &mut OsRng
+            ),
+            Err(SpiritError::RegistrationFailed(
+                AtACTError::InvalidAttribute
+            ))
+        ));
+    }
+
+    #[test]
+    fn registration_with_any_number_of_attributes() {
+        // one attribute is the deployment of spirit_setup
+        let setup = |num_attributes| {
+            let mut rng = ChaCha20Rng::seed_from_u64(85);
+            spirit_setup_with_attributes(3, 4, 4, num_attributes, b"test", &mut rng)
+                .expect("setup failed")
+        };
+        let single = spirit_setup(3, 4, 4, b"test", &mut ChaCha20Rng::seed_from_u64(85))
+            .expect("setup failed");
+        assert_eq!(setup(1).pp.pk, single.pp.pk);
+        assert!(setup(1).pp.attribute_bases == single.pp.attribute_bases);
+
+        for num_attributes in [1, 3, 8] {
+            let SetupOutput {
+                pp,
+                issuers,
+                mut t_rgstr,
+                domain,
+                ..
+            } = setup(num_attributes);
+            assert_eq!(pp.num_attributes(), num_attributes);
+            assert_eq!(pp.validate(), Ok(()));
+
+            let user = UserSecret::random(OsRng);
+            let attributes: Vec<_> = (1..num_attributes as u64).map(Scalar::from).collect();
+            let ppu = spirit_register_with_attributes(
+                &domain,
+                &user,
+                &attributes,
+                &issuers,
+                &pp,
+                EXPIRY,
+                &mut t_rgstr,
+                &mut OsRng,
+            )
+            .expect("registration failed");
+
+            let mut challenges = ChallengeSet::new(0);
+            let challenge = challenges.issue(NOW, &mut OsRng);
+            let report = spirit_diagnosis_osrng(
+                &domain,
+                &ppu,
+                &UserKeys::random(OsRng),
+                &HashSet::from([(NOW, Interval(1))]),
+                NOW,
+                &challenge,
+                &[],
+            )
+            .expect("diagnosis failed");
+            assert_eq!(
+                spirit_verify(
+                    &domain,
+                    &report,
+                    &t_rgstr,
+                    &RevocationList::new(),
+                    NOW,
+                    &mut HashSet::new(),
+                    &challenges,
+                    &mut HashSet::new(),
+                ),
+                accepted(&[true])
+            );
+
+            // attributes missing at the end are zero, and one too many is rejected
+            let padded = &attributes[..attributes.len().saturating_sub(1)];
+            assert!(
+                spirit_register_with_attributes(
+                    &domain,
+                    &UserSecret::random(OsRng),
+                    padded,
+                    &issuers,
+                    &pp,
+                    EXPIRY,
+                    &mut t_rgstr,
+                    &mut OsRng,
+                )
+                .is_ok()
+            );
+            let too_many = vec![Scalar::from(1u64); num_attributes];
+            assert!(matches!(
+                spirit_register_begin_with_attributes(
+                    &domain, &user.id_u, &too_many, &pp, EXPIRY, &mut OsRng
+                ),
+                Err(SpiritError::RegistrationFailed(
+                    AtACTError::InvalidAttribute
+                ))
+            ));
+        }
+    }
+
+    #[test]
+    fn selective_disclosure() {
+        let SetupOutput {
+            pp,
+            issuers,
+            mut t_rgstr,
+            domain,
+            ..
+        } = spirit_setup_with_attributes(3, 4, 4, 3, b"test", &mut OsRng).expect("setup failed");
+        let user = UserSecret::random(OsRng);
+        let jurisdiction = Scalar::from(276u64);
+        let ppu = spirit_register_with_attributes(
+            &domain,
+            &user,
+            &[jurisdiction, Scalar::from(EXPIRY)],
+            &issuers,
+            &pp,
+            EXPIRY,
+            &mut t_rgstr,
+            &mut OsRng,
+        )
+        .expect("registration failed");
+
+        let keys = UserKeys::random(OsRng);
+        let cp = HashSet::from([(NOW, Interval(1)), (NOW, Interval(2))]);
+        let mut challenges = ChallengeSet::new(0);
+        let challenge = challenges.issue(NOW, &mut OsRng);
+        let verify = |report: &TraceReport| {
+            spirit_verify(
+                &domain,
+                report,
+                &t_rgstr,
+                &RevocationList::new(),
+                NOW,
+                &mut HashSet::new(),
+                &challenges,
+                &mut HashSet::new(),
+            )
+        };
+
+        let report = spirit_diagnosis_osrng(&domain, &ppu, &keys, &cp, NOW, &challenge, &[1])
+            .expect("diagnosis failed");
+        assert_eq!(verify(&report), accepted(&[true; 2]));
+        assert_eq!(
+            report.disclosed_attributes(),
+            Some(&BTreeMap::from([(1, jurisdiction)]))
+        );
+
+        // a wrong value for a revealed or a hidden attribute is rejected
+        let mut forged = report;
+        *forged
+            .attributes
+            .as_mut()
+            .unwrap()
+            .revealed
+            .get_mut(&1)
+            .unwrap() = Scalar::from(840u64);
+        let invalid = Err(VerifyRejection::InvalidAttributes { token: ppu.0.id() });
+        assert_eq!(verify(&forged), invalid);
+        let mut other = ppu.clone();
+        other.1.attributes[1] = Scalar::from(EXPIRY + 1);
+        let report = spirit_diagnosis_osrng(&domain, &other, &keys, &cp, NOW, &challenge, &[1])
+            .expect("diagnosis failed");
+        assert_eq!(verify(&report), invalid);
+
+        // the identity and indices beyond the token's attributes cannot be disclosed
+        for index in [0, 3] {
+            assert_eq!(
+                spirit_diagnosis_osrng(&domain, &ppu, &keys, &cp, NOW, &challenge, &[1, index])
+                    .err(),
+                Some(SpiritError::InvalidAttributeIndex {
+                    index,
+                    num_attributes: 3
+                })
+            );
+        }
+        assert_eq!(
+            spirit_diagnosis_osrng(&domain, &ppu, &keys, &cp, NOW, &challenge, &[1, 2])
+                .map(|report| verify(&report)),
+            Ok(accepted(&[true; 2]))
+        );
+
+        // verifiers check the proofs against the attributes of their deployment
+        assert_eq!(domain.num_attributes, 3);
+        let report = spirit_diagnosis_osrng(&domain, &ppu, &keys, &cp, NOW, &challenge, &[1])
+            .expect("diagnosis failed");
+        let fewer = DomainConfig {
+            num_attributes: 2,
+            ..domain.clone()
+        };
+        assert_eq!(
+            spirit_verify(
+                &fewer,
+                &report,
+                &t_rgstr,
+                &RevocationList::new(),
+                NOW,
+                &mut HashSet::new(),
+                &challenges,
+                &mut HashSet::new(),
+            ),
+            invalid
+        );
+    }
+
+    #[test]
+    fn register_identity_twice() {
+        let (mut system, issuers) = SpiritSystem::setup(T, N, NUM_ISSUERS).expect("setup failed");
+        let user = UserSecret::random(OsRng);
+        system
+            .register(&user, &issuers, EXPIRY)
+            .expect("registration failed");
+
+        // the second request re-randomizes the commitment, but has the same tag
+        assert_eq!(
+            system.register(&user, &issuers, EXPIRY).err(),
+            Some(SpiritError::AlreadyRegistered)
+        );
+        assert_eq!(system.registry().len(), 1);
+
+        system
+            .register(&UserSecret::random(OsRng), &issuers, EXPIRY)
+            .expect("registration failed");
+        assert_eq!(system.registry().len(), 2);
+        assert_eq!(system.identities().len(), 2);
+    }
+
+    #[test]
+    fn register_identity_twice_concurrently() {
+        let (mut system, issuers) = SpiritSystem::setup(T, N, NUM_ISSUERS).expect("setup failed");
+        let domain = system.domain().clone();
+        let id_u = Scalar::rand(&mut OsRng);
+
+        // both requests are issued before either is registered
+        let requests: Vec<_> = (0..2)
+            .map(|_| {
+                let (blind_request, pending) = spirit_register_begin(
+                    &domain,
+                    &id_u,
+                    system.public_parameters(),
+                    EXPIRY,
+                    &mut OsRng,
+                )
+                .expect("token request failed");
+                let claim = pending.identity_claim(&domain, &mut OsRng);
+                let blind_tokens = system
+                    .issue(&issuers, &blind_request, &claim)
+                    .expect("issuance failed");
+                let (_, tact_token, token_proof) = spirit_register_finish(
+                    &domain,
+                    pending,
+                    &blind_tokens,
+                    system.public_parameters(),
+                    &mut OsRng,
+                )
+                .expect("unblinding failed");
+                (blind_request, claim, tact_token, token_proof)
+            })
+            .collect();
+
+        let results: Vec<_> = requests
+            .iter()
+            .map(|(blind_request, claim, tact_token, token_proof)| {
+                system
+                    .complete_registration(blind_request, claim, tact_token, token_proof, EXPIRY)
+                    .err()
+            })
+            .collect();
+        assert_eq!(results, [None, Some(SpiritError::AlreadyRegistered)]);
+        assert_eq!(system.registry().len(), 1);
+    }
+
+    #[test]
+    fn forged_identity_claim() {
+        let (system, issuers) = SpiritSystem::setup(T, N, NUM_ISSUERS).expect("setup failed");
+        let pp = system.public_parameters();
+        let (blind_request, pending) = spirit_register_begin(
+            system.domain(),
+            &Scalar::rand(&mut OsRng),
+            pp,
+            EXPIRY,
+            &mut OsRng,
+        )
+        .expect("token request failed");
+        let (_, other) = spirit_register_begin(
+            system.domain(),
+            &Scalar::rand(&mut OsRng),
+            pp,
+            EXPIRY,
+            &mut OsRng,
+        )
+        .expect("token request failed");
+
+        let claim = other.identity_claim(system.domain(), &mut OsRng);
+        assert_eq!(
+            system.issue(&issuers, &blind_request, &claim).err(),
+            Some(SpiritError::InvalidIdentityClaim)
+        );
+
+        // a claim is only valid in the deployment it was made for
+        let claim = pending.identity_claim(&DomainConfig::new(b"other".to_vec()), &mut OsRng);
+        assert_eq!(
+            claim.verify(system.domain(), &blind_request),
+            Err(SpiritError::InvalidIdentityClaim)
+        );
+        let claim = pending.identity_claim(system.domain(), &mut OsRng);
+        assert_eq!(claim.verify(system.domain(), &blind_request), Ok(()));
+    }
+
+    #[test]
+    fn register_finish_invalid_blind_tokens() {
+        let (system, issuers) = SpiritSystem::setup(T, N, NUM_ISSUERS).expect("setup failed");
+        let pp = system.public_parameters();
+
+        let (blind_request, pending) =
+            spirit_register_begin(&domain, &Scalar::rand(&mut OsRng), pp, EXPIRY, &mut OsRng)
+                .expect("token request failed");
+        let claim = pending.identity_claim(system.domain(), &mut OsRng);
+        let mut blind_tokens = system
+            .issue(&issuers, &blind_request, &claim)
+            .expect("issuance failed");
+        blind_tokens[1].pop();
+
+        assert!(matches!(
+            spirit_register_finish(system.domain(), pending, &blind_tokens, pp, &mut OsRng),
+            Err(SpiritError::InvalidBlindTokens {
+                issuer_index: 1,
+                expected: N,
+                actual,
+            }) if actual == N - 1
+        ));
+    }
+
+    #[cfg(feature = "zeroize")]
+    #[test]
+    fn user_secret_zeroized_on_drop() {
+        use crate::bls381_helpers::test::assert_zeroized_on_drop;
+
+        assert_zeroized_on_drop(UserSecret::random(OsRng), |user| {
+            vec![user.id_u, user.keys.prv]
+        });
+    }
+
+    #[test]
+    fn setup_invalid_parameters() {
+        assert_eq!(
+            SpiritSystem::setup(NUM_ISSUERS, N, NUM_ISSUERS).err(),
+            Some(SpiritError::SetupFailed(AtACTError::InvalidParameters))
+        );
+        assert!(matches!(
+            spirit_setup_osrng(0, N, NUM_ISSUERS, b"test"),
+            Err(SpiritError::SetupFailed(AtACTError::InvalidParameters))
+        ));
+    }
+
+    #[test]
+    fn spirit_config_rejects_invalid_combinations() {
+        let committee =
+            |t, num_issuers| SpiritConfig::new().threshold(t).committee_size(num_issuers);
+        for (config, expected) in [
+            (SpiritConfig::new(), ConfigError::ZeroThreshold),
+            // t - 1 would underflow
+            (committee(0, 4), ConfigError::ZeroThreshold),
+            (
+                committee(1, 4),
+                ConfigError::ThresholdTooLow { threshold: 1 },
+            ),
+            (
+                committee(2, 4),
+                ConfigError::ThresholdTooLow { threshold: 2 },
+            ),
+            (
+                committee(4, 4),
+                ConfigError::ThresholdNotBelowCommitteeSize {
+                    threshold: 4,
+                    committee_size: 4,
+                },
+            ),
+            (
+                committee(5, 4),
+                ConfigError::ThresholdNotBelowCommitteeSize {
+                    threshold: 5,
+                    committee_size: 4,
+                },
+            ),
+            (
+                committee(3, 0),
+                ConfigError::ThresholdNotBelowCommitteeSize {
+                    threshold: 3,
+                    committee_size: 0,
+                },
+            ),
+            (
+                committee(4, 8).request_size(3),
+                ConfigError::ThresholdAboveRequestSize {
+                    threshold: 4,
+                    request_size: 3,
+                },
+            ),
+            (
+                committee(3, 4).attribute_count(0),
+                ConfigError::NoAttributes,
+            ),
+            (
+                committee(3, 4)
+                    .domain(DomainConfig::default().with_hash_to_curve(HashToCurveSuite::Rfc9380))
+                    .hash_backend(HashBackendId::Sha3_256),
+                ConfigError::UnsupportedHashSuite {
+                    suite: HashToCurveSuite::Rfc9380,
+                    backend: HashBackendId::Sha3_256,
+                },
+            ),
+        ] {
+            assert_eq!(config.validate(), Err(expected.clone()));
+            assert_eq!(config.build(&mut OsRng).err(), Some(expected));
+        }
+        // the free functions fail as before
+        assert!(matches!(
+            spirit_setup_osrng(2, 3, 3, b"test"),
+            Err(SpiritError::SetupFailed(AtACTError::InvalidParameters))
+        ));
+
+        let (system, issuers) = committee(3, 4)
+            .request_size(3)
+            .hash_backend(HashBackendId::Sha3_256)
+            .build(&mut OsRng)
+            .expect("setup failed");
+        assert_eq!(issuers.issuers().len(), 4);
+        let pp = system.public_parameters();
+        assert_eq!((pp.t, pp.tprime, pp.n), (3, 2, 3));
+        assert_eq!(pp.hash_backend(), HashBackendId::Sha3_256);
+        assert_eq!(system.domain().hash_backend, HashBackendId::Sha3_256);
+    }
+
+    #[test]
+    fn register_not_enough_issuers() {
+        let (mut system, mut issuers) =
+            SpiritSystem::setup(T, N, NUM_ISSUERS).expect("setup failed");
+        issuers.issuers.truncate(T - 1);
+        let user = UserSecret::random(OsRng);
+
+        assert_eq!(
+            system.register(&user, &issuers, EXPIRY).err(),
+            Some(SpiritError::NotEnoughIssuers {
+                required: T,
+                available: T - 1
+            })
+        );
+        assert!(system.registry().is_empty());
+    }
+
+    #[test]
+    fn register_foreign_issuers() {
+        let (mut system, _) = SpiritSystem::setup(T, N, NUM_ISSUERS).expect("setup failed");
+        let (_, foreign_issuers) = SpiritSystem::setup(T, N, NUM_ISSUERS).expect("setup failed");
+        let user = UserSecret::random(OsRng);
+
+        assert!(matches!(
+            system.register(&user, &foreign_issuers, EXPIRY),
+            Err(SpiritError::ProofVerificationFailed(
+                AtACTError::InvalidSignature(_)
+            ))
+        ));
+        assert!(system.registry().is_empty());
+    }
+
+    #[test]
+    fn malformed_inputs_are_errors() {
+        assert!(spirit_setup_osrng(0, N, NUM_ISSUERS, b"test").is_err());
+        assert!(SpiritSystem::setup(0, N, NUM_ISSUERS).is_err());
+
+        // no issuers
+        let SetupOutput {
+            pp,
+            mut t_rgstr,
+            domain,
+            ..
+        } = spirit_setup_osrng(T, N, NUM_ISSUERS, b"test").expect("setup failed");
+        let user = UserSecret::random(OsRng);
+        assert_eq!(
+            spirit_register(&domain, &user, &[], &pp, EXPIRY, &mut t_rgstr, &mut OsRng).err(),
+            Some(SpiritError::NotEnoughIssuers {
+                required: T,
+                available: 0
+            })
+        );
+        let ids = [user.id_u];
+        assert!(matches!(
+            &spirit_register_batch(&domain, &ids, &[], &pp, EXPIRY, &mut t_rgstr, &mut OsRng)[..],
+            [Err(SpiritError::NotEnoughIssuers { .. })]
+        ));
+        assert!(t_rgstr.is_empty());
+
+        // a request begun with the parameters of another request size
+        let SetupOutput { pp: other_pp, .. } =
+            spirit_setup_osrng(T, N - 1, NUM_ISSUERS, b"test").expect("setup failed");
+        let (_, pending) =
+            spirit_register_begin(&domain, &user.id_u, &other_pp, EXPIRY, &mut OsRng)
+                .expect("token request failed");
+        assert_eq!(
+            spirit_register_finish(&domain, pending, &Vec::new(), &pp, &mut OsRng).err(),
+            Some(SpiritError::TokenRequestFailed(
+                AtACTError::InvalidRequestLength {
+                    expected: N,
+                    actual: N - 1
+                }
+            ))
+        );
+
+        // truncated encodings
+        let (mut report, mut system, t_el) =
+            diagnosed_report(&[(0, Interval(0)), (1, Interval(1))]);
+        let mut bytes = Vec::new();
+        report.serialize_compressed(&mut bytes).unwrap();
+        for len in [0, 1, bytes.len() / 2, bytes.len() - 1] {
+            assert!(TraceReport::deserialize_compressed(&bytes[..len]).is_err());
+        }
+        let token = report.token.to_bytes();
+        for len in [
+            0,
+            HEADER_SIZE,
+            Token::ENCODED_SIZE - 8,
+            Token::ENCODED_SIZE - 1,
+        ] {
+            assert!(Token::from_bytes(&token[..len]).is_err());
+        }
+        let table = t_el.to_bytes();
+        for len in [
+            0,
+            HEADER_SIZE + 8,
+            HEADER_SIZE + 8 + PackedEncounter::ENCODED_SIZE,
+        ] {
+            assert!(EncounterTable::from_bytes(&table[..len]).is_err());
+        }
+
+        // a disclosure of an epoch whose keys are missing
+        report.cm_prv.remove(&report.disclosures[0].epoch);
+        let rejection = || VerifyRejection::InvalidProof {
+            el_id: report.disclosures[0].el_id.clone(),
+        };
+        let mut cf = HashSet::new();
+        assert_eq!(
+            system.verify_reports(slice::from_ref(&report), NOW, &mut cf),
+            [Err(rejection())]
+        );
+        assert_eq!(
+            system.verify_report(&report, NOW, &mut cf),
+            Err(rejection())
+        );
+        assert!(cf.is_empty());
+    }
+
+    #[test]
+    fn token_expiry() {
+        let domain = DomainConfig::default();
+        let (mut system, issuers) = SpiritSystem::setup(T, N, NUM_ISSUERS).expect("setup failed");
+        let keys = UserKeys::random(OsRng);
+        let (token, strg) = system
+            .register(&UserSecret::random(OsRng), &issuers, EXPIRY)
+            .expect("registration failed");
+        assert_eq!(token.2, EXPIRY);
+        assert_eq!(
+            token.identity_commitment(&domain),
+            Commitment(&token.0.0 - expiry_term(&domain, EXPIRY))
+        );
+        assert!(!token.is_expired(EXPIRY - 1));
+        assert!(token.is_expired(EXPIRY));
+
+        // the reports disclose an encounter before expiry but are submitted at and after it
+        let ppu = (token, strg);
+        let report = |report_epoch, challenge: &ReportChallenge| {
+            spirit_diagnosis_osrng(
+                &domain,
+                &ppu,
+                &keys,
+                &HashSet::from([(EXPIRY - 1, Interval(0))]),
+                report_epoch,
+                challenge,
+                &[],
+            )
+            .expect("diagnosis failed")
+        };
+        let expired = Err(VerifyRejection::Expired {
+            token: ppu.0.id(),
+            expiry: EXPIRY,
+        });
+        for (current_epoch, result) in [
+            (NOW, accepted(&[true])),
+            (EXPIRY - 1, accepted(&[true])),
+            (EXPIRY, expired.clone()),
+            (EXPIRY + 1, expired),
+        ] {
+            let challenge = system.issue_challenge(current_epoch);
+            assert_eq!(
+                system.verify_report(
+                    &report(current_epoch, &challenge),
+                    current_epoch,
+                    &mut HashSet::new()
+                ),
+                result
+            );
+            system.nullifiers.clear();
+            assert_eq!(
+                system.verify_reports(
+                    &[report(current_epoch, &challenge)],
+                    current_epoch,
+                    &mut HashSet::new()
+                ),
+                vec![result]
+            );
+        }
+
+        // a token relabelled with a later expiry is neither registered nor bound to the proofs
+        let challenge = system.issue_challenge(EXPIRY);
+        let mut relabelled = report(EXPIRY, &challenge);
+        relabelled.token.2 = EXPIRY + 100;
+        let token = relabelled.token.id();
+        assert_eq!(
+            system.verify_report(&relabelled, EXPIRY, &mut HashSet::new()),
+            Err(VerifyRejection::Unregistered { token })
+        );
+        system.t_rgstr.insert(relabelled.token.clone());
+        assert_eq!(
+            system.verify_report(&relabelled, EXPIRY, &mut HashSet::new()),
+            Err(VerifyRejection::InvalidNullifier { token })
+        );
+    }
+
+    #[test]
+    fn revocation() {
+        let domain = DomainConfig::default();
+        let (report, mut system, _) = diagnosed_report(&[(0, Interval(1)), (0, Interval(4))]);
+        let other = diagnosed_report(&[(0, Interval(2))]).0;
+        system.t_rgstr.insert(other.token.clone());
+        system.challenges.issued.insert(other.challenge.clone());
+        let reports = [report, other];
+
+        assert_eq!(
+            spirit_verify(
+                &domain,
+                &reports[0],
+                system.registry(),
+                system.revocations(),
+                NOW,
+                &mut HashSet::new(),
+                system.challenges(),
+                &mut HashSet::new()
+            ),
+            accepted(&[true, true])
+        );
+
+        assert!(system.revoke(&reports[0].token));
+        assert!(!system.revoke(&reports[0].token));
+        assert_eq!(system.revocations().len(), 1);
+        assert!(system.registry().contains(&reports[0].token));
+
+        let revoked = Err(VerifyRejection::Revoked {
+            token: reports[0].token.id(),
+        });
+        let mut confirmed = HashSet::new();
+        assert_eq!(
+            system.verify_report(&reports[0], NOW, &mut confirmed),
+            revoked
+        );
+        assert!(confirmed.is_empty());
+        assert_eq!(
+            system.verify_reports(&reports, NOW, &mut confirmed),
+            [revoked.clone(), accepted(&[true])]
+        );
+        assert_eq!(confirmed.len(), 1);
+
+        // a verifier syncing the list from its encoding rejects the report as well
+        let synced = RevocationList::from_bytes(&system.revocations().to_bytes()).unwrap();
+        assert_eq!(
+            spirit_verify(
+                &domain,
+                &reports[0],
+                system.registry(),
+                &synced,
+                NOW,
+                &mut HashSet::new(),
+                system.challenges(),
+                &mut HashSet::new()
+            ),
+            revoked
+        );
+    }
+
+    #[test]
+    fn rotated_keys_keep_earlier_encounters_traceable() {
+        let SetupOutput {
+            pp,
+            issuers,
+            mut t_rgstr,
+            domain,
+            ..
+        } = spirit_setup_osrng(T, N, NUM_ISSUERS, b"test").expect("setup failed");
+        let registrar = RegistrarKeypair::random(&mut OsRng);
+        let mut revoked = RevocationList::new();
+        let old = UserSecret::random(OsRng);
+        let old_ppu = spirit_register(
+            &domain,
+            &old,
+            &issuers,
+            &pp,
+            EXPIRY,
+            &mut t_rgstr,
+            &mut OsRng,
+        )
+        .expect("registration failed");
+        // reports of epochs up to NOW + 1 may still disclose the ElIDs of the old keys
+        let unrotated = domain.clone();
+        let domain = domain.with_rotation_registrar(registrar.public_key().clone(), NOW + 1 - 3);
+
+        // the old keys broadcast in epochs 0 to 2, the new ones from epoch 3 on
+        let mut t_el = EncounterTable::new();
+        for epoch in 0..3 {
+            spirit_broadcast_osrng(
+                &domain,
+                epoch,
+                Interval(0),
+                old.keys(),
+                &ha_key(),
+                &mut t_el,
+            );
+        }
+        let rotation = spirit_rotate(
+            &domain,
+            &old,
+            &old_ppu.0,
+            &issuers,
+            &pp,
+            EXPIRY,
+            3,
+            &mut t_rgstr,
+            &mut revoked,
+            &registrar,
+            &mut OsRng,
+        )
+        .expect("rotation failed");
+        for epoch in 3..5 {
+            spirit_broadcast_osrng(
+                &domain,
+                epoch,
+                Interval(0),
+                rotation.secret.keys(),
+                &ha_key(),
+                &mut t_el,
+            );
+        }
+        assert_eq!(rotation.previous.until(), 3);
+        assert!(revoked.is_revoked(&old_ppu.0));
+
+        // the registrar's record links the tokens
+        let record = rotation.previous.record();
+        assert_eq!(
+            (record.old, record.new),
+            (old_ppu.0.id(), rotation.ppu.0.id())
+        );
+        assert!(record.verify(registrar.public_key()));
+        let mut tampered = record.clone();
+        tampered.epoch = 0;
+        assert!(!tampered.verify(registrar.public_key()));
+
+        let mut challenges = ChallengeSet::new(0);
+        let first = challenges.issue(NOW, &mut OsRng);
+        let second = challenges.issue(NOW + 1, &mut OsRng);
+        let stale = challenges.issue(NOW + 2, &mut OsRng);
+        let late = challenges.issue(NOW + 2, &mut OsRng);
+        let cp: HashSet<_> = (0..5).map(|epoch| (epoch, Interval(0))).collect();
+        let verify_in =
+            |domain: &DomainConfig, report: &TraceReport, epoch, cf: &mut HashSet<ElID>| {
+                spirit_verify(
+                    domain,
+                    report,
+                    &t_rgstr,
+                    &revoked,
+                    epoch,
+                    &mut HashSet::new(),
+                    &challenges,
+                    cf,
+                )
+            };
+        let verify = |report: &TraceReport, epoch: Epoch, cf: &mut HashSet<ElID>| {
+            verify_in(&domain, report, epoch, cf)
+        };
+        let diagnose = |previous: Option<&PreviousKeys>, epoch, challenge: &ReportChallenge| {
+            spirit_diagnosis(
+                &domain,
+                &rotation.ppu,
+                rotation.secret.keys(),
+                previous,
+                &cp,
+                epoch,
+                challenge,
+                &[],
+                &mut OsRng,
+            )
+            .expect("diagnosis failed")
+        };
+
+        // without the previous keys, only the ElIDs after the rotation match, and the report
+        // does not link the tokens
+        let mut cf = HashSet::new();
+        let report = diagnose(None, NOW, &first);
+        assert_eq!(report.rotation, None);
+        assert!(verify(&report, NOW, &mut cf).is_ok());
+        assert_eq!(spirit_trace(&cf, &t_el, 5).count, 2);
+
+        // with them, the ElIDs before the rotation match as well
+        let mut cf = HashSet::new();
+        let report = diagnose(Some(&rotation.previous), NOW + 1, &second);
+        assert_eq!(report.rotation.as_ref(), Some(record));
+        assert_eq!(verify(&report, NOW + 1, &mut cf), accepted(&[true; 5]));
+        let result = spirit_trace(&cf, &t_el, 5);
+        assert_eq!(result.count, 5);
+        assert!(result.alarm);
+
+        // the record must be the registrar's, and the domain must accept rotations
+        let invalid = Err(VerifyRejection::InvalidRotation {
+            token: rotation.ppu.0.id(),
+        });
+        let mut forged = report.clone();
+        forged.rotation = Some(tampered);
+        assert_eq!(verify(&forged, NOW + 1, &mut HashSet::new()), invalid);
+        assert_eq!(
+            verify_in(&unrotated, &report, NOW + 1, &mut HashSet::new()),
+            invalid
+        );
+
+        // after the overlap, the previous keys are not accepted anymore
+        let report = diagnose(Some(&rotation.previous), NOW + 2, &late);
+        assert_eq!(verify(&report, NOW + 2, &mut HashSet::new()), invalid);
+
+        // the old token cannot report anymore
+        let report =
+            spirit_diagnosis_osrng(&domain, &old_ppu, old.keys(), &cp, NOW + 2, &stale, &[])
+                .expect("diagnosis failed");
+        assert_eq!(
+            verify(&report, NOW + 2, &mut HashSet::new()),
+            Err(VerifyRejection::Revoked {
+                token: old_ppu.0.id()
+            })
+        );
+
+        // key-bound keys prove one key for every epoch
+        let bound = rotation.secret.keys().key_bound();
+        assert!(matches!(
+            spirit_diagnosis(
+                &domain,
+                &rotation.ppu,
+                &bound,
+                Some(&rotation.previous),
+                &cp,
+                NOW,
+                &first,
+                &[],
+                &mut OsRng,
+            ),
+            Err(SpiritError::RotatedBoundKey)
+        ));
+    }
+
+    #[test]
+    fn purge_expired() {
+        let (mut system, issuers) = SpiritSystem::setup(T, N, NUM_ISSUERS).expect("setup failed");
+        let (short, _) = system
+            .register(&UserSecret::random(OsRng), &issuers, NOW)
+            .expect("registration failed");
+        let (long, _) = system
+            .register(&UserSecret::random(OsRng), &issuers, EXPIRY)
+            .expect("registration failed");
+
+        assert_eq!(system.purge_expired(NOW - 1), Ok(0));
+        assert_eq!(system.purge_expired(NOW), Ok(1));
+        assert!(!system.registry().contains(&short));
+        assert!(system.registry().contains(&long));
+    }
+
+    #[test]
+    fn reshare_committee() {
+        let (mut system, issuers) = SpiritSystem::setup(T, N, NUM_ISSUERS).expect("setup failed");
+        let domain = system.domain().clone();
+        let keys = UserKeys::random(OsRng);
+        let old = system
+            .register(&UserSecret::random(OsRng), &issuers, EXPIRY)
+            .expect("registration failed");
+        let pk = system.public_parameters().pk.clone();
+
+        let new_issuers = system.reshare(&issuers, 6, 3).expect("resharing failed");
+        assert_eq!(new_issuers.issuers().len(), 6);
+        assert_eq!(system.public_parameters().pk, pk);
+        assert_eq!(system.public_parameters().t, 3);
+        let new = system
+            .register(&UserSecret::random(OsRng), &new_issuers, EXPIRY)
+            .expect("registration failed");
+
+        // the new shares do not combine with the old ones
+        let mut mixed = new_issuers.issuers;
+        mixed.truncate(1);
+        mixed.extend(issuers.issuers.into_iter().skip(1));
+        assert!(matches!(
+            system.register(
+                &UserSecret::random(OsRng),
+                &IssuerHandle::new(mixed),
+                EXPIRY
+            ),
+            Err(SpiritError::ProofVerificationFailed(_))
+        ));
+
+        let reports: Vec<_> = [old, new]
+            .iter()
+            .map(|ppu| {
+                let challenge = system.issue_challenge(NOW);
+                let cp = HashSet::from([(0, Interval(1))]);
+                spirit_diagnosis_osrng(&domain, ppu, &keys, &cp, NOW, &challenge, &[])
+                    .expect("diagnosis failed")
+            })
+            .collect();
+        assert_eq!(
+            system.verify_reports(&reports, NOW, &mut HashSet::new()),
+            [accepted(&[true]), accepted(&[true])]
+        );
+
+        assert_eq!(
+            system.reshare(&IssuerHandle::new(Vec::new()), 6, 3).err(),
+            Some(SpiritError::NotEnoughIssuers {
+                required: 3,
+                available: 0
+            })
+        );
+        let (mut system, issuers) = SpiritSystem::setup(T, N, NUM_ISSUERS).expect("setup failed");
+        assert_eq!(
+            system.reshare(&issuers, 3, 3).err(),
+            Some(SpiritError::ResharingFailed(AtACTError::InvalidParameters))
+        );
+        assert_eq!(system.public_parameters().t, T);
+    }
+
+    #[test]
+    fn issuer_rejects_blind_request() {
+        let (system, issuers) = SpiritSystem::setup(T, N, NUM_ISSUERS).expect("setup failed");
+        let pp = system.public_parameters();
+        let (strg, cm) = register(&Scalar::rand(&mut OsRng), pp, &mut OsRng).unwrap();
+        let (mut blind_request, _) = token_request(&strg, &cm, pp, &mut OsRng).unwrap();
+        blind_request.cm = Commitment::commit(&Scalar::rand(&mut OsRng)).0;
+
+        assert!(matches!(
+            issue_blind_tokens(&blind_request, issuers.issuers(), pp),
+            Err(SpiritError::IssuerRejected {
+                issuer_index: 0,
+                source: AtACTError::InvalidCommitment
+            })
+        ));
+    }
+
+    fn diagnosed_report(days: &[(Epoch, Interval)]) -> (TraceReport, SpiritSystem, EncounterTable) {
+        let domain = DomainConfig::default();
+        let (mut system, issuers) = SpiritSystem::setup(T, N, NUM_ISSUERS).expect("setup failed");
+        let user = UserSecret::random(OsRng);
+        let keys = user.keys();
+        let pk_ha = ha_key();
+
+        let ppu = system
+            .register(&user, &issuers, EXPIRY)
+            .expect("registration failed");
+
+        let mut t_el = EncounterTable::new();
+        for (epoch, i) in days {
+            spirit_broadcast_osrng(&domain, *epoch, *i, keys, &pk_ha, &mut t_el);
+        }
+
+        let cp: HashSet<_> = days.iter().copied().collect();
+        let challenge = system.issue_challenge(NOW);
+        let report = spirit_diagnosis_osrng(&domain, &ppu, keys, &cp, NOW, &challenge, &[])
+            .expect("diagnosis failed");
+        (report, system, t_el)
+    }
+
+    // 200 ElIDs in 4 epochs
+    fn bundle_cp() -> HashSet<(Epoch, Interval)> {
+        (0..4)
+            .flat_map(|epoch| (0..50).map(move |i| (epoch, Interval(i))))
+            .collect()
+    }
+
+    #[test]
+    fn bundles_verify_and_are_smaller_than_reports() {
+        let domain = DomainConfig::default();
+        let (mut system, issuers) = SpiritSystem::setup(T, N, NUM_ISSUERS).expect("setup failed");
+        let user = UserSecret::random(OsRng);
+        let ppu = system
+            .register(&user, &issuers, EXPIRY)
+            .expect("registration failed");
+        let cp = bundle_cp();
+        let challenge = system.issue_challenge(NOW);
+        let bundle = spirit_diagnosis_bundle(
+            &domain,
+            &ppu,
+            user.keys(),
+            &cp,
+            NOW,
+            &challenge,
+            &[],
+            &mut OsRng,
+        )
+        .expect("diagnosis failed");
+        let report = spirit_diagnosis_osrng(&domain, &ppu, user.keys(), &cp, NOW, &challenge, &[])
+            .expect("diagnosis failed");
+        assert_eq!(bundle.el_ids.len(), 200);
+        assert!(2 * bundle.compressed_size() < report.compressed_size());
+
+        let mut cf = HashSet::new();
+        let accepted = system
+            .verify_bundle(&bundle, NOW, &mut cf)
+            .expect("bundle rejected");
+        assert_eq!(accepted.verdicts, [true; 200]);
+        let disclosed: HashSet<_> = report
+            .disclosures
+            .iter()
+            .map(|disclosure| disclosure.el_id.clone())
+            .collect();
+        assert_eq!(cf, disclosed);
+
+        // the bundle spends the nullifier of the user's reports of the epoch
+        assert_eq!(
+            system
+                .verify_report(&report, NOW, &mut HashSet::new())
+                .map_err(|rejection| rejection.reason()),
+            Err("replayed_nullifier")
+        );
+        let mut bytes = Vec::new();
+        bundle.serialize_compressed(&mut bytes).unwrap();
+        let decoded = DiagnosisBundle::deserialize_compressed(&bytes[..]).unwrap();
+        let mut reencoded = Vec::new();
+        decoded.serialize_compressed(&mut reencoded).unwrap();
+        assert_eq!(reencoded, bytes);
+    }
+
+    #[test]
+    fn verification_costs_are_counted_and_limited() {
+        let ha_sk = SecretKey::random(&mut OsRng);
+        let ha_pk = ha_sk.to_public_key();
+        let (mut system, issuers) = SpiritConfig::new()
+            .threshold(T)
+            .committee_size(NUM_ISSUERS)
+            .request_size(N)
+            .diagnosis_authority(ha_pk.clone())
+            .build(&mut OsRng)
+            .expect("setup failed");
+        let domain = system.domain().clone();
+        let authorize = |token: &Token| {
+            let (blinded, blinding) = blind_authorization_tag(&domain, token, NOW, &mut OsRng);
+            unblind_authorization(blinding, &authorize_diagnosis(&ha_sk, &blinded), &ha_pk)
+                .expect("authorization failed")
+        };
+        let register = |system: &mut SpiritSystem| {
+            let user = UserSecret::random(OsRng);
+            let ppu = system
+                .register(&user, &issuers, EXPIRY)
+                .expect("registration failed");
+            (user, ppu)
+        };
+
+        // a bundle of three ElIDs of two epochs costs the 4 pairings of its authorization and
+        // 5 + 4 + 4 + 3 + 3 + 3 terms
+        let (user, ppu) = register(&mut system);
+        let challenge = system.issue_challenge(NOW);
+        let cp = HashSet::from([(0, Interval(0)), (0, Interval(1)), (1, Interval(0))]);
+        let mut bundle = spirit_diagnosis_bundle(
+            &domain,
+            &ppu,
+            user.keys(),
+            &cp,
+            NOW,
+            &challenge,
+            &[],
+            &mut OsRng,
+        )
+        .expect("diagnosis failed");
+        bundle.report.authorization = Some(authorize(&ppu.0));
+        let cost = CostReport {
+            pairings: 4,
+            msm_terms: 22,
+            bytes: bundle.compressed_size(),
+        };
+        let verify_bundle = |system: &mut SpiritSystem, limit: CostLimit| {
+            spirit_verify_bundle_metered(
+                &domain,
+                &bundle,
+                &system.t_rgstr,
+                &system.revoked,
+                NOW,
+                &mut system.nullifiers,
+                &system.challenges,
+                &mut HashSet::new(),
+                &limit,
+            )
+        };
+
+        // beyond any of the budgets, the bundle is rejected before it is verified
+        let exceeded: Result<Accepted, _> =
+            Err(VerifyRejection::CostExceeded { token: ppu.0.id() });
+        for limit in [
+            CostLimit {
+                pairings: 3,
+                ..CostLimit::UNLIMITED
+            },
+            CostLimit {
+                msm_terms: 21,
+                ..CostLimit::UNLIMITED
+            },
+            CostLimit {
+                bytes: cost.bytes - 1,
+                ..CostLimit::UNLIMITED
+            },
+        ] {
+            assert_eq!(
+                verify_bundle(&mut system, limit),
+                (exceeded.clone(), CostReport::default())
+            );
+        }
+        let exact = CostLimit {
+            pairings: cost.pairings,
+            msm_terms: cost.msm_terms,
+            bytes: cost.bytes,
+        };
+        assert_eq!(
+            verify_bundle(&mut system, exact),
+            (accepted(&[true; 3]), cost)
+        );
+
+        // a batch costs the generators g and u, 7 terms per ElID and the authorizations
+        let reports: Vec<_> = (0..2)
+            .map(|_| {
+                let (user, ppu) = register(&mut system);
+                let challenge = system.issue_challenge(NOW);
+                let cp = HashSet::from([(0, Interval(0)), (0, Interval(1))]);
+                let mut report =
+                    spirit_diagnosis_osrng(&domain, &ppu, user.keys(), &cp, NOW, &challenge, &[])
+                        .expect("diagnosis failed");
+                report.authorization = Some(authorize(&ppu.0));
+                report
+            })
+            .collect();
+        let bytes: Vec<_> = reports.iter().map(TraceReport::compressed_size).collect();
+        let verify_batch = |system: &mut SpiritSystem, limit: CostLimit| {
+            spirit_verify_batch_metered(
+                &domain,
+                &reports,
+                &system.t_rgstr,
+                &system.revoked,
+                NOW,
+                &mut HashSet::new(),
+                &system.challenges,
+                &mut HashSet::new(),
+                &limit,
+            )
+        };
+
+        // the second report does not fit in the budget of the first
+        let first_only = CostLimit {
+            pairings: 4,
+            msm_terms: 2 + 14,
+            ..CostLimit::UNLIMITED
+        };
+        assert_eq!(
+            verify_batch(&mut system, first_only),
+            (
+                vec![
+                    accepted(&[true, true]),
+                    Err(VerifyRejection::CostExceeded {
+                        token: reports[1].token.id()
+                    })
+                ],
+                CostReport {
+                    pairings: 4,
+                    msm_terms: 16,
+                    bytes: bytes[0],
+                }
+            )
+        );
+        assert_eq!(
+            verify_batch(&mut system, CostLimit::default()),
+            (
+                vec![accepted(&[true, true]); 2],
+                CostReport {
+                    pairings: 8,
+                    msm_terms: 30,
+                    bytes: bytes[0] + bytes[1],
+                }
+            )
+        );
+
+        // the bisection after an invalid proof is charged as well: it checks the two proofs
+        // of the first report, the first one of the second report, and the empty rest
+        let mut forged = reports.clone();
+        forged[1].disclosures[0].proof.pi_1.s_1 += Scalar::from(1u64);
+        let invalid = Err(VerifyRejection::InvalidProof {
+            el_id: forged[1].disclosures[0].el_id.clone(),
+        });
+        let verify_forged = |system: &SpiritSystem, msm_terms| {
+            spirit_verify_batch_metered(
+                &domain,
+                &forged,
+                &system.t_rgstr,
+                &system.revoked,
+                NOW,
+                &mut HashSet::new(),
+                &system.challenges,
+                &mut HashSet::new(),
+                &CostLimit {
+                    msm_terms,
+                    ..CostLimit::UNLIMITED
+                },
+            )
+        };
+        let (verified, cost) = verify_forged(&system, 57);
+        assert_eq!(verified, [accepted(&[true, true]), invalid.clone()]);
+        assert_eq!(cost.msm_terms, 30 + 16 + 9 + 2);
+        let (verified, cost) = verify_forged(&system, 55);
+        assert_eq!(verified, [accepted(&[true, true]), invalid]);
+        assert_eq!(cost.msm_terms, 55);
+        let (verified, cost) = verify_forged(&system, 54);
+        assert_eq!(
+            verified,
+            reports
+                .iter()
+                .map(|tr| Err(VerifyRejection::CostExceeded {
+                    token: tr.token.id()
+                }))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(cost.msm_terms, 30 + 16);
+    }
+
+    /// Upper bounds of the uploads of a user of the test deployment, who discloses two ElIDs of
+    /// one epoch. With a point of 144 and a scalar of 32 bytes, a report is a token of 296 bytes,
+    /// 633 bytes of fixed fields, 304 bytes per epoch and 786 bytes per ElID, and a bundle 304
+    /// bytes per ElID, 360 more per epoch and a proof of 216 bytes. Registration sends a token
+    /// proof with `N + T - 1` points besides its [Proof2PK].
+    #[test]
+    fn encodings_fit_their_size_budgets() {
+        const TOKEN_PROOF_BUDGET: usize = 2176;
+        const REPORT_BUDGET: usize = 2560;
+        const BUNDLE_BUDGET: usize = 2176;
+
+        let domain = DomainConfig::default();
+        let (mut system, issuers) = SpiritSystem::setup(T, N, NUM_ISSUERS).expect("setup failed");
+        let pp = system.public_parameters();
+        let user = UserSecret::random(OsRng);
+        let (blind_request, pending) =
+            spirit_register_begin(&domain, &user.id_u, pp, EXPIRY, &mut OsRng)
+                .expect("token request failed");
+        let blind_tokens =
+            issue_verified_blind_tokens(&blind_request, issuers.issuers(), pp).unwrap();
+        let (ppu, _, token_proof) =
+            spirit_register_finish_from(&domain, pending, &blind_tokens, pp, &mut OsRng)
+                .expect("unblinding failed");
+        assert!(token_proof.encoded_size() <= TOKEN_PROOF_BUDGET);
+
+        let cp = HashSet::from([(NOW, Interval(0)), (NOW, Interval(1))]);
+        let challenge = system.issue_challenge(NOW);
+        let report = spirit_diagnosis_osrng(&domain, &ppu, user.keys(), &cp, NOW, &challenge, &[])
+            .expect("diagnosis failed");
+        let proof = &report.disclosures[0].proof;
+        assert_eq!(proof.encoded_size(), Proof2PK::ENCODED_SIZE);
+        assert!(report.encoded_size() <= REPORT_BUDGET);
+
+        let bundle = spirit_diagnosis_bundle(
+            &domain,
+            &ppu,
+            user.keys(),
+            &cp,
+            NOW,
+            &challenge,
+            &[],
+            &mut OsRng,
+        )
+        .expect("diagnosis failed");
+        assert!(bundle.encoded_size() <= BUNDLE_BUDGET);
+    }
+
+    #[test]
+    fn tampered_bundles_are_rejected() {
+        let domain = DomainConfig::default();
+        let (mut system, issuers) = SpiritSystem::setup(T, N, NUM_ISSUERS).expect("setup failed");
+        let user = UserSecret::random(OsRng);
+        let ppu = system
+            .register(&user, &issuers, EXPIRY)
+            .expect("registration failed");
+        let challenge = system.issue_challenge(NOW);
+        let mut bundle = spirit_diagnosis_bundle(
+            &domain,
+            &ppu,
+            user.keys(),
+            &bundle_cp(),
+            NOW,
+            &challenge,
+            &[],
+            &mut OsRng,
+        )
+        .expect("diagnosis failed");
+        let rejection = Err(VerifyRejection::InvalidProof {
+            el_id: bundle.el_ids[0].el_id.clone(),
+        });
+        let mut cf = HashSet::new();
+
+        // the proof of one ElID
+        let other = bundle.el_ids[58].t.clone();
+        let t = std::mem::replace(&mut bundle.el_ids[57].t, other);
+        assert_eq!(system.verify_bundle(&bundle, NOW, &mut cf), rejection);
+        bundle.el_ids[57].t = t;
+
+        // an ElID of other keys
+        let other = prf_broadcast(&domain, &Scalar::rand(&mut OsRng), 1, Interval(7));
+        let el_id = std::mem::replace(&mut bundle.el_ids[57].el_id, other);
+        assert_eq!(system.verify_bundle(&bundle, NOW, &mut cf), rejection);
+        bundle.el_ids[57].el_id = el_id;
+
+        // a dropped ElID
+        let dropped = bundle.el_ids.pop().unwrap();
+        assert_eq!(system.verify_bundle(&bundle, NOW, &mut cf), rejection);
+        bundle.el_ids.push(dropped);
+
+        assert!(cf.is_empty());
+        assert!(system.verify_bundle(&bundle, NOW, &mut cf).is_ok());
+        assert_eq!(cf.len(), 200);
+    }
+
+    #[test]
+    fn padded_bundles_take_the_sizes_of_their_buckets() {
+        let domain = DomainConfig::default();
+        let (mut system, issuers) = SpiritSystem::setup(T, N, NUM_ISSUERS).expect("setup failed");
+        let user = UserSecret::random(OsRng);
+        let ppu = system
+            .register(&user, &issuers, EXPIRY)
+            .expect("registration failed");
+        let padding = BundlePadding::new([32, 8, 16, 0, 8], [4, 0, 2]);
+        assert_eq!(padding.buckets(), [8, 16, 32]);
+        assert_eq!(padding.epoch_buckets(), [2, 4]);
+        assert_eq!(
+            [1, 8, 9, 32, 33, 70].map(|len| padding.padded_len(len)),
+            [8, 8, 16, 32, 64, 96]
+        );
+        // at least one dummy epoch, with a dummy ElID each
+        assert_eq!(padding.padded_sizes(6, 1), (8, 2));
+        assert_eq!(padding.padded_sizes(7, 1), (16, 2));
+        assert_eq!(padding.padded_sizes(5, 3), (8, 4));
+        assert_eq!(padding.padded_sizes(6, 2), (16, 4));
+        assert_eq!(padding.padded_sizes(0, 0), (0, 0));
+
+        // bundles of 1 to 32 ElIDs of 1 to 3 epochs
+        let mut rng = ChaCha20Rng::seed_from_u64(31);
+        let challenge = system.issue_challenge(NOW);
+        let bundle = |len: u64, epochs: u64, rng: &mut ChaCha20Rng| {
+            let cp: HashSet<_> = (0..len)
+                .map(|i| (5 + i % epochs, Interval(i / epochs)))
+                .collect();
+            spirit_diagnosis_bundle_padded(
+                &domain,
+                &ppu,
+                user.keys(),
+                &cp,
+                NOW,
+                &challenge,
+                &[],
+                &padding,
+                rng,
+            )
+            .expect("diagnosis failed")
+        };
+        let to_bytes = |bundle: &DiagnosisBundle| {
+            let mut bytes = Vec::new();
+            bundle.serialize_compressed(&mut bytes).unwrap();
+            bytes
+        };
+        let mut sizes = BTreeMap::new();
+        for _ in 0..24 {
+            let epochs = rng.gen_range(1..=3);
+            let len = rng.gen_range(epochs..=32);
+            let padded = bundle(len, epochs, &mut rng);
+            let shape = (padded.el_ids.len(), padded.proof.epochs.len());
+            assert_eq!(shape, padding.padded_sizes(len as usize, epochs as usize));
+            assert_eq!(padded.report.cm_prv.len(), shape.1);
+            assert_eq!(padded.report.prf_pk.len(), shape.1);
+            sizes
+                .entry(shape)
+                .or_insert_with(BTreeSet::new)
+                .insert(to_bytes(&padded).len());
+        }
+        // bundles of the same padded sizes have the same length, and the sizes are only those of
+        // the buckets, of which several are hit
+        assert!(sizes.values().all(|lengths| lengths.len() == 1));
+        assert!(
+            sizes
+                .keys()
+                .all(|(len, epochs)| [8, 16, 32, 64].contains(len) && [2, 4].contains(epochs))
+        );
+        assert!(sizes.len() > 1);
+
+        // the same length for different numbers of ElIDs and epochs in the same buckets
+        let lengths = [(4, 2), (5, 3), (3, 3)].map(|(len, epochs)| {
+            let padded = bundle(len, epochs, &mut rng);
+            assert_eq!(padded.proof.epochs.len(), 4);
+            to_bytes(&padded).len()
+        });
+        assert!(lengths.iter().all(|length| *length == lengths[0]));
+    }
+
+    #[test]
+    fn dummies_never_match_in_a_trace() {
+        let domain = DomainConfig::default();
+        let (mut system, issuers) = SpiritSystem::setup(T, N, NUM_ISSUERS).expect("setup failed");
+        let user = UserSecret::random(OsRng);
+        let ppu = system
+            .register(&user, &issuers, EXPIRY)
+            .expect("registration failed");
+        let pk_ha = ha_key();
+        let mut t_el = EncounterTable::new();
+        let cp = HashSet::from([(0, Interval(0)), (0, Interval(1)), (1, Interval(0))]);
+        let padding = BundlePadding::new([8], [4]);
+        let padded = |cp: &HashSet<_>, system: &mut SpiritSystem| {
+            let challenge = system.issue_challenge(NOW);
+            spirit_diagnosis_bundle_padded(
+                &domain,
+                &ppu,
+                user.keys(),
+                cp,
+                NOW,
+                &challenge,
+                &[],
+                &padding,
+                &mut OsRng,
+            )
+            .expect("diagnosis failed")
+        };
+        let mut bundle = padded(&cp, &mut system);
+        assert_eq!(bundle.el_ids.len(), 8);
+
+        // the dummies are of the unused epochs next to the real ones, with the indices of the
+        // real ElIDs as long as there are enough, in the order of the real ElIDs
+        assert!(bundle.proof.epochs.keys().eq(&[0, 1, 2, 3]));
+        assert!(
+            bundle
+                .el_ids
+                .windows(2)
+                .all(|pair| { (pair[0].epoch, pair[0].index) < (pair[1].epoch, pair[1].index) })
+        );
+        let dummies: Vec<_> = bundle
+            .el_ids
+            .iter()
+            .filter(|el_id| el_id.epoch > 1)
+            .map(|el_id| (el_id.epoch, el_id.index))
+            .collect();
+        assert_eq!(dummies.len(), 5);
+        assert!(dummies.iter().all(|(_, index)| index.0 <= 2));
+
+        // the user also broadcast in the intervals of the dummies, which are not of its keys
+        for (epoch, i) in cp.iter().chain(&dummies) {
+            spirit_broadcast_osrng(&domain, *epoch, *i, user.keys(), &pk_ha, &mut t_el);
+        }
+        let rejection = Err(VerifyRejection::InvalidProof {
+            el_id: bundle.el_ids[0].el_id.clone(),
+        });
+        let mut cf = HashSet::new();
+
+        // dummies are bound to the proof like the real ElIDs
+        let dummy = bundle.el_ids.len() - 1;
+        let el_id = std::mem::replace(&mut bundle.el_ids[dummy].el_id, G1G2::random(OsRng));
+        assert_eq!(system.verify_bundle(&bundle, NOW, &mut cf), rejection);
+        bundle.el_ids[dummy].el_id = el_id;
+
+        // a bundle without ElIDs is not padded
+        let empty = padded(&HashSet::new(), &mut system);
+        assert!(empty.el_ids.is_empty());
+        assert!(matches!(
+            system.verify_bundle(&empty, NOW, &mut cf),
+            Err(VerifyRejection::NoDisclosures { .. })
+        ));
+
+        assert!(cf.is_empty());
+        let accepted = system
+            .verify_bundle(&bundle, NOW, &mut cf)
+            .expect("bundle rejected");
+        assert_eq!(accepted.verdicts, [true; 8]);
+        assert_eq!(cf.len(), 8);
+        let traced = system.trace(&cf, &t_el, 3);
+        assert_eq!((traced.count, traced.alarm), (3, true));
+        let traced = system.trace(&cf, &t_el, 4);
+        assert_eq!((traced.count, traced.alarm), (3, false));
+    }
+
+    #[test]
+    fn trace_reports_do_not_reveal_the_identity() {
+        let domain = DomainConfig::default();
+        let (mut system, issuers) = SpiritSystem::setup(T, N, NUM_ISSUERS).expect("setup failed");
+        let id_u = Scalar::from_le_bytes_mod_order(b"the fixed identity of a test user");
+        let user = UserSecret::new(id_u, Scalar::rand(&mut OsRng));
+        let ppu = system
+            .register(&user, &issuers, EXPIRY)
+            .expect("registration failed");
+        let cp = HashSet::from([(0, Interval(2)), (1, Interval(0))]);
+        let challenge = system.issue_challenge(NOW);
+        let report = spirit_diagnosis_osrng(&domain, &ppu, user.keys(), &cp, NOW, &challenge, &[])
+            .expect("diagnosis failed");
+
+        let mut bytes = Vec::new();
+        report.serialize_compressed(&mut bytes).unwrap();
+        let mut little_endian = Vec::new();
+        id_u.serialize_compressed(&mut little_endian).unwrap();
+        let big_endian: Vec<_> = little_endian.iter().rev().copied().collect();
+        for encoding in [little_endian, big_endian] {
+            assert!(
+                !bytes
+                    .windows(encoding.len())
+                    .any(|window| window == encoding)
+            );
+        }
+
+        // fails to compile when a field is added, which has to be checked not to reveal `id_u`
+        let TraceReport {
+            deployment: _,
+            registrar: _,
+            token: _,
+            cm_prv: _,
+            prf_pk: _,
+            device_keys: _,
+            disclosures: _,
+            report_epoch: _,
+            challenge: _,
+            nullifier: _,
+            nullifier_proof: _,
+            show: _,
+            attributes: _,
+            key_binding: _,
+            authorization: _,
+            attestation: _,
+            rotation: _,
+        } = report;
+    }
+
+    #[test]
+    fn device_broadcasts_trace_back_after_one_diagnosis() {
+        let domain = DomainConfig::default();
+        let (mut system, issuers) = SpiritSystem::setup(T, N, NUM_ISSUERS).expect("setup failed");
+        let user = UserSecret::random(OsRng);
+        let ppu = system
+            .register(&user, &issuers, EXPIRY)
+            .expect("registration failed");
+        let keys = user.keys();
+        let (phone, watch) = (keys.device_keys(&domain, 1), keys.device_keys(&domain, 2));
+        assert_eq!(
+            UserKeys::new(user.derive_device_key(&domain, 1)).epoch_key(0),
+            phone.epoch_key(0)
+        );
+        assert_ne!(phone.epoch_key(0), watch.epoch_key(0));
+        assert_ne!(phone.epoch_key(0), user.keys().epoch_key(0));
+
+        // the encounter tables of the contacts of the phone, of the watch, and of another
+        // user's device
+        let days = [(0, Interval(3)), (1, Interval(4))];
+        let pk_ha = ha_key();
+        let stranger = UserKeys::random(OsRng).device_keys(&domain, 1);
+        let [mut t_phone, mut t_watch, mut t_stranger] = [(); 3].map(|_| EncounterTable::new());
+        for (epoch, i) in days {
+            spirit_broadcast_osrng(&domain, epoch, i, &phone, &pk_ha, &mut t_phone);
+            spirit_broadcast_osrng(&domain, epoch, i, &watch, &pk_ha, &mut t_watch);
+            spirit_broadcast_osrng(&domain, epoch, i, &stranger, &pk_ha, &mut t_stranger);
+        }
+
+        let cp: HashSet<_> = days.into_iter().collect();
+        let challenge = system.issue_challenge(NOW);
+        let report = spirit_diagnosis_with_devices(
+            &domain,
+            &ppu,
+            user.keys(),
+            &[1, 2],
+            &cp,
+            NOW,
+            &challenge,
+            &[],
+            &mut OsRng,
+        )
+        .expect("diagnosis failed");
+        assert_eq!(
+            report
+                .disclosures
+                .iter()
+                .map(|disclosure| disclosure.device)
+                .collect::<Vec<_>>(),
+            [None, None, Some(1), Some(1), Some(2), Some(2)]
+        );
+        assert_eq!(report.device_keys.len(), 4);
+
+        let mut confirmed = HashSet::new();
+        assert_eq!(
+            system.verify_report(&report, NOW, &mut confirmed),
+            accepted(&[true; 6])
+        );
+        for t_el in [&t_phone, &t_watch] {
+            let result = system.trace(&confirmed, t_el, days.len());
+            assert_eq!((result.count, result.alarm), (days.len(), true));
+        }
+        assert_eq!(system.trace(&confirmed, &t_stranger, 1).count, 0);
+
+        // a chosen subset of the devices
+        let report = spirit_diagnosis_with_devices(
+            &domain,
+            &ppu,
+            user.keys(),
+            &[2],
+            &cp,
+            NOW,
+            &system.issue_challenge(NOW),
+            &[],
+            &mut OsRng,
+        )
+        .expect("diagnosis failed");
+        let disclosed: Vec<_> = report.disclosures.iter().map(|d| &d.el_id).collect();
+        assert_eq!(
+            spirit_trace(disclosed.iter().copied(), &t_watch, 1).count,
+            2
+        );
+        assert_eq!(spirit_trace(disclosed, &t_phone, 1).count, 0);
+    }
+
+    #[test]
+    fn rerandomized_tokens_are_unlinkable_and_verify() {
+        let domain = DomainConfig::default();
+        let (mut system, issuers) = SpiritSystem::setup(T, N, NUM_ISSUERS).expect("setup failed");
+        let user = UserSecret::random(OsRng);
+        let ppu = system
+            .register(&user, &issuers, EXPIRY)
+            .expect("registration failed");
+        let pp = system.public_parameters();
+        assert!(!ppu.0.verify_signature(pp));
+
+        let first = spirit_rerandomize(&ppu, pp, &mut OsRng);
+        let second = spirit_rerandomize(&ppu, pp, &mut OsRng);
+        assert_ne!(first.0.to_bytes(), second.0.to_bytes());
+        for presentation in [&first.0, &second.0] {
+            assert_ne!(presentation.to_bytes(), ppu.0.to_bytes());
+            assert!(presentation.verify_signature(pp));
+            assert!(!system.registry().contains(presentation));
+        }
+
+        // a shifted commitment without the matching signature, and a signature of another token
+        let shift = Scalar::rand(&mut OsRng);
+        let shifted = (
+            Token(
+                Commitment(&first.0.0.0 + &get_parameters().g * shift),
+                first.0.1.clone(),
+                EXPIRY,
+            ),
+            StRG {
+                r: first.1.r + shift,
+                ..first.1.clone()
+            },
+        );
+        let other = spirit_rerandomize(
+            &system
+                .register(&UserSecret::random(OsRng), &issuers, EXPIRY)
+                .expect("registration failed"),
+            system.public_parameters(),
+            &mut OsRng,
+        );
+        let swapped = (Token(first.0.0.clone(), other.0.1, EXPIRY), first.1.clone());
+        // a later expiry, with the commitment moved along so that the proofs still verify
+        let extended = (
+            Token(
+                Commitment(
+                    &first.0.0.0 - expiry_term(&domain, EXPIRY) + expiry_term(&domain, EXPIRY + 1),
+                ),
+                first.0.1.clone(),
+                EXPIRY + 1,
+            ),
+            first.1.clone(),
+        );
+        assert_eq!(
+            extended.0.identity_commitment(&domain),
+            first.0.identity_commitment(&domain)
+        );
+
+        // the affine combination of tokens with different expiries is signed, but its commitment
+        // is not the combination of their identity commitments for any expiry
+        let later = spirit_rerandomize(
+            &system
+                .register(&UserSecret::random(OsRng), &issuers, EXPIRY + 1)
+                .expect("registration failed"),
+            system.public_parameters(),
+            &mut OsRng,
+        );
+        let two = Scalar::from(2u64);
+        let combined = Token(
+            Commitment(&first.0.0.0 * two - &later.0.0.0),
+            Signature(&first.0.1.0 * two - &later.0.1.0),
+            EXPIRY - 1,
+        );
+        assert!(combined.verify_signature(system.public_parameters()));
+        assert_ne!(
+            combined.identity_commitment(&domain),
+            first.0.identity_commitment(&domain) * two - &later.0.identity_commitment(&domain)
+        );
+
+        let cp = HashSet::from([(0, Interval(2))]);
+        let mut verify = |ppu: &(Token, StRG)| {
+            let challenge = system.issue_challenge(NOW);
+            let report =
+                spirit_diagnosis_osrng(&domain, ppu, user.keys(), &cp, NOW, &challenge, &[])
+                    .expect("diagnosis failed");
+            spirit_verify_rerandomized(
+                &domain,
+                &report,
+                system.public_parameters(),
+                NOW,
+                &mut HashSet::new(),
+                system.challenges(),
+                &mut HashSet::new(),
+            )
+        };
+        assert_eq!(verify(&first), accepted(&[true]));
+        assert_eq!(verify(&second), accepted(&[true]));
+        for ppu in [&shifted, &swapped, &extended, &ppu] {
+            assert_eq!(
+                verify(ppu),
+                Err(VerifyRejection::InvalidSignature { token: ppu.0.id() })
+            );
+        }
+        // a later expiry on the signed commitment is not the expiry the identity is proven for
+        let relabelled = (
+            Token(first.0.0.clone(), first.0.1.clone(), EXPIRY + 1),
+            first.1.clone(),
+        );
+        assert!(relabelled.0.verify_signature(system.public_parameters()));
+        assert_eq!(
+            verify(&relabelled),
+            Err(VerifyRejection::InvalidNullifier {
+                token: relabelled.0.id()
+            })
+        );
+    }
+
+    #[test]
+    fn tokens_are_shown_at_most_k_times() {
+        let domain = DomainConfig::default();
+        let (mut system, issuers) = SpiritSystem::setup(T, N, NUM_ISSUERS).expect("setup failed");
+        let user = UserSecret::random(OsRng);
+        let ppu = system
+            .register(&user, &issuers, EXPIRY)
+            .expect("registration failed");
+
+        // one report per epoch, so the nullifiers do not reject any of them
+        let token = ppu.0.id();
+        let cp = HashSet::from([(0, Interval(2))]);
+        let mut shows = ShowRegistry::new(3);
+        let mut nullifiers = HashSet::new();
+        let mut submit = |system: &mut SpiritSystem, counter: &mut ShowCounter, epoch| {
+            let challenge = system.issue_challenge(epoch);
+            let report = spirit_diagnosis_with_show(
+                &domain,
+                &ppu,
+                user.keys(),
+                &cp,
+                counter,
+                epoch,
+                &challenge,
+                &[],
+                &mut OsRng,
+            )
+            .expect("diagnosis failed");
+            let verdicts = spirit_verify_with_shows(
+                &domain,
+                &report,
+                system.registry(),
+                system.revocations(),
+                epoch,
+                &mut nullifiers,
+                system.challenges(),
+                &mut shows,
+                &mut HashSet::new(),
+            );
+            (report.show.unwrap().index, verdicts)
+        };
+
+        // every index is shown once, in random order
+        let mut counter = ShowCounter::new(3);
+        let mut indices = Vec::new();
+        for epoch in NOW..NOW + 3 {
+            let (index, verdicts) = submit(&mut system, &mut counter, epoch);
+            assert_eq!(verdicts, accepted(&[true]));
+            indices.push(index);
+        }
+        assert_eq!(
+            BTreeSet::from_iter(indices.clone()),
+            BTreeSet::from([0, 1, 2])
+        );
+        assert_eq!(counter.remaining(), 0);
+        let challenge = system.issue_challenge(NOW + 3);
+        assert_eq!(
+            spirit_diagnosis_with_show(
+                &domain,
+                &ppu,
+                user.keys(),
+                &cp,
+                &mut counter,
+                NOW + 3,
+                &challenge,
+                &[],
+                &mut OsRng,
+            )
+            .err(),
+            Some(SpiritError::ShowsExhausted { limit: 3 })
+        );
+
+        // a device with a higher limit than the verifiers'
+        assert_eq!(
+            submit(&mut system, &mut ShowCounter::with_used(4, 0..3), NOW + 4),
+            (
+                3,
+                Err(VerifyRejection::ShowLimitExceeded {
+                    token,
+                    index: 3,
+                    limit: 3
+                })
+            )
+        );
+
+        // a reused index has the tag of its first show
+        let first = indices[0];
+        let mut replayed = ShowCounter::with_used(3, (0..3).filter(|index| *index != first));
+        assert_eq!(
+            submit(&mut system, &mut replayed, NOW + 5),
+            (first, Err(VerifyRejection::ReplayedShow { token }))
+        );
+        assert_eq!(shows.len(), 3);
+
+        // reports without a tag
+        let challenge = system.issue_challenge(NOW + 6);
+        let report =
+            spirit_diagnosis_osrng(&domain, &ppu, user.keys(), &cp, NOW + 6, &challenge, &[])
+                .expect("diagnosis failed");
+        assert_eq!(
+            spirit_verify_with_shows(
+                &domain,
+                &report,
+                system.registry(),
+                system.revocations(),
+                NOW + 6,
+                &mut HashSet::new(),
+                system.challenges(),
+                &mut shows,
+                &mut HashSet::new(),
+            ),
+            Err(VerifyRejection::MissingShow { token })
+        );
+    }
+
+    #[test]
+    fn show_limits_of_the_domain_are_enforced() {
+        let domain = DomainConfig::default().with_show_limit(3);
+        let (mut system, issuers) = SpiritSystem::setup(T, N, NUM_ISSUERS).expect("setup failed");
+        let user = UserSecret::random(OsRng);
+        let ppu = system
+            .register(&user, &issuers, EXPIRY)
+            .expect("registration failed");
+
+        let token = ppu.0.id();
+        let cp = HashSet::from([(0, Interval(2))]);
+        let challenge = system.issue_challenge(NOW);
+        let verify = |system: &SpiritSystem, report: &TraceReport| {
+            spirit_verify(
+                &domain,
+                report,
+                system.registry(),
+                system.revocations(),
+                NOW,
+                &mut HashSet::new(),
+                system.challenges(),
+                &mut HashSet::new(),
+            )
+        };
+        let diagnose = |counter: &mut ShowCounter| {
+            spirit_diagnosis_with_show(
+                &domain,
+                &ppu,
+                user.keys(),
+                &cp,
+                counter,
+                NOW,
+                &challenge,
+                &[],
+                &mut OsRng,
+            )
+            .expect("diagnosis failed")
+        };
+
+        let report = diagnose(&mut ShowCounter::new(3));
+        assert_eq!(verify(&system, &report), accepted(&[true]));
+        let report = diagnose(&mut ShowCounter::with_used(4, 0..3));
+        assert_eq!(
+            verify(&system, &report),
+            Err(VerifyRejection::ShowLimitExceeded {
+                token,
+                index: 3,
+                limit: 3
+            })
+        );
+        let mut forged = diagnose(&mut ShowCounter::new(3));
+        forged.show.as_mut().unwrap().index = (forged.show.as_ref().unwrap().index + 1) % 3;
+        assert_eq!(
+            verify(&system, &forged),
+            Err(VerifyRejection::InvalidShow { token })
+        );
+        let report = spirit_diagnosis_osrng(&domain, &ppu, user.keys(), &cp, NOW, &challenge, &[])
+            .expect("diagnosis failed");
+        assert_eq!(
+            verify(&system, &report),
+            Err(VerifyRejection::MissingShow { token })
+        );
+    }
+
+    #[test]
+    fn show_indices_are_drawn_at_random() {
+        let draws: BTreeSet<_> = (0..32)
+            .map(|_| ShowCounter::new(8).draw(&mut OsRng).unwrap())
+            .collect();
+        assert!(draws.len() > 1);
+        let counter = ShowCounter::with_used(8, (0..8).filter(|index| *index != 5));
+        assert_eq!(counter.draw(&mut OsRng), Some(5));
+        assert_eq!(ShowCounter::with_used(8, 0..8).draw(&mut OsRng), None);
+    }
+
+    #[test]
+    fn checkins_are_disclosed_and_traced_like_broadcasts() {
+        let domain = DomainConfig::default();
+        let (mut system, issuers) = SpiritSystem::setup(T, N, NUM_ISSUERS).expect("setup failed");
+        let user = UserSecret::random(OsRng);
+        let ppu = system
+            .register(&user, &issuers, EXPIRY)
+            .expect("registration failed");
+
+        // the venue's log of check-ins, with another user in the same slot
+        let pk_ha = ha_key();
+        let (venue_id, slot) = (b"cafe-42".as_slice(), Interval(7));
+        let mut t_venue = EncounterTable::new();
+        let (el_id, _) = spirit_checkin(
+            &domain,
+            0,
+            venue_id,
+            slot,
+            user.keys(),
+            &pk_ha,
+            &mut t_venue,
+            &mut OsRng,
+        );
+        let other = UserKeys::random(OsRng);
+        let (other_el_id, _) = spirit_checkin(
+            &domain,
+            0,
+            venue_id,
+            slot,
+            &other,
+            &pk_ha,
+            &mut t_venue,
+            &mut OsRng,
+        );
+        assert_ne!(el_id, other_el_id);
+        // the venue only knows the base, which is not the ElID of any key it could guess
+        let base = venue_base(&domain, venue_id, slot);
+        assert_eq!(el_id, &base * user.keys().epoch_key(0));
+        assert_ne!(el_id, base);
+        assert_ne!(
+            el_id,
+            prf_broadcast(&domain, &user.keys().epoch_key(0), 0, slot)
+        );
+        assert_eq!(t_venue.lookup(&el_id).unwrap().index, slot);
+
+        let mut t_el = EncounterTable::new();
+        spirit_broadcast_osrng(&domain, 1, Interval(3), user.keys(), &pk_ha, &mut t_el);
+        let checkins = [CheckIn {
+            epoch: 0,
+            venue_id: venue_id.to_vec(),
+            slot,
+        }];
+        let report = spirit_diagnosis_with_checkins(
+            &domain,
+            &ppu,
+            user.keys(),
+            &[],
+            &[(1, Interval(3))].into_iter().collect(),
+            &checkins,
+            NOW,
+            &system.issue_challenge(NOW),
+            &[],
+            &mut OsRng,
+        )
+        .expect("diagnosis failed");
+        assert_eq!(report.cm_prv.len(), 2);
+        assert_eq!(report.disclosures[1].venue.as_deref(), Some(venue_id));
+        assert_eq!(report.disclosures[1].el_id, el_id);
+
+        // a check-in cannot be passed off as a broadcast of its slot, or as one of another venue
+        for venue in [None, Some(b"cafe-43".to_vec())] {
+            let mut report = spirit_diagnosis_with_checkins(
+                &domain,
+                &ppu,
+                user.keys(),
+                &[],
+                &HashSet::new(),
+                &checkins,
+                NOW,
+                &system.issue_challenge(NOW),
+                &[],
+                &mut OsRng,
+            )
+            .expect("diagnosis failed");
+            report.disclosures[0].venue = venue;
+            assert_eq!(
+                system.verify_report(&report, NOW, &mut HashSet::new()),
+                Err(VerifyRejection::InvalidProof {
+                    el_id: el_id.clone()
+                })
+            );
+        }
+
+        let mut confirmed = HashSet::new();
+        assert_eq!(
+            system.verify_report(&report, NOW, &mut confirmed),
+            accepted(&[true; 2])
+        );
+        assert_eq!(system.trace(&confirmed, &t_venue, 1).matched, [el_id]);
+        assert_eq!(system.trace(&confirmed, &t_el, 1).count, 1);
+    }
+
+    #[test]
+    fn diagnosis_round_trip() {
+        let days = [(0, Interval(3)), (0, Interval(5)), (1, Interval(9))];
+        let (report, mut system, t_el) = diagnosed_report(&days);
+        assert_eq!(report.disclosures.len(), days.len());
+        assert_eq!(report.cm_prv.len(), 2);
+        for (disclosure, i) in report.disclosures.iter().zip(days) {
+            assert_eq!((disclosure.epoch, disclosure.index), i);
+            assert!(t_el.contains(&disclosure.el_id));
+        }
+
+        let mut confirmed = HashSet::new();
+        assert_eq!(
+            system.verify_report(&report, NOW, &mut confirmed),
+            accepted(&[true; 3])
+        );
+        assert_eq!(confirmed.len(), days.len());
+        assert!(t_el.el_ids().all(|el_id| confirmed.contains(&el_id)));
+        let result = system.trace(&confirmed, &t_el, days.len());
+        assert_eq!((result.count, result.alarm), (days.len(), true));
+    }
+
+    #[test]
+    fn epochs_unlinkable() {
+        let domain = DomainConfig::default();
+        let keys = UserKeys::random(OsRng);
+        let pk_ha = ha_key();
+        let mut t_el = EncounterTable::new();
+        for epoch in 0..3 {
+            for i in (0..4).map(Interval) {
+                spirit_broadcast_osrng(&domain, epoch, i, &keys, &pk_ha, &mut t_el);
+            }
+        }
+
+        // neither the master key nor the key of another epoch reproduces an epoch's ElIDs
+        for epoch in 0..3 {
+            for i in (0..4).map(Interval) {
+                assert!(!t_el.contains(&prf_broadcast(&domain, &keys.prv, epoch, i)));
+                let other_key = keys.epoch_key((epoch + 1) % 3);
+                assert!(!t_el.contains(&prf_broadcast(&domain, &other_key, epoch, i)));
+                assert!(t_el.contains(&prf_broadcast(&domain, &keys.epoch_key(epoch), epoch, i)));
+            }
+        }
+        assert_ne!(keys.epoch_key(0), keys.epoch_key(1));
+    }
+
+    #[test]
+    fn trace_disclosed_epochs() {
+        let domain = DomainConfig::default();
+        let (mut system, issuers) = SpiritSystem::setup(T, N, NUM_ISSUERS).expect("setup failed");
+        let keys = UserKeys::random(OsRng);
+        let pk_ha = ha_key();
+        let ppu = system
+            .register(&UserSecret::random(OsRng), &issuers, EXPIRY)
+            .expect("registration failed");
+
+        let mut t_el = EncounterTable::new();
+        for epoch in 0..3 {
+            for i in (0..4).map(Interval) {
+                spirit_broadcast_osrng(&domain, epoch, i, &keys, &pk_ha, &mut t_el);
+            }
+        }
+
+        let cp: HashSet<_> = (0..4).map(|i| (1, Interval(i))).collect();
+        let challenge = system.issue_challenge(NOW);
+        let report = spirit_diagnosis_osrng(&domain, &ppu, &keys, &cp, NOW, &challenge, &[])
+            .expect("diagnosis failed");
+        assert_eq!(report.cm_prv.keys().collect::<Vec<_>>(), vec![&1]);
+
+        let mut confirmed = HashSet::new();
+        assert_eq!(
+            system.verify_report(&report, NOW, &mut confirmed),
+            accepted(&[true; 4])
+        );
+        let result = system.trace(&confirmed, &t_el, 4);
+        assert_eq!((result.count, result.alarm), (4, true));
+        assert!(confirmed.iter().all(|el_id| {
+            (0..4).any(|i| *el_id == prf_broadcast(&domain, &keys.epoch_key(1), 1, Interval(i)))
+        }));
+
+        // a disclosure moved to an epoch without key commitment is rejected
+        let mut report = report;
+        report.disclosures[0].epoch = 2;
+        system.nullifiers.clear();
+        assert_eq!(
+            system.verify_report(&report, NOW, &mut HashSet::new()),
+            accepted(&[false, true, true, true])
+        );
+        let invalid = Err(VerifyRejection::InvalidProof {
+            el_id: report.disclosures[0].el_id.clone(),
+        });
+        assert_eq!(
+            system.verify_reports(&[report], NOW, &mut HashSet::new()),
+            [invalid]
+        );
+    }
+
+    fn encounters(keys: &UserKeys, indices: Range<u64>) -> Vec<ElID> {
+        let domain = DomainConfig::default();
+        indices
+            .map(|i| prf_broadcast(&domain, &keys.epoch_key(0), 0, Interval(i)))
+            .collect()
+    }
+
+    #[test]
+    fn encounter_secrets_are_encrypted() {
+        let domain = DomainConfig::default();
+        let SetupOutput { pk_ha, sk_ha, .. } =
+            spirit_setup_osrng(3, 4, 4, b"test").expect("setup failed");
+        let keys = UserKeys::random(OsRng);
+        let mut t_el = EncounterTable::new();
+
+        let (el_id, es_i) =
+            spirit_broadcast_osrng(&domain, 0, Interval(0), &keys, &pk_ha, &mut t_el);
+        spirit_broadcast_range_osrng(
+            &domain,
+            0,
+            Interval(1),
+            Interval(4),
+            &keys,
+            &pk_ha,
+            &mut t_el,
+        );
+        for (_, record) in t_el.iter() {
+            assert_eq!(
+                decrypt_encounter(&sk_ha, &record.encrypted_secret),
+                record.secret
+            );
+        }
+
+        // an upload is unlinkable to the stored ciphertext, but carries the same secret
+        let stored = &t_el.lookup(&el_id).unwrap().encrypted_secret;
+        let upload = stored.rerandomize(&pk_ha, &mut OsRng);
+        assert_ne!(&upload, stored);
+        assert_eq!(decrypt_encounter(&sk_ha, &upload), es_i);
+        assert_ne!(
+            decrypt_encounter(&DecryptionKey::random(&mut OsRng), &upload),
+            es_i
+        );
+    }
+
+    #[test]
+    fn threshold_decryption_of_encounter_secrets() {
+        let domain = DomainConfig::default();
+        let SetupOutput { pp, issuers, .. } =
+            spirit_setup_osrng(T, N, NUM_ISSUERS, b"test").expect("setup failed");
+        let pk_committee = pp.decryption_key.clone().expect("no decryption key");
+        assert_ne!(pk_committee.0, pp.pk.0);
+        let mut t_el = EncounterTable::new();
+        let (el_id, es_i) = spirit_broadcast_osrng(
+            &domain,
+            0,
+            Interval(0),
+            &UserKeys::random(OsRng),
+            &pk_committee,
+            &mut t_el,
+        );
+        let upload = t_el
+            .lookup(&el_id)
+            .unwrap()
+            .encrypted_secret
+            .rerandomize(&pk_committee, &mut OsRng);
+
+        let partials: Vec<_> = issuers
+            .iter()
+            .enumerate()
+            .map(|(index, issuer)| partial_decrypt(issuer, index, &upload, &mut OsRng))
+            .collect::<Result<_, _>>()
+            .expect("partial decryption failed");
+        for (partial, key) in partials.iter().zip(&pp.decryption_keys) {
+            assert!(partial.verify(key));
+        }
+        // the shares are not made with the issuers' signing keys
+        assert_ne!(partials[0].share, &upload.c1 * issuers[0].as_ref().sk);
+        assert_eq!(
+            partial_decrypt(
+                &Issuer::new(crate::tsw::SecretKey::random(&mut OsRng)),
+                0,
+                &upload,
+                &mut OsRng
+            ),
+            Err(SpiritError::NoDecryptionShare)
+        );
+
+        assert_eq!(combine_decryptions(&partials[..T]), es_i);
+        assert_eq!(combine_decryptions(&partials[NUM_ISSUERS - T..]), es_i);
+        assert_ne!(combine_decryptions(&partials[..T - 1]), es_i);
+        // a repeated share does not stand in for another issuer's
+        let repeated: Vec<_> = partials[..T - 1]
+            .iter()
+            .chain(&partials[..1])
+            .cloned()
+            .collect();
+        assert_ne!(combine_decryptions(&repeated), es_i);
+
+        // a corrupted share is identified, and would spoil the decryption
+        let mut corrupted = partials[..T].to_vec();
+        corrupted[1].share = &corrupted[1].share + &get_parameters().g;
+        let invalid: Vec<_> = corrupted
+            .iter()
+            .filter(|partial| !partial.verify(&pp.decryption_keys[partial.index()]))
+            .map(PartialDecryption::index)
+            .collect();
+        assert_eq!(invalid, [1]);
+        assert_ne!(combine_decryptions(&corrupted), es_i);
+        assert!(!partials[0].verify(&pp.decryption_keys[1]));
+    }
+
+    #[test]
+    fn broadcast_range() {
+        let domain = DomainConfig::default();
+        let keys = UserKeys::random(OsRng);
+        let pk_ha = ha_key();
+        let mut t_el = EncounterTable::new();
+        let (el_id, es_i) =
+            spirit_broadcast_weighted_osrng(&domain, 1, Interval(5), 3, &keys, &pk_ha, &mut t_el);
+        assert_eq!(
+            el_id,
+            prf_broadcast(&domain, &keys.epoch_key(1), 1, Interval(5))
+        );
+        assert_eq!(t_el.lookup(&el_id).unwrap().secret, es_i);
+        let previous = t_el.lookup(&el_id).unwrap();
+
+        let range = |start, end, t_el: &mut EncounterTable| {
+            let (start, end) = (Interval(start), Interval(end));
+            spirit_broadcast_range_osrng(&domain, 1, start, end, &keys, &pk_ha, t_el)
+        };
+        let broadcast = range(0, 96, &mut t_el);
+        assert_eq!(
+            broadcast.iter().map(|(i, _)| i.0).collect::<Vec<_>>(),
+            (0..96).filter(|i| *i != 5).collect::<Vec<_>>()
+        );
+        for (i, el_id) in &broadcast {
+            assert_eq!(*el_id, prf_broadcast(&domain, &keys.epoch_key(1), 1, *i));
+            let record = t_el.lookup(el_id).unwrap();
+            assert_eq!((record.epoch, record.index, record.weight), (1, *i, 1));
+        }
+        assert_eq!(t_el.len(), 96);
+        assert_eq!(t_el.lookup(&el_id).unwrap(), previous);
+
+        assert_eq!(range(90, 100, &mut t_el).len(), 4);
+        assert!(range(10, 10, &mut t_el).is_empty());
+        assert!(range(20, 10, &mut t_el).is_empty());
+    }
+
+    #[test]
+    fn trace_counting() {
+        let domain = DomainConfig::default();
+        let keys = UserKeys::random(OsRng);
+        let pk_ha = ha_key();
+        let mut t_el = EncounterTable::new();
+        for i in (0..6).map(Interval) {
+            spirit_broadcast_osrng(&domain, 0, i, &keys, &pk_ha, &mut t_el);
+        }
+        let own = encounters(&keys, 0..6);
+
+        // overlapping confirmed sets of two reports, concatenated with repetitions
+        let cf: Vec<_> = own[1..4]
+            .iter()
+            .chain(&own[2..5])
+            .chain(&encounters(&UserKeys::random(OsRng), 0..3))
+            .cloned()
+            .collect();
+        let result = spirit_trace(&cf, &t_el, 4);
+        assert_eq!(result.matched, own[1..5].to_vec());
+        assert_eq!(result.count, 4);
+        assert!(result.alarm);
+        assert!(!spirit_trace(&cf, &t_el, 5).alarm);
+
+        let none = spirit_trace(&encounters(&UserKeys::random(OsRng), 0..6), &t_el, 1);
+        assert_eq!(
+            none,
+            TraceResult {
+                matched: Vec::new(),
+                count: 0,
+                alarm: false
+            }
+        );
+    }
+
+    #[test]
+    fn streamed_trace_matches_the_set_trace() {
+        let domain = DomainConfig::default();
+        let keys = UserKeys::random(OsRng);
+        let pk_ha = ha_key();
+        let mut t_el = EncounterTable::new();
+        for i in (0..6).map(Interval) {
+            spirit_broadcast_osrng(&domain, 0, i, &keys, &pk_ha, &mut t_el);
+        }
+        let own = encounters(&keys, 0..6);
+        let cf: Vec<_> = own[1..4]
+            .iter()
+            .chain(&encounters(&UserKeys::random(OsRng), 0..3))
+            .chain(&own[2..5])
+            .cloned()
+            .collect();
+
+        for exposure_limit in 0..6 {
+            let result = spirit_trace(&cf, &t_el, exposure_limit);
+            assert_eq!(
+                spirit_trace_stream(cf.iter().cloned(), &t_el, exposure_limit),
+                result
+            );
+            assert_eq!(
+                spirit_trace_stream_alarm(cf.iter().cloned(), &t_el, exposure_limit),
+                result.alarm
+            );
+        }
+
+        // the alarm of 4 matches is raised at own[4], the last ElID of the stream, as the
+        // repetitions of own[2] and own[3] before it do not count
+        let mut stream = cf.iter().cloned();
+        assert!(spirit_trace_stream_alarm(stream.by_ref(), &t_el, 4));
+        assert_eq!(stream.next(), None);
+        let mut stream = cf.iter().cloned();
+        assert!(spirit_trace_stream_alarm(stream.by_ref(), &t_el, 2));
+        assert_eq!(stream.next(), Some(own[3].clone()));
+    }
+
+    #[test]
+    fn trace_detailed() {
+        let domain = DomainConfig::default();
+        let keys = UserKeys::random(OsRng);
+        let pk_ha = ha_key();
+        let mut t_el = EncounterTable::new();
+        for i in 0..6 {
+            let weight = i as u32 + 1;
+            spirit_broadcast_weighted_osrng(
+                &domain,
+                0,
+                Interval(i),
+                weight,
+                &keys,
+                &pk_ha,
+                &mut t_el,
+            );
+        }
+        let own = encounters(&keys, 0..6);
+
+        let cf: Vec<_> = [&own[4], &own[1], &own[4]]
+            .into_iter()
+            .chain(&encounters(&UserKeys::random(OsRng), 0..3))
+            .cloned()
+            .collect();
+        let result = spirit_trace_detailed(&cf, &t_el, 2);
+        assert_eq!(
+            result.exposures,
+            [4, 1].map(|i| Exposure {
+                el_id: own[i].clone(),
+                epoch: 0,
+                index: Interval::from(i),
+                weight: i as u32 + 1,
+            })
+        );
+        assert!(result.alarm);
+        assert!(
+            result
+                .exposures
+                .iter()
+                .all(|exposure| cf.contains(&exposure.el_id))
+        );
+
+        // the summary is derived from the detailed result
+        for exposure_limit in 0..4 {
+            assert_eq!(
+                spirit_trace(&cf, &t_el, exposure_limit),
+                TraceResult::from(spirit_trace_detailed(&cf, &t_el, exposure_limit))
+            );
+        }
+        assert!(!spirit_trace_detailed(&cf, &t_el, 3).alarm);
+        assert!(
+            spirit_trace_detailed(&encounters(&UserKeys::random(OsRng), 0..6), &t_el, 1)
+                .exposures
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn trace_windowed() {
+        let domain = DomainConfig::default();
+        let keys = UserKeys::random(OsRng);
+        let pk_ha = ha_key();
+        let mut t_el = EncounterTable::new();
+        for i in (0..30).map(Interval) {
+            spirit_broadcast_osrng(&domain, 0, i, &keys, &pk_ha, &mut t_el);
+        }
+        assert_eq!(
+            t_el.lookup(&prf_broadcast(&domain, &keys.epoch_key(0), 0, Interval(7)))
+                .unwrap()
+                .index,
+            Interval(7)
+        );
+        let own = encounters(&keys, 0..30);
+
+        // three matches spread over 8 indices do not fit into a 7-index window
+        let spread = [&own[2], &own[5], &own[9]];
+        let result = spirit_trace_windowed(spread, &t_el, 7, 3);
+        assert_eq!(result.count, 2);
+        assert!(!result.alarm);
+        assert!(spirit_trace_windowed(spread, &t_el, 8, 3).alarm);
+        assert!(spirit_trace(spread, &t_el, 3).alarm);
+
+        // a cluster inside one window alarms, and other matches outside it are not counted
+        let clustered = [&own[0], &own[20], &own[14], &own[16], &own[20], &own[15]];
+        let result = spirit_trace_windowed(clustered, &t_el, 7, 4);
+        assert_eq!(
+            result.matched,
+            vec![
+                own[14].clone(),
+                own[15].clone(),
+                own[16].clone(),
+                own[20].clone()
+            ]
+        );
+        assert_eq!(result.count, 4);
+        assert!(result.alarm);
+
+        assert_eq!(
+            spirit_trace_windowed(&[], &t_el, 7, 1),
+            TraceResult {
+                matched: Vec::new(),
+                count: 0,
+                alarm: false
+            }
+        );
+        assert_eq!(spirit_trace_windowed(clustered, &t_el, 0, 1).count, 1);
+
+        // matches of the same indices in another epoch are not in the same window
+        for i in (0..30).map(Interval) {
+            spirit_broadcast_osrng(&domain, 1, i, &keys, &pk_ha, &mut t_el);
+        }
+        let later: Vec<_> = (14..17)
+            .map(|i| prf_broadcast(&domain, &keys.epoch_key(1), 1, Interval(i)))
+            .collect();
+        let mixed = [&own[14], &later[0], &own[15], &later[1], &later[2]];
+        let result = spirit_trace_windowed(mixed, &t_el, 7, 4);
+        assert_eq!(result.matched, later);
+        assert_eq!(result.count, 3);
+        assert!(!result.alarm);
+    }
+
+    #[test]
+    fn trace_weighted() {
+        let domain = DomainConfig::default();
+        let keys = UserKeys::random(OsRng);
+        let pk_ha = ha_key();
+        let mut t_el = EncounterTable::new();
+        spirit_broadcast_weighted_osrng(&domain, 0, Interval(0), 3, &keys, &pk_ha, &mut t_el);
+        spirit_broadcast_weighted_osrng(&domain, 0, Interval(1), 4, &keys, &pk_ha, &mut t_el);
+        spirit_broadcast_weighted_osrng(&domain, 0, Interval(2), 12, &keys, &pk_ha, &mut t_el);
+        spirit_broadcast_osrng(&domain, 0, Interval(3), &keys, &pk_ha, &mut t_el);
+        let own = encounters(&keys, 0..4);
+
+        let low = [&own[0], &own[1], &own[0]];
+        let result = spirit_trace_weighted(low, &t_el, 10);
+        assert_eq!(result.matched, own[..2].to_vec());
+        assert_eq!(result.score, 7);
+        assert!(!result.alarm);
+        assert_eq!(spirit_trace(low, &t_el, 2).count, 2);
+
+        let high = spirit_trace_weighted([&own[2]], &t_el, 10);
+        assert_eq!(high.score, 12);
+        assert!(high.alarm);
+
+        assert_eq!(t_el.lookup(&own[3]).unwrap().weight, 1);
+        assert!(spirit_trace_weighted([&own[3]], &t_el, 1).alarm);
+        assert!(!spirit_trace_weighted(&[], &t_el, 0).alarm);
+    }
+
+    #[test]
+    fn trace_risk_of_scanned_encounters() {
+        let domain = DomainConfig::default();
+        let keys = UserKeys::random(OsRng);
+        let pk_ha = ha_key();
+        let model = BucketRiskModel::default();
+
+        // the broadcaster's ElIDs, of which the receiver records the scans
+        let mut t_el = EncounterTable::new();
+        let payload = |el_id: &ElID| BroadcastPayload::new(CompactElId::new(el_id), 0);
+
+        // one long close contact, scanned twice
+        let mut received = ReceivedTable::new();
+        let (close, _) = spirit_broadcast_osrng(&domain, 0, Interval(0), &keys, &pk_ha, &mut t_el);
+        assert_eq!(spirit_trace_risk([&close], &received, &model).score, 0);
+        assert!(payload(&close).receive(0, 10 * 60, 60, &mut received));
+        assert!(!payload(&close).receive(0, 10 * 60, 50, &mut received));
+        let risk = spirit_trace_risk([&close, &close], &received, &model);
+        assert_eq!(risk.matched, [close.clone()]);
+        assert_eq!(risk.score, 4 * 20);
+        assert!(risk.at_risk);
+
+        // many brief close and long distant contacts
+        let mut received = ReceivedTable::new();
+        let mut heard = Vec::new();
+        for i in (0..50).map(Interval) {
+            let (el_id, _) = spirit_broadcast_osrng(&domain, 1, i, &keys, &pk_ha, &mut t_el);
+            let (duration_secs, attenuation_db) = if i.0 % 2 == 0 {
+                (2 * 60, 50)
+            } else {
+                (30 * 60, 80)
+            };
+            payload(&el_id).receive(1, duration_secs, attenuation_db, &mut received);
+            heard.push(el_id);
+        }
+        let risk = spirit_trace_risk(&heard, &received, &model);
+        assert_eq!(risk.matched, heard);
+        assert_eq!(risk.score, 0);
+        assert!(!risk.at_risk);
+        assert!(
+            spirit_trace_risk([&close], &received, &model)
+                .matched
+                .is_empty()
+        );
+
+        // a custom model in which every encounter counts
+        struct Counting;
+        impl RiskModel for Counting {
+            fn weight(&self, _: u16, _: u8) -> u64 {
+                1
+            }
+
+            fn threshold(&self) -> u64 {
+                50
+            }
+        }
+        assert!(spirit_trace_risk(&heard, &received, &Counting).at_risk);
+    }
+
+    #[test]
+    fn trace_risk_levels() {
+        let domain = DomainConfig::default();
+        let keys = UserKeys::random(OsRng);
+        let pk_ha = ha_key();
+        let mut t_el = EncounterTable::new();
+        for i in (0..11).map(Interval) {
+            spirit_broadcast_weighted_osrng(&domain, 0, i, 2, &keys, &pk_ha, &mut t_el);
+        }
+        let own = encounters(&keys, 0..11);
+        let policy = ExposurePolicy::builder()
+            .tier(1, RiskLevel::Low)
+            .tier(3, RiskLevel::Medium)
+            .tier(10, RiskLevel::High)
+            .build()
+            .expect("invalid policy");
+
+        let expected = |count| match count {
+            0 => RiskLevel::None,
+            1..=2 => RiskLevel::Low,
+            3..=9 => RiskLevel::Medium,
+            _ => RiskLevel::High,
+        };
+        for count in [0, 1, 2, 3, 9, 10, 11] {
+            // repetitions count once
+            let cf: Vec<_> = own[..count].iter().chain(&own[..count / 2]).collect();
+            let assessment = spirit_trace_with_policy(cf.iter().copied(), &t_el, &policy);
+            assert_eq!(assessment.matched, own[..count]);
+            assert_eq!(assessment.score, count as u64);
+            assert_eq!(assessment.risk, expected(count as u64));
+
+            // every match weighs 2
+            let weighted = spirit_trace_weighted_with_policy(cf.iter().copied(), &t_el, &policy);
+            assert_eq!(weighted.score, 2 * count as u64);
+            assert_eq!(weighted.risk, expected(2 * count as u64));
+        }
+    }
+
+    #[test]
+    fn encounter_tables_pack_their_records() {
+        // a third of the expanded entry, and two weeks of 96 broadcasts a day in under 700 KB
+        assert!(3 * EncounterTable::RECORD_SIZE < size_of::<(ElID, EncounterRecord)>());
+        assert!(14 * 96 * EncounterTable::RECORD_SIZE < 700_000);
+
+        let domain = DomainConfig::default();
+        let keys = UserKeys::random(OsRng);
+        let sk_ha = DecryptionKey::random(&mut OsRng);
+        let pk_ha = sk_ha.encryption_key();
+        let mut t_el = EncounterTable::with_capacity(4);
+        let broadcasts: Vec<_> = (0..4)
+            .map(|i| spirit_broadcast_osrng(&domain, i, Interval(i), &keys, &pk_ha, &mut t_el))
+            .collect();
+
+        // the records are expanded as they were broadcast
+        assert_eq!(t_el.len(), 4);
+        for (el_id, es_i) in &broadcasts {
+            let record = t_el.lookup(el_id).unwrap();
+            assert_eq!(record.secret, *es_i);
+            assert_eq!(decrypt_encounter(&sk_ha, &record.encrypted_secret), *es_i);
+            assert_eq!(
+                t_el.lookup_compact(&CompactElId::new(el_id)),
+                Some((el_id.clone(), record))
+            );
+        }
+        let mut el_ids: Vec<_> = t_el.el_ids().collect();
+        el_ids.sort();
+        let mut own: Vec<_> = broadcasts.iter().map(|(el_id, _)| el_id.clone()).collect();
+        own.sort();
+        assert_eq!(el_ids, own);
+        assert_eq!(t_el.lookup(&G1G2::random(OsRng)), None);
+        // logs show the counts only
+        assert_eq!(
+            t_el.redacted_debug(),
+            "EncounterTable { entries: 4, epochs: 4 }"
+        );
+        assert_eq!(format!("{t_el:?}"), t_el.redacted_debug());
+
+        // a re-inserted ElID replaces its record
+        let (el_id, _) = &broadcasts[0];
+        let replaced = EncounterRecord {
+            weight: 5,
+            ..t_el.lookup(el_id).unwrap()
+        };
+        assert!(!t_el.insert(el_id, replaced.clone()));
+        assert_eq!(t_el.lookup(el_id), Some(replaced));
+
+        assert_eq!(t_el.prune(2), 2);
+        assert_eq!(t_el.drain().count(), 2);
+        assert!(t_el.is_empty());
+    }
+
+    #[test]
+    fn encounter_tables_load_leniently() {
+        const ENTRY_SIZE: usize = PackedEncounter::ENCODED_SIZE;
+
+        let domain = DomainConfig::default();
+        let keys = UserKeys::random(OsRng);
+        let pk_ha = DecryptionKey::random(&mut OsRng).encryption_key();
+        let mut t_el = EncounterTable::new();
+        for i in 0..5 {
+            spirit_broadcast_osrng(&domain, i, Interval(i), &keys, &pk_ha, &mut t_el);
+        }
+        let bytes = t_el.to_bytes();
+        assert_eq!(bytes.len(), HEADER_SIZE + 8 + 5 * ENTRY_SIZE + 32);
+        assert_eq!(EncounterTable::from_bytes(&bytes).as_ref(), Ok(&t_el));
+
+        // an ElID off the curve and a secret out of range, re-checksummed as if they had been
+        // corrupted while stored in the table
+        let offset = |entry: usize| HEADER_SIZE + 8 + entry * ENTRY_SIZE;
+        let el_id = |bytes: &[u8], entry: usize| {
+            G1G2::from_bytes(&bytes[offset(entry)..offset(entry) + G1G2::ENCODED_SIZE]).unwrap()
+        };
+        let mut corrupted = bytes.clone();
+        corrupted[offset(1) + 20] ^= 0x01;
+        let secret = offset(3) + G1G2::ENCODED_SIZE;
+        corrupted[secret..secret + 32].fill(0xff);
+        let checksum = Sha256::digest(&corrupted[..bytes.len() - 32]);
+        let unsealed = corrupted.clone();
+        corrupted[bytes.len() - 32..].copy_from_slice(&checksum);
+
+        assert_eq!(
+            EncounterTable::from_bytes(&corrupted),
+            Err(DecodeError::InvalidPoint)
+        );
+        let (loaded, warnings) =
+            EncounterTable::from_bytes_with(&corrupted, LoadMode::Lenient).unwrap();
+        assert_eq!(
+            warnings,
+            [
+                LoadWarning {
+                    offset: offset(1) as u64,
+                    reason: DecodeError::InvalidPoint
+                },
+                LoadWarning {
+                    offset: offset(3) as u64,
+                    reason: DecodeError::InvalidScalar
+                },
+            ]
+        );
+        assert_eq!(loaded.len(), 3);
+        for entry in [0, 2, 4] {
+            let intact = el_id(&bytes, entry);
+            assert_eq!(loaded.lookup(&intact), t_el.lookup(&intact));
+        }
+        assert!(!loaded.contains(&el_id(&bytes, 3)));
+
+        // the header, the length and the checksum are still checked
+        let lenient = |bytes: &[u8]| EncounterTable::from_bytes_with(bytes, LoadMode::Lenient);
+        assert_eq!(lenient(&unsealed), Err(DecodeError::ChecksumMismatch));
+        assert_eq!(
+            lenient(&corrupted[..corrupted.len() - 1]),
+            Err(DecodeError::InvalidLength {
+                expected: corrupted.len(),
+                actual: corrupted.len() - 1
+            })
+        );
+        let mut header = corrupted.clone();
+        header[0] ^= 0x01;
+        assert_eq!(lenient(&header), Err(DecodeError::InvalidHeader));
+        assert_eq!(
+            lenient(&EncounterTable::new().to_bytes()),
+            Ok((EncounterTable::new(), Vec::new()))
+        );
+    }
+
+    #[test]
+    fn pruned_encounters_no_longer_match() {
+        let domain = DomainConfig::default();
+        let keys = UserKeys::random(OsRng);
+        let pk_ha = ha_key();
+        let mut t_el = EncounterTable::new();
+        let mut retained = RetainedEncounters::new(14);
+        let mut own = Vec::new();
+        for epoch in 0..30 {
+            let mut scratch = EncounterTable::new();
+            for i in (0..3).map(Interval) {
+                let (el_id, _) =
+                    spirit_broadcast_osrng(&domain, epoch, i, &keys, &pk_ha, &mut scratch);
+                own.push(el_id);
+            }
+            t_el.extend(scratch.iter());
+            retained.append(&mut scratch);
+            assert!(retained.len() <= 14 * 3);
+        }
+        let (old, recent) = own.split_at(16 * 3);
+
+        assert_eq!(prune_encounters(&mut t_el, 16), old.len());
+        assert_eq!(prune_encounters(&mut t_el, 16), 0);
+        assert_eq!(retained.oldest_allowed(), 16);
+        for table in [&t_el, retained.table()] {
+            assert_eq!(table.len(), recent.len());
+            assert_eq!(spirit_trace(old, table, 1).count, 0);
+            assert_eq!(spirit_trace(&own, table, 1).matched, recent);
+        }
+
+        // encounters of epochs before the window are not inserted
+        let (_, encounter) = t_el.iter().next().unwrap();
+        assert!(!retained.insert(
+            old[0].clone(),
+            EncounterRecord {
+                epoch: 15,
+                ..encounter.clone()
+            }
+        ));
+        assert!(retained.insert(
+            old[0].clone(),
+            EncounterRecord {
+                epoch: 16,
+                ..encounter
+            }
+        ));
+
+        // a day without encounters moves the window, too
+        assert_eq!(retained.advance(30), 4);
+        assert_eq!(retained.advance(29), 0);
+        assert_eq!(retained.oldest_allowed(), 17);
+    }
+
+    #[test]
+    fn decoys_are_never_matched_or_disclosed() {
+        let domain = DomainConfig::default();
+        let (mut system, issuers) = SpiritSystem::setup(T, N, NUM_ISSUERS).expect("setup failed");
+        let user = UserSecret::random(OsRng);
+        let ppu = system
+            .register(&user, &issuers, EXPIRY)
+            .expect("registration failed");
+
+        let scheduler = BroadcastScheduler::new(3);
+        let mut t_el = EncounterTable::new();
+        let broadcasts = scheduler.schedule(
+            &domain,
+            0,
+            Interval(0)..Interval(4),
+            user.keys(),
+            &ha_key(),
+            &mut t_el,
+            &mut OsRng,
+        );
+        assert_eq!(broadcasts.len(), 16);
+        let (decoys, real): (Vec<_>, Vec<_>) = broadcasts
+            .iter()
+            .partition(|broadcast| broadcast.is_decoy());
+        assert_eq!(decoys.len(), 12);
+        let real: HashSet<_> = real.iter().map(|broadcast| broadcast.el_id()).collect();
+        let table: HashSet<_> = t_el.el_ids().collect();
+        assert_eq!(real, table.iter().collect());
+        assert_eq!(
+            real,
+            encounters(user.keys(), 0..4).iter().collect::<HashSet<_>>()
+        );
+
+        let decoys: Vec<_> = decoys.iter().map(|broadcast| broadcast.el_id()).collect();
+        assert_eq!(spirit_trace(decoys.iter().copied(), &t_el, 1).count, 0);
+        // decoys are powers of the base H(0, i), the PRF for the key 1, like real ElIDs and
+        // unlike random points
+        let bases: Vec<_> = (0..4)
+            .map(|i| prf_broadcast(&domain, &Scalar::from(1u64), 0, Interval(i)))
+            .collect();
+        let of_a_base = |el_id: &ElID| {
+            bases
+                .iter()
+                .any(|base| pairing(el_id, base) == pairing(base, el_id))
+        };
+        assert!(real.iter().chain(&decoys).all(|el_id| of_a_base(*el_id)));
+        assert!(!of_a_base(&G1G2::random(OsRng)));
+
+        let cp: HashSet<_> = (0..4).map(|i| (0, Interval(i))).collect();
+        let challenge = system.issue_challenge(NOW);
+        let report = spirit_diagnosis_osrng(&domain, &ppu, user.keys(), &cp, NOW, &challenge, &[])
+            .expect("diagnosis failed");
+        let disclosed: HashSet<_> = report
+            .disclosures
+            .iter()
+            .map(|disclosure| &disclosure.el_id)
+            .collect();
+        assert_eq!(disclosed, real);
+        assert!(decoys.iter().all(|decoy| !disclosed.contains(decoy)));
+    }
+
+    #[test]
+    fn decoys_are_encoded_like_real_el_ids() {
+        const SAMPLES: usize = 256;
+
+        let keys = UserKeys::random(OsRng);
+        let real: Vec<_> = encounters(&keys, 0..SAMPLES as u64)
+            .iter()
+            .map(G1G2::to_bytes)
+            .collect();
+        let domain = DomainConfig::default();
+        let decoys: Vec<_> = (0..SAMPLES as u64)
+            .map(|i| spirit_broadcast_decoy(&domain, 0, Interval(i), &mut OsRng).to_bytes())
+            .collect();
+        assert!(decoys.iter().all(|bytes| G1G2::from_bytes(bytes).is_ok()));
+
+        // the flag bits of the compressed encodings of both points, and the mean of all bytes
+        let g2_start = G1G2::ENCODED_SIZE - 96;
+        for position in [0, g2_start] {
+            let flags = |encodings: &[[u8; G1G2::ENCODED_SIZE]]| {
+                let mut counts = [0; 8];
+                for bytes in encodings {
+                    counts[usize::from(bytes[position] >> 5)] += 1;
+                }
+                counts
+            };
+            let (real_flags, decoy_flags) = (flags(&real), flags(&decoys));
+            for (real, decoy) in real_flags.into_iter().zip(decoy_flags) {
+                assert!(
+                    (real == 0) == (decoy == 0),
+                    "{real_flags:?} {decoy_flags:?}"
+                );
+                assert!(
+                    real.abs_diff(decoy) < SAMPLES / 5,
+                    "{real_flags:?} {decoy_flags:?}"
+                );
+            }
+        }
+        let mean = |encodings: &[[u8; G1G2::ENCODED_SIZE]]| {
+            let sum: usize = encodings
+                .iter()
+                .flatten()
+                .map(|byte| usize::from(*byte))
+                .sum();
+            sum as f64 / (SAMPLES * G1G2::ENCODED_SIZE) as f64
+        };
+        assert!((mean(&real) - mean(&decoys)).abs() < 2.0);
+    }
+
+    #[test]
+    fn compact_trace_matches_the_full_trace() {
+        let domain = DomainConfig::default();
+        let keys = UserKeys::random(OsRng);
+        let pk_ha = ha_key();
+        let mut t_el = EncounterTable::new();
+        let compact: Vec<_> = (0..10)
+            .map(Interval)
+            .map(|i| {
+                spirit_broadcast_compact(&domain, 0, i, &keys, &pk_ha, &mut t_el, &mut OsRng).0
+            })
+            .collect();
+        let own = encounters(&keys, 0..10);
+        assert_eq!(
+            compact,
+            own.iter().map(CompactElId::new).collect::<Vec<_>>()
+        );
+
+        let others: Vec<_> = (0..5)
+            .map(|_| CompactElId::new(&G1G2::random(OsRng)))
+            .collect();
+        let cf: Vec<_> = compact[3..7]
+            .iter()
+            .chain(&others)
+            .chain(&compact[3..5])
+            .collect();
+        let full: Vec<_> = own[3..7].iter().collect();
+        assert_eq!(
+            spirit_trace_compact(cf.iter().copied(), &t_el, 4),
+            spirit_trace(full, &t_el, 4)
+        );
+        assert_eq!(spirit_trace_compact(&others, &t_el, 1).count, 0);
+
+        // the compact IDs are looked up in the table without the full ElIDs
+        assert_eq!(
+            t_el.lookup_compact(&compact[2]),
+            Some((own[2].clone(), t_el.lookup(&own[2]).unwrap()))
+        );
+        assert_eq!(t_el.lookup_compact(&others[0]), None);
+    }
+
+    #[test]
+    fn trace_forced_collisions() {
+        let domain = DomainConfig::default();
+        let keys = UserKeys::random(OsRng);
+        let pk_ha = ha_key();
+        // a compaction under which all ElIDs collide
+        let collided = CompactElId([0; CompactElId::SIZE]);
+        let mut t_el = EncounterTable::with_compaction(|_| CompactElId([0; CompactElId::SIZE]));
+        let own: Vec<_> = (0..4)
+            .map(|epoch| {
+                spirit_broadcast_osrng(&domain, epoch, Interval(0), &keys, &pk_ha, &mut t_el).0
+            })
+            .collect();
+        assert_eq!(t_el.len(), 4);
+
+        // lookups by the full ElID find the ElID's own record, and not another's
+        for (epoch, el_id) in (0..).zip(&own) {
+            assert_eq!(
+                t_el.lookup(el_id).map(|encounter| encounter.epoch),
+                Some(epoch)
+            );
+        }
+        assert!(!t_el.contains(&G1G2::random(OsRng)));
+        let encounter = t_el.lookup(&own[2]).unwrap();
+        assert!(!t_el.insert(&own[2], encounter));
+        assert_eq!(t_el.len(), 4);
+
+        // the compact ID matches every colliding ElID as a false positive, but misses none
+        let traced = spirit_trace_compact(&[collided], &t_el, 4);
+        assert_eq!(traced.count, 4);
+        assert!(traced.alarm && own.iter().all(|el_id| traced.matched.contains(el_id)));
+        // the full ElIDs only match themselves
+        assert_eq!(spirit_trace(&own[1..3], &t_el, 1).matched, own[1..3]);
+
+        // a colliding record takes the place of the pruned first entry
+        assert_eq!(t_el.prune(2), 2);
+        assert_eq!(t_el.len(), 2);
+        assert!(!t_el.contains(&own[0]) && !t_el.contains(&own[1]));
+        assert!(own[2..].iter().all(|el_id| t_el.contains(el_id)));
+        assert_eq!(spirit_trace_compact(&[collided], &t_el, 1).count, 2);
+        assert!(t_el.lookup_compact(&collided).is_some());
+    }
+
+    #[test]
+    fn trace_zero_exposure_limit() {
+        let domain = DomainConfig::default();
+        let keys = UserKeys::random(OsRng);
+        let pk_ha = ha_key();
+        let mut t_el = EncounterTable::new();
+        spirit_broadcast_osrng(&domain, 0, Interval(0), &keys, &pk_ha, &mut t_el);
+
+        assert!(!spirit_trace(&[], &t_el, 0).alarm);
+        assert!(spirit_trace(&encounters(&keys, 0..1), &t_el, 0).alarm);
+    }
+
+    #[test]
+    fn prf_proofs() {
+        let domain = DomainConfig::default();
+        let k = Scalar::rand(&mut OsRng);
+        let pk = prf_public_key(&domain, &k);
+        let (el_id, proof) = prf_prove(&domain, &k, 2, Interval(5), &mut OsRng);
+        assert_eq!(el_id, prf_broadcast(&domain, &k, 2, Interval(5)));
+        assert!(prf_verify(&domain, &pk, 2, Interval(5), &el_id, &proof));
+        assert!(!prf_verify(&domain, &pk, 2, Interval(6), &el_id, &proof));
+        assert!(!prf_verify(&domain, &pk, 3, Interval(5), &el_id, &proof));
+
+        // correct form, but evaluated under a different key
+        let other = Scalar::rand(&mut OsRng);
+        let (forged, forged_proof) = prf_prove(&domain, &other, 2, Interval(5), &mut OsRng);
+        assert!(!prf_verify(
+            &domain,
+            &pk,
+            2,
+            Interval(5),
+            &forged,
+            &forged_proof
+        ));
+        assert!(!prf_verify(&domain, &pk, 2, Interval(5), &forged, &proof));
+    }
+
+    #[test]
+    fn domain_separation() {
+        let keys = UserKeys::random(OsRng);
+        let pk_ha = ha_key();
+        let first = DomainConfig::new(b"first".to_vec());
+        let second = DomainConfig::new(b"second".to_vec());
+
+        // the same user key material broadcasts unrelated ElIDs in different deployments
+        let mut t_first = EncounterTable::new();
+        let mut t_second = EncounterTable::new();
+        for i in (0..4).map(Interval) {
+            spirit_broadcast_osrng(&first, 0, i, &keys, &pk_ha, &mut t_first);
+            spirit_broadcast_osrng(&second, 0, i, &keys, &pk_ha, &mut t_second);
+        }
+        assert!(t_first.el_ids().all(|el_id| !t_second.contains(&el_id)));
+        let second_el_ids: Vec<_> = t_second.el_ids().collect();
+        assert!(spirit_trace(&second_el_ids, &t_first, 1).matched.is_empty());
+
+        // a length prefix keeps `app_id` and the tags from running into each other
+        let mut shifted = DomainConfig::new(b"firstP".to_vec());
+        shifted.prf_dst = b"RF-domain".to_vec();
+        assert_ne!(
+            prf_base(&first, 0, Interval(0)),
+            prf_base(&shifted, 0, Interval(0))
+        );
+
+        let k = keys.epoch_key(0);
+        let (el_id, proof) = prf_prove(&first, &k, 0, Interval(1), &mut OsRng);
+        assert!(prf_verify(
+            &first,
+            &prf_public_key(&first, &k),
+            0,
+            Interval(1),
+            &el_id,
+            &proof
+        ));
+        assert!(!prf_verify(
+            &second,
+            &prf_public_key(&second, &k),
+            0,
+            Interval(1),
+            &el_id,
+            &proof
+        ));
+    }
+
+    #[test]
+    fn prf_purposes_are_separated() {
+        let domain = DomainConfig::default();
+        let k = Scalar::rand(&mut OsRng);
+        let purposes = [
+            PrfPurpose::Broadcast,
+            PrfPurpose::Nullifier,
+            PrfPurpose::Checkin,
+            PrfPurpose::DeviceDerivation,
+        ];
+
+        // the same key and input give unrelated values for every purpose
+        for input in [
+            &broadcast_input(0, Interval(1))[..],
+            &7u64.to_le_bytes(),
+            b"",
+        ] {
+            let values: HashSet<_> = purposes
+                .iter()
+                .map(|purpose| prf(&domain, &k, *purpose, input))
+                .collect();
+            assert_eq!(values.len(), purposes.len());
+        }
+
+        // ElIDs, nullifiers and check-ins are evaluations of their purposes
+        assert_eq!(
+            prf_broadcast(&domain, &k, 0, Interval(1)),
+            prf(
+                &domain,
+                &k,
+                PrfPurpose::Broadcast,
+                &broadcast_input(0, Interval(1))
+            )
+        );
+        assert_eq!(
+            nullifier_base(&domain, 7) * k,
+            prf(&domain, &k, PrfPurpose::Nullifier, &7u64.to_le_bytes())
+        );
+        let mut venue_input = 5u64.to_le_bytes().to_vec();
+        venue_input.extend_from_slice(b"venue");
+        venue_input.extend_from_slice(&3u64.to_le_bytes());
+        assert_eq!(
+            venue_base(&domain, b"venue", Interval(3)) * k,
+            prf(&domain, &k, PrfPurpose::Checkin, &venue_input)
+        );
+
+        // and device keys are derived from the evaluations of theirs, which depend on the domain
+        let keys = UserKeys::new(k);
+        let other = DomainConfig::new(b"other".to_vec());
+        assert_ne!(
+            keys.device_keys(&domain, 1).epoch_key(0),
+            keys.device_keys(&other, 1).epoch_key(0)
+        );
+    }
+
+    #[test]
+    fn interval_inputs_do_not_depend_on_the_target() {
+        // the bytes of the PRF input are fixed, also beyond the intervals a 32-bit usize holds
+        let beyond_u32 = Interval(u64::from(u32::MAX) + 2);
+        let mut expected = [0u8; 16];
+        expected[..8].copy_from_slice(&[3, 0, 0, 0, 0, 0, 0, 0]);
+        expected[8..].copy_from_slice(&[1, 0, 0, 0, 1, 0, 0, 0]);
+        assert_eq!(broadcast_input(3, beyond_u32), expected);
+        assert_eq!(
+            broadcast_input(1, Interval(0x0102)),
+            [1, 0, 0, 0, 0, 0, 0, 0, 0x02, 0x01, 0, 0, 0, 0, 0, 0]
+        );
+        assert_eq!(
+            Interval::from_le_bytes(beyond_u32.to_le_bytes()),
+            beyond_u32
+        );
+        assert_eq!(Interval::from(7usize), Interval(7));
+
+        // so are its encodings, which are those of the usize indices before
+        let mut bytes = Vec::new();
+        beyond_u32.serialize_compressed(&mut bytes).unwrap();
+        assert_eq!(bytes, beyond_u32.to_le_bytes());
+        assert_eq!(bytes.len(), Interval::ENCODED_SIZE);
+        let mut legacy = Vec::new();
+        7usize.serialize_compressed(&mut legacy).unwrap();
+        assert_eq!(
+            Interval::deserialize_compressed(&legacy[..]).unwrap(),
+            Interval(7)
+        );
+    }
+
+    #[test]
+    fn intervals_of_unix_times() {
+        const LEN: u64 = 15 * 60;
+
+        // an interval holds its start, but not its end
+        assert_eq!(Interval::from_unix(0, LEN), Some(Interval(0)));
+        assert_eq!(Interval::from_unix(LEN - 1, LEN), Some(Interval(0)));
+        assert_eq!(Interval::from_unix(LEN, LEN), Some(Interval(1)));
+        assert_eq!(Interval::from_unix(96 * LEN + 1, LEN), Some(Interval(96)));
+        for interval in [Interval(0), Interval(1), Interval(20_000)] {
+            let start = interval.start_unix(LEN).unwrap();
+            assert_eq!(Interval::from_unix(start, LEN), Some(interval));
+            assert_eq!(Interval::from_unix(start + LEN - 1, LEN), Some(interval));
+        }
+
+        // the last interval, a length of 0 and starts beyond u64::MAX
+        let last = Interval::from_unix(u64::MAX, LEN).unwrap();
+        assert_eq!(last, Interval(u64::MAX / LEN));
+        assert!(last.start_unix(LEN).unwrap() <= u64::MAX - (LEN - 1));
+        assert_eq!(Interval::from_unix(u64::MAX, 0), None);
+        assert_eq!(Interval(5).start_unix(0), None);
+        assert_eq!(Interval(u64::MAX).start_unix(LEN), None);
+
+        // the distances of intervals
+        assert_eq!(Interval(9).checked_add(3), Some(Interval(12)));
+        assert_eq!(Interval(u64::MAX).checked_add(1), None);
+        assert_eq!(Interval(9).checked_sub(3), Some(Interval(6)));
+        assert_eq!(Interval(2).checked_sub(3), None);
+        assert_eq!(Interval(9).checked_since(Interval(6)), Some(3));
+        assert_eq!(Interval(6).checked_since(Interval(9)), None);
+        assert_eq!(Interval(9).since(Interval(6)), 3);
+        assert_eq!(Interval(6).since(Interval(9)), 0);
+        assert_eq!(
+            Interval::range(Interval(2)..Interval(5)).collect::<Vec<_>>(),
+            [Interval(2), Interval(3), Interval(4)]
+        );
+        assert_eq!(Interval::range(Interval(5)..Interval(2)).count(), 0);
+    }
+
+    #[test]
+    fn verify_foreign_domain() {
+        let (report, mut system, _) = diagnosed_report(&[(0, Interval(1)), (1, Interval(3))]);
+        let domain = DomainConfig::new(b"other".to_vec());
+        assert_ne!(system.domain(), &domain);
+
+        // the token is known to the other deployment, but the proofs are bound to the first one
+        let (mut other, _) = SpiritSystem::setup_with_domain(T, N, NUM_ISSUERS, domain.clone())
+            .expect("setup failed");
+        other.t_rgstr.insert(report.token.clone());
+        let foreign = Err(VerifyRejection::OtherDeployment {
+            token: report.token.id(),
+            deployment: report.deployment.clone(),
+        });
+        assert_eq!(
+            other.verify_report(&report, NOW, &mut HashSet::new()),
+            foreign
+        );
+        assert_eq!(
+            other.verify_reports(std::slice::from_ref(&report), NOW, &mut HashSet::new()),
+            [foreign.clone()]
+        );
+        assert_eq!(
+            spirit_verify(
+                &domain,
+                &report,
+                system.registry(),
+                &RevocationList::new(),
+                NOW,
+                &mut HashSet::new(),
+                system.challenges(),
+                &mut HashSet::new()
+            ),
+            foreign
+        );
+        assert_eq!(
+            system.verify_report(&report, NOW, &mut HashSet::new()),
+            accepted(&[true, true])
+        );
+    }
+
+    #[test]
+    fn verify_copied_el_id() {
+        let domain = DomainConfig::default();
+        let (mut report, mut system, _) = diagnosed_report(&[(0, Interval(2)), (0, Interval(4))]);
+
+        // an ElID broadcast by someone else, with a valid PRF proof under their key
+        let other = UserKeys::random(OsRng).epoch_key(0);
+        let (el_id, prf_proof) = prf_prove(&domain, &other, 0, Interval(2), &mut OsRng);
+        assert!(prf_verify(
+            &domain,
+            &prf_public_key(&domain, &other),
+            0,
+            Interval(2),
+            &el_id,
+            &prf_proof
+        ));
+        report.disclosures[0].el_id = el_id;
+        report.disclosures[0].prf_proof = prf_proof;
+
+        let invalid = Err(VerifyRejection::InvalidProof {
+            el_id: report.disclosures[0].el_id.clone(),
+        });
+        let mut confirmed = HashSet::new();
+        assert_eq!(
+            system.verify_report(&report, NOW, &mut confirmed),
+            accepted(&[false, true])
+        );
+        assert_eq!(
+            system.verify_reports(std::slice::from_ref(&report), NOW, &mut confirmed),
+            [invalid.clone()]
+        );
+
+        // swapping in the other user's PRF public key breaks the remaining disclosure
+        report.prf_pk.insert(0, prf_public_key(&domain, &other));
+        system.nullifiers.clear();
+        assert_eq!(
+            system.verify_report(&report, NOW, &mut HashSet::new()),
+            invalid
+        );
+    }
+
+    #[test]
+    fn verify_invalid_proof() {
+        let domain = DomainConfig::default();
+        let (mut report, mut system, _) = diagnosed_report(&[(0, Interval(2)), (0, Interval(4))]);
+        report.disclosures[0].el_id =
+            prf_broadcast(&domain, &Scalar::rand(&mut OsRng), 0, Interval(2));
+
+        let previous = G1G2::random(&mut OsRng);
+        let mut confirmed = HashSet::from([previous.clone()]);
+        assert_eq!(
+            system.verify_report(&report, NOW, &mut confirmed),
+            accepted(&[false, true])
+        );
+        assert!(!confirmed.contains(&report.disclosures[0].el_id));
+        assert!(confirmed.contains(&report.disclosures[1].el_id));
+        assert!(confirmed.contains(&previous));
+        assert_eq!(confirmed.len(), 2);
+    }
+
+    #[test]
+    fn verify_degenerate_points() {
+        let (report, mut system, _) = diagnosed_report(&[(0, Interval(2)), (0, Interval(4))]);
+        // (0, 2) is on the curve but of order 3, with a valid G2 part
+        let mut bytes = G1G2::random(&mut OsRng).to_bytes();
+        bytes[..48].copy_from_slice(&[0; 48]);
+        bytes[0] = 0x80;
+        let low_order =
+            G1G2::deserialize_with_mode(&bytes[..], Compress::Yes, Validate::No).unwrap();
+        assert!(!low_order.is_identity() && !low_order.is_valid_element());
+
+        let degenerate: [fn(&mut TraceReport, &G1G2); 6] = [
+            |tr, _| tr.disclosures[0].el_id = G1G2::default(),
+            |tr, point| tr.disclosures[1].el_id = point.clone(),
+            |tr, _| tr.token.0 = Commitment(G1G2::default()),
+            |tr, point| tr.token.1 = Signature(point.clone()),
+            |tr, _| tr.nullifier = G1G2::default(),
+            |tr, point| *tr.prf_pk.get_mut(&0).unwrap() = point.clone(),
+        ];
+        let mut encoded = Vec::new();
+        report.serialize_compressed(&mut encoded).unwrap();
+        for make_degenerate in degenerate {
+            let mut tr = TraceReport::deserialize_compressed(&encoded[..]).unwrap();
+            make_degenerate(&mut tr, &low_order);
+            // rejected before the registry lookup and the pairing of the signature check
+            let malformed = Err(VerifyRejection::MalformedPoint {
+                token: tr.token.id(),
+            });
+            let mut confirmed = HashSet::new();
+            assert_eq!(system.verify_report(&tr, NOW, &mut confirmed), malformed);
+            assert_eq!(
+                system.verify_rerandomized_report(&tr, NOW, &mut confirmed),
+                malformed
+            );
+            assert!(confirmed.is_empty());
+        }
+        assert_eq!(
+            system.verify_report(&report, NOW, &mut HashSet::new()),
+            accepted(&[true, true])
+        );
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    #[should_panic]
+    fn prf_of_key_0() {
+        prf_broadcast(&DomainConfig::default(), &Scalar::zero(), 0, Interval(0));
+    }
+
+    #[test]
+    fn verify_unregistered_token() {
+        let (report, _, _) = diagnosed_report(&[(0, Interval(1)), (2, Interval(7))]);
+        let (mut other_system, _) = SpiritSystem::setup(T, N, NUM_ISSUERS).expect("setup failed");
+
+        let mut confirmed = HashSet::new();
+        assert_eq!(
+            other_system.verify_report(&report, NOW, &mut confirmed),
+            Err(VerifyRejection::Unregistered {
+                token: report.token.id()
+            })
+        );
+        assert!(confirmed.is_empty());
+    }
+
+    #[test]
+    fn verify_with_filter() {
+        let domain = DomainConfig::default();
+        let (report, system, _) = diagnosed_report(&[(0, Interval(1)), (2, Interval(7))]);
+        let filter = RegistryFilter::new(system.registry().iter(), 0.01).unwrap();
+
+        let mut confirmed = HashSet::new();
+        assert_eq!(
+            spirit_verify(
+                &domain,
+                &report,
+                &filter,
+                &RevocationList::new(),
+                NOW,
+                &mut HashSet::new(),
+                system.challenges(),
+                &mut confirmed
+            ),
+            accepted(&[true, true])
+        );
+        assert_eq!(confirmed.len(), 2);
+    }
+
+    #[test]
+    fn verify_batch() {
+        let domain = DomainConfig::default();
+        let (mut system, issuers) = SpiritSystem::setup(T, N, NUM_ISSUERS).expect("setup failed");
+        let ppus: Vec<_> = (0..6)
+            .map(|_| {
+                system
+                    .register(&UserSecret::random(OsRng), &issuers, EXPIRY)
+                    .expect("registration failed")
+            })
+            .collect();
+        let keys = UserKeys::random(OsRng);
+
+        let mut reports: Vec<_> = (0..6)
+            .map(|i| {
+                let cp = HashSet::from([(0, Interval::from(i)), (1, Interval::from(i + 10))]);
+                let challenge = system.issue_challenge(NOW);
+                spirit_diagnosis_osrng(&domain, &ppus[i], &keys, &cp, NOW, &challenge, &[])
+                    .expect("diagnosis failed")
+            })
+            .collect();
+
+        let mut confirmed = HashSet::new();
+        assert_eq!(
+            system.verify_reports(&reports, NOW, &mut confirmed),
+            vec![accepted(&[true; 2]); reports.len()]
+        );
+        assert_eq!(confirmed.len(), 2 * reports.len());
+
+        // forge the second disclosure of the fourth report
+        reports[3].disclosures[1].el_id =
+            prf_broadcast(&domain, &Scalar::rand(&mut OsRng), 1, Interval(13));
+        system.nullifiers.clear();
+        let mut confirmed = HashSet::new();
+        let mut results = vec![accepted(&[true; 2]); reports.len()];
+        results[3] = Err(VerifyRejection::InvalidProof {
+            el_id: reports[3].disclosures[1].el_id.clone(),
+        });
+        assert_eq!(
+            system.verify_reports(&reports, NOW, &mut confirmed),
+            results
+        );
+        assert_eq!(confirmed.len(), 2 * (reports.len() - 1));
+        assert!(
+            reports[3]
+                .disclosures
+                .iter()
+                .all(|disclosure| !confirmed.contains(&disclosure.el_id))
+        );
+
+        // also forge the first disclosures of the first and the last report
+        for i in [0, 5] {
+            reports[i].disclosures[0].el_id =
+                prf_broadcast(&domain, &Scalar::rand(&mut OsRng), 0, Interval::from(i));
+            results[i] = Err(VerifyRejection::InvalidProof {
+                el_id: reports[i].disclosures[0].el_id.clone(),
+            });
+        }
+        system.nullifiers.clear();
+        assert_eq!(
+            system.verify_reports(&reports, NOW, &mut HashSet::new()),
+            results
+        );
+
+        // reports with unregistered tokens are rejected without affecting the others
+        system.t_rgstr.remove(&ppus[0].0);
+        system.nullifiers.clear();
+        let mut confirmed = HashSet::new();
+        results[0] = Err(VerifyRejection::Unregistered {
+            token: ppus[0].0.id(),
+        });
+        assert_eq!(
+            system.verify_reports(&reports, NOW, &mut confirmed),
+            results
+        );
+    }
+
+    #[test]
+    fn verify_replayed_report() {
+        let domain = DomainConfig::default();
+        let (mut system, issuers) = SpiritSystem::setup(T, N, NUM_ISSUERS).expect("setup failed");
+        let user = UserSecret::random(OsRng);
+        let ppu = system
+            .register(&user, &issuers, EXPIRY)
+            .expect("registration failed");
+        let cp = HashSet::from([(0, Interval(1)), (0, Interval(4))]);
+        let report = |report_epoch, challenge: &ReportChallenge| {
+            spirit_diagnosis_osrng(
+                &domain,
+                &ppu,
+                user.keys(),
+                &cp,
+                report_epoch,
+                challenge,
+                &[],
+            )
+            .expect("diagnosis failed")
+        };
+
+        let first = report(NOW, &system.issue_challenge(NOW));
+        let mut confirmed = HashSet::new();
+        assert_eq!(
+            system.verify_report(&first, NOW, &mut confirmed),
+            accepted(&[true, true])
+        );
+        assert_eq!(confirmed.len(), 2);
+        assert!(system.nullifiers().contains(&first.nullifier));
+
+        // neither the same report nor a fresh one of the same epoch is accepted again
+        let second = report(NOW, &system.issue_challenge(NOW));
+        assert_eq!(second.nullifier, first.nullifier);
+        let token = ppu.0.id();
+        let replayed = Err(VerifyRejection::ReplayedNullifier { token });
+        for tr in [&first, &second] {
+            let mut confirmed = HashSet::new();
+            assert_eq!(system.verify_report(tr, NOW, &mut confirmed), replayed);
+            assert_eq!(
+                system.verify_reports(std::slice::from_ref(tr), NOW, &mut confirmed),
+                [replayed.clone()]
+            );
+            assert!(confirmed.is_empty());
+        }
+
+        // a report is only accepted in the epoch it is made for
+        let later = report(NOW + 1, &system.issue_challenge(NOW + 1));
+        assert_ne!(later.nullifier, first.nullifier);
+        assert_eq!(
+            system.verify_report(&later, NOW, &mut HashSet::new()),
+            Err(VerifyRejection::OtherEpoch {
+                token,
+                report_epoch: NOW + 1
+            })
+        );
+        assert_eq!(
+            system.verify_report(&later, NOW + 1, &mut HashSet::new()),
+            accepted(&[true, true])
+        );
+
+        // of two reports with the same nullifier in a batch, only the first is accepted
+        let challenge = system.issue_challenge(NOW + 2);
+        let batch = [report(NOW + 2, &challenge), report(NOW + 2, &challenge)];
+        assert_eq!(
+            system.verify_reports(&batch, NOW + 2, &mut HashSet::new()),
+            [accepted(&[true, true]), replayed.clone()]
+        );
+
+        // an invalid report before it does not spend the nullifier of a valid one
+        let challenge = system.issue_challenge(NOW + 3);
+        let valid = report(NOW + 3, &challenge);
+        let mut invalid = valid.clone();
+        invalid.disclosures[0].proof.pi_1.s_1 += Scalar::from(1u64);
+        let el_id = invalid.disclosures[0].el_id.clone();
+        assert_eq!(
+            system.verify_reports(
+                &[invalid, valid.clone(), valid],
+                NOW + 3,
+                &mut HashSet::new()
+            ),
+            [
+                Err(VerifyRejection::InvalidProof { el_id }),
+                accepted(&[true, true]),
+                replayed
+            ]
+        );
+    }
+
+    fn encoded(tr: &TraceReport) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        tr.serialize_compressed(&mut bytes).unwrap();
+        bytes
+    }
+
+    /// Diagnoses a contact period of `len` indices in 3 epochs as a stream and verifies it in
+    /// chunks of `chunk` disclosures, and checks that both match the whole report.
+    fn streamed_report_matches_whole(len: u64, chunk: usize) {
+        let SetupOutput {
+            pp,
+            issuers,
+            mut t_rgstr,
+            domain,
+            ..
+        } = spirit_setup_osrng(T, N, NUM_ISSUERS, b"test").expect("setup failed");
+        let user = UserSecret::random(OsRng);
+        let ppu = spirit_register_osrng(&domain, &user, &issuers, &pp, EXPIRY, &mut t_rgstr)
+            .expect("registration failed");
+        let mut challenges = ChallengeSet::new(0);
+        let challenge = challenges.issue(NOW, &mut OsRng);
+        let cp: HashSet<_> = (0..len).map(|i| (i % 3, Interval(i))).collect();
+
+        let rng = || ChaCha20Rng::seed_from_u64(len);
+        let report = spirit_diagnosis(
+            &domain,
+            &ppu,
+            user.keys(),
+            None,
+            &cp,
+            NOW,
+            &challenge,
+            &[],
+            &mut rng(),
+        )
+        .expect("diagnosis failed");
+        let mut stream =
+            spirit_diagnose_iter(&domain, &ppu, user.keys(), &cp, NOW, &challenge, &[], rng())
+                .expect("diagnosis failed");
+        assert_eq!(stream.len(), cp.len());
+        assert!(stream.header().disclosures.is_empty());
+        let header = TraceReport::deserialize_compressed(&encoded(stream.header())[..]).unwrap();
+
+        let revoked = RevocationList::new();
+        let (mut nullifiers, mut confirmed) = (HashSet::new(), HashSet::new());
+        let mut verifier = spirit_verify_stream(
+            &domain,
+            &header,
+            &t_rgstr,
+            &revoked,
+            NOW,
+            &mut nullifiers,
+            &challenges,
+            &mut confirmed,
+        )
+        .expect("header rejected");
+        let mut disclosures = Vec::new();
+        loop {
+            let items: Vec<_> = stream
+                .by_ref()
+                .take(chunk)
+                .collect::<Result<_, _>>()
+                .expect("diagnosis failed");
+            if items.is_empty() {
+                break;
+            }
+            assert!(items.len() <= chunk);
+            assert!(verifier.verify_chunk(&items).iter().all(|verdict| *verdict));
+            disclosures.extend(items);
+        }
+        let streamed = verifier.finish();
+        let token = header.token.id();
+        let header = TraceReport {
+            disclosures,
+            ..header
+        };
+        assert_eq!(encoded(&header), encoded(&report));
+
+        let (mut whole_nullifiers, mut whole_confirmed) = (HashSet::new(), HashSet::new());
+        let whole = spirit_verify(
+            &domain,
+            &report,
+            &t_rgstr,
+            &revoked,
+            NOW,
+            &mut whole_nullifiers,
+            &challenges,
+            &mut whole_confirmed,
+        );
+        assert_eq!(streamed, accepted(&vec![true; cp.len()]));
+        assert_eq!(streamed, whole);
+        assert_eq!(confirmed, whole_confirmed);
+        assert_eq!(nullifiers, whole_nullifiers);
+
+        // the header of an accepted report is not accepted again
+        let mut header_only = header;
+        header_only.disclosures.clear();
+        assert_eq!(
+            spirit_verify_stream(
+                &domain,
+                &header_only,
+                &t_rgstr,
+                &revoked,
+                NOW,
+                &mut nullifiers,
+                &challenges,
+                &mut confirmed,
+            )
+            .err(),
+            Some(VerifyRejection::ReplayedNullifier { token })
+        );
+    }
+
+    #[test]
+    fn streamed_reports_match_whole_ones() {
+        streamed_report_matches_whole(120, 50);
+    }
+
+    #[test]
+    #[ignore = "diagnoses 5k ElIDs, run in release mode"]
+    fn streamed_reports_of_5k_elids() {
+        streamed_report_matches_whole(5_000, 100);
+    }
+
+    #[test]
+    fn streamed_reports_reject_like_whole_ones() {
+        let SetupOutput {
+            pp,
+            issuers,
+            mut t_rgstr,
+            domain,
+            ..
+        } = spirit_setup_osrng(T, N, NUM_ISSUERS, b"test").expect("setup failed");
+        let user = UserSecret::random(OsRng);
+        let ppu = spirit_register_osrng(&domain, &user, &issuers, &pp, EXPIRY, &mut t_rgstr)
+            .expect("registration failed");
+        let mut challenges = ChallengeSet::new(0);
+        let challenge = challenges.issue(NOW, &mut OsRng);
+        let cp: HashSet<_> = (0..4).map(|i| (0, Interval(i))).collect();
+        let stream =
+            spirit_diagnose_iter(&domain, &ppu, user.keys(), &cp, NOW, &challenge, &[], OsRng)
+                .expect("diagnosis failed");
+        let header = TraceReport::deserialize_compressed(&encoded(stream.header())[..]).unwrap();
+        let mut disclosures: Vec<_> = stream.collect::<Result<_, _>>().expect("diagnosis failed");
+        let token = header.token.id();
+
+        let revoked = RevocationList::new();
+        let verify = |chunks: &[&[DiagnosisItem]], nullifiers: &mut HashSet<Nullifier>| {
+            let mut confirmed = HashSet::new();
+            let mut verifier = spirit_verify_stream(
+                &domain,
+                &header,
+                &t_rgstr,
+                &revoked,
+                NOW,
+                nullifiers,
+                &challenges,
+                &mut confirmed,
+            )?;
+            for chunk in chunks {
+                verifier.verify_chunk(chunk);
+            }
+            verifier.finish()
+        };
+
+        // a forged disclosure in the middle chunk is rejected on its own
+        let el_id = std::mem::replace(&mut disclosures[2].el_id, G1G2::random(OsRng));
+        let mut nullifiers = HashSet::new();
+        assert_eq!(
+            verify(
+                &[&disclosures[..2], &disclosures[2..3], &disclosures[3..]],
+                &mut nullifiers
+            ),
+            accepted(&[true, true, false, true])
+        );
+        assert!(nullifiers.contains(&header.nullifier));
+        disclosures[2].el_id = el_id;
+
+        // an ElID that is not in the group rejects the whole report, and leaves the nullifiers
+        disclosures[3].el_id = G1G2::default();
+        let mut nullifiers = HashSet::new();
+        assert_eq!(
+            verify(&[&disclosures[..2], &disclosures[2..]], &mut nullifiers),
+            Err(VerifyRejection::MalformedPoint { token })
+        );
+        assert_eq!(
+            verify(&[], &mut nullifiers),
+            Err(VerifyRejection::NoDisclosures { token })
+        );
+        for disclosure in &mut disclosures {
+            disclosure.el_id = G1G2::random(OsRng);
+        }
+        assert_eq!(
+            verify(&[&disclosures], &mut nullifiers),
+            Err(VerifyRejection::InvalidProof {
+                el_id: disclosures[0].el_id.clone()
+            })
+        );
+        assert!(nullifiers.is_empty());
+    }
+
+    #[test]
+    fn verify_swapped_nullifier() {
+        let domain = DomainConfig::default();
+        let (mut system, issuers) = SpiritSystem::setup(T, N, NUM_ISSUERS).expect("setup failed");
+        let keys = UserKeys::random(OsRng);
+        let ppus: Vec<_> = (0..2)
+            .map(|_| {
+                system
+                    .register(&UserSecret::random(OsRng), &issuers, EXPIRY)
+                    .expect("registration failed")
+            })
+            .collect();
+        let cp = HashSet::from([(0, Interval(3))]);
+        // accepted in NOW and NOW + 1
+        let challenge = system.issue_challenge(NOW);
+        let report = |ppu, report_epoch| {
+            spirit_diagnosis_osrng(&domain, ppu, &keys, &cp, report_epoch, &challenge, &[])
+                .expect("diagnosis failed")
+        };
+
+        // another user's nullifier, or the user's nullifier of another epoch, does not verify
+        // with the report's token
+        let other = report(&ppus[1], NOW);
+        let later = report(&ppus[0], NOW + 1);
+        let token = ppus[0].0.id();
+        let invalid = Err(VerifyRejection::InvalidNullifier { token });
+        for (nullifier, proof) in [
+            (&other.nullifier, &other.nullifier_proof),
+            (&later.nullifier, &later.nullifier_proof),
+        ] {
+            let mut swapped = report(&ppus[0], NOW);
+            swapped.nullifier = nullifier.clone();
+            assert_eq!(
+                system.verify_report(&swapped, NOW, &mut HashSet::new()),
+                invalid
+            );
+            swapped.nullifier_proof = proof.clone();
+            assert_eq!(
+                system.verify_report(&swapped, NOW, &mut HashSet::new()),
+                invalid
+            );
+            assert_eq!(
+                system.verify_reports(&[swapped], NOW, &mut HashSet::new()),
+                [invalid.clone()]
+            );
+        }
+
+        let mut moved = report(&ppus[0], NOW);
+        moved.report_epoch = NOW + 1;
+        assert_eq!(
+            system.verify_report(&moved, NOW + 1, &mut HashSet::new()),
+            invalid
+        );
+
+        assert_eq!(
+            system.verify_reports(&[other, later], NOW, &mut HashSet::new()),
+            [
+                accepted(&[true]),
+                Err(VerifyRejection::OtherEpoch {
+                    token,
+                    report_epoch: NOW + 1
+                })
+            ]
+        );
+    }
+
+    #[test]
+    fn verify_reporting_window() {
+        const DIAGNOSIS: Epoch = 2;
+        let (mut system, issuers) = SpiritConfig::new()
+            .threshold(T)
+            .committee_size(NUM_ISSUERS)
+            .request_size(N)
+            .reporting_window(2)
+            .build(&mut OsRng)
+            .expect("setup failed");
+        system.set_challenge_window(3);
+        let domain = system.domain().clone();
+        assert_eq!(domain.reporting_window, 2);
+        let user = UserSecret::random(OsRng);
+        let ppu = system
+            .register(&user, &issuers, EXPIRY)
+            .expect("registration failed");
+        let cp = HashSet::from([(0, Interval(1))]);
+        let challenge = system.issue_challenge(DIAGNOSIS);
+        let diagnose = || {
+            spirit_diagnosis_osrng(&domain, &ppu, user.keys(), &cp, DIAGNOSIS, &challenge, &[])
+                .expect("diagnosis failed")
+        };
+        let token = ppu.0.id();
+
+        // neither before the diagnosis nor after the last epoch of the window
+        let outside = Err(VerifyRejection::OtherEpoch {
+            token,
+            report_epoch: DIAGNOSIS,
+        });
+        for current_epoch in [DIAGNOSIS - 1, DIAGNOSIS + 3] {
+            assert_eq!(
+                system.verify_report(&diagnose(), current_epoch, &mut HashSet::new()),
+                outside
+            );
+            assert_eq!(
+                system.verify_reports(&[diagnose()], current_epoch, &mut HashSet::new()),
+                [outside.clone()]
+            );
+        }
+
+        // the nullifier proof is bound to the epoch, so a report with a later epoch, or with the
+        // epoch stripped to 0, does not verify within the window of the altered epoch
+        let invalid = Err(VerifyRejection::InvalidNullifier { token });
+        for (report_epoch, current_epoch) in [(DIAGNOSIS + 1, DIAGNOSIS + 1), (0, DIAGNOSIS)] {
+            let mut altered = diagnose();
+            altered.report_epoch = report_epoch;
+            assert_eq!(
+                system.verify_report(&altered, current_epoch, &mut HashSet::new()),
+                invalid
+            );
+        }
+
+        // accepted in the last epoch of the window
+        assert_eq!(
+            system.verify_report(&diagnose(), DIAGNOSIS + 2, &mut HashSet::new()),
+            accepted(&[true])
+        );
+    }
+
+    #[test]
+    fn verify_diagnosis_authorization() {
+        let ha_sk = SecretKey::random(&mut OsRng);
+        let ha_pk = ha_sk.to_public_key();
+        let (mut system, issuers) = SpiritConfig::new()
+            .threshold(T)
+            .committee_size(NUM_ISSUERS)
+            .request_size(N)
+            .diagnosis_authority(ha_pk.clone())
+            .build(&mut OsRng)
+            .expect("setup failed");
+        let domain = system.domain().clone();
+        let cp = HashSet::from([(0, Interval(1))]);
+        let mut diagnosed = Vec::new();
+        for _ in 0..2 {
+            let user = UserSecret::random(OsRng);
+            let ppu = system
+                .register(&user, &issuers, EXPIRY)
+                .expect("registration failed");
+            diagnosed.push((user, ppu));
+        }
+        let diagnose = |system: &mut SpiritSystem,
+                        (user, ppu): &(UserSecret, (Token, StRG)),
+                        authorization| {
+            let challenge = system.issue_challenge(NOW);
+            let mut report =
+                spirit_diagnosis_osrng(&domain, ppu, user.keys(), &cp, NOW, &challenge, &[])
+                    .expect("diagnosis failed");
+            report.authorization = authorization;
+            report
+        };
+        let authorize = |token: &Token, diagnosis_epoch| {
+            let (blinded, blinding) =
+                blind_authorization_tag(&domain, token, diagnosis_epoch, &mut OsRng);
+            assert_ne!(
+                blinded.0,
+                authorization_tag(&domain, token, diagnosis_epoch)
+            );
+            let blind_signature = authorize_diagnosis(&ha_sk, &blinded);
+            unblind_authorization(blinding, &blind_signature, &ha_pk)
+        };
+        let [first, second] = &diagnosed[..] else {
+            unreachable!()
+        };
+        let unauthorized = |(_, ppu): &(UserSecret, (Token, StRG))| {
+            Err(VerifyRejection::Unauthorized { token: ppu.0.id() })
+        };
+
+        // reports without an authorization, or with one of an earlier diagnosis
+        let missing = diagnose(&mut system, first, None);
+        let expired = diagnose(
+            &mut system,
+            first,
+            Some(authorize(&first.1.0, NOW - 1).unwrap()),
+        );
+        for report in [missing, expired] {
+            assert_eq!(
+                system.verify_report(&report, NOW, &mut HashSet::new()),
+                unauthorized(first)
+            );
+            assert_eq!(
+                system.verify_reports(&[report], NOW, &mut HashSet::new()),
+                [unauthorized(first)]
+            );
+        }
+
+        // the honest flow
+        let authorization = authorize(&first.1.0, NOW).expect("authorization failed");
+        let report = diagnose(&mut system, first, Some(authorization.clone()));
+        let mut cf = HashSet::new();
+        assert_eq!(
+            system.verify_report(&report, NOW, &mut cf),
+            accepted(&[true])
+        );
+
+        // a reused authorization is rejected with the nullifier of its report, and in the report
+        // of another token
+        let replayed = diagnose(&mut system, first, Some(authorization.clone()));
+        assert_eq!(
+            system.verify_report(&replayed, NOW, &mut cf),
+            Err(VerifyRejection::ReplayedNullifier {
+                token: first.1.0.id()
+            })
+        );
+        let borrowed = diagnose(&mut system, second, Some(authorization));
+        assert_eq!(
+            system.verify_report(&borrowed, NOW, &mut cf),
+            unauthorized(second)
+        );
+
+        // the prepared key of the authority is replaced for a deployment of another authority
+        let other = domain
+            .clone()
+            .with_diagnosis_authority(SecretKey::random(&mut OsRng).to_public_key());
+        assert_eq!(
+            check_authorization(&other, &replayed),
+            Err(VerifyRejection::Unauthorized {
+                token: first.1.0.id()
+            })
+        );
+        assert_eq!(check_authorization(&domain, &replayed), Ok(()));
+
+        // signatures of another key do not unblind
+        let (blinded, blinding) = blind_authorization_tag(&domain, &second.1.0, NOW, &mut OsRng);
+        let forged = authorize_diagnosis(&SecretKey::random(&mut OsRng), &blinded);
+        assert!(matches!(
+            unblind_authorization(blinding, &forged, &ha_pk),
+            Err(SpiritError::InvalidAuthorization)
+        ));
+    }
+
+    #[test]
+    fn verify_broadcast_attestation() {
+        use crate::registry::RegistrarKeypair;
+
+        let registrar = RegistrarKeypair::random(&mut OsRng);
+        let (mut system, issuers) = SpiritConfig::new()
+            .threshold(T)
+            .committee_size(NUM_ISSUERS)
+            .request_size(N)
+            .anchoring_registrar(registrar.public_key().clone())
+            .build(&mut OsRng)
+            .expect("setup failed");
+        let domain = system.domain().clone();
+        let user = UserSecret::random(OsRng);
+        let ppu = system
+            .register(&user, &issuers, EXPIRY)
+            .expect("registration failed");
+
+        // a batch anchored before the diagnosis, one anchored in its epoch, and the broadcasts
+        // of an epoch that were never anchored
+        let pk_ha = ha_key();
+        let mut t_el = EncounterTable::new();
+        let mut broadcast = |epoch| {
+            let mut batch = BroadcastBatch::new();
+            for i in (0..3).map(Interval) {
+                let (el_id, _) =
+                    spirit_broadcast_osrng(&domain, epoch, i, user.keys(), &pk_ha, &mut t_el);
+                batch.push(epoch, i, &el_id);
+            }
+            batch
+        };
+        let anchor = |batch: BroadcastBatch, registrar: &RegistrarKeypair, epoch| {
+            let anchor = registrar.anchor_broadcasts(batch.root(), batch.len(), epoch);
+            (batch, anchor)
+        };
+        let batches = [
+            anchor(broadcast(0), &registrar, 1),
+            anchor(broadcast(2), &registrar, NOW),
+        ];
+        let unanchored = broadcast(5);
+        assert!(
+            batches
+                .iter()
+                .all(|(_, anchor)| anchor.verify(registrar.public_key()))
+        );
+
+        let diagnose = |system: &mut SpiritSystem, cp: &[(Epoch, Interval)]| {
+            let challenge = system.issue_challenge(NOW);
+            let cp = cp.iter().copied().collect();
+            spirit_diagnosis_osrng(&domain, &ppu, user.keys(), &cp, NOW, &challenge, &[])
+                .expect("diagnosis failed")
+        };
+        let rejected =
+            |report: &TraceReport, (epoch, index): (Epoch, Interval)| -> Result<Accepted, _> {
+                let disclosure = report
+                    .disclosures
+                    .iter()
+                    .find(|disclosure| (disclosure.epoch, disclosure.index) == (epoch, index))
+                    .unwrap();
+                Err(VerifyRejection::Unanchored {
+                    el_id: disclosure.el_id.clone(),
+                })
+            };
+
+        // ElIDs anchored in the epoch of the diagnosis, or never, cannot be attested, and
+        // attestations from a backdated anchor or another registrar's are rejected
+        let mut backdated = batches[1].clone();
+        backdated.1.epoch = 1;
+        let foreign = anchor(unanchored, &RegistrarKeypair::random(&mut OsRng), 1);
+        for ((epoch, index), forged) in [((2, Interval(0)), backdated), ((5, Interval(0)), foreign)]
+        {
+            let mut report = diagnose(&mut system, &[(0, Interval(1)), (epoch, index)]);
+            assert_eq!(
+                report.disclosures.len(),
+                2,
+                "the ElIDs of both epochs are disclosed"
+            );
+            assert_eq!(
+                attest_broadcasts(&report, &batches),
+                Err(SpiritError::UnanchoredBroadcast { epoch, index })
+            );
+            let without = system.verify_report(&report, NOW, &mut HashSet::new());
+            let first = &report.disclosures[0];
+            assert_eq!(without, rejected(&report, (first.epoch, first.index)));
+
+            let attestation = attest_broadcasts(&report, &[batches[0].clone(), forged])
+                .expect("attestation failed");
+            report.attestation = Some(attestation);
+            let expected = rejected(&report, (epoch, index));
+            assert_eq!(
+                system.verify_report(&report, NOW, &mut HashSet::new()),
+                expected
+            );
+            assert_eq!(
+                system.verify_reports(&[report], NOW, &mut HashSet::new()),
+                [expected]
+            );
+        }
+
+        // the honest flow, with ElIDs of the batch anchored before the diagnosis
+        let mut report = diagnose(&mut system, &[(0, Interval(0)), (0, Interval(2))]);
+        let attestation = attest_broadcasts(&report, &batches).expect("attestation failed");
+        assert_eq!(attestation.anchors, [batches[0].1.clone()]);
+        report.attestation = Some(attestation.clone());
+        let mut cf = HashSet::new();
+        assert_eq!(
+            system.verify_report(&report, NOW, &mut cf),
+            accepted(&[true, true])
+        );
+
+        // the attestation does not prove the ElIDs of another report
+        let mut moved = diagnose(&mut system, &[(0, Interval(1)), (2, Interval(1))]);
+        moved.attestation = Some(attestation);
+        let first = &moved.disclosures[0];
+        assert_eq!(
+            system.verify_report(&moved, NOW, &mut HashSet::new()),
+            rejected(&moved, (first.epoch, first.index))
+        );
+    }
+
+    #[test]
+    fn verify_challenge() {
+        let (mut report, mut system, _) = diagnosed_report(&[(0, Interval(1)), (0, Interval(4))]);
+        let challenge = report.challenge.clone();
+        assert!(system.challenges().accepts(&challenge, NOW));
+
+        // the proofs are bound to the challenge they were made for
+        let replayed = system.issue_challenge(NOW);
+        report.challenge = replayed;
+        let invalid = Err(VerifyRejection::InvalidNullifier {
+            token: report.token.id(),
+        });
+        assert_eq!(
+            system.verify_report(&report, NOW, &mut HashSet::new()),
+            invalid
+        );
+        assert_eq!(
+            system.verify_reports(std::slice::from_ref(&report), NOW, &mut HashSet::new()),
+            [invalid]
+        );
+
+        // challenges not issued by the verifier are rejected
+        let (unissued, _, _) = diagnosed_report(&[(0, Interval(1))]);
+        system.t_rgstr.insert(unissued.token.clone());
+        assert!(!system.challenges().accepts(&unissued.challenge, NOW));
+        assert_eq!(
+            system.verify_report(&unissued, NOW, &mut HashSet::new()),
+            Err(VerifyRejection::InvalidChallenge {
+                token: unissued.token.id()
+            })
+        );
+
+        report.challenge = challenge;
+        let mut confirmed = HashSet::new();
+        assert_eq!(
+            system.verify_report(&report, NOW, &mut confirmed),
+            accepted(&[true, true])
+        );
+        assert_eq!(confirmed.len(), 2);
+    }
+
+    #[test]
+    fn challenge_window() {
+        let domain = DomainConfig::default();
+        let (mut system, issuers) = SpiritSystem::setup(T, N, NUM_ISSUERS).expect("setup failed");
+        let user = UserSecret::random(OsRng);
+        let ppu = system
+            .register(&user, &issuers, EXPIRY)
+            .expect("registration failed");
+        assert_eq!(system.challenges().window(), DEFAULT_CHALLENGE_WINDOW);
+
+        let challenge = system.issue_challenge(NOW);
+        let cp = HashSet::from([(0, Interval(2))]);
+        let report = |report_epoch| {
+            spirit_diagnosis_osrng(
+                &domain,
+                &ppu,
+                user.keys(),
+                &cp,
+                report_epoch,
+                &challenge,
+                &[],
+            )
+            .expect("diagnosis failed")
+        };
+        assert!(!system.challenges().accepts(&challenge, NOW - 1));
+        assert!(system.challenges().accepts(&challenge, NOW + 1));
+        assert!(!system.challenges().accepts(&challenge, NOW + 2));
+
+        // a report made too long after the challenge is rejected, unless the window is widened
+        let late = report(NOW + 2);
+        let expired = Err(VerifyRejection::InvalidChallenge { token: ppu.0.id() });
+        assert_eq!(
+            system.verify_report(&late, NOW + 2, &mut HashSet::new()),
+            expired
+        );
+        system.set_challenge_window(2);
+        assert_eq!(
+            system.verify_report(&late, NOW + 2, &mut HashSet::new()),
+            accepted(&[true])
+        );
+
+        system.set_challenge_window(0);
+        assert_eq!(system.challenges().len(), 1);
+        system.purge_expired(NOW).unwrap();
+        assert_eq!(system.challenges().len(), 1);
+        system.purge_expired(NOW + 1).unwrap();
+        assert!(system.challenges().is_empty());
+        assert_eq!(
+            system.verify_report(&report(NOW), NOW, &mut HashSet::new()),
+            expired
+        );
+    }
+
+    #[test]
+    fn verify_with_membership_proof() {
+        let domain = DomainConfig::default();
+        let (mut system, issuers) = SpiritSystem::with_registry(
+            T,
+            N,
+            NUM_ISSUERS,
+            domain.clone(),
+            MerkleRegistry::new(RegistrarKeypair::random(&mut OsRng)),
+        )
+        .expect("setup failed");
+        let vk = system.registry().verifying_key();
+        let keys = UserKeys::random(OsRng);
+
+        let ppu = system
+            .register(&UserSecret::random(OsRng), &issuers, EXPIRY)
+            .expect("registration failed");
+        let cp = HashSet::from([(0, Interval(2)), (0, Interval(6))]);
+        let challenge = system.issue_challenge(NOW);
+        let report = spirit_diagnosis_osrng(&domain, &ppu, &keys, &cp, NOW, &challenge, &[])
+            .expect("diagnosis failed");
+        let root = system.registry().signed_root(NOW);
+        let membership = system.registry().prove(&ppu.0).unwrap();
+        system
+            .register(&UserSecret::random(OsRng), &issuers, EXPIRY)
+            .expect("registration failed");
+        let fresh_root = system.registry().signed_root(NOW);
+        let fresh_membership = system.registry().prove(&ppu.0).unwrap();
+
+        // verdicts and number of confirmed ElIDs
+        let verify = |root: &SignedRoot,
+                      vk: &PublicKey,
+                      membership: &MembershipProof,
+                      latest_root_seq: &mut u64| {
+            let mut confirmed = HashSet::new();
+            let verdicts = spirit_verify_with_proof(
+                &domain,
+                &report,
+                root,
+                vk,
+                latest_root_seq,
+                membership,
+                &RevocationList::new(),
+                NOW,
+                &mut HashSet::new(),
+                system.challenges(),
+                &mut confirmed,
+            );
+            (verdicts, confirmed.len())
+        };
+        let token = ppu.0.id();
+        let mut latest_root_seq = 0;
+        assert_eq!(
+            verify(&root, &vk, &membership, &mut latest_root_seq),
+            (accepted(&[true, true]), 2)
+        );
+        assert_eq!(latest_root_seq, root.seq);
+
+        // the proof is stale once another token has been registered
+        assert_eq!(
+            verify(&fresh_root, &vk, &membership, &mut latest_root_seq),
+            (Err(VerifyRejection::InvalidMembership { token }), 0)
+        );
+        let foreign_vk = MerkleRegistry::new(RegistrarKeypair::random(&mut OsRng)).verifying_key();
+        assert_eq!(
+            verify(
+                &fresh_root,
+                &foreign_vk,
+                &fresh_membership,
+                &mut latest_root_seq
+            ),
+            (Err(VerifyRejection::InvalidRoot { token }), 0)
+        );
+        assert_eq!(
+            verify(&fresh_root, &vk, &fresh_membership, &mut latest_root_seq),
+            (accepted(&[true, true]), 2)
+        );
+
+        // rolling back to the old root and proof is rejected once the fresh root was seen
+        assert!(root.verify(&vk) && membership.verify(&root, &ppu.0));
+        assert_eq!(
+            verify(&root, &vk, &membership, &mut latest_root_seq),
+            (Err(VerifyRejection::InvalidRoot { token }), 0)
+        );
+        assert_eq!(latest_root_seq, fresh_root.seq);
+    }
+
+    #[test]
+    fn verify_with_sharded_registry() {
+        const A: RegistrarId = 0;
+        const B: RegistrarId = 1;
+        let domain = DomainConfig::default();
+        let (mut system, issuers) = SpiritSystem::with_registry(
+            T,
+            N,
+            NUM_ISSUERS,
+            domain.clone(),
+            MerkleRegistry::for_shard(RegistrarKeypair::random(&mut OsRng), B),
+        )
+        .expect("setup failed");
+        let ppu = system
+            .register(&UserSecret::random(OsRng), &issuers, EXPIRY)
+            .expect("registration failed");
+        let cp = HashSet::from([(0, Interval(2))]);
+        let challenge = system.issue_challenge(NOW);
+        let mut report = spirit_diagnosis_osrng(
+            &domain,
+            &ppu,
+            &UserKeys::random(OsRng),
+            &cp,
+            NOW,
+            &challenge,
+            &[],
+        )
+        .expect("diagnosis failed");
+        report.registrar = B;
+
+        let shard_a = MerkleRegistry::for_shard(RegistrarKeypair::random(&mut OsRng), A);
+        let shard_b = system.registry();
+        let (root_a, vk_a) = (shard_a.signed_root(NOW), shard_a.verifying_key());
+        let (root_b, vk_b) = (shard_b.signed_root(NOW), shard_b.verifying_key());
+        assert_eq!((root_a.registrar_id, root_b.registrar_id), (A, B));
+        let membership = shard_b.prove(&ppu.0).unwrap();
+        fn registry(t_rgstr: &MerkleRegistry) -> RegistryHandle<'_> {
+            RegistryHandle::View(t_rgstr.into())
+        }
+        let root = |root, registrar| RegistryHandle::Root {
+            root,
+            registrar,
+            membership: &membership,
+        };
+        let verify = |report: &TraceReport, shards: &HashMap<RegistrarId, RegistryHandle<'_>>| {
+            spirit_verify(
+                &domain,
+                report,
+                shards,
+                &RevocationList::new(),
+                NOW,
+                &mut HashSet::new(),
+                system.challenges(),
+                &mut HashSet::new(),
+            )
+        };
+
+        // accepted with shard B's registry or signed root
+        let both = HashMap::from([(A, registry(&shard_a)), (B, registry(shard_b))]);
+        assert_eq!(verify(&report, &both), accepted(&[true]));
+        let signed = HashMap::from([(B, root(&root_b, &vk_b))]);
+        assert_eq!(verify(&report, &signed), accepted(&[true]));
+
+        // rejected with only shard A's, also when it is taken for B's or names the report's shard
+        let token = ppu.0.id();
+        assert_eq!(
+            verify(&report, &HashMap::from([(A, registry(&shard_a))])),
+            Err(VerifyRejection::UnknownRegistrar {
+                token,
+                registrar: B
+            })
+        );
+        let unregistered = Err(VerifyRejection::Unregistered { token });
+        assert_eq!(
+            verify(&report, &HashMap::from([(B, registry(&shard_a))])),
+            unregistered
+        );
+        assert_eq!(
+            verify(&report, &HashMap::from([(B, root(&root_a, &vk_a))])),
+            Err(VerifyRejection::InvalidRoot { token })
+        );
+        report.registrar = A;
+        assert_eq!(verify(&report, &both), unregistered);
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn parallel_issuance() {
+        let (system, issuers) = SpiritSystem::setup(T, N, NUM_ISSUERS).expect("setup failed");
+        let pp = system.public_parameters();
+        let issuers = &issuers.issuers()[..T];
+        let (strg, cm) = register(&Scalar::rand(&mut OsRng), pp, &mut OsRng).unwrap();
+        let (blind_request, rand) = token_request(&strg, &cm, pp, &mut OsRng).unwrap();
+
+        for blind_tokens in [
+            issue_blind_tokens_sequential(&blind_request, issuers, pp),
+            issue_blind_tokens_parallel(&blind_request, issuers, pp),
+        ] {
+            let blind_tokens = blind_tokens.expect("issuance failed");
+            assert_eq!(blind_tokens.len(), T);
+
+            let token = aggregate_unblind(&blind_tokens, &rand, pp).expect("unblinding failed");
+            let token_proof = prove(&token, &rand, pp, &mut OsRng);
+            assert_eq!(
+                verify(&token, &token_proof, &blind_request, pp, &mut OsRng),
+                Ok(())
+            );
+        }
+    }
+
+    /// Flips the bits of `mask` in the byte selected by `index` of the encoding of `value`, and
+    /// returns the decoded result unless the mutated encoding is rejected.
+    fn mutate<T: CanonicalSerialize + CanonicalDeserialize>(
+        value: &T,
+        index: Index,
+        mask: u8,
+    ) -> Option<T> {
+        let mut bytes = Vec::new();
+        value.serialize_compressed(&mut bytes).unwrap();
+        bytes[index.index(bytes.len())] ^= mask;
+        T::deserialize_compressed(&bytes[..]).ok()
+    }
+
+    proptest! {
+        #[test]
+        fn token_encoding_round_trip(token in arbitrary::token()) {
+            prop_assert_eq!(Token::from_bytes(&token.to_bytes()), Ok(token.clone()));
+            prop_assert_eq!(arbitrary::round_trip(&token).unwrap(), token);
+        }
+
+        #[test]
+        fn prf_proof_round_trip(evaluation in arbitrary::prf_evaluation()) {
+            let proof = arbitrary::round_trip(&evaluation.proof).unwrap();
+            prop_assert_eq!(&proof, &evaluation.proof);
+            prop_assert!(prf_verify(
+                &evaluation.domain,
+                &prf_public_key(&evaluation.domain, &evaluation.k),
+                evaluation.epoch,
+                evaluation.index,
+                &evaluation.el_id,
+                &proof
+            ));
+        }
+
+        #[test]
+        fn prf_deterministic_and_injective(
+            domain in arbitrary::domain(),
+            k in arbitrary::nonzero_scalar(),
+            epoch in any::<Epoch>(),
+            indices in btree_set((0..10_000u64).prop_map(Interval), 2..16),
+        ) {
+            let el_ids: HashSet<_> = indices
+                .iter()
+                .map(|i| {
+                    let el_id = prf_broadcast(&domain, &k, epoch, *i);
+                    assert_eq!(prf_broadcast(&domain, &k, epoch, *i), el_id);
+                    el_id
+                })
+                .collect();
+            prop_assert_eq!(el_ids.len(), indices.len());
+        }
+
+        #[test]
+        fn mutated_prf_proof_fails(
+            evaluation in arbitrary::prf_evaluation(),
+            index in any::<Index>(),
+            mask in 1..=u8::MAX,
+        ) {
+            if let Some(proof) = mutate(&evaluation.proof, index, mask) {
+                prop_assert!(!prf_verify(
+                    &evaluation.domain,
+                    &prf_public_key(&evaluation.domain, &evaluation.k),
+                    evaluation.epoch,
+                    evaluation.index,
+                    &evaluation.el_id,
+                    &proof
+                ));
+            }
+        }
+    }
+
+    proptest! {
+        // generating a report takes several scalar multiplications per disclosure
+        #![proptest_config(ProptestConfig::with_cases(32))]
+
+        #[test]
+        fn trace_report_round_trip(diagnosis in arbitrary::diagnosis()) {
+            let mut bytes = Vec::new();
+            diagnosis.report.serialize_compressed(&mut bytes).unwrap();
+            let decoded = TraceReport::deserialize_compressed(&bytes[..]).unwrap();
+
+            let mut reencoded = Vec::new();
+            decoded.serialize_compressed(&mut reencoded).unwrap();
+            prop_assert_eq!(reencoded, bytes);
+        }
+
+        #[test]
+        fn honest_reports_verify(diagnosis in arbitrary::diagnosis()) {
+            let Diagnosis { domain, ppu, report, challenges } = diagnosis;
+            let t_rgstr = HashSet::from([ppu.0]);
+            let mut cp = HashSet::new();
+            let revoked = RevocationList::new();
+            let mut nullifiers = HashSet::new();
+            let result = spirit_verify(
+                &domain, &report, &t_rgstr, &revoked, 0, &mut nullifiers, &challenges, &mut cp,
+            );
+            prop_assert_eq!(result, accepted(&vec![true; report.disclosures.len()]));
+            prop_assert_eq!(cp.len(), report.disclosures.len());
+        }
+
+        #[test]
+        fn mutated_proof_2_pk_fails(
+            diagnosis in arbitrary::diagnosis(),
+            disclosure in any::<Index>(),
+            index in any::<Index>(),
+            mask in 1..=u8::MAX,
+        ) {
+            let Diagnosis { domain, ppu, mut report, challenges } = diagnosis;
+            let disclosure = disclosure.index(report.disclosures.len());
+            if let Some(proof) = mutate(&report.disclosures[disclosure].proof, index, mask) {
+                report.disclosures[disclosure].proof = proof;
+                let t_rgstr = HashSet::from([ppu.0]);
+                let revoked = RevocationList::new();
+                let mut cp = HashSet::new();
+                let mut nullifiers = HashSet::new();
+                let result = spirit_verify(
+                    &domain, &report, &t_rgstr, &revoked, 0, &mut nullifiers, &challenges, &mut cp,
+                );
+                prop_assert!(!result.is_ok_and(|accepted| accepted.verdicts[disclosure]));
+            }
+        }
+    }
+
+    proptest! {
+        // every case sets up a deployment and runs the issuance
+        #![proptest_config(ProptestConfig::with_cases(4))]
+
+        #[test]
+        fn registered_users_reports_verify(
+            seed in any::<u64>(),
+            cp in btree_set((0..NOW, (0..96u64).prop_map(Interval)), 1..4),
+        ) {
+            let mut rng = ChaCha20Rng::seed_from_u64(seed);
+            let SetupOutput { pp, issuers, mut t_rgstr, domain, .. } =
+                spirit_setup(3, 4, 4, b"proptest", &mut rng).unwrap();
+            let user = UserSecret::random(&mut rng);
+            let ppu =
+                spirit_register(&domain, &user, &issuers, &pp, EXPIRY, &mut t_rgstr, &mut rng)
+                    .unwrap();
+
+            let cp: HashSet<_> = cp.into_iter().collect();
+            let mut challenges = ChallengeSet::new(0);
+            let challenge = challenges.issue(NOW, &mut rng);
+            let report = spirit_diagnosis(
+                &domain,
+                &ppu,
+                user.keys(),
+                None,
+                &cp,
+                NOW,
+                &challenge,
+                &[],
+                &mut rng,
+            )
+            .unwrap();
+            let revoked = RevocationList::new();
+            let mut nullifiers = HashSet::new();
+            let mut confirmed = HashSet::new();
+            let result = spirit_verify(
+                &domain,
+                &report,
+                &t_rgstr,
+                &revoked,
+                NOW,
+                &mut nullifiers,
+                &challenges,
+                &mut confirmed,
+            );
+            prop_assert_eq!(result, accepted(&vec![true; cp.len()]));
+            prop_assert_eq!(confirmed.len(), cp.len());
+        }
+    }
+}
@@ -0,0 +1,240 @@
+//! Activity of a deployment by epoch, for operators who publish day-by-day figures. A
+//! [SpiritSystem](crate::spirit::SpiritSystem) counts its registrations, verified reports, traces
+//! and alarms as it runs, and its [stats](crate::spirit::SpiritSystem::stats) are a
+//! [StatsDigest] of the counts of each epoch. Digests of the shards of a deployment are combined
+//! with [StatsDigest::merge].
+//!
+//! Unlike the [Metrics](crate::telemetry::Metrics) of the `telemetry` feature, a digest holds no
+//! timings and only counts, so it can be published as it is.
+
+use std::{collections::BTreeMap, fmt::Write};
+
+use crate::spirit::Epoch;
+
+/// Counts of one epoch of a [StatsDigest].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EpochStats {
+    pub registrations: u64,
+    pub reports_accepted: u64,
+    pub reports_rejected: u64,
+    pub traces: u64,
+    /// Number of traces that raised an alarm, of every [RiskLevel](crate::exposure::RiskLevel).
+    pub alarms: u64,
+}
+
+impl EpochStats {
+    /// Adds the counts of `other`.
+    pub fn add(&mut self, other: &EpochStats) {
+        self.registrations += other.registrations;
+        self.reports_accepted += other.reports_accepted;
+        self.reports_rejected += other.reports_rejected;
+        self.traces += other.traces;
+        self.alarms += other.alarms;
+    }
+
+    fn write_json(&self, json: &mut String) {
+        // infallible for a String
+        let _ = write!(
+            json,
+            "{{\"registrations\":{},\"reports_accepted\":{},\"reports_rejected\":{},\
+             \"traces\":{},\"alarms\":{}}}",
+            self.registrations,
+            self.reports_accepted,
+            self.reports_rejected,
+            self.traces,
+            self.alarms
+        );
+    }
+}
+
+/// [EpochStats] by epoch, with an entry for every epoch with activity.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StatsDigest {
+    epochs: BTreeMap<Epoch, EpochStats>,
+}
+
+impl StatsDigest {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Counts of `epoch`, which are all 0 for an epoch without activity.
+    pub fn epoch(&self, epoch: Epoch) -> EpochStats {
+        self.epochs.get(&epoch).copied().unwrap_or_default()
+    }
+
+    /// Epochs with activity and their counts, in ascending order.
+    pub fn epochs(&self) -> impl Iterator<Item = (Epoch, &EpochStats)> {
+        self.epochs.iter().map(|(epoch, stats)| (*epoch, stats))
+    }
+
+    /// Counts of all epochs together.
+    pub fn total(&self) -> EpochStats {
+        self.epochs
+            .values()
+            .fold(EpochStats::default(), |mut total, stats| {
+                total.add(stats);
+                total
+            })
+    }
+
+    /// Adds the counts of `other`, e.g. of another shard of the deployment, epoch by epoch.
+    pub fn merge(&mut self, other: &StatsDigest) {
+        for (epoch, stats) in &other.epochs {
+            self.epochs.entry(*epoch).or_default().add(stats);
+        }
+    }
+
+    /// The digest as a JSON object, with the epochs as keys in ascending order. It is the
+    /// encoding of `serde_json` with the `serde` feature.
+    pub fn to_json(&self) -> String {
+        let mut json = String::from("{\"epochs\":{");
+        for (i, (epoch, stats)) in self.epochs.iter().enumerate() {
+            if i > 0 {
+                json.push(',');
+            }
+            let _ = write!(json, "\"{epoch}\":");
+            stats.write_json(&mut json);
+        }
+        json.push_str("}}");
+        json
+    }
+
+    /// Counts of `epoch` for an update.
+    pub(crate) fn at(&mut self, epoch: Epoch) -> &mut EpochStats {
+        self.epochs.entry(epoch).or_default()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashSet;
+
+    use rand::rngs::OsRng;
+
+    use super::*;
+    use crate::{
+        elgamal::DecryptionKey,
+        spirit::{
+            EncounterTable, Interval, IssuerHandle, SpiritSystem, UserSecret,
+            spirit_broadcast_osrng, spirit_diagnosis_osrng,
+        },
+    };
+
+    const EXPIRY: Epoch = 100;
+
+    /// Registers `users` users in `epoch`, verifies and replays a report of each in the next
+    /// epoch, and traces their ElIDs.
+    fn run(system: &mut SpiritSystem, issuers: &IssuerHandle, epoch: Epoch, users: usize) {
+        system.advance_epoch(epoch);
+        let domain = system.domain().clone();
+        let pk_ha = DecryptionKey::random(&mut OsRng).encryption_key();
+        let mut t_el = EncounterTable::new();
+        let mut reports = Vec::new();
+        for _ in 0..users {
+            let user = UserSecret::random(OsRng);
+            let ppu = system
+                .register(&user, issuers, EXPIRY)
+                .expect("registration failed");
+            spirit_broadcast_osrng(&domain, epoch, Interval(0), user.keys(), &pk_ha, &mut t_el);
+            reports.push((user, ppu));
+        }
+        let mut cf = HashSet::new();
+        for (user, ppu) in &reports {
+            let challenge = system.issue_challenge(epoch + 1);
+            let cp = HashSet::from([(epoch, Interval(0))]);
+            let report =
+                spirit_diagnosis_osrng(&domain, ppu, user.keys(), &cp, epoch + 1, &challenge, &[])
+                    .expect("diagnosis failed");
+            assert!(system.verify_report(&report, epoch + 1, &mut cf).is_ok());
+            assert!(system.verify_report(&report, epoch + 1, &mut cf).is_err());
+        }
+        assert!(system.trace(&cf, &t_el, users).alarm);
+    }
+
+    fn stats(
+        registrations: u64,
+        reports_accepted: u64,
+        reports_rejected: u64,
+        traces: u64,
+        alarms: u64,
+    ) -> EpochStats {
+        EpochStats {
+            registrations,
+            reports_accepted,
+            reports_rejected,
+            traces,
+            alarms,
+        }
+    }
+
+    #[test]
+    fn systems_count_their_activity_by_epoch() {
+        let (mut system, issuers) = SpiritSystem::setup(3, 4, 4).expect("setup failed");
+        assert_eq!(system.stats(), StatsDigest::new());
+
+        run(&mut system, &issuers, 0, 2);
+        run(&mut system, &issuers, 4, 1);
+        assert!(
+            !system
+                .trace(&HashSet::new(), &EncounterTable::new(), 1)
+                .alarm
+        );
+
+        let digest = system.stats();
+        assert_eq!(
+            digest.epochs().collect::<Vec<_>>(),
+            [
+                (0, &stats(2, 0, 0, 0, 0)),
+                (1, &stats(0, 2, 2, 1, 1)),
+                (4, &stats(1, 0, 0, 0, 0)),
+                (5, &stats(0, 1, 1, 2, 1)),
+            ]
+        );
+        assert_eq!(digest.epoch(3), EpochStats::default());
+        assert_eq!(digest.total(), stats(3, 3, 3, 3, 2));
+        assert_eq!(
+            digest.to_json(),
+            "{\"epochs\":{\
+             \"0\":{\"registrations\":2,\"reports_accepted\":0,\"reports_rejected\":0,\
+             \"traces\":0,\"alarms\":0},\
+             \"1\":{\"registrations\":0,\"reports_accepted\":2,\"reports_rejected\":2,\
+             \"traces\":1,\"alarms\":1},\
+             \"4\":{\"registrations\":1,\"reports_accepted\":0,\"reports_rejected\":0,\
+             \"traces\":0,\"alarms\":0},\
+             \"5\":{\"registrations\":0,\"reports_accepted\":1,\"reports_rejected\":1,\
+             \"traces\":2,\"alarms\":1}}}"
+        );
+        assert_eq!(StatsDigest::new().to_json(), "{\"epochs\":{}}");
+
+        #[cfg(feature = "serde")]
+        {
+            use crate::bls381_helpers::serde_round_trip;
+
+            assert_eq!(serde_json::to_string(&digest).unwrap(), digest.to_json());
+            assert_eq!(serde_round_trip(&digest), digest);
+        }
+    }
+
+    #[test]
+    fn merged_shards_equal_their_union() {
+        let setup = || SpiritSystem::setup(3, 4, 4).expect("setup failed");
+        let (mut first, first_issuers) = setup();
+        let (mut second, second_issuers) = setup();
+        let (mut union, union_issuers) = setup();
+
+        run(&mut first, &first_issuers, 0, 1);
+        run(&mut second, &second_issuers, 0, 2);
+        run(&mut second, &second_issuers, 3, 1);
+        run(&mut union, &union_issuers, 0, 1);
+        run(&mut union, &union_issuers, 0, 2);
+        run(&mut union, &union_issuers, 3, 1);
+
+        let mut merged = first.stats();
+        merged.merge(&second.stats());
+        assert_eq!(merged, union.stats());
+        assert_eq!(merged.total(), stats(4, 4, 4, 3, 3));
+    }
+}
@@ -0,0 +1,686 @@
+//! Incremental sync of the registrar's registry and the health authority's confirmed ElIDs to
+//! replicas, e.g. verifier nodes or clients. The source numbers its changes in a [SyncLog] and
+//! hands out the changes since a replica's last sync as a [SignedDelta], which [apply_delta]
+//! applies.
+
+use std::collections::HashSet;
+
+use thiserror::Error;
+
+use crate::{
+    constants::{CONFIRMED_DELTA_DST, REGISTRY_DELTA_DST},
+    registry::{
+        KeyChainError, KeyId, RegistrarKeypair, RegistrarKeys, RegistryError, RevocationList,
+        TokenRegistry, verify_registrar_signature,
+    },
+    spirit::{ElID, Epoch, Token, TokenId},
+    tsw::Signature,
+};
+
+/// Append-only log of the changes to a synced set. The `n`-th change has the sequence number
+/// `n`, starting at 1, so the sequence number of a set is the number of its changes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyncLog<T> {
+    entries: Vec<T>,
+}
+
+impl<T> Default for SyncLog<T> {
+    fn default() -> Self {
+        Self {
+            entries: Vec::new(),
+        }
+    }
+}
+
+impl<T: Clone> SyncLog<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sequence number of the last change, 0 if there is none.
+    pub fn seq(&self) -> u64 {
+        self.entries.len() as u64
+    }
+
+    pub(crate) fn push(&mut self, entry: T) {
+        self.entries.push(entry);
+    }
+
+    /// The changes after the sequence number `seq`. A `seq` ahead of the log gives an empty
+    /// delta at [Self::seq].
+    pub fn delta_since(&self, seq: u64) -> Delta<T> {
+        let from = seq.min(self.seq());
+        Delta {
+            from,
+            entries: self.entries[from as usize..].to_vec(),
+        }
+    }
+}
+
+/// The changes of a [SyncLog] after the sequence number `from`, which a replica at a sequence
+/// number of at least `from` can apply.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Delta<T> {
+    pub from: u64,
+    pub entries: Vec<T>,
+}
+
+impl<T> Delta<T> {
+    /// Sequence number of a replica after applying the delta.
+    pub fn to(&self) -> u64 {
+        self.from + self.entries.len() as u64
+    }
+}
+
+/// [Delta] signed by the source at its sequence number [Delta::to] in `epoch`, with the key
+/// `key_id` of its [RegistrarKeyChain](crate::registry::RegistrarKeyChain).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignedDelta<T> {
+    pub delta: Delta<T>,
+    pub epoch: Epoch,
+    pub key_id: KeyId,
+    pub signature: Signature,
+}
+
+/// Change to a [SyncedRegistry].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RegistryChange {
+    Registered(Token),
+    Revoked(TokenId),
+}
+
+pub type RegistryDelta = Delta<RegistryChange>;
+
+impl RegistryDelta {
+    pub fn registered(&self) -> impl Iterator<Item = &Token> {
+        self.entries.iter().filter_map(|change| match change {
+            RegistryChange::Registered(token) => Some(token),
+            RegistryChange::Revoked(_) => None,
+        })
+    }
+
+    pub fn revoked(&self) -> impl Iterator<Item = &TokenId> {
+        self.entries.iter().filter_map(|change| match change {
+            RegistryChange::Registered(_) => None,
+            RegistryChange::Revoked(id) => Some(id),
+        })
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum SyncError {
+    #[error("The delta is not signed by the source.")]
+    InvalidSignature,
+    #[error("The delta ends at change {to}, but the replica is at change {seq}.")]
+    StaleDelta { seq: u64, to: u64 },
+    #[error("The delta starts after change {from}, but the replica is at change {seq}.")]
+    MissingChanges { seq: u64, from: u64 },
+    #[error("Applying a change to the registry failed: {0}")]
+    Registry(#[source] RegistryError),
+    #[error("The delta is signed with an invalid key: {0}")]
+    InvalidKey(#[source] KeyChainError),
+}
+
+/// Set whose changes are numbered in a [SyncLog], so replicas sync with [apply_delta].
+pub trait Synced {
+    type Entry: Clone;
+
+    /// Domain separator of the signatures on deltas of the set.
+    const DOMAIN: &'static [u8];
+
+    /// Appends the canonical encoding of `entry`, which the signatures on deltas cover.
+    fn encode_entry(entry: &Self::Entry, bytes: &mut Vec<u8>);
+
+    fn log(&self) -> &SyncLog<Self::Entry>;
+
+    /// Applies the next change of the source and appends it to [Self::log], also if it does not
+    /// change the set, so the sequence numbers of the source and the replica stay aligned.
+    fn apply_entry(&mut self, entry: Self::Entry) -> Result<(), SyncError>;
+
+    fn seq(&self) -> u64 {
+        self.log().seq()
+    }
+
+    fn delta_since(&self, seq: u64) -> Delta<Self::Entry> {
+        self.log().delta_since(seq)
+    }
+
+    /// [Self::delta_since] signed in `epoch` with the `registrar`'s key, e.g. of the health
+    /// authority for a [ConfirmedSet].
+    fn signed_delta_since(
+        &self,
+        seq: u64,
+        epoch: Epoch,
+        registrar: &RegistrarKeypair,
+    ) -> SignedDelta<Self::Entry> {
+        let delta = self.delta_since(seq);
+        let message = delta_message::<Self>(epoch, &delta);
+        SignedDelta {
+            signature: registrar.sign(Self::DOMAIN, delta.to(), &message),
+            delta,
+            epoch,
+            key_id: registrar.key_id(),
+        }
+    }
+}
+
+/// `epoch || from || entries`, with the sequence number [Delta::to] signed along.
+fn delta_message<S: Synced + ?Sized>(epoch: Epoch, delta: &Delta<S::Entry>) -> Vec<u8> {
+    let mut bytes = epoch.to_le_bytes().to_vec();
+    bytes.extend_from_slice(&delta.from.to_le_bytes());
+    for entry in &delta.entries {
+        S::encode_entry(entry, &mut bytes);
+    }
+    bytes
+}
+
+/// Applies the changes of `signed` that `target` has not applied yet and returns how many were
+/// applied. Deltas not signed by `registrar` are rejected, and so are deltas ending before the
+/// sequence number of `target`, e.g. an old delta served again to roll a replica back. Applying
+/// the latest delta twice or overlapping deltas has no further effect; a delta that starts after
+/// the sequence number of `target` is rejected, as the changes in between are missing. If a
+/// change fails, the changes before it stay applied. With a
+/// [RegistrarKeyChain](crate::registry::RegistrarKeyChain) at the replica's current epoch, the
+/// key of the delta has to be valid in the epoch of the delta and not yet retired.
+pub fn apply_delta<S: Synced>(
+    target: &mut S,
+    signed: SignedDelta<S::Entry>,
+    registrar: &impl RegistrarKeys,
+) -> Result<usize, SyncError> {
+    let delta = signed.delta;
+    let message = delta_message::<S>(signed.epoch, &delta);
+    let registrar = registrar
+        .verifying_key(signed.key_id, signed.epoch)
+        .map_err(SyncError::InvalidKey)?;
+    if !verify_registrar_signature(
+        registrar,
+        S::DOMAIN,
+        delta.to(),
+        &message,
+        &signed.signature,
+    ) {
+        return Err(SyncError::InvalidSignature);
+    }
+    let seq = target.seq();
+    if delta.to() < seq {
+        return Err(SyncError::StaleDelta {
+            seq,
+            to: delta.to(),
+        });
+    }
+    if delta.from > seq {
+        return Err(SyncError::MissingChanges {
+            seq,
+            from: delta.from,
+        });
+    }
+    let skip = (seq - delta.from) as usize;
+    let mut applied = 0;
+    for entry in delta.entries.into_iter().skip(skip) {
+        target.apply_entry(entry)?;
+        applied += 1;
+    }
+    Ok(applied)
+}
+
+/// Registry with its [RevocationList] whose registrations and revocations are numbered, so that
+/// replicas only fetch the changes since their last sync, signed by the registrar. Expired
+/// tokens are purged by every replica on its own, so purging is not a change.
+#[derive(Debug, Clone, Default)]
+pub struct SyncedRegistry<R = HashSet<Token>> {
+    registry: R,
+    revoked: RevocationList,
+    log: SyncLog<RegistryChange>,
+}
+
+impl<R: TokenRegistry> SyncedRegistry<R> {
+    /// Starts the log with the tokens already in `registry`.
+    pub fn new(registry: R) -> Self {
+        let mut log = SyncLog::new();
+        for token in registry.tokens() {
+            log.push(RegistryChange::Registered(token));
+        }
+        Self {
+            registry,
+            revoked: RevocationList::new(),
+            log,
+        }
+    }
+
+    pub fn registry(&self) -> &R {
+        &self.registry
+    }
+
+    /// Revokes `token`. Returns `false` if it was already revoked.
+    pub fn revoke(&mut self, token: &Token) -> bool {
+        let revoked = self.revoked.revoke(token);
+        if revoked {
+            self.log.push(RegistryChange::Revoked(token.id()));
+        }
+        revoked
+    }
+
+    /// Revocation list to pass to [spirit_verify](crate::spirit::spirit_verify).
+    pub fn revocations(&self) -> &RevocationList {
+        &self.revoked
+    }
+}
+
+impl<R: TokenRegistry> TokenRegistry for SyncedRegistry<R> {
+    fn insert(&mut self, token: Token) -> Result<bool, RegistryError> {
+        let inserted = self.registry.insert(token.clone())?;
+        if inserted {
+            self.log.push(RegistryChange::Registered(token));
+        }
+        Ok(inserted)
+    }
+
+    fn contains(&self, token: &Token) -> bool {
+        self.registry.contains(token)
+    }
+
+    fn len(&self) -> usize {
+        self.registry.len()
+    }
+
+    fn tokens(&self) -> Box<dyn Iterator<Item = Token> + '_> {
+        self.registry.tokens()
+    }
+
+    fn purge_expired(&mut self, current_epoch: Epoch) -> Result<usize, RegistryError> {
+        self.registry.purge_expired(current_epoch)
+    }
+}
+
+impl<R: TokenRegistry> Synced for SyncedRegistry<R> {
+    type Entry = RegistryChange;
+
+    const DOMAIN: &'static [u8] = REGISTRY_DELTA_DST;
+
+    fn encode_entry(entry: &RegistryChange, bytes: &mut Vec<u8>) {
+        match entry {
+            RegistryChange::Registered(token) => {
+                bytes.push(0);
+                bytes.extend_from_slice(&token.payload());
+            }
+            RegistryChange::Revoked(id) => {
+                bytes.push(1);
+                bytes.extend_from_slice(id);
+            }
+        }
+    }
+
+    fn log(&self) -> &SyncLog<RegistryChange> {
+        &self.log
+    }
+
+    fn apply_entry(&mut self, entry: RegistryChange) -> Result<(), SyncError> {
+        match &entry {
+            RegistryChange::Registered(token) => {
+                self.registry
+                    .insert(token.clone())
+                    .map_err(SyncError::Registry)?;
+            }
+            RegistryChange::Revoked(id) => {
+                self.revoked.revoke_id(*id);
+            }
+        }
+        self.log.push(entry);
+        Ok(())
+    }
+}
+
+/// The health authority's confirmed ElIDs `cf`, numbered in the order of their confirmation, so
+/// that clients only fetch the ElIDs confirmed since their last sync before [spirit_trace]. The
+/// health authority signs the deltas with a [RegistrarKeypair] of its own. Iterates in the order
+/// of confirmation, so replicas trace with the same results as the source.
+///
+/// [spirit_trace]: crate::spirit::spirit_trace
+#[derive(Debug, Clone, Default)]
+pub struct ConfirmedSet {
+    el_ids: HashSet<ElID>,
+    log: SyncLog<ElID>,
+}
+
+impl ConfirmedSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Confirms `el_id`. Returns `false` if it was already confirmed.
+    pub fn insert(&mut self, el_id: ElID) -> bool {
+        let inserted = self.el_ids.insert(el_id.clone());
+        if inserted {
+            self.log.push(el_id);
+        }
+        inserted
+    }
+
+    pub fn contains(&self, el_id: &ElID) -> bool {
+        self.el_ids.contains(el_id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.el_ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.el_ids.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &ElID> {
+        self.log.entries.iter()
+    }
+}
+
+impl Extend<ElID> for ConfirmedSet {
+    fn extend<I: IntoIterator<Item = ElID>>(&mut self, iter: I) {
+        for el_id in iter {
+            self.insert(el_id);
+        }
+    }
+}
+
+impl<'a> IntoIterator for &'a ConfirmedSet {
+    type Item = &'a ElID;
+    type IntoIter = std::slice::Iter<'a, ElID>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.log.entries.iter()
+    }
+}
+
+impl Synced for ConfirmedSet {
+    type Entry = ElID;
+
+    const DOMAIN: &'static [u8] = CONFIRMED_DELTA_DST;
+
+    fn encode_entry(el_id: &ElID, bytes: &mut Vec<u8>) {
+        bytes.extend_from_slice(&el_id.to_bytes());
+    }
+
+    fn log(&self) -> &SyncLog<ElID> {
+        &self.log
+    }
+
+    fn apply_entry(&mut self, el_id: ElID) -> Result<(), SyncError> {
+        self.el_ids.insert(el_id.clone());
+        self.log.push(el_id);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rand::rngs::OsRng;
+
+    use super::*;
+    use crate::{
+        registry::RegistrarKeyChain,
+        spirit::{
+            Accepted, ChallengeSet, EncounterTable, Interval, SetupOutput, UserKeys, UserSecret,
+            VerifyRejection, spirit_broadcast_osrng, spirit_diagnosis_osrng, spirit_register_osrng,
+            spirit_setup_osrng, spirit_trace, spirit_verify,
+        },
+        tsw::SecretKey,
+    };
+
+    const T: usize = 4;
+    const N: usize = 8;
+    const NUM_ISSUERS: usize = 8;
+
+    #[test]
+    fn synced_registry_verifies_like_the_source() {
+        let SetupOutput {
+            pp,
+            issuers,
+            pk_ha,
+            domain,
+            ..
+        } = spirit_setup_osrng(T, N, NUM_ISSUERS, b"test").expect("setup failed");
+        let registrar = RegistrarKeypair::random(&mut OsRng);
+        let pk = registrar.public_key();
+        let mut source = SyncedRegistry::new(HashSet::new());
+        let register = |t_rgstr: &mut SyncedRegistry| {
+            spirit_register_osrng(
+                &domain,
+                &UserSecret::random(OsRng),
+                &issuers,
+                &pp,
+                100,
+                t_rgstr,
+            )
+            .expect("registration failed")
+        };
+        let first = register(&mut source);
+        let second = register(&mut source);
+
+        let mut replica = SyncedRegistry::new(HashSet::new());
+        let old = source.signed_delta_since(replica.seq(), 0, &registrar);
+        assert_eq!(old.delta.registered().count(), 2);
+        assert_eq!(apply_delta(&mut replica, old.clone(), pk).unwrap(), 2);
+        assert_eq!(apply_delta(&mut replica, old.clone(), pk).unwrap(), 0);
+        assert_eq!(replica.len(), 2);
+
+        let third = register(&mut source);
+        assert!(source.revoke(&second.0));
+        assert!(!source.revoke(&second.0));
+        assert!(matches!(
+            apply_delta(
+                &mut replica,
+                source.signed_delta_since(3, 0, &registrar),
+                pk
+            ),
+            Err(SyncError::MissingChanges { seq: 2, from: 3 })
+        ));
+        let delta = source.signed_delta_since(replica.seq(), 0, &registrar);
+        assert_eq!(delta.delta.revoked().collect::<Vec<_>>(), [&second.0.id()]);
+        assert_eq!(apply_delta(&mut replica, delta, pk).unwrap(), 2);
+        assert_eq!(
+            apply_delta(
+                &mut replica,
+                source.signed_delta_since(1, 0, &registrar),
+                pk
+            )
+            .unwrap(),
+            0
+        );
+        assert_eq!(replica.seq(), source.seq());
+        assert!(replica.contains(&third.0));
+        assert!(source.delta_since(replica.seq()).entries.is_empty());
+
+        let keys = UserKeys::random(OsRng);
+        let mut t_el = EncounterTable::new();
+        spirit_broadcast_osrng(&domain, 0, Interval(3), &keys, &pk_ha, &mut t_el);
+        let mut challenges = ChallengeSet::new(0);
+        let cp = HashSet::from([(0, Interval(3))]);
+        for ppu in [&first, &second, &third] {
+            let challenge = challenges.issue(0, &mut OsRng);
+            let report = spirit_diagnosis_osrng(&domain, ppu, &keys, &cp, 0, &challenge, &[])
+                .expect("diagnosis failed");
+            let verify = |t_rgstr: &SyncedRegistry| {
+                spirit_verify(
+                    &domain,
+                    &report,
+                    t_rgstr,
+                    t_rgstr.revocations(),
+                    0,
+                    &mut HashSet::new(),
+                    &challenges,
+                    &mut HashSet::new(),
+                )
+            };
+            let result = verify(&source);
+            if ppu.0 == second.0 {
+                assert_eq!(
+                    result,
+                    Err(VerifyRejection::Revoked {
+                        token: second.0.id()
+                    })
+                );
+            } else {
+                assert_eq!(
+                    result,
+                    Ok(Accepted {
+                        verdicts: vec![true]
+                    })
+                );
+            }
+            assert_eq!(verify(&replica), result);
+        }
+    }
+
+    #[test]
+    fn rolled_back_and_forged_deltas_are_rejected() {
+        let registrar = RegistrarKeypair::random(&mut OsRng);
+        let pk = registrar.public_key();
+        let mut source = ConfirmedSet::new();
+        source.extend((0..2).map(|_| ElID::random(OsRng)));
+        let old = source.signed_delta_since(0, 0, &registrar);
+        source.insert(ElID::random(OsRng));
+
+        let mut replica = ConfirmedSet::new();
+        assert_eq!(apply_delta(&mut replica, old.clone(), pk).unwrap(), 2);
+        let delta = source.signed_delta_since(replica.seq(), 0, &registrar);
+        assert_eq!(apply_delta(&mut replica, delta, pk).unwrap(), 1);
+
+        // the old delta is validly signed, but ends before the replica's sequence number
+        assert!(matches!(
+            apply_delta(&mut replica, old.clone(), pk),
+            Err(SyncError::StaleDelta { seq: 3, to: 2 })
+        ));
+        let mut extended = old.clone();
+        extended.delta.entries.push(ElID::random(OsRng));
+        assert!(matches!(
+            apply_delta(&mut replica, extended, pk),
+            Err(SyncError::InvalidSignature)
+        ));
+        let other = RegistrarKeypair::random(&mut OsRng);
+        assert!(matches!(
+            apply_delta(&mut replica, source.signed_delta_since(0, 0, &other), pk),
+            Err(SyncError::InvalidSignature)
+        ));
+        // deltas of the registry are signed under another domain
+        let registry_delta =
+            SyncedRegistry::<HashSet<Token>>::default().signed_delta_since(0, 0, &registrar);
+        let relabelled = SignedDelta {
+            delta: Delta {
+                from: 0,
+                entries: Vec::new(),
+            },
+            epoch: 0,
+            key_id: 0,
+            signature: registry_delta.signature,
+        };
+        assert!(matches!(
+            apply_delta(&mut ConfirmedSet::new(), relabelled, pk),
+            Err(SyncError::InvalidSignature)
+        ));
+        // the epoch is signed along
+        let redated = SignedDelta {
+            epoch: 1,
+            ..source.signed_delta_since(0, 0, &registrar)
+        };
+        assert!(matches!(
+            apply_delta(&mut ConfirmedSet::new(), redated, pk),
+            Err(SyncError::InvalidSignature)
+        ));
+        assert_eq!(replica.seq(), 3);
+    }
+
+    #[test]
+    fn deltas_of_retired_keys_are_rejected() {
+        let first = RegistrarKeypair::random(&mut OsRng);
+        let mut chain = RegistrarKeyChain::new(&first, 0, 1);
+        let second = chain.rotate(SecretKey::random(&mut OsRng), 5).unwrap();
+        let mut source = ConfirmedSet::new();
+        source.extend((0..3).map(|_| ElID::random(OsRng)));
+
+        // deltas of the retired key from before the end of the overlap still apply
+        let mut replica = ConfirmedSet::new();
+        let old = source.signed_delta_since(0, 4, &first);
+        assert_eq!(apply_delta(&mut replica, old, &chain.at(4)).unwrap(), 3);
+        source.insert(ElID::random(OsRng));
+        let overlap = source.signed_delta_since(replica.seq(), 5, &first);
+        assert_eq!(
+            apply_delta(&mut replica.clone(), overlap.clone(), &chain.at(5)).unwrap(),
+            1
+        );
+        let retired = source.signed_delta_since(replica.seq(), 6, &first);
+        assert!(matches!(
+            apply_delta(&mut replica, retired, &chain.at(6)),
+            Err(SyncError::InvalidKey(KeyChainError::RetiredKey {
+                key_id: 0,
+                valid_to: 6
+            }))
+        ));
+        // nor can the retired key date its deltas back into the overlap
+        assert!(matches!(
+            apply_delta(&mut replica, overlap, &chain.at(6)),
+            Err(SyncError::InvalidKey(KeyChainError::RetiredKey {
+                key_id: 0,
+                ..
+            }))
+        ));
+        let delta = source.signed_delta_since(replica.seq(), 6, &second);
+        assert_eq!(delta.key_id, 1);
+        assert_eq!(apply_delta(&mut replica, delta, &chain.at(6)).unwrap(), 1);
+
+        let unknown = SignedDelta {
+            key_id: 2,
+            ..source.signed_delta_since(replica.seq(), 6, &second)
+        };
+        assert!(matches!(
+            apply_delta(&mut replica, unknown, &chain.at(6)),
+            Err(SyncError::InvalidKey(KeyChainError::UnknownKey {
+                key_id: 2
+            }))
+        ));
+        assert_eq!(replica.seq(), source.seq());
+    }
+
+    #[test]
+    fn confirmed_sets_trace_like_the_source() {
+        let SetupOutput { pk_ha, domain, .. } =
+            spirit_setup_osrng(T, N, NUM_ISSUERS, b"test").expect("setup failed");
+        let health_authority = RegistrarKeypair::random(&mut OsRng);
+        let pk = health_authority.public_key();
+        let keys = UserKeys::random(OsRng);
+        let mut t_el = EncounterTable::new();
+        let el_ids: Vec<_> = (0..4)
+            .map(|i| spirit_broadcast_osrng(&domain, 0, Interval(i), &keys, &pk_ha, &mut t_el).0)
+            .collect();
+
+        let mut source = ConfirmedSet::new();
+        source.extend(el_ids[..2].iter().cloned());
+        source.insert(ElID::random(OsRng));
+        assert!(!source.insert(el_ids[0].clone()));
+
+        let mut replica = ConfirmedSet::new();
+        let delta = source.signed_delta_since(0, 0, &health_authority);
+        assert_eq!(apply_delta(&mut replica, delta.clone(), pk).unwrap(), 3);
+        assert_eq!(apply_delta(&mut replica, delta, pk).unwrap(), 0);
+
+        source.insert(el_ids[3].clone());
+        let mut stale = ConfirmedSet::new();
+        assert!(matches!(
+            apply_delta(
+                &mut stale,
+                source.signed_delta_since(source.seq() - 1, 0, &health_authority),
+                pk
+            ),
+            Err(SyncError::MissingChanges { seq: 0, from: 3 })
+        ));
+        let delta = source.signed_delta_since(replica.seq(), 0, &health_authority);
+        assert_eq!(apply_delta(&mut replica, delta, pk).unwrap(), 1);
+
+        assert_eq!(replica.len(), source.len());
+        assert!(replica.iter().eq(source.iter()));
+        assert!(!replica.contains(&el_ids[2]));
+        assert_eq!(
+            spirit_trace(&replica, &t_el, 3),
+            spirit_trace(&source, &t_el, 3)
+        );
+        assert!(spirit_trace(&replica, &t_el, 3).alarm);
+    }
+}
@@ -0,0 +1,364 @@
+//! Instrumentation of a deployment with the `telemetry` feature, which requires `std`.
+//!
+//! [spirit_register](crate::spirit::spirit_register), [spirit_verify], [spirit_verify_batch] and
+//! [spirit_trace](crate::spirit::spirit_trace) run in `tracing` spans at the debug level, and
+//! emit events with the outcome of each verified report and trace. The spans and events only
+//! carry the fields of [FIELDS]: counts, epochs and the [reasons](VerifyRejection::reason) of
+//! rejections, but never scalars, keys, tokens or ElIDs.
+//!
+//! A [SpiritSystem](crate::spirit::SpiritSystem) also counts its registrations, verifications and
+//! traces in [Metrics], whose [snapshot](Metrics::snapshot) can be scraped while the system is in
+//! use.
+//!
+//! [spirit_verify]: crate::spirit::spirit_verify
+//! [spirit_verify_batch]: crate::spirit::spirit_verify_batch
+
+use std::{
+    collections::BTreeMap,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use crate::spirit::{Accepted, TraceResult, VerifyRejection};
+
+/// Names of all fields of the spans and events of this crate.
+pub const FIELDS: &[&str] = &[
+    "message",
+    "accepted",
+    "alarm",
+    "attributes",
+    "confirmed",
+    "disclosures",
+    "epoch",
+    "error",
+    "expiry",
+    "exposure_limit",
+    "matches",
+    "reason",
+    "rejected",
+    "reports",
+];
+
+/// Counters of a [SpiritSystem](crate::spirit::SpiritSystem), updated by its methods.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    registrations: AtomicU64,
+    failed_registrations: AtomicU64,
+    accepted_reports: AtomicU64,
+    rejected_reports: [AtomicU64; VerifyRejection::REASONS.len()],
+    batches: AtomicU64,
+    batched_reports: AtomicU64,
+    traces: AtomicU64,
+    registration_nanos: AtomicU64,
+    verification_nanos: AtomicU64,
+    trace_nanos: AtomicU64,
+}
+
+/// Values of the [Metrics] at one point in time.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct MetricsSnapshot {
+    pub registrations: u64,
+    pub failed_registrations: u64,
+    pub accepted_reports: u64,
+    /// Number of rejected reports by the [reason](VerifyRejection::reason) of their rejection,
+    /// with an entry for every reason.
+    pub rejected_reports: BTreeMap<&'static str, u64>,
+    pub batches: u64,
+    /// Number of reports verified in batches, which are also counted as accepted or rejected.
+    pub batched_reports: u64,
+    pub traces: u64,
+    /// Time spent in registrations, including the ones that failed.
+    pub registration_time: Duration,
+    /// Time spent in verifications of single reports and of batches.
+    pub verification_time: Duration,
+    pub trace_time: Duration,
+}
+
+impl MetricsSnapshot {
+    pub fn rejected(&self) -> u64 {
+        self.rejected_reports.values().sum()
+    }
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        let load = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+        let time = |nanos: &AtomicU64| Duration::from_nanos(load(nanos));
+        MetricsSnapshot {
+            registrations: load(&self.registrations),
+            failed_registrations: load(&self.failed_registrations),
+            accepted_reports: load(&self.accepted_reports),
+            rejected_reports: VerifyRejection::REASONS
+                .into_iter()
+                .zip(self.rejected_reports.iter().map(load))
+                .collect(),
+            batches: load(&self.batches),
+            batched_reports: load(&self.batched_reports),
+            traces: load(&self.traces),
+            registration_time: time(&self.registration_nanos),
+            verification_time: time(&self.verification_nanos),
+            trace_time: time(&self.trace_nanos),
+        }
+    }
+
+    pub(crate) fn record_registration(&self, registered: bool, elapsed: Duration) {
+        if registered {
+            increment(&self.registrations, 1);
+        } else {
+            increment(&self.failed_registrations, 1);
+        }
+        add_time(&self.registration_nanos, elapsed);
+    }
+
+    pub(crate) fn record_verification(
+        &self,
+        verified: &Result<Accepted, VerifyRejection>,
+        elapsed: Duration,
+    ) {
+        self.count_verification(verified);
+        add_time(&self.verification_nanos, elapsed);
+    }
+
+    pub(crate) fn record_batch(
+        &self,
+        verified: &[Result<Accepted, VerifyRejection>],
+        elapsed: Duration,
+    ) {
+        verified
+            .iter()
+            .for_each(|verified| self.count_verification(verified));
+        increment(&self.batches, 1);
+        increment(&self.batched_reports, verified.len() as u64);
+        add_time(&self.verification_nanos, elapsed);
+    }
+
+    pub(crate) fn record_trace(&self, elapsed: Duration) {
+        increment(&self.traces, 1);
+        add_time(&self.trace_nanos, elapsed);
+    }
+
+    fn count_verification(&self, verified: &Result<Accepted, VerifyRejection>) {
+        match verified {
+            Ok(_) => increment(&self.accepted_reports, 1),
+            Err(rejection) => increment(&self.rejected_reports[rejection.reason_index()], 1),
+        }
+    }
+}
+
+fn increment(counter: &AtomicU64, n: u64) {
+    counter.fetch_add(n, Ordering::Relaxed);
+}
+
+fn add_time(nanos: &AtomicU64, elapsed: Duration) {
+    increment(nanos, elapsed.as_nanos().try_into().unwrap_or(u64::MAX));
+}
+
+/// Emits the outcome of a verified report.
+pub(crate) fn verified(verified: &Result<Accepted, VerifyRejection>) {
+    match verified {
+        Ok(accepted) => tracing::debug!(
+            accepted = true,
+            disclosures = accepted.verdicts.len(),
+            confirmed = accepted
+                .verdicts
+                .iter()
+                .filter(|accepted| **accepted)
+                .count(),
+            "verified report"
+        ),
+        Err(rejection) => tracing::debug!(
+            accepted = false,
+            reason = rejection.reason(),
+            "verified report"
+        ),
+    }
+}
+
+/// Emits the outcome of a verified batch of reports, after the one of each report.
+pub(crate) fn verified_batch(verified: &[Result<Accepted, VerifyRejection>]) {
+    verified.iter().for_each(self::verified);
+    let accepted = verified.iter().filter(|verified| verified.is_ok()).count();
+    tracing::debug!(
+        accepted,
+        rejected = verified.len() - accepted,
+        "verified batch"
+    );
+}
+
+/// Emits the outcome of a trace.
+pub(crate) fn traced(result: &TraceResult) {
+    tracing::debug!(
+        matches = result.count,
+        alarm = result.alarm,
+        "traced confirmed ElIDs"
+    );
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        collections::{BTreeSet, HashSet},
+        sync::{Arc, Mutex},
+    };
+
+    use rand::rngs::OsRng;
+    use tracing::{
+        Event, Metadata, Subscriber,
+        field::FieldSet,
+        span::{Attributes, Id, Record},
+    };
+
+    use super::*;
+    use crate::{
+        elgamal::DecryptionKey,
+        spirit::{
+            DomainConfig, EncounterTable, Epoch, Interval, SpiritSystem, UserSecret,
+            spirit_broadcast_osrng, spirit_diagnosis_osrng,
+        },
+    };
+
+    const T: usize = 4;
+    const N: usize = 8;
+    const NUM_ISSUERS: usize = 8;
+    const NOW: Epoch = 10;
+    const EXPIRY: Epoch = 100;
+
+    /// Collects the names of the fields of all spans and events, and the names of the spans
+    /// with the names of the spans they were opened in.
+    #[derive(Clone, Default)]
+    struct FieldRecorder {
+        names: Arc<Mutex<BTreeSet<&'static str>>>,
+        spans: Arc<Mutex<Vec<&'static str>>>,
+        entered: Arc<Mutex<Vec<u64>>>,
+        nested: Arc<Mutex<Vec<(&'static str, &'static str)>>>,
+    }
+
+    impl FieldRecorder {
+        fn add(&self, fields: &FieldSet) {
+            let mut names = self.names.lock().unwrap();
+            names.extend(fields.iter().map(|field| field.name()));
+        }
+    }
+
+    impl Subscriber for FieldRecorder {
+        fn enabled(&self, _: &Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, span: &Attributes<'_>) -> Id {
+            self.add(span.metadata().fields());
+            let mut spans = self.spans.lock().unwrap();
+            let name = span.metadata().name();
+            if let Some(parent) = self.entered.lock().unwrap().last() {
+                let parent = spans[*parent as usize - 1];
+                self.nested.lock().unwrap().push((parent, name));
+            }
+            spans.push(name);
+            Id::from_u64(spans.len() as u64)
+        }
+
+        fn record(&self, _: &Id, _: &Record<'_>) {}
+
+        fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+        fn event(&self, event: &Event<'_>) {
+            self.add(event.metadata().fields());
+        }
+
+        fn enter(&self, span: &Id) {
+            self.entered.lock().unwrap().push(span.into_u64());
+        }
+
+        fn exit(&self, _: &Id) {
+            self.entered.lock().unwrap().pop();
+        }
+    }
+
+    #[test]
+    fn metrics_count_the_operations_of_a_system() {
+        let domain = DomainConfig::default();
+        let (mut system, issuers) = SpiritSystem::setup(T, N, NUM_ISSUERS).expect("setup failed");
+        let user = UserSecret::random(OsRng);
+        let ppu = system
+            .register(&user, &issuers, EXPIRY)
+            .expect("registration failed");
+        assert!(system.register(&user, &issuers, EXPIRY).is_err());
+
+        let mut t_el = EncounterTable::new();
+        let pk_ha = DecryptionKey::random(&mut OsRng).encryption_key();
+        spirit_broadcast_osrng(&domain, 0, Interval(2), user.keys(), &pk_ha, &mut t_el);
+        let challenge = system.issue_challenge(NOW);
+        let cp = HashSet::from([(0, Interval(2))]);
+        let report = spirit_diagnosis_osrng(&domain, &ppu, user.keys(), &cp, NOW, &challenge, &[])
+            .expect("diagnosis failed");
+
+        let mut cf = HashSet::new();
+        assert!(system.verify_report(&report, NOW, &mut cf).is_ok());
+        // replayed, alone and in a batch
+        assert!(system.verify_report(&report, NOW, &mut cf).is_err());
+        let batch = system.verify_reports(&[report.clone(), report], NOW, &mut cf);
+        assert!(batch.iter().all(Result::is_err));
+        assert!(system.trace(&cf, &t_el, 1).alarm);
+
+        let snapshot = system.metrics().snapshot();
+        assert_eq!(snapshot.registrations, 1);
+        assert_eq!(snapshot.failed_registrations, 1);
+        assert_eq!(snapshot.accepted_reports, 1);
+        assert_eq!(snapshot.rejected_reports["replayed_nullifier"], 3);
+        assert_eq!(snapshot.rejected(), 3);
+        assert_eq!(
+            snapshot.rejected_reports.len(),
+            VerifyRejection::REASONS.len()
+        );
+        assert_eq!((snapshot.batches, snapshot.batched_reports), (1, 2));
+        assert_eq!(snapshot.traces, 1);
+        assert!(snapshot.registration_time > Duration::ZERO);
+        assert!(snapshot.verification_time > Duration::ZERO);
+    }
+
+    #[test]
+    fn only_allowed_fields_are_emitted() {
+        let recorder = FieldRecorder::default();
+        tracing::subscriber::with_default(recorder.clone(), || {
+            let domain = DomainConfig::default();
+            let (mut system, issuers) =
+                SpiritSystem::setup(T, N, NUM_ISSUERS).expect("setup failed");
+            let user = UserSecret::random(OsRng);
+            let ppu = system
+                .register(&user, &issuers, EXPIRY)
+                .expect("registration failed");
+            let mut t_el = EncounterTable::new();
+            let pk_ha = DecryptionKey::random(&mut OsRng).encryption_key();
+            spirit_broadcast_osrng(&domain, 0, Interval(2), user.keys(), &pk_ha, &mut t_el);
+            let challenge = system.issue_challenge(NOW);
+            let cp = HashSet::from([(0, Interval(2))]);
+            let report =
+                spirit_diagnosis_osrng(&domain, &ppu, user.keys(), &cp, NOW, &challenge, &[])
+                    .expect("diagnosis failed");
+
+            let mut cf = HashSet::new();
+            system.verify_report(&report, NOW, &mut cf).unwrap();
+            system.verify_report(&report, NOW, &mut cf).unwrap_err();
+            system.verify_reports(&[report], NOW, &mut cf);
+            system.trace(&cf, &t_el, 1);
+        });
+
+        let names = recorder.names.lock().unwrap();
+        assert!(names.iter().all(|name| FIELDS.contains(name)), "{names:?}");
+        for name in ["accepted", "reason", "reports", "matches"] {
+            assert!(names.contains(name), "{name} is not emitted");
+        }
+
+        // spans nested in each other have different names, e.g. the registration of a system
+        let nested = recorder.nested.lock().unwrap();
+        assert!(nested.contains(&("spirit_system_register", "spirit_register")));
+        assert!(
+            nested.iter().all(|(parent, child)| parent != child),
+            "{nested:?}"
+        );
+    }
+}
@@ -0,0 +1,489 @@
+use std::{collections::HashSet, mem};
+
+use ark_ff::UniformRand;
+use rand::{SeedableRng, seq::SliceRandom};
+use rand_chacha::ChaCha20Rng;
+
+use crate::{
+    G1G2, Scalar,
+    atact::{Issuer, PublicParameters, StRG, tissue},
+    elgamal::Ciphertext,
+    pedersen::Commitment,
+    spirit::{
+        ChallengeSet, DomainConfig, ElID, EncounterRecord, EncounterTable, Epoch, Interval,
+        SetupOutput, SpiritError, Token, TraceReport, UserSecret, spirit_diagnosis,
+        spirit_register, spirit_register_begin, spirit_register_finish, spirit_setup,
+    },
+    tsw::Signature,
+};
+
+/// A deployment set up by [spirit_setup], with all randomness derived from a seed so that
+/// benchmarks and tests are reproducible. `challenges` accepts a challenge in the epoch it is
+/// issued in.
+pub struct Deployment {
+    pub pp: PublicParameters,
+    pub issuers: Vec<Issuer>,
+    pub domain: DomainConfig,
+    pub t_rgstr: HashSet<Token>,
+    pub challenges: ChallengeSet,
+}
+
+impl Deployment {
+    pub fn new(t: usize, n: usize, num_issuers: usize, seed: u64) -> Result<Self, SpiritError> {
+        let mut rng = ChaCha20Rng::seed_from_u64(seed);
+        let SetupOutput {
+            pp,
+            issuers,
+            t_rgstr,
+            domain,
+            ..
+        } = spirit_setup(t, n, num_issuers, b"testkit", &mut rng)?;
+        Ok(Self {
+            pp,
+            issuers,
+            domain,
+            t_rgstr,
+            challenges: ChallengeSet::new(0),
+        })
+    }
+
+    /// Registers the user derived from `seed` with a token that expires at the start of epoch
+    /// `expiry`, and returns the user and its registration.
+    pub fn register_user(
+        &mut self,
+        expiry: Epoch,
+        seed: u64,
+    ) -> Result<(UserSecret, (Token, StRG)), SpiritError> {
+        let mut rng = ChaCha20Rng::seed_from_u64(seed);
+        let user = UserSecret::random(&mut rng);
+        let ppu = spirit_register(
+            &self.domain,
+            &user,
+            &self.issuers,
+            &self.pp,
+            expiry,
+            &mut self.t_rgstr,
+            &mut rng,
+        )?;
+        Ok((user, ppu))
+    }
+
+    /// Adds `num_tokens` tokens held by no user to the registry, e.g. to measure lookups in a
+    /// registry of realistic size. The tokens are random points and are not signed.
+    pub fn populate_registry(&mut self, num_tokens: usize, expiry: Epoch, seed: u64) {
+        let mut rng = ChaCha20Rng::seed_from_u64(seed);
+        self.t_rgstr.extend((0..num_tokens).map(|_| {
+            Token(
+                Commitment(G1G2::random(&mut rng)),
+                Signature(G1G2::random(&mut rng)),
+                expiry,
+            )
+        }));
+    }
+
+    /// Adds the tokens of `num_users` users to the registry without running the issuance, e.g.
+    /// to get many reporting users cheaply, and returns the users and their registrations. The
+    /// tokens are random points and are not signed.
+    pub fn unsigned_users(
+        &mut self,
+        num_users: usize,
+        expiry: Epoch,
+        seed: u64,
+    ) -> Vec<(UserSecret, (Token, StRG))> {
+        let mut rng = ChaCha20Rng::seed_from_u64(seed);
+        (0..num_users)
+            .map(|_| {
+                let id_u = Scalar::rand(&mut rng);
+                let user = UserSecret::new(id_u, Scalar::rand(&mut rng));
+                let (cm, opening) =
+                    Commitment::commit_with_randomness(&id_u, &Scalar::rand(&mut rng));
+                let token =
+                    Token::new(&self.domain, &cm, Signature(G1G2::random(&mut rng)), expiry);
+                self.t_rgstr.insert(token.clone());
+                (
+                    user,
+                    (
+                        token,
+                        StRG {
+                            a: id_u,
+                            r: opening.r,
+                            attributes: Vec::new(),
+                        },
+                    ),
+                )
+            })
+            .collect()
+    }
+
+    /// One trace report of each of `users`, submitted in `epoch` for a challenge issued by
+    /// `challenges`. The `i`-th report discloses the ElID of interval `i` of `epoch`.
+    pub fn trace_reports(
+        &mut self,
+        users: &[(UserSecret, (Token, StRG))],
+        epoch: Epoch,
+        seed: u64,
+    ) -> Result<Vec<TraceReport>, SpiritError> {
+        let mut rng = ChaCha20Rng::seed_from_u64(seed);
+        users
+            .iter()
+            .enumerate()
+            .map(|(i, (user, ppu))| {
+                let cp = HashSet::from([(epoch, Interval::from(i))]);
+                let challenge = self.challenges.issue(epoch, &mut rng);
+                spirit_diagnosis(
+                    &self.domain,
+                    ppu,
+                    user.keys(),
+                    None,
+                    &cp,
+                    epoch,
+                    &challenge,
+                    &[],
+                    &mut rng,
+                )
+            })
+            .collect()
+    }
+
+    /// Like [Self::register_user], but the token's signature is unblinded from the blind tokens
+    /// of the first `num_signers` issuers only, the last of which stands in for the missing
+    /// ones. The token is a forgery for tests: the registrar is skipped, so it is not added to
+    /// the registry. Panics if `num_signers` is 0.
+    pub fn register_with_signers(
+        &self,
+        num_signers: usize,
+        expiry: Epoch,
+        seed: u64,
+    ) -> Result<(UserSecret, (Token, StRG)), SpiritError> {
+        let mut rng = ChaCha20Rng::seed_from_u64(seed);
+        let user = UserSecret::random(&mut rng);
+        let (blind_request, pending) =
+            spirit_register_begin(&self.domain, &user.id_u, &self.pp, expiry, &mut rng)?;
+        let mut blind_tokens = self.issuers[..num_signers]
+            .iter()
+            .enumerate()
+            .map(|(issuer_index, issuer)| {
+                tissue(&blind_request, issuer, &self.pp).map_err(|source| {
+                    SpiritError::IssuerRejected {
+                        issuer_index,
+                        source,
+                    }
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let last = blind_tokens[num_signers - 1].clone();
+        blind_tokens.resize(self.pp.t, last);
+
+        let (ppu, _, _) =
+            spirit_register_finish(&self.domain, pending, &blind_tokens, &self.pp, &mut rng)?;
+        Ok((user, ppu))
+    }
+
+    /// A forgery for tests: a token with the issuers' signature and expiry of `ppu`'s token, but
+    /// on a commitment to another identity, derived from `seed`. The returned registration
+    /// opens the commitment, so the proofs of its trace reports verify. The token is not added
+    /// to the registry.
+    pub fn forge_identity(&self, ppu: &(Token, StRG), seed: u64) -> (UserSecret, (Token, StRG)) {
+        let mut rng = ChaCha20Rng::seed_from_u64(seed);
+        let user = UserSecret::random(&mut rng);
+        let (cm, opening) = Commitment::commit_with_randomness(&user.id_u, &Scalar::rand(&mut rng));
+        let (token, _) = ppu;
+        let forged = Token::new(&self.domain, &cm, token.1.clone(), token.2);
+        let strg = StRG {
+            a: user.id_u,
+            r: opening.r,
+            attributes: Vec::new(),
+        };
+        (user, (forged, strg))
+    }
+}
+
+/// Swaps the [Proof2PK](crate::pedersen::Proof2PK)s of the disclosures of `a` and `b`, pairwise
+/// in disclosure order, e.g. to pair a valid token with a proof for another commitment. The PRF
+/// proofs, which do not involve the token, are kept.
+pub fn swap_proofs(a: &mut TraceReport, b: &mut TraceReport) {
+    for (a, b) in a.disclosures.iter_mut().zip(&mut b.disclosures) {
+        mem::swap(&mut a.proof, &mut b.proof);
+    }
+}
+
+/// Swaps the disclosures of `a` and `b` with the keys of their epochs, so that each report
+/// presents the other user's ElIDs and proofs with its own token and nullifier.
+pub fn swap_disclosures(a: &mut TraceReport, b: &mut TraceReport) {
+    mem::swap(&mut a.disclosures, &mut b.disclosures);
+    mem::swap(&mut a.cm_prv, &mut b.cm_prv);
+    mem::swap(&mut a.prf_pk, &mut b.prf_pk);
+    mem::swap(&mut a.device_keys, &mut b.device_keys);
+}
+
+/// Encounter table with `num_entries` distinct ElIDs recorded at the indices
+/// `0..num_entries` of `epoch`. The ElIDs are derived by repeated addition of a random step,
+/// which is much cheaper than the PRF for large tables, so they do not belong to any user. For the
+/// same reason, the encrypted secrets are placeholders that do not decrypt to the secrets.
+pub fn encounter_table(num_entries: usize, epoch: Epoch, seed: u64) -> EncounterTable {
+    let mut rng = ChaCha20Rng::seed_from_u64(seed);
+    let step = G1G2::random(&mut rng);
+    let mut el_id = G1G2::random(&mut rng);
+
+    let mut t_el = EncounterTable::with_capacity(num_entries);
+    for index in 0..num_entries as u64 {
+        el_id = &el_id + &step;
+        t_el.insert(
+            &el_id,
+            EncounterRecord {
+                secret: Scalar::from(index),
+                encrypted_secret: Ciphertext {
+                    c1: step.clone(),
+                    c2: el_id.clone(),
+                },
+                weight: 1,
+                epoch,
+                index: Interval(index),
+            },
+        );
+    }
+    t_el
+}
+
+/// Confirmed ElIDs as a verifier publishes them: the ElIDs of the `num_matches` entries of
+/// `t_el` with the lowest indices and `num_others` ElIDs that are not in it, shuffled.
+pub fn confirmed_contacts(
+    t_el: &EncounterTable,
+    num_matches: usize,
+    num_others: usize,
+    seed: u64,
+) -> Vec<ElID> {
+    let mut rng = ChaCha20Rng::seed_from_u64(seed);
+    let mut matches: Vec<_> = t_el.iter().collect();
+    matches.sort_by_key(|(_, encounter)| encounter.index);
+
+    let mut cf: Vec<_> = matches
+        .into_iter()
+        .take(num_matches)
+        .map(|(el_id, _)| el_id)
+        .collect();
+    cf.extend((0..num_others).map(|_| G1G2::random(&mut rng)));
+    cf.shuffle(&mut rng);
+    cf
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        registry::RevocationList,
+        spirit::{
+            Accepted, spirit_trace, spirit_trace_stream, spirit_trace_stream_alarm,
+            spirit_verify_batch,
+        },
+    };
+
+    const EPOCH: Epoch = 1;
+    const EXPIRY: Epoch = 100;
+
+    #[test]
+    fn deployment_is_deterministic() {
+        let mut deployment = Deployment::new(3, 4, 4, 35).unwrap();
+        let mut other = Deployment::new(3, 4, 4, 35).unwrap();
+        assert_eq!(deployment.pp.pk, other.pp.pk);
+
+        let (_, (token, _)) = deployment.register_user(EXPIRY, 1).unwrap();
+        let (_, (other_token, _)) = other.register_user(EXPIRY, 1).unwrap();
+        assert_eq!(token, other_token);
+    }
+
+    #[test]
+    fn reports_of_registered_users_verify() {
+        let mut deployment = Deployment::new(3, 4, 4, 35).unwrap();
+        let mut users = vec![deployment.register_user(EXPIRY, 1).unwrap()];
+        users.extend(deployment.unsigned_users(2, EXPIRY, 2));
+        deployment.populate_registry(100, EXPIRY, 3);
+        assert_eq!(deployment.t_rgstr.len(), 103);
+
+        let reports = deployment.trace_reports(&users, EPOCH, 4).unwrap();
+        let mut nullifiers = HashSet::new();
+        let mut cp = HashSet::new();
+        assert_eq!(
+            spirit_verify_batch(
+                &deployment.domain,
+                &reports,
+                &deployment.t_rgstr,
+                &RevocationList::new(),
+                EPOCH,
+                &mut nullifiers,
+                &deployment.challenges,
+                &mut cp
+            ),
+            vec![
+                Ok(Accepted {
+                    verdicts: vec![true]
+                });
+                3
+            ]
+        );
+        assert_eq!(cp.len(), 3);
+        assert_eq!(nullifiers.len(), 3);
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    #[ignore = "verifies 10k reports, run in release mode"]
+    fn parallel_verification_matches_the_sequential_one() {
+        use crate::spirit::{spirit_verify, spirit_verify_batch_parallel, spirit_verify_parallel};
+
+        const NUM_USERS: usize = 10_000;
+
+        let mut rng = ChaCha20Rng::seed_from_u64(6);
+        let mut deployment = Deployment::new(3, 4, 4, 35).unwrap();
+        let users = deployment.unsigned_users(NUM_USERS, EXPIRY, 2);
+        let mut reports = deployment.trace_reports(&users, EPOCH, 4).unwrap();
+        for i in 0..NUM_USERS {
+            match i % 5 {
+                1 => reports[i].disclosures[0].el_id = G1G2::random(&mut rng),
+                2 => reports[i].report_epoch = EPOCH + 1,
+                // the PRF proof verifies, but the Proof2PK is of the previous report
+                3 => {
+                    let (previous, report) = reports.split_at_mut(i);
+                    std::mem::swap(
+                        &mut previous[i - 1].disclosures[0].proof,
+                        &mut report[0].disclosures[0].proof,
+                    );
+                }
+                _ => {}
+            }
+        }
+        // resubmissions reuse the nullifiers of the reports before or after them
+        let resubmitted = deployment
+            .trace_reports(&users[NUM_USERS - 500..], EPOCH, 5)
+            .unwrap();
+        reports.splice(0..0, resubmitted);
+        reports.extend(deployment.trace_reports(&users[..500], EPOCH, 7).unwrap());
+        let mut revoked = RevocationList::new();
+        for (_, (token, _)) in users.iter().step_by(7) {
+            revoked.revoke(token);
+        }
+
+        let mut nullifiers = HashSet::new();
+        let mut cp = HashSet::new();
+        let verdicts: Vec<_> = reports
+            .iter()
+            .map(|report| {
+                spirit_verify(
+                    &deployment.domain,
+                    report,
+                    &deployment.t_rgstr,
+                    &revoked,
+                    EPOCH,
+                    &mut nullifiers,
+                    &deployment.challenges,
+                    &mut cp,
+                )
+            })
+            .collect();
+        assert!(verdicts.iter().any(Result::is_ok) && verdicts.iter().any(Result::is_err));
+
+        let mut parallel_nullifiers = HashSet::new();
+        let mut parallel_cp = HashSet::new();
+        let parallel_verdicts = spirit_verify_parallel(
+            &deployment.domain,
+            &reports,
+            &deployment.t_rgstr,
+            &revoked,
+            EPOCH,
+            &mut parallel_nullifiers,
+            &deployment.challenges,
+            &mut parallel_cp,
+        );
+        assert_eq!(parallel_verdicts, verdicts);
+        assert_eq!(parallel_nullifiers, nullifiers);
+        assert_eq!(parallel_cp, cp);
+
+        let verify_batch = |chunk_size| {
+            let mut nullifiers = HashSet::new();
+            let mut cp = HashSet::new();
+            let verdicts = match chunk_size {
+                Some(chunk_size) => spirit_verify_batch_parallel(
+                    &deployment.domain,
+                    &reports,
+                    &deployment.t_rgstr,
+                    &revoked,
+                    EPOCH,
+                    &mut nullifiers,
+                    &deployment.challenges,
+                    &mut cp,
+                    chunk_size,
+                ),
+                None => spirit_verify_batch(
+                    &deployment.domain,
+                    &reports,
+                    &deployment.t_rgstr,
+                    &revoked,
+                    EPOCH,
+                    &mut nullifiers,
+                    &deployment.challenges,
+                    &mut cp,
+                ),
+            };
+            (verdicts, nullifiers, cp)
+        };
+        let batch = verify_batch(None);
+        assert!(batch.0.iter().any(Result::is_ok) && batch.0.iter().any(Result::is_err));
+        for chunk_size in [1, 64, NUM_USERS] {
+            assert_eq!(verify_batch(Some(chunk_size)), batch);
+        }
+    }
+
+    #[test]
+    fn confirmed_contacts_match_the_table() {
+        let t_el = encounter_table(1000, EPOCH, 4);
+        assert_eq!(t_el.len(), 1000);
+        assert_eq!(t_el, encounter_table(1000, EPOCH, 4));
+
+        let cf = confirmed_contacts(&t_el, 10, 90, 5);
+        assert_eq!(cf.len(), 100);
+        assert_eq!(spirit_trace(&cf, &t_el, 10).count, 10);
+    }
+
+    #[test]
+    #[ignore = "streams 1M ElIDs, run in release mode"]
+    fn streamed_trace_of_a_million_elids() {
+        const LENGTH: usize = 1_000_000;
+        const SPACING: usize = 10_000;
+
+        let t_el = encounter_table(1000, EPOCH, 4);
+        let mut own: Vec<_> = t_el.iter().collect();
+        own.sort_by_key(|(_, encounter)| encounter.index);
+        let own: &Vec<_> = &own.into_iter().map(|(el_id, _)| el_id).collect();
+
+        // every SPACING-th ElID is one of the first 50 of the table, each of them streamed twice
+        let mut rng = ChaCha20Rng::seed_from_u64(5);
+        let (step, start) = (&G1G2::random(&mut rng), G1G2::random(&mut rng));
+        let read = &std::cell::Cell::new(0);
+        let stream = || {
+            read.set(0);
+            let mut other = start.clone();
+            (0..LENGTH).map(move |i| {
+                read.set(read.get() + 1);
+                if i % SPACING == 0 {
+                    own[(i / SPACING) % 50].clone()
+                } else {
+                    other = &other + step;
+                    other.clone()
+                }
+            })
+        };
+
+        let result = spirit_trace_stream(stream(), &t_el, 50);
+        assert_eq!(read.get(), LENGTH);
+        assert_eq!(result.count, 50);
+        assert_eq!(result.matched, own[..50]);
+        assert!(result.alarm);
+        assert!(!spirit_trace_stream(stream(), &t_el, 51).alarm);
+
+        // the 25th distinct match ends the stream early, the repetitions never do
+        assert!(spirit_trace_stream_alarm(stream(), &t_el, 25));
+        assert_eq!(read.get(), 24 * SPACING + 1);
+        assert!(!spirit_trace_stream_alarm(stream(), &t_el, 51));
+        assert_eq!(read.get(), LENGTH);
+    }
+}
@@ -0,0 +1,166 @@
+#![cfg(feature = "testkit")]
+
+use std::collections::HashSet;
+
+use spirit::{
+    registry::RevocationList,
+    spirit::{
+        Accepted, Epoch, TraceReport, VerifyRejection, spirit_verify, spirit_verify_rerandomized,
+    },
+    testkit::{Deployment, swap_disclosures, swap_proofs},
+};
+
+const T: usize = 3;
+const N: usize = 4;
+const NUM_ISSUERS: usize = 4;
+const EPOCH: Epoch = 1;
+const EXPIRY: Epoch = 100;
+
+// checks the report's token against the registry
+fn verify(deployment: &Deployment, report: &TraceReport) -> Result<Accepted, VerifyRejection> {
+    spirit_verify(
+        &deployment.domain,
+        report,
+        &deployment.t_rgstr,
+        &RevocationList::new(),
+        EPOCH,
+        &mut HashSet::new(),
+        &deployment.challenges,
+        &mut HashSet::new(),
+    )
+}
+
+// checks the issuers' signature on the report's token instead
+fn verify_signed(
+    deployment: &Deployment,
+    report: &TraceReport,
+) -> Result<Accepted, VerifyRejection> {
+    spirit_verify_rerandomized(
+        &deployment.domain,
+        report,
+        &deployment.pp,
+        EPOCH,
+        &mut HashSet::new(),
+        &deployment.challenges,
+        &mut HashSet::new(),
+    )
+}
+
+fn accepted() -> Result<Accepted, VerifyRejection> {
+    Ok(Accepted {
+        verdicts: vec![true],
+    })
+}
+
+#[test]
+fn honest_reports_are_accepted() {
+    let mut deployment = Deployment::new(T, N, NUM_ISSUERS, 86).unwrap();
+    let users = vec![
+        deployment.register_user(EXPIRY, 1).unwrap(),
+        deployment.register_user(EXPIRY, 2).unwrap(),
+    ];
+    for report in deployment.trace_reports(&users, EPOCH, 3).unwrap() {
+        assert_eq!(verify(&deployment, &report), accepted());
+        assert_eq!(verify_signed(&deployment, &report), accepted());
+    }
+
+    // the hooks build honest tokens with a quorum of signers
+    let user = deployment.register_with_signers(T, EXPIRY, 4).unwrap();
+    let report = deployment
+        .trace_reports(&[user], EPOCH, 5)
+        .unwrap()
+        .remove(0);
+    assert_eq!(verify_signed(&deployment, &report), accepted());
+}
+
+#[test]
+fn tokens_of_fewer_than_t_issuers_are_rejected() {
+    let mut deployment = Deployment::new(T, N, NUM_ISSUERS, 86).unwrap();
+    for num_signers in 1..T {
+        let user = deployment
+            .register_with_signers(num_signers, EXPIRY, num_signers as u64)
+            .unwrap();
+        let report = deployment
+            .trace_reports(&[user], EPOCH, 3)
+            .unwrap()
+            .remove(0);
+        let token = report.token.id();
+        assert_eq!(
+            verify(&deployment, &report),
+            Err(VerifyRejection::Unregistered { token })
+        );
+        assert_eq!(
+            verify_signed(&deployment, &report),
+            Err(VerifyRejection::InvalidSignature { token })
+        );
+    }
+}
+
+#[test]
+fn proofs_for_another_commitment_are_rejected() {
+    let mut deployment = Deployment::new(T, N, NUM_ISSUERS, 86).unwrap();
+    let users = vec![
+        deployment.register_user(EXPIRY, 1).unwrap(),
+        deployment.register_user(EXPIRY, 2).unwrap(),
+    ];
+    let mut reports = deployment.trace_reports(&users, EPOCH, 3).unwrap();
+    let (a, b) = reports.split_at_mut(1);
+    swap_proofs(&mut a[0], &mut b[0]);
+
+    for report in &reports {
+        let el_id = report.disclosures[0].el_id.clone();
+        assert_eq!(
+            verify(&deployment, report),
+            Err(VerifyRejection::InvalidProof {
+                el_id: el_id.clone()
+            })
+        );
+        assert_eq!(
+            verify_signed(&deployment, report),
+            Err(VerifyRejection::InvalidProof { el_id })
+        );
+    }
+}
+
+#[test]
+fn elids_of_another_user_are_rejected() {
+    let mut deployment = Deployment::new(T, N, NUM_ISSUERS, 86).unwrap();
+    let users = vec![
+        deployment.register_user(EXPIRY, 1).unwrap(),
+        deployment.register_user(EXPIRY, 2).unwrap(),
+    ];
+    let mut reports = deployment.trace_reports(&users, EPOCH, 3).unwrap();
+    let (a, b) = reports.split_at_mut(1);
+    swap_disclosures(&mut a[0], &mut b[0]);
+
+    for report in &reports {
+        assert_eq!(
+            verify(&deployment, report),
+            Err(VerifyRejection::InvalidProof {
+                el_id: report.disclosures[0].el_id.clone()
+            })
+        );
+    }
+}
+
+#[test]
+fn commitments_to_another_identity_are_rejected() {
+    let mut deployment = Deployment::new(T, N, NUM_ISSUERS, 86).unwrap();
+    let (_, ppu) = deployment.register_user(EXPIRY, 1).unwrap();
+    let forged = deployment.forge_identity(&ppu, 2);
+    assert_eq!(forged.1.0.1, ppu.0.1);
+
+    let report = deployment
+        .trace_reports(&[forged], EPOCH, 3)
+        .unwrap()
+        .remove(0);
+    let token = report.token.id();
+    assert_eq!(
+        verify(&deployment, &report),
+        Err(VerifyRejection::Unregistered { token })
+    );
+    assert_eq!(
+        verify_signed(&deployment, &report),
+        Err(VerifyRejection::InvalidSignature { token })
+    );
+}
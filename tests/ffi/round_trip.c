@@ -0,0 +1,114 @@
+/*
+ * Registration round trip through the C API: a registrar with in-process issuers registers a
+ * user, the user broadcasts and discloses two ElIDs, and the registrar verifies the report.
+ * Called from the `c_round_trip` test of ffi.rs; returns 0 on success and the failing line
+ * otherwise.
+ */
+#include <stddef.h>
+#include <stdint.h>
+#include <string.h>
+
+#include "spirit.h"
+
+#define T 3
+#define N 4
+#define NUM_ISSUERS 4
+#define EPOCH 1
+#define EXPIRY 100
+
+#define FAIL()               \
+    do {                     \
+        result = __LINE__;   \
+        goto out;            \
+    } while (0)
+
+#define CHECK(call)                             \
+    do {                                        \
+        if ((call) != SPIRIT_FFI_STATUS_OK) {   \
+            FAIL();                             \
+        }                                       \
+    } while (0)
+
+int spirit_ffi_test_round_trip(void) {
+    static const uint8_t app_id[] = "ffi-test";
+    /* little-endian encodings of the identity scalar 1 and the user key 2 */
+    const uint8_t id[32] = {1};
+    const uint8_t prv[32] = {2};
+    const uint64_t indices[] = {1, 2};
+
+    int result = 0;
+    SpiritFfiRegistrar *registrar = NULL;
+    SpiritFfiClient *client = NULL;
+    SpiritFfiRegistration *registration = NULL;
+    SpiritBuffer pk = {0}, blind_request = {0}, blind_tokens = {0}, ppu = {0}, proof = {0};
+    SpiritBuffer token = {0}, el_id = {0}, challenge = {0}, report = {0}, verdicts = {0};
+
+    /* registration */
+    CHECK(spirit_ffi_registrar_new(app_id, sizeof(app_id) - 1, T, N, NUM_ISSUERS, &registrar));
+    CHECK(spirit_ffi_registrar_public_key(registrar, &pk));
+    CHECK(spirit_ffi_client_new(app_id, sizeof(app_id) - 1, pk.data, pk.len, N, T, &client));
+    CHECK(spirit_ffi_register_begin(client, id, sizeof(id), EXPIRY, &registration,
+                                    &blind_request));
+    CHECK(spirit_ffi_registrar_issue(registrar, blind_request.data, blind_request.len,
+                                     &blind_tokens));
+    CHECK(spirit_ffi_register_finish(client, registration, blind_tokens.data, blind_tokens.len,
+                                     &ppu, &proof));
+    CHECK(spirit_ffi_registrar_complete(registrar, blind_request.data, blind_request.len,
+                                        proof.data, proof.len, EXPIRY, &token));
+    /* the encoded (Token, StRG) pair starts with the encoding of the registered token */
+    if (ppu.len < token.len || memcmp(ppu.data, token.data, token.len) != 0) {
+        FAIL();
+    }
+
+    /* broadcast and diagnosis */
+    CHECK(spirit_ffi_broadcast(client, prv, sizeof(prv), EPOCH, indices[0], &el_id));
+    if (el_id.len == 0) {
+        FAIL();
+    }
+    CHECK(spirit_ffi_registrar_challenge(registrar, EPOCH, &challenge));
+    CHECK(spirit_ffi_diagnose(client, ppu.data, ppu.len, prv, sizeof(prv), EPOCH, indices,
+                              sizeof(indices) / sizeof(indices[0]), EPOCH, challenge.data,
+                              challenge.len, &report));
+
+    /* verification */
+    CHECK(spirit_ffi_verify(registrar, report.data, report.len, EPOCH, &verdicts));
+    if (verdicts.len != 2 || verdicts.data[0] != 1 || verdicts.data[1] != 1) {
+        FAIL();
+    }
+    if (spirit_ffi_last_error_message() != NULL) {
+        FAIL();
+    }
+
+    /* a replayed report is rejected */
+    spirit_ffi_buffer_free(verdicts);
+    verdicts = (SpiritBuffer){0};
+    CHECK(spirit_ffi_verify(registrar, report.data, report.len, EPOCH, &verdicts));
+    if (verdicts.len != 2 || verdicts.data[0] != 0 || verdicts.data[1] != 0) {
+        FAIL();
+    }
+
+    /* a truncated report is rejected with an error message */
+    spirit_ffi_buffer_free(verdicts);
+    verdicts = (SpiritBuffer){0};
+    if (spirit_ffi_verify(registrar, report.data, report.len - 1, EPOCH, &verdicts) !=
+            SPIRIT_FFI_STATUS_INVALID_ENCODING ||
+        spirit_ffi_last_error_message() == NULL) {
+        FAIL();
+    }
+
+out:
+    spirit_ffi_buffer_free(pk);
+    spirit_ffi_buffer_free(blind_request);
+    spirit_ffi_buffer_free(blind_tokens);
+    spirit_ffi_buffer_free(ppu);
+    spirit_ffi_buffer_free(proof);
+    spirit_ffi_buffer_free(token);
+    spirit_ffi_buffer_free(el_id);
+    spirit_ffi_buffer_free(challenge);
+    spirit_ffi_buffer_free(report);
+    spirit_ffi_buffer_free(verdicts);
+    spirit_ffi_registration_free(registration);
+    spirit_ffi_client_free(client);
+    spirit_ffi_registrar_free(registrar);
+    return result;
+}
@@ -0,0 +1,137 @@
+#![cfg(feature = "testkit")]
+
+use std::collections::BTreeSet;
+
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+use spirit::{
+    exposure::{ExposurePolicy, RiskLevel},
+    simulation::{Contact, Scenario, SimulationError},
+};
+
+#[test]
+fn small_graph_raises_the_expected_alarms() {
+    let policy = ExposurePolicy::builder()
+        .tier(1, RiskLevel::Low)
+        .tier(2, RiskLevel::Medium)
+        .build()
+        .unwrap();
+    // 0 and 3 are diagnosed; 1 met 0 twice on one day, 4 met 3 on two days, 2 and 5 only met
+    // users who are not diagnosed, and 6 met no one
+    let scenario = Scenario::builder(7)
+        .days(7)
+        .contact(0, 1, 2)
+        .contact(1, 0, 2)
+        .contact(1, 2, 3)
+        .contact(3, 4, 1)
+        .contact(3, 4, 5)
+        .contact(0, 3, 6)
+        .contact(2, 5, 6)
+        .diagnosed([0, 3])
+        .policy(policy)
+        .seed(7)
+        .build()
+        .unwrap();
+    let report = scenario.run().expect("simulation failed");
+
+    assert_eq!(report.confirmed, 2 * 7);
+    assert_eq!(report.alarmed(), [0, 1, 3, 4]);
+    let risks: Vec<_> = report.outcomes.iter().map(|outcome| outcome.risk).collect();
+    assert_eq!(
+        risks,
+        [
+            RiskLevel::Low,
+            RiskLevel::Low,
+            RiskLevel::None,
+            RiskLevel::Low,
+            RiskLevel::Medium,
+            RiskLevel::None,
+            RiskLevel::None
+        ]
+    );
+    let scores: Vec<_> = report
+        .outcomes
+        .iter()
+        .map(|outcome| outcome.score)
+        .collect();
+    assert_eq!(scores, [1, 1, 0, 1, 2, 0, 0]);
+    assert!(report.outcomes[0].diagnosed && !report.outcomes[1].diagnosed);
+
+    assert_eq!(scenario.run(), Ok(report));
+}
+
+#[test]
+fn random_graph_has_no_false_alarms() {
+    assert_no_false_alarms(150, 76);
+}
+
+#[test]
+#[ignore = "registers and traces 1k users, run in release mode"]
+fn random_graph_of_a_thousand_users_has_no_false_alarms() {
+    assert_no_false_alarms(1_000, 1_000);
+}
+
+// runs a random graph of `users` users over 10 days, 10% of whom are diagnosed
+fn assert_no_false_alarms(users: usize, seed: u64) {
+    const DAYS: u64 = 10;
+
+    let mut rng = ChaCha20Rng::seed_from_u64(seed);
+    let contacts: Vec<_> = (0..2 * users)
+        .map(|_| {
+            let a = rng.gen_range(0..users);
+            let b = (a + rng.gen_range(1..users)) % users;
+            Contact {
+                a,
+                b,
+                day: rng.gen_range(0..DAYS),
+            }
+        })
+        .collect();
+    let diagnosed: BTreeSet<_> = (0..users / 10).map(|_| rng.gen_range(0..users)).collect();
+    let scenario = Scenario::builder(users)
+        .days(DAYS)
+        .contacts(contacts.iter().copied())
+        .diagnosed(diagnosed.iter().copied())
+        .seed(seed)
+        .build()
+        .unwrap();
+    let report = scenario.run().expect("simulation failed");
+
+    let exposed: BTreeSet<_> = contacts
+        .iter()
+        .flat_map(|contact| [(contact.a, contact.b), (contact.b, contact.a)])
+        .filter(|(sender, _)| diagnosed.contains(sender))
+        .map(|(_, receiver)| receiver)
+        .collect();
+    assert!(exposed.len() < users, "every user is exposed");
+    for (user, outcome) in report.outcomes.iter().enumerate() {
+        if !exposed.contains(&user) {
+            assert!(!outcome.alarm(), "false alarm of user {user}");
+        }
+    }
+    assert_eq!(report.alarmed(), exposed.into_iter().collect::<Vec<_>>());
+}
+
+#[test]
+fn invalid_scenarios_are_rejected() {
+    assert_eq!(
+        Scenario::builder(3).contact(0, 3, 0).build().unwrap_err(),
+        SimulationError::UnknownUser {
+            user: 3,
+            num_users: 3
+        }
+    );
+    assert_eq!(
+        Scenario::builder(3)
+            .days(2)
+            .contact(0, 1, 2)
+            .build()
+            .unwrap_err(),
+        SimulationError::UnknownDay { day: 2, days: 2 }
+    );
+    assert_eq!(
+        Scenario::builder(3).contact(1, 1, 0).build().unwrap_err(),
+        SimulationError::SelfContact { user: 1 }
+    );
+    assert!(Scenario::builder(3).diagnosed([5]).build().is_err());
+}
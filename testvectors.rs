@@ -0,0 +1,408 @@
+//! Known-answer vectors for implementations of SPiRiT in other languages, with the
+//! `testvectors` feature. [generate] derives all inputs of the vectors from a seed, so the same
+//! seed always yields the same [JSON document](TestVectors::to_json), and [verify] recomputes
+//! every entry of a document from its inputs.
+//!
+//! All points are in the encoding of [G1G2::to_bytes], scalars are 32 bytes little-endian, and
+//! the artifacts of the registration are in the encodings of this crate, everything in hex.
+//!
+//! ```text
+//! cargo run --bin gen-vectors --features testvectors -- 1 > vectors.json
+//! ```
+
+use std::iter;
+
+use ark_ff::UniformRand;
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
+use rand::SeedableRng;
+use rand_chacha::ChaCha20Rng;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    G1G2, HashBackendId, HashToCurveSuite, Scalar,
+    atact::tissue,
+    bls381_helpers::hash_to_curve_with,
+    pedersen::Commitment,
+    spirit::{
+        DomainConfig, Epoch, Interval, SetupOutput, UserSecret, prf_broadcast,
+        spirit_complete_registration, spirit_register_begin, spirit_register_finish, spirit_setup,
+    },
+    transcript::RecordingRng,
+};
+
+/// Seed of the `gen-vectors` binary if none is given.
+pub const DEFAULT_SEED: u64 = 1;
+
+const APP_ID: &[u8] = b"spirit-test-vectors";
+const T: usize = 3;
+const N: usize = 4;
+const NUM_ISSUERS: usize = 4;
+const EXPIRY: Epoch = 100;
+/// Messages and tags of the hash vectors, which do not depend on the seed. With
+/// [HashToCurveSuite::Rfc9380] and SHA-256, the last two are the inputs of the vectors of
+/// RFC 9380, appendices J.9.1 and J.10.1.
+const HASH_INPUTS: [(&[u8], &[u8]); 6] = [
+    (b"", b"SPiRiT-test"),
+    (b"abc", b"SPiRiT-test"),
+    (b"g", b"Pedersen-PP"),
+    (b"u", b"Pedersen-PP"),
+    (b"", RFC_9380_TAG),
+    (b"abc", RFC_9380_TAG),
+];
+/// Tag of the RFC 9380 vectors without the suite ID, which [hash_to_curve_with] appends.
+const RFC_9380_TAG: &[u8] = b"QUUX-V01-CS02-with-";
+
+/// All vectors of a seed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TestVectors {
+    pub seed: u64,
+    pub hashes: Vec<HashVector>,
+    pub prfs: Vec<PrfVector>,
+    pub commitments: Vec<CommitmentVector>,
+    pub registration: RegistrationVector,
+}
+
+/// Hash of `msg` under the tag `dst` with `suite` and `backend`, see [hash_to_curve_with].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HashVector {
+    pub suite: HashToCurveSuite,
+    pub backend: HashBackendId,
+    pub msg: String,
+    pub dst: String,
+    pub point: String,
+}
+
+/// ElID of interval `index` of `epoch` for the epoch key `key`, see [prf_broadcast], in the
+/// deployment of [DomainConfig::new] with `app_id`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PrfVector {
+    pub app_id: String,
+    pub key: String,
+    pub epoch: Epoch,
+    pub index: Interval,
+    pub el_id: String,
+}
+
+/// Pedersen commitment `g^blinding u^message`, see [Commitment::commit_with_randomness].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CommitmentVector {
+    pub message: String,
+    pub blinding: String,
+    pub commitment: String,
+}
+
+/// Registration of the user `id_u` with the issuers of a deployment set up with the seed: the
+/// registrar's parameters, the random values the user drew as a
+/// [Transcript](crate::transcript::Transcript), and the messages of the protocol.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RegistrationVector {
+    pub app_id: String,
+    pub t: usize,
+    pub n: usize,
+    pub expiry: Epoch,
+    pub public_key: String,
+    pub id_u: String,
+    pub draws: String,
+    pub blind_request: String,
+    /// Blind tokens of the first `t` issuers, in issuer order.
+    pub blind_tokens: Vec<Vec<String>>,
+    pub token_proof: String,
+    pub token: String,
+}
+
+#[derive(Error, Debug, PartialEq, Eq, Clone)]
+pub enum VectorError {
+    #[error("Invalid input of entry {0}.")]
+    InvalidInput(String),
+    #[error("Entry {0} does not match.")]
+    Mismatch(String),
+}
+
+impl TestVectors {
+    /// Pretty-printed JSON of the vectors with a trailing newline.
+    pub fn to_json(&self) -> String {
+        let mut json = serde_json::to_string_pretty(self).expect("serializing vectors failed");
+        json.push('\n');
+        json
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(json)
+    }
+}
+
+/// Vectors with the inputs drawn from a ChaCha20 RNG seeded with `seed`. The registration draws
+/// from a second stream of the RNG, so it does not depend on the other vectors.
+pub fn generate(seed: u64) -> TestVectors {
+    let mut rng = ChaCha20Rng::seed_from_u64(seed);
+    let hashes = [HashToCurveSuite::Legacy, HashToCurveSuite::Rfc9380]
+        .into_iter()
+        .flat_map(|suite| {
+            [HashBackendId::Sha256, HashBackendId::Sha3_256].map(|backend| (suite, backend))
+        })
+        .flat_map(|config| iter::repeat(config).zip(HASH_INPUTS))
+        .map(|((suite, backend), (msg, dst))| hash_vector(suite, backend, msg, dst))
+        .collect();
+    let prfs = (0..4)
+        .map(|i| prf_vector(APP_ID, &Scalar::rand(&mut rng), i, Interval(2 * i + 1)))
+        .collect();
+    let commitments = (0..4)
+        .map(|_| commitment_vector(&Scalar::rand(&mut rng), &Scalar::rand(&mut rng)))
+        .collect();
+
+    TestVectors {
+        seed,
+        hashes,
+        prfs,
+        commitments,
+        registration: registration_vector(seed),
+    }
+}
+
+/// Recomputes every entry of `vectors` from its inputs, and the registration from the seed.
+/// Fails with the first entry that does not match.
+pub fn verify(vectors: &TestVectors) -> Result<(), VectorError> {
+    for (i, vector) in vectors.hashes.iter().enumerate() {
+        let entry = format!("hashes[{i}]");
+        let msg = decode(&vector.msg, &entry)?;
+        let dst = decode(&vector.dst, &entry)?;
+        check(
+            hash_vector(vector.suite, vector.backend, &msg, &dst) == *vector,
+            entry,
+        )?;
+    }
+    for (i, vector) in vectors.prfs.iter().enumerate() {
+        let entry = format!("prfs[{i}]");
+        let app_id = decode(&vector.app_id, &entry)?;
+        let key = decode_scalar(&vector.key, &entry)?;
+        check(
+            prf_vector(&app_id, &key, vector.epoch, vector.index) == *vector,
+            entry,
+        )?;
+    }
+    for (i, vector) in vectors.commitments.iter().enumerate() {
+        let entry = format!("commitments[{i}]");
+        let message = decode_scalar(&vector.message, &entry)?;
+        let blinding = decode_scalar(&vector.blinding, &entry)?;
+        check(commitment_vector(&message, &blinding) == *vector, entry)?;
+    }
+    check(
+        registration_vector(vectors.seed) == vectors.registration,
+        "registration".to_string(),
+    )
+}
+
+fn check(matches: bool, entry: String) -> Result<(), VectorError> {
+    if matches {
+        Ok(())
+    } else {
+        Err(VectorError::Mismatch(entry))
+    }
+}
+
+fn hash_vector(
+    suite: HashToCurveSuite,
+    backend: HashBackendId,
+    msg: &[u8],
+    dst: &[u8],
+) -> HashVector {
+    HashVector {
+        suite,
+        backend,
+        msg: hex::encode(msg),
+        dst: hex::encode(dst),
+        point: encode_point(&hash_to_curve_with(suite, backend, msg, dst)),
+    }
+}
+
+fn prf_vector(app_id: &[u8], key: &Scalar, epoch: Epoch, index: Interval) -> PrfVector {
+    PrfVector {
+        app_id: hex::encode(app_id),
+        key: encode(key),
+        epoch,
+        index,
+        el_id: encode_point(&prf_broadcast(
+            &DomainConfig::new(app_id.to_vec()),
+            key,
+            epoch,
+            index,
+        )),
+    }
+}
+
+fn commitment_vector(message: &Scalar, blinding: &Scalar) -> CommitmentVector {
+    let (commitment, _) = Commitment::commit_with_randomness(message, blinding);
+    CommitmentVector {
+        message: encode(message),
+        blinding: encode(blinding),
+        commitment: encode_point(&commitment.0),
+    }
+}
+
+fn registration_vector(seed: u64) -> RegistrationVector {
+    let mut rng = ChaCha20Rng::seed_from_u64(seed);
+    rng.set_stream(1);
+    let SetupOutput {
+        pp,
+        issuers,
+        mut t_rgstr,
+        domain,
+        ..
+    } = spirit_setup(T, N, NUM_ISSUERS, APP_ID, &mut rng).expect("setup failed");
+    let user = UserSecret::random(&mut rng);
+
+    let mut user_rng = RecordingRng::new(&mut rng);
+    let (blind_request, pending) =
+        spirit_register_begin(&domain, &user.id_u, &pp, EXPIRY, &mut user_rng)
+            .expect("token request failed");
+    let blind_tokens: Vec<_> = issuers[..pp.t]
+        .iter()
+        .map(|issuer| tissue(&blind_request, issuer, &pp).expect("issuance failed"))
+        .collect();
+    let (_, tact_token, token_proof) =
+        spirit_register_finish(&domain, pending, &blind_tokens, &pp, &mut user_rng)
+            .expect("unblinding failed");
+    let draws = user_rng.into_transcript();
+    let token = spirit_complete_registration(
+        &domain,
+        &blind_request,
+        &tact_token,
+        &token_proof,
+        &pp,
+        EXPIRY,
+        &mut t_rgstr,
+    )
+    .expect("registration failed");
+
+    RegistrationVector {
+        app_id: hex::encode(APP_ID),
+        t: pp.t,
+        n: pp.n,
+        expiry: EXPIRY,
+        public_key: hex::encode(pp.pk.to_bytes()),
+        id_u: encode(&user.id_u),
+        draws: hex::encode(draws.to_bytes()),
+        blind_request: encode(&blind_request),
+        blind_tokens: blind_tokens
+            .iter()
+            .map(|tokens| tokens.iter().map(encode).collect())
+            .collect(),
+        token_proof: encode(&token_proof),
+        token: hex::encode(token.to_bytes()),
+    }
+}
+
+// compressed encoding
+fn encode(value: &impl CanonicalSerialize) -> String {
+    let mut bytes = Vec::new();
+    value
+        .serialize_compressed(&mut bytes)
+        .expect("serializing failed");
+    hex::encode(bytes)
+}
+
+fn encode_point(point: &G1G2) -> String {
+    hex::encode(point.to_bytes())
+}
+
+fn decode(value: &str, entry: &str) -> Result<Vec<u8>, VectorError> {
+    hex::decode(value).map_err(|_| VectorError::InvalidInput(entry.to_string()))
+}
+
+fn decode_scalar(value: &str, entry: &str) -> Result<Scalar, VectorError> {
+    Scalar::deserialize_compressed(&decode(value, entry)?[..])
+        .map_err(|_| VectorError::InvalidInput(entry.to_string()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn generated_vectors_verify() {
+        let vectors = generate(DEFAULT_SEED);
+        assert_eq!(vectors.hashes.len(), 4 * HASH_INPUTS.len());
+        assert_eq!(vectors.registration.blind_tokens.len(), T);
+
+        let parsed = TestVectors::from_json(&vectors.to_json()).expect("parsing failed");
+        assert_eq!(parsed, vectors);
+        assert_eq!(verify(&parsed), Ok(()));
+    }
+
+    #[test]
+    fn hash_vectors_match_rfc_9380() {
+        // the G1 point of J.9.1 followed by the G2 point of J.10.1, compressed
+        const EXPECTED: [(&[u8], &str); 2] = [
+            (
+                b"",
+                "852926add2207b76ca4fa57a8734416c8dc95e24501772c814278700eed6d1e4e8cf62d9c09db0fac3\
+                 49612b759e79a1a5cb8437535e20ecffaef7752baddf98034139c38452458baeefab379ba13dff5b\
+                 f5dd71b72418717047f5b0f37da03d0141ebfbdca40eb85b87142e130ab689c673cf60f1a3e98d69\
+                 335266f30d9b8d4ac44c1038e9dcdd5393faf5c41fb78a",
+            ),
+            (
+                b"abc",
+                "83567bc5ef9c690c2ab2ecdf6a96ef1c139cc0b2f284dca0a9a7943388a49a3aee664ba5379a7655\
+                 d3c68900be2f6903939cddbccdc5e91b9623efd38c49f81a6f83f175e80b06fc374de9eb4b41dfe4\
+                 ca3a230ed250fbe3a2acf73a41177fd802c2d18e033b960562aae3cab37a27ce00d80ccd5ba4b7fe\
+                 0e7a210245129dbec7780ccc7954725f4168aff2787776e6",
+            ),
+        ];
+
+        let vectors = generate(DEFAULT_SEED);
+        for (msg, point) in EXPECTED {
+            let vector = vectors
+                .hashes
+                .iter()
+                .find(|vector| {
+                    vector.suite == HashToCurveSuite::Rfc9380
+                        && vector.backend == HashBackendId::Sha256
+                        && vector.msg == hex::encode(msg)
+                        && vector.dst == hex::encode(RFC_9380_TAG)
+                })
+                .expect("missing vector");
+            assert_eq!(vector.point, point);
+        }
+    }
+
+    #[test]
+    fn generation_is_deterministic() {
+        let json = generate(DEFAULT_SEED).to_json();
+        assert_eq!(generate(DEFAULT_SEED).to_json(), json);
+        assert_ne!(generate(DEFAULT_SEED + 1).to_json(), json);
+    }
+
+    #[test]
+    fn tampered_entries_are_rejected() {
+        let vectors = generate(DEFAULT_SEED);
+
+        let mut tampered = vectors.clone();
+        tampered.prfs[2].epoch += 1;
+        assert_eq!(
+            verify(&tampered),
+            Err(VectorError::Mismatch("prfs[2]".to_string()))
+        );
+
+        let mut tampered = vectors.clone();
+        tampered.hashes[1].backend = HashBackendId::Sha3_256;
+        assert_eq!(
+            verify(&tampered),
+            Err(VectorError::Mismatch("hashes[1]".to_string()))
+        );
+
+        let mut tampered = vectors.clone();
+        tampered.commitments[1].blinding = "zz".to_string();
+        assert_eq!(
+            verify(&tampered),
+            Err(VectorError::InvalidInput("commitments[1]".to_string()))
+        );
+
+        let mut tampered = vectors;
+        tampered.registration.id_u = tampered.prfs[0].key.clone();
+        assert_eq!(
+            verify(&tampered),
+            Err(VectorError::Mismatch("registration".to_string()))
+        );
+    }
+}
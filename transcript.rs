@@ -0,0 +1,400 @@
+use alloc::vec::Vec;
+
+use rand::{CryptoRng, RngCore};
+use thiserror::Error;
+
+use crate::{
+    DecodeError,
+    envelope::{self, ArtifactType},
+};
+
+const TAG_U32: u8 = 1;
+const TAG_U64: u8 = 2;
+const TAG_BYTES: u8 = 3;
+
+/// One call on the RNG wrapped by a [RecordingRng], with the value it returned.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Draw {
+    U32(u32),
+    U64(u64),
+    Bytes(Vec<u8>),
+}
+
+/// Log of all random values drawn during a protocol run, e.g. a failed registration, from
+/// which a [ReplayRng] reproduces the run exactly.
+///
+/// The transcript determines the user's secrets of the run, so it has to be kept like the
+/// [StRG](crate::atact::StRG) and is only meant for debugging.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Transcript {
+    draws: Vec<Draw>,
+}
+
+impl Transcript {
+    pub fn draws(&self) -> &[Draw] {
+        &self.draws
+    }
+
+    /// Encodes every draw as a tag byte followed by the value, little-endian, with byte strings
+    /// prefixed by their length as `u32`, behind an [envelope](crate::envelope) header.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = envelope::header(ArtifactType::Transcript).to_vec();
+        for draw in &self.draws {
+            match draw {
+                Draw::U32(value) => {
+                    bytes.push(TAG_U32);
+                    bytes.extend_from_slice(&value.to_le_bytes());
+                }
+                Draw::U64(value) => {
+                    bytes.push(TAG_U64);
+                    bytes.extend_from_slice(&value.to_le_bytes());
+                }
+                Draw::Bytes(value) => {
+                    bytes.push(TAG_BYTES);
+                    // draws are as long as the buffers the protocol fills, far below 4 GiB
+                    bytes.extend_from_slice(&(value.len() as u32).to_le_bytes());
+                    bytes.extend_from_slice(value);
+                }
+            }
+        }
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, TranscriptError> {
+        match envelope::open(bytes, ArtifactType::Transcript)
+            .map_err(TranscriptError::InvalidEncoding)?
+        {
+            (1, payload) => Self::from_payload_v1(payload),
+            (version, _) => Err(TranscriptError::InvalidEncoding(
+                DecodeError::UnsupportedVersion(version),
+            )),
+        }
+    }
+
+    fn from_payload_v1(mut bytes: &[u8]) -> Result<Self, TranscriptError> {
+        fn take<'a>(bytes: &mut &'a [u8], len: usize) -> Result<&'a [u8], TranscriptError> {
+            if bytes.len() < len {
+                return Err(TranscriptError::Truncated);
+            }
+            let (value, rest) = bytes.split_at(len);
+            *bytes = rest;
+            Ok(value)
+        }
+
+        let mut draws = Vec::new();
+        while let Some((&tag, rest)) = bytes.split_first() {
+            bytes = rest;
+            let draw = match tag {
+                TAG_U32 => Draw::U32(u32::from_le_bytes(take(&mut bytes, 4)?.try_into().unwrap())),
+                TAG_U64 => Draw::U64(u64::from_le_bytes(take(&mut bytes, 8)?.try_into().unwrap())),
+                TAG_BYTES => {
+                    let len = u32::from_le_bytes(take(&mut bytes, 4)?.try_into().unwrap());
+                    Draw::Bytes(take(&mut bytes, len as usize)?.to_vec())
+                }
+                _ => return Err(TranscriptError::InvalidTag(tag)),
+            };
+            draws.push(draw);
+        }
+        Ok(Self { draws })
+    }
+}
+
+/// RNG that passes through the values of `R` and records them in a [Transcript].
+pub struct RecordingRng<R> {
+    inner: R,
+    transcript: Transcript,
+}
+
+impl<R: RngCore> RecordingRng<R> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            transcript: Transcript::default(),
+        }
+    }
+
+    pub fn into_transcript(self) -> Transcript {
+        self.transcript
+    }
+}
+
+impl<R: RngCore> RngCore for RecordingRng<R> {
+    fn next_u32(&mut self) -> u32 {
+        let value = self.inner.next_u32();
+        self.transcript.draws.push(Draw::U32(value));
+        value
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let value = self.inner.next_u64();
+        self.transcript.draws.push(Draw::U64(value));
+        value
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.inner.fill_bytes(dest);
+        self.transcript.draws.push(Draw::Bytes(dest.to_vec()));
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.inner.try_fill_bytes(dest)?;
+        self.transcript.draws.push(Draw::Bytes(dest.to_vec()));
+        Ok(())
+    }
+}
+
+impl<R: CryptoRng> CryptoRng for RecordingRng<R> {}
+
+/// RNG that returns the values of a [Transcript] in order.
+///
+/// # Panics
+///
+/// If the run asks for a value of another kind or size than recorded, or for more values, the
+/// replay has diverged, and the draw panics with [TranscriptError::Diverged] instead of making
+/// up a value.
+///
+/// It implements [CryptoRng] only so that it can be passed to the protocol functions, and must
+/// never be used outside of debugging.
+pub struct ReplayRng<'a> {
+    draws: &'a [Draw],
+    position: usize,
+}
+
+impl<'a> ReplayRng<'a> {
+    pub fn new(transcript: &'a Transcript) -> Self {
+        Self {
+            draws: &transcript.draws,
+            position: 0,
+        }
+    }
+
+    /// Checks that the run drew all recorded values.
+    pub fn finish(self) -> Result<(), TranscriptError> {
+        if self.position != self.draws.len() {
+            return Err(TranscriptError::NotExhausted {
+                remaining: self.draws.len() - self.position,
+            });
+        }
+        Ok(())
+    }
+
+    fn next(&mut self) -> Option<&'a Draw> {
+        let draw = self.draws.get(self.position);
+        self.position += 1;
+        draw
+    }
+
+    fn diverge(&self) -> ! {
+        panic!(
+            "{}",
+            TranscriptError::Diverged {
+                index: self.position - 1
+            }
+        )
+    }
+}
+
+impl RngCore for ReplayRng<'_> {
+    fn next_u32(&mut self) -> u32 {
+        match self.next() {
+            Some(Draw::U32(value)) => *value,
+            _ => self.diverge(),
+        }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        match self.next() {
+            Some(Draw::U64(value)) => *value,
+            _ => self.diverge(),
+        }
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        match self.next() {
+            Some(Draw::Bytes(value)) if value.len() == dest.len() => dest.copy_from_slice(value),
+            _ => self.diverge(),
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+impl CryptoRng for ReplayRng<'_> {}
+
+#[derive(Error, Debug, PartialEq, Eq, Clone)]
+pub enum TranscriptError {
+    #[error("Truncated transcript.")]
+    Truncated,
+    #[error("Invalid draw tag {0}.")]
+    InvalidTag(u8),
+    #[error("The replay diverged from the transcript at draw {index}.")]
+    Diverged { index: usize },
+    #[error("The replay left {remaining} draws of the transcript unused.")]
+    NotExhausted { remaining: usize },
+    #[error("Invalid transcript encoding: {0}")]
+    InvalidEncoding(#[source] DecodeError),
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashSet;
+
+    use ark_serialize::CanonicalSerialize;
+    use rand::rngs::OsRng;
+
+    use super::*;
+    use crate::{
+        Scalar,
+        atact::{Issuer, PublicParameters, tissue},
+        spirit::{
+            DomainConfig, SetupOutput, Token, UserSecret, spirit_register, spirit_register_begin,
+            spirit_register_finish, spirit_register_recorded, spirit_setup_osrng,
+        },
+    };
+
+    const EXPIRY: u64 = 100;
+
+    fn encoding(value: &impl CanonicalSerialize) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        value.serialize_compressed(&mut bytes).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn replayed_registration_is_identical() {
+        let SetupOutput {
+            pp,
+            issuers,
+            t_rgstr,
+            domain,
+            ..
+        } = spirit_setup_osrng(3, 4, 4, b"test").expect("setup failed");
+        let user = UserSecret::random(OsRng);
+
+        let mut recorded_rgstr = t_rgstr.clone();
+        let (result, transcript) = spirit_register_recorded(
+            &domain,
+            &user,
+            &issuers,
+            &pp,
+            EXPIRY,
+            &mut recorded_rgstr,
+            &mut OsRng,
+        );
+        let (token, strg) = result.expect("registration failed");
+        let transcript = Transcript::from_bytes(&transcript.to_bytes()).unwrap();
+
+        let mut replayed_rgstr: HashSet<Token> = t_rgstr;
+        let mut rng = ReplayRng::new(&transcript);
+        let (replayed_token, replayed_strg) = spirit_register(
+            &domain,
+            &user,
+            &issuers,
+            &pp,
+            EXPIRY,
+            &mut replayed_rgstr,
+            &mut rng,
+        )
+        .expect("replay failed");
+        assert_eq!(rng.finish(), Ok(()));
+
+        assert_eq!(replayed_token.to_bytes(), token.to_bytes());
+        assert_eq!(encoding(&replayed_strg), encoding(&strg));
+        assert_eq!(replayed_rgstr, recorded_rgstr);
+    }
+
+    /// Registers a user in the client-side flow, and returns the encodings of the unblinded tACT
+    /// token and its proof.
+    fn token_and_proof(
+        pp: &PublicParameters,
+        issuers: &[Issuer],
+        domain: &DomainConfig,
+        rng: &mut (impl RngCore + CryptoRng),
+    ) -> (Vec<u8>, Vec<u8>) {
+        let (blind_request, pending) =
+            spirit_register_begin(domain, &Scalar::from(1u64), pp, EXPIRY, rng)
+                .expect("request failed");
+        let blind_tokens: Vec<_> = issuers[..pp.t]
+            .iter()
+            .map(|issuer| tissue(&blind_request, issuer, pp).expect("issuance failed"))
+            .collect();
+        let (_, token, token_proof) =
+            spirit_register_finish(domain, pending, &blind_tokens, pp, rng)
+                .expect("unblinding failed");
+        (encoding(&token), encoding(&token_proof))
+    }
+
+    #[test]
+    fn replayed_token_proof_is_identical() {
+        let SetupOutput {
+            pp,
+            issuers,
+            domain,
+            ..
+        } = spirit_setup_osrng(3, 4, 4, b"test").expect("setup failed");
+
+        let mut recording = RecordingRng::new(OsRng);
+        let recorded = token_and_proof(&pp, &issuers, &domain, &mut recording);
+        let transcript = recording.into_transcript();
+
+        let mut replay = ReplayRng::new(&transcript);
+        assert_eq!(
+            token_and_proof(&pp, &issuers, &domain, &mut replay),
+            recorded
+        );
+        assert_eq!(replay.finish(), Ok(()));
+    }
+
+    #[test]
+    fn unfinished_replay_is_reported() {
+        let mut recording = RecordingRng::new(OsRng);
+        recording.next_u64();
+        let mut bytes = [0u8; 16];
+        recording.fill_bytes(&mut bytes);
+        let transcript = recording.into_transcript();
+
+        let mut replay = ReplayRng::new(&transcript);
+        replay.next_u64();
+        assert_eq!(
+            replay.finish(),
+            Err(TranscriptError::NotExhausted { remaining: 1 })
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "The replay diverged from the transcript at draw 1.")]
+    fn diverging_replay_panics() {
+        let mut recording = RecordingRng::new(OsRng);
+        recording.next_u64();
+        recording.fill_bytes(&mut [0u8; 16]);
+        let transcript = recording.into_transcript();
+
+        let mut replay = ReplayRng::new(&transcript);
+        replay.next_u64();
+        replay.fill_bytes(&mut [0u8; 8]);
+    }
+
+    #[test]
+    fn invalid_encodings_are_rejected() {
+        let transcript = Transcript {
+            draws: vec![Draw::U32(1), Draw::U64(2), Draw::Bytes(vec![3; 5])],
+        };
+        let bytes = transcript.to_bytes();
+        assert_eq!(Transcript::from_bytes(&bytes), Ok(transcript));
+        assert_eq!(
+            Transcript::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(TranscriptError::Truncated)
+        );
+        assert_eq!(
+            Transcript::from_bytes(&envelope::seal(ArtifactType::Transcript, &[4])),
+            Err(TranscriptError::InvalidTag(4))
+        );
+        assert_eq!(
+            Transcript::from_bytes(&[4]),
+            Err(TranscriptError::InvalidEncoding(DecodeError::InvalidHeader))
+        );
+    }
+}
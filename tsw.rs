@@ -1,15 +1,27 @@
-use std::{
+use alloc::vec::Vec;
+use core::{
     fmt,
     iter::Sum,
     ops::{Add, Index, Mul, Sub},
 };
 
 use ark_ff::{Field, UniformRand, Zero};
-use rand::thread_rng;
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
+use rand::{CryptoRng, RngCore};
+#[cfg(feature = "std")]
+use rand::{Rng, thread_rng};
+use subtle::{Choice, ConstantTimeEq};
 use thiserror::Error;
+#[cfg(feature = "zeroize")]
+use zeroize::{Zeroize, ZeroizeOnDrop};
 
 use crate::{
-    bls381_helpers::{G1G2, Scalar, hash_usize, multi_pairing},
+    bls381_helpers::{
+        DecodeError, G1G2, G2Prepared, OnceGlobal, Scalar, hash_usize, hash_with_domain_separation,
+        msm, multi_pairing, multi_pairing_prepared, prepare_g2,
+    },
+    constants::PROOF_OF_POSSESSION_DST,
+    envelope::{self, ArtifactType, HEADER_SIZE},
     lagrange::Lagrange,
     pedersen::{Commitment, get_parameters},
 };
@@ -29,7 +41,7 @@ impl Error {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq, Eq)]
 pub struct PublicParameters {
     hashed_indices: Vec<G1G2>,
 }
@@ -58,15 +70,24 @@ pub struct SecretKey {
 
 #[allow(clippy::new_without_default)]
 impl SecretKey {
+    #[cfg(feature = "std")]
     pub fn new() -> Self {
+        Self::random(&mut thread_rng())
+    }
+
+    pub fn random(rng: &mut (impl RngCore + CryptoRng)) -> Self {
         Self {
-            sk: Scalar::rand(&mut thread_rng()),
+            sk: Scalar::rand(rng),
         }
     }
 
-    pub fn into_shares(&self, num_shares: usize, t: usize) -> Vec<Self> {
-        let mut rng = thread_rng();
-        let mut sks: Vec<_> = (0..t - 1).map(|_| Scalar::rand(&mut rng)).collect();
+    pub fn into_shares(
+        &self,
+        num_shares: usize,
+        t: usize,
+        rng: &mut (impl RngCore + CryptoRng),
+    ) -> Vec<Self> {
+        let mut sks: Vec<_> = (0..t - 1).map(|_| Scalar::rand(rng)).collect();
 
         let mut base_points: Vec<_> = (1..=t).map(|i| Scalar::from(i as u64)).collect();
         for k in t..=num_shares {
@@ -99,12 +120,61 @@ impl SecretKey {
     ) -> Signature {
         Signature((&pp[index] + &commitment.0) * self.sk)
     }
+
+    /// Proves possession of the secret key by signing the serialized public key.
+    pub fn pop(&self) -> ProofOfPossession {
+        ProofOfPossession(pop_base(&self.to_public_key()) * self.sk)
+    }
+
+    /// Signs `msg` hashed to the curve with `domain`, like the serialized key of [Self::pop].
+    pub(crate) fn sign_message(&self, msg: &[u8], domain: &[u8]) -> Signature {
+        Signature(hash_with_domain_separation(msg, domain) * self.sk)
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl Zeroize for SecretKey {
+    fn zeroize(&mut self) {
+        self.sk.zeroize();
+    }
 }
 
-#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg(feature = "zeroize")]
+impl Drop for SecretKey {
+    fn drop(&mut self) {
+        self.zeroize();
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl ZeroizeOnDrop for SecretKey {}
+
+fn pop_base(pk: &PublicKey) -> G1G2 {
+    hash_with_domain_separation(&pk.0.to_bytes(), PROOF_OF_POSSESSION_DST)
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, CanonicalSerialize, CanonicalDeserialize)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct PublicKey(pub(crate) G1G2);
 
 impl PublicKey {
+    /// Size of the point behind its [envelope] header.
+    pub const ENCODED_SIZE: usize = HEADER_SIZE + G1G2::ENCODED_SIZE;
+
+    pub fn to_bytes(&self) -> [u8; Self::ENCODED_SIZE] {
+        let mut bytes = [0u8; Self::ENCODED_SIZE];
+        bytes[..HEADER_SIZE].copy_from_slice(&envelope::header(ArtifactType::PublicKey));
+        bytes[HEADER_SIZE..].copy_from_slice(&self.0.to_bytes());
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, DecodeError> {
+        match envelope::open(bytes, ArtifactType::PublicKey)? {
+            (1, payload) => G1G2::from_bytes(payload).map(Self),
+            (version, _) => Err(DecodeError::UnsupportedVersion(version)),
+        }
+    }
+
     pub fn from_secret_key_shares<'a, I>(shares: I, lagrange: &Lagrange) -> Self
     where
         I: Iterator<Item = &'a SecretKey>,
@@ -139,16 +209,96 @@ impl PublicKey {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(
+    Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, CanonicalSerialize, CanonicalDeserialize,
+)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Signature(pub(crate) G1G2);
 
+impl ConstantTimeEq for Signature {
+    fn ct_eq(&self, other: &Self) -> Choice {
+        self.0.ct_eq(&other.0)
+    }
+}
+
 impl Signature {
+    /// Size of the point behind its [envelope] header.
+    pub const ENCODED_SIZE: usize = HEADER_SIZE + G1G2::ENCODED_SIZE;
+
+    pub fn to_bytes(&self) -> [u8; Self::ENCODED_SIZE] {
+        let mut bytes = [0u8; Self::ENCODED_SIZE];
+        bytes[..HEADER_SIZE].copy_from_slice(&envelope::header(ArtifactType::Signature));
+        bytes[HEADER_SIZE..].copy_from_slice(&self.0.to_bytes());
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, DecodeError> {
+        match envelope::open(bytes, ArtifactType::Signature)? {
+            (1, payload) => G1G2::from_bytes(payload).map(Self),
+            (version, _) => Err(DecodeError::UnsupportedVersion(version)),
+        }
+    }
+
     pub fn from_shares(signatures: &[Self], lagrange: &Lagrange) -> Self {
-        signatures
-            .iter()
-            .enumerate()
-            .map(|(j, sig)| sig * lagrange.eval_j_0(j))
-            .sum()
+        let shares: Vec<_> = signatures.iter().map(|sig| sig.0.clone()).collect();
+        Self(msm(&shares, &lagrange.coefficients_0()[..shares.len()]))
+    }
+
+    /// Aggregates signatures on possibly different commitments under possibly different keys,
+    /// see [verify_aggregate].
+    pub fn aggregate(signatures: &[Self]) -> Self {
+        signatures.iter().sum()
+    }
+
+    /// Like [PublicKey::verify_pedersen_commitment], under a key prepared once for many
+    /// verifications.
+    pub fn verify_prepared(
+        &self,
+        pk: &PreparedPublicKey,
+        commitment: &Commitment,
+        index: usize,
+        pp: &PublicParameters,
+    ) -> Result<(), Error> {
+        let check = -(&pp[index] + &commitment.0);
+        if pk.pairings_hold(&check, &self.0) {
+            Ok(())
+        } else {
+            Err(Error::new())
+        }
+    }
+}
+
+/// Verifies an aggregate of the signatures on `(commitment, index)` under `pk` for all entries
+/// of `messages` with one multi-pairing. The keys have to come with a valid
+/// [ProofOfPossession], otherwise a rogue key can forge an aggregate.
+pub fn verify_aggregate(
+    messages: &[(&Commitment, usize, &PublicKey)],
+    aggregate: &Signature,
+    pp: &PublicParameters,
+) -> Result<(), Error> {
+    let pedersen_pp = get_parameters();
+
+    let checks: Vec<_> = messages
+        .iter()
+        .map(|(commitment, index, _)| -(&pp[*index] + &commitment.0))
+        .collect();
+    let mut lhs: Vec<_> = checks
+        .iter()
+        .zip(messages)
+        .map(|(check, (_, _, pk))| (check, &pk.0))
+        .collect();
+    lhs.push((&aggregate.0, &pedersen_pp.g));
+    let mut rhs: Vec<_> = checks
+        .iter()
+        .zip(messages)
+        .map(|(check, (_, _, pk))| (&pk.0, check))
+        .collect();
+    rhs.push((&pedersen_pp.g, &aggregate.0));
+
+    if multi_pairing(&lhs).is_zero() && multi_pairing(&rhs).is_zero() {
+        Ok(())
+    } else {
+        Err(Error::new())
     }
 }
 
@@ -157,8 +307,123 @@ impl PublicKey {
         let pp = get_parameters();
         multi_pairing(&[(&-&self.0, &pp.g), (&pp.g, &self.0)]).is_zero()
     }
+
+    /// Verifies independent signatures on `(commitment, index)` under this key at once. The
+    /// signatures and messages are combined with random 128-bit scalars, so the check costs two
+    /// multi-scalar multiplications and four pairings. An invalid signature passes with
+    /// probability at most 2^-128.
+    #[cfg(feature = "std")]
+    pub fn verify_batch(
+        &self,
+        messages: &[(&Commitment, usize, &Signature)],
+        pp: &PublicParameters,
+    ) -> Result<(), Error> {
+        PreparedPublicKey::new(self).verify_batch(messages, pp)
+    }
+
+    /// Verifies a [ProofOfPossession] of the secret key. A rogue key derived from the public
+    /// keys of others, e.g. to cancel them in an aggregate, has no valid proof.
+    pub fn verify_pop(&self, pop: &ProofOfPossession) -> bool {
+        let pedersen_pp = get_parameters();
+        let check = -pop_base(self);
+
+        multi_pairing(&[(&check, &self.0), (&pop.0, &pedersen_pp.g)]).is_zero()
+            && multi_pairing(&[(&self.0, &check), (&pedersen_pp.g, &pop.0)]).is_zero()
+    }
+
+    /// Verifies a signature of [SecretKey::sign_message] on `msg` with `domain`.
+    pub(crate) fn verify_message(&self, msg: &[u8], domain: &[u8], signature: &Signature) -> bool {
+        let pedersen_pp = get_parameters();
+        let check = -hash_with_domain_separation(msg, domain);
+
+        multi_pairing(&[(&check, &self.0), (&signature.0, &pedersen_pp.g)]).is_zero()
+            && multi_pairing(&[(&self.0, &check), (&pedersen_pp.g, &signature.0)]).is_zero()
+    }
+}
+
+/// A [PublicKey] with the Miller-loop precomputation of its G2 part, for many verifications
+/// under the same key, e.g. of the issuers' aggregate key by a registrar.
+#[derive(Clone)]
+pub struct PreparedPublicKey {
+    pk: PublicKey,
+    prepared: G2Prepared,
+}
+
+impl PreparedPublicKey {
+    pub fn new(pk: &PublicKey) -> Self {
+        Self {
+            pk: pk.clone(),
+            prepared: prepare_g2(&pk.0),
+        }
+    }
+
+    pub fn public_key(&self) -> &PublicKey {
+        &self.pk
+    }
+
+    /// [PublicKey::verify_batch] under the prepared key.
+    #[cfg(feature = "std")]
+    pub fn verify_batch(
+        &self,
+        messages: &[(&Commitment, usize, &Signature)],
+        pp: &PublicParameters,
+    ) -> Result<(), Error> {
+        let mut rng = thread_rng();
+
+        let rhos: Vec<_> = messages
+            .iter()
+            .map(|_| Scalar::from(rng.r#gen::<u128>()))
+            .collect();
+        let bases: Vec<_> = messages
+            .iter()
+            .map(|(commitment, index, _)| &pp[*index] + &commitment.0)
+            .collect();
+        let signatures: Vec<_> = messages
+            .iter()
+            .map(|(_, _, signature)| signature.0.clone())
+            .collect();
+        let check = -msm(&bases, &rhos);
+        let signature = msm(&signatures, &rhos);
+
+        if self.pairings_hold(&check, &signature) {
+            Ok(())
+        } else {
+            Err(Error::new())
+        }
+    }
+
+    // e(check, pk) e(signature, g) = 1 in both groups
+    fn pairings_hold(&self, check: &G1G2, signature: &G1G2) -> bool {
+        let g = &get_parameters().g;
+        multi_pairing_prepared(&[(check, &self.prepared), (signature, prepared_generator())])
+            .is_zero()
+            && multi_pairing(&[(&self.pk.0, check), (g, signature)]).is_zero()
+    }
+}
+
+impl From<&PublicKey> for PreparedPublicKey {
+    fn from(pk: &PublicKey) -> Self {
+        Self::new(pk)
+    }
+}
+
+impl fmt::Debug for PreparedPublicKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("PreparedPublicKey").field(&self.pk).finish()
+    }
+}
+
+// the prepared G2 part of the Pedersen generator `g`, with which every signature is paired
+fn prepared_generator() -> &'static G2Prepared {
+    static INSTANCE: OnceGlobal<G2Prepared> = OnceGlobal::new();
+    INSTANCE.get_or_init(|| prepare_g2(&get_parameters().g))
 }
 
+/// Signature of a [PublicKey] under its own secret key, see [SecretKey::pop].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ProofOfPossession(pub(crate) G1G2);
+
 impl Mul<Scalar> for &PublicKey {
     type Output = PublicKey;
 
@@ -280,10 +545,24 @@ impl<'a> Sum<&'a Self> for Signature {
 
 #[cfg(test)]
 mod test {
-    use crate::{bls381_helpers::pairing, pedersen::MultiBasePublicParameters};
+    use proptest::{prop_assert_eq, proptest};
+
+    use crate::{
+        arbitrary,
+        bls381_helpers::{pairing, test::generator_multiple},
+        pedersen::MultiBasePublicParameters,
+    };
 
     use super::*;
 
+    #[cfg(feature = "zeroize")]
+    #[test]
+    fn secret_key_zeroized_on_drop() {
+        use crate::bls381_helpers::test::assert_zeroized_on_drop;
+
+        assert_zeroized_on_drop(SecretKey::new(), |sk| vec![sk.sk]);
+    }
+
     #[test]
     fn tsw() {
         let pp = PublicParameters::new(2);
@@ -292,7 +571,7 @@ mod test {
 
         let (cm, _) = Commitment::commit(&Scalar::rand(&mut rand::thread_rng()));
         let sk = SecretKey::new();
-        let sks = sk.into_shares(n, t);
+        let sks = sk.into_shares(n, t, &mut thread_rng());
         let sigs: Vec<_> = sks
             .iter()
             .map(|sk| sk.sign_pedersen_commitment(&cm, 0, &pp))
@@ -352,6 +631,158 @@ mod test {
         assert!(pk.verify_pedersen_commitment(&cm, 2, &sig, &pp).is_err());
     }
 
+    #[test]
+    fn signature_bytes_kat() {
+        const KAT: &str = "5350525301000289ece308f9d1f0131765212deca99697b112d61f9be9a5f1f3780a51335b3ff981747a0b2ca2179b96d2c0c9024e522489380275bbc8e5dcea7dc4dd7e0550ff2ac480905396eda55062650f8d251c96eb480673937cc6d9d6a44aaa56ca66dc122915c824a0857e2ee414a3dccb23ae691ae54329781315a0c75df1c04d6d7a50a030fc866f09d516020ef82324afae";
+
+        let sig = Signature(generator_multiple(3));
+        let bytes = sig.to_bytes();
+        assert_eq!(hex::encode(bytes), KAT);
+        assert_eq!(Signature::from_bytes(&bytes), Ok(sig));
+        assert_eq!(
+            Signature::from_bytes(&Signature(G1G2::default()).to_bytes()),
+            Err(DecodeError::Identity)
+        );
+    }
+
+    #[test]
+    fn proof_of_possession() {
+        let sk = SecretKey::new();
+        let pk = sk.to_public_key();
+        let pop = sk.pop();
+        assert!(pk.verify_pop(&pop));
+        assert!(!SecretKey::new().to_public_key().verify_pop(&pop));
+
+        // a rogue key cancelling `pk` in the aggregate has no proof under the attacker's key
+        let attacker = SecretKey::new();
+        let rogue = &attacker.to_public_key() - &pk;
+        assert_eq!(pk.clone() + &rogue, attacker.to_public_key());
+        assert!(!rogue.verify_pop(&attacker.pop()));
+        assert!(!rogue.verify_pop(&pop));
+    }
+
+    fn signed_commitments(
+        sk: &SecretKey,
+        count: usize,
+        pp: &PublicParameters,
+    ) -> Vec<(Commitment, Signature)> {
+        (0..count)
+            .map(|i| {
+                let (cm, _) = Commitment::commit(&Scalar::rand(&mut thread_rng()));
+                let sig = sk.sign_pedersen_commitment(&cm, i % 2, pp);
+                (cm, sig)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn aggregate() {
+        let pp = PublicParameters::new(2);
+        let sks: Vec<_> = (0..4).map(|_| SecretKey::new()).collect();
+        let pks: Vec<_> = sks.iter().map(SecretKey::to_public_key).collect();
+        let mut signed: Vec<_> = sks
+            .iter()
+            .map(|sk| signed_commitments(sk, 1, &pp).remove(0))
+            .collect();
+        let messages: Vec<_> = signed
+            .iter()
+            .zip(&pks)
+            .map(|((cm, _), pk)| (cm, 0, pk))
+            .collect();
+        let sigs: Vec<_> = signed.iter().map(|(_, sig)| sig.clone()).collect();
+        assert!(verify_aggregate(&messages, &Signature::aggregate(&sigs), &pp).is_ok());
+        assert!(verify_aggregate(&messages[1..], &Signature::aggregate(&sigs), &pp).is_err());
+
+        // one signature under the wrong key poisons the aggregate, and individual verification
+        // identifies it
+        signed[2].1 = sks[1].sign_pedersen_commitment(&signed[2].0, 0, &pp);
+        let sigs: Vec<_> = signed.iter().map(|(_, sig)| sig.clone()).collect();
+        let messages: Vec<_> = signed
+            .iter()
+            .zip(&pks)
+            .map(|((cm, _), pk)| (cm, 0, pk))
+            .collect();
+        assert!(verify_aggregate(&messages, &Signature::aggregate(&sigs), &pp).is_err());
+        let invalid: Vec<_> = messages
+            .iter()
+            .zip(&sigs)
+            .enumerate()
+            .filter(|(_, ((cm, index, pk), sig))| {
+                pk.verify_pedersen_commitment(cm, *index, sig, &pp).is_err()
+            })
+            .map(|(i, _)| i)
+            .collect();
+        assert_eq!(invalid, vec![2]);
+    }
+
+    #[test]
+    fn batch() {
+        let pp = PublicParameters::new(2);
+        let sk = SecretKey::new();
+        let pk = sk.to_public_key();
+        let mut signed = signed_commitments(&sk, 8, &pp);
+        let messages: Vec<_> = signed
+            .iter()
+            .enumerate()
+            .map(|(i, (cm, sig))| (cm, i % 2, sig))
+            .collect();
+        assert!(pk.verify_batch(&messages, &pp).is_ok());
+        assert!(pk.verify_batch(&[], &pp).is_ok());
+        assert!(
+            SecretKey::new()
+                .to_public_key()
+                .verify_batch(&messages, &pp)
+                .is_err()
+        );
+
+        // swapping two valid signatures keeps the sums but not the random combination
+        let (first, rest) = signed.split_at_mut(1);
+        std::mem::swap(&mut first[0].1, &mut rest[1].1);
+        let messages: Vec<_> = signed
+            .iter()
+            .enumerate()
+            .map(|(i, (cm, sig))| (cm, i % 2, sig))
+            .collect();
+        assert!(pk.verify_batch(&messages, &pp).is_err());
+    }
+
+    #[test]
+    fn prepared_verification_agrees() {
+        let pp = PublicParameters::new(2);
+        let sk = SecretKey::new();
+        let pk = sk.to_public_key();
+        let prepared = PreparedPublicKey::from(&pk);
+        let other = PreparedPublicKey::new(&SecretKey::new().to_public_key());
+        let signed = signed_commitments(&sk, 4, &pp);
+
+        for (i, (cm, sig)) in signed.iter().enumerate() {
+            // the signed index, another index, another commitment, and another key
+            let (other_cm, _) = &signed[(i + 1) % signed.len()];
+            for (cm, index, key) in [
+                (cm, i % 2, &prepared),
+                (cm, (i + 1) % 2, &prepared),
+                (other_cm, i % 2, &prepared),
+                (cm, i % 2, &other),
+            ] {
+                assert_eq!(
+                    sig.verify_prepared(key, cm, index, &pp).is_ok(),
+                    key.public_key()
+                        .verify_pedersen_commitment(cm, index, sig, &pp)
+                        .is_ok()
+                );
+            }
+            assert!(sig.verify_prepared(&prepared, cm, i % 2, &pp).is_ok());
+        }
+
+        let messages: Vec<_> = signed
+            .iter()
+            .enumerate()
+            .map(|(i, (cm, sig))| (cm, i % 2, sig))
+            .collect();
+        assert!(prepared.verify_batch(&messages, &pp).is_ok());
+        assert!(other.verify_batch(&messages, &pp).is_err());
+    }
+
     #[test]
     fn pk_sum() {
         let sk_1 = SecretKey::new();
@@ -363,6 +794,22 @@ mod test {
         assert_eq!(pk_1.clone() + &pk_2, [pk_1, pk_2].iter().sum());
     }
 
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_signature() {
+        use crate::bls381_helpers::serde_round_trip;
+
+        let pp = PublicParameters::new(1);
+        let (cm, _) = Commitment::commit(&Scalar::rand(&mut rand::thread_rng()));
+        let sk = SecretKey::new();
+        let pk = sk.to_public_key();
+        let sig = sk.sign_pedersen_commitment(&cm, 0, &pp);
+
+        assert_eq!(serde_round_trip(&pk), pk);
+        let sig = serde_round_trip(&sig);
+        assert!(pk.verify_pedersen_commitment(&cm, 0, &sig, &pp).is_ok());
+    }
+
     #[test]
     fn sw_multi_index_commitment() {
         const L: usize = 10;
@@ -399,4 +846,18 @@ mod test {
         assert_eq!(pairing(&sigma.0, &pedersen_pp.g), pairing(&check, &pk.0));
         assert_eq!(pairing(&pedersen_pp.g, &sigma.0), pairing(&pk.0, &check));
     }
+
+    proptest! {
+        #[test]
+        fn signature_bytes_round_trip(sig in arbitrary::signature()) {
+            prop_assert_eq!(Signature::from_bytes(&sig.to_bytes()), Ok(sig.clone()));
+            prop_assert_eq!(arbitrary::round_trip(&sig).unwrap(), sig);
+        }
+
+        #[test]
+        fn public_key_bytes_round_trip(pk in arbitrary::public_key()) {
+            prop_assert_eq!(PublicKey::from_bytes(&pk.to_bytes()), Ok(pk.clone()));
+            prop_assert_eq!(arbitrary::round_trip(&pk).unwrap(), pk);
+        }
+    }
 }
@@ -0,0 +1,333 @@
+//! Traces that run on a server, with proofs of the matches: the server commits to its confirmed
+//! set in a [ConfirmedTree], a Merkle tree over the sorted [G1G2::to_bytes] encodings of the
+//! confirmed ElIDs, and returns a membership proof for every ElID of the user's that it
+//! matched. [spirit_trace_verifiable] recomputes the count and the alarm from the proofs instead
+//! of trusting the server's alarm bit.
+//!
+//! The proofs are asymmetric. A proven match cannot be made up, so the count is a lower bound
+//! and an alarm is proven. A server can however withhold the proof of a match, and nothing in
+//! the remaining proofs shows it. A result without alarm is only proven if the committed set
+//! has fewer than `exposure_limit` ElIDs, see [ConfirmedRoot::bounds_no_alarm]. Otherwise a
+//! user who holds an ElID that might be confirmed, e.g. of a contact who reported, asks for an
+//! [AbsenceProof]: the sorted leaves make the proofs of absence of confirmed ElIDs impossible.
+//!
+//! The root has to reach the user independently of the server's answer, e.g. published with the
+//! confirmed set, or the server could commit to a different set.
+
+use std::collections::HashSet;
+
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+use crate::{
+    G1G2,
+    registry::{MembershipProof, MerkleHash, merkle_levels, merkle_path},
+    spirit::{ElID, EncounterTable, TraceResult},
+};
+
+fn leaf(encoding: &[u8; G1G2::ENCODED_SIZE]) -> MerkleHash {
+    let mut digest = Sha256::new();
+    digest.update([0u8]);
+    digest.update(encoding);
+    digest.finalize().into()
+}
+
+/// Commitment of a server to its confirmed set of `size` distinct ElIDs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ConfirmedRoot {
+    pub root: MerkleHash,
+    pub size: usize,
+}
+
+impl ConfirmedRoot {
+    /// Whether the committed set is too small to raise the alarm at `exposure_limit`, so that a
+    /// result without alarm is proven, too. A limit of 0 is treated like 1.
+    pub fn bounds_no_alarm(&self, exposure_limit: usize) -> bool {
+        self.size < exposure_limit.max(1)
+    }
+}
+
+/// Proof that `el_id` is in the confirmed set, returned by [ConfirmedTree::trace].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MatchProof {
+    pub el_id: ElID,
+    pub proof: MembershipProof,
+}
+
+/// Proof that an ElID is not in the confirmed set: the proofs of the confirmed ElIDs right
+/// before and after it in the sorted order, of which the first or last one is missing at the
+/// ends of the order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AbsenceProof {
+    pub(crate) before: Option<MatchProof>,
+    pub(crate) after: Option<MatchProof>,
+}
+
+impl AbsenceProof {
+    /// Checks that `el_id` is not in the set committed to by `root`.
+    pub fn verify(&self, root: &ConfirmedRoot, el_id: &ElID) -> bool {
+        let encoding = el_id.to_bytes();
+        let verified = |neighbour: &MatchProof| {
+            let bytes = neighbour.el_id.to_bytes();
+            let valid = neighbour
+                .proof
+                .verify_leaf(&root.root, root.size, leaf(&bytes));
+            valid.then_some((neighbour.proof.index, bytes))
+        };
+
+        match (&self.before, &self.after) {
+            (None, None) => root.size == 0,
+            (Some(before), None) => verified(before)
+                .is_some_and(|(index, before)| index + 1 == root.size && before < encoding),
+            (None, Some(after)) => {
+                verified(after).is_some_and(|(index, after)| index == 0 && encoding < after)
+            }
+            (Some(before), Some(after)) => match (verified(before), verified(after)) {
+                (Some((i, before)), Some((j, after))) => {
+                    i + 1 == j && before < encoding && encoding < after
+                }
+                _ => false,
+            },
+        }
+    }
+}
+
+/// Merkle tree of a server over its confirmed set. The leaves are the hashes of the distinct
+/// ElIDs' encodings in ascending order, so the tree of a set does not depend on the order in
+/// which its ElIDs were confirmed.
+#[derive(Debug, Clone)]
+pub struct ConfirmedTree {
+    el_ids: Vec<[u8; G1G2::ENCODED_SIZE]>,
+    levels: Vec<Vec<MerkleHash>>,
+}
+
+impl ConfirmedTree {
+    pub fn new<'a>(cf: impl IntoIterator<Item = &'a ElID>) -> Self {
+        let mut el_ids: Vec<_> = cf.into_iter().map(G1G2::to_bytes).collect();
+        el_ids.sort_unstable();
+        el_ids.dedup();
+        let levels = merkle_levels(el_ids.iter().map(leaf).collect());
+        Self { el_ids, levels }
+    }
+
+    pub fn root(&self) -> ConfirmedRoot {
+        ConfirmedRoot {
+            root: self.levels[self.levels.len() - 1]
+                .first()
+                .copied()
+                .unwrap_or_default(),
+            size: self.el_ids.len(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.el_ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.el_ids.is_empty()
+    }
+
+    /// The membership proof of `el_id`, or `None` if it is not confirmed.
+    pub fn prove(&self, el_id: &ElID) -> Option<MatchProof> {
+        let index = self.el_ids.binary_search(&el_id.to_bytes()).ok()?;
+        Some(self.proof_at(index, el_id.clone()))
+    }
+
+    /// The proof that `el_id` is not confirmed, or `None` if it is.
+    pub fn prove_absent(&self, el_id: &ElID) -> Option<AbsenceProof> {
+        let index = self.el_ids.binary_search(&el_id.to_bytes()).err()?;
+        let neighbour = |index: usize| {
+            let el_id = G1G2::from_bytes(&self.el_ids[index]).expect("confirmed ElIDs are valid");
+            self.proof_at(index, el_id)
+        };
+        Some(AbsenceProof {
+            before: index.checked_sub(1).map(neighbour),
+            after: (index < self.el_ids.len()).then(|| neighbour(index)),
+        })
+    }
+
+    /// Server side of a verifiable trace: the proofs of the user's ElIDs `el_ids` that are
+    /// confirmed, for [spirit_trace_verifiable].
+    pub fn trace<'a>(&self, el_ids: impl IntoIterator<Item = &'a ElID>) -> Vec<MatchProof> {
+        el_ids
+            .into_iter()
+            .filter_map(|el_id| self.prove(el_id))
+            .collect()
+    }
+
+    fn proof_at(&self, index: usize, el_id: ElID) -> MatchProof {
+        MatchProof {
+            el_id,
+            proof: merkle_path(&self.levels, index),
+        }
+    }
+}
+
+#[derive(Error, Debug, PartialEq, Eq, Clone)]
+pub enum VerifiableTraceError {
+    #[error("The membership proof of a matched ElID is invalid.")]
+    InvalidProof { el_id: ElID },
+}
+
+/// Like [spirit_trace](crate::spirit::spirit_trace), but counts the ElIDs of `t_el` whose
+/// membership in the set committed to by `cf_root` is proven by `proofs`. Proofs of ElIDs that
+/// are not in `t_el` are ignored, and every ElID counts once. The count is a lower bound: see the
+/// [module documentation](self) for results without alarm.
+pub fn spirit_trace_verifiable(
+    cf_root: &ConfirmedRoot,
+    proofs: &[MatchProof],
+    t_el: &EncounterTable,
+    exposure_limit: usize,
+) -> Result<TraceResult, VerifiableTraceError> {
+    let (mut matched, mut seen) = (Vec::new(), HashSet::new());
+    for MatchProof { el_id, proof } in proofs {
+        if !t_el.contains(el_id) || !seen.insert(el_id) {
+            continue;
+        }
+        if !proof.verify_leaf(&cf_root.root, cf_root.size, leaf(&el_id.to_bytes())) {
+            return Err(VerifiableTraceError::InvalidProof {
+                el_id: el_id.clone(),
+            });
+        }
+        matched.push(el_id.clone());
+    }
+
+    Ok(TraceResult {
+        count: matched.len(),
+        alarm: matched.len() >= exposure_limit.max(1),
+        matched,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use rand::rngs::OsRng;
+
+    use super::*;
+    use crate::{
+        elgamal::DecryptionKey,
+        spirit::{DomainConfig, Interval, UserKeys, spirit_broadcast_osrng, spirit_trace},
+    };
+
+    // the user's table of 8 broadcasts, and a confirmed set with the first 4 of them
+    fn confirmed_encounters() -> (EncounterTable, Vec<ElID>, Vec<ElID>) {
+        let domain = DomainConfig::default();
+        let keys = UserKeys::random(OsRng);
+        let pk_ha = DecryptionKey::random(&mut OsRng).encryption_key();
+        let mut t_el = EncounterTable::new();
+        let own: Vec<_> = (0..8)
+            .map(|i| spirit_broadcast_osrng(&domain, 0, Interval(i), &keys, &pk_ha, &mut t_el).0)
+            .collect();
+        let cf = own[..4]
+            .iter()
+            .cloned()
+            .chain((0..12).map(|_| G1G2::random(OsRng)))
+            .collect();
+        (t_el, own, cf)
+    }
+
+    #[test]
+    fn verifiable_traces_count_like_plain_ones() {
+        let (t_el, own, cf) = confirmed_encounters();
+        let tree = ConfirmedTree::new(&cf);
+        let root = tree.root();
+        assert_eq!(root.size, 16);
+        // the root does not depend on the order or repetitions of the confirmed ElIDs
+        let mut reordered = cf.clone();
+        reordered.reverse();
+        reordered.push(cf[0].clone());
+        assert_eq!(ConfirmedTree::new(&reordered).root(), root);
+
+        let proofs = tree.trace(&own);
+        assert_eq!(proofs.len(), 4);
+        for limit in [1, 4, 5] {
+            assert_eq!(
+                spirit_trace_verifiable(&root, &proofs, &t_el, limit),
+                Ok(spirit_trace(&cf, &t_el, limit))
+            );
+        }
+        // repeated proofs count once, proofs of foreign ElIDs not at all
+        let mut padded = proofs.clone();
+        padded.extend(proofs.clone());
+        padded.push(tree.prove(&cf[10]).unwrap());
+        assert_eq!(
+            spirit_trace_verifiable(&root, &padded, &t_el, 1)
+                .unwrap()
+                .count,
+            4
+        );
+
+        // a proof against another root is rejected
+        let other = ConfirmedTree::new(&cf[..15]).root();
+        assert_eq!(
+            spirit_trace_verifiable(&other, &proofs, &t_el, 1),
+            Err(VerifiableTraceError::InvalidProof {
+                el_id: own[0].clone()
+            })
+        );
+        assert!(root.bounds_no_alarm(17) && !root.bounds_no_alarm(16));
+        assert!(ConfirmedTree::new(&[]).root().bounds_no_alarm(0));
+    }
+
+    #[test]
+    fn withheld_matches_are_detected() {
+        let (t_el, own, cf) = confirmed_encounters();
+        let tree = ConfirmedTree::new(&cf);
+        let root = tree.root();
+
+        // the server withholds the proof of a match, which drops the count below the limit
+        let withheld = &own[2];
+        let proofs: Vec<_> = tree
+            .trace(&own)
+            .into_iter()
+            .filter(|proof| proof.el_id != *withheld)
+            .collect();
+        let result = spirit_trace_verifiable(&root, &proofs, &t_el, 4).unwrap();
+        assert_eq!((result.count, result.alarm), (3, false));
+        assert!(!root.bounds_no_alarm(4));
+
+        // but cannot prove that the user's ElID is absent
+        assert!(tree.prove_absent(withheld).is_none());
+        let index = tree.el_ids.binary_search(&withheld.to_bytes()).unwrap();
+        let around = |index: usize| {
+            let el_id = G1G2::from_bytes(&tree.el_ids[index]).unwrap();
+            tree.proof_at(index, el_id)
+        };
+        let forged = [
+            AbsenceProof {
+                before: index.checked_sub(1).map(around),
+                after: (index + 1 < tree.len()).then(|| around(index + 1)),
+            },
+            AbsenceProof {
+                before: None,
+                after: Some(around(index)),
+            },
+            AbsenceProof {
+                before: Some(around(index)),
+                after: None,
+            },
+            AbsenceProof {
+                before: None,
+                after: None,
+            },
+        ];
+        for absence in &forged {
+            assert!(!absence.verify(&root, withheld));
+        }
+
+        // the unconfirmed ElIDs of the user are proven absent
+        for el_id in &own[4..] {
+            assert!(tree.prove_absent(el_id).unwrap().verify(&root, el_id));
+            assert!(!tree.prove_absent(el_id).unwrap().verify(&root, withheld));
+        }
+        let empty = ConfirmedTree::new(&[]);
+        assert!(
+            empty
+                .prove_absent(withheld)
+                .unwrap()
+                .verify(&empty.root(), withheld)
+        );
+    }
+}
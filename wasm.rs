@@ -0,0 +1,278 @@
+use std::cell::RefCell;
+
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
+use js_sys::Uint8Array;
+use rand::rngs::OsRng;
+use wasm_bindgen::prelude::*;
+
+use crate::{
+    Scalar,
+    atact::{BlindToken, PublicParameters, StRG},
+    spirit::{
+        DomainConfig, Epoch, Interval, PendingRegistration, ReportChallenge, Token, UserKeys,
+        prf_broadcast, spirit_client_parameters, spirit_diagnosis, spirit_register_begin,
+        spirit_register_finish,
+    },
+    tsw::PublicKey,
+};
+
+/// Deployment set by [wasm_init] and the registration started by [wasm_token_request]. The
+/// blinding randomness never leaves the module.
+#[derive(Default)]
+struct Client {
+    deployment: Option<(DomainConfig, PublicParameters)>,
+    pending: Option<PendingRegistration>,
+}
+
+thread_local! {
+    static CLIENT: RefCell<Client> = RefCell::default();
+}
+
+/// Result of [wasm_finalize_registration]: the encoded `(Token, StRG)` pair the user keeps for
+/// [wasm_diagnose], and the encoded unblinded tACT token and its proof the registrar checks with
+/// [spirit_complete_registration](crate::spirit::spirit_complete_registration).
+#[wasm_bindgen]
+pub struct WasmRegistration {
+    ppu: Vec<u8>,
+    registration: Vec<u8>,
+}
+
+#[wasm_bindgen]
+impl WasmRegistration {
+    #[wasm_bindgen(getter)]
+    pub fn ppu(&self) -> Uint8Array {
+        Uint8Array::from(&self.ppu[..])
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn registration(&self) -> Uint8Array {
+        Uint8Array::from(&self.registration[..])
+    }
+}
+
+fn encode(value: &impl CanonicalSerialize) -> Result<Vec<u8>, JsError> {
+    let mut bytes = Vec::with_capacity(value.compressed_size());
+    value
+        .serialize_compressed(&mut bytes)
+        .map_err(|err| JsError::new(&err.to_string()))?;
+    Ok(bytes)
+}
+
+fn decode<T: CanonicalDeserialize>(bytes: &[u8]) -> Result<T, JsError> {
+    T::deserialize_compressed(bytes).map_err(|err| JsError::new(&err.to_string()))
+}
+
+fn with_deployment<T>(
+    f: impl FnOnce(&DomainConfig, &PublicParameters) -> Result<T, JsError>,
+) -> Result<T, JsError> {
+    CLIENT.with_borrow(|client| match &client.deployment {
+        Some((domain, pp)) => f(domain, pp),
+        None => Err(JsError::new("wasm_init has not been called.")),
+    })
+}
+
+/// Sets up the client for the deployment `app_id` whose issuers share the tACT public key `pk`,
+/// as given by [PublicKey::to_bytes], with the parameters `n` and `t` of
+/// [spirit_setup](crate::spirit::spirit_setup). Discards a pending registration.
+#[wasm_bindgen]
+pub fn wasm_init(app_id: &[u8], pk: &[u8], n: usize, t: usize) -> Result<(), JsError> {
+    let pk = PublicKey::from_bytes(pk).map_err(|err| JsError::new(&err.to_string()))?;
+    let domain = DomainConfig::new(app_id.to_vec());
+    let pp = spirit_client_parameters(&domain, pk, n, t)
+        .map_err(|err| JsError::new(&err.to_string()))?;
+
+    CLIENT.with_borrow_mut(|client| {
+        client.deployment = Some((domain, pp));
+        client.pending = None;
+    });
+    Ok(())
+}
+
+/// Starts the registration of the user with the encoded identity scalar `id` for a token that
+/// expires at the start of epoch `expiry`, and returns the encoded blind request for the
+/// issuers, together with its [IdentityClaim](crate::spirit::IdentityClaim).
+#[wasm_bindgen]
+pub fn wasm_token_request(id: &[u8], expiry: Epoch) -> Result<Uint8Array, JsError> {
+    let id_u: Scalar = decode(id)?;
+    let (blind_request, claim, pending) = with_deployment(|domain, pp| {
+        let (blind_request, pending) = spirit_register_begin(domain, &id_u, pp, expiry, &mut OsRng)
+            .map_err(|err| JsError::new(&err.to_string()))?;
+        let claim = pending.identity_claim(domain, &mut OsRng);
+        Ok((blind_request, claim, pending))
+    })?;
+
+    CLIENT.with_borrow_mut(|client| client.pending = Some(pending));
+    Ok(Uint8Array::from(&encode(&(blind_request, claim))?[..]))
+}
+
+/// Unblinds the encoded blind tokens of the first `t` issuers, ordered by issuer, into the token
+/// requested with [wasm_token_request], and proves the unblinding to the registrar.
+#[wasm_bindgen]
+pub fn wasm_finalize_registration(blind_tokens: &[u8]) -> Result<WasmRegistration, JsError> {
+    let blind_tokens: Vec<Vec<BlindToken>> = decode(blind_tokens)?;
+    let pending = CLIENT
+        .with_borrow_mut(|client| client.pending.take())
+        .ok_or_else(|| JsError::new("wasm_token_request has not been called."))?;
+
+    with_deployment(|domain, pp| {
+        let (ppu, token, token_proof) =
+            spirit_register_finish(domain, pending, &blind_tokens, pp, &mut OsRng)
+                .map_err(|err| JsError::new(&err.to_string()))?;
+        Ok(WasmRegistration {
+            ppu: encode(&ppu)?,
+            registration: encode(&(token, token_proof))?,
+        })
+    })
+}
+
+/// Returns the encoded ElID of interval `i` of `epoch` for the encoded key `prv`.
+#[wasm_bindgen]
+pub fn wasm_broadcast(prv: &[u8], epoch: Epoch, i: u32) -> Result<Uint8Array, JsError> {
+    let keys = UserKeys::new(decode(prv)?);
+    with_deployment(|domain, _| {
+        let el_id = prf_broadcast(
+            domain,
+            &keys.epoch_key(epoch),
+            epoch,
+            Interval(u64::from(i)),
+        );
+        Ok(Uint8Array::from(&el_id.to_bytes()[..]))
+    })
+}
+
+/// Discloses the ElIDs of the `indices` of `epoch` for the encoded key `prv` and the encoded
+/// `ppu` of [WasmRegistration::ppu] in a report submitted in `report_epoch` for the encoded
+/// `challenge` of the verifier, and returns the encoded trace report.
+#[wasm_bindgen]
+pub fn wasm_diagnose(
+    ppu: &[u8],
+    prv: &[u8],
+    epoch: Epoch,
+    indices: &[u32],
+    report_epoch: Epoch,
+    challenge: &[u8],
+) -> Result<Uint8Array, JsError> {
+    let ppu: (Token, StRG) = decode(ppu)?;
+    let keys = UserKeys::new(decode(prv)?);
+    let challenge: ReportChallenge = decode(challenge)?;
+    let cp = indices
+        .iter()
+        .map(|index| (epoch, Interval(u64::from(*index))))
+        .collect();
+
+    with_deployment(|domain, _| {
+        let report = spirit_diagnosis(
+            domain,
+            &ppu,
+            &keys,
+            None,
+            &cp,
+            report_epoch,
+            &challenge,
+            &[],
+            &mut OsRng,
+        )
+        .map_err(|err| JsError::new(&err.to_string()))?;
+        Ok(Uint8Array::from(&encode(&report)?[..]))
+    })
+}
+
+#[cfg(all(test, target_arch = "wasm32"))]
+mod test {
+    use std::collections::HashSet;
+
+    use ark_ff::UniformRand;
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    use super::*;
+    use crate::{
+        atact::{self, BlindRequest, TokenProof, tissue},
+        registry::RevocationList,
+        spirit::{
+            Accepted, ChallengeSet, ElID, IdentityClaim, SetupOutput, TraceReport,
+            spirit_complete_registration, spirit_setup_osrng, spirit_verify,
+        },
+    };
+
+    const APP_ID: &[u8] = b"wasm-test";
+    const EPOCH: Epoch = 1;
+    const EXPIRY: Epoch = 100;
+
+    #[wasm_bindgen_test]
+    fn register_broadcast_diagnose() -> Result<(), JsValue> {
+        // registrar and issuers, in native code
+        let SetupOutput {
+            pp,
+            issuers,
+            mut t_rgstr,
+            domain,
+            ..
+        } = spirit_setup_osrng(3, 4, 4, APP_ID).unwrap();
+        wasm_init(APP_ID, &pp.pk.to_bytes(), pp.n, pp.t)?;
+
+        let blind_request = wasm_token_request(&encode(&Scalar::rand(&mut OsRng))?, EXPIRY)?;
+        let (blind_request, claim): (BlindRequest, IdentityClaim) =
+            decode(&blind_request.to_vec())?;
+        assert_eq!(claim.verify(&domain, &blind_request), Ok(()));
+        let blind_tokens: Vec<_> = issuers[..pp.t]
+            .iter()
+            .map(|issuer| tissue(&blind_request, issuer, &pp).unwrap())
+            .collect();
+
+        let registration = wasm_finalize_registration(&encode(&blind_tokens)?)?;
+        let (token, token_proof): (atact::Token, TokenProof) =
+            decode(&registration.registration().to_vec())?;
+        let registered = spirit_complete_registration(
+            &domain,
+            &blind_request,
+            &token,
+            &token_proof,
+            &pp,
+            EXPIRY,
+            &mut t_rgstr,
+        )
+        .unwrap();
+
+        let ppu = registration.ppu().to_vec();
+        let (ppu_token, _): (Token, StRG) = decode(&ppu)?;
+        assert_eq!(ppu_token, registered);
+
+        let prv = encode(&Scalar::rand(&mut OsRng))?;
+        let el_ids = (0..4)
+            .map(|i| Ok(ElID::from_bytes(&wasm_broadcast(&prv, EPOCH, i)?.to_vec()).unwrap()))
+            .collect::<Result<Vec<_>, JsError>>()?;
+
+        let mut challenges = ChallengeSet::new(0);
+        let challenge = encode(&challenges.issue(EPOCH, &mut OsRng))?;
+        let report: TraceReport =
+            decode(&wasm_diagnose(&ppu, &prv, EPOCH, &[0, 2], EPOCH, &challenge)?.to_vec())?;
+        let mut cp = HashSet::new();
+        assert_eq!(
+            spirit_verify(
+                &domain,
+                &report,
+                &t_rgstr,
+                &RevocationList::new(),
+                EPOCH,
+                &mut HashSet::new(),
+                &challenges,
+                &mut cp
+            ),
+            Ok(Accepted {
+                verdicts: vec![true, true]
+            })
+        );
+        assert_eq!(cp, HashSet::from([el_ids[0].clone(), el_ids[2].clone()]));
+        Ok(())
+    }
+
+    #[wasm_bindgen_test]
+    fn finalize_without_request_fails() -> Result<(), JsValue> {
+        let SetupOutput { pp, .. } = spirit_setup_osrng(3, 4, 4, APP_ID).unwrap();
+        wasm_init(APP_ID, &pp.pk.to_bytes(), pp.n, pp.t)?;
+
+        let blind_tokens: Vec<Vec<BlindToken>> = Vec::new();
+        assert!(wasm_finalize_registration(&encode(&blind_tokens)?).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,682 @@
+use alloc::vec::Vec;
+
+use thiserror::Error;
+
+#[cfg(feature = "async")]
+use crate::issuer::PingResponse;
+use crate::{
+    DecodeError, G1G2,
+    atact::{BlindRequest, BlindToken},
+    pedersen::Commitment,
+    spirit::{Token, TokenId},
+    tsw::Signature,
+};
+
+/// First bytes of every frame.
+pub const MAGIC: [u8; 4] = *b"SPRT";
+/// Version of the frame format. Frames of other versions are rejected.
+///
+/// Version 2 derives the challenges of the token and trace report proofs from a labelled
+/// transcript, so proofs of version 1 do not verify. Version 3 hashes a
+/// [PrfPurpose](crate::spirit::PrfPurpose) into every evaluation of the PRF, so the ElIDs and
+/// nullifiers of version 2 do not match, and neither do the keys of a user's devices. Version 4
+/// appends the [fingerprint](BlindRequest::fingerprint) of the parameters to blind requests.
+pub const VERSION: u8 = 4;
+
+const HEADER_SIZE: usize = MAGIC.len() + 2;
+const LENGTH_SIZE: usize = size_of::<u32>();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum MessageType {
+    BlindRequest = 1,
+    BlindTokenResponse = 2,
+    RegistryUpdate = 3,
+    PingRequest = 4,
+    PingResponse = 5,
+}
+
+#[derive(Error, Debug, PartialEq, Eq, Clone)]
+pub enum WireError {
+    #[error("Invalid magic {0:02x?}.")]
+    InvalidMagic([u8; 4]),
+    #[error("Unsupported wire version {0}, expected {VERSION}.")]
+    UnsupportedVersion(u8),
+    #[error("Unexpected message type {actual}, expected {expected:?}.")]
+    UnexpectedMessageType { expected: MessageType, actual: u8 },
+    #[error("Truncated frame: {field} needs {expected} bytes, got {actual}.")]
+    Truncated {
+        field: &'static str,
+        expected: usize,
+        actual: usize,
+    },
+    #[error("Frame has {0} trailing bytes.")]
+    TrailingBytes(usize),
+    #[error("Field {field} has invalid length {length}.")]
+    InvalidFieldLength { field: &'static str, length: usize },
+    #[error("Field {field} is invalid: {source}")]
+    InvalidField {
+        field: &'static str,
+        source: DecodeError,
+    },
+}
+
+/// A protocol message with its wire encoding: the [MAGIC], the [VERSION] and the
+/// [MessageType] byte, followed by the fields of the message. Each field is prefixed with its
+/// length as little-endian `u32`; lists are the concatenation of their fixed-size elements.
+/// Tokens and signatures are encoded without the [envelope](crate::envelope) header of their
+/// `to_bytes`, as the frame's version covers them.
+pub trait WireMessage: Sized {
+    const MESSAGE_TYPE: MessageType;
+
+    fn encode(&self) -> Vec<u8>;
+
+    fn decode(frame: &[u8]) -> Result<Self, WireError>;
+}
+
+/// Response of one issuer to a [BlindRequest]: its index in the committee, as taken by
+/// [aggregate_unblind_from](crate::atact::aggregate_unblind_from), and its blind tokens.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlindTokenResponse {
+    pub issuer_index: usize,
+    pub blind_tokens: Vec<BlindToken>,
+}
+
+/// Changes of the registry the registrar publishes to verifiers: the newly registered tokens
+/// and the identifiers of the newly revoked ones.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RegistryUpdate {
+    pub registered: Vec<Token>,
+    pub revoked: Vec<TokenId>,
+}
+
+/// Ping of an issuer with the challenge `nonce` that its [PingResponse] signs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PingRequest {
+    pub nonce: [u8; 32],
+}
+
+struct FrameWriter(Vec<u8>);
+
+impl FrameWriter {
+    fn new(message_type: MessageType) -> Self {
+        let mut bytes = Vec::with_capacity(HEADER_SIZE);
+        bytes.extend_from_slice(&MAGIC);
+        bytes.push(VERSION);
+        bytes.push(message_type as u8);
+        Self(bytes)
+    }
+
+    fn field(self, bytes: impl AsRef<[u8]>) -> Self {
+        self.list([bytes])
+    }
+
+    fn list<B: AsRef<[u8]>>(mut self, elements: impl IntoIterator<Item = B>) -> Self {
+        let start = self.0.len();
+        self.0.extend_from_slice(&[0; LENGTH_SIZE]);
+        for element in elements {
+            self.0.extend_from_slice(element.as_ref());
+        }
+        let length = u32::try_from(self.0.len() - start - LENGTH_SIZE)
+            .expect("field does not fit into a frame");
+        self.0[start..start + LENGTH_SIZE].copy_from_slice(&length.to_le_bytes());
+        self
+    }
+
+    fn finish(self) -> Vec<u8> {
+        self.0
+    }
+}
+
+struct FrameReader<'a>(&'a [u8]);
+
+impl<'a> FrameReader<'a> {
+    /// Checks the header of `frame` and returns a reader for its fields.
+    fn new(frame: &'a [u8], expected: MessageType) -> Result<Self, WireError> {
+        let mut reader = Self(frame);
+        let header = reader.take("header", HEADER_SIZE)?;
+        let (magic, rest) = header.split_at(MAGIC.len());
+        if magic != MAGIC {
+            return Err(WireError::InvalidMagic(magic.try_into().unwrap()));
+        }
+        if rest[0] != VERSION {
+            return Err(WireError::UnsupportedVersion(rest[0]));
+        }
+        if rest[1] != expected as u8 {
+            return Err(WireError::UnexpectedMessageType {
+                expected,
+                actual: rest[1],
+            });
+        }
+        Ok(reader)
+    }
+
+    fn take(&mut self, field: &'static str, length: usize) -> Result<&'a [u8], WireError> {
+        if self.0.len() < length {
+            return Err(WireError::Truncated {
+                field,
+                expected: length,
+                actual: self.0.len(),
+            });
+        }
+        let (bytes, rest) = self.0.split_at(length);
+        self.0 = rest;
+        Ok(bytes)
+    }
+
+    fn field(&mut self, field: &'static str) -> Result<&'a [u8], WireError> {
+        let length = self.take(field, LENGTH_SIZE)?;
+        let length = u32::from_le_bytes(length.try_into().unwrap()) as usize;
+        self.take(field, length)
+    }
+
+    fn list<T>(
+        &mut self,
+        field: &'static str,
+        element_size: usize,
+        decode: impl Fn(&[u8]) -> Result<T, DecodeError>,
+    ) -> Result<Vec<T>, WireError> {
+        let bytes = self.field(field)?;
+        if bytes.len() % element_size != 0 {
+            return Err(WireError::InvalidFieldLength {
+                field,
+                length: bytes.len(),
+            });
+        }
+        bytes
+            .chunks_exact(element_size)
+            .map(|element| {
+                decode(element).map_err(|source| WireError::InvalidField { field, source })
+            })
+            .collect()
+    }
+
+    fn array<const L: usize>(&mut self, field: &'static str) -> Result<[u8; L], WireError> {
+        let bytes = self.field(field)?;
+        bytes.try_into().map_err(|_| WireError::InvalidFieldLength {
+            field,
+            length: bytes.len(),
+        })
+    }
+
+    fn commitment(&mut self, field: &'static str) -> Result<Commitment, WireError> {
+        let bytes = self.field(field)?;
+        G1G2::from_bytes(bytes)
+            .map(Commitment)
+            .map_err(|source| WireError::InvalidField { field, source })
+    }
+
+    fn finish(self) -> Result<(), WireError> {
+        if self.0.is_empty() {
+            Ok(())
+        } else {
+            Err(WireError::TrailingBytes(self.0.len()))
+        }
+    }
+}
+
+impl WireMessage for BlindRequest {
+    const MESSAGE_TYPE: MessageType = MessageType::BlindRequest;
+
+    fn encode(&self) -> Vec<u8> {
+        FrameWriter::new(Self::MESSAGE_TYPE)
+            .field(self.cm.0.to_bytes())
+            .list(self.cm_ks.iter().map(|cm_k| cm_k.0.to_bytes()))
+            .field(self.bold_cm_k.0.to_bytes())
+            .field(self.fingerprint)
+            .finish()
+    }
+
+    fn decode(frame: &[u8]) -> Result<Self, WireError> {
+        let mut reader = FrameReader::new(frame, Self::MESSAGE_TYPE)?;
+        let cm = reader.commitment("cm")?;
+        let cm_ks = reader.list("cm_ks", G1G2::ENCODED_SIZE, |bytes| {
+            G1G2::from_bytes(bytes).map(Commitment)
+        })?;
+        let bold_cm_k = reader.commitment("bold_cm_k")?;
+        let fingerprint = reader.array("fingerprint")?;
+        reader.finish()?;
+        Ok(Self {
+            cm,
+            cm_ks,
+            bold_cm_k,
+            fingerprint,
+        })
+    }
+}
+
+impl WireMessage for BlindTokenResponse {
+    const MESSAGE_TYPE: MessageType = MessageType::BlindTokenResponse;
+
+    /// The issuer index is encoded as little-endian `u64`.
+    fn encode(&self) -> Vec<u8> {
+        FrameWriter::new(Self::MESSAGE_TYPE)
+            .field((self.issuer_index as u64).to_le_bytes())
+            .list(
+                self.blind_tokens
+                    .iter()
+                    .map(|token| token.sigma.0.to_bytes()),
+            )
+            .finish()
+    }
+
+    fn decode(frame: &[u8]) -> Result<Self, WireError> {
+        let mut reader = FrameReader::new(frame, Self::MESSAGE_TYPE)?;
+        let issuer_index = reader.field("issuer_index")?;
+        let issuer_index = <[u8; 8]>::try_from(issuer_index)
+            .ok()
+            .and_then(|index| usize::try_from(u64::from_le_bytes(index)).ok())
+            .ok_or(WireError::InvalidFieldLength {
+                field: "issuer_index",
+                length: issuer_index.len(),
+            })?;
+        let blind_tokens = reader.list("blind_tokens", G1G2::ENCODED_SIZE, |bytes| {
+            G1G2::from_bytes(bytes).map(|sigma| BlindToken {
+                sigma: Signature(sigma),
+            })
+        })?;
+        reader.finish()?;
+        Ok(Self {
+            issuer_index,
+            blind_tokens,
+        })
+    }
+}
+
+impl WireMessage for RegistryUpdate {
+    const MESSAGE_TYPE: MessageType = MessageType::RegistryUpdate;
+
+    fn encode(&self) -> Vec<u8> {
+        FrameWriter::new(Self::MESSAGE_TYPE)
+            .list(self.registered.iter().map(Token::payload))
+            .list(&self.revoked)
+            .finish()
+    }
+
+    fn decode(frame: &[u8]) -> Result<Self, WireError> {
+        let mut reader = FrameReader::new(frame, Self::MESSAGE_TYPE)?;
+        let registered = reader.list("registered", Token::PAYLOAD_SIZE, Token::from_payload)?;
+        let revoked = reader.list("revoked", size_of::<TokenId>(), |bytes| {
+            Ok(bytes.try_into().unwrap())
+        })?;
+        reader.finish()?;
+        Ok(Self {
+            registered,
+            revoked,
+        })
+    }
+}
+
+impl WireMessage for PingRequest {
+    const MESSAGE_TYPE: MessageType = MessageType::PingRequest;
+
+    fn encode(&self) -> Vec<u8> {
+        FrameWriter::new(Self::MESSAGE_TYPE)
+            .field(self.nonce)
+            .finish()
+    }
+
+    fn decode(frame: &[u8]) -> Result<Self, WireError> {
+        let mut reader = FrameReader::new(frame, Self::MESSAGE_TYPE)?;
+        let nonce = reader.array("nonce")?;
+        reader.finish()?;
+        Ok(Self { nonce })
+    }
+}
+
+#[cfg(feature = "async")]
+impl WireMessage for PingResponse {
+    const MESSAGE_TYPE: MessageType = MessageType::PingResponse;
+
+    /// The issuer index and the epoch are encoded as little-endian `u64`.
+    fn encode(&self) -> Vec<u8> {
+        FrameWriter::new(Self::MESSAGE_TYPE)
+            .field((self.issuer_index as u64).to_le_bytes())
+            .field(self.fingerprint)
+            .field(self.epoch.to_le_bytes())
+            .field(self.nonce)
+            .field(self.signature.0.to_bytes())
+            .finish()
+    }
+
+    fn decode(frame: &[u8]) -> Result<Self, WireError> {
+        let mut reader = FrameReader::new(frame, Self::MESSAGE_TYPE)?;
+        let issuer_index = reader.array("issuer_index")?;
+        let issuer_index = usize::try_from(u64::from_le_bytes(issuer_index)).map_err(|_| {
+            WireError::InvalidFieldLength {
+                field: "issuer_index",
+                length: issuer_index.len(),
+            }
+        })?;
+        let fingerprint = reader.array("fingerprint")?;
+        let epoch = u64::from_le_bytes(reader.array("epoch")?);
+        let nonce = reader.array("nonce")?;
+        let signature = reader.field("signature")?;
+        let signature = G1G2::from_bytes(signature)
+            .map(Signature)
+            .map_err(|source| WireError::InvalidField {
+                field: "signature",
+                source,
+            })?;
+        reader.finish()?;
+        Ok(Self {
+            issuer_index,
+            fingerprint,
+            epoch,
+            nonce,
+            signature,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use proptest::{
+        collection::vec,
+        prelude::{Strategy, any},
+        prop_assert_eq, proptest,
+    };
+    use rand::{Rng, RngCore, SeedableRng};
+    use rand_chacha::ChaCha20Rng;
+
+    use super::*;
+    use crate::{arbitrary, bls381_helpers::test::generator_multiple};
+
+    const BLIND_REQUEST: &str = concat!(
+        "53505254",
+        "04",
+        "01",
+        "90000000",
+        "97f1d3a73197d7942695638c4fa9ac0fc3688c4f9774b905a14e3a3f171bac586c55e83ff97a1aeffb3af00adb22c6bb93e02b6052719f607dacd3a088274f65596bd0d09920b61ab5da61bbdc7f5049334cf11213945d57e5ac7d055d042b7e024aa2b2f08f0a91260805272dc51051c6e47ad4fa403b02b4510b647ae3d1770bac0326a805bbefd48056c8c121bdb8",
+        "20010000",
+        "a572cbea904d67468808c8eb50a9450c9721db309128012543902d0ac358a62ae28f75bb8f1c7c42c39a8c5529bf0f4eaa4edef9c1ed7f729f520e47730a124fd70662a904ba1074728114d1031e1572c6c886f6b57ec72a6178288c47c335771638533957d540a9d2370f17cc7ed5863bc0b995b8825e0ee1ea1e1e4d00dbae81f14b0bf3611b78c952aacab827a053",
+        "89ece308f9d1f0131765212deca99697b112d61f9be9a5f1f3780a51335b3ff981747a0b2ca2179b96d2c0c9024e522489380275bbc8e5dcea7dc4dd7e0550ff2ac480905396eda55062650f8d251c96eb480673937cc6d9d6a44aaa56ca66dc122915c824a0857e2ee414a3dccb23ae691ae54329781315a0c75df1c04d6d7a50a030fc866f09d516020ef82324afae",
+        "90000000",
+        "ac9b60d5afcbd5663a8a44b7c5a02f19e9a77ab0a35bd65809bb5c67ec582c897feb04decc694b13e08587f3ff9b5b60870227d3f13684fdb7ce31b8065ba3acb35f7bde6fe2ddfefa359f8b35d08a9ab9537b43e24f4ffb720b5a0bda2a82f20e7a30979a8853a077454eb63b8dcee75f106221b262886bb8e01b0abb043368da82f60899cc1412e33e4120195fc557",
+        "20000000",
+        "3333333333333333333333333333333333333333333333333333333333333333",
+    );
+    const BLIND_TOKEN_RESPONSE: &str = concat!(
+        "53505254",
+        "04",
+        "02",
+        "08000000",
+        "0200000000000000",
+        "20010000",
+        "b0e7791fb972fe014159aa33a98622da3cdc98ff707965e536d8636b5fcc5ac7a91a8c46e59a00dca575af0f18fb13dc80fb837804dba8213329db46608b6c121d973363c1234a86dd183baff112709cf97096c5e9a1a770ee9d7dc641a894d60411a5de6730ffece671a9f21d65028cc0f1102378de124562cb1ff49db6f004fcd14d683024b0548eff3d1468df2688",
+        "a6e82f6da4520f85c5d27d8f329eccfa05944fd1096b20734c894966d12a9e2a9a9744529d7212d33883113a0cadb90983f4b4e761936d90fd5f55f99087138a07a69755ad4a46e4dd1c2cfe6d11371e1cc033111a0595e3bba98d0f538db45119e384121b7d70927c49e6d044fd8517c36bc6ed2813a8956dd64f049869e8a77f7e46930240e6984abe26fa6a89658f",
+    );
+    const REGISTRY_UPDATE: &str = concat!(
+        "53505254",
+        "04",
+        "03",
+        "28010000",
+        "b0e7791fb972fe014159aa33a98622da3cdc98ff707965e536d8636b5fcc5ac7a91a8c46e59a00dca575af0f18fb13dc80fb837804dba8213329db46608b6c121d973363c1234a86dd183baff112709cf97096c5e9a1a770ee9d7dc641a894d60411a5de6730ffece671a9f21d65028cc0f1102378de124562cb1ff49db6f004fcd14d683024b0548eff3d1468df2688b928f3beb93519eecf0145da903b40a4c97dca00b21f12ac0df3be9116ef2ef27b2ae6bcd4c5bc2d54ef5a70627efcb78d0273f6bf31ed37c3b8d68083ec3d8e20b5f2cc170fa24b9b5be35b34ed013f9a921f1cad1644d4bdb14674247234c8049cd1dbb2d2c3581e54c088135fef36505a6823d61b859437bfc79b617030dc8b40e32bad1fa85b9c0f368af6d38d3c5a00000000000000",
+        "40000000",
+        "1111111111111111111111111111111111111111111111111111111111111111",
+        "2222222222222222222222222222222222222222222222222222222222222222",
+    );
+    fn blind_request() -> BlindRequest {
+        BlindRequest {
+            cm: Commitment(generator_multiple(1)),
+            cm_ks: vec![
+                Commitment(generator_multiple(2)),
+                Commitment(generator_multiple(3)),
+            ],
+            bold_cm_k: Commitment(generator_multiple(4)),
+            fingerprint: [0x33; 32],
+        }
+    }
+
+    fn blind_token_response() -> BlindTokenResponse {
+        BlindTokenResponse {
+            issuer_index: 2,
+            blind_tokens: vec![
+                BlindToken {
+                    sigma: Signature(generator_multiple(5)),
+                },
+                BlindToken {
+                    sigma: Signature(generator_multiple(6)),
+                },
+            ],
+        }
+    }
+
+    fn registry_update() -> RegistryUpdate {
+        RegistryUpdate {
+            registered: vec![Token(
+                Commitment(generator_multiple(5)),
+                Signature(generator_multiple(7)),
+                90,
+            )],
+            revoked: vec![[0x11; 32], [0x22; 32]],
+        }
+    }
+
+    /// Feeds `frame` into every decoder, which must not panic.
+    fn decode_all(frame: &[u8]) {
+        let _ = BlindRequest::decode(frame);
+        let _ = BlindTokenResponse::decode(frame);
+        let _ = RegistryUpdate::decode(frame);
+        let _ = PingRequest::decode(frame);
+        #[cfg(feature = "async")]
+        let _ = PingResponse::decode(frame);
+    }
+
+    #[test]
+    fn blind_request_golden() {
+        let frame = blind_request().encode();
+        assert_eq!(hex::encode(&frame), BLIND_REQUEST);
+
+        let decoded = BlindRequest::decode(&hex::decode(BLIND_REQUEST).unwrap()).unwrap();
+        assert_eq!(decoded.encode(), frame);
+    }
+
+    #[test]
+    fn blind_token_response_golden() {
+        let frame = blind_token_response().encode();
+        assert_eq!(hex::encode(&frame), BLIND_TOKEN_RESPONSE);
+        assert_eq!(
+            BlindTokenResponse::decode(&frame),
+            Ok(blind_token_response())
+        );
+    }
+
+    #[test]
+    fn registry_update_golden() {
+        let frame = registry_update().encode();
+        assert_eq!(hex::encode(&frame), REGISTRY_UPDATE);
+        assert_eq!(RegistryUpdate::decode(&frame), Ok(registry_update()));
+
+        let empty = RegistryUpdate::default().encode();
+        assert_eq!(
+            hex::encode(&empty),
+            "53505254020300000000".to_owned() + "00000000"
+        );
+        assert_eq!(
+            RegistryUpdate::decode(&empty),
+            Ok(RegistryUpdate::default())
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_headers() {
+        let frame = blind_request().encode();
+
+        let mut bad_magic = frame.clone();
+        bad_magic[0] = b'X';
+        assert_eq!(
+            BlindRequest::decode(&bad_magic).err(),
+            Some(WireError::InvalidMagic(*b"XPRT"))
+        );
+
+        let mut next_version = frame.clone();
+        next_version[4] = VERSION + 1;
+        assert_eq!(
+            BlindRequest::decode(&next_version).err(),
+            Some(WireError::UnsupportedVersion(VERSION + 1))
+        );
+
+        assert_eq!(
+            RegistryUpdate::decode(&frame),
+            Err(WireError::UnexpectedMessageType {
+                expected: MessageType::RegistryUpdate,
+                actual: MessageType::BlindRequest as u8
+            })
+        );
+    }
+
+    #[test]
+    fn rejects_truncated_frames() {
+        let frame = blind_request().encode();
+        for length in 0..frame.len() {
+            assert!(matches!(
+                BlindRequest::decode(&frame[..length]),
+                Err(WireError::Truncated { .. })
+            ));
+        }
+        assert_eq!(
+            BlindRequest::decode(&frame[..HEADER_SIZE + LENGTH_SIZE + 100]).err(),
+            Some(WireError::Truncated {
+                field: "cm",
+                expected: G1G2::ENCODED_SIZE,
+                actual: 100
+            })
+        );
+
+        let mut trailing = frame;
+        trailing.push(0);
+        assert_eq!(
+            BlindRequest::decode(&trailing).err(),
+            Some(WireError::TrailingBytes(1))
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_fields() {
+        let mut frame = blind_request().encode();
+        frame[HEADER_SIZE + LENGTH_SIZE + 20] ^= 0x01;
+        assert_eq!(
+            BlindRequest::decode(&frame).err(),
+            Some(WireError::InvalidField {
+                field: "cm",
+                source: DecodeError::InvalidPoint
+            })
+        );
+
+        let mut update = registry_update();
+        update.revoked.clear();
+        let mut frame = update.encode();
+        // a one-byte revoked field, which is the last field of the frame
+        let revoked_length = frame.len() - LENGTH_SIZE;
+        frame[revoked_length] = 1;
+        frame.push(0x11);
+        assert_eq!(
+            RegistryUpdate::decode(&frame),
+            Err(WireError::InvalidFieldLength {
+                field: "revoked",
+                length: 1
+            })
+        );
+
+        let mut response = blind_token_response().encode();
+        response[HEADER_SIZE] = 4;
+        assert_eq!(
+            BlindTokenResponse::decode(&response),
+            Err(WireError::InvalidFieldLength {
+                field: "issuer_index",
+                length: 4
+            })
+        );
+    }
+
+    #[test]
+    fn decoders_do_not_panic_on_random_bytes() {
+        let mut rng = ChaCha20Rng::seed_from_u64(34);
+        let frames = [
+            blind_request().encode(),
+            blind_token_response().encode(),
+            registry_update().encode(),
+        ];
+
+        for _ in 0..1000 {
+            // random bytes, with and without a valid header
+            let mut bytes = vec![0u8; rng.gen_range(0..2 * HEADER_SIZE + LENGTH_SIZE)];
+            rng.fill_bytes(&mut bytes);
+            decode_all(&bytes);
+            for message_type in 1..=5 {
+                let mut framed = [&MAGIC[..], &[VERSION, message_type]].concat();
+                framed.extend_from_slice(&bytes);
+                decode_all(&framed);
+            }
+
+            // valid frames with a few random bytes replaced and cut at a random length
+            for frame in &frames {
+                let mut mutated = frame.clone();
+                for _ in 0..rng.gen_range(1..4) {
+                    let index = rng.gen_range(0..mutated.len());
+                    mutated[index] = rng.r#gen();
+                }
+                mutated.truncate(rng.gen_range(0..=mutated.len()));
+                decode_all(&mutated);
+            }
+        }
+    }
+
+    fn blind_tokens() -> impl Strategy<Value = Vec<BlindToken>> {
+        vec(
+            arbitrary::signature().prop_map(|sigma| BlindToken { sigma }),
+            0..4,
+        )
+    }
+
+    proptest! {
+        #[test]
+        fn blind_request_round_trip(
+            cm in arbitrary::commitment(),
+            cm_ks in vec(arbitrary::commitment(), 0..4),
+            bold_cm_k in arbitrary::commitment(),
+            fingerprint in any::<[u8; 32]>(),
+        ) {
+            let frame = BlindRequest { cm, cm_ks, bold_cm_k, fingerprint }.encode();
+            prop_assert_eq!(BlindRequest::decode(&frame).unwrap().encode(), frame);
+        }
+
+        #[test]
+        fn blind_token_response_round_trip(
+            issuer_index in any::<usize>(),
+            blind_tokens in blind_tokens(),
+        ) {
+            let response = BlindTokenResponse { issuer_index, blind_tokens };
+            prop_assert_eq!(BlindTokenResponse::decode(&response.encode()), Ok(response));
+        }
+
+        #[test]
+        fn registry_update_round_trip(
+            registered in vec(arbitrary::token(), 0..4),
+            revoked in vec(any::<TokenId>(), 0..4),
+        ) {
+            let update = RegistryUpdate { registered, revoked };
+            prop_assert_eq!(RegistryUpdate::decode(&update.encode()), Ok(update));
+        }
+
+        #[test]
+        fn ping_request_round_trip(nonce in any::<[u8; 32]>()) {
+            let request = PingRequest { nonce };
+            prop_assert_eq!(PingRequest::decode(&request.encode()), Ok(request));
+        }
+
+        #[cfg(feature = "async")]
+        #[test]
+        fn ping_response_round_trip(
+            issuer_index in any::<usize>(),
+            fingerprint in any::<[u8; 32]>(),
+            epoch in any::<u64>(),
+            nonce in any::<[u8; 32]>(),
+            signature in arbitrary::signature(),
+        ) {
+            let response = PingResponse { issuer_index, fingerprint, epoch, nonce, signature };
+            prop_assert_eq!(PingResponse::decode(&response.encode()), Ok(response));
+        }
+    }
+}